@@ -0,0 +1,357 @@
+use crate::connection::{ConnectionManager, PendingResponse};
+use crate::node_id::NodeId;
+use crate::state::DeviceState;
+use anyhow::{Result, bail};
+use meshtastic::{Message, protobufs};
+use serde::Serialize;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+use protobufs::config::device_config::Role;
+
+/// Hop limit for remote admin packets, matching the firmware default
+const REMOTE_ADMIN_HOP_LIMIT: u32 = 3;
+
+/// Node number addressing every node on the mesh
+pub const BROADCAST_NODE_NUM: u32 = 0xffff_ffff;
+
+/// Target of an admin message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdminDestination {
+    /// The locally connected node
+    Local,
+    /// A single remote node, via remote admin
+    Node(u32),
+    /// Every node on the mesh that accepts the message
+    Broadcast,
+}
+
+impl AdminDestination {
+    /// Resolve a destination node number, refusing broadcast unless allowed
+    ///
+    /// A broadcast admin message reconfigures every node that accepts it,
+    /// so it must be requested explicitly rather than by a mistyped id.
+    pub fn resolve(dest: Option<u32>, allow_broadcast: bool) -> Result<Self> {
+        match dest {
+            None => Ok(Self::Local),
+            Some(BROADCAST_NODE_NUM) if allow_broadcast => {
+                warn!("Sending admin message to broadcast; every node may apply it");
+                Ok(Self::Broadcast)
+            }
+            Some(BROADCAST_NODE_NUM) => {
+                bail!("Refusing to send an admin message to broadcast (every node on the mesh)")
+            }
+            Some(node) => Ok(Self::Node(node)),
+        }
+    }
+
+    /// Node number of a remote target, `None` for the local node
+    pub fn node_num(self) -> Option<u32> {
+        match self {
+            Self::Local => None,
+            Self::Node(node) => Some(node),
+            Self::Broadcast => Some(BROADCAST_NODE_NUM),
+        }
+    }
+}
+
+impl std::fmt::Display for AdminDestination {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Local => write!(f, "local node"),
+            Self::Node(node) => write!(f, "node {node}", node = NodeId(*node)),
+            Self::Broadcast => write!(f, "all nodes (broadcast)"),
+        }
+    }
+}
+
+/// Session key requests sent before giving up
+pub const SESSION_KEY_ATTEMPTS: u32 = 3;
+
+/// Pause before the second session key request, doubled for each later one
+const SESSION_KEY_INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Routing errors meaning the node will not accept admin messages from us
+const ADMIN_REFUSAL_REASONS: &[&str] = &[
+    "NotAuthorized",
+    "AdminPublicKeyUnauthorized",
+    "AdminBadSessionKey",
+    "PkiUnknownPubkey",
+    "NoChannel",
+];
+
+/// Why no admin session key could be obtained
+#[derive(Debug, thiserror::Error)]
+pub enum SessionKeyError {
+    /// The node answered but rejected the request
+    #[error(
+        "The {target} refused admin access ({reason}); add this client's public key to its \
+         admin keys, or on firmware before 2.5 create a channel named \"admin\" with the \
+         same PSK on both nodes"
+    )]
+    Refused { target: String, reason: String },
+
+    /// No session key arrived after every attempt
+    #[error(
+        "No admin session key from the {target} after {attempts} attempt(s); the node may be \
+         out of range, or on firmware before 2.5 it needs a channel named \"admin\" for \
+         remote admin"
+    )]
+    NoResponse { target: String, attempts: u32 },
+}
+
+/// Whether a routing error reason means the node refuses admin from us
+pub fn is_admin_refusal(reason: &str) -> bool {
+    ADMIN_REFUSAL_REASONS.contains(&reason)
+}
+
+/// Pause after a failed session key `attempt` (1-based) before the next one
+pub fn session_key_backoff(attempt: u32) -> Duration {
+    SESSION_KEY_INITIAL_BACKOFF * 2u32.saturating_pow(attempt.saturating_sub(1))
+}
+
+/// Build the mesh packet carrying an admin message
+///
+/// Remote admin packets to a single node are PKI encrypted and sent reliably
+/// so the firmware reports delivery; broadcasts cannot be either.
+pub fn admin_packet(
+    destination: AdminDestination,
+    packet_id: u32,
+    admin_msg: &protobufs::AdminMessage,
+    want_response: bool,
+) -> protobufs::MeshPacket {
+    let mut packet = protobufs::MeshPacket {
+        payload_variant: Some(protobufs::mesh_packet::PayloadVariant::Decoded(
+            protobufs::Data {
+                portnum: protobufs::PortNum::AdminApp as i32,
+                payload: admin_msg.encode_to_vec(),
+                want_response,
+                ..Default::default()
+            },
+        )),
+        to: 0, // Local destination
+        id: packet_id,
+        priority: protobufs::mesh_packet::Priority::Default as i32,
+        ..Default::default()
+    };
+
+    match destination {
+        AdminDestination::Local => {}
+        AdminDestination::Node(node) => {
+            packet.to = node;
+            packet.want_ack = true;
+            packet.pki_encrypted = true;
+            packet.hop_limit = REMOTE_ADMIN_HOP_LIMIT;
+            packet.priority = protobufs::mesh_packet::Priority::Reliable as i32;
+        }
+        AdminDestination::Broadcast => {
+            packet.to = BROADCAST_NODE_NUM;
+            packet.hop_limit = REMOTE_ADMIN_HOP_LIMIT;
+        }
+    }
+
+    packet
+}
+
+/// Send an admin message to the local node or a remote one
+///
+/// The session key is fetched first; local nodes that do not require one
+/// still accept the message, so a failure there is only logged unless the
+/// node refused outright. Broadcasts have no single session key and go out
+/// without one.
+pub async fn send_admin_message(
+    connection: &mut ConnectionManager,
+    destination: AdminDestination,
+    payload: protobufs::admin_message::PayloadVariant,
+) -> Result<()> {
+    let admin_msg = protobufs::AdminMessage {
+        payload_variant: Some(payload),
+        session_passkey: admin_session_passkey(connection, destination).await?,
+    };
+    let packet = admin_packet(destination, connection.next_packet_id(), &admin_msg, false);
+
+    connection.send_mesh_packet(packet).await?;
+
+    Ok(())
+}
+
+/// Send an admin request and register a waiter for its response
+///
+/// Session keys are handled as in [`send_admin_message`]. Responses echo the
+/// request's packet id, so the returned handle resolves once the response
+/// has been stored in the device state.
+pub async fn request_admin(
+    connection: &mut ConnectionManager,
+    destination: AdminDestination,
+    payload: protobufs::admin_message::PayloadVariant,
+) -> Result<PendingResponse> {
+    let admin_msg = protobufs::AdminMessage {
+        payload_variant: Some(payload),
+        session_passkey: admin_session_passkey(connection, destination).await?,
+    };
+    let request_id = connection.next_packet_id();
+    let packet = admin_packet(destination, request_id, &admin_msg, true);
+    let pending = connection.register_response(request_id)?;
+
+    connection.send_mesh_packet(packet).await?;
+
+    Ok(pending)
+}
+
+/// Session key to authorize an admin message to `destination` with
+async fn admin_session_passkey(
+    connection: &mut ConnectionManager,
+    destination: AdminDestination,
+) -> Result<Vec<u8>> {
+    Ok(match destination {
+        AdminDestination::Local => {
+            if let Err(e) = connection.ensure_session_key().await {
+                if matches!(
+                    e.downcast_ref::<SessionKeyError>(),
+                    Some(SessionKeyError::Refused { .. })
+                ) {
+                    return Err(e);
+                }
+                debug!("Failed to get session key (may not be required): {e}");
+            }
+            connection.get_session_key().await.unwrap_or_default()
+        }
+        AdminDestination::Node(node) => {
+            if let Some(issue) = crate::firmware::require_firmware(
+                &connection.get_device_state().await,
+                "Remote admin",
+                crate::firmware::PKI_ADMIN_FIRMWARE,
+            ) {
+                warn!("{issue}");
+            }
+            connection.ensure_session_key_for(Some(node)).await?;
+            connection
+                .get_session_key_for(Some(node))
+                .await
+                .unwrap_or_default()
+        }
+        AdminDestination::Broadcast => Vec::new(),
+    })
+}
+
+/// What switching to a role means for the node and the mesh
+#[derive(Debug, Clone, Serialize)]
+pub struct RoleAdvice {
+    pub role: String,
+    pub summary: &'static str,
+    /// Serious consequences the user should acknowledge
+    pub warnings: Vec<String>,
+    /// Infrastructure roles need an explicit acknowledgement
+    pub requires_acknowledgement: bool,
+}
+
+/// Describe a role, checking it against the node's configuration when known
+///
+/// `state` is the cached state of the target node, which is only available
+/// for the locally connected one.
+pub fn role_advice(role: Role, state: Option<&DeviceState>) -> RoleAdvice {
+    let mut warnings = Vec::new();
+
+    let summary = match role {
+        Role::Client => "Normal client: rebroadcasts packets and shows on the mesh",
+        Role::ClientMute => "Client that never rebroadcasts; good for nodes near a router",
+        Role::ClientHidden => "Client that only transmits when needed and stays hidden",
+        Role::Router => "Infrastructure node: always rebroadcasts, with priority over clients",
+        Role::RouterClient => "Deprecated hybrid of router and client",
+        Role::Repeater => "Infrastructure node that rebroadcasts but hides itself",
+        Role::Tracker => "Prioritises broadcasting its own GPS position",
+        Role::TakTracker => "Tracker that sends ATAK position reports",
+        Role::Tak => "Optimised for ATAK clients",
+        Role::Sensor => "Prioritises broadcasting its telemetry",
+        Role::LostAndFound => "Regularly broadcasts its position as a text message",
+        _ => "Role without specific guidance",
+    };
+
+    let infrastructure = matches!(role, Role::Router | Role::Repeater);
+    if infrastructure {
+        warnings.push(
+            "Only use this role for well-sited nodes with a clear view of the mesh; \
+             misplaced routers add hops and congestion for everyone"
+                .to_string(),
+        );
+        warnings.push(
+            "The node will rebroadcast all traffic it hears, increasing airtime and power use"
+                .to_string(),
+        );
+    }
+
+    if role == Role::RouterClient {
+        warnings.push("ROUTER_CLIENT is deprecated; use CLIENT or ROUTER instead".to_string());
+    }
+
+    if matches!(role, Role::Tracker | Role::TakTracker | Role::LostAndFound)
+        && let Some(state) = state
+    {
+        let has_position_source = state
+            .position_config
+            .as_ref()
+            .is_some_and(|config| config.gps_enabled || config.fixed_position);
+        if !has_position_source {
+            warnings.push(
+                "GPS is disabled and no fixed position is set, so there is no position to report"
+                    .to_string(),
+            );
+        }
+    }
+
+    if let Some(minimum) = crate::firmware::role_min_firmware(&role_name(role))
+        && let Some(issue) = state.and_then(|state| {
+            crate::firmware::require_firmware(
+                state,
+                &format!("The {name} role", name = role_name(role)),
+                minimum,
+            )
+        })
+    {
+        warnings.push(issue.to_string());
+    }
+
+    if let Some(current) = state
+        .and_then(|state| state.device_config.as_ref())
+        .map(|config| &config.role)
+        && current.eq_ignore_ascii_case(&role_name(role).replace('_', ""))
+    {
+        warnings.push(format!(
+            "The node already uses the {name} role",
+            name = role_name(role)
+        ));
+    }
+
+    RoleAdvice {
+        role: role_name(role),
+        summary,
+        warnings,
+        requires_acknowledgement: infrastructure,
+    }
+}
+
+/// Protobuf name of a role, e.g. "CLIENT_MUTE"
+pub fn role_name(role: Role) -> String {
+    role.as_str_name().to_string()
+}
+
+/// Switch the device role of the local node or a remote one
+pub async fn set_device_role(
+    connection: &mut ConnectionManager,
+    role: Role,
+    destination: AdminDestination,
+) -> Result<()> {
+    let config = protobufs::config::DeviceConfig {
+        role: role as i32,
+        ..Default::default()
+    };
+
+    send_admin_message(
+        connection,
+        destination,
+        protobufs::admin_message::PayloadVariant::SetConfig(protobufs::Config {
+            payload_variant: Some(protobufs::config::PayloadVariant::Device(config)),
+        }),
+    )
+    .await
+}
@@ -0,0 +1,114 @@
+use crate::connection::ConnectionManager;
+use crate::state::DeviceMetrics;
+use anyhow::Result;
+use serde::Serialize;
+
+/// Channel utilization above which the firmware starts delaying its own
+/// broadcasts, in percent
+pub const BUSY_CHANNEL_UTILIZATION: f32 = 25.0;
+
+/// Share of the last hour a node may spend transmitting under common duty
+/// cycle limits, in percent
+pub const TX_AIRTIME_LIMIT: f32 = 10.0;
+
+/// Bar heights of a sparkline, lowest first
+const SPARK_LEVELS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Shown in a sparkline where a sample has no value
+const SPARK_GAP: char = ' ';
+
+/// One reading of how busy the radio channel is
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct AirtimeSample {
+    /// Unix time of the reading
+    pub time: u64,
+    /// Share of time the channel was in use by any node, in percent
+    pub channel_utilization: Option<f32>,
+    /// Share of the last hour this node spent transmitting, in percent
+    pub air_util_tx: Option<f32>,
+}
+
+impl AirtimeSample {
+    /// Airtime figures of a telemetry report, `None` if it carries neither
+    pub fn from_metrics(time: u64, metrics: &DeviceMetrics) -> Option<Self> {
+        if metrics.channel_utilization.is_none() && metrics.air_util_tx.is_none() {
+            return None;
+        }
+        Some(Self {
+            time,
+            channel_utilization: metrics.channel_utilization,
+            air_util_tx: metrics.air_util_tx,
+        })
+    }
+
+    /// Whether either figure is above the level where the mesh suffers
+    pub fn is_congested(&self) -> bool {
+        self.channel_utilization
+            .is_some_and(|value| value > BUSY_CHANNEL_UTILIZATION)
+            || self
+                .air_util_tx
+                .is_some_and(|value| value > TX_AIRTIME_LIMIT)
+    }
+}
+
+/// Airtime readings of the local node collected so far, oldest first
+///
+/// Drawn from the telemetry history, so its depth follows the retention
+/// policy.
+pub async fn get_airtime_history(connection: &ConnectionManager) -> Result<Vec<AirtimeSample>> {
+    let state = connection.get_device_state().await;
+    let Some(node_num) = state.my_node_info.as_ref().map(|info| info.node_num) else {
+        return Ok(Vec::new());
+    };
+
+    Ok(state
+        .telemetry_history(node_num)
+        .filter_map(|telemetry| {
+            let metrics = telemetry.device_metrics.as_ref()?;
+            AirtimeSample::from_metrics(telemetry.time, metrics)
+        })
+        .collect())
+}
+
+/// Ask the local node for fresh device metrics and read its airtime figures
+pub async fn sample_airtime(
+    connection: &mut ConnectionManager,
+    timeout_secs: u64,
+) -> Result<Option<AirtimeSample>> {
+    let metrics = crate::telemetry::request_device_metrics(connection, timeout_secs).await?;
+    Ok(metrics
+        .as_ref()
+        .and_then(|metrics| AirtimeSample::from_metrics(crate::time::unix_now(), metrics)))
+}
+
+/// Draw values as a row of bars scaled so `ceiling` fills the cell
+///
+/// Missing values leave a gap; values above the ceiling are drawn full.
+pub fn sparkline(values: &[Option<f32>], ceiling: f32) -> String {
+    values
+        .iter()
+        .map(|value| match value {
+            Some(value) => {
+                let fraction = if ceiling > 0.0 {
+                    (value / ceiling).clamp(0.0, 1.0)
+                } else {
+                    0.0
+                };
+                let top = SPARK_LEVELS.len() - 1;
+                let level = (fraction * top as f32).round() as usize;
+                SPARK_LEVELS.get(level).copied().unwrap_or(SPARK_GAP)
+            }
+            None => SPARK_GAP,
+        })
+        .collect()
+}
+
+/// Draw a percentage as a bar `width` characters wide
+pub fn percent_bar(percent: f32, width: usize) -> String {
+    let filled = ((percent.clamp(0.0, 100.0) / 100.0) * width as f32).round() as usize;
+    format!(
+        "{filled}{empty}",
+        filled = "█".repeat(filled),
+        empty = "░".repeat(width - filled)
+    )
+}
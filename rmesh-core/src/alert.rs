@@ -0,0 +1,144 @@
+use crate::admin::BROADCAST_NODE_NUM;
+use crate::connection::ConnectionManager;
+use crate::message::SentMessage;
+use crate::node_id::NodeId;
+use crate::time::unix_now;
+use anyhow::{Context, Result, ensure};
+use meshtastic::protobufs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::debug;
+
+/// Shortest time between two alerts sent from this machine
+///
+/// Alerts sound a bell on every receiving node, so a script stuck in a loop
+/// must not keep the whole mesh ringing.
+pub const MIN_ALERT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Longest alert text that fits in one packet
+pub const MAX_ALERT_BYTES: usize = 200;
+
+/// An alert refused because the previous one was too recent
+#[derive(Debug, thiserror::Error)]
+#[error("An alert was sent {elapsed}s ago; wait {wait}s before the next one or use --force")]
+pub struct AlertRateLimited {
+    pub elapsed: u64,
+    pub wait: u64,
+}
+
+/// Check an alert may go out at `now`, given when the last one did
+pub fn check_alert_rate(
+    last_sent: Option<u64>,
+    now: u64,
+    min_interval: Duration,
+) -> Result<(), AlertRateLimited> {
+    let Some(last_sent) = last_sent else {
+        return Ok(());
+    };
+    let elapsed = now.saturating_sub(last_sent);
+    let min_interval = min_interval.as_secs();
+    if elapsed < min_interval {
+        return Err(AlertRateLimited {
+            elapsed,
+            wait: min_interval - elapsed,
+        });
+    }
+    Ok(())
+}
+
+/// File remembering when the last alert was sent, shared by every run
+#[derive(Debug, Clone)]
+pub struct AlertLog {
+    path: PathBuf,
+}
+
+impl Default for AlertLog {
+    fn default() -> Self {
+        Self::new(std::env::temp_dir().join("rmesh-last-alert"))
+    }
+}
+
+impl AlertLog {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Unix time of the last alert, `None` if none was logged
+    pub fn last_sent(&self) -> Option<u64> {
+        std::fs::read_to_string(&self.path)
+            .ok()
+            .and_then(|text| text.trim().parse().ok())
+    }
+
+    pub fn record(&self, sent_at: u64) -> Result<()> {
+        std::fs::write(&self.path, sent_at.to_string()).with_context(|| {
+            format!(
+                "Failed to write alert log {path}",
+                path = self.path.display()
+            )
+        })
+    }
+}
+
+/// Build the mesh packet of a critical alert
+pub fn alert_packet(text: &str, to: u32, channel: u32, packet_id: u32) -> protobufs::MeshPacket {
+    protobufs::MeshPacket {
+        payload_variant: Some(protobufs::mesh_packet::PayloadVariant::Decoded(
+            protobufs::Data {
+                portnum: protobufs::PortNum::AlertApp as i32,
+                payload: text.as_bytes().to_vec(),
+                ..Default::default()
+            },
+        )),
+        to,
+        channel,
+        id: packet_id,
+        priority: protobufs::mesh_packet::Priority::Alert as i32,
+        ..Default::default()
+    }
+}
+
+/// Send a critical alert, which receiving nodes show and sound like a bell
+/// message even when their notifications are muted
+///
+/// Goes to every node unless `destination` is given. Unless `force` is set,
+/// the alert is refused with [`AlertRateLimited`] when `log` shows one went
+/// out less than [`MIN_ALERT_INTERVAL`] ago.
+pub async fn send_alert(
+    connection: &mut ConnectionManager,
+    text: &str,
+    destination: Option<u32>,
+    channel: u32,
+    log: &AlertLog,
+    force: bool,
+) -> Result<SentMessage> {
+    ensure!(!text.trim().is_empty(), "Alert text is empty");
+    ensure!(
+        text.len() <= MAX_ALERT_BYTES,
+        "Alert text is {len} bytes, the limit is {MAX_ALERT_BYTES}",
+        len = text.len()
+    );
+    if !force {
+        check_alert_rate(log.last_sent(), unix_now(), MIN_ALERT_INTERVAL)?;
+    }
+
+    let to = destination.unwrap_or(BROADCAST_NODE_NUM);
+    let packet = alert_packet(text, to, channel, connection.next_packet_id());
+    connection.send_mesh_packet(packet).await?;
+    debug!("Sent alert to {to} on channel {channel}", to = NodeId(to));
+    log.record(unix_now())?;
+
+    Ok(SentMessage {
+        text: text.to_string(),
+        destination: destination
+            .map(|node| NodeId(node).to_string())
+            .unwrap_or_else(|| "Broadcast".to_string()),
+        channel,
+        acknowledged: None,
+        attempts: None,
+    })
+}
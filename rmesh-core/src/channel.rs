@@ -1,26 +1,118 @@
-use crate::connection::ConnectionManager;
-use anyhow::Result;
+use crate::admin::{AdminDestination, request_admin};
+use crate::connection::processor::channel_info;
+use crate::connection::{ConnectionManager, RequestResponse};
+use crate::lora::{FrequencySlot, primary_frequency_slot};
+use anyhow::{Result, bail};
 use meshtastic::{Message, protobufs};
 use serde::Serialize;
+use std::time::Duration;
+use strum::Display;
 use tracing::debug;
 
+/// Number of channel slots on a device
+pub const MAX_CHANNELS: u32 = 8;
+
+/// How long the local node gets to answer a channel request
+pub const CHANNEL_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
 /// List all channels configured on the device
+///
+/// Channels come from the cache filled during the handshake and by later
+/// channel responses; see [`refresh_channels`] to ask the device again.
 pub async fn list_channels(connection: &ConnectionManager) -> Result<Vec<ChannelInfo>> {
     // Get cached channels from device state
     let state = connection.get_device_state().await;
 
-    // Convert from internal ChannelInfo to public ChannelInfo
-    let channels: Vec<ChannelInfo> = state
-        .channels
-        .into_iter()
-        .map(|ch| ChannelInfo {
-            index: ch.index,
-            name: ch.name,
-            role: ch.role,
-            has_psk: ch.has_psk,
-        })
+    Ok(state.channels.into_iter().map(ChannelInfo::from).collect())
+}
+
+/// Frequency slot the primary channel transmits on, from the cached LoRa
+/// settings and channels
+pub async fn get_frequency_slot(connection: &ConnectionManager) -> Option<FrequencySlot> {
+    let state = connection.get_device_state().await;
+    primary_frequency_slot(state.lora_config.as_ref()?, &state.channels)
+}
+
+/// Index of the enabled channel called `name`, ignoring case
+///
+/// An exact match wins over one differing in case. On a miss the error lists
+/// the channels there are.
+pub fn channel_index_by_name(channels: &[crate::state::ChannelInfo], name: &str) -> Result<u32> {
+    let mut enabled: Vec<_> = channels
+        .iter()
+        .filter(|channel| channel.role != "Disabled")
         .collect();
+    enabled.sort_by_key(|channel| channel.index);
+
+    if let Some(channel) = enabled
+        .iter()
+        .find(|channel| channel.name == name)
+        .or_else(|| {
+            enabled
+                .iter()
+                .find(|channel| channel.name.eq_ignore_ascii_case(name))
+        })
+    {
+        return Ok(channel.index);
+    }
+
+    if enabled.is_empty() {
+        bail!("No channel named \"{name}\": the device has not reported any channels");
+    }
+    let available = enabled
+        .iter()
+        .map(|channel| match channel.name.as_str() {
+            "" => format!("{index} (unnamed)", index = channel.index),
+            named => format!("{index} ({named})", index = channel.index),
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    bail!("No channel named \"{name}\"; available channels: {available}")
+}
+
+/// Ask the device for the current settings of one channel
+///
+/// The response also updates the cached channel, so later listings show it.
+pub async fn get_channel(
+    connection: &mut ConnectionManager,
+    index: u32,
+    timeout: Duration,
+) -> Result<ChannelInfo> {
+    if index >= MAX_CHANNELS {
+        bail!(
+            "Channel index {index} is out of range (0-{max})",
+            max = MAX_CHANNELS - 1
+        );
+    }
+
+    // Channel requests number channels from 1, so 0 can mean "unset"
+    let pending = request_admin(
+        connection,
+        AdminDestination::Local,
+        protobufs::admin_message::PayloadVariant::GetChannelRequest(index + 1),
+    )
+    .await?;
+    debug!("Sent channel {index} request");
 
+    match pending.wait(timeout).await {
+        Some(RequestResponse::Channel(channel)) => Ok(ChannelInfo::from(channel)),
+        Some(other) => bail!("Unexpected response to channel {index} request: {other:?}"),
+        None => bail!(
+            "No response to channel {index} request within {secs}s",
+            secs = timeout.as_secs()
+        ),
+    }
+}
+
+/// Ask the device for every channel, replacing the cached ones
+pub async fn refresh_channels(
+    connection: &mut ConnectionManager,
+    timeout: Duration,
+) -> Result<Vec<ChannelInfo>> {
+    let mut channels = Vec::new();
+    for index in 0..MAX_CHANNELS {
+        channels.push(get_channel(connection, index, timeout).await?);
+    }
     Ok(channels)
 }
 
@@ -39,7 +131,7 @@ pub async fn add_channel(
     // Get the session key
     let session_key = connection.get_session_key().await.unwrap_or_default();
 
-    let api = connection.get_api()?;
+    let packet_id = connection.next_packet_id();
 
     // Create channel settings
     let mut settings = protobufs::ChannelSettings {
@@ -75,7 +167,7 @@ pub async fn add_channel(
         )),
         from: 0,
         to: 0,
-        id: 0,
+        id: packet_id,
         rx_time: 0,
         rx_snr: 0.0,
         hop_limit: 0,
@@ -88,10 +180,7 @@ pub async fn add_channel(
     };
 
     // Send as ToRadio packet
-    api.send_to_radio_packet(Some(protobufs::to_radio::PayloadVariant::Packet(
-        mesh_packet,
-    )))
-    .await?;
+    connection.send_mesh_packet(mesh_packet).await?;
 
     Ok(())
 }
@@ -107,7 +196,7 @@ pub async fn delete_channel(connection: &mut ConnectionManager, index: u32) -> R
     // Get the session key
     let session_key = connection.get_session_key().await.unwrap_or_default();
 
-    let api = connection.get_api()?;
+    let packet_id = connection.next_packet_id();
 
     // Create admin message for channel delete
     let admin_msg = protobufs::AdminMessage {
@@ -128,7 +217,7 @@ pub async fn delete_channel(connection: &mut ConnectionManager, index: u32) -> R
         )),
         from: 0,
         to: 0,
-        id: 0,
+        id: packet_id,
         rx_time: 0,
         rx_snr: 0.0,
         hop_limit: 0,
@@ -141,21 +230,26 @@ pub async fn delete_channel(connection: &mut ConnectionManager, index: u32) -> R
     };
 
     // Send as ToRadio packet
-    api.send_to_radio_packet(Some(protobufs::to_radio::PayloadVariant::Packet(
-        mesh_packet,
-    )))
-    .await?;
+    connection.send_mesh_packet(mesh_packet).await?;
 
     Ok(())
 }
 
 /// Set channel configuration
+///
+/// A `position_precision` of 0 or `None` stops sharing positions on the
+/// channel. Returns the channel as written.
 pub async fn set_channel(
     connection: &mut ConnectionManager,
     index: u32,
     name: Option<&str>,
     psk: Option<&str>,
-) -> Result<()> {
+    position_precision: Option<u32>,
+) -> Result<ChannelInfo> {
+    if position_precision.is_some_and(|bits| bits > FULL_POSITION_PRECISION) {
+        bail!("Position precision must be 0-{FULL_POSITION_PRECISION} bits");
+    }
+
     // Try to get a session key, but continue even if it fails
     // Some devices may not require authentication
     if let Err(e) = connection.ensure_session_key().await {
@@ -165,7 +259,7 @@ pub async fn set_channel(
     // Get the session key
     let session_key = connection.get_session_key().await.unwrap_or_default();
 
-    let api = connection.get_api()?;
+    let packet_id = connection.next_packet_id();
 
     // Create channel settings
     let mut settings = protobufs::ChannelSettings::default();
@@ -178,14 +272,23 @@ pub async fn set_channel(
         settings.psk = key.as_bytes().to_vec();
     }
 
+    if let Some(bits) = position_precision {
+        settings
+            .module_settings
+            .get_or_insert_with(Default::default)
+            .position_precision = bits;
+    }
+
+    let channel = protobufs::Channel {
+        index: index as i32,
+        settings: Some(settings),
+        role: protobufs::channel::Role::Primary as i32,
+    };
+
     // Create admin message for channel set
     let admin_msg = protobufs::AdminMessage {
         payload_variant: Some(protobufs::admin_message::PayloadVariant::SetChannel(
-            protobufs::Channel {
-                index: index as i32,
-                settings: Some(settings),
-                role: protobufs::channel::Role::Primary as i32,
-            },
+            channel.clone(),
         )),
         session_passkey: session_key,
     };
@@ -201,7 +304,7 @@ pub async fn set_channel(
         )),
         from: 0,
         to: 0,
-        id: 0,
+        id: packet_id,
         rx_time: 0,
         rx_snr: 0.0,
         hop_limit: 0,
@@ -214,12 +317,9 @@ pub async fn set_channel(
     };
 
     // Send as ToRadio packet
-    api.send_to_radio_packet(Some(protobufs::to_radio::PayloadVariant::Packet(
-        mesh_packet,
-    )))
-    .await?;
+    connection.send_mesh_packet(mesh_packet).await?;
 
-    Ok(())
+    Ok(ChannelInfo::from(channel_info(channel)))
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -228,4 +328,220 @@ pub struct ChannelInfo {
     pub name: String,
     pub role: String,
     pub has_psk: bool,
+    /// Bits of position shared on the channel, 0 for none
+    pub position_precision: u32,
+    /// How far off a shared position may be, see [`position_precision_m`]
+    pub position_precision_m: Option<f64>,
+    /// Exact positions are shared without a private PSK
+    pub exposes_position: bool,
+}
+
+impl From<crate::state::ChannelInfo> for ChannelInfo {
+    fn from(channel: crate::state::ChannelInfo) -> Self {
+        let settings = channel.settings.unwrap_or_default();
+        let position_precision = settings
+            .module_settings
+            .map(|module| module.position_precision)
+            .unwrap_or_default();
+        Self {
+            index: channel.index,
+            name: channel.name,
+            role: channel.role,
+            has_psk: channel.has_psk,
+            position_precision,
+            position_precision_m: position_precision_m(position_precision),
+            exposes_position: exposes_exact_position(&settings.psk, position_precision),
+        }
+    }
+}
+
+/// Position precision (in bits) at which exact coordinates are shared
+pub const FULL_POSITION_PRECISION: u32 = 32;
+
+/// Distance in meters a position shared at `precision_bits` may be off,
+/// `None` when 0 bits share no position at all
+///
+/// The firmware keeps the top bits of the 1e-7 degree coordinates, which
+/// snaps positions to the middle of a grid cell twice this size.
+pub fn position_precision_m(precision_bits: u32) -> Option<f64> {
+    if precision_bits == 0 {
+        return None;
+    }
+    let dropped = FULL_POSITION_PRECISION.saturating_sub(precision_bits);
+    // ~111 km per degree of latitude
+    Some(2f64.powi(dropped as i32) * 1e-7 * 111_320.0 / 2.0)
+}
+
+/// Whether a channel shares exact positions that anyone can read, as with
+/// no PSK or one derived from the public default key
+pub fn exposes_exact_position(psk: &[u8], precision_bits: u32) -> bool {
+    precision_bits >= FULL_POSITION_PRECISION
+        && matches!(
+            psk_strength(psk),
+            PskStrength::None | PskStrength::Default | PskStrength::Simple
+        )
+}
+
+/// What a channel PSK provides, by its length and value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum PskStrength {
+    /// No PSK, or the single byte 0: traffic is not encrypted
+    None,
+    /// The single byte 1 ("AQ=="): the publicly known default key
+    Default,
+    /// A single byte 2-10: a variant of the default key
+    Simple,
+    /// 16 bytes: AES-128
+    Aes128,
+    /// 32 bytes: AES-256
+    Aes256,
+    /// Any other length, which the firmware does not accept as a key
+    Invalid,
+}
+
+/// Classify a channel PSK
+pub fn psk_strength(psk: &[u8]) -> PskStrength {
+    match psk {
+        [] | [0] => PskStrength::None,
+        [1] => PskStrength::Default,
+        [_] => PskStrength::Simple,
+        _ if psk.len() == 16 => PskStrength::Aes128,
+        _ if psk.len() == 32 => PskStrength::Aes256,
+        _ => PskStrength::Invalid,
+    }
+}
+
+/// How serious an audit finding is, in increasing order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum AuditSeverity {
+    Info,
+    Warning,
+    Critical,
+}
+
+/// A weakness found in one channel's settings
+#[derive(Debug, Clone, Serialize)]
+pub struct ChannelAuditFinding {
+    pub channel_index: u32,
+    pub channel_name: String,
+    pub severity: AuditSeverity,
+    pub issue: String,
+    pub recommendation: String,
+}
+
+/// Security report over all active channels
+#[derive(Debug, Clone, Serialize)]
+pub struct ChannelAudit {
+    pub channels_checked: usize,
+    /// Most severe first
+    pub findings: Vec<ChannelAuditFinding>,
+}
+
+impl ChannelAudit {
+    /// Severity of the worst finding, `None` when nothing was found
+    pub fn highest_severity(&self) -> Option<AuditSeverity> {
+        self.findings.iter().map(|finding| finding.severity).max()
+    }
+}
+
+/// Check channel settings for weak encryption and location leaks
+///
+/// Disabled channels are skipped.
+pub fn audit_channels(channels: &[crate::state::ChannelInfo]) -> ChannelAudit {
+    let mut findings = Vec::new();
+    let mut channels_checked = 0;
+
+    for channel in channels.iter().filter(|channel| channel.role != "Disabled") {
+        channels_checked += 1;
+
+        let psk = channel
+            .settings
+            .as_ref()
+            .map(|settings| settings.psk.as_slice())
+            .unwrap_or_default();
+        let precision = channel
+            .settings
+            .as_ref()
+            .and_then(|settings| settings.module_settings.as_ref())
+            .map(|module| module.position_precision)
+            .unwrap_or_default();
+        let is_primary = channel.role == "Primary";
+
+        let mut finding = |severity, issue: &str, recommendation: &str| {
+            findings.push(ChannelAuditFinding {
+                channel_index: channel.index,
+                channel_name: channel.name.clone(),
+                severity,
+                issue: issue.to_string(),
+                recommendation: recommendation.to_string(),
+            });
+        };
+
+        let strength = psk_strength(psk);
+        match strength {
+            PskStrength::None => finding(
+                AuditSeverity::Warning,
+                "Channel is not encrypted",
+                "Set a random 32-byte PSK",
+            ),
+            PskStrength::Default => finding(
+                AuditSeverity::Warning,
+                "Channel uses the publicly known default PSK (AQ==)",
+                "Set a random 32-byte PSK unless this is meant to be a public channel",
+            ),
+            PskStrength::Simple => finding(
+                AuditSeverity::Warning,
+                "Channel uses a 1-byte PSK derived from the public default key",
+                "Set a random 32-byte PSK",
+            ),
+            PskStrength::Invalid => finding(
+                AuditSeverity::Warning,
+                "PSK length is not a valid AES key size (16 or 32 bytes)",
+                "Set a random 32-byte PSK",
+            ),
+            PskStrength::Aes128 => finding(
+                AuditSeverity::Info,
+                "Channel uses AES-128",
+                "Consider a 32-byte PSK for AES-256",
+            ),
+            PskStrength::Aes256 => {}
+        }
+
+        if exposes_exact_position(psk, precision) {
+            if strength == PskStrength::None && is_primary {
+                finding(
+                    AuditSeverity::Critical,
+                    "Unencrypted primary channel shares exact positions with anyone in range",
+                    "Encrypt the channel or reduce its position precision",
+                );
+            } else {
+                finding(
+                    AuditSeverity::Warning,
+                    "Exact positions are shared on a channel anyone can read",
+                    "Reduce the position precision or set a private PSK",
+                );
+            }
+        }
+    }
+
+    findings.sort_by(|a, b| {
+        b.severity
+            .cmp(&a.severity)
+            .then(a.channel_index.cmp(&b.channel_index))
+    });
+
+    ChannelAudit {
+        channels_checked,
+        findings,
+    }
+}
+
+/// Audit the channels cached from the device
+pub async fn audit_channel_security(connection: &ConnectionManager) -> Result<ChannelAudit> {
+    let state = connection.get_device_state().await;
+    Ok(audit_channels(&state.channels))
 }
@@ -0,0 +1,261 @@
+use crate::admin::{AdminDestination, send_admin_message};
+use crate::channel::MAX_CHANNELS;
+use crate::connection::ConnectionManager;
+use crate::connection::processor::channel_info;
+use crate::state::ChannelInfo;
+use anyhow::{Context, Result, bail, ensure};
+use meshtastic::protobufs;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use strum::Display;
+use tracing::debug;
+
+/// Role of a channel slot as written in channel files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ChannelRole {
+    Disabled,
+    Primary,
+    Secondary,
+}
+
+impl ChannelRole {
+    /// Role from the name the device state uses, e.g. "Primary"
+    pub fn from_state(role: &str) -> Self {
+        match role {
+            "Primary" => Self::Primary,
+            "Secondary" => Self::Secondary,
+            _ => Self::Disabled,
+        }
+    }
+
+    fn to_protobuf(self) -> protobufs::channel::Role {
+        match self {
+            Self::Disabled => protobufs::channel::Role::Disabled,
+            Self::Primary => protobufs::channel::Role::Primary,
+            Self::Secondary => protobufs::channel::Role::Secondary,
+        }
+    }
+}
+
+/// One channel slot of a channel file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChannelSlot {
+    pub index: u32,
+    pub role: ChannelRole,
+    #[serde(default)]
+    pub name: String,
+    /// Hex encoded PSK, left out when redacted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub psk: Option<String>,
+    #[serde(default)]
+    pub uplink_enabled: bool,
+    #[serde(default)]
+    pub downlink_enabled: bool,
+    /// Bits of position shared on the channel, 0 for none
+    #[serde(default)]
+    pub position_precision: u32,
+}
+
+/// The channel slots of a device, for sharing between devices
+///
+/// Written as YAML or JSON, chosen by the file extension.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChannelSet {
+    pub channels: Vec<ChannelSlot>,
+}
+
+impl ChannelSet {
+    /// Channel set covering every slot, with unknown slots disabled
+    ///
+    /// PSKs are left out unless `include_psks` is set.
+    pub fn from_channels(channels: &[ChannelInfo], include_psks: bool) -> Self {
+        let slots = (0..MAX_CHANNELS)
+            .map(|index| match channels.iter().find(|c| c.index == index) {
+                Some(channel) => {
+                    let settings = channel.settings.clone().unwrap_or_default();
+                    ChannelSlot {
+                        index,
+                        role: ChannelRole::from_state(&channel.role),
+                        name: settings.name,
+                        psk: include_psks.then(|| hex::encode(&settings.psk)),
+                        uplink_enabled: settings.uplink_enabled,
+                        downlink_enabled: settings.downlink_enabled,
+                        position_precision: settings
+                            .module_settings
+                            .map(|module| module.position_precision)
+                            .unwrap_or_default(),
+                    }
+                }
+                None => ChannelSlot {
+                    index,
+                    role: ChannelRole::Disabled,
+                    name: String::new(),
+                    psk: include_psks.then(String::new),
+                    uplink_enabled: false,
+                    downlink_enabled: false,
+                    position_precision: 0,
+                },
+            })
+            .collect();
+        Self { channels: slots }
+    }
+
+    /// Check slot indexes, roles and PSKs before anything is sent
+    ///
+    /// The firmware only accepts a single primary channel, in slot 0.
+    pub fn validate(&self) -> Result<()> {
+        let mut seen = Vec::new();
+        for slot in &self.channels {
+            let index = slot.index;
+            ensure!(
+                index < MAX_CHANNELS,
+                "Channel index {index} is out of range (0-{max})",
+                max = MAX_CHANNELS - 1
+            );
+            ensure!(!seen.contains(&index), "Channel {index} is listed twice");
+            seen.push(index);
+
+            match (index, slot.role) {
+                (0, ChannelRole::Primary) => {}
+                (0, role) => bail!("Channel 0 must be the primary channel, found {role}"),
+                (_, ChannelRole::Primary) => {
+                    bail!("Channel {index} cannot be primary; only channel 0 is")
+                }
+                _ => {}
+            }
+            if let Some(psk) = &slot.psk {
+                let psk = hex::decode(psk)
+                    .with_context(|| format!("Channel {index} PSK is not valid hex"))?;
+                ensure!(
+                    matches!(psk.len(), 0 | 1 | 16 | 32),
+                    "Channel {index} PSK must be 0, 1, 16 or 32 bytes, found {len}",
+                    len = psk.len()
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Load a channel set from a YAML or JSON file
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {path}", path = path.display()))?;
+        let set: Self = if is_json(path) {
+            serde_json::from_str(&text)?
+        } else {
+            serde_yaml::from_str(&text)?
+        };
+        set.validate()
+            .with_context(|| format!("Invalid channel file {path}", path = path.display()))?;
+        Ok(set)
+    }
+
+    /// Write the channel set as YAML, or JSON for a `.json` path
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let text = if is_json(path) {
+            serde_json::to_string_pretty(self)?
+        } else {
+            serde_yaml::to_string(self)?
+        };
+        std::fs::write(path, text)
+            .with_context(|| format!("Failed to write {path}", path = path.display()))
+    }
+}
+
+/// Channel set of the local device from the cached channels
+pub async fn export_channels(connection: &ConnectionManager, include_psks: bool) -> ChannelSet {
+    let state = connection.get_device_state().await;
+    ChannelSet::from_channels(&state.channels, include_psks)
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("json"))
+}
+
+/// Apply a channel set to the local device in one settings transaction
+///
+/// Slots missing from the set are left alone. A redacted slot keeps the PSK
+/// the device already has. Returns the number of slots written.
+pub async fn import_channels(
+    connection: &mut ConnectionManager,
+    set: &ChannelSet,
+) -> Result<usize> {
+    set.validate()?;
+    let current = connection.get_device_state().await.channels;
+
+    let mut channels = Vec::with_capacity(set.channels.len());
+    for slot in &set.channels {
+        let existing = current
+            .iter()
+            .find(|channel| channel.index == slot.index)
+            .and_then(|channel| channel.settings.clone());
+        let psk = match &slot.psk {
+            Some(psk) => hex::decode(psk)?,
+            None if slot.role == ChannelRole::Disabled => Vec::new(),
+            None => existing
+                .as_ref()
+                .map(|settings| settings.psk.clone())
+                .with_context(|| {
+                    format!(
+                        "Channel {index} has a redacted PSK and the device's is unknown",
+                        index = slot.index
+                    )
+                })?,
+        };
+
+        // Keep settings the file does not cover, such as the channel id
+        let mut settings = existing.unwrap_or_default();
+        settings.name = slot.name.clone();
+        settings.psk = psk;
+        settings.uplink_enabled = slot.uplink_enabled;
+        settings.downlink_enabled = slot.downlink_enabled;
+        settings
+            .module_settings
+            .get_or_insert_with(Default::default)
+            .position_precision = slot.position_precision;
+
+        channels.push(protobufs::Channel {
+            index: slot.index as i32,
+            settings: Some(settings),
+            role: slot.role.to_protobuf() as i32,
+        });
+    }
+
+    // Batch the writes so the device saves and restarts the radio once
+    send_admin_message(
+        connection,
+        AdminDestination::Local,
+        protobufs::admin_message::PayloadVariant::BeginEditSettings(true),
+    )
+    .await?;
+    for channel in &channels {
+        debug!("Writing channel {index}", index = channel.index);
+        send_admin_message(
+            connection,
+            AdminDestination::Local,
+            protobufs::admin_message::PayloadVariant::SetChannel(channel.clone()),
+        )
+        .await?;
+    }
+    send_admin_message(
+        connection,
+        AdminDestination::Local,
+        protobufs::admin_message::PayloadVariant::CommitEditSettings(true),
+    )
+    .await?;
+
+    let state = connection.get_device_state_ref();
+    let changes: Vec<_> = {
+        let mut state = state.lock().await;
+        channels
+            .into_iter()
+            .filter_map(|channel| state.update_channel(channel_info(channel)))
+            .collect()
+    };
+    connection.publish_changes(changes);
+
+    Ok(set.channels.len())
+}
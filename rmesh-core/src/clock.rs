@@ -0,0 +1,118 @@
+use crate::admin::{AdminDestination, send_admin_message};
+use crate::connection::{ConnectionManager, RequestResponse};
+use crate::node_id::NodeId;
+use crate::telemetry::TelemetryType;
+use anyhow::{Context, Result, bail};
+use meshtastic::{Message, protobufs};
+use serde::Serialize;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::debug;
+
+/// Drift beyond which a device clock counts as wrong, in seconds
+///
+/// Timestamps only carry whole seconds, so a few seconds are noise.
+pub const MAX_CLOCK_DRIFT_SECS: u64 = 60;
+
+/// Device clock compared with the host clock
+#[derive(Debug, Clone, Serialize)]
+pub struct ClockReport {
+    /// Node whose clock was read, e.g. `!abcd1234`
+    pub node: String,
+    /// Unix time the device reported, `None` when it has no valid time
+    pub device_time: Option<u64>,
+    /// Unix time of the host halfway through the request
+    pub host_time: u64,
+    /// Device time minus host time; positive when the device runs ahead
+    pub drift_secs: Option<i64>,
+    /// Time from the request to the reply
+    pub round_trip_ms: u64,
+    /// The host time was written to the device after the reading
+    pub synced: bool,
+}
+
+impl ClockReport {
+    /// Whether the device has no valid time or drifts beyond
+    /// [`MAX_CLOCK_DRIFT_SECS`]
+    pub fn needs_sync(&self) -> bool {
+        self.drift_secs
+            .is_none_or(|drift| drift.unsigned_abs() > MAX_CLOCK_DRIFT_SECS)
+    }
+}
+
+/// Seconds the device clock is ahead of the host, given the request was
+/// sent at `sent_at` (Unix seconds) and answered after `round_trip`
+///
+/// The reply is taken to be stamped halfway through the round trip.
+pub(crate) fn clock_drift(device_time: u64, sent_at: f64, round_trip: Duration) -> i64 {
+    let host_time = sent_at + round_trip.as_secs_f64() / 2.0;
+    (device_time as f64 - host_time).round() as i64
+}
+
+fn unix_now_f64() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
+
+/// Read the local device's clock from the timestamp of a telemetry reply
+///
+/// Devices without GPS or a phone setting their time report 0, which shows
+/// as no device time.
+pub async fn measure_clock(
+    connection: &mut ConnectionManager,
+    timeout: Duration,
+) -> Result<ClockReport> {
+    let state = connection.get_device_state().await;
+    let node_num = state
+        .my_node_info
+        .as_ref()
+        .map(|info| info.node_num)
+        .context("Local node information is not available")?;
+
+    let sent_at = unix_now_f64();
+    let started = tokio::time::Instant::now();
+    let pending = connection
+        .send_request(
+            node_num,
+            protobufs::PortNum::TelemetryApp,
+            TelemetryType::Device.request().encode_to_vec(),
+        )
+        .await?;
+    let telemetry = match pending.wait(timeout).await {
+        Some(RequestResponse::Telemetry(telemetry)) => telemetry,
+        Some(other) => bail!("Unexpected response to the telemetry request: {other:?}"),
+        None => bail!(
+            "No telemetry reply from the device within {secs}s",
+            secs = timeout.as_secs()
+        ),
+    };
+    let round_trip = started.elapsed();
+    debug!(
+        "Device reported time {time} after {round_trip:?}",
+        time = telemetry.time
+    );
+
+    let device_time = Some(telemetry.time).filter(|time| *time > 0);
+    Ok(ClockReport {
+        node: NodeId(node_num).to_string(),
+        device_time,
+        host_time: (sent_at + round_trip.as_secs_f64() / 2.0) as u64,
+        drift_secs: device_time.map(|time| clock_drift(time, sent_at, round_trip)),
+        round_trip_ms: u64::try_from(round_trip.as_millis()).unwrap_or(u64::MAX),
+        synced: false,
+    })
+}
+
+/// Set the local device's clock to the host time
+pub async fn sync_clock(connection: &mut ConnectionManager) -> Result<()> {
+    let now = u32::try_from(crate::time::unix_now()).context("Host time is out of range")?;
+    send_admin_message(
+        connection,
+        AdminDestination::Local,
+        protobufs::admin_message::PayloadVariant::SetTimeOnly(now),
+    )
+    .await?;
+    debug!("Set the device time to {now}");
+    Ok(())
+}
@@ -1,14 +1,56 @@
+use crate::admin::{AdminDestination, send_admin_message};
 use crate::connection::ConnectionManager;
-use anyhow::{Result, bail, ensure};
+use crate::lora;
+use crate::state::{
+    BluetoothConfig, DeviceConfig, DeviceState, DisplayConfig, LoraConfig, NeighborInfoConfig,
+    NetworkConfig, PositionConfig, PowerConfig, TelemetryConfig,
+};
+use anyhow::{Context, Result, bail, ensure};
 use meshtastic::{Message, protobufs};
-use serde_json::json;
+use serde::Serialize;
+use serde_json::{Value, json};
+use std::str::FromStr;
+use strum::{Display, EnumString};
 use tracing::debug;
 
+/// A single configuration value
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigValue {
+    pub key: String,
+    /// The value, or null if the device has not reported it
+    pub value: serde_json::Value,
+}
+
+/// All configuration sections, each null until the device reports it
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ConfigListing {
+    pub device: Option<DeviceConfig>,
+    pub position: Option<PositionConfig>,
+    pub power: Option<PowerConfig>,
+    pub network: Option<NetworkConfig>,
+    pub display: Option<DisplayConfig>,
+    pub lora: Option<LoraConfig>,
+    pub bluetooth: Option<BluetoothConfig>,
+}
+
+impl ConfigListing {
+    /// Whether no section has been reported yet
+    pub fn is_empty(&self) -> bool {
+        self.device.is_none()
+            && self.position.is_none()
+            && self.power.is_none()
+            && self.network.is_none()
+            && self.display.is_none()
+            && self.lora.is_none()
+            && self.bluetooth.is_none()
+    }
+}
+
 /// Get a configuration value by key
 pub async fn get_config_value(
     connection: &mut ConnectionManager,
     key: &str,
-) -> Result<serde_json::Value> {
+) -> Result<ConfigValue> {
     // Try to get a session key, but continue even if it fails
     // Some devices may not require authentication
     if let Err(e) = connection.ensure_session_key().await {
@@ -29,7 +71,7 @@ pub async fn get_config_value(
     let session_key = connection.get_session_key().await.unwrap_or_default();
 
     // Send config request
-    let api = connection.get_api()?;
+    let packet_id = connection.next_packet_id();
 
     // Create the appropriate config request based on category
     let config_type = match category {
@@ -62,7 +104,7 @@ pub async fn get_config_value(
         )),
         from: 0,
         to: 0, // Local destination
-        id: 0,
+        id: packet_id,
         rx_time: 0,
         rx_snr: 0.0,
         hop_limit: 0,
@@ -75,10 +117,7 @@ pub async fn get_config_value(
     };
 
     // Send as ToRadio packet
-    api.send_to_radio_packet(Some(protobufs::to_radio::PayloadVariant::Packet(
-        mesh_packet,
-    )))
-    .await?;
+    connection.send_mesh_packet(mesh_packet).await?;
 
     // Wait a moment for the response to be processed
     tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
@@ -135,132 +174,611 @@ pub async fn get_config_value(
                     "tx_power" => json!(config.tx_power),
                     "channel_num" => json!(config.channel_num),
                     "ignore_mqtt" => json!(config.ignore_mqtt),
+                    "override_frequency" => json!(config.override_frequency),
+                    "override_duty_cycle" => json!(config.override_duty_cycle),
+                    "sx126x_rx_boosted_gain" => json!(config.sx126x_rx_boosted_gain),
                     _ => bail!("Unknown lora config field: {field}"),
                 }
             } else {
                 json!(null)
             }
         }
+        "network" => {
+            if let Some(config) = &state.network_config {
+                match field {
+                    "wifi_enabled" => json!(config.wifi_enabled),
+                    "wifi_ssid" => json!(config.wifi_ssid),
+                    "wifi_psk" => json!(config.wifi_psk),
+                    "ntp_server" => json!(config.ntp_server),
+                    "eth_enabled" => json!(config.eth_enabled),
+                    "ipv4_config" => json!(config.ipv4_config),
+                    _ => bail!("Unknown network config field: {field}"),
+                }
+            } else {
+                json!(null)
+            }
+        }
         _ => json!(null),
     };
 
-    Ok(json!({
-        "key": key,
-        "value": value
-    }))
+    Ok(ConfigValue {
+        key: key.to_string(),
+        value,
+    })
 }
 
-/// Set a configuration value by key
-pub async fn set_config_value(
-    connection: &mut ConnectionManager,
+/// How `config set` reads a value, instead of following the current one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, EnumString)]
+#[strum(serialize_all = "lowercase", ascii_case_insensitive)]
+pub enum ConfigValueType {
+    Bool,
+    Int,
+    Float,
+    String,
+    /// Name of a value of the field's enum, e.g. ROUTER for device.role
+    Enum,
+}
+
+/// One `key=value` pair of `config set`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigAssignment {
+    /// Section then fields, dotted, e.g. mqtt.map_report_settings.publish_interval_secs
+    pub key: String,
+    pub value: String,
+}
+
+impl ConfigAssignment {
+    pub fn new(key: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            key: key.into(),
+            value: value.into(),
+        }
+    }
+}
+
+impl FromStr for ConfigAssignment {
+    type Err = anyhow::Error;
+
+    fn from_str(pair: &str) -> Result<Self> {
+        let (key, value) = pair
+            .split_once('=')
+            .with_context(|| format!("Expected key=value, got '{pair}'"))?;
+        let key = key.trim();
+        ensure!(!key.is_empty(), "Missing key in '{pair}'");
+        Ok(Self::new(key, value))
+    }
+}
+
+/// Parser of an enum value given by name
+type EnumParser = fn(&str) -> Result<i32>;
+
+/// Enum fields whose values `config set` takes by name
+const ENUM_FIELDS: &[(&str, EnumParser)] = &[
+    ("device.role", |value| Ok(parse_role(value)? as i32)),
+    ("device.rebroadcast_mode", |value| {
+        proto_enum(
+            value,
+            protobufs::config::device_config::RebroadcastMode::from_str_name,
+        )
+    }),
+    ("position.gps_mode", |value| {
+        proto_enum(
+            value,
+            protobufs::config::position_config::GpsMode::from_str_name,
+        )
+    }),
+    ("display.units", |value| {
+        proto_enum(
+            value,
+            protobufs::config::display_config::DisplayUnits::from_str_name,
+        )
+    }),
+    ("display.displaymode", |value| {
+        proto_enum(
+            value,
+            protobufs::config::display_config::DisplayMode::from_str_name,
+        )
+    }),
+    ("lora.region", |value| Ok(parse_region(value)? as i32)),
+    ("lora.modem_preset", |value| {
+        proto_enum(
+            value,
+            protobufs::config::lo_ra_config::ModemPreset::from_str_name,
+        )
+    }),
+    ("bluetooth.mode", |value| {
+        proto_enum(
+            value,
+            protobufs::config::bluetooth_config::PairingMode::from_str_name,
+        )
+    }),
+];
+
+fn proto_enum<E: Into<i32>>(value: &str, from_str_name: fn(&str) -> Option<E>) -> Result<i32> {
+    from_str_name(&value.to_uppercase().replace('-', "_"))
+        .map(Into::into)
+        .with_context(|| format!("Unknown value: {value}"))
+}
+
+/// Field names compare alike in snake_case and camelCase
+fn same_field(a: &str, b: &str) -> bool {
+    let normalize = |name: &str| name.replace('_', "").to_lowercase();
+    normalize(a) == normalize(b)
+}
+
+fn enum_field(key: &str) -> Option<fn(&str) -> Result<i32>> {
+    ENUM_FIELDS
+        .iter()
+        .find(|(field, _)| {
+            field.split('.').count() == key.split('.').count()
+                && field
+                    .split('.')
+                    .zip(key.split('.'))
+                    .all(|(a, b)| same_field(a, b))
+        })
+        .map(|(_, parse)| *parse)
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    value.to_lowercase().parse().ok()
+}
+
+/// Type of a value typed for a field the device left unset
+fn infer_value_type(value: &str) -> ConfigValueType {
+    if parse_bool(value).is_some() {
+        ConfigValueType::Bool
+    } else if value.parse::<i64>().is_ok() {
+        ConfigValueType::Int
+    } else if value.parse::<f64>().is_ok() {
+        ConfigValueType::Float
+    } else {
+        ConfigValueType::String
+    }
+}
+
+/// Parse the text of a value for a field currently holding `current`
+fn parse_config_value(
     key: &str,
+    current: &Value,
     value: &str,
+    value_type: Option<ConfigValueType>,
+) -> Result<Value> {
+    let value_type = match (value_type, current) {
+        (Some(value_type), _) => value_type,
+        (None, Value::Bool(_)) => ConfigValueType::Bool,
+        (None, Value::Number(number)) if number.is_f64() => ConfigValueType::Float,
+        // Enums are numbers on the wire, but are typed by name
+        (None, Value::Number(_)) if enum_field(key).is_some() && value.parse::<i64>().is_err() => {
+            ConfigValueType::Enum
+        }
+        (None, Value::Number(_)) => ConfigValueType::Int,
+        (None, Value::String(_)) => ConfigValueType::String,
+        (None, Value::Array(_)) => bail!("{key} is a list, which config set cannot change"),
+        (None, _) => infer_value_type(value),
+    };
+
+    let parsed = match value_type {
+        ConfigValueType::Bool => Value::Bool(
+            parse_bool(value).with_context(|| format!("Invalid boolean for {key}: {value}"))?,
+        ),
+        ConfigValueType::Int => json!(
+            value
+                .parse::<i64>()
+                .with_context(|| format!("Invalid integer for {key}: {value}"))?
+        ),
+        ConfigValueType::Float => json!(
+            value
+                .parse::<f64>()
+                .with_context(|| format!("Invalid number for {key}: {value}"))?
+        ),
+        ConfigValueType::String => json!(value),
+        ConfigValueType::Enum => {
+            let parse = enum_field(key).with_context(|| format!("{key} is not an enum field"))?;
+            json!(parse(value).with_context(|| format!("Invalid value for {key}"))?)
+        }
+    };
+    Ok(parsed)
+}
+
+/// Set a field of a configuration section serialized to JSON
+///
+/// The key's first part names the section and the rest lead to the field,
+/// in snake_case or camelCase. The value takes the type of the current one
+/// unless `value_type` is given; a field without a value infers it from the
+/// text: bool, integer, float, then string.
+pub(crate) fn apply_assignment(
+    section: &mut Value,
+    assignment: &ConfigAssignment,
+    value_type: Option<ConfigValueType>,
 ) -> Result<()> {
-    // Try to get a session key, but continue even if it fails
-    // Some devices may not require authentication
-    if let Err(e) = connection.ensure_session_key().await {
-        debug!("Failed to get session key (may not be required): {e}");
-    }
+    let key = assignment.key.as_str();
+    let fields: Vec<&str> = key.split('.').skip(1).collect();
+    ensure!(
+        !fields.is_empty(),
+        "Invalid config key '{key}'. Use format: section.field (e.g., lora.region)"
+    );
 
-    // Get the session key
-    let session_key = connection.get_session_key().await.unwrap_or_default();
+    let mut target = section;
+    for field in fields {
+        let object = match target {
+            Value::Object(object) => object,
+            Value::Null => {
+                bail!("Cannot set {key}: the device has not reported the settings around {field}")
+            }
+            _ => bail!("Unknown config field: {key}"),
+        };
+        target = object
+            .iter_mut()
+            .find(|(name, _)| same_field(name, field))
+            .map(|(_, value)| value)
+            .with_context(|| format!("Unknown config field: {key}"))?;
+    }
+    ensure!(
+        !target.is_object(),
+        "{key} is a group of settings; set one of its fields"
+    );
 
-    let api = connection.get_api()?;
+    *target = parse_config_value(key, target, &assignment.value, value_type)?;
+    Ok(())
+}
 
-    let parts: Vec<&str> = key.split('.').collect();
+/// Module configuration sections, by the names `config get` takes
+pub(crate) const MODULE_SECTIONS: &[&str] = &[
+    "mqtt",
+    "serial",
+    "external_notification",
+    "store_forward",
+    "range_test",
+    "telemetry",
+    "canned_message",
+    "audio",
+    "remote_hardware",
+    "neighbor_info",
+    "ambient_lighting",
+    "detection_sensor",
+    "paxcounter",
+];
+
+/// A section as the device last reported it, as JSON
+fn raw_section(state: &DeviceState, section: &str) -> Result<Value> {
+    let config = &state.raw_config;
+    let value = match section {
+        "device" => serde_json::to_value(&config.device)?,
+        "position" => serde_json::to_value(config.position)?,
+        "power" => serde_json::to_value(config.power)?,
+        "network" => serde_json::to_value(&config.network)?,
+        "display" => serde_json::to_value(config.display)?,
+        "lora" => serde_json::to_value(&config.lora)?,
+        "bluetooth" => serde_json::to_value(config.bluetooth)?,
+        "security" => serde_json::to_value(&config.security)?,
+        _ => module_section(&state.raw_module_config, section)?,
+    };
     ensure!(
-        parts.len() == 2,
-        "Invalid config key format. Use format: category.field (e.g., lora.region)"
+        !value.is_null(),
+        "The device has not reported its {section} settings"
     );
+    Ok(value)
+}
 
-    let category = parts[0];
-    let field = parts[1];
+/// A module configuration section as JSON, null if it was not reported
+pub(crate) fn module_section(
+    module: &protobufs::LocalModuleConfig,
+    section: &str,
+) -> Result<Value> {
+    let value = match section {
+        "mqtt" => serde_json::to_value(&module.mqtt),
+        "serial" => serde_json::to_value(module.serial),
+        "external_notification" => serde_json::to_value(module.external_notification),
+        "store_forward" => serde_json::to_value(module.store_forward),
+        "range_test" => serde_json::to_value(module.range_test),
+        "telemetry" => serde_json::to_value(module.telemetry),
+        "canned_message" => serde_json::to_value(&module.canned_message),
+        "audio" => serde_json::to_value(module.audio),
+        "remote_hardware" => serde_json::to_value(&module.remote_hardware),
+        "neighbor_info" => serde_json::to_value(module.neighbor_info),
+        "ambient_lighting" => serde_json::to_value(module.ambient_lighting),
+        "detection_sensor" => serde_json::to_value(&module.detection_sensor),
+        "paxcounter" => serde_json::to_value(module.paxcounter),
+        _ => bail!("Unknown config section: {section}"),
+    }?;
+    Ok(value)
+}
 
-    // Create admin message for config change
-    let admin_msg = match category {
+/// Admin payload writing a section back
+fn section_payload(
+    section: &str,
+    value: Value,
+) -> Result<protobufs::admin_message::PayloadVariant> {
+    use protobufs::config::PayloadVariant as Config;
+    use protobufs::module_config::PayloadVariant as Module;
+
+    let config = match section {
+        "device" => Config::Device(serde_json::from_value(value)?),
+        "position" => Config::Position(serde_json::from_value(value)?),
+        "power" => Config::Power(serde_json::from_value(value)?),
+        "network" => Config::Network(serde_json::from_value(value)?),
+        "display" => Config::Display(serde_json::from_value(value)?),
         "lora" => {
-            match field {
-                "region" => {
-                    // Parse region enum
-                    let region = parse_region(value)?;
-                    let config = protobufs::config::LoRaConfig {
-                        region: region as i32,
-                        ..Default::default()
-                    };
-                    protobufs::AdminMessage {
-                        payload_variant: Some(protobufs::admin_message::PayloadVariant::SetConfig(
-                            protobufs::Config {
-                                payload_variant: Some(protobufs::config::PayloadVariant::Lora(
-                                    config,
-                                )),
-                            },
-                        )),
-                        session_passkey: session_key.clone(),
-                    }
-                }
-                _ => bail!("Unknown lora field: {field}"),
-            }
+            let lora: protobufs::config::LoRaConfig = serde_json::from_value(value)?;
+            // A region change can leave an existing override out of band
+            lora::check_override_frequency(
+                &lora.region().as_str_name().replace('_', ""),
+                lora.override_frequency,
+            )?;
+            Config::Lora(lora)
         }
-        "device" => {
-            match field {
-                "role" => {
-                    // Parse role enum
-                    let role = parse_role(value)?;
-                    let config = protobufs::config::DeviceConfig {
-                        role: role as i32,
-                        ..Default::default()
-                    };
-                    protobufs::AdminMessage {
-                        payload_variant: Some(protobufs::admin_message::PayloadVariant::SetConfig(
-                            protobufs::Config {
-                                payload_variant: Some(protobufs::config::PayloadVariant::Device(
-                                    config,
-                                )),
-                            },
-                        )),
-                        session_passkey: session_key.clone(),
-                    }
+        "bluetooth" => Config::Bluetooth(serde_json::from_value(value)?),
+        "security" => Config::Security(serde_json::from_value(value)?),
+        _ => {
+            let module = match section {
+                "mqtt" => Module::Mqtt(serde_json::from_value(value)?),
+                "serial" => Module::Serial(serde_json::from_value(value)?),
+                "external_notification" => {
+                    Module::ExternalNotification(serde_json::from_value(value)?)
                 }
-                _ => bail!("Unknown device field: {field}"),
-            }
+                "store_forward" => Module::StoreForward(serde_json::from_value(value)?),
+                "range_test" => Module::RangeTest(serde_json::from_value(value)?),
+                "telemetry" => Module::Telemetry(serde_json::from_value(value)?),
+                "canned_message" => Module::CannedMessage(serde_json::from_value(value)?),
+                "audio" => Module::Audio(serde_json::from_value(value)?),
+                "remote_hardware" => Module::RemoteHardware(serde_json::from_value(value)?),
+                "neighbor_info" => Module::NeighborInfo(serde_json::from_value(value)?),
+                "ambient_lighting" => Module::AmbientLighting(serde_json::from_value(value)?),
+                "detection_sensor" => Module::DetectionSensor(serde_json::from_value(value)?),
+                "paxcounter" => Module::Paxcounter(serde_json::from_value(value)?),
+                _ => bail!("Unknown config section: {section}"),
+            };
+            return Ok(protobufs::admin_message::PayloadVariant::SetModuleConfig(
+                protobufs::ModuleConfig {
+                    payload_variant: Some(module),
+                },
+            ));
         }
-        _ => bail!("Config category '{category}' not yet implemented"),
     };
+    Ok(protobufs::admin_message::PayloadVariant::SetConfig(
+        protobufs::Config {
+            payload_variant: Some(config),
+        },
+    ))
+}
 
-    // Create mesh packet
-    let mesh_packet = protobufs::MeshPacket {
-        payload_variant: Some(protobufs::mesh_packet::PayloadVariant::Decoded(
-            protobufs::Data {
-                portnum: protobufs::PortNum::AdminApp as i32,
-                payload: admin_msg.encode_to_vec(),
-                ..Default::default()
-            },
-        )),
-        from: 0,
-        to: 0, // Local destination
-        id: 0,
-        rx_time: 0,
-        rx_snr: 0.0,
-        hop_limit: 0,
-        want_ack: false,
-        priority: protobufs::mesh_packet::Priority::Default as i32,
-        rx_rssi: 0,
-        via_mqtt: false,
-        hop_start: 0,
-        ..Default::default()
-    };
+/// Set configuration values in one admin transaction
+///
+/// Keys are dotted paths such as lora.hop_limit or
+/// mqtt.map_report_settings.publish_interval_secs. Each section touched is
+/// sent whole, starting from the one the device reported during the
+/// handshake, and the device applies all of them at once.
+pub async fn set_config_values(
+    connection: &mut ConnectionManager,
+    assignments: &[ConfigAssignment],
+    value_type: Option<ConfigValueType>,
+) -> Result<()> {
+    ensure!(!assignments.is_empty(), "No configuration values given");
 
-    // Send as ToRadio packet
-    api.send_to_radio_packet(Some(protobufs::to_radio::PayloadVariant::Packet(
-        mesh_packet,
-    )))
+    let state = connection.get_device_state().await;
+    // Sections in the order they are first named
+    let mut sections: Vec<(&str, Value)> = Vec::new();
+    for assignment in assignments {
+        let section = assignment.key.split('.').next().unwrap_or_default();
+        let index = match sections.iter().position(|(name, _)| *name == section) {
+            Some(index) => index,
+            None => {
+                sections.push((section, raw_section(&state, section)?));
+                sections.len() - 1
+            }
+        };
+        apply_assignment(&mut sections[index].1, assignment, value_type)?;
+    }
+    let payloads = sections
+        .into_iter()
+        .map(|(section, value)| {
+            section_payload(section, value).with_context(|| format!("Invalid {section} settings"))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    send_admin_message(
+        connection,
+        AdminDestination::Local,
+        protobufs::admin_message::PayloadVariant::BeginEditSettings(true),
+    )
+    .await?;
+    for payload in &payloads {
+        send_admin_message(connection, AdminDestination::Local, payload.clone()).await?;
+    }
+    send_admin_message(
+        connection,
+        AdminDestination::Local,
+        protobufs::admin_message::PayloadVariant::CommitEditSettings(true),
+    )
     .await?;
 
+    // Later changes in this session start from the values just written
+    let state = connection.get_device_state_ref();
+    let mut state = state.lock().await;
+    let changes: Vec<_> = payloads
+        .into_iter()
+        .filter_map(|payload| match payload {
+            protobufs::admin_message::PayloadVariant::SetConfig(protobufs::Config {
+                payload_variant: Some(config),
+            }) => state.store_raw_config(&config),
+            protobufs::admin_message::PayloadVariant::SetModuleConfig(
+                protobufs::ModuleConfig {
+                    payload_variant: Some(module),
+                },
+            ) => state.store_raw_module_config(&module),
+            _ => None,
+        })
+        .collect();
+    drop(state);
+    connection.publish_changes(changes);
+
     Ok(())
 }
 
+/// Set a configuration value by key, see [`set_config_values`]
+pub async fn set_config_value(
+    connection: &mut ConnectionManager,
+    key: &str,
+    value: &str,
+) -> Result<()> {
+    set_config_values(connection, &[ConfigAssignment::new(key, value)], None).await
+}
+
+/// Shortest NeighborInfo interval firmware 2.5 and later accept; shorter
+/// ones are raised to it
+pub const MIN_NEIGHBOR_INFO_INTERVAL_SECS: u32 = 4 * 60 * 60;
+
+/// Changes to the NeighborInfo module, `None` keeping the current value
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NeighborInfoUpdate {
+    pub enabled: Option<bool>,
+    pub update_interval: Option<u32>,
+}
+
+impl NeighborInfoUpdate {
+    pub fn is_empty(&self) -> bool {
+        self.enabled.is_none() && self.update_interval.is_none()
+    }
+
+    /// Apply the changes on top of the current settings
+    pub fn apply(&self, current: Option<&NeighborInfoConfig>) -> NeighborInfoConfig {
+        NeighborInfoConfig {
+            enabled: self
+                .enabled
+                .or(current.map(|config| config.enabled))
+                .unwrap_or(false),
+            update_interval: self
+                .update_interval
+                .or(current.map(|config| config.update_interval))
+                .unwrap_or(0),
+        }
+    }
+}
+
+/// NeighborInfo module settings reported by the local node
+pub async fn get_neighbor_info(connection: &ConnectionManager) -> Option<NeighborInfoConfig> {
+    connection.get_device_state().await.neighbor_info_config
+}
+
+/// Configure the NeighborInfo module of the local node
+///
+/// The module config is replaced as a whole, so unchanged settings are
+/// carried over from the ones reported during the handshake. Returns the
+/// settings sent.
+pub async fn set_neighbor_info(
+    connection: &mut ConnectionManager,
+    update: NeighborInfoUpdate,
+) -> Result<NeighborInfoConfig> {
+    ensure!(
+        !update.is_empty(),
+        "Nothing to change; give --enabled, --disabled or --interval"
+    );
+
+    let current = get_neighbor_info(connection).await;
+    let config = update.apply(current.as_ref());
+
+    send_admin_message(
+        connection,
+        AdminDestination::Local,
+        protobufs::admin_message::PayloadVariant::SetModuleConfig(protobufs::ModuleConfig {
+            payload_variant: Some(protobufs::module_config::PayloadVariant::NeighborInfo(
+                protobufs::module_config::NeighborInfoConfig {
+                    enabled: config.enabled,
+                    update_interval: config.update_interval,
+                    ..Default::default()
+                },
+            )),
+        }),
+    )
+    .await?;
+
+    Ok(config)
+}
+
+/// Changes to the Telemetry module, `None` keeping the current value
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TelemetryConfigUpdate {
+    pub device_update_interval: Option<u32>,
+    pub environment_update_interval: Option<u32>,
+    pub environment_measurement_enabled: Option<bool>,
+    pub air_quality_enabled: Option<bool>,
+}
+
+impl TelemetryConfigUpdate {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Apply the changes on top of the current settings
+    pub fn apply(&self, current: Option<&TelemetryConfig>) -> TelemetryConfig {
+        let current = current.cloned().unwrap_or_default();
+        TelemetryConfig {
+            device_update_interval: self
+                .device_update_interval
+                .unwrap_or(current.device_update_interval),
+            environment_update_interval: self
+                .environment_update_interval
+                .unwrap_or(current.environment_update_interval),
+            environment_measurement_enabled: self
+                .environment_measurement_enabled
+                .unwrap_or(current.environment_measurement_enabled),
+            air_quality_enabled: self
+                .air_quality_enabled
+                .unwrap_or(current.air_quality_enabled),
+            ..current
+        }
+    }
+}
+
+/// Telemetry module settings reported by the local node
+pub async fn get_telemetry_config(connection: &ConnectionManager) -> Option<TelemetryConfig> {
+    connection.get_device_state().await.telemetry_config
+}
+
+/// Configure the Telemetry module of the local node
+///
+/// Like [`set_neighbor_info`], settings without a change are carried over
+/// from the ones reported during the handshake. Returns the settings sent.
+pub async fn set_telemetry_config(
+    connection: &mut ConnectionManager,
+    update: TelemetryConfigUpdate,
+) -> Result<TelemetryConfig> {
+    ensure!(
+        !update.is_empty(),
+        "Nothing to change; give an interval or a module to turn on or off"
+    );
+
+    let current = get_telemetry_config(connection).await;
+    let config = update.apply(current.as_ref());
+
+    send_admin_message(
+        connection,
+        AdminDestination::Local,
+        protobufs::admin_message::PayloadVariant::SetModuleConfig(protobufs::ModuleConfig {
+            payload_variant: Some(protobufs::module_config::PayloadVariant::Telemetry(
+                protobufs::module_config::TelemetryConfig {
+                    device_update_interval: config.device_update_interval,
+                    environment_update_interval: config.environment_update_interval,
+                    environment_measurement_enabled: config.environment_measurement_enabled,
+                    environment_screen_enabled: config.environment_screen_enabled,
+                    environment_display_fahrenheit: config.environment_display_fahrenheit,
+                    air_quality_enabled: config.air_quality_enabled,
+                    air_quality_interval: config.air_quality_interval,
+                    power_measurement_enabled: config.power_measurement_enabled,
+                    power_update_interval: config.power_update_interval,
+                    power_screen_enabled: config.power_screen_enabled,
+                    ..Default::default()
+                },
+            )),
+        }),
+    )
+    .await?;
+
+    Ok(config)
+}
+
 /// List all configuration settings
-pub async fn list_config(connection: &mut ConnectionManager) -> Result<serde_json::Value> {
+pub async fn list_config(connection: &mut ConnectionManager) -> Result<ConfigListing> {
     // Try to get a session key, but continue even if it fails
     // Some devices may not require authentication
     if let Err(e) = connection.ensure_session_key().await {
@@ -270,8 +788,6 @@ pub async fn list_config(connection: &mut ConnectionManager) -> Result<serde_jso
     // Get the session key
     let session_key = connection.get_session_key().await.unwrap_or_default();
 
-    let api = connection.get_api()?;
-
     // Request all config types to get fresh data
     let config_types = [
         protobufs::admin_message::ConfigType::DeviceConfig,
@@ -307,10 +823,7 @@ pub async fn list_config(connection: &mut ConnectionManager) -> Result<serde_jso
         };
 
         // Send config request
-        api.send_to_radio_packet(Some(protobufs::to_radio::PayloadVariant::Packet(
-            mesh_packet,
-        )))
-        .await?;
+        connection.send_mesh_packet(mesh_packet).await?;
 
         // Small delay between requests
         tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
@@ -323,110 +836,15 @@ pub async fn list_config(connection: &mut ConnectionManager) -> Result<serde_jso
     let state = connection.get_device_state().await;
 
     // Build complete configuration from cached state
-    let mut config = json!({});
-
-    // Add device config if available
-    if let Some(device_cfg) = &state.device_config {
-        config["device"] = json!({
-            "role": device_cfg.role,
-            "button_gpio": device_cfg.button_gpio,
-            "buzzer_gpio": device_cfg.buzzer_gpio,
-            "rebroadcast_mode": device_cfg.rebroadcast_mode,
-            "node_info_broadcast_secs": device_cfg.node_info_broadcast_secs,
-            "tzdef": device_cfg.tzdef,
-            "disable_triple_click": device_cfg.disable_triple_click,
-        });
-    }
-
-    // Add position config if available
-    if let Some(pos_cfg) = &state.position_config {
-        config["position"] = json!({
-            "position_broadcast_secs": pos_cfg.position_broadcast_secs,
-            "position_broadcast_smart_enabled": pos_cfg.position_broadcast_smart_enabled,
-            "fixed_position": pos_cfg.fixed_position,
-            "gps_enabled": pos_cfg.gps_enabled,
-            "gps_mode": pos_cfg.gps_mode,
-        });
-    }
-
-    // Add power config if available
-    if let Some(power_cfg) = &state.power_config {
-        config["power"] = json!({
-            "is_power_saving": power_cfg.is_power_saving,
-            "on_battery_shutdown_after_secs": power_cfg.on_battery_shutdown_after_secs,
-            "adc_multiplier_override": power_cfg.adc_multiplier_override,
-            "wait_bluetooth_secs": power_cfg.wait_bluetooth_secs,
-            "sds_secs": power_cfg.sds_secs,
-            "ls_secs": power_cfg.ls_secs,
-            "min_wake_secs": power_cfg.min_wake_secs,
-        });
-    }
-
-    // Add network config if available
-    if let Some(net_cfg) = &state.network_config {
-        config["network"] = json!({
-            "wifi_enabled": net_cfg.wifi_enabled,
-            "wifi_ssid": net_cfg.wifi_ssid,
-            "wifi_psk": net_cfg.wifi_psk,
-            "ntp_server": net_cfg.ntp_server,
-            "eth_enabled": net_cfg.eth_enabled,
-            "ipv4_config": net_cfg.ipv4_config,
-        });
-    }
-
-    // Add display config if available
-    if let Some(display_cfg) = &state.display_config {
-        config["display"] = json!({
-            "screen_on_secs": display_cfg.screen_on_secs,
-            "gps_format": display_cfg.gps_format,
-            "auto_screen_carousel_secs": display_cfg.auto_screen_carousel_secs,
-            "compass_north_top": display_cfg.compass_north_top,
-            "flip_screen": display_cfg.flip_screen,
-            "units": display_cfg.units,
-            "displaymode": display_cfg.displaymode,
-            "heading_bold": display_cfg.heading_bold,
-            "wake_on_tap_or_motion": display_cfg.wake_on_tap_or_motion,
-        });
-    }
-
-    // Add LoRa config if available
-    if let Some(lora_cfg) = &state.lora_config {
-        config["lora"] = json!({
-            "use_preset": lora_cfg.use_preset,
-            "modem_preset": lora_cfg.modem_preset,
-            "bandwidth": lora_cfg.bandwidth,
-            "spread_factor": lora_cfg.spread_factor,
-            "coding_rate": lora_cfg.coding_rate,
-            "frequency_offset": lora_cfg.frequency_offset,
-            "region": lora_cfg.region,
-            "hop_limit": lora_cfg.hop_limit,
-            "tx_enabled": lora_cfg.tx_enabled,
-            "tx_power": lora_cfg.tx_power,
-            "channel_num": lora_cfg.channel_num,
-            "ignore_mqtt": lora_cfg.ignore_mqtt,
-        });
-    }
-
-    // Add Bluetooth config if available
-    if let Some(bt_cfg) = &state.bluetooth_config {
-        config["bluetooth"] = json!({
-            "enabled": bt_cfg.enabled,
-            "mode": bt_cfg.mode,
-            "fixed_pin": bt_cfg.fixed_pin,
-            "device_logging_enabled": bt_cfg.device_logging_enabled,
-        });
-    }
-
-    // Return the complete configuration
-    if config.as_object().is_none_or(|o| o.is_empty()) {
-        Ok(json!({
-            "status": "no_config",
-            "message": "No configuration data available. Device may not be fully synchronized.",
-            "hint": "Try running 'rmesh device refresh' to fetch latest configuration"
-        }))
-    } else {
-        Ok(config)
-    }
+    Ok(ConfigListing {
+        device: state.device_config,
+        position: state.position_config,
+        power: state.power_config,
+        network: state.network_config,
+        display: state.display_config,
+        lora: state.lora_config,
+        bluetooth: state.bluetooth_config,
+    })
 }
 
 fn parse_region(value: &str) -> Result<protobufs::config::lo_ra_config::RegionCode> {
@@ -447,17 +865,21 @@ fn parse_region(value: &str) -> Result<protobufs::config::lo_ra_config::RegionCo
         "TH" => RegionCode::Th,
         "UA433" | "UA_433" => RegionCode::Ua433,
         "UA868" | "UA_868" => RegionCode::Ua868,
-        "MY_433" => RegionCode::My433,
-        "MY_919" => RegionCode::My919,
-        "SG_923" => RegionCode::Sg923,
-        "LORA_24" => RegionCode::Lora24,
+        "MY433" | "MY_433" => RegionCode::My433,
+        "MY919" | "MY_919" => RegionCode::My919,
+        "SG923" | "SG_923" => RegionCode::Sg923,
+        "LORA24" | "LORA_24" => RegionCode::Lora24,
+        "PH433" | "PH_433" => RegionCode::Ph433,
+        "PH868" | "PH_868" => RegionCode::Ph868,
+        "PH915" | "PH_915" => RegionCode::Ph915,
         _ => bail!("Unknown region: {value}"),
     };
 
     Ok(region)
 }
 
-fn parse_role(value: &str) -> Result<protobufs::config::device_config::Role> {
+/// Parse a device role name such as "CLIENT_MUTE" (case-insensitive)
+pub fn parse_role(value: &str) -> Result<protobufs::config::device_config::Role> {
     use protobufs::config::device_config::Role;
 
     let role = match value.to_uppercase().as_str() {
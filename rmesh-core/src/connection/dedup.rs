@@ -0,0 +1,85 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// Default number of recently seen packets remembered by the filter
+const DEFAULT_CAPACITY: usize = 512;
+
+/// Default time a packet is remembered after it was first seen
+const DEFAULT_TTL: Duration = Duration::from_secs(600);
+
+/// Time-bounded LRU of recently seen `(from, id)` packet pairs
+///
+/// Mesh rebroadcasts deliver the same packet several times. The packet
+/// processor consults this filter so each packet is only handled once.
+#[derive(Debug)]
+pub struct DuplicateFilter {
+    seen: HashMap<(u32, u32), Instant>,
+    order: VecDeque<((u32, u32), Instant)>,
+    capacity: usize,
+    ttl: Duration,
+}
+
+impl DuplicateFilter {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            seen: HashMap::new(),
+            order: VecDeque::new(),
+            capacity,
+            ttl,
+        }
+    }
+
+    /// Record a packet and return true if it was already seen within the TTL
+    pub fn check_and_insert(&mut self, from: u32, id: u32) -> bool {
+        self.check_and_insert_at(from, id, Instant::now())
+    }
+
+    /// Same as [`check_and_insert`](Self::check_and_insert) with an explicit clock
+    pub fn check_and_insert_at(&mut self, from: u32, id: u32, now: Instant) -> bool {
+        self.expire(now);
+
+        let key = (from, id);
+        if self.seen.contains_key(&key) {
+            return true;
+        }
+
+        self.seen.insert(key, now);
+        self.order.push_back((key, now));
+
+        // Evict the oldest entries once the capacity is exceeded
+        while self.order.len() > self.capacity {
+            if let Some((old_key, _)) = self.order.pop_front() {
+                self.seen.remove(&old_key);
+            }
+        }
+
+        false
+    }
+
+    /// Remember a packet without checking whether it was seen
+    pub fn insert(&mut self, from: u32, id: u32) {
+        self.check_and_insert(from, id);
+    }
+
+    /// Check whether a packet was seen within the TTL without recording it
+    pub fn contains(&mut self, from: u32, id: u32) -> bool {
+        self.expire(Instant::now());
+        self.seen.contains_key(&(from, id))
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while let Some((key, seen_at)) = self.order.front().copied() {
+            if now.saturating_duration_since(seen_at) < self.ttl {
+                break;
+            }
+            self.order.pop_front();
+            self.seen.remove(&key);
+        }
+    }
+}
+
+impl Default for DuplicateFilter {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY, DEFAULT_TTL)
+    }
+}
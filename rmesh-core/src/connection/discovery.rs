@@ -0,0 +1,356 @@
+use anyhow::{Context, Result, bail};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::{debug, info};
+
+use crate::connection::{ConnectionError, ConnectionManager, HandshakeOptions};
+
+/// Directory of stable, descriptive symlinks to USB serial devices on Linux
+const SERIAL_BY_ID_DIR: &str = "/dev/serial/by-id";
+
+/// Port of the TCP API on network nodes and meshtasticd
+pub const MESHTASTIC_TCP_PORT: u16 = 4403;
+
+/// Socket a local meshtasticd, the Linux native node, serves the API on
+pub const MESHTASTICD_SOCKET: &str = "/run/meshtasticd.sock";
+
+/// How long a probed port gets to answer the handshake
+pub const DEFAULT_PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How strongly a serial port looks like a Meshtastic device
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Likelihood {
+    /// Built-in UARTs, Bluetooth and other ports no radio uses
+    Unlikely,
+    /// A USB serial port of unknown make
+    Possible,
+    /// A USB-serial bridge chip common on LoRa boards
+    Likely,
+    /// The USB vendor of a board or chip that ships Meshtastic firmware
+    Known,
+}
+
+/// USB identifiers of chips and boards found on Meshtastic radios
+///
+/// A `None` product matches every product of the vendor.
+const USB_DEVICES: &[(u16, Option<u16>, &str, Likelihood)] = &[
+    (
+        0x303a,
+        None,
+        "Espressif native USB (ESP32-S2/S3/C3)",
+        Likelihood::Known,
+    ),
+    (
+        0x239a,
+        None,
+        "Adafruit nRF52 bootloader (RAK4631, T-Echo)",
+        Likelihood::Known,
+    ),
+    (
+        0x2886,
+        None,
+        "Seeed Studio (XIAO, Wio Tracker)",
+        Likelihood::Known,
+    ),
+    (0x2e8a, None, "Raspberry Pi RP2040", Likelihood::Known),
+    (
+        0x1915,
+        None,
+        "Nordic Semiconductor nRF52",
+        Likelihood::Known,
+    ),
+    (
+        0x10c4,
+        Some(0xea60),
+        "Silicon Labs CP210x",
+        Likelihood::Likely,
+    ),
+    (0x1a86, Some(0x7523), "WCH CH340", Likelihood::Likely),
+    (0x1a86, Some(0x55d4), "WCH CH9102", Likelihood::Likely),
+    (0x1a86, Some(0x55d3), "WCH CH343", Likelihood::Likely),
+    (0x0403, Some(0x6001), "FTDI FT232", Likelihood::Likely),
+    (0x0403, Some(0x6015), "FTDI FT231X", Likelihood::Likely),
+];
+
+/// Words in `/dev/serial/by-id` names identifying Meshtastic hardware
+const BOARD_NAME_HINTS: &[&str] = &[
+    "meshtastic",
+    "heltec",
+    "lilygo",
+    "tbeam",
+    "t-beam",
+    "t-echo",
+    "rak",
+    "wisblock",
+    "seeed",
+    "esp32",
+];
+
+/// Words in `/dev/serial/by-id` names identifying USB-serial bridges
+const BRIDGE_NAME_HINTS: &[&str] = &["cp210", "ch340", "ch9102", "ch343", "ftdi"];
+
+/// A serial port that may have a Meshtastic device attached
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DeviceCandidate {
+    pub port: String,
+    pub vid: Option<u16>,
+    pub pid: Option<u16>,
+    /// What the USB identifiers or by-id name say the device is
+    pub description: Option<String>,
+    /// Name of the port's `/dev/serial/by-id` link, when there is one
+    pub by_id: Option<String>,
+    pub likelihood: Likelihood,
+}
+
+/// Whether a `--port` value names a TCP address rather than a serial port
+pub fn is_tcp_address(port: &str) -> bool {
+    port.contains(':') || port.starts_with("192.") || port.starts_with("10.") || port == "localhost"
+}
+
+/// What a `--port` value connects to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PortTarget {
+    Serial(String),
+    /// `host:port` of a network node or meshtasticd
+    Tcp(String),
+    /// Unix socket of a local meshtasticd
+    Unix(PathBuf),
+}
+
+impl std::fmt::Display for PortTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Serial(port) => write!(f, "{}", port),
+            Self::Tcp(address) => write!(f, "tcp://{}", address),
+            Self::Unix(path) => write!(f, "unix://{}", path.display()),
+        }
+    }
+}
+
+/// Parse a `--port` value
+///
+/// `unix:///run/meshtasticd.sock` and `tcp://host[:port]` name the transport
+/// explicitly; otherwise anything that looks like an address is TCP and the
+/// rest a serial port. TCP addresses without a port use 4403.
+pub fn parse_port(port: &str) -> PortTarget {
+    if let Some(path) = port
+        .strip_prefix("unix://")
+        .or_else(|| port.strip_prefix("unix:"))
+    {
+        return PortTarget::Unix(PathBuf::from(path));
+    }
+    if let Some(address) = port.strip_prefix("tcp://") {
+        return PortTarget::Tcp(with_default_port(address.trim_end_matches('/')));
+    }
+    if is_tcp_address(port) {
+        return PortTarget::Tcp(with_default_port(port));
+    }
+    PortTarget::Serial(port.to_string())
+}
+
+fn with_default_port(address: &str) -> String {
+    if address.contains(':') {
+        address.to_string()
+    } else {
+        format!("{address}:{MESHTASTIC_TCP_PORT}")
+    }
+}
+
+/// Pick what to connect to when neither `--port` nor `--ble` is given
+///
+/// The most likely serial port wins; without one, a running meshtasticd is
+/// used through its socket.
+pub fn auto_detect_target() -> Result<PortTarget> {
+    let candidates = discover_devices()?;
+    if let Some(candidate) = candidates.first() {
+        info!(
+            "Using auto-detected port: {port} ({description})",
+            port = candidate.port,
+            description = candidate.description.as_deref().unwrap_or("unknown device")
+        );
+        return Ok(PortTarget::Serial(candidate.port.clone()));
+    }
+
+    let socket = Path::new(MESHTASTICD_SOCKET);
+    if cfg!(unix) && socket.exists() {
+        info!("No serial ports found, using meshtasticd at {MESHTASTICD_SOCKET}");
+        return Ok(PortTarget::Unix(socket.to_path_buf()));
+    }
+
+    Err(ConnectionError::NoDevice {
+        target: "auto-detected serial port".to_string(),
+        reason: format!(
+            "no serial ports found and no meshtasticd at {MESHTASTICD_SOCKET}; specify --port or --ble"
+        ),
+    }
+    .into())
+}
+
+/// Look up a USB vendor and product in the known device table
+pub fn identify_usb(vid: u16, pid: u16) -> Option<(&'static str, Likelihood)> {
+    USB_DEVICES
+        .iter()
+        .find(|(known_vid, known_pid, _, _)| {
+            *known_vid == vid && known_pid.is_none_or(|known| known == pid)
+        })
+        .map(|(_, _, description, likelihood)| (*description, *likelihood))
+}
+
+/// Rate a port from its path, USB identifiers and by-id name
+pub fn classify_port(port: &str, usb_id: Option<(u16, u16)>, by_id: Option<&str>) -> Likelihood {
+    let by_id = by_id.map(str::to_lowercase).unwrap_or_default();
+
+    let from_usb = usb_id.and_then(|(vid, pid)| identify_usb(vid, pid));
+    if from_usb.is_some_and(|(_, likelihood)| likelihood == Likelihood::Known)
+        || BOARD_NAME_HINTS.iter().any(|hint| by_id.contains(hint))
+    {
+        return Likelihood::Known;
+    }
+    if from_usb.is_some() || BRIDGE_NAME_HINTS.iter().any(|hint| by_id.contains(hint)) {
+        return Likelihood::Likely;
+    }
+
+    let name = port.rsplit('/').next().unwrap_or(port);
+    let usb_port = [
+        "ttyACM", "ttyUSB", "cu.usb", "tty.usb", "cu.SLAB", "tty.SLAB", "COM",
+    ]
+    .iter()
+    .any(|prefix| name.starts_with(prefix));
+    if usb_id.is_some() || !by_id.is_empty() || usb_port {
+        Likelihood::Possible
+    } else {
+        Likelihood::Unlikely
+    }
+}
+
+/// List serial ports, most likely Meshtastic devices first
+///
+/// Ports that are clearly not radios, such as built-in UARTs, are left out.
+pub fn discover_devices() -> Result<Vec<DeviceCandidate>> {
+    let ports = meshtastic::utils::stream::available_serial_ports()
+        .context("Failed to list serial ports")?;
+    let by_id = read_serial_by_id();
+
+    let mut candidates: Vec<DeviceCandidate> = ports
+        .into_iter()
+        .map(|port| {
+            let usb_id = usb_id_of(&port);
+            let link = by_id
+                .iter()
+                .find(|(_, target)| *target == port)
+                .map(|(name, _)| name.clone());
+            let likelihood = classify_port(&port, usb_id, link.as_deref());
+            let description = usb_id
+                .and_then(|(vid, pid)| identify_usb(vid, pid))
+                .map(|(description, _)| description.to_string())
+                .or_else(|| link.clone());
+
+            DeviceCandidate {
+                vid: usb_id.map(|(vid, _)| vid),
+                pid: usb_id.map(|(_, pid)| pid),
+                port,
+                description,
+                by_id: link,
+                likelihood,
+            }
+        })
+        .filter(|candidate| candidate.likelihood > Likelihood::Unlikely)
+        .collect();
+
+    // Stable order within a likelihood keeps ttyACM0 ahead of ttyACM1
+    candidates.sort_by(|a, b| b.likelihood.cmp(&a.likelihood).then(a.port.cmp(&b.port)));
+    debug!("Discovered serial candidates: {candidates:?}");
+    Ok(candidates)
+}
+
+/// Check whether a Meshtastic device answers the config handshake on a port
+pub async fn probe_port(port: &str, timeout: Duration) -> bool {
+    let Ok(mut connection) = ConnectionManager::new(Some(port.to_string()), None, timeout).await
+    else {
+        return false;
+    };
+    connection.set_handshake_options(HandshakeOptions {
+        timeout,
+        attempts: 1,
+    });
+
+    let answered = match connection.connect().await {
+        Ok(()) => true,
+        Err(e) => {
+            debug!("Probe of {port} failed: {e}");
+            false
+        }
+    };
+    if let Err(e) = connection.disconnect().await {
+        debug!("Failed to close probe connection to {port}: {e}");
+    }
+    answered
+}
+
+/// Find the port of an attached Meshtastic device, probing candidates in order
+///
+/// The first candidate that completes the handshake wins, so a USB-serial
+/// adapter without a radio behind it is skipped.
+pub async fn find_device(timeout: Duration) -> Result<DeviceCandidate> {
+    let candidates = discover_devices()?;
+    if candidates.is_empty() {
+        bail!("No serial ports that could be a Meshtastic device; specify --port or --ble");
+    }
+
+    for candidate in &candidates {
+        info!(
+            "Probing {port} ({description})...",
+            port = candidate.port,
+            description = candidate.description.as_deref().unwrap_or("unknown device")
+        );
+        if probe_port(&candidate.port, timeout).await {
+            return Ok(candidate.clone());
+        }
+    }
+
+    bail!(
+        "No Meshtastic device answered on {ports}",
+        ports = candidates
+            .iter()
+            .map(|candidate| candidate.port.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    )
+}
+
+/// `/dev/serial/by-id` link names with the device paths they point to
+fn read_serial_by_id() -> Vec<(String, String)> {
+    let Ok(entries) = std::fs::read_dir(SERIAL_BY_ID_DIR) else {
+        return Vec::new();
+    };
+
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_str()?.to_string();
+            let target = entry.path().canonicalize().ok()?;
+            Some((name, target.to_string_lossy().to_string()))
+        })
+        .collect()
+}
+
+/// USB vendor and product of a Linux tty, read from sysfs
+fn usb_id_of(port: &str) -> Option<(u16, u16)> {
+    let name = Path::new(port).file_name()?.to_str()?;
+    let device = Path::new("/sys/class/tty")
+        .join(name)
+        .join("device")
+        .canonicalize()
+        .ok()?;
+
+    // The tty sits below the USB interface; the identifiers are on the device
+    device.ancestors().take(4).find_map(|dir| {
+        let read_hex = |file: &str| {
+            let text = std::fs::read_to_string(dir.join(file)).ok()?;
+            u16::from_str_radix(text.trim(), 16).ok()
+        };
+        Some((read_hex("idVendor")?, read_hex("idProduct")?))
+    })
+}
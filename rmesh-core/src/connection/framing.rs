@@ -0,0 +1,133 @@
+use meshtastic::Message;
+use meshtastic::protobufs::FromRadio;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tracing::debug;
+
+/// Serial framing of the stream API: two start bytes and a big-endian length
+const START1: u8 = 0x94;
+const START2: u8 = 0xc3;
+
+/// Largest ToRadio or FromRadio payload the firmware sends or accepts
+const MAX_FRAME_PAYLOAD: usize = 512;
+
+/// Frame a payload for the stream API
+pub fn encode_frame(payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(payload.len() + 4);
+    frame.extend_from_slice(&[START1, START2]);
+    frame.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// Read the payload of the next frame, skipping bytes outside of frames
+pub async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> std::io::Result<Vec<u8>> {
+    loop {
+        let mut previous = reader.read_u8().await?;
+        loop {
+            let byte = reader.read_u8().await?;
+            if previous == START1 && byte == START2 {
+                break;
+            }
+            previous = byte;
+        }
+
+        let len = usize::from(reader.read_u16().await?);
+        if len > MAX_FRAME_PAYLOAD {
+            debug!("Skipping oversized frame of {len} bytes");
+            continue;
+        }
+        let mut payload = vec![0; len];
+        reader.read_exact(&mut payload).await?;
+        return Ok(payload);
+    }
+}
+
+/// Framing errors seen by a [`FrameScanner`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct ScanErrors {
+    pub sync: u64,
+    pub decode: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+enum ScanState {
+    #[default]
+    Start1,
+    Start2,
+    LengthHigh,
+    LengthLow(u8),
+    Payload(usize),
+}
+
+/// Follows the framing of the bytes read from a device, to measure link
+/// quality without taking part in decoding
+///
+/// A run of bytes outside frames, or an impossible frame length, counts as
+/// one sync error; a complete frame that is not a FromRadio message counts
+/// as a decode error. Stale bytes before the first frame are not counted.
+#[derive(Debug, Default)]
+pub(crate) struct FrameScanner {
+    state: ScanState,
+    payload: Vec<u8>,
+    seen_frame: bool,
+    /// Inside a run of bytes already counted as a sync error
+    out_of_sync: bool,
+}
+
+impl FrameScanner {
+    pub fn feed(&mut self, bytes: &[u8]) -> ScanErrors {
+        let mut errors = ScanErrors::default();
+        for &byte in bytes {
+            self.state = match self.state {
+                ScanState::Start1 if byte == START1 => ScanState::Start2,
+                ScanState::Start2 if byte == START2 => ScanState::LengthHigh,
+                ScanState::Start1 | ScanState::Start2 => {
+                    self.lose_sync(&mut errors);
+                    if byte == START1 {
+                        ScanState::Start2
+                    } else {
+                        ScanState::Start1
+                    }
+                }
+                ScanState::LengthHigh => ScanState::LengthLow(byte),
+                ScanState::LengthLow(high) => match usize::from(u16::from_be_bytes([high, byte])) {
+                    len if len > MAX_FRAME_PAYLOAD => {
+                        self.lose_sync(&mut errors);
+                        ScanState::Start1
+                    }
+                    0 => self.complete_frame(&mut errors),
+                    len => {
+                        self.payload.clear();
+                        ScanState::Payload(len)
+                    }
+                },
+                ScanState::Payload(len) => {
+                    self.payload.push(byte);
+                    if self.payload.len() == len {
+                        self.complete_frame(&mut errors)
+                    } else {
+                        ScanState::Payload(len)
+                    }
+                }
+            };
+        }
+        errors
+    }
+
+    fn lose_sync(&mut self, errors: &mut ScanErrors) {
+        if self.seen_frame && !self.out_of_sync {
+            errors.sync += 1;
+        }
+        self.out_of_sync = true;
+    }
+
+    fn complete_frame(&mut self, errors: &mut ScanErrors) -> ScanState {
+        if FromRadio::decode(self.payload.as_slice()).is_err() {
+            errors.decode += 1;
+        }
+        self.payload.clear();
+        self.seen_frame = true;
+        self.out_of_sync = false;
+        ScanState::Start1
+    }
+}
@@ -0,0 +1,163 @@
+use super::framing::FrameScanner;
+use super::stats::ConnectionCounters;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::Ordering;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// Timing of the want_config handshake performed by `connect()`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HandshakeOptions {
+    /// How long to wait for the device to finish sending its config
+    pub timeout: Duration,
+    /// Number of want_config requests sent before giving up
+    pub attempts: u32,
+}
+
+impl Default for HandshakeOptions {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(10),
+            attempts: 3,
+        }
+    }
+}
+
+/// Why `connect()` could not establish a session with the device
+#[derive(Debug, thiserror::Error)]
+pub enum ConnectionError {
+    /// The port or address could not be opened at all
+    #[error("No device found at {target}: {reason}")]
+    NoDevice { target: String, reason: String },
+
+    /// The link opened but the device never completed the handshake
+    #[error(
+        "Device at {target} did not respond after {attempts} handshake attempt(s) of {timeout_secs}s; \
+         check that it is powered on and not in use by another program"
+    )]
+    NotResponding {
+        target: String,
+        attempts: u32,
+        timeout_secs: u64,
+        /// Frames decoded before giving up; non-zero means the device is alive but stalled
+        frames_received: u64,
+    },
+
+    /// Bytes arrived but none of them decoded as Meshtastic frames
+    #[error(
+        "Received {bytes_received} bytes from {target} but no Meshtastic frames; \
+         check the baud rate and that the serial API is enabled on the device"
+    )]
+    ProtocolMismatch { target: String, bytes_received: u64 },
+}
+
+/// Classify a failed handshake from what was seen on the link
+pub fn diagnose_handshake_failure(
+    target: &str,
+    options: &HandshakeOptions,
+    bytes_received: u64,
+    frames_received: u64,
+) -> ConnectionError {
+    if bytes_received > 0 && frames_received == 0 {
+        ConnectionError::ProtocolMismatch {
+            target: target.to_string(),
+            bytes_received,
+        }
+    } else {
+        ConnectionError::NotResponding {
+            target: target.to_string(),
+            attempts: options.attempts,
+            timeout_secs: options.timeout.as_secs(),
+            frames_received,
+        }
+    }
+}
+
+/// Handshake progress published by the packet processor
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct HandshakeProgress {
+    pub frames_received: u64,
+    pub config_complete_id: Option<u32>,
+}
+
+/// Device restarts and link loss noticed by the packet processor
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct LinkStatus {
+    /// `connect()` finished and the link was not closed since
+    pub connected: bool,
+    /// Reboots detected since `connect()`
+    pub reboots: u64,
+    /// The stream ended, as when a USB serial device resets
+    pub link_lost: bool,
+}
+
+/// Stream wrapper counting the bytes read from and written to the device
+///
+/// Lets a failed handshake tell a silent link from one carrying data that
+/// never decodes, such as a serial port opened at the wrong baud rate. The
+/// bytes read are also scanned for framing errors.
+pub(crate) struct CountingStream<S> {
+    inner: S,
+    counters: ConnectionCounters,
+    scanner: FrameScanner,
+}
+
+impl<S> CountingStream<S> {
+    pub fn new(inner: S, counters: &ConnectionCounters) -> Self {
+        Self {
+            inner,
+            counters: counters.clone(),
+            scanner: FrameScanner::default(),
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for CountingStream<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        let result = Pin::new(&mut self.inner).poll_read(cx, buf);
+        let read = buf.filled().get(before..).unwrap_or_default();
+        let errors = self.scanner.feed(read);
+        let counters = &self.counters;
+        counters
+            .bytes_read
+            .fetch_add(read.len() as u64, Ordering::Relaxed);
+        counters
+            .sync_errors
+            .fetch_add(errors.sync, Ordering::Relaxed);
+        counters
+            .decode_errors
+            .fetch_add(errors.decode, Ordering::Relaxed);
+        result
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for CountingStream<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let result = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(written)) = &result {
+            self.counters
+                .bytes_written
+                .fetch_add(*written as u64, Ordering::Relaxed);
+        }
+        result
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
@@ -0,0 +1,244 @@
+use super::handshake::{HandshakeProgress, LinkStatus};
+use super::processor::PacketProcessor;
+use super::stats::ConnectionCounters;
+use crate::time::unix_now;
+use anyhow::{Context, Result, ensure};
+use serde::Serialize;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+/// Longest request head read from a probe
+const MAX_REQUEST_BYTES: usize = 4096;
+
+/// Time a probe gets to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Pause after a failed accept, so errors such as running out of file
+/// descriptors don't spin the loop
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Work waiting inside the connection
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct QueueDepths {
+    /// Events not yet read by the slowest subscriber
+    pub events: usize,
+    /// Sent packets waiting for an acknowledgment
+    pub pending_acks: usize,
+    /// Requests waiting for their response
+    pub pending_responses: usize,
+    /// Traceroutes waiting for their route
+    pub pending_traceroutes: usize,
+    /// Frames waiting to be written to the device
+    pub sends: u64,
+}
+
+/// Health of a connection, as served on `/healthz` and `/readyz`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HealthReport {
+    /// The link to the device is open
+    pub connected: bool,
+    /// The config handshake finished
+    pub handshake_complete: bool,
+    /// Device reboots noticed since connecting
+    pub reboots: u64,
+    /// Seconds since the last frame from the device, null before the first
+    pub last_packet_age_secs: Option<u64>,
+    pub queues: QueueDepths,
+}
+
+impl HealthReport {
+    /// Whether the device link is up and, with a `max_packet_age`, heard
+    /// from recently enough
+    pub fn is_live(&self, max_packet_age: Option<Duration>) -> bool {
+        self.connected
+            && max_packet_age.is_none_or(|max_age| {
+                self.last_packet_age_secs
+                    .is_some_and(|age| age <= max_age.as_secs())
+            })
+    }
+
+    /// Whether commands can be served: live, with the handshake done
+    pub fn is_ready(&self, max_packet_age: Option<Duration>) -> bool {
+        self.is_live(max_packet_age) && self.handshake_complete
+    }
+}
+
+/// Status code and JSON body answering a probe of `path`, `None` for paths
+/// other than `/healthz` and `/readyz`
+///
+/// A report that fails to serialize is answered with a 500 and the error.
+pub fn health_response(
+    path: &str,
+    report: &HealthReport,
+    max_packet_age: Option<Duration>,
+) -> Option<(u16, String)> {
+    let healthy = match path {
+        "/healthz" => report.is_live(max_packet_age),
+        "/readyz" => report.is_ready(max_packet_age),
+        _ => return None,
+    };
+    let mut body = match serde_json::to_value(report) {
+        Ok(body) => body,
+        Err(e) => {
+            let error = serde_json::json!({ "error": e.to_string() });
+            return Some((500, error.to_string()));
+        }
+    };
+    body["status"] = if healthy { "ok" } else { "unavailable" }.into();
+    Some((if healthy { 200 } else { 503 }, body.to_string()))
+}
+
+/// Handle reading the health of a connection from another task
+///
+/// Obtained from [`crate::ConnectionManager::health_probe`] before the
+/// manager is moved into a command; it follows reconnects.
+#[derive(Clone)]
+pub struct HealthProbe {
+    link_status: watch::Receiver<LinkStatus>,
+    handshake_progress: watch::Receiver<HandshakeProgress>,
+    counters: ConnectionCounters,
+    processor: PacketProcessor,
+}
+
+impl HealthProbe {
+    pub(crate) fn new(
+        link_status: watch::Receiver<LinkStatus>,
+        handshake_progress: watch::Receiver<HandshakeProgress>,
+        counters: ConnectionCounters,
+        processor: PacketProcessor,
+    ) -> Self {
+        Self {
+            link_status,
+            handshake_progress,
+            counters,
+            processor,
+        }
+    }
+
+    pub async fn report(&self) -> HealthReport {
+        let link = *self.link_status.borrow();
+        let handshake_complete = self
+            .handshake_progress
+            .borrow()
+            .config_complete_id
+            .is_some();
+        let pending_responses = self
+            .processor
+            .response_waiters
+            .lock()
+            .map(|waiters| waiters.len())
+            .unwrap_or_default();
+        HealthReport {
+            connected: link.connected && !link.link_lost,
+            handshake_complete,
+            reboots: link.reboots,
+            last_packet_age_secs: self
+                .counters
+                .last_frame_at()
+                .map(|time| unix_now().saturating_sub(time)),
+            queues: QueueDepths {
+                events: self.processor.events.queued(),
+                pending_acks: self.processor.ack_waiters.lock().await.len(),
+                pending_responses,
+                pending_traceroutes: self.processor.route_waiters.lock().await.len(),
+                sends: self.counters.send_queue_depth(),
+            },
+        }
+    }
+}
+
+/// Serve `/healthz` and `/readyz` on `address` until the returned task is
+/// aborted
+///
+/// `/healthz` answers 200 while the device link is up and, with a
+/// `max_packet_age`, a frame arrived within it; `/readyz` also needs the
+/// config handshake to be done. Both answer 503 otherwise, with the
+/// [`HealthReport`] as body either way.
+pub async fn serve_health(
+    address: SocketAddr,
+    probe: HealthProbe,
+    max_packet_age: Option<Duration>,
+) -> Result<JoinHandle<()>> {
+    let listener = TcpListener::bind(address)
+        .await
+        .with_context(|| format!("Failed to listen on {address} for health probes"))?;
+    info!("Serving health probes on {address}");
+
+    Ok(tokio::spawn(async move {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    warn!("Failed to accept a health probe: {e}");
+                    tokio::time::sleep(ACCEPT_RETRY_DELAY).await;
+                    continue;
+                }
+            };
+            let probe = probe.clone();
+            tokio::spawn(async move {
+                let answered = tokio::time::timeout(
+                    REQUEST_TIMEOUT,
+                    answer_probe(stream, &probe, max_packet_age),
+                )
+                .await;
+                match answered {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => debug!("Failed to answer a health probe: {e}"),
+                    Err(_) => debug!("Health probe sent no request in time"),
+                }
+            });
+        }
+    }))
+}
+
+async fn answer_probe(
+    mut stream: TcpStream,
+    probe: &HealthProbe,
+    max_packet_age: Option<Duration>,
+) -> Result<()> {
+    let mut request = Vec::new();
+    let mut buffer = [0; 512];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        ensure!(request.len() < MAX_REQUEST_BYTES, "Request head too large");
+        let read = stream.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        request.extend_from_slice(&buffer[..read]);
+    }
+
+    let head = String::from_utf8_lossy(&request);
+    let mut request_line = head.split_whitespace();
+    let method = request_line.next().unwrap_or_default();
+    let path = request_line
+        .next()
+        .and_then(|target| target.split('?').next())
+        .unwrap_or_default();
+
+    let (status, body) = if method == "GET" {
+        let report = probe.report().await;
+        health_response(path, &report, max_packet_age)
+            .unwrap_or_else(|| (404, r#"{"error":"not found"}"#.to_string()))
+    } else {
+        (405, r#"{"error":"method not allowed"}"#.to_string())
+    };
+    let reason = match status {
+        200 => "OK",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        500 => "Internal Server Error",
+        _ => "Service Unavailable",
+    };
+    let response = format!(
+        "HTTP/1.1 {status} {reason}\r\nContent-Type: application/json\r\nContent-Length: {length}\r\nConnection: close\r\n\r\n{body}",
+        length = body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
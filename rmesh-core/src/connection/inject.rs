@@ -0,0 +1,114 @@
+use super::handshake::HandshakeProgress;
+use crate::debug::{decode_from_radio, parse_hex};
+use anyhow::{Context, Result};
+use meshtastic::packet::PacketReceiver;
+use meshtastic::protobufs::FromRadio;
+use serde_json::Value;
+use std::path::Path;
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+use tracing::{debug, info};
+
+/// Recorded frames fed to the packet processor as if the device sent them
+///
+/// A developer aid for reproducing parsing bugs: the frames take the same
+/// path as real traffic, from duplicate filtering to the command's output.
+#[derive(Debug, Clone, Default)]
+pub struct Injection {
+    pub frames: Vec<FromRadio>,
+    /// Wait after the config handshake before the first frame, so the
+    /// command is listening by then
+    pub delay: Duration,
+}
+
+impl Injection {
+    pub fn new(frames: Vec<FromRadio>, delay: Duration) -> Self {
+        Self { frames, delay }
+    }
+
+    /// Injection of the frames recorded in a capture file
+    pub fn from_file(path: &Path, delay: Duration) -> Result<Self> {
+        Ok(Self::new(read_capture(path)?, delay))
+    }
+}
+
+/// Read a capture of FromRadio frames, one per line
+///
+/// A line is either a frame as JSON or a JSON string of its hex bytes, with
+/// or without the stream frame header. Blank lines are skipped.
+pub fn read_capture(path: &Path) -> Result<Vec<FromRadio>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {path}", path = path.display()))?;
+    parse_capture(&text).with_context(|| format!("Failed to parse {path}", path = path.display()))
+}
+
+pub(crate) fn parse_capture(text: &str) -> Result<Vec<FromRadio>> {
+    let mut frames = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let line_number = index + 1;
+        let value: Value = serde_json::from_str(line)
+            .with_context(|| format!("Line {line_number} is not JSON"))?;
+        let frame = match value {
+            Value::String(hex) => parse_hex(&hex).and_then(|bytes| decode_from_radio(&bytes)),
+            value => serde_json::from_value(value).map_err(Into::into),
+        }
+        .with_context(|| format!("Line {line_number} is not a FromRadio frame"))?;
+        frames.push(frame);
+    }
+    Ok(frames)
+}
+
+/// Receiver of the device frames with the injected ones merged in
+///
+/// The injected frames follow once the handshake completes and the delay
+/// passed. The merged receiver ends when the device stream does.
+pub(crate) fn merge_injected(
+    mut device: PacketReceiver,
+    injection: Injection,
+    mut handshake_progress: watch::Receiver<HandshakeProgress>,
+) -> PacketReceiver {
+    let (sender, merged) = mpsc::unbounded_channel();
+    let (injector, mut injected) = mpsc::unbounded_channel();
+
+    tokio::spawn(async move {
+        if handshake_progress
+            .wait_for(|progress| progress.config_complete_id.is_some())
+            .await
+            .is_err()
+        {
+            return;
+        }
+        tokio::time::sleep(injection.delay).await;
+        info!(
+            "Injecting {count} recorded frame(s)",
+            count = injection.frames.len()
+        );
+        for frame in injection.frames {
+            if injector.send(frame).is_err() {
+                debug!("Packet processing ended, dropping the remaining injected frames");
+                break;
+            }
+        }
+    });
+
+    // Only this task sends on the merged channel, so it closes with the device
+    tokio::spawn(async move {
+        loop {
+            let frame = tokio::select! {
+                frame = device.recv() => match frame {
+                    Some(frame) => frame,
+                    None => break,
+                },
+                Some(frame) = injected.recv() => frame,
+            };
+            if sender.send(frame).is_err() {
+                break;
+            }
+        }
+    });
+
+    merged
+}
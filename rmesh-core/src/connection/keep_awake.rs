@@ -0,0 +1,156 @@
+use anyhow::{Result, bail, ensure};
+use meshtastic::Message;
+use meshtastic::protobufs::{self, ToRadio, to_radio};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, DuplexStream};
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+use crate::connection::framing::{encode_frame, read_frame};
+
+/// Byte the firmware's serial state machine resyncs on (the second start byte)
+const WAKE_BYTE: u8 = 0xc3;
+
+/// Length of the default wake sequence, as sent by the Python client
+const WAKE_SEQUENCE_LEN: usize = 32;
+
+/// Longest wake sequence accepted, so a typo cannot flood the port
+const MAX_WAKE_SEQUENCE_LEN: usize = 256;
+
+/// Buffer between meshtastic's stream API and the keep-awake pump
+const PUMP_BUFFER: usize = 4096;
+
+/// How rmesh wakes a serial device and keeps it awake
+#[derive(Debug, Clone)]
+pub struct SerialWakeOptions {
+    /// Send the wake sequence when the port is opened
+    pub wake_on_connect: bool,
+    /// Bytes written to resync the device's serial state machine
+    pub wake_sequence: Vec<u8>,
+    /// Resend the wake sequence and a heartbeat after this long without
+    /// anything sent to the device
+    pub keep_awake: Option<Duration>,
+}
+
+impl Default for SerialWakeOptions {
+    fn default() -> Self {
+        Self {
+            wake_on_connect: true,
+            wake_sequence: vec![WAKE_BYTE; WAKE_SEQUENCE_LEN],
+            keep_awake: None,
+        }
+    }
+}
+
+/// Parse a wake sequence given as hex, e.g. `c3c3c3c3` or `0xc3 0xc3`
+pub fn parse_wake_sequence(input: &str) -> Result<Vec<u8>> {
+    let digits: String = input
+        .split(|c: char| c.is_whitespace() || c == ',' || c == ':')
+        .map(|part| {
+            part.strip_prefix("0x")
+                .or_else(|| part.strip_prefix("0X"))
+                .unwrap_or(part)
+        })
+        .collect();
+    ensure!(!digits.is_empty(), "Wake sequence is empty");
+
+    let bytes = match hex::decode(&digits) {
+        Ok(bytes) => bytes,
+        Err(e) => bail!("Invalid wake sequence '{input}': {e}"),
+    };
+    ensure!(
+        bytes.len() <= MAX_WAKE_SEQUENCE_LEN,
+        "Wake sequence is {len} bytes, at most {MAX_WAKE_SEQUENCE_LEN} are allowed",
+        len = bytes.len()
+    );
+    Ok(bytes)
+}
+
+/// Bytes of one keep-awake ping: the wake sequence followed by a framed heartbeat
+pub fn keep_awake_ping(wake_sequence: &[u8]) -> Vec<u8> {
+    let heartbeat = ToRadio {
+        payload_variant: Some(to_radio::PayloadVariant::Heartbeat(
+            protobufs::Heartbeat::default(),
+        )),
+    };
+    let mut bytes = wake_sequence.to_vec();
+    bytes.extend_from_slice(&encode_frame(&heartbeat.encode_to_vec()));
+    bytes
+}
+
+/// Put a keep-awake pump between meshtastic's stream API and a serial device
+///
+/// Frames from the client pass through whole, so a ping is never written in
+/// the middle of one. When nothing has been sent for `interval`, the wake
+/// sequence and a heartbeat go out. If the device stops accepting writes or
+/// closes its end, the returned stream ends too, the packet processor reports
+/// the link as lost and [`resync_if_rebooted`] reopens the port.
+///
+/// [`resync_if_rebooted`]: crate::connection::ConnectionManager::resync_if_rebooted
+pub fn keep_awake<S>(device: S, interval: Duration, wake_sequence: Vec<u8>) -> DuplexStream
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    let (client, pump) = tokio::io::duplex(PUMP_BUFFER);
+    let (mut device_reader, mut device_writer) = tokio::io::split(device);
+    let (mut pump_reader, mut pump_writer) = tokio::io::split(pump);
+
+    let downlink = tokio::spawn(async move {
+        match tokio::io::copy(&mut device_reader, &mut pump_writer).await {
+            Ok(_) => info!("Serial device closed the link"),
+            Err(e) => warn!("Serial link read failed: {e}"),
+        }
+        // Pass the end of the link on to the packet processor
+        if let Err(e) = pump_writer.shutdown().await {
+            debug!("Failed to close the keep-awake pump: {e}");
+        }
+    });
+
+    // Frames are read on their own task, since a read interrupted by the
+    // heartbeat timer would lose its partial frame
+    let (frames_tx, mut frames) = mpsc::unbounded_channel();
+    let uplink = tokio::spawn(async move {
+        while let Ok(payload) = read_frame(&mut pump_reader).await {
+            if frames_tx.send(payload).is_err() {
+                break;
+            }
+        }
+    });
+
+    tokio::spawn(async move {
+        let ping = keep_awake_ping(&wake_sequence);
+        let mut idle = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+        idle.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            let bytes = tokio::select! {
+                frame = frames.recv() => match frame {
+                    Some(payload) => {
+                        idle.reset();
+                        encode_frame(&payload)
+                    }
+                    None => break,
+                },
+                _ = idle.tick() => {
+                    debug!("Sending keep-awake heartbeat");
+                    ping.clone()
+                }
+            };
+            let written = async {
+                device_writer.write_all(&bytes).await?;
+                device_writer.flush().await
+            };
+            if let Err(e) = written.await {
+                warn!("Serial link write failed, dropping the connection: {e}");
+                break;
+            }
+        }
+
+        // Dropping both halves of the pump closes the client's stream, which
+        // the packet processor reports as a lost link
+        uplink.abort();
+        downlink.abort();
+    });
+
+    client
+}
@@ -1,57 +1,162 @@
-use anyhow::{Context, Result, bail, ensure};
+use anyhow::{Context, Result, anyhow, bail, ensure};
 use meshtastic::Message;
 use meshtastic::api::state::Configured;
-use meshtastic::api::{ConnectedStreamApi, StreamApi};
-use meshtastic::packet::{PacketReceiver, PacketRouter};
+use meshtastic::api::{ConnectedStreamApi, StreamApi, StreamHandle};
+use meshtastic::packet::PacketReceiver;
+use meshtastic::protobufs::FromRadio;
 use meshtastic::utils;
-use std::collections::HashMap;
 use std::sync::Arc;
-use std::time::Duration;
-use tokio::sync::{Mutex, oneshot};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::{Mutex, broadcast, mpsc, oneshot, watch};
 use tokio::task::JoinHandle;
-use tracing::{debug, info, warn};
+use tracing::{Instrument, debug, info, warn};
 
+use crate::admin::{
+    AdminDestination, SESSION_KEY_ATTEMPTS, SessionKeyError, admin_packet, is_admin_refusal,
+    session_key_backoff,
+};
+use crate::connection::discovery::PortTarget;
+use crate::connection::handshake::{
+    ConnectionError, CountingStream, HandshakeOptions, HandshakeProgress, LinkStatus,
+    diagnose_handshake_failure,
+};
+use crate::connection::health::HealthProbe;
+use crate::connection::inject::{Injection, merge_injected};
+use crate::connection::keep_awake::{SerialWakeOptions, keep_awake};
+use crate::connection::processor::{PacketProcessor, ResponseWaiters};
+use crate::connection::send_queue::{RadioSender, RadioWriter, SendPriority};
+use crate::connection::simulation::{SIMULATED_TARGET, SimulationOptions, spawn_virtual_mesh};
+use crate::connection::stats::ConnectionCounters;
+use crate::connection::trace::PacketTracer;
+use crate::connection::{DuplicateFilter, PacketIdSource, discovery};
+use crate::events::{EventFilter, MeshEvent, publish, publish_changes};
+use crate::message::{AckOptions, AckReport};
+use crate::node_id::NodeId;
+use crate::presence::PresencePolicy;
+use crate::session_cache::{SessionCache, SessionId};
 use crate::state::{
-    AirQualityMetrics, BluetoothConfig, ChannelInfo, DeviceConfig, DeviceMetrics, DeviceState,
-    DisplayConfig, EnvironmentMetrics, LoraConfig, MyNodeInfo, NetworkConfig, NodeInfo, Position,
-    PositionConfig, PowerConfig, TelemetryData, TextMessage, User,
+    ChangeRecord, ChannelInfo, DeviceState, Position, RetentionPolicy, RetentionStats, StateSlice,
+    StateSnapshot, TelemetryData, TextMessage,
 };
 
-/// A simple packet router that doesn't handle incoming packets
-struct NoOpRouter;
+/// Reconnection attempts after the link drops, e.g. a USB serial reset
+const RECONNECT_ATTEMPTS: u32 = 5;
+
+/// Pause before each reconnection attempt while the device boots
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+
+/// Reply to a want_response request
+#[derive(Debug, Clone)]
+pub enum RequestResponse {
+    Position(Position),
+    /// The node replied without a usable position, e.g. as it has no fix
+    NoPosition,
+    Telemetry(TelemetryData),
+    Channel(ChannelInfo),
+    Owner(meshtastic::protobufs::User),
+}
 
-impl PacketRouter<(), std::io::Error> for NoOpRouter {
-    fn handle_packet_from_radio(
-        &mut self,
-        _packet: meshtastic::protobufs::FromRadio,
-    ) -> std::result::Result<(), std::io::Error> {
-        Ok(())
+/// A sent request waiting for its reply
+///
+/// Dropping the handle unregisters the waiter.
+pub struct PendingResponse {
+    request_id: u32,
+    receiver: oneshot::Receiver<RequestResponse>,
+    waiters: ResponseWaiters,
+    registered_at: tokio::time::Instant,
+    counters: ConnectionCounters,
+}
+
+impl PendingResponse {
+    /// Packet id of the request, echoed as `request_id` in the reply
+    pub fn request_id(&self) -> u32 {
+        self.request_id
     }
 
-    fn handle_mesh_packet(
-        &mut self,
-        _packet: meshtastic::protobufs::MeshPacket,
-    ) -> std::result::Result<(), std::io::Error> {
-        Ok(())
+    /// Wait for the reply, giving up after `timeout`
+    pub async fn wait(self, timeout: Duration) -> Option<RequestResponse> {
+        self.wait_until(tokio::time::Instant::now() + timeout).await
     }
 
-    fn source_node_id(&self) -> meshtastic::types::NodeId {
-        0u32.into()
+    /// Wait for the reply, giving up at `deadline`
+    pub async fn wait_until(mut self, deadline: tokio::time::Instant) -> Option<RequestResponse> {
+        match tokio::time::timeout_at(deadline, &mut self.receiver).await {
+            Ok(Ok(response)) => {
+                self.counters.record_response(self.registered_at.elapsed());
+                Some(response)
+            }
+            Ok(Err(_)) => {
+                debug!(
+                    "Response channel closed for request {request_id}",
+                    request_id = self.request_id
+                );
+                None
+            }
+            Err(_) => {
+                debug!(
+                    "Response timeout for request {request_id}",
+                    request_id = self.request_id
+                );
+                self.counters.record_timeout();
+                None
+            }
+        }
     }
 }
 
+impl Drop for PendingResponse {
+    fn drop(&mut self) {
+        if let Ok(mut waiters) = self.waiters.lock() {
+            waiters.remove(&self.request_id);
+        }
+    }
+}
+
+/// A send refused because the manager is in listen-only mode
+#[derive(Debug, thiserror::Error)]
+#[error("Refusing to transmit {what}: listen-only mode is on")]
+pub struct ListenOnlyError {
+    pub what: String,
+}
+
 pub struct ConnectionManager {
     port: Option<String>,
     ble: Option<String>,
+    /// Connect to an in-process virtual mesh instead of a radio
+    simulation: Option<SimulationOptions>,
+    /// Wake sequence and keep-awake heartbeat for serial devices
+    serial_wake: SerialWakeOptions,
     #[allow(dead_code)] // Will be used for connection timeouts in the future
     timeout: Duration,
-    api: Option<ConnectedStreamApi<Configured>>,
-    packet_receiver: Option<PacketReceiver>,
-    device_state: Arc<Mutex<DeviceState>>,
+    handshake: HandshakeOptions,
+    handshake_progress: watch::Sender<HandshakeProgress>,
+    link_status: watch::Sender<LinkStatus>,
+    /// Reboots already resynced, compared against `link_status`
+    handled_reboots: u64,
+    /// Port or address of the current connection, for diagnostics
+    target: String,
+    counters: ConnectionCounters,
+    /// The single task writing frames to the radio, while connected
+    writer: Option<RadioWriter<ConnectedStreamApi<Configured>>>,
+    packet_forwarder: Arc<std::sync::Mutex<Option<mpsc::UnboundedSender<FromRadio>>>>,
+    /// Device state and the waiters the received frames resolve
+    processor: PacketProcessor,
     packet_processor: Option<JoinHandle<()>>,
-    ack_waiters: Arc<Mutex<HashMap<u32, oneshot::Sender<bool>>>>,
-    route_waiters: Arc<Mutex<HashMap<u32, oneshot::Sender<Vec<crate::mesh::RouteHop>>>>>,
-    admin_session_passkey: Arc<Mutex<Option<Vec<u8>>>>,
+    duplicate_filter: Arc<Mutex<DuplicateFilter>>,
+    /// Ids of packets we sent, to recognise their echoes
+    sent_packets: Arc<Mutex<DuplicateFilter>>,
+    /// Keep echoes of our own text messages instead of dropping them
+    include_own: bool,
+    packet_ids: PacketIdSource,
+    tracer: PacketTracer,
+    /// Refuse every send that could reach the mesh
+    listen_only: bool,
+    /// Where the local passkey is kept between runs, if anywhere
+    session_cache: Option<SessionCache>,
+    /// Recorded frames to feed in after the next handshake
+    injection: Option<Injection>,
 }
 
 impl ConnectionManager {
@@ -59,25 +164,122 @@ impl ConnectionManager {
         Ok(Self {
             port,
             ble,
+            simulation: None,
+            serial_wake: SerialWakeOptions::default(),
             timeout,
-            api: None,
-            packet_receiver: None,
-            device_state: Arc::new(Mutex::new(DeviceState::new())),
+            handshake: HandshakeOptions::default(),
+            handshake_progress: watch::channel(HandshakeProgress::default()).0,
+            link_status: watch::channel(LinkStatus::default()).0,
+            handled_reboots: 0,
+            target: String::new(),
+            counters: ConnectionCounters::default(),
+            writer: None,
+            packet_forwarder: Arc::new(std::sync::Mutex::new(None)),
+            processor: PacketProcessor::new(Arc::new(Mutex::new(DeviceState::new()))),
             packet_processor: None,
-            ack_waiters: Arc::new(Mutex::new(HashMap::new())),
-            route_waiters: Arc::new(Mutex::new(HashMap::new())),
-            admin_session_passkey: Arc::new(Mutex::new(None)),
+            duplicate_filter: Arc::new(Mutex::new(DuplicateFilter::default())),
+            sent_packets: Arc::new(Mutex::new(DuplicateFilter::default())),
+            include_own: false,
+            packet_ids: PacketIdSource::new(),
+            tracer: PacketTracer::default(),
+            listen_only: false,
+            session_cache: None,
+            injection: None,
         })
     }
 
+    /// Configure the want_config handshake timeout and retry count
+    pub fn set_handshake_options(&mut self, options: HandshakeOptions) {
+        self.handshake = options;
+    }
+
+    /// Connect to a virtual mesh of fake nodes instead of the port or BLE device
+    pub fn set_simulation(&mut self, options: Option<SimulationOptions>) {
+        self.simulation = options;
+    }
+
+    pub fn is_simulated(&self) -> bool {
+        self.simulation.is_some()
+    }
+
+    /// Configure how serial devices are woken on connect and kept awake
+    pub fn set_serial_wake(&mut self, options: SerialWakeOptions) {
+        self.serial_wake = options;
+    }
+
+    /// Never transmit: mesh packets, admin requests and broker downlinks are
+    /// refused with [`ListenOnlyError`]
+    ///
+    /// Frames only the connected device sees, such as the want_config
+    /// handshake, still go out, so the node database and config are read as
+    /// usual. Set before [`connect`](Self::connect) to also skip the admin
+    /// config requests made after the handshake.
+    pub fn set_listen_only(&mut self, enabled: bool) {
+        self.listen_only = enabled;
+    }
+
+    pub fn is_listen_only(&self) -> bool {
+        self.listen_only
+    }
+
+    /// Keep text messages sent from our own node when the device echoes them
+    /// back, as it does for broadcasts and messages to ourselves
+    ///
+    /// By default the packet processor drops them so they don't show up as
+    /// received messages. Takes effect on the next `connect()`.
+    pub fn set_include_own(&mut self, enabled: bool) {
+        self.include_own = enabled;
+    }
+
+    /// Reuse the local node's admin passkey across runs through a file
+    ///
+    /// Scripts running many admin commands in a row then skip the session
+    /// key request after the first one.
+    pub fn set_session_cache(&mut self, cache: Option<SessionCache>) {
+        self.session_cache = cache;
+    }
+
+    /// Feed recorded frames to the processor alongside the device's, once
+    /// the next `connect()` completes its handshake
+    pub fn set_injection(&mut self, injection: Option<Injection>) {
+        self.injection = injection;
+    }
+
+    fn ensure_can_transmit(&self, what: impl FnOnce() -> String) -> Result<(), ListenOnlyError> {
+        if self.listen_only {
+            return Err(ListenOnlyError { what: what() });
+        }
+        Ok(())
+    }
+
     pub async fn connect(&mut self) -> Result<()> {
         info!("Establishing connection to Meshtastic device...");
 
         // Create StreamApi instance
         let stream_api = StreamApi::new();
+        let started = Instant::now();
+        let counters = self.counters.clone();
+        counters.reset();
+        self.packet_ids.reseed(rand::random());
+        self.handshake_progress
+            .send_replace(HandshakeProgress::default());
+        self.link_status.send_replace(LinkStatus::default());
+        self.handled_reboots = 0;
 
         // Determine connection type and connect
-        let (packet_receiver, connected_api) = if let Some(_ble_addr) = &self.ble {
+        let (target, (packet_receiver, connected_api)) = if let Some(options) = &self.simulation {
+            info!(
+                "Connecting to a simulated mesh of {nodes} node(s)",
+                nodes = options.nodes
+            );
+            let stream = spawn_virtual_mesh(options.clone())?;
+            (
+                SIMULATED_TARGET.to_string(),
+                stream_api
+                    .connect(count_bytes(StreamHandle::from_stream(stream), &counters))
+                    .await,
+            )
+        } else if let Some(_ble_addr) = &self.ble {
             #[cfg(feature = "bluetooth")]
             {
                 info!("Connecting via Bluetooth to {addr}", addr = _ble_addr);
@@ -86,86 +288,101 @@ impl ConnectionManager {
                     .unwrap_or_else(|_| utils::stream::BleId::from_name(_ble_addr));
                 let stream = utils::stream::build_ble_stream(&ble_id, Duration::from_secs(10))
                     .await
-                    .context("Failed to connect via Bluetooth")?;
-                stream_api.connect(stream).await
+                    .map_err(|e| ConnectionError::NoDevice {
+                        target: _ble_addr.clone(),
+                        reason: format!("Bluetooth connection failed: {e}"),
+                    })?;
+                (
+                    _ble_addr.clone(),
+                    stream_api.connect(count_bytes(stream, &counters)).await,
+                )
             }
             #[cfg(not(feature = "bluetooth"))]
             {
                 bail!("Bluetooth support not compiled. Build with --features bluetooth");
             }
-        } else if let Some(port) = &self.port {
-            if port.contains(':') || port.starts_with("192.") || port.starts_with("10.") {
-                // TCP connection
-                info!("Connecting via TCP to {port}");
-                let stream = utils::stream::build_tcp_stream(port.clone())
-                    .await
-                    .context("Failed to connect via TCP")?;
-                stream_api.connect(stream).await
-            } else {
-                // Serial connection
-                info!("Connecting via serial port {port}");
-                let mut stream = utils::stream::build_serial_stream(
-                    port.clone(),
-                    None, // Use default baud rate
-                    None, // Use default DTR
-                    None, // Use default RTS
-                )
-                .context("Failed to connect via serial")?;
-
-                // Send wake sequence to force device resync (similar to Python implementation)
-                // This helps the device wake up and resync its serial state machine
-                use tokio::io::AsyncWriteExt;
-                let wake_sequence = vec![0xc3; 32]; // START2 byte repeated
-                if let Err(e) = stream.stream.write_all(&wake_sequence).await {
-                    debug!("Failed to send wake sequence: {e}");
+        } else {
+            let port = match &self.port {
+                Some(port) => discovery::parse_port(port),
+                None => {
+                    info!("Auto-detecting serial port...");
+                    discovery::auto_detect_target()?
                 }
-                if let Err(e) = stream.stream.flush().await {
-                    debug!("Failed to flush wake sequence: {e}");
+            };
+            match port {
+                PortTarget::Tcp(address) => {
+                    info!("Connecting via TCP to {address}");
+                    let stream = utils::stream::build_tcp_stream(address.clone())
+                        .await
+                        .map_err(|e| ConnectionError::NoDevice {
+                            target: address.clone(),
+                            reason: format!("TCP connection failed: {e}"),
+                        })?;
+                    (
+                        address,
+                        stream_api.connect(count_bytes(stream, &counters)).await,
+                    )
+                }
+                PortTarget::Unix(path) => {
+                    let target = PortTarget::Unix(path.clone()).to_string();
+                    info!("Connecting via unix socket to {target}");
+                    #[cfg(unix)]
+                    {
+                        // meshtasticd speaks the same framed API as over TCP
+                        let stream = tokio::net::UnixStream::connect(&path).await.map_err(|e| {
+                            ConnectionError::NoDevice {
+                                target: target.clone(),
+                                reason: format!("could not connect to the meshtasticd socket: {e}"),
+                            }
+                        })?;
+                        let stream = StreamHandle::from_stream(stream);
+                        (
+                            target,
+                            stream_api.connect(count_bytes(stream, &counters)).await,
+                        )
+                    }
+                    #[cfg(not(unix))]
+                    {
+                        bail!("Unix sockets are not supported on this platform, use TCP instead");
+                    }
+                }
+                PortTarget::Serial(port) => {
+                    info!("Connecting via serial port {port}");
+                    let mut stream = utils::stream::build_serial_stream(
+                        port.clone(),
+                        None, // Use default baud rate
+                        None, // Use default DTR
+                        None, // Use default RTS
+                    )
+                    .map_err(|e| ConnectionError::NoDevice {
+                        target: port.clone(),
+                        reason: format!("could not open serial port: {e}"),
+                    })?;
+                    if self.serial_wake.wake_on_connect {
+                        wake_serial_device(&mut stream.stream, &self.serial_wake.wake_sequence)
+                            .await;
+                    }
+                    let connected = match self.serial_wake.keep_awake {
+                        Some(interval) => {
+                            info!(
+                                "Keeping the device awake every {secs}s",
+                                secs = interval.as_secs()
+                            );
+                            let stream = StreamHandle {
+                                stream: keep_awake(
+                                    stream.stream,
+                                    interval,
+                                    self.serial_wake.wake_sequence.clone(),
+                                ),
+                                join_handle: stream.join_handle,
+                            };
+                            stream_api.connect(count_bytes(stream, &counters)).await
+                        }
+                        None => stream_api.connect(count_bytes(stream, &counters)).await,
+                    };
+                    (port, connected)
                 }
-
-                // Add a brief delay for serial port stabilization
-                // This helps avoid initial sync errors with stale data
-                tokio::time::sleep(Duration::from_millis(100)).await;
-
-                stream_api.connect(stream).await
-            }
-        } else {
-            // Auto-detect serial port
-            info!("Auto-detecting serial port...");
-            let ports =
-                utils::stream::available_serial_ports().context("Failed to list serial ports")?;
-
-            ensure!(
-                !ports.is_empty(),
-                "No serial ports found. Please specify --port or --ble"
-            );
-
-            let port_name = ports[0].clone();
-            info!("Using auto-detected port: {port_name}");
-
-            let mut stream = utils::stream::build_serial_stream(
-                port_name, None, // Use default baud rate
-                None, // Use default DTR
-                None, // Use default RTS
-            )
-            .context("Failed to connect to auto-detected serial port")?;
-
-            // Send wake sequence to force device resync (similar to Python implementation)
-            // This helps the device wake up and resync its serial state machine
-            use tokio::io::AsyncWriteExt;
-            let wake_sequence = vec![0xc3; 32]; // START2 byte repeated
-            if let Err(e) = stream.stream.write_all(&wake_sequence).await {
-                debug!("Failed to send wake sequence: {e}");
-            }
-            if let Err(e) = stream.stream.flush().await {
-                debug!("Failed to flush wake sequence: {e}");
             }
-
-            // Add a brief delay for serial port stabilization
-            // This helps avoid initial sync errors with stale data
-            tokio::time::sleep(Duration::from_millis(100)).await;
-
-            stream_api.connect(stream).await
         };
 
         // Configure the connection
@@ -176,47 +393,236 @@ impl ConnectionManager {
             .await
             .context("Failed to configure connection")?;
 
-        // Store the configured API
-        self.api = Some(configured_api);
+        // From here on every frame goes through the writer task
+        self.writer = Some(RadioWriter::spawn(configured_api, counters.clone()));
 
         // Start packet processing
         self.start_packet_processing(packet_receiver).await;
 
+        if let Err(e) = self
+            .wait_for_handshake(&target, config_id, &counters.bytes_read)
+            .await
+        {
+            if let Err(disconnect_error) = self.disconnect().await {
+                debug!("Failed to close connection after handshake failure: {disconnect_error}");
+            }
+            return Err(e);
+        }
+
         // Request all configuration from the device
-        if let Err(e) = self.request_all_configs().await {
+        if self.listen_only {
+            info!("Listen-only mode: using the configuration sent during the handshake");
+        } else if let Err(e) = self.request_all_configs().await {
             warn!("Failed to request device configuration: {e}");
             // Continue anyway as this is not critical for connection
         }
 
+        self.target = target;
+        counters.record_connect_time(started.elapsed());
+        self.link_status
+            .send_modify(|status| status.connected = true);
+
         info!("Connection established and configured successfully");
         Ok(())
     }
 
-    async fn start_packet_processing(&mut self, mut receiver: PacketReceiver) {
-        let device_state = self.device_state.clone();
-        let ack_waiters = self.ack_waiters.clone();
-        let route_waiters = self.route_waiters.clone();
-        let admin_session_passkey = self.admin_session_passkey.clone();
+    /// Resync with the device if it rebooted or the link dropped
+    ///
+    /// The packet processor only notices a reboot; the handshake is re-run
+    /// here, before the next request, so commands never act on the
+    /// configuration from before the restart. A dropped link is reopened.
+    /// Returns whether a resync took place.
+    pub async fn resync_if_rebooted(&mut self) -> Result<bool> {
+        let status = *self.link_status.borrow();
+
+        if status.link_lost {
+            warn!("Connection to the device was lost, reconnecting");
+            if let Err(e) = self.disconnect().await {
+                debug!("Failed to close the lost connection: {e}");
+            }
+
+            let mut last_error = None;
+            for attempt in 1..=RECONNECT_ATTEMPTS {
+                tokio::time::sleep(RECONNECT_DELAY).await;
+                match self.connect().await {
+                    Ok(()) => {
+                        info!("Reconnected to the device");
+                        return Ok(true);
+                    }
+                    Err(e) => {
+                        debug!("Reconnect attempt {attempt}/{RECONNECT_ATTEMPTS} failed: {e}");
+                        last_error = Some(e);
+                    }
+                }
+            }
+            return Err(last_error
+                .unwrap_or_else(|| anyhow!("Device did not come back"))
+                .context("Failed to reconnect after the device restarted"));
+        }
+
+        if status.reboots == self.handled_reboots {
+            return Ok(false);
+        }
+        self.handled_reboots = status.reboots;
+
+        info!("Device rebooted, resyncing");
+        let config_id = utils::generate_rand_id();
+        self.radio()?
+            .send(meshtastic::protobufs::to_radio::PayloadVariant::WantConfigId(config_id))
+            .await
+            .context("Failed to request config after reboot")?;
+
+        let target = self.target.clone();
+        let bytes_read = self.counters.bytes_read.clone();
+        self.wait_for_handshake(&target, config_id, &bytes_read)
+            .await?;
+
+        if !self.listen_only
+            && let Err(e) = self.request_all_configs().await
+        {
+            warn!("Failed to request device configuration after reboot: {e}");
+        }
+
+        info!("Device state resynced after reboot");
+        Ok(true)
+    }
+
+    /// Wait for the device to echo our config id, re-sending want_config on timeout
+    async fn wait_for_handshake(
+        &mut self,
+        target: &str,
+        mut config_id: u32,
+        bytes_read: &AtomicU64,
+    ) -> Result<()> {
+        let options = self.handshake;
+        let mut progress = self.handshake_progress.subscribe();
+
+        for attempt in 1..=options.attempts.max(1) {
+            // Consume the watch guard right away so it is not held across the resend
+            let completed = tokio::time::timeout(
+                options.timeout,
+                progress.wait_for(|p| p.config_complete_id == Some(config_id)),
+            )
+            .await
+            .is_ok_and(|result| result.is_ok());
+
+            if completed {
+                debug!("Handshake completed on attempt {attempt}");
+                return Ok(());
+            }
+
+            if attempt < options.attempts {
+                warn!(
+                    "Handshake attempt {attempt} timed out after {secs}s, retrying",
+                    secs = options.timeout.as_secs()
+                );
+                config_id = utils::generate_rand_id();
+                self.radio()?
+                    .send(meshtastic::protobufs::to_radio::PayloadVariant::WantConfigId(config_id))
+                    .await
+                    .context("Failed to resend config request")?;
+            }
+        }
+
+        // Having our own node info means the device is talking, just slowly
+        if self
+            .processor
+            .device_state
+            .lock()
+            .await
+            .my_node_info
+            .is_some()
+        {
+            warn!("Device did not confirm the config handshake, continuing with partial state");
+            return Ok(());
+        }
+
+        let frames_received = progress.borrow().frames_received;
+        Err(diagnose_handshake_failure(
+            target,
+            &options,
+            bytes_read.load(Ordering::Relaxed),
+            frames_received,
+        )
+        .into())
+    }
+
+    async fn start_packet_processing(&mut self, receiver: PacketReceiver) {
+        let mut receiver = match self.injection.take() {
+            Some(injection) => {
+                merge_injected(receiver, injection, self.handshake_progress.subscribe())
+            }
+            None => receiver,
+        };
+        let processor = self.processor.clone();
+        let device_state = processor.device_state();
+        let duplicate_filter = self.duplicate_filter.clone();
+        let sent_packets = self.sent_packets.clone();
+        let include_own = self.include_own;
+        let packet_forwarder = self.packet_forwarder.clone();
+        let handshake_progress = self.handshake_progress.clone();
+        let link_status = self.link_status.clone();
+        let tracer = self.tracer.clone();
+        let frames_processed = self.counters.frames_processed.clone();
+        let last_frame_at = self.counters.last_frame_at.clone();
 
         // Spawn a background task to process packets
         let handle = tokio::spawn(async move {
             info!("Starting packet processing loop");
 
-            while let Some(packet) = receiver.recv().await {
-                if let Err(e) = process_from_radio_packet(
-                    packet,
-                    device_state.clone(),
-                    ack_waiters.clone(),
-                    route_waiters.clone(),
-                    admin_session_passkey.clone(),
-                )
-                .await
+            while let Some(mut packet) = receiver.recv().await {
+                handshake_progress.send_modify(|progress| {
+                    progress.frames_received += 1;
+                    if let Some(
+                        meshtastic::protobufs::from_radio::PayloadVariant::ConfigCompleteId(id),
+                    ) = &packet.payload_variant
+                    {
+                        progress.config_complete_id = Some(*id);
+                    }
+                });
+
+                // Rebroadcasts deliver the same packet multiple times; only handle it once
+                if is_duplicate_packet(&packet, &duplicate_filter, &device_state).await {
+                    continue;
+                }
+
+                if claim_own_text_message(&mut packet, &sent_packets, &device_state).await
+                    && !include_own
+                {
+                    debug!("Dropping the echo of our own text message");
+                    continue;
+                }
+
+                if let Some(reboot_count) = detect_reboot(&packet, &device_state).await {
+                    warn!("Device rebooted, cached configuration is stale; resyncing");
+                    device_state.lock().await.clear_device_config();
+                    // The device issues new session keys after a restart
+                    processor.admin_session_keys.lock().await.clear();
+                    link_status.send_modify(|status| status.reboots += 1);
+                    publish(
+                        &processor.events,
+                        MeshEvent::DeviceRebooted { reboot_count },
+                    );
+                }
+
+                // Forward the packet to the subscriber of take_packet_receiver, if any
+                if let Ok(slot) = packet_forwarder.lock()
+                    && let Some(sender) = slot.as_ref()
+                    && sender.send(packet.clone()).is_err()
                 {
+                    debug!("Packet receiver dropped, no longer forwarding packets");
+                }
+
+                frames_processed.fetch_add(1, Ordering::Relaxed);
+                last_frame_at.store(crate::time::unix_now(), Ordering::Relaxed);
+                let span = tracer.receive_span(&packet);
+                if let Err(e) = processor.process_from_radio(packet).instrument(span).await {
                     warn!("Error processing packet: {e}");
                 }
             }
 
             info!("Packet processing loop ended");
+            link_status.send_modify(|status| status.link_lost = true);
         });
 
         self.packet_processor = Some(handle);
@@ -227,8 +633,13 @@ impl ConnectionManager {
         tokio::time::sleep(Duration::from_millis(500)).await;
     }
 
+    /// Handle on the traffic counters, readable after the manager is moved
+    pub fn counters(&self) -> ConnectionCounters {
+        self.counters.clone()
+    }
+
     pub fn is_connected(&self) -> bool {
-        self.api.is_some()
+        self.writer.is_some()
     }
 
     pub async fn disconnect(&mut self) -> Result<()> {
@@ -236,29 +647,422 @@ impl ConnectionManager {
             processor.abort();
         }
 
-        if let Some(api) = self.api.take() {
-            api.disconnect().await?;
+        self.link_status
+            .send_modify(|status| status.connected = false);
+
+        if let Some(writer) = self.writer.take() {
+            writer.close().await?.disconnect().await?;
         }
 
         Ok(())
     }
 
-    pub fn get_api(&mut self) -> Result<&mut ConnectedStreamApi<Configured>> {
-        self.api.as_mut().context("Not connected")
+    /// Handle for sending raw frames from other tasks, such as a beacon
+    /// running next to a monitor
+    ///
+    /// Its frames share the writer with the manager's own sends, so they
+    /// never interleave on the link. Refused in listen-only mode, since
+    /// anything sent through it may reach the mesh.
+    pub fn radio_sender(&self) -> Result<RadioSender> {
+        self.ensure_can_transmit(|| "through a radio sender".to_string())?;
+        self.radio().cloned()
+    }
+
+    fn radio(&self) -> Result<&RadioSender> {
+        self.writer
+            .as_ref()
+            .map(RadioWriter::sender)
+            .context("Not connected")
     }
 
     pub async fn get_device_state(&self) -> DeviceState {
-        self.device_state.lock().await.clone()
+        self.processor.device_state.lock().await.clone()
     }
 
     pub fn get_device_state_ref(&self) -> Arc<Mutex<DeviceState>> {
-        self.device_state.clone()
+        self.processor.device_state.clone()
+    }
+
+    /// Wait until the cached state holds every slice, or `timeout` passes
+    ///
+    /// Returns the slices still missing, so callers can warn that their
+    /// output may be incomplete. A device whose handshake timed out, or a
+    /// slow one, may still be sending its state after `connect()`.
+    pub async fn wait_for_state(
+        &self,
+        slices: &[StateSlice],
+        timeout: Duration,
+    ) -> Vec<StateSlice> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        // Every frame received bumps the handshake progress
+        let mut progress = self.handshake_progress.subscribe();
+        loop {
+            let config_complete = progress.borrow_and_update().config_complete_id.is_some();
+            let missing = {
+                let state = self.processor.device_state.lock().await;
+                slices
+                    .iter()
+                    .copied()
+                    .filter(|slice| !slice.is_ready(&state, config_complete))
+                    .collect::<Vec<_>>()
+            };
+            if missing.is_empty() {
+                return missing;
+            }
+            match tokio::time::timeout_at(deadline, progress.changed()).await {
+                Ok(Ok(())) => {}
+                Ok(Err(_)) | Err(_) => return missing,
+            }
+        }
+    }
+
+    /// Snapshot of the cached device state, secrets included, to persist
+    /// between runs
+    pub async fn export_state(&self) -> StateSnapshot {
+        self.processor.device_state.lock().await.to_snapshot()
+    }
+
+    /// Replace the cached device state with a snapshot
+    ///
+    /// Importing before `connect()` starts from what an earlier run knew,
+    /// such as nodes no longer in the device's node database; the handshake
+    /// then updates it with what the device reports.
+    pub async fn import_state(&self, snapshot: StateSnapshot) -> Result<()> {
+        let state = DeviceState::from_snapshot(snapshot)?;
+        *self.processor.device_state.lock().await = state;
+        Ok(())
+    }
+
+    /// Configure how many messages are kept in the cached device state
+    pub async fn set_retention_policy(&self, policy: RetentionPolicy) {
+        self.processor
+            .device_state
+            .lock()
+            .await
+            .set_retention_policy(policy);
+    }
+
+    /// Drop cached messages older than `older_than`, or all of them,
+    /// returning how many were dropped
+    pub async fn clear_messages(&self, older_than: Option<Duration>) -> usize {
+        let cutoff = older_than.map(|age| crate::time::unix_now().saturating_sub(age.as_secs()));
+        self.processor
+            .device_state
+            .lock()
+            .await
+            .clear_messages(cutoff)
+    }
+
+    /// Configure when nodes are reported as online, recently heard or offline
+    pub async fn set_presence_policy(&self, policy: PresencePolicy) {
+        self.processor.device_state.lock().await.presence = policy;
+    }
+
+    /// Recent positions of a node, oldest first, bounded by the retention policy
+    pub async fn get_position_history(&self, node_num: u32) -> Vec<Position> {
+        self.processor
+            .device_state
+            .lock()
+            .await
+            .position_history(node_num)
+            .cloned()
+            .collect()
+    }
+
+    /// Recent telemetry reports of a node, oldest first
+    pub async fn get_telemetry_history(&self, node_num: u32) -> Vec<TelemetryData> {
+        self.processor
+            .device_state
+            .lock()
+            .await
+            .telemetry_history(node_num)
+            .cloned()
+            .collect()
+    }
+
+    /// Positions recorded at or after `since` (Unix seconds), oldest first
+    pub async fn positions_since(&self, since: u64) -> Vec<Position> {
+        self.processor
+            .device_state
+            .lock()
+            .await
+            .positions_since(since)
+            .into_iter()
+            .cloned()
+            .collect()
+    }
+
+    /// Telemetry reports from `start` to `end` inclusive, oldest first
+    pub async fn telemetry_between(&self, start: u64, end: u64) -> Vec<TelemetryData> {
+        self.processor
+            .device_state
+            .lock()
+            .await
+            .telemetry_between(start, end)
+            .into_iter()
+            .cloned()
+            .collect()
+    }
+
+    /// Messages received at or after `since` (Unix seconds), oldest first
+    pub async fn messages_since(&self, since: u64) -> Vec<TextMessage> {
+        self.processor
+            .device_state
+            .lock()
+            .await
+            .messages_since(since)
+            .cloned()
+            .collect()
     }
 
+    /// Get the counters of entries dropped by the retention policy
+    pub async fn get_retention_stats(&self) -> RetentionStats {
+        self.processor
+            .device_state
+            .lock()
+            .await
+            .retention_stats
+            .clone()
+    }
+
+    /// Subscribe to state changes as they are processed
+    ///
+    /// Any number of subscribers can be active; each receives every event
+    /// published after it subscribed.
+    pub fn subscribe_events(&self) -> broadcast::Receiver<MeshEvent> {
+        self.processor.events.subscribe()
+    }
+
+    /// Subscribe to the events passing `filter` only
+    ///
+    /// Other events are never queued for this subscriber, so on a busy mesh
+    /// it doesn't lag behind traffic it would throw away.
+    pub fn subscribe_filtered(&self, filter: EventFilter) -> broadcast::Receiver<MeshEvent> {
+        self.processor.subscribe_filtered(filter)
+    }
+
+    /// Handle reporting the health of this connection, for health probes
+    /// served while the manager is busy in a command
+    pub fn health_probe(&self) -> HealthProbe {
+        HealthProbe::new(
+            self.link_status.subscribe(),
+            self.handshake_progress.subscribe(),
+            self.counters.clone(),
+            self.processor.clone(),
+        )
+    }
+
+    /// Publish records a command added to the change journal
+    pub(crate) fn publish_changes(&self, changes: impl IntoIterator<Item = ChangeRecord>) {
+        publish_changes(&self.processor.events, changes);
+    }
+
+    /// Allocate an id for an outgoing packet
+    ///
+    /// All senders share this sequence so ids never repeat within a session.
+    pub fn next_packet_id(&self) -> u32 {
+        self.packet_ids.next_id()
+    }
+
+    /// Send a mesh packet to the radio
+    ///
+    /// The send is traced with the packet id and port, and the reply to it,
+    /// if any, is logged with the round-trip time. Packets queued behind
+    /// others are written by their [`SendPriority`], so admin messages
+    /// overtake a telemetry poll running from another task.
+    pub async fn send_mesh_packet(
+        &mut self,
+        packet: meshtastic::protobufs::MeshPacket,
+    ) -> Result<()> {
+        let priority = SendPriority::of_packet(&packet);
+        self.send_mesh_packet_with_priority(packet, priority).await
+    }
+
+    /// Send a mesh packet to the radio at `priority`, e.g. as part of a poll
+    pub async fn send_mesh_packet_with_priority(
+        &mut self,
+        packet: meshtastic::protobufs::MeshPacket,
+        priority: SendPriority,
+    ) -> Result<()> {
+        self.ensure_can_transmit(|| {
+            format!(
+                "packet {id} to {to}",
+                id = packet.id,
+                to = NodeId(packet.to)
+            )
+        })?;
+        let span = PacketTracer::send_span(&packet);
+        self.tracer.record_sent(&packet);
+        if packet.id != 0 {
+            self.sent_packets.lock().await.insert(0, packet.id);
+        }
+        self.radio()?
+            .send_with_priority(
+                meshtastic::protobufs::to_radio::PayloadVariant::Packet(packet),
+                priority,
+            )
+            .instrument(span)
+            .await?;
+        Ok(())
+    }
+
+    /// Send a data payload to `destination` on `channel`
+    ///
+    /// Returns the id of the packet, to match an ACK or reply against.
+    pub async fn send_data(
+        &mut self,
+        destination: u32,
+        channel: u32,
+        data: meshtastic::protobufs::Data,
+        want_ack: bool,
+    ) -> Result<u32> {
+        let priority = SendPriority::of_port(data.portnum());
+        self.send_data_with_priority(destination, channel, data, want_ack, priority)
+            .await
+    }
+
+    /// Send a data payload at `priority`, e.g. as part of a poll
+    pub async fn send_data_with_priority(
+        &mut self,
+        destination: u32,
+        channel: u32,
+        data: meshtastic::protobufs::Data,
+        want_ack: bool,
+        priority: SendPriority,
+    ) -> Result<u32> {
+        let id = self.packet_ids.next_id();
+        let mesh_packet = meshtastic::protobufs::MeshPacket {
+            payload_variant: Some(meshtastic::protobufs::mesh_packet::PayloadVariant::Decoded(
+                data,
+            )),
+            to: destination,
+            id,
+            channel,
+            hop_limit: 3, // Firmware default hop limit
+            want_ack,
+            ..Default::default()
+        };
+        self.send_mesh_packet_with_priority(mesh_packet, priority)
+            .await?;
+        Ok(id)
+    }
+
+    /// Send any frame to the radio
+    ///
+    /// Mesh packets go through [`send_mesh_packet`](Self::send_mesh_packet).
+    /// In listen-only mode MQTT proxy messages are refused too, since the
+    /// device rebroadcasts downlinked traffic; frames for the device alone
+    /// are allowed.
+    pub async fn send_to_radio(
+        &mut self,
+        payload: meshtastic::protobufs::to_radio::PayloadVariant,
+    ) -> Result<()> {
+        use meshtastic::protobufs::to_radio::PayloadVariant;
+
+        match payload {
+            PayloadVariant::Packet(packet) => return self.send_mesh_packet(packet).await,
+            PayloadVariant::MqttClientProxyMessage(_) => {
+                self.ensure_can_transmit(|| "an MQTT proxy message".to_string())?;
+            }
+            _ => {}
+        }
+        self.radio()?.send(payload).await?;
+        Ok(())
+    }
+
+    /// Subscribe to the raw packets received from the device
+    ///
+    /// Packets are forwarded by the processing loop from the moment the
+    /// receiver is taken. Only one receiver can be active at a time.
     pub fn take_packet_receiver(&mut self) -> Result<PacketReceiver> {
-        self.packet_receiver
-            .take()
-            .context("Packet receiver already taken or not connected")
+        ensure!(self.is_connected(), "Not connected");
+
+        let mut slot = self
+            .packet_forwarder
+            .lock()
+            .map_err(|_| anyhow!("Packet forwarder lock poisoned"))?;
+        ensure!(
+            slot.as_ref().is_none_or(|sender| sender.is_closed()),
+            "Packet receiver already taken"
+        );
+
+        let (sender, receiver) = mpsc::unbounded_channel();
+        *slot = Some(sender);
+        Ok(receiver)
+    }
+
+    /// Register a waiter for the reply to the packet with id `request_id`
+    ///
+    /// Register before sending the request, so a fast reply cannot be missed.
+    pub fn register_response(&self, request_id: u32) -> Result<PendingResponse> {
+        let (tx, rx) = oneshot::channel();
+        self.processor
+            .response_waiters
+            .lock()
+            .map_err(|_| anyhow!("Response waiter lock poisoned"))?
+            .insert(request_id, tx);
+        Ok(PendingResponse {
+            request_id,
+            receiver: rx,
+            waiters: self.processor.response_waiters.clone(),
+            registered_at: tokio::time::Instant::now(),
+            counters: self.counters.clone(),
+        })
+    }
+
+    /// Send a want_response request and register a waiter for its reply
+    ///
+    /// Position and telemetry replies echo the packet id as `request_id`, so
+    /// the returned handle resolves as soon as the reply is processed.
+    pub async fn send_request(
+        &mut self,
+        destination: u32,
+        portnum: meshtastic::protobufs::PortNum,
+        payload: Vec<u8>,
+    ) -> Result<PendingResponse> {
+        let priority = SendPriority::of_port(portnum);
+        self.send_request_with_priority(destination, portnum, payload, priority)
+            .await
+    }
+
+    /// Send a request at `priority`, e.g. one of many in a poll
+    pub async fn send_request_with_priority(
+        &mut self,
+        destination: u32,
+        portnum: meshtastic::protobufs::PortNum,
+        payload: Vec<u8>,
+        priority: SendPriority,
+    ) -> Result<PendingResponse> {
+        self.resync_if_rebooted().await?;
+
+        let request_id = self.packet_ids.next_id();
+        // Register before sending so a fast reply cannot be missed
+        let pending = self.register_response(request_id)?;
+
+        let mesh_packet = meshtastic::protobufs::MeshPacket {
+            payload_variant: Some(meshtastic::protobufs::mesh_packet::PayloadVariant::Decoded(
+                meshtastic::protobufs::Data {
+                    portnum: portnum as i32,
+                    payload,
+                    want_response: true,
+                    ..Default::default()
+                },
+            )),
+            to: destination,
+            id: request_id,
+            hop_limit: 3, // Firmware default hop limit
+            priority: meshtastic::protobufs::mesh_packet::Priority::Reliable as i32,
+            ..Default::default()
+        };
+
+        self.send_mesh_packet_with_priority(mesh_packet, priority)
+            .await?;
+
+        debug!(
+            "Sent {portnum:?} request {request_id} to {destination}",
+            destination = NodeId(destination)
+        );
+        Ok(pending)
     }
 
     pub async fn send_traceroute(
@@ -266,15 +1070,17 @@ impl ConnectionManager {
         destination: u32,
         timeout_secs: u64,
     ) -> Result<Vec<crate::mesh::RouteHop>> {
+        self.resync_if_rebooted().await?;
+
         // Generate a unique request ID for tracking
-        let request_id = rand::random::<u32>();
+        let request_id = self.packet_ids.next_id();
 
         // Create a oneshot channel for route response
         let (tx, rx) = oneshot::channel();
 
         // Register the route waiter
         {
-            let mut waiters = self.route_waiters.lock().await;
+            let mut waiters = self.processor.route_waiters.lock().await;
             waiters.insert(request_id, tx);
         }
 
@@ -324,17 +1130,20 @@ impl ConnectionManager {
         };
 
         // Send the traceroute packet
-        let api = self.get_api()?;
-        api.send_to_radio_packet(Some(
-            meshtastic::protobufs::to_radio::PayloadVariant::Packet(mesh_packet),
-        ))
-        .await?;
+        let sent_at = tokio::time::Instant::now();
+        self.send_mesh_packet(mesh_packet).await?;
 
-        debug!("Sent traceroute to {destination:08x} with request ID {request_id}");
+        debug!(
+            "Sent traceroute to {destination} with request ID {request_id}",
+            destination = NodeId(destination)
+        );
 
         // Wait for route response with timeout
         match tokio::time::timeout(Duration::from_secs(timeout_secs), rx).await {
-            Ok(Ok(hops)) => Ok(hops),
+            Ok(Ok(hops)) => {
+                self.counters.record_response(sent_at.elapsed());
+                Ok(hops)
+            }
             Ok(Err(_)) => {
                 // Channel was closed without receiving data
                 debug!("Traceroute channel closed for request {request_id}");
@@ -342,9 +1151,10 @@ impl ConnectionManager {
             }
             Err(_) => {
                 // Timeout occurred, clean up the waiter
-                let mut waiters = self.route_waiters.lock().await;
+                let mut waiters = self.processor.route_waiters.lock().await;
                 waiters.remove(&request_id);
                 debug!("Traceroute timeout for request {request_id}");
+                self.counters.record_timeout();
                 Ok(Vec::new())
             }
         }
@@ -354,8 +1164,6 @@ impl ConnectionManager {
     async fn request_all_configs(&mut self) -> Result<()> {
         info!("Requesting device configuration...");
 
-        let api = self.get_api()?;
-
         // List of config types to request
         let config_types = [
             meshtastic::protobufs::admin_message::ConfigType::DeviceConfig,
@@ -390,14 +1198,12 @@ impl ConnectionManager {
                     },
                 )),
                 to: 0, // Local destination
+                id: self.packet_ids.next_id(),
                 ..Default::default()
             };
 
             // Send config request
-            api.send_to_radio_packet(Some(
-                meshtastic::protobufs::to_radio::PayloadVariant::Packet(mesh_packet),
-            ))
-            .await?;
+            self.send_mesh_packet(mesh_packet).await?;
 
             // Small delay between requests to avoid overwhelming the device
             tokio::time::sleep(Duration::from_millis(100)).await;
@@ -410,708 +1216,447 @@ impl ConnectionManager {
         Ok(())
     }
 
+    /// Send a text message with want_ack and wait for its ACK
+    ///
+    /// Without an ACK within `options.timeout`, or after a NAK, the message is
+    /// sent again with a new packet id, up to `options.retries` times. An ACK
+    /// for any of the transmissions counts as delivered.
     pub async fn send_text_with_ack(
         &mut self,
         text: String,
         destination: u32,
-        channel: u8,
-        timeout_secs: u64,
-    ) -> Result<bool> {
-        // Generate a unique packet ID for tracking
-        let packet_id = rand::random::<u32>();
-
-        // Create a oneshot channel for ACK notification
-        let (tx, rx) = oneshot::channel();
+        channel: u32,
+        options: AckOptions,
+    ) -> Result<AckReport> {
+        self.resync_if_rebooted().await?;
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let attempts = options.retries.saturating_add(1);
+        let mut sent_ids = Vec::new();
+        let mut report = AckReport {
+            acknowledged: false,
+            attempts: 0,
+            packet_id: 0,
+        };
 
-        // Register the ACK waiter
-        {
-            let mut waiters = self.ack_waiters.lock().await;
-            waiters.insert(packet_id, tx);
-        }
+        for attempt in 1..=attempts {
+            // A reused id would be dropped by the mesh as a duplicate
+            let packet_id = self.packet_ids.next_id();
+            self.processor
+                .ack_waiters
+                .lock()
+                .await
+                .insert(packet_id, tx.clone());
+            sent_ids.push(packet_id);
 
-        // Create a no-op packet router for sending
-        let mut router = NoOpRouter;
-
-        // Send the message with want_ack set to true
-        let api = self.get_api()?;
-        api.send_mesh_packet(
-            &mut router,
-            text.into_bytes().into(),
-            meshtastic::protobufs::PortNum::TextMessageApp,
-            if destination == 0xFFFFFFFF {
-                meshtastic::packet::PacketDestination::Broadcast
-            } else {
-                meshtastic::packet::PacketDestination::Node(destination.into())
-            },
-            (channel as u32).into(),
-            true,  // want_ack
-            false, // want_response
-            false, // echo_response
-            Some(packet_id),
-            None, // emoji
-        )
-        .await?;
+            // Build the packet ourselves so the ACK's request_id matches our packet ID
+            let mesh_packet = meshtastic::protobufs::MeshPacket {
+                payload_variant: Some(meshtastic::protobufs::mesh_packet::PayloadVariant::Decoded(
+                    meshtastic::protobufs::Data {
+                        portnum: meshtastic::protobufs::PortNum::TextMessageApp as i32,
+                        payload: text.as_bytes().to_vec(),
+                        ..Default::default()
+                    },
+                )),
+                to: destination,
+                id: packet_id,
+                channel,
+                hop_limit: 3, // Firmware default hop limit
+                want_ack: true,
+                priority: meshtastic::protobufs::mesh_packet::Priority::Reliable as i32,
+                ..Default::default()
+            };
 
-        debug!("Sent message with ID {packet_id} and ACK request");
+            let sent_at = tokio::time::Instant::now();
+            let sent = self.send_mesh_packet(mesh_packet).await;
+            if let Err(e) = sent {
+                self.remove_ack_waiters(&sent_ids).await;
+                return Err(e);
+            }
+            debug!(
+                "Sent message with ID {packet_id} and ACK request (attempt {attempt}/{attempts})"
+            );
 
-        // Wait for ACK with timeout
-        match tokio::time::timeout(Duration::from_secs(timeout_secs), rx).await {
-            Ok(Ok(ack)) => Ok(ack),
-            Ok(Err(_)) => {
-                // Channel was closed without receiving ACK
-                debug!("ACK channel closed for packet {packet_id}");
-                Ok(false)
+            report.attempts = attempt;
+            report.packet_id = packet_id;
+            if let Some(acked_id) = wait_for_ack(&mut rx, packet_id, options.timeout).await {
+                self.counters.record_response(sent_at.elapsed());
+                report.acknowledged = true;
+                report.packet_id = acked_id;
+                break;
             }
-            Err(_) => {
-                // Timeout occurred, clean up the waiter
-                let mut waiters = self.ack_waiters.lock().await;
-                waiters.remove(&packet_id);
-                debug!("ACK timeout for packet {packet_id}");
-                Ok(false)
+            self.counters.record_timeout();
+
+            if attempt < attempts {
+                info!(
+                    "No ACK for packet {packet_id}, retransmitting (attempt {next}/{attempts})",
+                    next = attempt + 1
+                );
             }
         }
+
+        // ACKs arriving later, e.g. for an earlier transmission, are ignored
+        self.remove_ack_waiters(&sent_ids).await;
+        Ok(report)
+    }
+
+    async fn remove_ack_waiters(&self, packet_ids: &[u32]) {
+        let mut waiters = self.processor.ack_waiters.lock().await;
+        for packet_id in packet_ids {
+            waiters.remove(packet_id);
+        }
     }
 
     /// Request a session key from the device for admin operations
     pub async fn ensure_session_key(&mut self) -> Result<()> {
+        self.ensure_session_key_for(None).await
+    }
+
+    /// Request a session key from the local node (`None`) or a remote one
+    ///
+    /// Each node issues its own key, so remote admin needs one per target.
+    /// Unanswered requests are retried with a growing pause; a node that
+    /// rejects the request fails at once with [`SessionKeyError::Refused`].
+    pub async fn ensure_session_key_for(&mut self, node: Option<u32>) -> Result<()> {
+        self.resync_if_rebooted().await?;
+
         // Check if we already have a session key
+        if self.get_session_key_for(node).await.is_some() {
+            debug!("Session key already exists");
+            return Ok(());
+        }
+        if node.is_none()
+            && let Some(cache) = &self.session_cache
+            && let Some(id) = self.session_id().await
+            && let Some(key) = cache.load(&id)
         {
-            let session_key = self.admin_session_passkey.lock().await;
-            if session_key.is_some() {
-                debug!("Session key already exists");
-                return Ok(());
-            }
+            debug!(
+                "Reusing session key from {path}",
+                path = cache.path().display()
+            );
+            self.set_session_key(key).await;
+            return Ok(());
         }
 
-        info!("Requesting admin session key...");
-
-        let api = self.get_api()?;
-
-        // Create admin message for session key request
-        let admin_msg = meshtastic::protobufs::AdminMessage {
-            payload_variant: Some(
-                meshtastic::protobufs::admin_message::PayloadVariant::GetConfigRequest(
-                    meshtastic::protobufs::admin_message::ConfigType::SessionkeyConfig as i32,
-                ),
+        let destination = node.map_or(AdminDestination::Local, AdminDestination::Node);
+        match node {
+            Some(node) => info!(
+                "Requesting admin session key from {node}...",
+                node = NodeId(node)
             ),
-            session_passkey: Vec::new(),
-        };
+            None => info!("Requesting admin session key..."),
+        }
 
-        // Create mesh packet
-        let mesh_packet = meshtastic::protobufs::MeshPacket {
-            payload_variant: Some(meshtastic::protobufs::mesh_packet::PayloadVariant::Decoded(
-                meshtastic::protobufs::Data {
-                    portnum: meshtastic::protobufs::PortNum::AdminApp as i32,
-                    payload: admin_msg.encode_to_vec(),
-                    want_response: true,
-                    ..Default::default()
-                },
-            )),
-            to: 0, // Local destination
-            ..Default::default()
-        };
+        // Remote replies cross the mesh
+        let timeout = Duration::from_secs(if node.is_some() { 30 } else { 5 });
+
+        for attempt in 1..=SESSION_KEY_ATTEMPTS {
+            // Subscribe before sending so a fast refusal is not missed
+            let mut events = self.subscribe_events();
+            let packet_id = self.packet_ids.next_id();
 
-        // Send session key request
-        api.send_to_radio_packet(Some(
-            meshtastic::protobufs::to_radio::PayloadVariant::Packet(mesh_packet),
-        ))
-        .await?;
+            let admin_msg = meshtastic::protobufs::AdminMessage {
+                payload_variant: Some(
+                    meshtastic::protobufs::admin_message::PayloadVariant::GetConfigRequest(
+                        meshtastic::protobufs::admin_message::ConfigType::SessionkeyConfig as i32,
+                    ),
+                ),
+                session_passkey: Vec::new(),
+            };
+            let mesh_packet = admin_packet(destination, packet_id, &admin_msg, true);
 
-        // Wait for the session key to be received
-        let timeout = Duration::from_secs(5);
-        let start = std::time::Instant::now();
+            self.send_mesh_packet(mesh_packet).await?;
 
-        loop {
-            tokio::time::sleep(Duration::from_millis(100)).await;
+            let deadline = tokio::time::Instant::now() + timeout;
+            loop {
+                if let Some(key) = self.get_session_key_for(node).await {
+                    info!("Session key received successfully");
+                    if node.is_none() {
+                        self.cache_session_key(&key).await;
+                    }
+                    return Ok(());
+                }
+                if tokio::time::Instant::now() >= deadline {
+                    break;
+                }
 
-            let session_key = self.admin_session_passkey.lock().await;
-            if session_key.is_some() {
-                info!("Session key received successfully");
-                return Ok(());
+                tokio::select! {
+                    event = events.recv() => match event {
+                        Ok(MeshEvent::Nak(report)) if report.packet_id == packet_id => {
+                            if is_admin_refusal(&report.reason) {
+                                return Err(SessionKeyError::Refused {
+                                    target: destination.to_string(),
+                                    reason: report.reason,
+                                }
+                                .into());
+                            }
+                            debug!("Session key request not delivered: {reason}", reason = report.reason);
+                            break;
+                        }
+                        Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                        Err(broadcast::error::RecvError::Closed) => {
+                            tokio::time::sleep_until(deadline).await;
+                        }
+                    },
+                    _ = tokio::time::sleep(Duration::from_millis(100)) => {}
+                }
             }
 
-            if start.elapsed() > timeout {
-                bail!("Timeout waiting for session key");
+            if attempt < SESSION_KEY_ATTEMPTS {
+                let backoff = session_key_backoff(attempt);
+                warn!(
+                    "No session key from {destination} (attempt {attempt}/{SESSION_KEY_ATTEMPTS}), retrying in {secs}s",
+                    secs = backoff.as_secs()
+                );
+                tokio::time::sleep(backoff).await;
             }
         }
+
+        Err(SessionKeyError::NoResponse {
+            target: destination.to_string(),
+            attempts: SESSION_KEY_ATTEMPTS,
+        }
+        .into())
     }
 
     /// Get the current session key if available
     pub async fn get_session_key(&self) -> Option<Vec<u8>> {
-        self.admin_session_passkey.lock().await.clone()
+        self.get_session_key_for(None).await
+    }
+
+    /// Get the session key issued by the local node (`None`) or a remote one
+    pub async fn get_session_key_for(&self, node: Option<u32>) -> Option<Vec<u8>> {
+        let node = match node {
+            Some(node) => node,
+            None => self.local_node_num().await,
+        };
+        self.processor
+            .admin_session_keys
+            .lock()
+            .await
+            .get(&node)
+            .cloned()
     }
 
     /// Set the session key (used when receiving admin responses)
     pub async fn set_session_key(&self, key: Vec<u8>) {
-        let mut session_key = self.admin_session_passkey.lock().await;
-        *session_key = Some(key);
+        let node = self.local_node_num().await;
+        self.processor
+            .admin_session_keys
+            .lock()
+            .await
+            .insert(node, key);
         debug!("Session key updated");
     }
 
     /// Clear the session key (used on disconnect or authentication failure)
+    ///
+    /// A passkey kept in the session cache is dropped too.
     pub async fn clear_session_key(&self) {
-        let mut session_key = self.admin_session_passkey.lock().await;
-        *session_key = None;
-        debug!("Session key cleared");
-    }
-}
-
-async fn process_from_radio_packet(
-    from_radio: meshtastic::protobufs::FromRadio,
-    device_state: Arc<Mutex<DeviceState>>,
-    ack_waiters: Arc<Mutex<HashMap<u32, oneshot::Sender<bool>>>>,
-    route_waiters: Arc<Mutex<HashMap<u32, oneshot::Sender<Vec<crate::mesh::RouteHop>>>>>,
-    admin_session_passkey: Arc<Mutex<Option<Vec<u8>>>>,
-) -> Result<()> {
-    let payload_variant = match from_radio.payload_variant {
-        Some(variant) => variant,
-        None => return Ok(()), // Ignore empty packets
-    };
-
-    match payload_variant {
-        meshtastic::protobufs::from_radio::PayloadVariant::MyInfo(my_info) => {
-            let mut state = device_state.lock().await;
-            state.set_my_node_info(MyNodeInfo {
-                node_num: my_info.my_node_num,
-                node_id: format!("{num:08x}", num = my_info.my_node_num),
-                reboot_count: my_info.reboot_count,
-                min_app_version: my_info.min_app_version,
-                device_id: hex::encode(my_info.device_id),
-            });
-            debug!("Updated my node info");
-        }
-
-        meshtastic::protobufs::from_radio::PayloadVariant::NodeInfo(node_info) => {
-            let mut state = device_state.lock().await;
-            let user = node_info.user.clone().unwrap_or_default();
-            let last_heard = node_info.last_heard as u64;
-            let last_heard_iso =
-                chrono::DateTime::from_timestamp(last_heard as i64, 0).map(|dt| dt.to_rfc3339());
-
-            state.update_node(
-                node_info.num,
-                NodeInfo {
-                    id: format!("{num:08x}", num = node_info.num),
-                    num: node_info.num,
-                    user: User {
-                        id: user.id.clone(),
-                        long_name: user.long_name.clone(),
-                        short_name: user.short_name.clone(),
-                        hw_model: Some(format!("{model:?}", model = user.hw_model())),
-                    },
-                    last_heard: Some(last_heard),
-                    last_heard_iso,
-                    snr: Some(node_info.snr),
-                    rssi: Some(0), // NodeInfo doesn't have RSSI
-                },
-            );
-            debug!("Updated node info for {num}", num = node_info.num);
-        }
-
-        meshtastic::protobufs::from_radio::PayloadVariant::Channel(channel) => {
-            let mut state = device_state.lock().await;
-            state.update_channel(ChannelInfo {
-                index: channel.index as u32,
-                name: channel
-                    .settings
-                    .as_ref()
-                    .map(|s| s.name.clone())
-                    .unwrap_or_else(|| format!("Channel {index}", index = channel.index)),
-                role: format!("{role:?}", role = channel.role()),
-                has_psk: channel
-                    .settings
-                    .as_ref()
-                    .map(|s| !s.psk.is_empty())
-                    .unwrap_or_default(),
-                settings: channel.settings,
-            });
-            debug!("Updated channel {index}", index = channel.index);
+        self.processor.admin_session_keys.lock().await.clear();
+        if let Some(cache) = &self.session_cache
+            && let Some(id) = self.session_id().await
+            && let Err(e) = cache.forget(&id)
+        {
+            warn!("Failed to drop the cached session key: {e}");
         }
+        debug!("Session keys cleared");
+    }
 
-        meshtastic::protobufs::from_radio::PayloadVariant::Packet(mesh_packet) => {
-            process_mesh_packet(
-                mesh_packet,
-                device_state,
-                ack_waiters,
-                route_waiters,
-                admin_session_passkey,
-            )
-            .await?;
-        }
+    /// Identity of the connected device in the session cache
+    async fn session_id(&self) -> Option<SessionId> {
+        let state = self.processor.device_state.lock().await;
+        let info = state.my_node_info.as_ref()?;
+        Some(SessionId {
+            target: self.target.clone(),
+            node_num: info.node_num,
+            reboot_count: info.reboot_count,
+        })
+    }
 
-        meshtastic::protobufs::from_radio::PayloadVariant::Config(config) => {
-            debug!("Received Config packet during initial connection");
-            process_config_response(config, device_state).await?;
+    async fn cache_session_key(&self, key: &[u8]) {
+        let Some(cache) = &self.session_cache else {
+            return;
+        };
+        let Some(id) = self.session_id().await else {
+            return;
+        };
+        match cache.store(&id, key) {
+            Ok(()) => debug!("Saved session key to {path}", path = cache.path().display()),
+            Err(e) => warn!("Failed to cache the session key: {e}"),
         }
+    }
 
-        meshtastic::protobufs::from_radio::PayloadVariant::ConfigCompleteId(id) => {
-            info!("Config complete received with ID: {id}");
-        }
+    /// Number of the connected node, or 0 before it has reported in
+    async fn local_node_num(&self) -> u32 {
+        self.processor
+            .device_state
+            .lock()
+            .await
+            .my_node_info
+            .as_ref()
+            .map_or(0, |info| info.node_num)
+    }
+}
 
-        variant => {
-            // Other packet types not yet handled
-            debug!("Unhandled FromRadio packet variant: {variant:?}");
-        }
+/// Nudge a freshly opened serial device into resyncing its framing
+async fn wake_serial_device<S: AsyncWrite + Unpin>(stream: &mut S, wake_sequence: &[u8]) {
+    // Send wake sequence to force device resync (similar to Python implementation)
+    // This helps the device wake up and resync its serial state machine
+    use tokio::io::AsyncWriteExt;
+    if let Err(e) = stream.write_all(wake_sequence).await {
+        debug!("Failed to send wake sequence: {e}");
+    }
+    if let Err(e) = stream.flush().await {
+        debug!("Failed to flush wake sequence: {e}");
     }
 
-    Ok(())
+    // Add a brief delay for serial port stabilization
+    // This helps avoid initial sync errors with stale data
+    tokio::time::sleep(Duration::from_millis(100)).await;
 }
 
-async fn process_mesh_packet(
-    mesh_packet: meshtastic::protobufs::MeshPacket,
-    device_state: Arc<Mutex<DeviceState>>,
-    ack_waiters: Arc<Mutex<HashMap<u32, oneshot::Sender<bool>>>>,
-    route_waiters: Arc<Mutex<HashMap<u32, oneshot::Sender<Vec<crate::mesh::RouteHop>>>>>,
-    admin_session_passkey: Arc<Mutex<Option<Vec<u8>>>>,
-) -> Result<()> {
-    let payload_variant = match mesh_packet.payload_variant {
-        Some(variant) => variant,
-        None => return Ok(()),
-    };
+/// Wrap a device stream so a failed handshake can tell whether any bytes arrived
+fn count_bytes<S>(
+    handle: StreamHandle<S>,
+    counters: &ConnectionCounters,
+) -> StreamHandle<CountingStream<S>>
+where
+    S: AsyncRead + AsyncWrite + Send + Unpin,
+{
+    StreamHandle {
+        stream: CountingStream::new(handle.stream, counters),
+        join_handle: handle.join_handle,
+    }
+}
 
-    let packet_data = match &payload_variant {
-        meshtastic::protobufs::mesh_packet::PayloadVariant::Decoded(decoded) => decoded,
-        meshtastic::protobufs::mesh_packet::PayloadVariant::Encrypted(_) => {
-            // Can't process encrypted packets
-            return Ok(());
-        }
+/// Check a packet against the duplicate filter, counting dropped copies
+async fn is_duplicate_packet(
+    from_radio: &FromRadio,
+    duplicate_filter: &Mutex<DuplicateFilter>,
+    device_state: &Mutex<DeviceState>,
+) -> bool {
+    let Some(meshtastic::protobufs::from_radio::PayloadVariant::Packet(mesh_packet)) =
+        &from_radio.payload_variant
+    else {
+        return false;
     };
 
-    match packet_data.portnum() {
-        meshtastic::protobufs::PortNum::TextMessageApp => {
-            let text = String::from_utf8_lossy(&packet_data.payload).to_string();
-            let mut state = device_state.lock().await;
-
-            state.add_message(TextMessage {
-                from: format!("{from:08x}", from = mesh_packet.from),
-                from_node: mesh_packet.from,
-                to: format!("{to:08x}", to = mesh_packet.to),
-                to_node: mesh_packet.to,
-                channel: mesh_packet.channel,
-                text,
-                time: std::time::SystemTime::now()
-                    .duration_since(std::time::UNIX_EPOCH)
-                    .unwrap_or_default()
-                    .as_secs(),
-                snr: Some(mesh_packet.rx_snr),
-                rssi: Some(mesh_packet.rx_rssi),
-                acknowledged: false,
-            });
-            debug!(
-                "Received text message from {from:08x}",
-                from = mesh_packet.from
-            );
-        }
-
-        meshtastic::protobufs::PortNum::PositionApp => {
-            if let Ok(position_proto) =
-                meshtastic::protobufs::Position::decode(packet_data.payload.as_slice())
-            {
-                let mut state = device_state.lock().await;
-
-                if let (Some(lat), Some(lon)) =
-                    (position_proto.latitude_i, position_proto.longitude_i)
-                {
-                    state.update_position(
-                        mesh_packet.from,
-                        Position {
-                            node_id: format!("{from:08x}", from = mesh_packet.from),
-                            node_num: mesh_packet.from,
-                            latitude: lat as f64 / 1e7,
-                            longitude: lon as f64 / 1e7,
-                            altitude: position_proto.altitude,
-                            time: if position_proto.time > 0 {
-                                chrono::DateTime::from_timestamp(position_proto.time as i64, 0)
-                                    .map(|dt| dt.to_rfc3339())
-                            } else {
-                                None
-                            },
-                            last_updated: std::time::SystemTime::now()
-                                .duration_since(std::time::UNIX_EPOCH)
-                                .unwrap_or_default()
-                                .as_secs(),
-                        },
-                    );
-                    debug!("Updated position for {from:08x}", from = mesh_packet.from);
-                }
-            }
-        }
-
-        meshtastic::protobufs::PortNum::TelemetryApp => {
-            if let Ok(telemetry) =
-                meshtastic::protobufs::Telemetry::decode(packet_data.payload.as_slice())
-            {
-                let mut state = device_state.lock().await;
-
-                let mut telemetry_data = TelemetryData {
-                    node_num: mesh_packet.from,
-                    time: telemetry.time as u64,
-                    device_metrics: None,
-                    environment_metrics: None,
-                    air_quality_metrics: None,
-                };
-
-                // Process the telemetry variant
-                if let Some(variant) = telemetry.variant {
-                    match variant {
-                        meshtastic::protobufs::telemetry::Variant::DeviceMetrics(m) => {
-                            telemetry_data.device_metrics = Some(DeviceMetrics {
-                                battery_level: m.battery_level,
-                                voltage: m.voltage,
-                                channel_utilization: m.channel_utilization,
-                                air_util_tx: m.air_util_tx,
-                                uptime_seconds: m.uptime_seconds,
-                            });
-                        }
-                        meshtastic::protobufs::telemetry::Variant::EnvironmentMetrics(m) => {
-                            telemetry_data.environment_metrics = Some(EnvironmentMetrics {
-                                temperature: m.temperature,
-                                relative_humidity: m.relative_humidity,
-                                barometric_pressure: m.barometric_pressure,
-                                gas_resistance: m.gas_resistance,
-                                iaq: m.iaq,
-                                distance: m.distance,
-                                lux: m.lux,
-                                white_lux: m.white_lux,
-                                ir_lux: m.ir_lux,
-                                uv_lux: m.uv_lux,
-                                wind_direction: m.wind_direction,
-                                wind_speed: m.wind_speed,
-                                weight: m.weight,
-                            });
-                        }
-                        meshtastic::protobufs::telemetry::Variant::AirQualityMetrics(m) => {
-                            telemetry_data.air_quality_metrics = Some(AirQualityMetrics {
-                                pm10_standard: m.pm10_standard,
-                                pm25_standard: m.pm25_standard,
-                                pm100_standard: m.pm100_standard,
-                                pm10_environmental: m.pm10_environmental,
-                                pm25_environmental: m.pm25_environmental,
-                                pm100_environmental: m.pm100_environmental,
-                                particles_03um: m.particles_03um,
-                                particles_05um: m.particles_05um,
-                                particles_10um: m.particles_10um,
-                                particles_25um: m.particles_25um,
-                                particles_50um: m.particles_50um,
-                                particles_100um: m.particles_100um,
-                            });
-                        }
-                        variant => {
-                            // Other telemetry types not yet handled
-                            debug!("Unhandled telemetry variant: {variant:?}");
-                        }
-                    }
-                }
-
-                state.update_telemetry(mesh_packet.from, telemetry_data);
-                debug!("Updated telemetry for {from:08x}", from = mesh_packet.from);
-            }
-        }
-
-        meshtastic::protobufs::PortNum::AdminApp => {
-            debug!("Received AdminApp packet");
-            if let Ok(admin_msg) =
-                meshtastic::protobufs::AdminMessage::decode(packet_data.payload.as_slice())
-            {
-                debug!("Decoded admin message: {admin_msg:?}");
-
-                // Extract and store the session passkey if present
-                if !admin_msg.session_passkey.is_empty() {
-                    let mut session_key = admin_session_passkey.lock().await;
-                    *session_key = Some(admin_msg.session_passkey.clone());
-                    info!("Received and stored admin session passkey");
-                }
-
-                if let Some(
-                    meshtastic::protobufs::admin_message::PayloadVariant::GetConfigResponse(config),
-                ) = admin_msg.payload_variant
-                {
-                    debug!("Processing config response");
-                    process_config_response(config, device_state).await?;
-                }
-            } else {
-                debug!("Failed to decode admin message");
-            }
-        }
+    if mesh_packet.id == 0 {
+        return false;
+    }
 
-        meshtastic::protobufs::PortNum::RoutingApp => {
-            // Handle routing packets (including ACKs and route replies)
-            if let Ok(routing) =
-                meshtastic::protobufs::Routing::decode(packet_data.payload.as_slice())
-                && let Some(variant) = routing.variant
-            {
-                match variant {
-                    meshtastic::protobufs::routing::Variant::RouteReply(route) => {
-                        debug!(
-                            "Received route reply with {hops} hops",
-                            hops = route.route.len()
-                        );
-
-                        // Check if this is a response to a traceroute request
-                        if packet_data.request_id != 0 {
-                            let mut waiters = route_waiters.lock().await;
-                            if let Some(sender) = waiters.remove(&packet_data.request_id) {
-                                // Convert route to RouteHop structure
-                                let mut hops = Vec::new();
-                                for (idx, node_num) in route.route.iter().enumerate() {
-                                    // Look up node info from state
-                                    let state = device_state.lock().await;
-                                    let node_name = state
-                                        .nodes
-                                        .get(node_num)
-                                        .map(|n| n.user.long_name.clone())
-                                        .unwrap_or_else(|| {
-                                            format!("Unknown ({num:08x})", num = node_num)
-                                        });
-
-                                    hops.push(crate::mesh::RouteHop {
-                                        node_id: *node_num,
-                                        node_name,
-                                        hop_number: idx as u32,
-                                        snr: None,  // Route replies don't include SNR
-                                        rssi: None, // Route replies don't include RSSI
-                                    });
-                                }
+    let is_duplicate = duplicate_filter
+        .lock()
+        .await
+        .check_and_insert(mesh_packet.from, mesh_packet.id);
+    if is_duplicate {
+        device_state.lock().await.duplicate_packets += 1;
+        debug!(
+            "Dropping duplicate packet {id} from {from}",
+            id = mesh_packet.id,
+            from = NodeId(mesh_packet.from)
+        );
+    }
 
-                                if sender.send(hops).is_err() {
-                                    debug!(
-                                        "Route reply receiver dropped for request {request_id}",
-                                        request_id = packet_data.request_id
-                                    );
-                                } else {
-                                    debug!(
-                                        "Sent route reply for request {request_id}",
-                                        request_id = packet_data.request_id
-                                    );
-                                }
-                            }
-                        }
-                    }
-                    meshtastic::protobufs::routing::Variant::ErrorReason(reason) => {
-                        debug!("Routing error: {reason:?}");
-                        // If this is an error for a traceroute request, send empty result
-                        if packet_data.request_id != 0 {
-                            let mut waiters = route_waiters.lock().await;
-                            if let Some(sender) = waiters.remove(&packet_data.request_id) {
-                                if sender.send(Vec::new()).is_err() {
-                                    debug!(
-                                        "Route error receiver dropped for request {request_id}",
-                                        request_id = packet_data.request_id
-                                    );
-                                } else {
-                                    debug!(
-                                        "Route request {request_id} failed: {reason:?}",
-                                        request_id = packet_data.request_id
-                                    );
-                                }
-                            }
-                        }
-                    }
-                    variant => {
-                        debug!("Unhandled routing variant: {variant:?}");
-                    }
-                }
-            }
+    is_duplicate
+}
 
-            // Check if this is an ACK by looking at the request_id
-            if packet_data.request_id != 0 {
-                let mut waiters = ack_waiters.lock().await;
-                if let Some(sender) = waiters.remove(&packet_data.request_id) {
-                    if sender.send(true).is_err() {
-                        debug!(
-                            "ACK receiver dropped for packet {request_id}",
-                            request_id = packet_data.request_id
-                        );
-                    } else {
-                        debug!(
-                            "Received ACK for packet {request_id}",
-                            request_id = packet_data.request_id
-                        );
-                    }
-                }
-            }
-        }
+/// Recognise a text message sent from our own node echoing back
+///
+/// The device hands broadcasts and messages to ourselves back to the client.
+/// An echo carries our node number, or a zero sender with the id of a packet
+/// we sent; the latter is rewritten to our node number so it is attributed
+/// like the rest.
+async fn claim_own_text_message(
+    from_radio: &mut FromRadio,
+    sent_packets: &Mutex<DuplicateFilter>,
+    device_state: &Mutex<DeviceState>,
+) -> bool {
+    use meshtastic::protobufs::mesh_packet::PayloadVariant as MeshPayload;
+
+    let Some(meshtastic::protobufs::from_radio::PayloadVariant::Packet(mesh_packet)) =
+        &mut from_radio.payload_variant
+    else {
+        return false;
+    };
+    let is_text = matches!(
+        &mesh_packet.payload_variant,
+        Some(MeshPayload::Decoded(data))
+            if data.portnum() == meshtastic::protobufs::PortNum::TextMessageApp
+    );
+    if !is_text {
+        return false;
+    }
 
-        portnum => {
-            // Other port types not yet handled
-            debug!(
-                "Unhandled mesh packet with portnum {portnum:?} from {from:08x}",
-                from = mesh_packet.from
-            );
-        }
+    let Some(my_node_num) = device_state
+        .lock()
+        .await
+        .my_node_info
+        .as_ref()
+        .map(|info| info.node_num)
+    else {
+        return false;
+    };
+    if mesh_packet.from == my_node_num {
+        return true;
     }
+    if mesh_packet.from == 0
+        && mesh_packet.id != 0
+        && sent_packets.lock().await.contains(0, mesh_packet.id)
+    {
+        mesh_packet.from = my_node_num;
+        return true;
+    }
+    false
+}
 
-    // Also check for ACKs in any packet type if they have a request_id
-    if mesh_packet.id != 0 && mesh_packet.want_ack {
-        // This packet wants an ACK, but we're not handling that here
-    } else if mesh_packet.id != 0 {
-        // Check if this might be an implicit ACK
-        if let meshtastic::protobufs::mesh_packet::PayloadVariant::Decoded(ref data) =
-            payload_variant
-            && data.request_id != 0
+/// Recognise a device restart from a packet
+///
+/// The firmware announces a reboot, and a node info with a higher reboot
+/// count than the cached one means it restarted unannounced. Returns the
+/// new reboot count when known.
+async fn detect_reboot(
+    packet: &FromRadio,
+    device_state: &Mutex<DeviceState>,
+) -> Option<Option<u32>> {
+    match &packet.payload_variant {
+        Some(meshtastic::protobufs::from_radio::PayloadVariant::Rebooted(true)) => Some(None),
+        Some(meshtastic::protobufs::from_radio::PayloadVariant::MyInfo(info))
+            if device_state.lock().await.is_reboot(info.reboot_count) =>
         {
-            let mut waiters = ack_waiters.lock().await;
-            if let Some(sender) = waiters.remove(&data.request_id) {
-                if sender.send(true).is_err() {
-                    debug!(
-                        "Implicit ACK receiver dropped for packet {request_id}",
-                        request_id = data.request_id
-                    );
-                } else {
-                    debug!(
-                        "Received implicit ACK for packet {request_id}",
-                        request_id = data.request_id
-                    );
-                }
-            }
+            Some(Some(info.reboot_count))
         }
+        _ => None,
     }
-
-    Ok(())
 }
 
-async fn process_config_response(
-    config: meshtastic::protobufs::Config,
-    device_state: Arc<Mutex<DeviceState>>,
-) -> Result<()> {
-    let mut state = device_state.lock().await;
-
-    if let Some(payload) = config.payload_variant {
-        match payload {
-            meshtastic::protobufs::config::PayloadVariant::Device(device_config) => {
-                state.device_config = Some(DeviceConfig {
-                    role: format!("{role:?}", role = device_config.role()),
-                    button_gpio: device_config.button_gpio,
-                    buzzer_gpio: device_config.buzzer_gpio,
-                    rebroadcast_mode: format!("{mode:?}", mode = device_config.rebroadcast_mode()),
-                    node_info_broadcast_secs: device_config.node_info_broadcast_secs,
-                    tzdef: if device_config.tzdef.is_empty() {
-                        None
-                    } else {
-                        Some(device_config.tzdef)
-                    },
-                    disable_triple_click: device_config.disable_triple_click,
-                });
-                debug!("Updated device config");
-            }
-            meshtastic::protobufs::config::PayloadVariant::Position(position_config) => {
-                state.position_config = Some(PositionConfig {
-                    position_broadcast_secs: position_config.position_broadcast_secs,
-                    position_broadcast_smart_enabled: position_config
-                        .position_broadcast_smart_enabled,
-                    fixed_position: position_config.fixed_position,
-                    gps_enabled: position_config.gps_mode()
-                        != meshtastic::protobufs::config::position_config::GpsMode::Disabled,
-                    gps_mode: format!("{mode:?}", mode = position_config.gps_mode()),
-                });
-                debug!("Updated position config");
-            }
-            meshtastic::protobufs::config::PayloadVariant::Power(power_config) => {
-                state.power_config = Some(PowerConfig {
-                    is_power_saving: power_config.is_power_saving,
-                    on_battery_shutdown_after_secs: power_config.on_battery_shutdown_after_secs,
-                    adc_multiplier_override: power_config.adc_multiplier_override,
-                    wait_bluetooth_secs: power_config.wait_bluetooth_secs,
-                    sds_secs: power_config.sds_secs,
-                    ls_secs: power_config.ls_secs,
-                    min_wake_secs: power_config.min_wake_secs,
-                });
-                debug!("Updated power config");
-            }
-            meshtastic::protobufs::config::PayloadVariant::Network(network_config) => {
-                state.network_config = Some(NetworkConfig {
-                    wifi_enabled: network_config.wifi_enabled,
-                    wifi_ssid: network_config.wifi_ssid,
-                    wifi_psk: network_config.wifi_psk,
-                    ntp_server: network_config.ntp_server,
-                    eth_enabled: network_config.eth_enabled,
-                    ipv4_config: network_config
-                        .ipv4_config
-                        .as_ref()
-                        .map(|config| format!("{config:?}")),
-                });
-                debug!("Updated network config");
-            }
-            meshtastic::protobufs::config::PayloadVariant::Display(display_config) => {
-                state.display_config = Some(DisplayConfig {
-                    screen_on_secs: display_config.screen_on_secs,
-                    gps_format: format!("{format:?}", format = display_config.gps_format()),
-                    auto_screen_carousel_secs: display_config.auto_screen_carousel_secs,
-                    compass_north_top: display_config.compass_north_top,
-                    flip_screen: display_config.flip_screen,
-                    units: format!("{units:?}", units = display_config.units()),
-                    displaymode: format!("{mode:?}", mode = display_config.displaymode()),
-                    heading_bold: display_config.heading_bold,
-                    wake_on_tap_or_motion: display_config.wake_on_tap_or_motion,
-                });
-                debug!("Updated display config");
-            }
-            meshtastic::protobufs::config::PayloadVariant::Lora(lora_config) => {
-                // Convert region enum to human-readable string
-                let region_str = match lora_config.region() {
-                    meshtastic::protobufs::config::lo_ra_config::RegionCode::Unset => "Unset",
-                    meshtastic::protobufs::config::lo_ra_config::RegionCode::Us => "US",
-                    meshtastic::protobufs::config::lo_ra_config::RegionCode::Eu433 => "EU433",
-                    meshtastic::protobufs::config::lo_ra_config::RegionCode::Eu868 => "EU868",
-                    meshtastic::protobufs::config::lo_ra_config::RegionCode::Cn => "CN",
-                    meshtastic::protobufs::config::lo_ra_config::RegionCode::Jp => "JP",
-                    meshtastic::protobufs::config::lo_ra_config::RegionCode::Anz => "ANZ",
-                    meshtastic::protobufs::config::lo_ra_config::RegionCode::Kr => "KR",
-                    meshtastic::protobufs::config::lo_ra_config::RegionCode::Tw => "TW",
-                    meshtastic::protobufs::config::lo_ra_config::RegionCode::Ru => "RU",
-                    meshtastic::protobufs::config::lo_ra_config::RegionCode::In => "IN",
-                    meshtastic::protobufs::config::lo_ra_config::RegionCode::Nz865 => "NZ865",
-                    meshtastic::protobufs::config::lo_ra_config::RegionCode::Th => "TH",
-                    meshtastic::protobufs::config::lo_ra_config::RegionCode::Lora24 => "LORA24",
-                    meshtastic::protobufs::config::lo_ra_config::RegionCode::Ua433 => "UA433",
-                    meshtastic::protobufs::config::lo_ra_config::RegionCode::Ua868 => "UA868",
-                    meshtastic::protobufs::config::lo_ra_config::RegionCode::My433 => "MY433",
-                    meshtastic::protobufs::config::lo_ra_config::RegionCode::My919 => "MY919",
-                    meshtastic::protobufs::config::lo_ra_config::RegionCode::Sg923 => "SG923",
-                    meshtastic::protobufs::config::lo_ra_config::RegionCode::Ph433 => "PH433",
-                    meshtastic::protobufs::config::lo_ra_config::RegionCode::Ph868 => "PH868",
-                    meshtastic::protobufs::config::lo_ra_config::RegionCode::Ph915 => "PH915",
-                };
-
-                state.lora_config = Some(LoraConfig {
-                    use_preset: lora_config.use_preset,
-                    modem_preset: format!("{preset:?}", preset = lora_config.modem_preset()),
-                    bandwidth: lora_config.bandwidth,
-                    spread_factor: lora_config.spread_factor,
-                    coding_rate: lora_config.coding_rate,
-                    frequency_offset: lora_config.frequency_offset,
-                    region: region_str.to_string(),
-                    hop_limit: lora_config.hop_limit,
-                    tx_enabled: lora_config.tx_enabled,
-                    tx_power: lora_config.tx_power,
-                    channel_num: lora_config.channel_num,
-                    ignore_mqtt: lora_config.ignore_mqtt,
-                });
-                debug!("Updated LoRa config");
-            }
-            meshtastic::protobufs::config::PayloadVariant::Bluetooth(bluetooth_config) => {
-                state.bluetooth_config = Some(BluetoothConfig {
-                    enabled: bluetooth_config.enabled,
-                    mode: format!("{mode:?}", mode = bluetooth_config.mode()),
-                    fixed_pin: bluetooth_config.fixed_pin,
-                    device_logging_enabled: false, // Not available in current protobuf
-                });
-                debug!("Updated Bluetooth config");
-            }
-            meshtastic::protobufs::config::PayloadVariant::Security(_security_config) => {
-                // Security config not yet handled
-                debug!("Security config received but not yet handled");
+/// Wait for an ACK of any transmission of a message
+///
+/// Returns the id of the acknowledged transmission, or `None` after the
+/// timeout or a NAK of `packet_id`. NAKs of earlier transmissions are ignored
+/// since a later one may still get through.
+async fn wait_for_ack(
+    rx: &mut mpsc::UnboundedReceiver<(u32, bool)>,
+    packet_id: u32,
+    timeout: Duration,
+) -> Option<u32> {
+    let deadline = tokio::time::Instant::now() + timeout;
+    loop {
+        match tokio::time::timeout_at(deadline, rx.recv()).await {
+            Ok(Some((acked_id, true))) => return Some(acked_id),
+            Ok(Some((naked_id, false))) if naked_id == packet_id => {
+                debug!("NAK for packet {packet_id}");
+                return None;
             }
-            meshtastic::protobufs::config::PayloadVariant::Sessionkey(_sessionkey_config) => {
-                // Sessionkey config not yet handled
-                debug!("Sessionkey config received but not yet handled");
+            Ok(Some((naked_id, false))) => {
+                debug!("Ignoring NAK for earlier transmission {naked_id}");
             }
-            meshtastic::protobufs::config::PayloadVariant::DeviceUi(_device_ui_config) => {
-                // DeviceUI config not yet handled
-                debug!("DeviceUI config received but not yet handled");
+            Ok(None) => return None,
+            Err(_) => {
+                debug!("ACK timeout for packet {packet_id}");
+                return None;
             }
         }
     }
-
-    Ok(())
 }
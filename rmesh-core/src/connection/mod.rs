@@ -1,3 +1,27 @@
+pub mod dedup;
+pub mod discovery;
+pub mod framing;
+pub mod handshake;
+pub mod health;
+pub mod inject;
+pub mod keep_awake;
 pub mod manager;
+pub mod packet_id;
+pub mod processor;
+pub mod send_queue;
+pub mod simulation;
+pub mod stats;
+pub mod trace;
 
-pub use manager::ConnectionManager;
+pub use dedup::DuplicateFilter;
+pub use discovery::DeviceCandidate;
+pub use handshake::{ConnectionError, HandshakeOptions};
+pub use health::{HealthProbe, HealthReport, QueueDepths};
+pub use inject::Injection;
+pub use keep_awake::SerialWakeOptions;
+pub use manager::{ConnectionManager, ListenOnlyError, PendingResponse, RequestResponse};
+pub use packet_id::PacketIdSource;
+pub use processor::PacketProcessor;
+pub use send_queue::{RadioSender, SEND_QUEUE_CAPACITY, SendPriority};
+pub use simulation::{SimulationOptions, Topology};
+pub use stats::{ConnectionCounters, ConnectionStats, LinkQuality, QualityRating};
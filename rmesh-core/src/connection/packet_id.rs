@@ -0,0 +1,47 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// Generates packet ids that are unique within a session
+///
+/// Like the firmware, ids start from a random point and increment for each
+/// packet, wrapping around at `u32::MAX`. Zero is skipped because it asks the
+/// firmware to assign the id itself, which would defeat ACK matching.
+#[derive(Debug)]
+pub struct PacketIdSource {
+    next: AtomicU32,
+}
+
+impl PacketIdSource {
+    /// Start a new sequence from a random seed
+    pub fn new() -> Self {
+        Self::with_seed(rand::random())
+    }
+
+    /// Start a sequence at `seed`
+    pub fn with_seed(seed: u32) -> Self {
+        Self {
+            next: AtomicU32::new(seed),
+        }
+    }
+
+    /// Restart the sequence, e.g. for a new connection session
+    pub fn reseed(&self, seed: u32) {
+        self.next.store(seed, Ordering::Relaxed);
+    }
+
+    /// Take the next id in the sequence
+    pub fn next_id(&self) -> u32 {
+        loop {
+            // fetch_add wraps on overflow, giving the rollover for free
+            let id = self.next.fetch_add(1, Ordering::Relaxed);
+            if id != 0 {
+                return id;
+            }
+        }
+    }
+}
+
+impl Default for PacketIdSource {
+    fn default() -> Self {
+        Self::new()
+    }
+}
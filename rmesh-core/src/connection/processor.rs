@@ -0,0 +1,928 @@
+use anyhow::Result;
+use meshtastic::Message;
+use meshtastic::protobufs::{FromRadio, MeshPacket};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::{Mutex, broadcast, mpsc, oneshot};
+use tracing::{debug, info};
+
+use crate::connection::manager::RequestResponse;
+use crate::events::{EventBus, EventFilter, MeshEvent, RoutingReport, publish, publish_changes};
+use crate::node_id::NodeId;
+use crate::state::{
+    AirQualityMetrics, BluetoothConfig, ChannelInfo, DeviceConfig, DeviceMetadata, DeviceMetrics,
+    DeviceState, DisplayConfig, EnvironmentMetrics, LoraConfig, MqttConfig, MyNodeInfo,
+    NeighborInfoConfig, NetworkConfig, NodeInfo, Position, PositionConfig, PowerConfig,
+    TelemetryConfig, TelemetryData, TextMessage, User,
+};
+
+/// Admin session passkeys by the node that issued them
+pub(crate) type SessionKeys = Arc<Mutex<HashMap<u32, Vec<u8>>>>;
+
+/// Senders notified with `(packet_id, delivered)` when a packet is ACKed or NAKed
+pub(crate) type AckWaiters = Arc<Mutex<HashMap<u32, mpsc::UnboundedSender<(u32, bool)>>>>;
+
+/// Senders waiting for the route of a traceroute request
+pub(crate) type RouteWaiters =
+    Arc<Mutex<HashMap<u32, oneshot::Sender<Vec<crate::mesh::RouteHop>>>>>;
+
+/// Senders waiting for the reply to a want_response request
+pub(crate) type ResponseWaiters =
+    Arc<std::sync::Mutex<HashMap<u32, oneshot::Sender<RequestResponse>>>>;
+
+/// Applies the frames the radio sends to the device state
+///
+/// Shares its state and waiters with the [`ConnectionManager`] that feeds it,
+/// and can be fed frames without a device, e.g. recorded ones.
+///
+/// [`ConnectionManager`]: crate::connection::ConnectionManager
+#[derive(Clone)]
+pub struct PacketProcessor {
+    pub(crate) device_state: Arc<Mutex<DeviceState>>,
+    pub(crate) ack_waiters: AckWaiters,
+    pub(crate) route_waiters: RouteWaiters,
+    pub(crate) response_waiters: ResponseWaiters,
+    pub(crate) admin_session_keys: SessionKeys,
+    pub(crate) events: EventBus,
+}
+
+impl PacketProcessor {
+    /// Processor updating `device_state`, with no waiters registered
+    pub fn new(device_state: Arc<Mutex<DeviceState>>) -> Self {
+        Self {
+            device_state,
+            ack_waiters: Arc::new(Mutex::new(HashMap::new())),
+            route_waiters: Arc::new(Mutex::new(HashMap::new())),
+            response_waiters: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            admin_session_keys: Arc::new(Mutex::new(HashMap::new())),
+            events: EventBus::default(),
+        }
+    }
+
+    /// The state frames are applied to
+    pub fn device_state(&self) -> Arc<Mutex<DeviceState>> {
+        self.device_state.clone()
+    }
+
+    /// Receive the events published while processing
+    pub fn subscribe_events(&self) -> broadcast::Receiver<MeshEvent> {
+        self.events.subscribe()
+    }
+
+    /// Receive only the events passing `filter`
+    pub fn subscribe_filtered(&self, filter: EventFilter) -> broadcast::Receiver<MeshEvent> {
+        self.events.subscribe_filtered(filter)
+    }
+
+    /// Admin session passkey issued by `node`, as seen in its admin messages
+    pub async fn session_key(&self, node: u32) -> Option<Vec<u8>> {
+        self.admin_session_keys.lock().await.get(&node).cloned()
+    }
+
+    /// Apply one frame from the radio to the device state
+    pub async fn process_from_radio(&self, from_radio: FromRadio) -> Result<()> {
+        let payload_variant = match from_radio.payload_variant {
+            Some(variant) => variant,
+            None => return Ok(()), // Ignore empty packets
+        };
+
+        match payload_variant {
+            meshtastic::protobufs::from_radio::PayloadVariant::MyInfo(my_info) => {
+                let mut state = self.device_state.lock().await;
+                state.set_my_node_info(MyNodeInfo {
+                    node_num: my_info.my_node_num,
+                    node_id: NodeId(my_info.my_node_num).to_string(),
+                    reboot_count: my_info.reboot_count,
+                    min_app_version: my_info.min_app_version,
+                    device_id: hex::encode(&my_info.device_id),
+                    pio_env: (!my_info.pio_env.is_empty()).then(|| my_info.pio_env.clone()),
+                });
+                debug!("Updated my node info");
+            }
+
+            meshtastic::protobufs::from_radio::PayloadVariant::NodeInfo(node_info) => {
+                let mut state = self.device_state.lock().await;
+                let last_heard = node_info.last_heard as u64;
+                let last_heard_iso = crate::time::to_rfc3339(last_heard);
+
+                let node = NodeInfo {
+                    id: NodeId(node_info.num).to_string(),
+                    num: node_info.num,
+                    user: user_info(&node_info.user.clone().unwrap_or_default()),
+                    last_heard: Some(last_heard),
+                    last_heard_iso,
+                    snr: Some(node_info.snr),
+                    rssi: Some(0), // NodeInfo doesn't have RSSI
+                    device_metrics: node_info.device_metrics.as_ref().map(device_metrics),
+                };
+                let changes = state.update_node(node_info.num, node.clone());
+                publish(&self.events, MeshEvent::NodeUpdated(node));
+                publish_changes(&self.events, changes);
+                debug!("Updated node info for {num}", num = node_info.num);
+            }
+
+            meshtastic::protobufs::from_radio::PayloadVariant::Channel(channel) => {
+                let index = channel.index;
+                let mut state = self.device_state.lock().await;
+                let change = state.update_channel(channel_info(channel));
+                publish_changes(&self.events, change);
+                debug!("Updated channel {index}");
+            }
+
+            meshtastic::protobufs::from_radio::PayloadVariant::Packet(mesh_packet) => {
+                self.process_mesh_packet(mesh_packet).await?;
+            }
+
+            meshtastic::protobufs::from_radio::PayloadVariant::Config(config) => {
+                debug!("Received Config packet during initial connection");
+                process_config_response(config, &self.device_state, &self.events).await?;
+            }
+
+            meshtastic::protobufs::from_radio::PayloadVariant::ModuleConfig(module_config) => {
+                debug!("Received ModuleConfig packet during initial connection");
+                process_module_config_response(module_config, &self.device_state, &self.events)
+                    .await;
+            }
+
+            meshtastic::protobufs::from_radio::PayloadVariant::Metadata(metadata) => {
+                let mut state = self.device_state.lock().await;
+                state.metadata = Some(device_metadata(&metadata));
+                debug!(
+                    "Updated device metadata (firmware {version})",
+                    version = metadata.firmware_version
+                );
+            }
+
+            meshtastic::protobufs::from_radio::PayloadVariant::ConfigCompleteId(id) => {
+                info!("Config complete received with ID: {id}");
+                self.device_state.lock().await.mark_synced();
+            }
+
+            variant => {
+                // Other packet types not yet handled
+                debug!("Unhandled FromRadio packet variant: {variant:?}");
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn process_mesh_packet(&self, mesh_packet: MeshPacket) -> Result<()> {
+        let payload_variant = match mesh_packet.payload_variant {
+            Some(variant) => variant,
+            None => return Ok(()),
+        };
+
+        let packet_data = match &payload_variant {
+            meshtastic::protobufs::mesh_packet::PayloadVariant::Decoded(decoded) => decoded,
+            meshtastic::protobufs::mesh_packet::PayloadVariant::Encrypted(_) => {
+                // Can't process encrypted packets
+                return Ok(());
+            }
+        };
+
+        match packet_data.portnum() {
+            meshtastic::protobufs::PortNum::TextMessageApp => {
+                let text = crate::message::decode_text_payload(&packet_data.payload);
+                let mut state = self.device_state.lock().await;
+                let own = state
+                    .my_node_info
+                    .as_ref()
+                    .is_some_and(|info| info.node_num == mesh_packet.from);
+
+                let message = TextMessage {
+                    from: NodeId(mesh_packet.from).to_string(),
+                    from_node: mesh_packet.from,
+                    to: NodeId(mesh_packet.to).to_string(),
+                    to_node: mesh_packet.to,
+                    channel: mesh_packet.channel,
+                    text,
+                    time: crate::time::unix_now(),
+                    snr: Some(mesh_packet.rx_snr),
+                    rssi: Some(mesh_packet.rx_rssi),
+                    acknowledged: false,
+                    own,
+                };
+                state.add_message(message.clone());
+                publish(&self.events, MeshEvent::TextMessage(message));
+                debug!(
+                    "Received text message from {from}",
+                    from = NodeId(mesh_packet.from)
+                );
+            }
+
+            meshtastic::protobufs::PortNum::PositionApp => {
+                let position =
+                    meshtastic::protobufs::Position::decode(packet_data.payload.as_slice())
+                        .ok()
+                        .and_then(|proto| Position::from_protobuf(mesh_packet.from, &proto));
+                match position {
+                    Some(position) => {
+                        let mut state = self.device_state.lock().await;
+                        state.update_position(mesh_packet.from, position.clone());
+                        resolve_response(
+                            &self.response_waiters,
+                            packet_data.request_id,
+                            RequestResponse::Position(position.clone()),
+                        );
+                        publish(&self.events, MeshEvent::PositionUpdated(position));
+                        debug!(
+                            "Updated position for {from}",
+                            from = NodeId(mesh_packet.from)
+                        );
+                    }
+                    // Nodes without a fix answer requests with an empty
+                    // position; the requester should not wait for a better one
+                    None => {
+                        resolve_response(
+                            &self.response_waiters,
+                            packet_data.request_id,
+                            RequestResponse::NoPosition,
+                        );
+                        debug!(
+                            "No usable position from {from}",
+                            from = NodeId(mesh_packet.from)
+                        );
+                    }
+                }
+            }
+
+            meshtastic::protobufs::PortNum::TelemetryApp => {
+                if let Ok(telemetry) =
+                    meshtastic::protobufs::Telemetry::decode(packet_data.payload.as_slice())
+                {
+                    let mut state = self.device_state.lock().await;
+
+                    let mut telemetry_data = TelemetryData {
+                        node_num: mesh_packet.from,
+                        time: telemetry.time as u64,
+                        device_metrics: None,
+                        environment_metrics: None,
+                        air_quality_metrics: None,
+                    };
+
+                    // Process the telemetry variant
+                    if let Some(variant) = telemetry.variant {
+                        match variant {
+                            meshtastic::protobufs::telemetry::Variant::DeviceMetrics(m) => {
+                                telemetry_data.device_metrics = Some(device_metrics(&m));
+                            }
+                            meshtastic::protobufs::telemetry::Variant::EnvironmentMetrics(m) => {
+                                telemetry_data.environment_metrics = Some(EnvironmentMetrics {
+                                    temperature: m.temperature,
+                                    relative_humidity: m.relative_humidity,
+                                    barometric_pressure: m.barometric_pressure,
+                                    gas_resistance: m.gas_resistance,
+                                    iaq: m.iaq,
+                                    distance: m.distance,
+                                    lux: m.lux,
+                                    white_lux: m.white_lux,
+                                    ir_lux: m.ir_lux,
+                                    uv_lux: m.uv_lux,
+                                    wind_direction: m.wind_direction,
+                                    wind_speed: m.wind_speed,
+                                    weight: m.weight,
+                                });
+                            }
+                            meshtastic::protobufs::telemetry::Variant::AirQualityMetrics(m) => {
+                                telemetry_data.air_quality_metrics = Some(AirQualityMetrics {
+                                    pm10_standard: m.pm10_standard,
+                                    pm25_standard: m.pm25_standard,
+                                    pm100_standard: m.pm100_standard,
+                                    pm10_environmental: m.pm10_environmental,
+                                    pm25_environmental: m.pm25_environmental,
+                                    pm100_environmental: m.pm100_environmental,
+                                    particles_03um: m.particles_03um,
+                                    particles_05um: m.particles_05um,
+                                    particles_10um: m.particles_10um,
+                                    particles_25um: m.particles_25um,
+                                    particles_50um: m.particles_50um,
+                                    particles_100um: m.particles_100um,
+                                });
+                            }
+                            variant => {
+                                // Other telemetry types not yet handled
+                                debug!("Unhandled telemetry variant: {variant:?}");
+                            }
+                        }
+                    }
+
+                    state.update_telemetry(mesh_packet.from, telemetry_data.clone());
+                    resolve_response(
+                        &self.response_waiters,
+                        packet_data.request_id,
+                        RequestResponse::Telemetry(telemetry_data.clone()),
+                    );
+                    publish(&self.events, MeshEvent::TelemetryUpdated(telemetry_data));
+                    debug!(
+                        "Updated telemetry for {from}",
+                        from = NodeId(mesh_packet.from)
+                    );
+                }
+            }
+
+            meshtastic::protobufs::PortNum::AdminApp => {
+                debug!("Received AdminApp packet");
+                if let Ok(admin_msg) =
+                    meshtastic::protobufs::AdminMessage::decode(packet_data.payload.as_slice())
+                {
+                    debug!("Decoded admin message: {admin_msg:?}");
+
+                    // Extract and store the session passkey if present
+                    if !admin_msg.session_passkey.is_empty() {
+                        let mut session_keys = self.admin_session_keys.lock().await;
+                        session_keys.insert(mesh_packet.from, admin_msg.session_passkey.clone());
+                        info!(
+                            "Received and stored admin session passkey from {from}",
+                            from = NodeId(mesh_packet.from)
+                        );
+                    }
+
+                    if let Some(payload) = admin_msg.payload_variant {
+                        self.process_admin_response(
+                            payload,
+                            mesh_packet.from,
+                            packet_data.request_id,
+                        )
+                        .await?;
+                    }
+                } else {
+                    debug!("Failed to decode admin message");
+                }
+            }
+
+            meshtastic::protobufs::PortNum::TracerouteApp => {
+                match meshtastic::protobufs::RouteDiscovery::decode(packet_data.payload.as_slice())
+                {
+                    Ok(discovery) => {
+                        let mut state = self.device_state.lock().await;
+                        if let Some(local_node) =
+                            state.my_node_info.as_ref().map(|info| info.node_num)
+                            && let Some((destination, path)) = crate::mesh::learned_route(
+                                local_node,
+                                mesh_packet.from,
+                                mesh_packet.to,
+                                packet_data.request_id,
+                                &discovery.route,
+                            )
+                        {
+                            debug!(
+                                "Learned route to {destination} via {relays} relay(s)",
+                                relays = path.len(),
+                                destination = NodeId(destination)
+                            );
+                            state.record_route(destination, &path, crate::time::unix_now());
+                        }
+
+                        // Replies to our own traceroute also resolve its waiter
+                        if packet_data.request_id != 0
+                            && let Some(sender) = self
+                                .route_waiters
+                                .lock()
+                                .await
+                                .remove(&packet_data.request_id)
+                            && sender.send(route_hops(&state, &discovery.route)).is_err()
+                        {
+                            debug!(
+                                "Route reply receiver dropped for request {request_id}",
+                                request_id = packet_data.request_id
+                            );
+                        }
+                    }
+                    Err(e) => debug!("Failed to decode traceroute payload: {e}"),
+                }
+            }
+
+            meshtastic::protobufs::PortNum::RoutingApp => {
+                // Handle routing packets (including ACKs and route replies)
+                let mut delivered = true;
+                if let Ok(routing) =
+                    meshtastic::protobufs::Routing::decode(packet_data.payload.as_slice())
+                    && let Some(variant) = routing.variant
+                {
+                    match variant {
+                        meshtastic::protobufs::routing::Variant::RouteReply(route) => {
+                            debug!(
+                                "Received route reply with {hops} hops",
+                                hops = route.route.len()
+                            );
+
+                            // Check if this is a response to a traceroute request
+                            if packet_data.request_id != 0 {
+                                let mut waiters = self.route_waiters.lock().await;
+                                if let Some(sender) = waiters.remove(&packet_data.request_id) {
+                                    let hops =
+                                        route_hops(&*self.device_state.lock().await, &route.route);
+                                    if sender.send(hops).is_err() {
+                                        debug!(
+                                            "Route reply receiver dropped for request {request_id}",
+                                            request_id = packet_data.request_id
+                                        );
+                                    } else {
+                                        debug!(
+                                            "Sent route reply for request {request_id}",
+                                            request_id = packet_data.request_id
+                                        );
+                                    }
+                                }
+                            }
+                        }
+                        meshtastic::protobufs::routing::Variant::ErrorReason(code) => {
+                            let reason = meshtastic::protobufs::routing::Error::try_from(code);
+                            delivered =
+                                matches!(reason, Ok(meshtastic::protobufs::routing::Error::None));
+                            let reason = match reason {
+                                Ok(reason) => format!("{reason:?}"),
+                                Err(_) => format!("Unknown({code})"),
+                            };
+                            debug!("Routing status: {reason}");
+
+                            // If this is an error for a traceroute request, send empty result
+                            if !delivered && packet_data.request_id != 0 {
+                                let mut waiters = self.route_waiters.lock().await;
+                                if let Some(sender) = waiters.remove(&packet_data.request_id) {
+                                    if sender.send(Vec::new()).is_err() {
+                                        debug!(
+                                            "Route error receiver dropped for request {request_id}",
+                                            request_id = packet_data.request_id
+                                        );
+                                    } else {
+                                        debug!(
+                                            "Route request {request_id} failed: {reason}",
+                                            request_id = packet_data.request_id
+                                        );
+                                    }
+                                }
+                            }
+
+                            let report = RoutingReport {
+                                packet_id: packet_data.request_id,
+                                from: NodeId(mesh_packet.from).to_string(),
+                                from_node: mesh_packet.from,
+                                reason,
+                            };
+                            match (delivered, packet_data.request_id) {
+                                (true, 0) => {}
+                                (true, _) => publish(&self.events, MeshEvent::Ack(report)),
+                                (false, 0) => {
+                                    publish(&self.events, MeshEvent::RoutingError(report))
+                                }
+                                (false, _) => publish(&self.events, MeshEvent::Nak(report)),
+                            }
+                        }
+                        variant => {
+                            debug!("Unhandled routing variant: {variant:?}");
+                        }
+                    }
+                }
+
+                // Resolve the ACK waiter; a routing error for the packet is a NAK
+                if packet_data.request_id != 0 {
+                    let mut waiters = self.ack_waiters.lock().await;
+                    if let Some(sender) = waiters.remove(&packet_data.request_id) {
+                        if sender.send((packet_data.request_id, delivered)).is_err() {
+                            debug!(
+                                "ACK receiver dropped for packet {request_id}",
+                                request_id = packet_data.request_id
+                            );
+                        } else {
+                            debug!(
+                                "Received {status} for packet {request_id}",
+                                status = if delivered { "ACK" } else { "NAK" },
+                                request_id = packet_data.request_id
+                            );
+                        }
+                    }
+                }
+            }
+
+            portnum => {
+                // Other port types not yet handled
+                debug!(
+                    "Unhandled mesh packet with portnum {portnum:?} from {from}",
+                    from = NodeId(mesh_packet.from)
+                );
+            }
+        }
+
+        // Also check for ACKs in any packet type if they have a request_id
+        if mesh_packet.id != 0 && mesh_packet.want_ack {
+            // This packet wants an ACK, but we're not handling that here
+        } else if mesh_packet.id != 0 {
+            // Check if this might be an implicit ACK
+            if let meshtastic::protobufs::mesh_packet::PayloadVariant::Decoded(ref data) =
+                payload_variant
+                && data.request_id != 0
+            {
+                let mut waiters = self.ack_waiters.lock().await;
+                if let Some(sender) = waiters.remove(&data.request_id) {
+                    if sender.send((data.request_id, true)).is_err() {
+                        debug!(
+                            "Implicit ACK receiver dropped for packet {request_id}",
+                            request_id = data.request_id
+                        );
+                    } else {
+                        debug!(
+                            "Received implicit ACK for packet {request_id}",
+                            request_id = data.request_id
+                        );
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Store the settings carried by an admin response in the device state
+    ///
+    /// Requests and responses of other kinds are ignored.
+    async fn process_admin_response(
+        &self,
+        payload: meshtastic::protobufs::admin_message::PayloadVariant,
+        from: u32,
+        request_id: u32,
+    ) -> Result<()> {
+        use meshtastic::protobufs::admin_message::PayloadVariant;
+
+        match payload {
+            PayloadVariant::GetConfigResponse(config) => {
+                debug!("Processing config response");
+                process_config_response(config, &self.device_state, &self.events).await?;
+            }
+            PayloadVariant::GetModuleConfigResponse(module_config) => {
+                debug!("Processing module config response");
+                process_module_config_response(module_config, &self.device_state, &self.events)
+                    .await;
+            }
+            PayloadVariant::GetChannelResponse(channel) => {
+                debug!("Processing channel {index} response", index = channel.index);
+                let channel = channel_info(channel);
+                let change = self
+                    .device_state
+                    .lock()
+                    .await
+                    .update_channel(channel.clone());
+                publish_changes(&self.events, change);
+                resolve_response(
+                    &self.response_waiters,
+                    request_id,
+                    RequestResponse::Channel(channel),
+                );
+            }
+            PayloadVariant::GetOwnerResponse(user) => {
+                debug!("Processing owner response from {from}", from = NodeId(from));
+                let changes = self
+                    .device_state
+                    .lock()
+                    .await
+                    .update_owner(from, user_info(&user));
+                publish_changes(&self.events, changes);
+                resolve_response(
+                    &self.response_waiters,
+                    request_id,
+                    RequestResponse::Owner(user),
+                );
+            }
+            PayloadVariant::GetDeviceMetadataResponse(metadata) => {
+                let mut state = self.device_state.lock().await;
+                // Metadata of a remote node must not replace the local node's,
+                // which the firmware version checks rely on
+                let local = state
+                    .my_node_info
+                    .as_ref()
+                    .is_none_or(|info| info.node_num == from);
+                if local {
+                    debug!("Processing device metadata response");
+                    state.metadata = Some(device_metadata(&metadata));
+                } else {
+                    debug!(
+                        "Ignoring device metadata of {from} (firmware {version})",
+                        version = metadata.firmware_version,
+                        from = NodeId(from)
+                    );
+                }
+            }
+            PayloadVariant::GetRingtoneResponse(ringtone) => {
+                debug!("Processing ringtone response");
+                self.device_state.lock().await.ringtone = Some(ringtone);
+            }
+            PayloadVariant::GetCannedMessageModuleMessagesResponse(messages) => {
+                debug!("Processing canned messages response");
+                self.device_state
+                    .lock()
+                    .await
+                    .set_canned_messages(&messages);
+            }
+            other => {
+                debug!(
+                    "Admin message not stored: {variant:?}",
+                    variant = std::mem::discriminant(&other)
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn resolve_response(waiters: &ResponseWaiters, request_id: u32, response: RequestResponse) {
+    if request_id == 0 {
+        return;
+    }
+
+    let Some(sender) = waiters
+        .lock()
+        .ok()
+        .and_then(|mut waiters| waiters.remove(&request_id))
+    else {
+        return;
+    };
+
+    if sender.send(response).is_err() {
+        debug!("Response receiver dropped for request {request_id}");
+    } else {
+        debug!("Resolved response for request {request_id}");
+    }
+}
+
+/// Traceroute hops with the names of the nodes on the route
+fn route_hops(state: &DeviceState, route: &[u32]) -> Vec<crate::mesh::RouteHop> {
+    route
+        .iter()
+        .enumerate()
+        .map(|(idx, node_num)| crate::mesh::RouteHop {
+            node_id: *node_num,
+            node_name: state
+                .nodes
+                .get(node_num)
+                .map(|n| n.user.long_name.clone())
+                .unwrap_or_else(|| format!("Unknown ({num})", num = NodeId(*node_num))),
+            hop_number: idx as u32,
+            snr: None,  // Route replies don't include SNR
+            rssi: None, // Route replies don't include RSSI
+        })
+        .collect()
+}
+
+pub(crate) fn channel_info(channel: meshtastic::protobufs::Channel) -> ChannelInfo {
+    ChannelInfo {
+        index: channel.index as u32,
+        name: channel
+            .settings
+            .as_ref()
+            .map(|s| s.name.clone())
+            .unwrap_or_else(|| format!("Channel {index}", index = channel.index)),
+        role: format!("{role:?}", role = channel.role()),
+        has_psk: channel
+            .settings
+            .as_ref()
+            .map(|s| !s.psk.is_empty())
+            .unwrap_or_default(),
+        settings: channel.settings,
+    }
+}
+
+fn user_info(user: &meshtastic::protobufs::User) -> User {
+    User {
+        id: user.id.clone(),
+        long_name: user.long_name.clone(),
+        short_name: user.short_name.clone(),
+        hw_model: Some(format!("{model:?}", model = user.hw_model())),
+        role: Some(format!("{role:?}", role = user.role())),
+        public_key: (!user.public_key.is_empty()).then(|| hex::encode(&user.public_key)),
+        is_licensed: user.is_licensed,
+    }
+}
+
+fn device_metrics(metrics: &meshtastic::protobufs::DeviceMetrics) -> DeviceMetrics {
+    DeviceMetrics {
+        battery_level: metrics.battery_level,
+        voltage: metrics.voltage,
+        channel_utilization: metrics.channel_utilization,
+        air_util_tx: metrics.air_util_tx,
+        uptime_seconds: metrics.uptime_seconds,
+    }
+}
+
+fn device_metadata(metadata: &meshtastic::protobufs::DeviceMetadata) -> DeviceMetadata {
+    DeviceMetadata {
+        firmware_version: metadata.firmware_version.clone(),
+        device_state_version: metadata.device_state_version,
+        hw_model: format!("{model:?}", model = metadata.hw_model()),
+        role: format!("{role:?}", role = metadata.role()),
+        has_wifi: metadata.has_wifi,
+        has_bluetooth: metadata.has_bluetooth,
+        has_ethernet: metadata.has_ethernet,
+    }
+}
+
+async fn process_module_config_response(
+    module_config: meshtastic::protobufs::ModuleConfig,
+    device_state: &Mutex<DeviceState>,
+    events: &EventBus,
+) {
+    if let Some(payload) = &module_config.payload_variant {
+        let change = device_state.lock().await.store_raw_module_config(payload);
+        publish_changes(events, change);
+    }
+    match module_config.payload_variant {
+        Some(meshtastic::protobufs::module_config::PayloadVariant::NeighborInfo(config)) => {
+            let mut state = device_state.lock().await;
+            state.neighbor_info_config = Some(NeighborInfoConfig {
+                enabled: config.enabled,
+                update_interval: config.update_interval,
+            });
+            debug!("Updated NeighborInfo module config");
+        }
+        Some(meshtastic::protobufs::module_config::PayloadVariant::Mqtt(config)) => {
+            let mut state = device_state.lock().await;
+            state.mqtt_config = Some(MqttConfig {
+                enabled: config.enabled,
+                address: config.address,
+                username: config.username,
+                password: config.password,
+                encryption_enabled: config.encryption_enabled,
+                json_enabled: config.json_enabled,
+                tls_enabled: config.tls_enabled,
+                root: config.root,
+                proxy_to_client_enabled: config.proxy_to_client_enabled,
+            });
+            debug!("Updated MQTT module config");
+        }
+        Some(meshtastic::protobufs::module_config::PayloadVariant::Telemetry(config)) => {
+            let mut state = device_state.lock().await;
+            state.telemetry_config = Some(TelemetryConfig {
+                device_update_interval: config.device_update_interval,
+                environment_update_interval: config.environment_update_interval,
+                environment_measurement_enabled: config.environment_measurement_enabled,
+                environment_screen_enabled: config.environment_screen_enabled,
+                environment_display_fahrenheit: config.environment_display_fahrenheit,
+                air_quality_enabled: config.air_quality_enabled,
+                air_quality_interval: config.air_quality_interval,
+                power_measurement_enabled: config.power_measurement_enabled,
+                power_update_interval: config.power_update_interval,
+                power_screen_enabled: config.power_screen_enabled,
+            });
+            debug!("Updated Telemetry module config");
+        }
+        Some(variant) => {
+            debug!(
+                "Module config not yet handled: {variant:?}",
+                variant = std::mem::discriminant(&variant)
+            );
+        }
+        None => {}
+    }
+}
+
+async fn process_config_response(
+    config: meshtastic::protobufs::Config,
+    device_state: &Mutex<DeviceState>,
+    events: &EventBus,
+) -> Result<()> {
+    let mut state = device_state.lock().await;
+
+    if let Some(payload) = config.payload_variant {
+        publish_changes(events, state.store_raw_config(&payload));
+        match payload {
+            meshtastic::protobufs::config::PayloadVariant::Device(device_config) => {
+                state.device_config = Some(DeviceConfig {
+                    role: format!("{role:?}", role = device_config.role()),
+                    button_gpio: device_config.button_gpio,
+                    buzzer_gpio: device_config.buzzer_gpio,
+                    rebroadcast_mode: format!("{mode:?}", mode = device_config.rebroadcast_mode()),
+                    node_info_broadcast_secs: device_config.node_info_broadcast_secs,
+                    tzdef: if device_config.tzdef.is_empty() {
+                        None
+                    } else {
+                        Some(device_config.tzdef)
+                    },
+                    disable_triple_click: device_config.disable_triple_click,
+                });
+                debug!("Updated device config");
+            }
+            meshtastic::protobufs::config::PayloadVariant::Position(position_config) => {
+                state.position_config = Some(PositionConfig {
+                    position_broadcast_secs: position_config.position_broadcast_secs,
+                    position_broadcast_smart_enabled: position_config
+                        .position_broadcast_smart_enabled,
+                    fixed_position: position_config.fixed_position,
+                    gps_enabled: position_config.gps_mode()
+                        != meshtastic::protobufs::config::position_config::GpsMode::Disabled,
+                    gps_mode: format!("{mode:?}", mode = position_config.gps_mode()),
+                });
+                debug!("Updated position config");
+            }
+            meshtastic::protobufs::config::PayloadVariant::Power(power_config) => {
+                state.power_config = Some(PowerConfig {
+                    is_power_saving: power_config.is_power_saving,
+                    on_battery_shutdown_after_secs: power_config.on_battery_shutdown_after_secs,
+                    adc_multiplier_override: power_config.adc_multiplier_override,
+                    wait_bluetooth_secs: power_config.wait_bluetooth_secs,
+                    sds_secs: power_config.sds_secs,
+                    ls_secs: power_config.ls_secs,
+                    min_wake_secs: power_config.min_wake_secs,
+                });
+                debug!("Updated power config");
+            }
+            meshtastic::protobufs::config::PayloadVariant::Network(network_config) => {
+                state.network_config = Some(NetworkConfig {
+                    wifi_enabled: network_config.wifi_enabled,
+                    wifi_ssid: network_config.wifi_ssid,
+                    wifi_psk: network_config.wifi_psk,
+                    ntp_server: network_config.ntp_server,
+                    eth_enabled: network_config.eth_enabled,
+                    ipv4_config: network_config
+                        .ipv4_config
+                        .as_ref()
+                        .map(|config| format!("{config:?}")),
+                });
+                debug!("Updated network config");
+            }
+            meshtastic::protobufs::config::PayloadVariant::Display(display_config) => {
+                state.display_config = Some(DisplayConfig {
+                    screen_on_secs: display_config.screen_on_secs,
+                    gps_format: format!("{format:?}", format = display_config.gps_format()),
+                    auto_screen_carousel_secs: display_config.auto_screen_carousel_secs,
+                    compass_north_top: display_config.compass_north_top,
+                    flip_screen: display_config.flip_screen,
+                    units: format!("{units:?}", units = display_config.units()),
+                    displaymode: format!("{mode:?}", mode = display_config.displaymode()),
+                    heading_bold: display_config.heading_bold,
+                    wake_on_tap_or_motion: display_config.wake_on_tap_or_motion,
+                });
+                debug!("Updated display config");
+            }
+            meshtastic::protobufs::config::PayloadVariant::Lora(lora_config) => {
+                // Convert region enum to human-readable string
+                let region_str = match lora_config.region() {
+                    meshtastic::protobufs::config::lo_ra_config::RegionCode::Unset => "Unset",
+                    meshtastic::protobufs::config::lo_ra_config::RegionCode::Us => "US",
+                    meshtastic::protobufs::config::lo_ra_config::RegionCode::Eu433 => "EU433",
+                    meshtastic::protobufs::config::lo_ra_config::RegionCode::Eu868 => "EU868",
+                    meshtastic::protobufs::config::lo_ra_config::RegionCode::Cn => "CN",
+                    meshtastic::protobufs::config::lo_ra_config::RegionCode::Jp => "JP",
+                    meshtastic::protobufs::config::lo_ra_config::RegionCode::Anz => "ANZ",
+                    meshtastic::protobufs::config::lo_ra_config::RegionCode::Kr => "KR",
+                    meshtastic::protobufs::config::lo_ra_config::RegionCode::Tw => "TW",
+                    meshtastic::protobufs::config::lo_ra_config::RegionCode::Ru => "RU",
+                    meshtastic::protobufs::config::lo_ra_config::RegionCode::In => "IN",
+                    meshtastic::protobufs::config::lo_ra_config::RegionCode::Nz865 => "NZ865",
+                    meshtastic::protobufs::config::lo_ra_config::RegionCode::Th => "TH",
+                    meshtastic::protobufs::config::lo_ra_config::RegionCode::Lora24 => "LORA24",
+                    meshtastic::protobufs::config::lo_ra_config::RegionCode::Ua433 => "UA433",
+                    meshtastic::protobufs::config::lo_ra_config::RegionCode::Ua868 => "UA868",
+                    meshtastic::protobufs::config::lo_ra_config::RegionCode::My433 => "MY433",
+                    meshtastic::protobufs::config::lo_ra_config::RegionCode::My919 => "MY919",
+                    meshtastic::protobufs::config::lo_ra_config::RegionCode::Sg923 => "SG923",
+                    meshtastic::protobufs::config::lo_ra_config::RegionCode::Ph433 => "PH433",
+                    meshtastic::protobufs::config::lo_ra_config::RegionCode::Ph868 => "PH868",
+                    meshtastic::protobufs::config::lo_ra_config::RegionCode::Ph915 => "PH915",
+                };
+
+                state.lora_config = Some(LoraConfig {
+                    use_preset: lora_config.use_preset,
+                    modem_preset: format!("{preset:?}", preset = lora_config.modem_preset()),
+                    bandwidth: lora_config.bandwidth,
+                    spread_factor: lora_config.spread_factor,
+                    coding_rate: lora_config.coding_rate,
+                    frequency_offset: lora_config.frequency_offset,
+                    region: region_str.to_string(),
+                    hop_limit: lora_config.hop_limit,
+                    tx_enabled: lora_config.tx_enabled,
+                    tx_power: lora_config.tx_power,
+                    channel_num: lora_config.channel_num,
+                    ignore_mqtt: lora_config.ignore_mqtt,
+                    override_frequency: lora_config.override_frequency,
+                    override_duty_cycle: lora_config.override_duty_cycle,
+                    sx126x_rx_boosted_gain: lora_config.sx126x_rx_boosted_gain,
+                });
+                debug!("Updated LoRa config");
+            }
+            meshtastic::protobufs::config::PayloadVariant::Bluetooth(bluetooth_config) => {
+                state.bluetooth_config = Some(BluetoothConfig {
+                    enabled: bluetooth_config.enabled,
+                    mode: format!("{mode:?}", mode = bluetooth_config.mode()),
+                    fixed_pin: bluetooth_config.fixed_pin,
+                    device_logging_enabled: false, // Not available in current protobuf
+                });
+                debug!("Updated Bluetooth config");
+            }
+            meshtastic::protobufs::config::PayloadVariant::Security(_security_config) => {
+                // Security config not yet handled
+                debug!("Security config received but not yet handled");
+            }
+            meshtastic::protobufs::config::PayloadVariant::Sessionkey(_sessionkey_config) => {
+                // Sessionkey config not yet handled
+                debug!("Sessionkey config received but not yet handled");
+            }
+            meshtastic::protobufs::config::PayloadVariant::DeviceUi(_device_ui_config) => {
+                // DeviceUI config not yet handled
+                debug!("DeviceUI config received but not yet handled");
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,237 @@
+use super::stats::ConnectionCounters;
+use anyhow::{Context, Result, anyhow, bail};
+use meshtastic::api::ConnectedStreamApi;
+use meshtastic::api::state::Configured;
+use meshtastic::protobufs::to_radio::PayloadVariant;
+use meshtastic::protobufs::{MeshPacket, PortNum, mesh_packet};
+use std::future::Future;
+use std::sync::atomic::Ordering;
+use strum::Display;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tracing::{Instrument, Span, debug, warn};
+
+/// Frames of one priority that may wait for the writer before their
+/// senders are held back
+pub const SEND_QUEUE_CAPACITY: usize = 32;
+
+/// Order in which queued frames are written, highest first
+///
+/// A frame waits only while frames of a higher priority are queued, so
+/// interactive operations stay quick while bulk ones run alongside.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum SendPriority {
+    /// Requests sent in bulk or on a timer: telemetry and position polls,
+    /// beacons
+    Background,
+    /// Traffic a user asked for, such as messages, traceroutes and requests
+    /// for one node's position or info
+    Normal,
+    /// Admin messages, ACKs and frames for the device itself
+    High,
+}
+
+impl SendPriority {
+    /// Default class of a frame
+    ///
+    /// The port alone can't tell a user's request from one of a bulk poll,
+    /// so nothing is [`Background`](Self::Background) by default; polls and
+    /// beacons ask for it when sending.
+    pub fn of(payload: &PayloadVariant) -> Self {
+        match payload {
+            PayloadVariant::Packet(packet) => Self::of_packet(packet),
+            // Config requests, heartbeats and the like never reach the mesh
+            _ => Self::High,
+        }
+    }
+
+    /// Default class of a mesh packet, from its port
+    pub fn of_packet(packet: &MeshPacket) -> Self {
+        match &packet.payload_variant {
+            Some(mesh_packet::PayloadVariant::Decoded(data)) => Self::of_port(data.portnum()),
+            _ => Self::Normal,
+        }
+    }
+
+    /// Default class of a packet on `port`
+    pub fn of_port(port: PortNum) -> Self {
+        match port {
+            PortNum::AdminApp | PortNum::RoutingApp => Self::High,
+            _ => Self::Normal,
+        }
+    }
+}
+
+/// Where the writer task puts frames: the radio API, or a recorder in tests
+pub(crate) trait FrameSink: Send + 'static {
+    fn send_frame(&mut self, payload: PayloadVariant) -> impl Future<Output = Result<()>> + Send;
+}
+
+impl FrameSink for ConnectedStreamApi<Configured> {
+    async fn send_frame(&mut self, payload: PayloadVariant) -> Result<()> {
+        self.send_to_radio_packet(Some(payload)).await?;
+        Ok(())
+    }
+}
+
+struct QueuedFrame {
+    payload: PayloadVariant,
+    /// Span of the sender, so the write is traced along with the send
+    span: Span,
+    sent: oneshot::Sender<Result<()>>,
+}
+
+/// Handle for sending frames through the connection's single writer task
+///
+/// Clones can send from several tasks at once: each frame is written whole,
+/// by [`SendPriority`] and then in the order queued, so concurrent sends
+/// never interleave on the link. Once [`SEND_QUEUE_CAPACITY`] frames of a
+/// priority are waiting, senders of that priority wait too.
+#[derive(Clone)]
+pub struct RadioSender {
+    high: mpsc::Sender<QueuedFrame>,
+    normal: mpsc::Sender<QueuedFrame>,
+    background: mpsc::Sender<QueuedFrame>,
+    counters: ConnectionCounters,
+}
+
+impl RadioSender {
+    /// Queue a frame at the priority of its class and wait until it was
+    /// written
+    pub async fn send(&self, payload: PayloadVariant) -> Result<()> {
+        let priority = SendPriority::of(&payload);
+        self.send_with_priority(payload, priority).await
+    }
+
+    /// Queue a frame at `priority` and wait until it was written
+    pub async fn send_with_priority(
+        &self,
+        payload: PayloadVariant,
+        priority: SendPriority,
+    ) -> Result<()> {
+        let queue = match priority {
+            SendPriority::High => &self.high,
+            SendPriority::Normal => &self.normal,
+            SendPriority::Background => &self.background,
+        };
+        let _queued = QueueSlot::new(&self.counters);
+        let (sent, written) = oneshot::channel();
+        let frame = QueuedFrame {
+            payload,
+            span: Span::current(),
+            sent,
+        };
+
+        match queue.try_send(frame) {
+            Ok(()) => {}
+            Err(TrySendError::Full(frame)) => {
+                debug!("Send queue for {priority} frames is full, waiting for the writer");
+                if queue.send(frame).await.is_err() {
+                    bail!("Not connected");
+                }
+            }
+            Err(TrySendError::Closed(_)) => bail!("Not connected"),
+        }
+
+        written
+            .await
+            .unwrap_or_else(|_| Err(anyhow!("The connection closed before the frame was sent")))
+    }
+}
+
+/// Counts a frame in the send queue depth until its send completes or is
+/// abandoned
+struct QueueSlot<'a>(&'a ConnectionCounters);
+
+impl<'a> QueueSlot<'a> {
+    fn new(counters: &'a ConnectionCounters) -> Self {
+        let depth = counters.send_queue.fetch_add(1, Ordering::Relaxed) + 1;
+        counters.send_queue_peak.fetch_max(depth, Ordering::Relaxed);
+        Self(counters)
+    }
+}
+
+impl Drop for QueueSlot<'_> {
+    fn drop(&mut self) {
+        self.0.send_queue.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// The writer task, owning the sink every frame is written to
+pub(crate) struct RadioWriter<S> {
+    sender: RadioSender,
+    shutdown: oneshot::Sender<()>,
+    task: JoinHandle<S>,
+}
+
+impl<S: FrameSink> RadioWriter<S> {
+    pub fn spawn(sink: S, counters: ConnectionCounters) -> Self {
+        let (high, high_frames) = mpsc::channel(SEND_QUEUE_CAPACITY);
+        let (normal, normal_frames) = mpsc::channel(SEND_QUEUE_CAPACITY);
+        let (background, background_frames) = mpsc::channel(SEND_QUEUE_CAPACITY);
+        let (shutdown, stop) = oneshot::channel();
+        let queues = Queues {
+            high: high_frames,
+            normal: normal_frames,
+            background: background_frames,
+        };
+        Self {
+            sender: RadioSender {
+                high,
+                normal,
+                background,
+                counters,
+            },
+            shutdown,
+            task: tokio::spawn(write_frames(sink, queues, stop)),
+        }
+    }
+
+    pub fn sender(&self) -> &RadioSender {
+        &self.sender
+    }
+
+    /// Stop the writer and take back the sink
+    ///
+    /// A frame being written is finished; frames still queued fail.
+    pub async fn close(self) -> Result<S> {
+        if self.shutdown.send(()).is_err() {
+            debug!("Radio writer already stopped: every sender was dropped");
+        }
+        self.task.await.context("Radio writer task failed")
+    }
+}
+
+struct Queues {
+    high: mpsc::Receiver<QueuedFrame>,
+    normal: mpsc::Receiver<QueuedFrame>,
+    background: mpsc::Receiver<QueuedFrame>,
+}
+
+async fn write_frames<S: FrameSink>(
+    mut sink: S,
+    mut queues: Queues,
+    mut stop: oneshot::Receiver<()>,
+) -> S {
+    loop {
+        // Checked in order, so a lower priority is only taken once the
+        // higher ones are empty
+        let frame = tokio::select! {
+            biased;
+            _ = &mut stop => break,
+            Some(frame) = queues.high.recv() => frame,
+            Some(frame) = queues.normal.recv() => frame,
+            Some(frame) = queues.background.recv() => frame,
+            // Every sender was dropped
+            else => break,
+        };
+        let result = sink.send_frame(frame.payload).instrument(frame.span).await;
+        // The sender may have stopped waiting for the result
+        if let Err(Err(e)) = frame.sent.send(result) {
+            warn!("Failed to send a frame nobody waited for: {e:#}");
+        }
+    }
+    sink
+}
@@ -0,0 +1,653 @@
+use anyhow::{Result, ensure};
+use meshtastic::Message;
+use meshtastic::protobufs::{
+    self, FromRadio, MeshPacket, PortNum, ToRadio, from_radio, mesh_packet, to_radio,
+};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::time::Duration;
+use strum::{Display, EnumString};
+use tokio::io::{AsyncWrite, AsyncWriteExt, DuplexStream};
+use tokio::sync::mpsc;
+use tracing::debug;
+
+use crate::connection::framing::{encode_frame, read_frame};
+use crate::firmware::BUNDLED_PROTOBUF_VERSION;
+use crate::node_id::NodeId;
+
+/// Name shown for the connection instead of a port or address
+pub const SIMULATED_TARGET: &str = "simulated mesh";
+
+/// Node number of the simulated radio rmesh is connected to
+pub const SIMULATED_LOCAL_NODE: u32 = 0x5e11_0000;
+
+/// Most fake nodes a simulation can have
+pub const MAX_SIMULATED_NODES: usize = 64;
+
+const BROADCAST: u32 = 0xffff_ffff;
+
+const DUPLEX_BUFFER: usize = 64 * 1024;
+
+const HOP_LIMIT: u32 = 3;
+
+/// Airtime of one hop, so replies arrive after a plausible delay
+const HOP_LATENCY: Duration = Duration::from_millis(400);
+
+/// Where the first fake node sits; the others are spread out from it
+const BASE_POSITION: (f64, f64) = (52.5200, 13.4050);
+
+const CHATTER: &[&str] = &[
+    "Hello mesh!",
+    "Anyone copy?",
+    "Battery holding up fine here",
+    "Heading out, back in an hour",
+    "Signal is great from the ridge",
+    "Testing, testing",
+    "Good morning everyone",
+    "Weather is turning, stay safe",
+];
+
+/// How the fake nodes hear each other and the local radio
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Display, EnumString)]
+#[strum(serialize_all = "lowercase", ascii_case_insensitive)]
+pub enum Topology {
+    /// Every node is in direct range of the local radio
+    #[default]
+    Full,
+    /// The first node is a hub relaying all the others
+    Star,
+    /// A chain: each node is one hop further than the one before
+    Line,
+}
+
+impl Topology {
+    /// Relays between the local radio and the node at `index`, nearest first
+    pub fn relays(self, index: usize, nodes: &[u32]) -> Vec<u32> {
+        match self {
+            Self::Full => Vec::new(),
+            Self::Star if index == 0 => Vec::new(),
+            Self::Star => nodes.first().copied().into_iter().collect(),
+            Self::Line => nodes[..index.min(nodes.len())].to_vec(),
+        }
+    }
+}
+
+/// Shape and behaviour of the virtual mesh
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimulationOptions {
+    /// Fake nodes besides the local radio
+    pub nodes: usize,
+    pub topology: Topology,
+    /// Chance, from 0 to 1, that a packet is lost on each hop
+    pub loss: f64,
+    /// Pause between packets generated by the fake nodes
+    pub interval: Duration,
+    /// Seed for a repeatable run; random when not set
+    pub seed: Option<u64>,
+}
+
+impl Default for SimulationOptions {
+    fn default() -> Self {
+        Self {
+            nodes: 4,
+            topology: Topology::default(),
+            loss: 0.0,
+            interval: Duration::from_secs(10),
+            seed: None,
+        }
+    }
+}
+
+impl SimulationOptions {
+    pub fn validate(&self) -> Result<()> {
+        ensure!(
+            (1..=MAX_SIMULATED_NODES).contains(&self.nodes),
+            "Simulated node count must be between 1 and {MAX_SIMULATED_NODES}, got {nodes}",
+            nodes = self.nodes
+        );
+        ensure!(
+            (0.0..=1.0).contains(&self.loss),
+            "Simulated packet loss must be between 0 and 1, got {loss}",
+            loss = self.loss
+        );
+        ensure!(
+            !self.interval.is_zero(),
+            "Simulated traffic interval must be positive"
+        );
+        Ok(())
+    }
+}
+
+/// A frame for the client, sent once `delay` has passed
+#[derive(Debug, Clone)]
+pub struct Scheduled {
+    pub delay: Duration,
+    pub frame: FromRadio,
+}
+
+/// Start a virtual mesh and return the client end of its stream
+///
+/// The other end behaves like a radio on the serial stream API: it answers
+/// the config handshake, ACKs and replies to requests for the fake nodes,
+/// and broadcasts their positions, telemetry and chatter. The mesh stops
+/// when the client end is dropped.
+pub fn spawn_virtual_mesh(options: SimulationOptions) -> Result<DuplexStream> {
+    options.validate()?;
+    let (client, device) = tokio::io::duplex(DUPLEX_BUFFER);
+    tokio::spawn(VirtualMesh::new(options).run(device));
+    Ok(client)
+}
+
+/// The fake nodes and the radio the client is connected to
+pub struct VirtualMesh {
+    options: SimulationOptions,
+    rng: StdRng,
+    nodes: Vec<u32>,
+    positions: Vec<(f64, f64)>,
+    next_frame_id: u32,
+    next_packet_id: u32,
+    session_passkey: Vec<u8>,
+    started: std::time::Instant,
+}
+
+impl VirtualMesh {
+    pub fn new(options: SimulationOptions) -> Self {
+        let mut rng = match options.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_os_rng(),
+        };
+        let nodes: Vec<u32> = (1..=options.nodes as u32)
+            .map(|index| SIMULATED_LOCAL_NODE + index)
+            .collect();
+        let positions = (0..nodes.len())
+            .map(|index| {
+                (
+                    BASE_POSITION.0 + index as f64 * 0.01,
+                    BASE_POSITION.1 + index as f64 * 0.015,
+                )
+            })
+            .collect();
+        let session_passkey = (0..8).map(|_| rng.random()).collect();
+        let next_packet_id = rng.random_range(1..u32::MAX / 2);
+
+        Self {
+            options,
+            rng,
+            nodes,
+            positions,
+            next_frame_id: 1,
+            next_packet_id,
+            session_passkey,
+            started: std::time::Instant::now(),
+        }
+    }
+
+    /// Node numbers of the fake nodes
+    pub fn nodes(&self) -> &[u32] {
+        &self.nodes
+    }
+
+    async fn run(mut self, stream: DuplexStream) {
+        let (mut reader, writer) = tokio::io::split(stream);
+        let (requests_tx, mut requests) = mpsc::unbounded_channel();
+        let (frames, frames_rx) = mpsc::unbounded_channel();
+
+        // Frames are read on their own task, since a read interrupted by
+        // the traffic timer would lose its partial frame
+        tokio::spawn(async move {
+            while let Ok(payload) = read_frame(&mut reader).await {
+                match ToRadio::decode(payload.as_slice()) {
+                    Ok(to_radio) => {
+                        if requests_tx.send(to_radio).is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => debug!("Simulation ignored an undecodable frame: {e}"),
+                }
+            }
+        });
+        tokio::spawn(write_frames(writer, frames_rx));
+
+        let mut traffic = tokio::time::interval(self.options.interval);
+        // The first tick completes at once; the handshake comes first
+        traffic.tick().await;
+
+        loop {
+            let scheduled = tokio::select! {
+                to_radio = requests.recv() => match to_radio {
+                    Some(to_radio) => self.handle_to_radio(to_radio),
+                    None => break,
+                },
+                _ = traffic.tick() => self.tick(),
+            };
+
+            for Scheduled { delay, frame } in scheduled {
+                let frames = frames.clone();
+                tokio::spawn(async move {
+                    tokio::time::sleep(delay).await;
+                    if frames.send(frame).is_err() {
+                        debug!("Simulated mesh client went away, dropping frame");
+                    }
+                });
+            }
+        }
+
+        debug!("Simulated mesh stopped");
+    }
+
+    /// React to a frame from the client
+    pub fn handle_to_radio(&mut self, to_radio: ToRadio) -> Vec<Scheduled> {
+        match to_radio.payload_variant {
+            Some(to_radio::PayloadVariant::WantConfigId(config_id)) => self
+                .config_frames(config_id)
+                .into_iter()
+                .map(|frame| Scheduled {
+                    delay: Duration::ZERO,
+                    frame,
+                })
+                .collect(),
+            Some(to_radio::PayloadVariant::Packet(packet)) => self.handle_packet(packet),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Everything a radio reports in reply to want_config
+    pub fn config_frames(&mut self, config_id: u32) -> Vec<FromRadio> {
+        let mut variants = vec![from_radio::PayloadVariant::MyInfo(protobufs::MyNodeInfo {
+            my_node_num: SIMULATED_LOCAL_NODE,
+            pio_env: "native".to_string(),
+            ..Default::default()
+        })];
+
+        let now = crate::time::unix_now() as u32;
+        for (index, node) in std::iter::once(SIMULATED_LOCAL_NODE)
+            .chain(self.nodes.clone())
+            .enumerate()
+        {
+            variants.push(from_radio::PayloadVariant::NodeInfo(protobufs::NodeInfo {
+                num: node,
+                user: Some(simulated_user(node, index)),
+                snr: if index == 0 { 0.0 } else { self.snr() },
+                last_heard: now,
+                ..Default::default()
+            }));
+        }
+
+        variants.push(from_radio::PayloadVariant::Metadata(
+            protobufs::DeviceMetadata {
+                firmware_version: format!("{BUNDLED_PROTOBUF_VERSION}.simulated"),
+                hw_model: protobufs::HardwareModel::Portduino as i32,
+                ..Default::default()
+            },
+        ));
+        for index in 0..8 {
+            variants.push(from_radio::PayloadVariant::Channel(simulated_channel(
+                index,
+            )));
+        }
+        for config in [lora_config(), device_config()] {
+            variants.push(from_radio::PayloadVariant::Config(config));
+        }
+        variants.push(from_radio::PayloadVariant::ConfigCompleteId(config_id));
+
+        variants
+            .into_iter()
+            .map(|variant| self.frame(variant))
+            .collect()
+    }
+
+    /// Deliver a packet sent by the client and produce the mesh's answers
+    pub fn handle_packet(&mut self, packet: MeshPacket) -> Vec<Scheduled> {
+        let Some(mesh_packet::PayloadVariant::Decoded(data)) = packet.payload_variant.clone()
+        else {
+            return Vec::new();
+        };
+
+        // Admin requests for the local radio never leave it
+        if packet.to == 0 || packet.to == SIMULATED_LOCAL_NODE {
+            let mut replies = Vec::new();
+            if data.portnum() == PortNum::AdminApp
+                && let Some(reply) = self.admin_reply(SIMULATED_LOCAL_NODE, &data)
+            {
+                replies.push(self.reply(SIMULATED_LOCAL_NODE, 0, reply, Duration::ZERO));
+            }
+            if packet.want_ack {
+                replies.push(self.routing(SIMULATED_LOCAL_NODE, packet.id, true, 0));
+            }
+            return replies;
+        }
+
+        if packet.to == BROADCAST {
+            // The radio confirms a broadcast once it hears it rebroadcast
+            return if packet.want_ack {
+                vec![self.routing(SIMULATED_LOCAL_NODE, packet.id, true, 1)]
+            } else {
+                Vec::new()
+            };
+        }
+
+        let Some(index) = self.nodes.iter().position(|node| *node == packet.to) else {
+            debug!(
+                "Simulation has no node {to}, dropping packet",
+                to = NodeId(packet.to)
+            );
+            return if packet.want_ack {
+                vec![self.routing(SIMULATED_LOCAL_NODE, packet.id, false, 2)]
+            } else {
+                Vec::new()
+            };
+        };
+
+        let hops = self.options.topology.relays(index, &self.nodes).len() as u32;
+        if !self.delivered(hops) {
+            debug!("Simulation lost packet {id}", id = packet.id);
+            return if packet.want_ack {
+                vec![self.routing(SIMULATED_LOCAL_NODE, packet.id, false, 2 * (hops + 1))]
+            } else {
+                Vec::new()
+            };
+        }
+
+        let mut replies = Vec::new();
+        let round_trip = 2 * (hops + 1);
+        if packet.want_ack {
+            replies.push(self.routing(packet.to, packet.id, true, round_trip));
+        }
+        if data.want_response
+            && let Some(payload) = self.response(index, &data)
+            && self.delivered(hops)
+        {
+            let delay = HOP_LATENCY * (round_trip + 1);
+            let data = protobufs::Data {
+                portnum: data.portnum,
+                payload,
+                request_id: packet.id,
+                ..Default::default()
+            };
+            replies.push(self.reply(packet.to, hops, data, delay));
+        }
+        replies
+    }
+
+    /// A random fake node broadcasts a position, telemetry or a message
+    pub fn tick(&mut self) -> Vec<Scheduled> {
+        let index = self.rng.random_range(0..self.nodes.len());
+        let node = self.nodes[index];
+        let hops = self.options.topology.relays(index, &self.nodes).len() as u32;
+        if !self.delivered(hops) {
+            debug!("Simulation lost traffic from {node}", node = NodeId(node));
+            return Vec::new();
+        }
+
+        let roll: f64 = self.rng.random();
+        let data = if roll < 0.5 {
+            let (latitude, longitude) = &mut self.positions[index];
+            *latitude += self.rng.random_range(-0.0005..0.0005);
+            *longitude += self.rng.random_range(-0.0005..0.0005);
+            protobufs::Data {
+                portnum: PortNum::PositionApp as i32,
+                payload: self.position(index).encode_to_vec(),
+                ..Default::default()
+            }
+        } else if roll < 0.8 {
+            protobufs::Data {
+                portnum: PortNum::TelemetryApp as i32,
+                payload: self.telemetry().encode_to_vec(),
+                ..Default::default()
+            }
+        } else {
+            let text = CHATTER[self.rng.random_range(0..CHATTER.len())];
+            protobufs::Data {
+                portnum: PortNum::TextMessageApp as i32,
+                payload: text.as_bytes().to_vec(),
+                ..Default::default()
+            }
+        };
+
+        let packet = self.packet(node, BROADCAST, hops, data);
+        vec![Scheduled {
+            delay: HOP_LATENCY * hops,
+            frame: self.frame(from_radio::PayloadVariant::Packet(packet)),
+        }]
+    }
+
+    /// Payload answering a want_response request to the node at `index`
+    fn response(&mut self, index: usize, data: &protobufs::Data) -> Option<Vec<u8>> {
+        match data.portnum() {
+            PortNum::PositionApp => Some(self.position(index).encode_to_vec()),
+            PortNum::TelemetryApp => Some(self.telemetry().encode_to_vec()),
+            PortNum::TracerouteApp => {
+                let relays = self.options.topology.relays(index, &self.nodes);
+                let snr = relays.iter().map(|_| (self.snr() * 4.0) as i32).collect();
+                Some(
+                    protobufs::RouteDiscovery {
+                        route: relays.clone(),
+                        route_back: relays.into_iter().rev().collect(),
+                        snr_towards: snr,
+                        snr_back: Vec::new(),
+                    }
+                    .encode_to_vec(),
+                )
+            }
+            PortNum::AdminApp => self
+                .admin_reply(self.nodes[index], data)
+                .map(|reply| reply.payload),
+            _ => None,
+        }
+    }
+
+    /// Answer to an admin request, carrying the session passkey
+    fn admin_reply(&mut self, node: u32, data: &protobufs::Data) -> Option<protobufs::Data> {
+        use protobufs::admin_message::{ConfigType, PayloadVariant};
+
+        let request = protobufs::AdminMessage::decode(data.payload.as_slice()).ok()?;
+        let index = std::iter::once(SIMULATED_LOCAL_NODE)
+            .chain(self.nodes.iter().copied())
+            .position(|n| n == node)
+            .unwrap_or_default();
+        let payload_variant = match request.payload_variant? {
+            PayloadVariant::GetConfigRequest(config_type) => {
+                match ConfigType::try_from(config_type) {
+                    Ok(ConfigType::LoraConfig) => {
+                        Some(PayloadVariant::GetConfigResponse(lora_config()))
+                    }
+                    Ok(ConfigType::DeviceConfig) => {
+                        Some(PayloadVariant::GetConfigResponse(device_config()))
+                    }
+                    // The passkey alone answers a session key request
+                    _ => None,
+                }
+            }
+            PayloadVariant::GetChannelRequest(index) => Some(PayloadVariant::GetChannelResponse(
+                simulated_channel(index.saturating_sub(1) as i32),
+            )),
+            PayloadVariant::GetOwnerRequest(_) => Some(PayloadVariant::GetOwnerResponse(
+                simulated_user(node, index),
+            )),
+            _ => return None,
+        };
+
+        let reply = protobufs::AdminMessage {
+            payload_variant,
+            session_passkey: self.session_passkey.clone(),
+        };
+        Some(protobufs::Data {
+            portnum: PortNum::AdminApp as i32,
+            payload: reply.encode_to_vec(),
+            ..Default::default()
+        })
+    }
+
+    /// ACK, or NAK when not `delivered`, of packet `request_id` from `from`
+    fn routing(&mut self, from: u32, request_id: u32, delivered: bool, hops: u32) -> Scheduled {
+        let error = if delivered {
+            protobufs::routing::Error::None
+        } else {
+            protobufs::routing::Error::MaxRetransmit
+        };
+        let data = protobufs::Data {
+            portnum: PortNum::RoutingApp as i32,
+            payload: protobufs::Routing {
+                variant: Some(protobufs::routing::Variant::ErrorReason(error as i32)),
+            }
+            .encode_to_vec(),
+            request_id,
+            ..Default::default()
+        };
+        self.reply(from, 0, data, HOP_LATENCY * hops)
+    }
+
+    fn reply(&mut self, from: u32, hops: u32, data: protobufs::Data, delay: Duration) -> Scheduled {
+        let packet = self.packet(from, SIMULATED_LOCAL_NODE, hops, data);
+        Scheduled {
+            delay,
+            frame: self.frame(from_radio::PayloadVariant::Packet(packet)),
+        }
+    }
+
+    fn packet(&mut self, from: u32, to: u32, hops: u32, data: protobufs::Data) -> MeshPacket {
+        self.next_packet_id = self.next_packet_id.wrapping_add(1).max(1);
+        let local = from == SIMULATED_LOCAL_NODE;
+        MeshPacket {
+            from,
+            to,
+            id: self.next_packet_id,
+            payload_variant: Some(mesh_packet::PayloadVariant::Decoded(data)),
+            rx_time: crate::time::unix_now() as u32,
+            rx_snr: if local { 0.0 } else { self.snr() },
+            rx_rssi: if local {
+                0
+            } else {
+                self.rng.random_range(-120..-60)
+            },
+            hop_start: HOP_LIMIT,
+            hop_limit: HOP_LIMIT.saturating_sub(hops),
+            ..Default::default()
+        }
+    }
+
+    fn frame(&mut self, payload_variant: from_radio::PayloadVariant) -> FromRadio {
+        let id = self.next_frame_id;
+        self.next_frame_id += 1;
+        FromRadio {
+            id,
+            payload_variant: Some(payload_variant),
+        }
+    }
+
+    fn position(&self, index: usize) -> protobufs::Position {
+        let (latitude, longitude) = self.positions[index];
+        protobufs::Position {
+            latitude_i: Some((latitude * 1e7) as i32),
+            longitude_i: Some((longitude * 1e7) as i32),
+            altitude: Some(30 + index as i32 * 5),
+            time: crate::time::unix_now() as u32,
+            ..Default::default()
+        }
+    }
+
+    fn telemetry(&mut self) -> protobufs::Telemetry {
+        protobufs::Telemetry {
+            time: crate::time::unix_now() as u32,
+            variant: Some(protobufs::telemetry::Variant::DeviceMetrics(
+                protobufs::DeviceMetrics {
+                    battery_level: Some(self.rng.random_range(20..=100)),
+                    voltage: Some(self.rng.random_range(3.5..4.2)),
+                    channel_utilization: Some(self.rng.random_range(0.0..25.0)),
+                    air_util_tx: Some(self.rng.random_range(0.0..5.0)),
+                    uptime_seconds: Some(self.started.elapsed().as_secs() as u32),
+                },
+            )),
+        }
+    }
+
+    fn snr(&mut self) -> f32 {
+        self.rng.random_range(-10.0..10.0)
+    }
+
+    /// Whether a packet survives `hops` relays plus the final hop
+    fn delivered(&mut self, hops: u32) -> bool {
+        (0..=hops).all(|_| !self.rng.random_bool(self.options.loss))
+    }
+}
+
+fn simulated_user(node: u32, index: usize) -> protobufs::User {
+    let (long_name, short_name) = if index == 0 {
+        ("Simulated Radio".to_string(), "SIM".to_string())
+    } else {
+        (format!("Sim Node {index}"), format!("S{index:02}"))
+    };
+    protobufs::User {
+        id: NodeId(node).to_string(),
+        long_name,
+        short_name,
+        hw_model: protobufs::HardwareModel::Portduino as i32,
+        ..Default::default()
+    }
+}
+
+fn simulated_channel(index: i32) -> protobufs::Channel {
+    if index == 0 {
+        protobufs::Channel {
+            index,
+            settings: Some(protobufs::ChannelSettings {
+                name: "Simulated".to_string(),
+                // The firmware's default key
+                psk: vec![1],
+                ..Default::default()
+            }),
+            role: protobufs::channel::Role::Primary as i32,
+        }
+    } else {
+        protobufs::Channel {
+            index,
+            settings: None,
+            role: protobufs::channel::Role::Disabled as i32,
+        }
+    }
+}
+
+fn lora_config() -> protobufs::Config {
+    protobufs::Config {
+        payload_variant: Some(protobufs::config::PayloadVariant::Lora(
+            protobufs::config::LoRaConfig {
+                use_preset: true,
+                modem_preset: protobufs::config::lo_ra_config::ModemPreset::LongFast as i32,
+                region: protobufs::config::lo_ra_config::RegionCode::Us as i32,
+                hop_limit: HOP_LIMIT,
+                tx_enabled: true,
+                tx_power: 20,
+                ..Default::default()
+            },
+        )),
+    }
+}
+
+fn device_config() -> protobufs::Config {
+    protobufs::Config {
+        payload_variant: Some(protobufs::config::PayloadVariant::Device(
+            protobufs::config::DeviceConfig {
+                role: protobufs::config::device_config::Role::Client as i32,
+                node_info_broadcast_secs: 10800,
+                ..Default::default()
+            },
+        )),
+    }
+}
+
+async fn write_frames<W: AsyncWrite + Unpin>(
+    mut writer: W,
+    mut frames: mpsc::UnboundedReceiver<FromRadio>,
+) {
+    while let Some(frame) = frames.recv().await {
+        let bytes = encode_frame(&frame.encode_to_vec());
+        if let Err(e) = writer.write_all(&bytes).await {
+            debug!("Simulated mesh lost its client: {e}");
+            break;
+        }
+        if let Err(e) = writer.flush().await {
+            debug!("Simulated mesh failed to flush: {e}");
+            break;
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use strum::Display;
+
+/// Number of recent response times the average is taken over
+const RESPONSE_TIME_SAMPLES: usize = 32;
+
+/// Counters of the current connection, shared with the tasks updating them
+///
+/// Cloning gives a handle that stays valid after the `ConnectionManager` is
+/// moved into a command, so the counters can be read once it is done. They
+/// restart at every `connect()`.
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionCounters {
+    pub(crate) bytes_read: Arc<AtomicU64>,
+    pub(crate) bytes_written: Arc<AtomicU64>,
+    pub(crate) frames_processed: Arc<AtomicU64>,
+    /// Unix time of the last frame processed, 0 before the first
+    pub(crate) last_frame_at: Arc<AtomicU64>,
+    /// Runs of bytes outside frames and impossible frame lengths
+    pub(crate) sync_errors: Arc<AtomicU64>,
+    /// Frames whose payload is not a FromRadio message
+    pub(crate) decode_errors: Arc<AtomicU64>,
+    responses: Arc<AtomicU64>,
+    timeouts: Arc<AtomicU64>,
+    /// Most recent response times, oldest first
+    response_times: Arc<Mutex<VecDeque<Duration>>>,
+    /// Frames queued for the radio writer and not yet written
+    pub(crate) send_queue: Arc<AtomicU64>,
+    /// Deepest the send queue got
+    pub(crate) send_queue_peak: Arc<AtomicU64>,
+    connect_micros: Arc<AtomicU64>,
+}
+
+impl ConnectionCounters {
+    pub(crate) fn reset(&self) {
+        for counter in [
+            &self.bytes_read,
+            &self.bytes_written,
+            &self.frames_processed,
+            &self.last_frame_at,
+            &self.sync_errors,
+            &self.decode_errors,
+            &self.responses,
+            &self.timeouts,
+            &self.send_queue_peak,
+            &self.connect_micros,
+        ] {
+            counter.store(0, Ordering::Relaxed);
+        }
+        if let Ok(mut times) = self.response_times.lock() {
+            times.clear();
+        }
+    }
+
+    /// A request, ACK or traceroute answered after `elapsed`
+    pub(crate) fn record_response(&self, elapsed: Duration) {
+        self.responses.fetch_add(1, Ordering::Relaxed);
+        if let Ok(mut times) = self.response_times.lock() {
+            if times.len() == RESPONSE_TIME_SAMPLES {
+                times.pop_front();
+            }
+            times.push_back(elapsed);
+        }
+    }
+
+    /// A request, ACK or traceroute that went unanswered
+    pub(crate) fn record_timeout(&self) {
+        self.timeouts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_connect_time(&self, elapsed: Duration) {
+        let micros = u64::try_from(elapsed.as_micros()).unwrap_or(u64::MAX);
+        self.connect_micros.store(micros, Ordering::Relaxed);
+    }
+
+    /// Frames waiting for the radio writer right now
+    pub fn send_queue_depth(&self) -> u64 {
+        self.send_queue.load(Ordering::Relaxed)
+    }
+
+    /// Unix time the last frame from the device was processed
+    pub fn last_frame_at(&self) -> Option<u64> {
+        Some(self.last_frame_at.load(Ordering::Relaxed)).filter(|time| *time > 0)
+    }
+
+    /// Values of the counters right now
+    pub fn snapshot(&self) -> ConnectionStats {
+        ConnectionStats {
+            connect_time: Duration::from_micros(self.connect_micros.load(Ordering::Relaxed)),
+            frames_processed: self.frames_processed.load(Ordering::Relaxed),
+            bytes_rx: self.bytes_read.load(Ordering::Relaxed),
+            bytes_tx: self.bytes_written.load(Ordering::Relaxed),
+            send_queue_peak: self.send_queue_peak.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Errors and successes of the link so far
+    pub fn quality(&self) -> LinkQuality {
+        let average_response_time = self.response_times.lock().ok().and_then(|times| {
+            let count = u32::try_from(times.len()).ok().filter(|count| *count > 0)?;
+            Some(times.iter().sum::<Duration>() / count)
+        });
+        LinkQuality::new(
+            self.frames_processed.load(Ordering::Relaxed),
+            self.sync_errors.load(Ordering::Relaxed),
+            self.decode_errors.load(Ordering::Relaxed),
+            self.responses.load(Ordering::Relaxed),
+            self.timeouts.load(Ordering::Relaxed),
+            average_response_time,
+        )
+    }
+}
+
+/// What the connection cost, for the `--stats` footer
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ConnectionStats {
+    /// Time from opening the link to the end of the config handshake
+    pub connect_time: Duration,
+    /// Frames from the radio handed to the packet processor, duplicates
+    /// excluded
+    pub frames_processed: u64,
+    /// Bytes read from the link, framing included
+    pub bytes_rx: u64,
+    /// Bytes written to the link, framing included
+    pub bytes_tx: u64,
+    /// Most frames waiting for the radio writer at once
+    pub send_queue_peak: u64,
+}
+
+/// Rating of a link by its error rate
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Display)]
+#[serde(rename_all = "snake_case")]
+pub enum QualityRating {
+    Excellent,
+    Good,
+    Fair,
+    Poor,
+}
+
+impl QualityRating {
+    /// Rating for a share of failed frames and requests, from 0 to 1
+    pub fn from_error_rate(error_rate: f64) -> Self {
+        match error_rate {
+            r if r < 0.01 => Self::Excellent,
+            r if r < 0.05 => Self::Good,
+            r if r < 0.10 => Self::Fair,
+            _ => Self::Poor,
+        }
+    }
+}
+
+/// How reliably the link carries frames and replies, for
+/// `info radio --quality`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct LinkQuality {
+    /// Frames from the radio handed to the packet processor
+    pub frames_processed: u64,
+    /// Runs of bytes outside frames, as from a noisy serial line
+    pub sync_errors: u64,
+    /// Frames that did not decode as FromRadio messages
+    pub decode_errors: u64,
+    /// Requests, ACKs and traceroutes answered in time
+    pub responses: u64,
+    /// Requests, ACKs and traceroutes that went unanswered
+    pub timeouts: u64,
+    /// Share of errors among all frames and requests, from 0 to 1
+    pub error_rate: f64,
+    /// Average over the most recent responses
+    pub average_response_time_ms: Option<u64>,
+    /// `None` until anything was received or requested
+    pub rating: Option<QualityRating>,
+}
+
+impl LinkQuality {
+    pub(crate) fn new(
+        frames_processed: u64,
+        sync_errors: u64,
+        decode_errors: u64,
+        responses: u64,
+        timeouts: u64,
+        average_response_time: Option<Duration>,
+    ) -> Self {
+        let mut quality = Self {
+            frames_processed,
+            sync_errors,
+            decode_errors,
+            responses,
+            timeouts,
+            average_response_time_ms: average_response_time
+                .map(|time| u64::try_from(time.as_millis()).unwrap_or(u64::MAX)),
+            ..Self::default()
+        };
+        let total = quality.errors() + quality.successes();
+        if total > 0 {
+            quality.error_rate = quality.errors() as f64 / total as f64;
+            quality.rating = Some(QualityRating::from_error_rate(quality.error_rate));
+        }
+        quality
+    }
+
+    /// Sync errors, decode errors and timeouts
+    pub fn errors(&self) -> u64 {
+        self.sync_errors + self.decode_errors + self.timeouts
+    }
+
+    /// Frames processed and responses received
+    pub fn successes(&self) -> u64 {
+        self.frames_processed + self.responses
+    }
+}
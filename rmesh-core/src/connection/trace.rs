@@ -0,0 +1,164 @@
+use meshtastic::protobufs::{self, FromRadio, MeshPacket, PortNum};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{Span, debug, info, info_span};
+
+use crate::node_id::NodeId;
+
+/// Target of packet trace spans and events, for log filters
+pub const PACKET_TRACE_TARGET: &str = "rmesh::packets";
+
+/// Sent packets older than this are no longer matched with responses
+const ROUND_TRIP_WINDOW: Duration = Duration::from_secs(600);
+
+/// Set by `--trace-packets` to log packet traffic at INFO instead of DEBUG
+static TRACE_PACKETS: AtomicBool = AtomicBool::new(false);
+
+/// Log every packet sent and every response with its round-trip time at
+/// INFO level
+pub fn set_trace_packets(enabled: bool) {
+    TRACE_PACKETS.store(enabled, Ordering::Relaxed);
+}
+
+pub fn trace_packets() -> bool {
+    TRACE_PACKETS.load(Ordering::Relaxed)
+}
+
+/// Log a packet trace event at INFO with `--trace-packets`, DEBUG otherwise
+macro_rules! packet_event {
+    ($($arg:tt)*) => {
+        if trace_packets() {
+            info!(target: PACKET_TRACE_TARGET, $($arg)*);
+        } else {
+            debug!(target: PACKET_TRACE_TARGET, $($arg)*);
+        }
+    };
+}
+
+#[derive(Debug, Clone, Copy)]
+struct SentPacket {
+    portnum: PortNum,
+    to: u32,
+    sent_at: Instant,
+}
+
+/// A sent packet matched with its reply
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RoundTrip {
+    pub portnum: PortNum,
+    pub to: u32,
+    pub elapsed: Duration,
+}
+
+/// Matches responses to the packets they answer to time radio round trips
+///
+/// Responses, ACKs and NAKs carry the id of the packet they answer as
+/// `request_id`.
+#[derive(Debug, Clone, Default)]
+pub struct PacketTracer {
+    sent: Arc<Mutex<HashMap<u32, SentPacket>>>,
+}
+
+impl PacketTracer {
+    /// Span covering the send of a packet
+    pub fn send_span(packet: &MeshPacket) -> Span {
+        info_span!(
+            target: PACKET_TRACE_TARGET,
+            "radio_send",
+            packet_id = packet.id,
+            portnum = ?portnum_of(packet),
+            to = %format_args!("{to}", to = NodeId(packet.to)),
+        )
+    }
+
+    /// Remember when a packet went out
+    pub fn record_sent(&self, packet: &MeshPacket) {
+        let portnum = portnum_of(packet);
+        packet_event!(
+            "Sending {portnum:?} packet {id} to {to}",
+            id = packet.id,
+            to = NodeId(packet.to)
+        );
+
+        let Ok(mut sent) = self.sent.lock() else {
+            return;
+        };
+        let now = Instant::now();
+        sent.retain(|_, packet| now.duration_since(packet.sent_at) < ROUND_TRIP_WINDOW);
+        sent.insert(
+            packet.id,
+            SentPacket {
+                portnum,
+                to: packet.to,
+                sent_at: now,
+            },
+        );
+    }
+
+    /// Span covering the processing of a received frame
+    ///
+    /// Frames other than mesh packets get no span.
+    pub fn receive_span(&self, frame: &FromRadio) -> Span {
+        let Some(protobufs::from_radio::PayloadVariant::Packet(packet)) = &frame.payload_variant
+        else {
+            return Span::none();
+        };
+        let request_id = decoded_request_id(packet);
+
+        let span = info_span!(
+            target: PACKET_TRACE_TARGET,
+            "radio_receive",
+            packet_id = packet.id,
+            portnum = ?portnum_of(packet),
+            from = %format_args!("{from}", from = NodeId(packet.from)),
+            request_id,
+        );
+
+        if let Some(round_trip) = self.complete(request_id) {
+            let _entered = span.enter();
+            packet_event!(
+                "{portnum:?} reply from {from} to {request:?} packet {request_id} \
+                 (sent to {to}) after {elapsed} ms",
+                portnum = portnum_of(packet),
+                from = NodeId(packet.from),
+                request = round_trip.portnum,
+                to = NodeId(round_trip.to),
+                elapsed = round_trip.elapsed.as_millis()
+            );
+        }
+        span
+    }
+
+    /// Match a reply to the packet it answers
+    ///
+    /// Only the first reply is matched; repeats and replies to packets sent
+    /// by other clients give `None`.
+    pub fn complete(&self, request_id: u32) -> Option<RoundTrip> {
+        if request_id == 0 {
+            return None;
+        }
+        let sent = self.sent.lock().ok()?.remove(&request_id)?;
+        Some(RoundTrip {
+            portnum: sent.portnum,
+            to: sent.to,
+            elapsed: sent.sent_at.elapsed(),
+        })
+    }
+}
+
+/// Port of a packet, `UnknownApp` for encrypted ones
+fn portnum_of(packet: &MeshPacket) -> PortNum {
+    match &packet.payload_variant {
+        Some(protobufs::mesh_packet::PayloadVariant::Decoded(data)) => data.portnum(),
+        _ => PortNum::UnknownApp,
+    }
+}
+
+fn decoded_request_id(packet: &MeshPacket) -> u32 {
+    match &packet.payload_variant {
+        Some(protobufs::mesh_packet::PayloadVariant::Decoded(data)) => data.request_id,
+        _ => 0,
+    }
+}
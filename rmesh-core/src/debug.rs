@@ -0,0 +1,88 @@
+use crate::connection::ConnectionManager;
+use anyhow::{Context, Result, bail};
+use meshtastic::{Message, protobufs};
+use tracing::debug;
+
+/// First two bytes of every frame on the serial and TCP streams
+const STREAM_MAGIC: [u8; 2] = [0x94, 0xc3];
+
+/// Parse hex bytes, ignoring whitespace, `:` separators and a `0x` prefix
+///
+/// Accepts the formats of common packet captures and hex dumps, such as
+/// `0a0b10`, `0x0a0b10`, `0a 0b 10` and `0a:0b:10`.
+pub fn parse_hex(input: &str) -> Result<Vec<u8>> {
+    let input = input.trim();
+    let input = input
+        .strip_prefix("0x")
+        .or_else(|| input.strip_prefix("0X"))
+        .unwrap_or(input);
+    let digits: String = input
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != ':')
+        .collect();
+
+    if digits.is_empty() {
+        bail!("No hex bytes given");
+    }
+    hex::decode(&digits).with_context(|| format!("Invalid hex bytes '{digits}'"))
+}
+
+/// Strip the stream frame header, if any, from captured bytes
+///
+/// Frames captured from the serial or TCP stream start with a magic number
+/// and a length, which is checked and stripped.
+fn strip_frame_header(bytes: &[u8]) -> Result<&[u8]> {
+    match bytes.strip_prefix(STREAM_MAGIC.as_slice()) {
+        Some([len_high, len_low, payload @ ..]) => {
+            let len = usize::from(u16::from_be_bytes([*len_high, *len_low]));
+            if len != payload.len() {
+                bail!(
+                    "Frame header announces {len} bytes but {actual} follow",
+                    actual = payload.len()
+                );
+            }
+            Ok(payload)
+        }
+        Some(_) => bail!("Frame header is truncated"),
+        None => Ok(bytes),
+    }
+}
+
+/// Decode a ToRadio message, with or without the stream frame header
+pub fn decode_to_radio(bytes: &[u8]) -> Result<protobufs::ToRadio> {
+    let to_radio = protobufs::ToRadio::decode(strip_frame_header(bytes)?)
+        .context("Bytes are not a ToRadio message")?;
+    if to_radio.payload_variant.is_none() {
+        bail!("ToRadio message is empty");
+    }
+    Ok(to_radio)
+}
+
+/// Decode a FromRadio message, with or without the stream frame header
+pub fn decode_from_radio(bytes: &[u8]) -> Result<protobufs::FromRadio> {
+    let from_radio = protobufs::FromRadio::decode(strip_frame_header(bytes)?)
+        .context("Bytes are not a FromRadio message")?;
+    if from_radio.payload_variant.is_none() {
+        bail!("FromRadio message is empty");
+    }
+    Ok(from_radio)
+}
+
+/// Send a ToRadio message to the device as is
+///
+/// No packet id, hop limit or session key is filled in, so the frame reaches
+/// the firmware exactly as given.
+pub async fn send_to_radio(
+    connection: &mut ConnectionManager,
+    to_radio: protobufs::ToRadio,
+) -> Result<()> {
+    debug!("Sending raw ToRadio: {to_radio:?}");
+    let payload = to_radio
+        .payload_variant
+        .context("ToRadio message has no payload")?;
+    connection
+        .send_to_radio(payload)
+        .await
+        .context("Failed to send ToRadio message")?;
+    Ok(())
+}
@@ -1,6 +1,107 @@
-use crate::connection::ConnectionManager;
-use anyhow::Result;
+use crate::admin::{AdminDestination, BROADCAST_NODE_NUM, request_admin, send_admin_message};
+use crate::connection::{ConnectionManager, LinkQuality, RequestResponse};
+use crate::events::MeshEvent;
+use crate::lora::{FrequencySlot, primary_frequency_slot};
+use crate::node_id::NodeId;
+use anyhow::{Context, Result, bail};
 use meshtastic::{Message, protobufs};
+use serde::Serialize;
+use std::time::Duration;
+use strum::Display;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::{Instant, timeout_at};
+use tracing::debug;
+
+/// Summary of the connected radio
+#[derive(Debug, Clone, Serialize)]
+pub struct RadioInfo {
+    pub firmware_version: String,
+    pub hardware_model: String,
+    pub region: String,
+    pub node_id: String,
+    pub node_num: u32,
+    pub has_gps: bool,
+    pub num_channels: usize,
+    /// PlatformIO environment the firmware was built for
+    pub platform: Option<String>,
+    /// Hex device id, when the hardware reports one
+    pub device_id: Option<String>,
+    /// Frequency of the primary channel, when the LoRa settings are known
+    pub frequency: Option<FrequencySlot>,
+    /// Link error and success counts, filled in by `info radio --quality`
+    pub quality: Option<LinkQuality>,
+}
+
+/// Summarize the connected radio from the cached device state
+pub async fn get_radio_info(connection: &ConnectionManager) -> RadioInfo {
+    let state = connection.get_device_state().await;
+
+    // Prefer the version from the device metadata, falling back to
+    // min_app_version for firmware that does not send it
+    let firmware_version = if let Some(metadata) = &state.metadata {
+        metadata.firmware_version.clone()
+    } else if let Some(my_info) = &state.my_node_info {
+        let major = my_info.min_app_version / 10000;
+        let minor = (my_info.min_app_version % 10000) / 100;
+        let patch = my_info.min_app_version % 100;
+        format!("{major}.{minor}.{patch}")
+    } else {
+        "Unknown".to_string()
+    };
+
+    // Get hardware model from nodes (typically the local node has this info)
+    let hardware_model = if let Some(my_info) = &state.my_node_info {
+        state
+            .nodes
+            .get(&my_info.node_num)
+            .and_then(|node| node.user.hw_model.clone())
+            .unwrap_or_else(|| "Unknown".to_string())
+    } else {
+        "Unknown".to_string()
+    };
+
+    // Get region from LoRa config
+    let region = state
+        .lora_config
+        .as_ref()
+        .map(|cfg| cfg.region.clone())
+        .unwrap_or_else(|| "Unknown".to_string());
+
+    // Get node ID and number from my_node_info
+    let (node_id, node_num) = if let Some(my_info) = &state.my_node_info {
+        (my_info.node_id.clone(), my_info.node_num)
+    } else {
+        ("Unknown".to_string(), 0)
+    };
+
+    // Check GPS status from position config
+    let has_gps = state
+        .position_config
+        .as_ref()
+        .map(|cfg| cfg.gps_enabled)
+        .unwrap_or_default();
+
+    let my_info = state.my_node_info.as_ref();
+    RadioInfo {
+        firmware_version,
+        hardware_model,
+        region,
+        node_id,
+        node_num,
+        has_gps,
+        num_channels: state.channels.len(),
+        platform: my_info.and_then(|info| info.pio_env.clone()),
+        // Hardware without a unique id reports none, or all zeros
+        device_id: my_info
+            .map(|info| info.device_id.clone())
+            .filter(|id| id.chars().any(|c| c != '0')),
+        frequency: state
+            .lora_config
+            .as_ref()
+            .and_then(|lora| primary_frequency_slot(lora, &state.channels)),
+        quality: None,
+    }
+}
 
 /// Reboot the connected Meshtastic device
 ///
@@ -17,7 +118,6 @@ pub async fn reboot_device(
     // Get the session key
     let session_key = connection.get_session_key().await.unwrap_or_default();
 
-    let api = connection.get_api()?;
     let delay = delay_seconds.unwrap_or(5);
 
     // Create admin message for reboot
@@ -52,63 +152,51 @@ pub async fn reboot_device(
     };
 
     // Send as ToRadio packet
-    api.send_to_radio_packet(Some(protobufs::to_radio::PayloadVariant::Packet(
-        mesh_packet,
-    )))
-    .await?;
+    connection.send_mesh_packet(mesh_packet).await?;
 
     Ok(())
 }
 
+/// What a factory reset erases
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum FactoryResetScope {
+    /// Restore default config and channels, keeping the node database and keys
+    Config,
+    /// Erase everything, including the node database and device keys
+    Device,
+}
+
 /// Factory reset the connected Meshtastic device
 ///
 /// # Warning
 /// This will erase all device settings and cannot be undone!
 pub async fn factory_reset_device(connection: &mut ConnectionManager) -> Result<()> {
+    factory_reset(connection, FactoryResetScope::Device).await
+}
+
+/// Factory reset the connected device, erasing either config only or everything
+///
+/// # Warning
+/// This cannot be undone!
+pub async fn factory_reset(
+    connection: &mut ConnectionManager,
+    scope: FactoryResetScope,
+) -> Result<()> {
     // Ensure we have a session key for admin operations
     connection.ensure_session_key().await?;
 
-    // Get the session key
-    let session_key = connection.get_session_key().await.unwrap_or_default();
-
-    let api = connection.get_api()?;
-
-    // Create admin message for factory reset
-    let admin_msg = protobufs::AdminMessage {
-        payload_variant: Some(protobufs::admin_message::PayloadVariant::FactoryResetDevice(1)),
-        session_passkey: session_key,
+    let payload = match scope {
+        FactoryResetScope::Config => {
+            protobufs::admin_message::PayloadVariant::FactoryResetConfig(1)
+        }
+        FactoryResetScope::Device => {
+            protobufs::admin_message::PayloadVariant::FactoryResetDevice(1)
+        }
     };
 
-    // Create mesh packet
-    let mesh_packet = protobufs::MeshPacket {
-        payload_variant: Some(protobufs::mesh_packet::PayloadVariant::Decoded(
-            protobufs::Data {
-                portnum: protobufs::PortNum::AdminApp as i32,
-                payload: admin_msg.encode_to_vec(),
-                ..Default::default()
-            },
-        )),
-        from: 0,
-        to: 0, // Local destination
-        id: 0,
-        rx_time: 0,
-        rx_snr: 0.0,
-        hop_limit: 0,
-        want_ack: false,
-        priority: protobufs::mesh_packet::Priority::Default as i32,
-        rx_rssi: 0,
-        via_mqtt: false,
-        hop_start: 0,
-        ..Default::default()
-    };
-
-    // Send as ToRadio packet
-    api.send_to_radio_packet(Some(protobufs::to_radio::PayloadVariant::Packet(
-        mesh_packet,
-    )))
-    .await?;
-
-    Ok(())
+    send_admin_message(connection, AdminDestination::Local, payload).await
 }
 
 /// Shutdown the connected Meshtastic device
@@ -126,7 +214,6 @@ pub async fn shutdown_device(
     // Get the session key
     let session_key = connection.get_session_key().await.unwrap_or_default();
 
-    let api = connection.get_api()?;
     let delay = delay_seconds.unwrap_or(5);
 
     // Create admin message for shutdown
@@ -161,10 +248,177 @@ pub async fn shutdown_device(
     };
 
     // Send as ToRadio packet
-    api.send_to_radio_packet(Some(protobufs::to_radio::PayloadVariant::Packet(
-        mesh_packet,
-    )))
-    .await?;
+    connection.send_mesh_packet(mesh_packet).await?;
 
     Ok(())
 }
+
+/// Clear the node database of the local node or a remote one
+///
+/// The node forgets every other node it has heard and reboots.
+pub async fn reset_node_db(
+    connection: &mut ConnectionManager,
+    destination: AdminDestination,
+) -> Result<()> {
+    send_admin_message(
+        connection,
+        destination,
+        protobufs::admin_message::PayloadVariant::NodedbReset(1),
+    )
+    .await
+}
+
+/// Remove the fixed position so the node goes back to its GPS (if any)
+pub async fn remove_fixed_position(
+    connection: &mut ConnectionManager,
+    destination: AdminDestination,
+) -> Result<()> {
+    send_admin_message(
+        connection,
+        destination,
+        protobufs::admin_message::PayloadVariant::RemoveFixedPosition(true),
+    )
+    .await
+}
+
+/// How long the local node gets to answer an owner request
+pub const OWNER_REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A NodeInfo broadcast sent on behalf of the local node
+#[derive(Debug, Clone, Serialize)]
+pub struct NodeAnnouncement {
+    pub node_id: String,
+    pub long_name: String,
+    pub short_name: String,
+    /// Destination, `!ffffffff` for every node
+    pub to: String,
+    /// Whether the receivers were asked to answer with their own NodeInfo
+    pub want_response: bool,
+}
+
+/// Ask the local node for its owner, the user it announces to the mesh
+pub async fn get_owner(
+    connection: &mut ConnectionManager,
+    timeout: Duration,
+) -> Result<protobufs::User> {
+    let pending = request_admin(
+        connection,
+        AdminDestination::Local,
+        protobufs::admin_message::PayloadVariant::GetOwnerRequest(true),
+    )
+    .await?;
+    debug!("Sent owner request");
+
+    match pending.wait(timeout).await {
+        Some(RequestResponse::Owner(user)) => Ok(user),
+        Some(other) => bail!("Unexpected response to owner request: {other:?}"),
+        None => bail!(
+            "No response to owner request within {secs}s",
+            secs = timeout.as_secs()
+        ),
+    }
+}
+
+/// Build the mesh packet carrying a NodeInfo for `to`
+pub fn node_info_packet(
+    user: &protobufs::User,
+    to: u32,
+    packet_id: u32,
+    want_response: bool,
+) -> protobufs::MeshPacket {
+    protobufs::MeshPacket {
+        payload_variant: Some(protobufs::mesh_packet::PayloadVariant::Decoded(
+            protobufs::Data {
+                portnum: protobufs::PortNum::NodeinfoApp as i32,
+                payload: user.encode_to_vec(),
+                want_response,
+                ..Default::default()
+            },
+        )),
+        to,
+        id: packet_id,
+        ..Default::default()
+    }
+}
+
+/// Send the local node's NodeInfo now instead of at the next scheduled
+/// broadcast, so peers pick up a new name quickly
+///
+/// The owner is read from the node first, so the announcement carries
+/// exactly what the firmware would send. With `want_response`, receivers
+/// answer with their own NodeInfo, as the apps' user info exchange does.
+pub async fn announce_node_info(
+    connection: &mut ConnectionManager,
+    dest: Option<u32>,
+    want_response: bool,
+) -> Result<NodeAnnouncement> {
+    let user = get_owner(connection, OWNER_REQUEST_TIMEOUT).await?;
+    let node_num = connection
+        .get_device_state()
+        .await
+        .my_node_info
+        .map(|info| info.node_num)
+        .context("The device has not reported its node number")?;
+
+    let to = dest.unwrap_or(BROADCAST_NODE_NUM);
+    let packet = node_info_packet(&user, to, connection.next_packet_id(), want_response);
+    connection.send_mesh_packet(packet).await?;
+    debug!("Sent NodeInfo to {to}", to = NodeId(to));
+
+    Ok(NodeAnnouncement {
+        node_id: NodeId(node_num).to_string(),
+        long_name: user.long_name,
+        short_name: user.short_name,
+        to: NodeId(to).to_string(),
+        want_response,
+    })
+}
+
+/// Outcome of asking the mesh for a NodeInfo exchange
+#[derive(Debug, Clone, Serialize)]
+pub struct NodeRefresh {
+    pub announcement: NodeAnnouncement,
+    /// Ids of the nodes heard from while waiting, in the order they answered
+    pub updated: Vec<String>,
+}
+
+/// Broadcast the local NodeInfo asking every node to answer with theirs,
+/// then collect NodeInfo updates for `wait`
+///
+/// Fresh devices otherwise only learn about a node at its next periodic
+/// broadcast, which can be hours away. Replies keep arriving after the wait,
+/// and are added to the node list as usual.
+pub async fn refresh_nodes(
+    connection: &mut ConnectionManager,
+    wait: Duration,
+) -> Result<NodeRefresh> {
+    // Subscribe before sending so no quick reply is missed
+    let mut events = connection.subscribe_events();
+    let announcement = announce_node_info(connection, None, true).await?;
+    let local_node = connection
+        .get_device_state()
+        .await
+        .my_node_info
+        .map(|info| info.node_num);
+
+    let mut updated = Vec::new();
+    let deadline = Instant::now() + wait;
+    loop {
+        match timeout_at(deadline, events.recv()).await {
+            Ok(Ok(MeshEvent::NodeUpdated(node))) => {
+                let id = NodeId(node.num).to_string();
+                if Some(node.num) != local_node && !updated.contains(&id) {
+                    debug!("NodeInfo from {id} after refresh");
+                    updated.push(id);
+                }
+            }
+            Ok(Ok(_)) | Ok(Err(RecvError::Lagged(_))) => {}
+            Ok(Err(RecvError::Closed)) | Err(_) => break,
+        }
+    }
+
+    Ok(NodeRefresh {
+        announcement,
+        updated,
+    })
+}
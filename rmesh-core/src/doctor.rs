@@ -0,0 +1,489 @@
+use crate::connection::discovery::{self, DeviceCandidate, PortTarget};
+use crate::connection::{ConnectionManager, HandshakeOptions};
+use serde::Serialize;
+use std::path::Path;
+use std::time::Duration;
+use strum::Display;
+use tracing::debug;
+
+/// Outcome of one diagnostic check
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum CheckStatus {
+    Ok,
+    /// Not relevant to this connection or could not be checked
+    Skipped,
+    /// Likely to cause trouble, but not necessarily fatal
+    Warning,
+    /// Will stop rmesh from reaching the device
+    Failed,
+}
+
+/// Result of one diagnostic check, with a suggested fix when it did not pass
+#[derive(Debug, Clone, Serialize)]
+pub struct DoctorCheck {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub detail: String,
+    pub fix: Option<String>,
+}
+
+impl DoctorCheck {
+    fn ok(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: CheckStatus::Ok,
+            detail: detail.into(),
+            fix: None,
+        }
+    }
+
+    fn skipped(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: CheckStatus::Skipped,
+            detail: detail.into(),
+            fix: None,
+        }
+    }
+
+    fn problem(
+        name: &'static str,
+        status: CheckStatus,
+        detail: impl Into<String>,
+        fix: impl Into<String>,
+    ) -> Self {
+        Self {
+            name,
+            status,
+            detail: detail.into(),
+            fix: Some(fix.into()),
+        }
+    }
+}
+
+/// All diagnostic checks of a `doctor` run, in the order they ran
+#[derive(Debug, Clone, Serialize)]
+pub struct DoctorReport {
+    /// Port or Bluetooth device the checks were run against
+    pub target: Option<String>,
+    pub checks: Vec<DoctorCheck>,
+}
+
+impl DoctorReport {
+    /// Worst status of any check
+    pub fn status(&self) -> CheckStatus {
+        self.checks
+            .iter()
+            .map(|check| check.status)
+            .max()
+            .unwrap_or(CheckStatus::Ok)
+    }
+}
+
+/// Check the environment for common connection problems, then try a handshake
+///
+/// Serial checks run against `port`, or the most likely discovered port when
+/// neither a port nor a Bluetooth device is given.
+pub async fn run_doctor(port: Option<&str>, ble: Option<&str>, timeout: Duration) -> DoctorReport {
+    let mut checks = Vec::new();
+
+    let serial_port = match (port, ble) {
+        (_, Some(_)) => {
+            checks.push(DoctorCheck::skipped(
+                "serial ports",
+                "Connecting over Bluetooth",
+            ));
+            None
+        }
+        (Some(port), None) => match discovery::parse_port(port) {
+            PortTarget::Tcp(address) => {
+                checks.push(DoctorCheck::skipped(
+                    "serial ports",
+                    format!("Connecting over TCP to {address}"),
+                ));
+                None
+            }
+            PortTarget::Unix(path) => {
+                checks.push(check_socket_exists(&path));
+                None
+            }
+            PortTarget::Serial(port) => {
+                checks.push(check_port_exists(&port));
+                Some(port)
+            }
+        },
+        (None, None) => {
+            let (check, candidate) = check_discovery();
+            checks.push(check);
+            candidate.map(|candidate| candidate.port)
+        }
+    };
+
+    if let Some(port) = serial_port
+        .as_deref()
+        .filter(|port| Path::new(port).exists())
+    {
+        checks.push(check_permissions(port));
+        checks.push(check_port_busy(port));
+        checks.push(check_modem_manager());
+    }
+    checks.push(check_bluetooth(ble.is_some()));
+
+    let target = ble
+        .map(str::to_string)
+        .or_else(|| serial_port.clone())
+        .or_else(|| port.map(str::to_string));
+    let blocked = checks
+        .iter()
+        .any(|check| check.status == CheckStatus::Failed);
+    if blocked {
+        checks.push(DoctorCheck::skipped(
+            "handshake",
+            "Skipped until the problems above are fixed",
+        ));
+    } else {
+        let port = serial_port.or_else(|| port.map(str::to_string));
+        checks.extend(check_handshake(port, ble.map(str::to_string), timeout).await);
+    }
+
+    DoctorReport { target, checks }
+}
+
+fn check_port_exists(port: &str) -> DoctorCheck {
+    if Path::new(port).exists() {
+        DoctorCheck::ok("serial ports", format!("{port} exists"))
+    } else {
+        DoctorCheck::problem(
+            "serial ports",
+            CheckStatus::Failed,
+            format!("{port} does not exist"),
+            "Check the port name, or leave out --port to auto-detect the device",
+        )
+    }
+}
+
+fn check_socket_exists(path: &Path) -> DoctorCheck {
+    if path.exists() {
+        DoctorCheck::ok(
+            "meshtasticd",
+            format!("{path} exists", path = path.display()),
+        )
+    } else {
+        DoctorCheck::problem(
+            "meshtasticd",
+            CheckStatus::Failed,
+            format!("{path} does not exist", path = path.display()),
+            "Start meshtasticd (e.g. `systemctl start meshtasticd`), or connect over TCP \
+             with --port localhost:4403",
+        )
+    }
+}
+
+fn check_discovery() -> (DoctorCheck, Option<DeviceCandidate>) {
+    let candidates = match discovery::discover_devices() {
+        Ok(candidates) => candidates,
+        Err(e) => {
+            return (
+                DoctorCheck::problem(
+                    "serial ports",
+                    CheckStatus::Failed,
+                    format!("Could not list serial ports: {e:#}"),
+                    "Pass the port with --port",
+                ),
+                None,
+            );
+        }
+    };
+
+    let Some(best) = candidates.first().cloned() else {
+        return (
+            DoctorCheck::problem(
+                "serial ports",
+                CheckStatus::Failed,
+                "No USB serial ports found",
+                "Connect the device with a data-capable USB cable (charge-only cables are \
+                 common) and check `dmesg` for a new ttyUSB or ttyACM device",
+            ),
+            None,
+        );
+    };
+
+    let detail = format!(
+        "Found {count} candidate(s); using {port} ({description})",
+        count = candidates.len(),
+        port = best.port,
+        description = best.description.as_deref().unwrap_or("unknown device")
+    );
+    (DoctorCheck::ok("serial ports", detail), Some(best))
+}
+
+#[cfg(unix)]
+fn check_permissions(port: &str) -> DoctorCheck {
+    use std::os::unix::fs::MetadataExt;
+
+    const NAME: &str = "permissions";
+    let metadata = match std::fs::metadata(port) {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            return DoctorCheck::problem(
+                NAME,
+                CheckStatus::Failed,
+                format!("Cannot read {port}: {e}"),
+                "Check that the device is still connected",
+            );
+        }
+    };
+    let Some(ids) = std::fs::read_to_string("/proc/self/status")
+        .ok()
+        .and_then(|status| parse_process_ids(&status))
+    else {
+        return DoctorCheck::skipped(NAME, "User groups are only checked on Linux");
+    };
+
+    let mode = metadata.mode();
+    let read_write = if ids.uid == 0 {
+        true
+    } else if ids.uid == metadata.uid() {
+        mode & 0o600 == 0o600
+    } else if ids.gids.contains(&metadata.gid()) {
+        mode & 0o060 == 0o060
+    } else {
+        mode & 0o006 == 0o006
+    };
+    if read_write {
+        return DoctorCheck::ok(NAME, format!("{port} is readable and writable"));
+    }
+
+    let group = std::fs::read_to_string("/etc/group")
+        .ok()
+        .and_then(|groups| group_name(&groups, metadata.gid()))
+        .unwrap_or_else(|| "dialout".to_string());
+    DoctorCheck::problem(
+        NAME,
+        CheckStatus::Failed,
+        format!("No read/write access to {port}, which belongs to the {group} group"),
+        format!("Run `sudo usermod -aG {group} $USER`, then log out and back in"),
+    )
+}
+
+#[cfg(not(unix))]
+fn check_permissions(_port: &str) -> DoctorCheck {
+    DoctorCheck::skipped("permissions", "Only checked on Linux")
+}
+
+/// Processes other than this one holding the port open
+fn check_port_busy(port: &str) -> DoctorCheck {
+    const NAME: &str = "port busy";
+    let Ok(target) = Path::new(port).canonicalize() else {
+        return DoctorCheck::skipped(NAME, format!("Could not resolve {port}"));
+    };
+    let Ok(processes) = std::fs::read_dir("/proc") else {
+        return DoctorCheck::skipped(NAME, "Only checked on Linux");
+    };
+
+    let own_pid = std::process::id();
+    let holders: Vec<String> = processes
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok())
+        .filter(|pid| *pid != own_pid)
+        .filter(|pid| {
+            // Processes of other users cannot be inspected and are skipped
+            std::fs::read_dir(format!("/proc/{pid}/fd")).is_ok_and(|fds| {
+                fds.flatten()
+                    .any(|fd| std::fs::read_link(fd.path()).is_ok_and(|link| link == target))
+            })
+        })
+        .map(|pid| {
+            let name = process_name(pid).unwrap_or_else(|| "unknown".to_string());
+            format!("{name} ({pid})")
+        })
+        .collect();
+
+    if holders.is_empty() {
+        DoctorCheck::ok(NAME, format!("No other process has {port} open"))
+    } else {
+        DoctorCheck::problem(
+            NAME,
+            CheckStatus::Failed,
+            format!("{port} is open in {holders}", holders = holders.join(", ")),
+            "Close the other program (serial monitors, the Python CLI, a running rmesh) first",
+        )
+    }
+}
+
+/// ModemManager probes new serial ports as modems, garbling the handshake
+fn check_modem_manager() -> DoctorCheck {
+    const NAME: &str = "modem manager";
+    let Ok(processes) = std::fs::read_dir("/proc") else {
+        return DoctorCheck::skipped(NAME, "Only checked on Linux");
+    };
+
+    let running = processes
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok())
+        .any(|pid| process_name(pid).is_some_and(|name| name == "ModemManager"));
+    if running {
+        DoctorCheck::problem(
+            NAME,
+            CheckStatus::Warning,
+            "ModemManager is running and may grab the port when the device is plugged in",
+            "Run `sudo systemctl disable --now ModemManager`, or add a udev rule setting \
+             ENV{ID_MM_DEVICE_IGNORE}=\"1\" for the device",
+        )
+    } else {
+        DoctorCheck::ok(NAME, "ModemManager is not running")
+    }
+}
+
+fn check_bluetooth(requested: bool) -> DoctorCheck {
+    const NAME: &str = "bluetooth";
+    if requested && !cfg!(feature = "bluetooth") {
+        return DoctorCheck::problem(
+            NAME,
+            CheckStatus::Failed,
+            "This build has no Bluetooth support",
+            "Rebuild with `--features bluetooth`",
+        );
+    }
+
+    let adapters: Vec<String> = std::fs::read_dir("/sys/class/bluetooth")
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter_map(|entry| entry.file_name().to_str().map(str::to_string))
+                .filter(|name| name.starts_with("hci") && !name.contains(':'))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    match (adapters.is_empty(), requested) {
+        (false, _) => DoctorCheck::ok(
+            NAME,
+            format!(
+                "Adapter(s) found: {adapters}",
+                adapters = adapters.join(", ")
+            ),
+        ),
+        (true, true) => DoctorCheck::problem(
+            NAME,
+            CheckStatus::Failed,
+            "No Bluetooth adapter found",
+            "Plug in or enable a Bluetooth adapter (`rfkill unblock bluetooth`), and start \
+             the bluetooth service",
+        ),
+        (true, false) => DoctorCheck::skipped(NAME, "No adapter found; only needed for --ble"),
+    }
+}
+
+/// Run one handshake attempt, then check the reported firmware
+async fn check_handshake(
+    port: Option<String>,
+    ble: Option<String>,
+    timeout: Duration,
+) -> Vec<DoctorCheck> {
+    const NAME: &str = "handshake";
+    let connection = ConnectionManager::new(port, ble, timeout).await;
+    let mut connection = match connection {
+        Ok(connection) => connection,
+        Err(e) => {
+            return vec![DoctorCheck::problem(
+                NAME,
+                CheckStatus::Failed,
+                format!("{e:#}"),
+                "Check the --port or --ble value",
+            )];
+        }
+    };
+    connection.set_handshake_options(HandshakeOptions {
+        timeout,
+        attempts: 1,
+    });
+
+    if let Err(e) = connection.connect().await {
+        return vec![DoctorCheck::problem(
+            NAME,
+            CheckStatus::Failed,
+            format!("{e:#}"),
+            "Make sure the device runs Meshtastic firmware and has finished booting; \
+             press its reset button, or try a longer --handshake-timeout",
+        )];
+    }
+
+    let state = connection.get_device_state().await;
+    if let Err(e) = connection.disconnect().await {
+        debug!("Failed to close doctor connection: {e}");
+    }
+
+    let firmware = crate::firmware::firmware_version(&state).unwrap_or("unknown");
+    let mut checks = vec![DoctorCheck::ok(
+        NAME,
+        format!(
+            "Device answered ({nodes} node(s), firmware {firmware})",
+            nodes = state.nodes.len()
+        ),
+    )];
+
+    let issues = crate::firmware::check_versions(&state);
+    if issues.is_empty() {
+        checks.push(DoctorCheck::ok(
+            "firmware",
+            format!(
+                "Matches the {bundled} protobufs rmesh was built with",
+                bundled = crate::firmware::BUNDLED_PROTOBUF_VERSION
+            ),
+        ));
+    }
+    for issue in issues {
+        checks.push(DoctorCheck::problem(
+            "firmware",
+            CheckStatus::Warning,
+            issue.to_string(),
+            "Update the device firmware or rmesh so both use the same release",
+        ));
+    }
+    checks
+}
+
+/// Effective user id and the groups of a process
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ProcessIds {
+    pub uid: u32,
+    pub gids: Vec<u32>,
+}
+
+/// Read ids from the contents of `/proc/<pid>/status`
+pub(crate) fn parse_process_ids(status: &str) -> Option<ProcessIds> {
+    // "Uid:" and "Gid:" list the real, effective, saved and filesystem ids
+    let ids = |key: &str| -> Option<Vec<u32>> {
+        let line = status.lines().find(|line| line.starts_with(key))?;
+        Some(
+            line.trim_start_matches(key)
+                .split_whitespace()
+                .filter_map(|id| id.parse().ok())
+                .collect(),
+        )
+    };
+
+    let uid = *ids("Uid:")?.get(1)?;
+    let mut gids = ids("Groups:").unwrap_or_default();
+    gids.extend(ids("Gid:")?.get(1));
+    Some(ProcessIds { uid, gids })
+}
+
+/// Name of a group id in the contents of `/etc/group`
+pub(crate) fn group_name(groups: &str, gid: u32) -> Option<String> {
+    groups.lines().find_map(|line| {
+        let mut fields = line.split(':');
+        let name = fields.next()?;
+        let id = fields.nth(1)?.parse::<u32>().ok()?;
+        (id == gid).then(|| name.to_string())
+    })
+}
+
+fn process_name(pid: u32) -> Option<String> {
+    std::fs::read_to_string(format!("/proc/{pid}/comm"))
+        .ok()
+        .map(|name| name.trim().to_string())
+}
@@ -0,0 +1,206 @@
+use crate::node_id::NodeId;
+use crate::state::{ChangeRecord, MeshChange, NodeInfo, Position, TelemetryData, TextMessage};
+use meshtastic::protobufs::PortNum;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+
+/// Number of events buffered per subscriber before the oldest are dropped
+pub const EVENT_CHANNEL_CAPACITY: usize = 256;
+
+/// State changes published by the packet processor as they happen
+///
+/// Subscribe with [`crate::ConnectionManager::subscribe_events`], or with
+/// [`crate::ConnectionManager::subscribe_filtered`] for only some ports or
+/// nodes. Slow subscribers miss the oldest events rather than blocking
+/// packet processing.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MeshEvent {
+    /// A text message was received
+    TextMessage(TextMessage),
+    /// A node reported a new position
+    PositionUpdated(Position),
+    /// A node reported telemetry
+    TelemetryUpdated(TelemetryData),
+    /// Node information was received or refreshed
+    NodeUpdated(NodeInfo),
+    /// A packet we sent was acknowledged
+    Ack(RoutingReport),
+    /// A packet we sent could not be delivered
+    Nak(RoutingReport),
+    /// A routing error not tied to a specific packet
+    RoutingError(RoutingReport),
+    /// The device restarted; cached configuration was dropped and is resynced
+    /// on the next request
+    DeviceRebooted {
+        /// New reboot count, when the reboot was seen in the node info
+        reboot_count: Option<u32>,
+    },
+    /// A change was added to the change journal
+    MeshChanged(ChangeRecord),
+}
+
+impl MeshEvent {
+    /// Port of the packet the event came from, `None` for device events
+    pub fn port(&self) -> Option<PortNum> {
+        match self {
+            Self::TextMessage(_) => Some(PortNum::TextMessageApp),
+            Self::PositionUpdated(_) => Some(PortNum::PositionApp),
+            Self::TelemetryUpdated(_) => Some(PortNum::TelemetryApp),
+            Self::NodeUpdated(_) => Some(PortNum::NodeinfoApp),
+            Self::Ack(_) | Self::Nak(_) | Self::RoutingError(_) => Some(PortNum::RoutingApp),
+            Self::DeviceRebooted { .. } | Self::MeshChanged(_) => None,
+        }
+    }
+
+    /// Node the event is about, `None` for device and channel events
+    pub fn node(&self) -> Option<u32> {
+        match self {
+            Self::TextMessage(message) => Some(message.from_node),
+            Self::PositionUpdated(position) => Some(position.node_num),
+            Self::TelemetryUpdated(telemetry) => Some(telemetry.node_num),
+            Self::NodeUpdated(node) => Some(node.num),
+            Self::Ack(report) | Self::Nak(report) | Self::RoutingError(report) => {
+                Some(report.from_node)
+            }
+            Self::MeshChanged(record) => match &record.change {
+                MeshChange::NodeJoined { node, .. }
+                | MeshChange::NodeRenamed { node, .. }
+                | MeshChange::RoleChanged { node, .. } => {
+                    node.parse::<NodeId>().ok().map(NodeId::num)
+                }
+                MeshChange::ChannelChanged { .. } | MeshChange::ConfigChanged { .. } => None,
+            },
+            Self::DeviceRebooted { .. } => None,
+        }
+    }
+}
+
+/// Events a filtered subscriber receives
+///
+/// Each set restricts events to the listed ports or nodes; an empty filter
+/// passes everything. Events without a port or node, such as reboots, only
+/// pass filters that don't restrict it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EventFilter {
+    ports: Option<HashSet<PortNum>>,
+    nodes: Option<HashSet<u32>>,
+}
+
+impl EventFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only pass events from packets on these ports
+    pub fn ports(mut self, ports: impl IntoIterator<Item = PortNum>) -> Self {
+        self.ports.get_or_insert_default().extend(ports);
+        self
+    }
+
+    /// Only pass events about these nodes
+    pub fn nodes(mut self, nodes: impl IntoIterator<Item = u32>) -> Self {
+        self.nodes.get_or_insert_default().extend(nodes);
+        self
+    }
+
+    pub fn matches(&self, event: &MeshEvent) -> bool {
+        passes(&self.ports, event.port()) && passes(&self.nodes, event.node())
+    }
+}
+
+/// An unset filter passes everything; a set one needs a value it contains
+fn passes<T: Eq + Hash>(set: &Option<HashSet<T>>, value: Option<T>) -> bool {
+    match set {
+        Some(set) => value.is_some_and(|value| set.contains(&value)),
+        None => true,
+    }
+}
+
+/// Filtered subscribers, each with the channel its matching events go to
+type FilteredSenders = Arc<Mutex<Vec<(EventFilter, broadcast::Sender<MeshEvent>)>>>;
+
+/// Channel events are published on
+///
+/// Filtered subscribers get a broadcast channel of their own that only
+/// matching events are sent to, so busy ports don't fill their buffer.
+#[derive(Clone)]
+pub(crate) struct EventBus {
+    all: broadcast::Sender<MeshEvent>,
+    filtered: FilteredSenders,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self {
+            all: broadcast::channel(EVENT_CHANNEL_CAPACITY).0,
+            filtered: Arc::default(),
+        }
+    }
+}
+
+impl EventBus {
+    pub fn subscribe(&self) -> broadcast::Receiver<MeshEvent> {
+        self.all.subscribe()
+    }
+
+    pub fn subscribe_filtered(&self, filter: EventFilter) -> broadcast::Receiver<MeshEvent> {
+        let (sender, receiver) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+        if let Ok(mut filtered) = self.filtered.lock() {
+            filtered.push((filter, sender));
+        }
+        receiver
+    }
+
+    /// Events not yet read by the slowest subscriber
+    pub fn queued(&self) -> usize {
+        let filtered = self
+            .filtered
+            .lock()
+            .map(|filtered| {
+                filtered
+                    .iter()
+                    .map(|(_, sender)| sender.len())
+                    .max()
+                    .unwrap_or_default()
+            })
+            .unwrap_or_default();
+        self.all.len().max(filtered)
+    }
+}
+
+/// Routing status reported by the mesh for a packet
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RoutingReport {
+    /// Id of the packet the report refers to, 0 when not tied to one
+    pub packet_id: u32,
+    /// Node that sent the report
+    pub from: String,
+    pub from_node: u32,
+    /// Firmware routing error name, "None" for successful delivery
+    pub reason: String,
+}
+
+/// Publish an event to all current subscribers
+pub(crate) fn publish(events: &EventBus, event: MeshEvent) {
+    if let Ok(mut filtered) = events.filtered.lock() {
+        // Dropped subscribers are unregistered whether or not the event
+        // would have reached them, so rarely matching filters don't linger
+        filtered.retain(|(filter, sender)| {
+            sender.receiver_count() > 0
+                && (!filter.matches(&event) || sender.send(event.clone()).is_ok())
+        });
+    }
+    // Sending only fails when nobody is subscribed, which is the common case
+    let _ = events.all.send(event);
+}
+
+/// Publish records just added to the change journal
+pub(crate) fn publish_changes(events: &EventBus, changes: impl IntoIterator<Item = ChangeRecord>) {
+    for change in changes {
+        publish(events, MeshEvent::MeshChanged(change));
+    }
+}
@@ -0,0 +1,121 @@
+use crate::channel::{CHANNEL_REQUEST_TIMEOUT, refresh_channels};
+use crate::channel_set::{ChannelSet, export_channels};
+use crate::config::{ConfigListing, MODULE_SECTIONS, list_config, module_section};
+use crate::connection::ConnectionManager;
+use crate::redact::replace_secrets;
+use anyhow::{Context, Result, bail, ensure};
+use meshtastic::protobufs;
+use serde::Serialize;
+use serde_json::{Value, json};
+
+/// Hash of one configuration section
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SectionHash {
+    pub section: String,
+    pub hash: String,
+}
+
+/// Stable hash of a device's configuration, for spotting drifted devices
+///
+/// Devices with the same settings get the same fingerprint, whatever their
+/// identity. Fingerprints are only comparable between runs of the same
+/// rmesh version, since new fields change them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConfigFingerprint {
+    pub fingerprint: String,
+    /// Per section hashes, to see where two devices differ
+    pub sections: Vec<SectionHash>,
+}
+
+impl ConfigFingerprint {
+    /// Whether the fingerprint is `expected`, ignoring case and whitespace
+    pub fn matches(&self, expected: &str) -> bool {
+        self.fingerprint.eq_ignore_ascii_case(expected.trim())
+    }
+}
+
+/// 64-bit FNV-1a hash
+fn fnv1a_64(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+fn hash_value(value: &Value) -> String {
+    format!("{hash:016x}", hash = fnv1a_64(value.to_string().as_bytes()))
+}
+
+/// Fingerprint settings already read from the device
+///
+/// Every config section must have been reported, so a device that has not
+/// synced yet is not mistaken for a drifted one; modules are included as
+/// far as the device reported them. Secrets such as PSKs are hashed before
+/// they go in, so a wrong key changes the fingerprint without the key
+/// itself being part of it.
+pub fn fingerprint_config(
+    config: &ConfigListing,
+    modules: &protobufs::LocalModuleConfig,
+    channels: &ChannelSet,
+) -> Result<ConfigFingerprint> {
+    let Value::Object(mut sections) = serde_json::to_value(config)? else {
+        bail!("Configuration did not serialize to an object");
+    };
+    let missing = sections
+        .iter()
+        .filter(|(_, value)| value.is_null())
+        .map(|(section, _)| section.as_str())
+        .collect::<Vec<_>>();
+    ensure!(
+        missing.is_empty(),
+        "The device has not reported its {sections} settings",
+        sections = missing.join(", ")
+    );
+
+    // A fixed PIN only matters in fixed PIN mode; otherwise it is random
+    if let Some(Value::Object(bluetooth)) = sections.get_mut("bluetooth")
+        && bluetooth.get("mode") != Some(&json!("FixedPin"))
+    {
+        bluetooth.remove("fixed_pin");
+    }
+
+    let mut reported_modules = 0;
+    for &section in MODULE_SECTIONS {
+        let value = module_section(modules, section)?;
+        if !value.is_null() {
+            sections.insert(section.to_string(), value);
+            reported_modules += 1;
+        }
+    }
+    ensure!(
+        reported_modules > 0,
+        "The device has not reported its module settings"
+    );
+
+    sections.insert("channels".to_string(), serde_json::to_value(channels)?);
+    for value in sections.values_mut() {
+        replace_secrets(value, &|secret| json!(hash_value(secret)));
+    }
+
+    let hashes = sections
+        .iter()
+        .map(|(section, value)| SectionHash {
+            section: section.clone(),
+            hash: hash_value(value),
+        })
+        .collect();
+    Ok(ConfigFingerprint {
+        fingerprint: hash_value(&Value::Object(sections)),
+        sections: hashes,
+    })
+}
+
+/// Read the configuration and channels from the device and fingerprint them
+pub async fn config_fingerprint(connection: &mut ConnectionManager) -> Result<ConfigFingerprint> {
+    let config = list_config(connection).await?;
+    refresh_channels(connection, CHANNEL_REQUEST_TIMEOUT)
+        .await
+        .context("Failed to read the channels")?;
+    let channels = export_channels(connection, true).await;
+    let modules = connection.get_device_state().await.raw_module_config;
+    fingerprint_config(&config, &modules, &channels)
+}
@@ -0,0 +1,205 @@
+use crate::state::DeviceState;
+use serde::Serialize;
+use std::cmp::Ordering;
+
+/// Firmware release the protobufs bundled with the meshtastic crate match
+///
+/// Firmware of a later minor release may have fields rmesh cannot decode,
+/// and any field rmesh sets may be unknown to older firmware.
+pub const BUNDLED_PROTOBUF_VERSION: &str = "2.6.0";
+
+/// Oldest firmware rmesh is expected to work with
+pub const MIN_SUPPORTED_FIRMWARE: &str = "2.0.0";
+
+/// First firmware accepting admin messages authorized by session key and PKI
+pub const PKI_ADMIN_FIRMWARE: &str = "2.5.0";
+
+/// First firmware with the NeighborInfo module
+pub const NEIGHBOR_INFO_FIRMWARE: &str = "2.2.0";
+
+/// First firmware sounding critical alerts received on the alert port
+pub const ALERT_FIRMWARE: &str = "2.6.0";
+
+/// Device roles added after the oldest supported firmware, with the release
+/// that introduced them
+const ROLE_FIRMWARE: &[(&str, &str)] = &[
+    ("TAK", "2.2.0"),
+    ("CLIENT_HIDDEN", "2.3.0"),
+    ("LOST_AND_FOUND", "2.3.0"),
+    ("TAK_TRACKER", "2.3.0"),
+    ("ROUTER_LATE", "2.6.0"),
+];
+
+/// A mismatch between the device firmware and the protobufs rmesh speaks
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum VersionIssue {
+    /// Older than any firmware rmesh is expected to work with
+    FirmwareTooOld { firmware: String, minimum: String },
+    /// From a later release than the bundled protobufs
+    FirmwareNewer { firmware: String, bundled: String },
+    /// The device asks for a newer client than the bundled protobufs match
+    ClientTooOld {
+        min_app_version: String,
+        bundled: String,
+    },
+    /// A setting or message the firmware predates
+    Unsupported {
+        feature: String,
+        firmware: String,
+        minimum: String,
+    },
+}
+
+impl std::fmt::Display for VersionIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::FirmwareTooOld { firmware, minimum } => write!(
+                f,
+                "Firmware {firmware} is older than {minimum}, the oldest rmesh supports; \
+                 settings may be silently ignored, so update the firmware"
+            ),
+            Self::FirmwareNewer { firmware, bundled } => write!(
+                f,
+                "Firmware {firmware} is newer than the {bundled} protobufs rmesh was built \
+                 with; new fields are not shown and may be reset when settings are written"
+            ),
+            Self::ClientTooOld {
+                min_app_version,
+                bundled,
+            } => write!(
+                f,
+                "The device expects clients matching firmware {min_app_version} or later, \
+                 but rmesh's protobufs match {bundled}"
+            ),
+            Self::Unsupported {
+                feature,
+                firmware,
+                minimum,
+            } => write!(
+                f,
+                "{feature} needs firmware {minimum} or later, but the device runs {firmware}; \
+                 it will likely be ignored"
+            ),
+        }
+    }
+}
+
+/// Firmware version reported by the device, once the handshake has run
+pub fn firmware_version(state: &DeviceState) -> Option<&str> {
+    state
+        .metadata
+        .as_ref()
+        .map(|metadata| metadata.firmware_version.as_str())
+        .filter(|version| !version.is_empty())
+}
+
+/// Firmware release matching an app version code such as 30200
+///
+/// App version codes are the Android app's, where 2.x releases are
+/// numbered 3xxyy, so 30200 means 2.2.0.
+pub fn app_version_from_code(code: u32) -> String {
+    format!(
+        "{major}.{minor}.{patch}",
+        major = (code / 10_000).saturating_sub(1),
+        minor = code / 100 % 100,
+        patch = code % 100
+    )
+}
+
+/// Compare the device's firmware and client requirements with the bundled
+/// protobufs
+pub fn check_versions(state: &DeviceState) -> Vec<VersionIssue> {
+    let mut issues = Vec::new();
+
+    if let Some(firmware) = firmware_version(state) {
+        if compare_versions(firmware, MIN_SUPPORTED_FIRMWARE) == Ordering::Less {
+            issues.push(VersionIssue::FirmwareTooOld {
+                firmware: firmware.to_string(),
+                minimum: MIN_SUPPORTED_FIRMWARE.to_string(),
+            });
+        }
+        // Patch releases do not change the protobufs
+        if compare_versions(
+            &major_minor(firmware),
+            &major_minor(BUNDLED_PROTOBUF_VERSION),
+        ) == Ordering::Greater
+        {
+            issues.push(VersionIssue::FirmwareNewer {
+                firmware: firmware.to_string(),
+                bundled: BUNDLED_PROTOBUF_VERSION.to_string(),
+            });
+        }
+    }
+
+    if let Some(min_app_version) = state
+        .my_node_info
+        .as_ref()
+        .map(|info| info.min_app_version)
+        .filter(|code| *code > 0)
+    {
+        let min_app_version = app_version_from_code(min_app_version);
+        if compare_versions(&min_app_version, BUNDLED_PROTOBUF_VERSION) == Ordering::Greater {
+            issues.push(VersionIssue::ClientTooOld {
+                min_app_version,
+                bundled: BUNDLED_PROTOBUF_VERSION.to_string(),
+            });
+        }
+    }
+
+    issues
+}
+
+/// Check that the device firmware is at least `minimum` before using `feature`
+///
+/// Unknown firmware versions pass, since the device has not said otherwise.
+pub fn require_firmware(state: &DeviceState, feature: &str, minimum: &str) -> Option<VersionIssue> {
+    let firmware = firmware_version(state)?;
+    (compare_versions(firmware, minimum) == Ordering::Less).then(|| VersionIssue::Unsupported {
+        feature: feature.to_string(),
+        firmware: firmware.to_string(),
+        minimum: minimum.to_string(),
+    })
+}
+
+/// First firmware with a device role, `None` for roles every supported
+/// firmware has
+pub fn role_min_firmware(role_name: &str) -> Option<&'static str> {
+    ROLE_FIRMWARE
+        .iter()
+        .find(|(name, _)| *name == role_name)
+        .map(|(_, version)| *version)
+}
+
+/// Compare dotted version strings numerically, ignoring non-numeric suffixes
+///
+/// "2.5.6.abc1234" compares equal to "2.5.6", and missing components count as zero.
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    fn components(version: &str) -> Vec<u64> {
+        version
+            .trim_start_matches(['v', 'V'])
+            .split('.')
+            .map_while(|part| part.parse::<u64>().ok())
+            .collect()
+    }
+
+    let a = components(a);
+    let b = components(b);
+    for i in 0..a.len().max(b.len()) {
+        let ordering = a
+            .get(i)
+            .copied()
+            .unwrap_or(0)
+            .cmp(&b.get(i).copied().unwrap_or(0));
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+
+    Ordering::Equal
+}
+
+/// "2.5.6.abc1234" shortened to "2.5"
+fn major_minor(version: &str) -> String {
+    version.split('.').take(2).collect::<Vec<_>>().join(".")
+}
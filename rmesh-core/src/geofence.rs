@@ -0,0 +1,163 @@
+use crate::state::Position;
+use anyhow::{Context, Result, ensure};
+use serde::Serialize;
+use std::collections::HashMap;
+use strum::Display;
+
+/// Mean Earth radius used for great-circle distances
+const EARTH_RADIUS_M: f64 = 6_371_000.0;
+
+/// Great-circle distance between two coordinates in meters
+pub fn haversine_distance_m(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let d_lat = (lat2 - lat1).to_radians();
+    let d_lon = (lon2 - lon1).to_radians();
+    let a = (d_lat / 2.0).sin().powi(2)
+        + lat1.to_radians().cos() * lat2.to_radians().cos() * (d_lon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_M * a.sqrt().atan2((1.0 - a).sqrt())
+}
+
+/// Circular area around a center point
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Geofence {
+    pub latitude: f64,
+    pub longitude: f64,
+    pub radius_m: f64,
+}
+
+impl Geofence {
+    pub fn new(latitude: f64, longitude: f64, radius_m: f64) -> Result<Self> {
+        ensure!(
+            (-90.0..=90.0).contains(&latitude),
+            "Latitude {latitude} out of range"
+        );
+        ensure!(
+            (-180.0..=180.0).contains(&longitude),
+            "Longitude {longitude} out of range"
+        );
+        ensure!(radius_m > 0.0, "Geofence radius must be positive");
+        Ok(Self {
+            latitude,
+            longitude,
+            radius_m,
+        })
+    }
+
+    /// Distance from the fence center in meters
+    pub fn distance_m(&self, latitude: f64, longitude: f64) -> f64 {
+        haversine_distance_m(self.latitude, self.longitude, latitude, longitude)
+    }
+
+    pub fn contains(&self, latitude: f64, longitude: f64) -> bool {
+        self.distance_m(latitude, longitude) <= self.radius_m
+    }
+}
+
+/// Direction of a geofence crossing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum GeofenceTransition {
+    Enter,
+    Exit,
+}
+
+/// A node crossing the fence boundary
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GeofenceEvent {
+    pub node_id: String,
+    pub node_num: u32,
+    pub transition: GeofenceTransition,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub distance_m: f64,
+}
+
+/// Tracks which nodes are inside a geofence and reports crossings
+#[derive(Debug, Clone)]
+pub struct GeofenceMonitor {
+    fence: Geofence,
+    nodes: Vec<u32>,
+    inside: HashMap<u32, bool>,
+}
+
+impl GeofenceMonitor {
+    /// Watch the given nodes, or every node when the list is empty
+    pub fn new(fence: Geofence, nodes: Vec<u32>) -> Self {
+        Self {
+            fence,
+            nodes,
+            inside: HashMap::new(),
+        }
+    }
+
+    pub fn fence(&self) -> &Geofence {
+        &self.fence
+    }
+
+    /// Feed a position update, returning an event if the node crossed the fence
+    ///
+    /// A node first seen inside the fence reports an enter event; one first
+    /// seen outside is only recorded.
+    pub fn update(&mut self, position: &Position) -> Option<GeofenceEvent> {
+        if !self.nodes.is_empty() && !self.nodes.contains(&position.node_num) {
+            return None;
+        }
+
+        let distance_m = self.fence.distance_m(position.latitude, position.longitude);
+        let is_inside = distance_m <= self.fence.radius_m;
+        let was_inside = self.inside.insert(position.node_num, is_inside);
+
+        let transition = match (was_inside, is_inside) {
+            (None | Some(false), true) => GeofenceTransition::Enter,
+            (Some(true), false) => GeofenceTransition::Exit,
+            _ => return None,
+        };
+
+        Some(GeofenceEvent {
+            node_id: position.node_id.clone(),
+            node_num: position.node_num,
+            transition,
+            latitude: position.latitude,
+            longitude: position.longitude,
+            distance_m,
+        })
+    }
+}
+
+/// Parse a "lat,lon" pair
+pub fn parse_coordinates(value: &str) -> Result<(f64, f64)> {
+    let (lat, lon) = value
+        .split_once(',')
+        .context("Expected coordinates as 'lat,lon'")?;
+    let lat = lat
+        .trim()
+        .parse::<f64>()
+        .with_context(|| format!("Invalid latitude '{lat}'"))?;
+    let lon = lon
+        .trim()
+        .parse::<f64>()
+        .with_context(|| format!("Invalid longitude '{lon}'"))?;
+    Ok((lat, lon))
+}
+
+/// Parse a distance such as "500m", "1.5km" or "250" (meters)
+pub fn parse_distance_m(value: &str) -> Result<f64> {
+    let value = value.trim().to_lowercase();
+    let (number, multiplier) = if let Some(km) = value.strip_suffix("km") {
+        (km, 1000.0)
+    } else if let Some(m) = value.strip_suffix('m') {
+        (m, 1.0)
+    } else {
+        (value.as_str(), 1.0)
+    };
+
+    let distance = number
+        .trim()
+        .parse::<f64>()
+        .with_context(|| format!("Invalid distance '{value}' (expected e.g. 500m or 1.5km)"))?;
+    ensure!(
+        distance.is_finite() && distance > 0.0,
+        "Distance must be positive"
+    );
+    Ok(distance * multiplier)
+}
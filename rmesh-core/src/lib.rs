@@ -3,15 +3,44 @@
 //! This crate provides the business logic for interacting with Meshtastic devices,
 //! including connection management, message handling, configuration, and more.
 
+pub mod admin;
+pub mod airtime;
+pub mod alert;
 pub mod channel;
+pub mod channel_set;
+pub mod clock;
 pub mod config;
 pub mod connection;
+pub mod debug;
 pub mod device;
+pub mod doctor;
+pub mod events;
+pub mod fingerprint;
+pub mod firmware;
+pub mod geofence;
+pub mod link_budget;
+pub mod lora;
+pub mod map;
 pub mod mesh;
 pub mod message;
+pub mod mqtt_proxy;
+pub mod node_id;
+pub mod packet_filter;
 pub mod position;
+pub mod presence;
+pub mod progress;
+pub mod redact;
+pub mod responder;
+pub mod schema;
+pub mod session_cache;
+pub mod snapshot;
 pub mod state;
+#[cfg(all(target_os = "linux", feature = "systemd"))]
+pub mod systemd;
 pub mod telemetry;
+pub mod time;
+pub mod transcript;
+pub mod waypoint;
 
 // Re-export commonly used types
 pub use anyhow::Result;
@@ -0,0 +1,188 @@
+use crate::lora::{ModemParams, ModemPreset};
+use serde::Serialize;
+use strum::Display;
+
+/// Noise figure of common LoRa receivers such as the SX1262, in dB
+pub const NOISE_FIGURE_DB: f64 = 6.0;
+
+/// Preamble length Meshtastic transmits, in symbols
+pub const PREAMBLE_SYMBOLS: u32 = 16;
+
+/// Bytes added to a text payload on air: the 16 byte packet header and the
+/// Data protobuf around the text
+pub const MESH_OVERHEAD_BYTES: usize = 20;
+
+/// Thermal noise density at room temperature, in dBm/Hz
+const THERMAL_NOISE_DBM_HZ: f64 = -174.0;
+
+/// Margin from which a link is expected to hold up through fading, in dB
+pub const RELIABLE_MARGIN_DB: f64 = 10.0;
+
+/// Lowest SNR at which a spreading factor still demodulates, in dB
+pub fn snr_limit_db(spread_factor: u32) -> f64 {
+    -2.5 * (f64::from(spread_factor) - 4.0)
+}
+
+/// Receiver noise floor over a bandwidth, in dBm
+pub fn noise_floor_dbm(bandwidth_khz: f64) -> f64 {
+    THERMAL_NOISE_DBM_HZ + 10.0 * (bandwidth_khz * 1000.0).log10() + NOISE_FIGURE_DB
+}
+
+/// Weakest signal a receiver can decode with these settings, in dBm
+pub fn sensitivity_dbm(params: &ModemParams) -> f64 {
+    noise_floor_dbm(params.bandwidth_khz) + snr_limit_db(params.spread_factor)
+}
+
+/// Time on air of one LoRa packet in milliseconds, per the Semtech formula
+///
+/// Assumes an explicit header and a payload CRC, as Meshtastic sends them.
+pub fn airtime_ms(params: &ModemParams, payload_bytes: usize, preamble_symbols: u32) -> f64 {
+    let spread_factor = f64::from(params.spread_factor);
+    let symbol_ms = 2f64.powf(spread_factor) / params.bandwidth_khz;
+    // Symbols longer than 16 ms need low data rate optimization
+    let low_data_rate = if symbol_ms > 16.0 { 1.0 } else { 0.0 };
+
+    let payload_bits = 8.0 * payload_bytes as f64 - 4.0 * spread_factor + 28.0 + 16.0;
+    let blocks = (payload_bits / (4.0 * (spread_factor - 2.0 * low_data_rate))).ceil();
+    let payload_symbols = 8.0 + (blocks * f64::from(params.coding_rate - 4)).max(0.0);
+
+    (f64::from(preamble_symbols) + 4.25 + payload_symbols) * symbol_ms
+}
+
+/// Free space path loss over a distance, in dB
+pub fn free_space_path_loss_db(distance_m: f64, frequency_mhz: f64) -> f64 {
+    20.0 * (distance_m / 1000.0).log10() + 20.0 * frequency_mhz.log10() + 32.44
+}
+
+/// Distance at which free space path loss reaches `loss_db`, in meters
+pub fn free_space_range_m(loss_db: f64, frequency_mhz: f64) -> f64 {
+    1000.0 * 10f64.powf((loss_db - 20.0 * frequency_mhz.log10() - 32.44) / 20.0)
+}
+
+/// Radio setup both ends of the link share
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkSetup {
+    pub tx_power_dbm: f64,
+    /// Antenna gain at each end, in dBi
+    pub antenna_gain_dbi: f64,
+    /// Cable, connector and obstruction losses, in dB
+    pub extra_loss_db: f64,
+    pub frequency_mhz: f64,
+    /// Length of the text, without mesh overhead
+    pub payload_bytes: usize,
+    /// Use the wider bandwidths of 2.4 GHz radios
+    pub wide_lora: bool,
+}
+
+/// What the SNR of each preset is estimated from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LinkSource {
+    /// Free space propagation over this many meters
+    Distance(f64),
+    /// SNR seen on the link while using a preset
+    ObservedSnr { snr_db: f64, preset: ModemPreset },
+}
+
+/// How a link is expected to perform
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum LinkVerdict {
+    /// Enough margin to ride out fading
+    Reliable,
+    /// Decodable, but drops out as conditions change
+    Marginal,
+    /// Below what the receiver can decode
+    Unlikely,
+}
+
+impl LinkVerdict {
+    pub fn from_margin(margin_db: f64) -> Self {
+        if margin_db >= RELIABLE_MARGIN_DB {
+            Self::Reliable
+        } else if margin_db >= 0.0 {
+            Self::Marginal
+        } else {
+            Self::Unlikely
+        }
+    }
+}
+
+/// Expected link performance with one preset
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PresetEstimate {
+    pub preset: ModemPreset,
+    /// The preset the estimate was asked about
+    pub selected: bool,
+    pub spread_factor: u32,
+    pub bandwidth_khz: f64,
+    /// Time on air of one message, in milliseconds
+    pub airtime_ms: f64,
+    pub sensitivity_dbm: f64,
+    pub expected_snr_db: f64,
+    /// Expected SNR above the demodulation limit
+    pub margin_db: f64,
+    pub verdict: LinkVerdict,
+    /// Free space distance at which the margin runs out, when estimating from
+    /// a distance
+    pub max_range_m: Option<f64>,
+}
+
+/// Estimate the link with every preset, fastest first
+///
+/// From a distance, the received power follows free space propagation, so
+/// real links with obstructions fare worse. From an observed SNR, the signal
+/// is taken as fixed and only the noise over each bandwidth changes.
+pub fn estimate_presets(
+    source: LinkSource,
+    setup: &LinkSetup,
+    selected: ModemPreset,
+) -> Vec<PresetEstimate> {
+    let bytes = setup.payload_bytes + MESH_OVERHEAD_BYTES;
+    // Power reaching the receiver, less the path loss
+    let budget_dbm = setup.tx_power_dbm + 2.0 * setup.antenna_gain_dbi - setup.extra_loss_db;
+
+    ModemPreset::ALL
+        .into_iter()
+        .map(|preset| {
+            let params = preset.params(setup.wide_lora);
+            let sensitivity_dbm = sensitivity_dbm(&params);
+            let (expected_snr_db, max_range_m) = match source {
+                LinkSource::Distance(distance_m) => {
+                    let received_dbm =
+                        budget_dbm - free_space_path_loss_db(distance_m, setup.frequency_mhz);
+                    let range_m =
+                        free_space_range_m(budget_dbm - sensitivity_dbm, setup.frequency_mhz);
+                    (
+                        received_dbm - noise_floor_dbm(params.bandwidth_khz),
+                        Some(range_m),
+                    )
+                }
+                LinkSource::ObservedSnr {
+                    snr_db,
+                    preset: observed,
+                } => {
+                    let observed_khz = observed.params(setup.wide_lora).bandwidth_khz;
+                    (
+                        snr_db + 10.0 * (observed_khz / params.bandwidth_khz).log10(),
+                        None,
+                    )
+                }
+            };
+            let margin_db = expected_snr_db - snr_limit_db(params.spread_factor);
+
+            PresetEstimate {
+                preset,
+                selected: preset == selected,
+                spread_factor: params.spread_factor,
+                bandwidth_khz: params.bandwidth_khz,
+                airtime_ms: airtime_ms(&params, bytes, PREAMBLE_SYMBOLS),
+                sensitivity_dbm,
+                expected_snr_db,
+                margin_db,
+                verdict: LinkVerdict::from_margin(margin_db),
+                max_range_m,
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,295 @@
+use crate::state::{ChannelInfo, LoraConfig};
+use anyhow::{Result, bail, ensure};
+use serde::Serialize;
+use strum::Display;
+
+/// Frequency band of a LoRa region, as defined by the firmware
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RegionBand {
+    /// Region code as rmesh shows it, e.g. `EU868`
+    pub code: &'static str,
+    pub freq_start_mhz: f64,
+    pub freq_end_mhz: f64,
+    /// Gap between slots; zero in every region today
+    pub spacing_mhz: f64,
+    /// 2.4 GHz radios, which use wider bandwidths
+    pub wide_lora: bool,
+}
+
+const fn band(code: &'static str, freq_start_mhz: f64, freq_end_mhz: f64) -> RegionBand {
+    RegionBand {
+        code,
+        freq_start_mhz,
+        freq_end_mhz,
+        spacing_mhz: 0.0,
+        wide_lora: false,
+    }
+}
+
+/// Bands of every region the bundled protobufs know
+pub const REGION_BANDS: &[RegionBand] = &[
+    band("US", 902.0, 928.0),
+    band("EU433", 433.0, 434.0),
+    band("EU868", 869.4, 869.65),
+    band("CN", 470.0, 510.0),
+    band("JP", 920.5, 923.5),
+    band("ANZ", 915.0, 928.0),
+    band("KR", 920.0, 923.0),
+    band("TW", 920.0, 925.0),
+    band("RU", 868.7, 869.2),
+    band("IN", 865.0, 867.0),
+    band("NZ865", 864.0, 868.0),
+    band("TH", 920.0, 925.0),
+    RegionBand {
+        wide_lora: true,
+        ..band("LORA24", 2400.0, 2483.5)
+    },
+    band("UA433", 433.0, 434.7),
+    band("UA868", 868.0, 868.6),
+    band("MY433", 433.0, 435.0),
+    band("MY919", 919.0, 924.0),
+    band("SG923", 917.0, 925.0),
+    band("PH433", 433.0, 434.7),
+    band("PH868", 868.0, 869.4),
+    band("PH915", 915.0, 918.0),
+];
+
+/// Band of a region code, `None` for an unset or unknown region
+pub fn region_band(code: &str) -> Option<&'static RegionBand> {
+    REGION_BANDS
+        .iter()
+        .find(|band| band.code.eq_ignore_ascii_case(code))
+}
+
+/// Check a frequency override is legal in a region
+///
+/// Zero clears the override and is always accepted; anything else must lie
+/// inside the region's band.
+pub fn check_override_frequency(region: &str, frequency_mhz: f32) -> Result<()> {
+    ensure!(
+        frequency_mhz.is_finite() && frequency_mhz >= 0.0,
+        "Invalid override frequency: {frequency_mhz}"
+    );
+    if frequency_mhz == 0.0 {
+        return Ok(());
+    }
+    let Some(band) = region_band(region) else {
+        bail!("Set lora.region before overriding the frequency");
+    };
+    let frequency_mhz = f64::from(frequency_mhz);
+    ensure!(
+        (band.freq_start_mhz..=band.freq_end_mhz).contains(&frequency_mhz),
+        "{frequency_mhz} MHz is outside the {code} band ({start}-{end} MHz)",
+        code = band.code,
+        start = band.freq_start_mhz,
+        end = band.freq_end_mhz
+    );
+    Ok(())
+}
+
+/// LoRa modem presets
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Display)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+#[strum(serialize_all = "SCREAMING_SNAKE_CASE")]
+pub enum ModemPreset {
+    LongFast,
+    LongSlow,
+    VeryLongSlow,
+    MediumSlow,
+    MediumFast,
+    ShortSlow,
+    ShortFast,
+    LongModerate,
+    ShortTurbo,
+}
+
+/// Modulation settings of a preset
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ModemParams {
+    pub bandwidth_khz: f64,
+    pub spread_factor: u32,
+    /// Denominator of the coding rate, e.g. 5 for 4/5
+    pub coding_rate: u32,
+}
+
+impl ModemPreset {
+    pub const ALL: [ModemPreset; 9] = [
+        Self::ShortTurbo,
+        Self::ShortFast,
+        Self::ShortSlow,
+        Self::MediumFast,
+        Self::MediumSlow,
+        Self::LongFast,
+        Self::LongModerate,
+        Self::LongSlow,
+        Self::VeryLongSlow,
+    ];
+
+    /// Parse a preset name, e.g. `LONG_FAST`, `LongFast` or `long-fast`
+    pub fn from_name(name: &str) -> Option<Self> {
+        let normalize = |name: &str| {
+            name.chars()
+                .filter(|c| c.is_ascii_alphanumeric())
+                .collect::<String>()
+                .to_ascii_lowercase()
+        };
+        let wanted = normalize(name);
+        Self::ALL
+            .into_iter()
+            .find(|preset| normalize(&format!("{preset:?}")) == wanted)
+    }
+
+    /// Name the firmware gives an unnamed primary channel using this preset
+    pub fn display_name(self) -> &'static str {
+        match self {
+            Self::ShortTurbo => "ShortTurbo",
+            Self::ShortFast => "ShortFast",
+            Self::ShortSlow => "ShortSlow",
+            Self::MediumFast => "MediumFast",
+            Self::MediumSlow => "MediumSlow",
+            Self::LongFast => "LongFast",
+            Self::LongModerate => "LongMod",
+            Self::LongSlow => "LongSlow",
+            Self::VeryLongSlow => "VLongSlow",
+        }
+    }
+
+    /// Modulation settings, with the wider bandwidths of 2.4 GHz radios when
+    /// `wide_lora` is set
+    pub fn params(self, wide_lora: bool) -> ModemParams {
+        let (bandwidth_khz, wide_bandwidth_khz, spread_factor, coding_rate) = match self {
+            Self::ShortTurbo => (500.0, 1625.0, 7, 5),
+            Self::ShortFast => (250.0, 812.5, 7, 5),
+            Self::ShortSlow => (250.0, 812.5, 8, 5),
+            Self::MediumFast => (250.0, 812.5, 9, 5),
+            Self::MediumSlow => (250.0, 812.5, 10, 5),
+            Self::LongFast => (250.0, 812.5, 11, 5),
+            Self::LongModerate => (125.0, 406.25, 11, 8),
+            Self::LongSlow => (125.0, 406.25, 12, 8),
+            Self::VeryLongSlow => (62.5, 203.125, 12, 8),
+        };
+        ModemParams {
+            bandwidth_khz: if wide_lora {
+                wide_bandwidth_khz
+            } else {
+                bandwidth_khz
+            },
+            spread_factor,
+            coding_rate,
+        }
+    }
+}
+
+/// Bandwidth in kHz of a custom modem setting, expanding the rounded values
+/// the config stores
+fn custom_bandwidth_khz(bandwidth: u32) -> f64 {
+    match bandwidth {
+        31 => 31.25,
+        62 => 62.5,
+        200 => 203.125,
+        400 => 406.25,
+        800 => 812.5,
+        1600 => 1625.0,
+        other => f64::from(other),
+    }
+}
+
+/// Channel name hash the firmware picks the frequency slot with (djb2)
+pub fn channel_name_hash(name: &str) -> u32 {
+    name.bytes().fold(5381u32, |hash, byte| {
+        hash.wrapping_shl(5)
+            .wrapping_add(hash)
+            .wrapping_add(u32::from(byte))
+    })
+}
+
+/// Where the radio transmits, worked out the way the firmware does
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FrequencySlot {
+    pub region: String,
+    /// Preset name, or `Custom` for custom modem settings
+    pub modem: String,
+    /// Name the slot is derived from; the preset name for an unnamed channel
+    pub channel_name: String,
+    /// Slot number, counted from 1 as in the apps
+    pub slot: u32,
+    pub num_slots: u32,
+    pub frequency_mhz: f64,
+    pub bandwidth_khz: f64,
+}
+
+/// Frequency slot of a primary channel under the given LoRa settings
+///
+/// The slot is the channel name hash modulo the number of slots in the
+/// region, unless `channel_num` pins it. An `override_frequency` replaces the
+/// slot's frequency. Returns `None` when the region is unset or the modem
+/// settings are unknown.
+pub fn frequency_slot(lora: &LoraConfig, primary_channel_name: &str) -> Option<FrequencySlot> {
+    let band = region_band(&lora.region)?;
+
+    let (modem, bandwidth_khz, default_name) = if lora.use_preset {
+        let preset = ModemPreset::from_name(&lora.modem_preset)?;
+        (
+            format!("{preset:?}"),
+            preset.params(band.wide_lora).bandwidth_khz,
+            preset.display_name(),
+        )
+    } else {
+        if lora.bandwidth == 0 {
+            return None;
+        }
+        (
+            "Custom".to_string(),
+            custom_bandwidth_khz(lora.bandwidth),
+            "Custom",
+        )
+    };
+    let channel_name = if primary_channel_name.is_empty() {
+        default_name
+    } else {
+        primary_channel_name
+    };
+
+    let bandwidth_mhz = bandwidth_khz / 1000.0;
+    let num_slots =
+        ((band.freq_end_mhz - band.freq_start_mhz) / (band.spacing_mhz + bandwidth_mhz)).floor();
+    if num_slots < 1.0 {
+        return None;
+    }
+    let num_slots = num_slots as u32;
+    let index = match lora.channel_num {
+        0 => channel_name_hash(channel_name),
+        pinned => pinned - 1,
+    } % num_slots;
+
+    let base_mhz = if lora.override_frequency > 0.0 {
+        f64::from(lora.override_frequency)
+    } else {
+        band.freq_start_mhz + bandwidth_mhz / 2.0 + f64::from(index) * bandwidth_mhz
+    };
+    let frequency_mhz = base_mhz + f64::from(lora.frequency_offset);
+
+    Some(FrequencySlot {
+        region: band.code.to_string(),
+        modem,
+        channel_name: channel_name.to_string(),
+        slot: index + 1,
+        num_slots,
+        frequency_mhz,
+        bandwidth_khz,
+    })
+}
+
+/// Frequency slot of the primary channel among `channels`
+pub fn primary_frequency_slot(
+    lora: &LoraConfig,
+    channels: &[ChannelInfo],
+) -> Option<FrequencySlot> {
+    let primary = channels.iter().find(|channel| channel.role == "Primary")?;
+    let name = primary
+        .settings
+        .as_ref()
+        .map(|settings| settings.name.as_str())
+        .unwrap_or_default();
+    frequency_slot(lora, name)
+}
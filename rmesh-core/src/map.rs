@@ -0,0 +1,180 @@
+use crate::connection::ConnectionManager;
+use crate::geofence::haversine_distance_m;
+use anyhow::Result;
+use serde::Serialize;
+
+/// Symbols assigned to nodes in legend order
+const NODE_SYMBOLS: &str = "123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+/// Symbol used once the node symbols run out
+const OVERFLOW_SYMBOL: char = '#';
+
+/// Symbol for cells holding more than one node
+const COLLISION_SYMBOL: char = '*';
+
+/// Symbol marking the map center
+const CENTER_SYMBOL: char = '@';
+
+/// Terminal cells are roughly twice as tall as they are wide
+const CELL_ASPECT: f64 = 2.0;
+
+/// Mean Earth radius used for the local projection
+const EARTH_RADIUS_M: f64 = 6_371_000.0;
+
+/// A node to place on the map
+#[derive(Debug, Clone)]
+pub struct MapNode {
+    pub node_num: u32,
+    pub label: String,
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+/// Legend entry linking a map symbol to its node
+#[derive(Debug, Clone, Serialize)]
+pub struct MapLegendEntry {
+    pub symbol: char,
+    pub node_num: u32,
+    pub label: String,
+    pub distance_m: f64,
+}
+
+/// A rendered character map of node positions around a center point
+#[derive(Debug, Clone, Serialize)]
+pub struct AsciiMap {
+    pub center_latitude: f64,
+    pub center_longitude: f64,
+    /// Framed map rows, north at the top
+    pub rows: Vec<String>,
+    /// Horizontal distance covered by one character
+    pub meters_per_column: f64,
+    pub legend: Vec<MapLegendEntry>,
+}
+
+/// Render nodes as a scatter plot around `center` (lat, lon)
+///
+/// The scale is chosen so the farthest node still fits; `width` and
+/// `height` are the inner size in characters, excluding the frame.
+pub fn render_ascii_map(
+    center: (f64, f64),
+    nodes: &[MapNode],
+    width: usize,
+    height: usize,
+) -> AsciiMap {
+    let width = width.max(3);
+    let height = height.max(3);
+    let (center_lat, center_lon) = center;
+
+    // Equirectangular projection is accurate enough at mesh scales
+    let projected: Vec<(f64, f64)> = nodes
+        .iter()
+        .map(|node| {
+            let x = (node.longitude - center_lon).to_radians()
+                * center_lat.to_radians().cos()
+                * EARTH_RADIUS_M;
+            let y = (node.latitude - center_lat).to_radians() * EARTH_RADIUS_M;
+            (x, y)
+        })
+        .collect();
+
+    let half_columns = ((width - 1) / 2).max(1) as f64;
+    let half_rows = ((height - 1) / 2).max(1) as f64;
+    let meters_per_column = projected
+        .iter()
+        .map(|(x, y)| (x.abs() / half_columns).max(y.abs() / (half_rows * CELL_ASPECT)))
+        .fold(1.0, f64::max);
+
+    let center_column = width / 2;
+    let center_row = height / 2;
+    let mut grid = vec![vec![' '; width]; height];
+    grid[center_row][center_column] = CENTER_SYMBOL;
+
+    let mut symbols = NODE_SYMBOLS.chars();
+    let mut legend = Vec::with_capacity(nodes.len());
+
+    for (node, (x, y)) in nodes.iter().zip(projected) {
+        let symbol = symbols.next().unwrap_or(OVERFLOW_SYMBOL);
+
+        let column = center_column as f64 + (x / meters_per_column).round();
+        let row = center_row as f64 - (y / (meters_per_column * CELL_ASPECT)).round();
+        let column = column.clamp(0.0, (width - 1) as f64) as usize;
+        let row = row.clamp(0.0, (height - 1) as f64) as usize;
+
+        let cell = &mut grid[row][column];
+        *cell = if *cell == ' ' {
+            symbol
+        } else {
+            COLLISION_SYMBOL
+        };
+
+        legend.push(MapLegendEntry {
+            symbol,
+            node_num: node.node_num,
+            label: node.label.clone(),
+            distance_m: haversine_distance_m(center_lat, center_lon, node.latitude, node.longitude),
+        });
+    }
+
+    let border = format!("+{line}+", line = "-".repeat(width));
+    let mut rows = Vec::with_capacity(height + 2);
+    rows.push(border.clone());
+    for line in grid {
+        rows.push(format!(
+            "|{line}|",
+            line = line.into_iter().collect::<String>()
+        ));
+    }
+    rows.push(border);
+
+    AsciiMap {
+        center_latitude: center_lat,
+        center_longitude: center_lon,
+        rows,
+        meters_per_column,
+        legend,
+    }
+}
+
+/// Render the known node positions around the local node
+///
+/// Falls back to the centroid of all positions when the local node has not
+/// reported one. Returns `None` when no node has a position.
+pub async fn get_node_map(
+    connection: &ConnectionManager,
+    width: usize,
+    height: usize,
+) -> Result<Option<AsciiMap>> {
+    let state = connection.get_device_state().await;
+    let my_node_num = state.my_node_info.as_ref().map(|info| info.node_num);
+
+    let mut nodes: Vec<MapNode> = state
+        .positions
+        .values()
+        .filter(|position| Some(position.node_num) != my_node_num)
+        .map(|position| MapNode {
+            node_num: position.node_num,
+            label: state
+                .nodes
+                .get(&position.node_num)
+                .map(|node| node.user.long_name.clone())
+                .unwrap_or_else(|| position.node_id.clone()),
+            latitude: position.latitude,
+            longitude: position.longitude,
+        })
+        .collect();
+    nodes.sort_by_key(|node| node.node_num);
+
+    let center = match my_node_num.and_then(|num| state.positions.get(&num)) {
+        Some(position) => (position.latitude, position.longitude),
+        None if nodes.is_empty() => return Ok(None),
+        None => {
+            let count = nodes.len() as f64;
+            (
+                nodes.iter().map(|node| node.latitude).sum::<f64>() / count,
+                nodes.iter().map(|node| node.longitude).sum::<f64>() / count,
+            )
+        }
+    };
+
+    Ok(Some(render_ascii_map(center, &nodes, width, height)))
+}
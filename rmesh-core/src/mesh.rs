@@ -1,8 +1,8 @@
 use crate::connection::ConnectionManager;
-use crate::state::NodeInfo;
+use crate::node_id::NodeId;
+use crate::state::{ChangeRecord, DeviceState, MyNodeInfo, NodeInfo, RouteEntry};
 use anyhow::Result;
 use serde::Serialize;
-use serde_json::json;
 use strum::{Display, EnumString};
 use tracing::debug;
 
@@ -24,7 +24,8 @@ pub struct MeshTopology {
     pub nodes: Vec<MeshNode>,
     pub edges: Vec<MeshEdge>,
     pub total_nodes: usize,
-    pub my_node_id: String,
+    /// The local node, if the device has reported it
+    pub my_node: Option<MyNodeInfo>,
 }
 
 /// Represents an edge/connection between two nodes
@@ -56,7 +57,7 @@ pub struct RouteHop {
 }
 
 /// Get the current mesh network topology
-pub async fn get_topology(connection: &ConnectionManager) -> Result<serde_json::Value> {
+pub async fn get_topology(connection: &ConnectionManager) -> Result<MeshTopology> {
     let state = connection.get_device_state().await;
 
     // Build node list from cached state
@@ -105,12 +106,12 @@ pub async fn get_topology(connection: &ConnectionManager) -> Result<serde_json::
         }
     }
 
-    Ok(json!({
-        "nodes": nodes,
-        "edges": edges,
-        "total_nodes": nodes.len(),
-        "my_node": state.my_node_info,
-    }))
+    Ok(MeshTopology {
+        total_nodes: nodes.len(),
+        nodes,
+        edges,
+        my_node: state.my_node_info,
+    })
 }
 
 /// Perform a traceroute to a specific node
@@ -123,13 +124,14 @@ pub async fn traceroute(
 
     if hops.is_empty() {
         debug!(
-            "No route found to destination {dest:08x}",
-            dest = destination
+            "No route found to destination {dest}",
+            dest = NodeId(destination)
         );
     } else {
         debug!(
-            "Found route to {destination:08x} with {hops} hops",
-            hops = hops.len()
+            "Found route to {destination} with {hops} hops",
+            hops = hops.len(),
+            destination = NodeId(destination)
         );
     }
 
@@ -140,21 +142,12 @@ pub async fn traceroute(
 pub async fn get_neighbors(connection: &ConnectionManager) -> Result<Vec<NodeInfo>> {
     let state = connection.get_device_state().await;
 
-    // Filter nodes that have recent SNR/RSSI values (indicating direct connection)
+    // Consider it a neighbor if we have signal strength info and heard recently
     let neighbors: Vec<NodeInfo> = state
         .nodes
         .values()
         .filter(|node| {
-            // Consider it a neighbor if we have signal strength info and heard recently
-            (node.snr.is_some() || node.rssi.is_some())
-                && node.last_heard.is_some_and(|h| {
-                    let now = std::time::SystemTime::now()
-                        .duration_since(std::time::UNIX_EPOCH)
-                        .unwrap_or_default()
-                        .as_secs();
-                    // Use saturating subtraction to avoid overflow if timestamp is in the future
-                    now.saturating_sub(h) < 3600 // Heard within last hour
-                })
+            (node.snr.is_some() || node.rssi.is_some()) && state.presence.is_active(node)
         })
         .cloned()
         .collect();
@@ -162,10 +155,93 @@ pub async fn get_neighbors(connection: &ConnectionManager) -> Result<Vec<NodeInf
     Ok(neighbors)
 }
 
-/// Get list of all nodes in the mesh
+/// Get the routes learned from traceroute traffic, nearest destinations first
+pub async fn get_routes(connection: &ConnectionManager) -> Vec<RouteEntry> {
+    let state = connection.get_device_state_ref();
+    let state = state.lock().await;
+    let mut routes: Vec<RouteEntry> = state.routes.values().cloned().collect();
+    routes.sort_by_key(|route| (route.hop_count, route.destination));
+    routes
+}
+
+/// Get the changes journaled at or after `since`, oldest first
+pub async fn get_changes(connection: &ConnectionManager, since: Option<u64>) -> Vec<ChangeRecord> {
+    let state = connection.get_device_state_ref();
+    let state = state.lock().await;
+    state.changes_since(since)
+}
+
+/// The destination and path from the local node that a traceroute reveals
+///
+/// A reply to our traceroute, with `request_id` set, lists the relays
+/// towards its sender. A traceroute another node sent to us lists the relays
+/// from that node, so its path is reversed. Traceroutes between other nodes
+/// reveal nothing about our own routes and give `None`.
+pub fn learned_route(
+    local_node: u32,
+    from: u32,
+    to: u32,
+    request_id: u32,
+    route: &[u32],
+) -> Option<(u32, Vec<u32>)> {
+    if to != local_node || from == local_node {
+        return None;
+    }
+    let path = if request_id != 0 {
+        route.to_vec()
+    } else {
+        route.iter().rev().copied().collect()
+    };
+    Some((from, path))
+}
+
+/// Get list of all nodes in the mesh, most recently heard first
 pub async fn get_nodes(connection: &ConnectionManager) -> Result<Vec<NodeInfo>> {
-    let state = connection.get_device_state().await;
-    Ok(state.nodes.values().cloned().collect())
+    Ok(get_nodes_page(connection, 0, None).await?.nodes)
+}
+
+/// A slice of the node list
+#[derive(Debug, Clone, Serialize)]
+pub struct NodePage {
+    pub nodes: Vec<NodeInfo>,
+    /// Position of the first node of the page in the full list
+    pub offset: usize,
+    /// Number of nodes in the full list
+    pub total: usize,
+}
+
+/// Get up to `limit` nodes starting at `offset`, most recently heard first
+///
+/// Only the nodes of the page are copied out of the device state, which
+/// keeps listing large meshes cheap.
+pub async fn get_nodes_page(
+    connection: &ConnectionManager,
+    offset: usize,
+    limit: Option<usize>,
+) -> Result<NodePage> {
+    let state = connection.get_device_state_ref();
+    let state = state.lock().await;
+    Ok(node_page(&state, offset, limit))
+}
+
+/// Page through the nodes of a device state, most recently heard first
+///
+/// Nodes never heard sort last; ties are broken by node number so pages
+/// stay stable between calls.
+pub fn node_page(state: &DeviceState, offset: usize, limit: Option<usize>) -> NodePage {
+    let mut nodes: Vec<&NodeInfo> = state.nodes.values().collect();
+    nodes.sort_by(|a, b| b.last_heard.cmp(&a.last_heard).then(a.num.cmp(&b.num)));
+
+    NodePage {
+        total: nodes.len(),
+        nodes: nodes
+            .into_iter()
+            .skip(offset)
+            .take(limit.unwrap_or(usize::MAX))
+            .cloned()
+            .collect(),
+        offset,
+    }
 }
 
 /// Mesh network health status
@@ -210,22 +286,19 @@ pub struct NetworkStats {
     pub average_snr: Option<f32>,
     pub average_rssi: Option<i32>,
     pub mesh_health: MeshHealth,
+    pub duplicate_packets: u64,
 }
 
 pub async fn get_network_stats(connection: &ConnectionManager) -> Result<NetworkStats> {
     let state = connection.get_device_state().await;
-    let now = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_secs();
 
     let total_nodes = state.nodes.len();
 
-    // Active nodes (heard in last hour)
+    // Active nodes (not offline under the presence policy)
     let active_nodes = state
         .nodes
         .values()
-        .filter(|n| n.last_heard.is_some_and(|h| now.saturating_sub(h) < 3600))
+        .filter(|n| state.presence.is_active(n))
         .count();
 
     // Direct neighbors
@@ -261,6 +334,7 @@ pub async fn get_network_stats(connection: &ConnectionManager) -> Result<Network
         average_snr,
         average_rssi,
         mesh_health,
+        duplicate_packets: state.duplicate_packets,
     })
 }
 
@@ -275,21 +349,19 @@ pub async fn request_node_info(
     use meshtastic::protobufs;
     use tokio::time::Duration;
 
-    let api = connection.get_api()?;
-
     // Generate a unique config ID for tracking
     let config_id = rand::random::<u32>();
 
-    // Send WantConfigId to request full node database
-    api.send_to_radio_packet(Some(protobufs::to_radio::PayloadVariant::WantConfigId(
-        config_id,
-    )))
-    .await?;
+    // Send WantConfigId to request full node database; the device answers
+    // from its own database, so this is allowed in listen-only mode
+    connection
+        .send_to_radio(protobufs::to_radio::PayloadVariant::WantConfigId(config_id))
+        .await?;
 
     debug!(
         "Requesting node info for {target} via WantConfigId",
         target = node_num
-            .map(|n| format!("{n:08x}"))
+            .map(|n| NodeId(n).to_string())
             .unwrap_or_else(|| "all nodes".to_string())
     );
 
@@ -1,8 +1,14 @@
+use crate::admin::BROADCAST_NODE_NUM;
+use crate::channel::{PskStrength, psk_strength};
 use crate::connection::ConnectionManager;
+use crate::node_id::NodeId;
+use crate::state::ChannelInfo;
 use anyhow::Result;
-use meshtastic::packet::{PacketDestination, PacketReceiver};
+use meshtastic::packet::PacketReceiver;
 use meshtastic::protobufs;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use strum::Display;
 use tokio::time::{Duration, timeout};
 use tracing::debug;
 
@@ -14,35 +20,55 @@ pub async fn send_text_message(
     channel: u32,
     want_ack: bool,
 ) -> Result<()> {
-    let api = connection.get_api()?;
-
-    // Determine destination
-    let dest = match destination {
-        Some(node_num) => PacketDestination::Node(node_num.into()),
-        None => PacketDestination::Broadcast,
+    let dest = destination.unwrap_or(BROADCAST_NODE_NUM);
+    let data = protobufs::Data {
+        portnum: protobufs::PortNum::TextMessageApp as i32,
+        payload: text.as_bytes().to_vec(),
+        ..Default::default()
     };
+    connection.send_data(dest, channel, data, want_ack).await?;
 
-    // Create a simple packet router that ignores packets
-    let mut packet_router = SimplePacketRouter;
+    debug!(
+        "Text message sent to {dest} on channel {channel}",
+        dest = NodeId(dest)
+    );
+    Ok(())
+}
 
-    // Send the text message
-    api.send_text(
-        &mut packet_router,
-        text.to_string(),
-        dest,
-        want_ack,
-        channel.into(),
-    )
-    .await?;
+/// Criteria a received packet must match to be reported as a message
+///
+/// Unset fields match any value.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MessageFilter {
+    /// Sender node number
+    pub from: Option<u32>,
+    /// Destination node number
+    pub to: Option<u32>,
+    /// Channel index
+    pub channel: Option<u32>,
+    /// Our node number, so messages we sent are tagged as our own
+    ///
+    /// Not a criterion: echoes of our messages are dropped by the
+    /// [`ConnectionManager`] unless it was told to include them.
+    pub own_node: Option<u32>,
+}
 
-    debug!("Text message sent to {dest:?} on channel {channel}");
-    Ok(())
+impl MessageFilter {
+    /// Check whether a mesh packet satisfies every set criterion
+    pub fn matches(&self, packet: &protobufs::MeshPacket) -> bool {
+        self.from.is_none_or(|from| packet.from == from)
+            && self.to.is_none_or(|to| packet.to == to)
+            && self.channel.is_none_or(|channel| packet.channel == channel)
+    }
 }
 
 /// Receive messages from the mesh network
+///
+/// `channels` resolve the channel name and encryption of each message.
 pub async fn receive_messages(
     receiver: &mut PacketReceiver,
-    from_node: Option<u32>,
+    filter: &MessageFilter,
+    channels: &[ChannelInfo],
     count: Option<usize>,
     timeout_secs: u64,
 ) -> Result<Vec<ReceivedMessage>> {
@@ -54,7 +80,7 @@ pub async fn receive_messages(
     let result = timeout(timeout_duration, async {
         while messages.len() < target_count {
             if let Some(packet) = receiver.recv().await {
-                if let Some(msg) = process_packet_for_message(packet, from_node) {
+                if let Some(msg) = process_packet_for_message(packet, filter, channels) {
                     messages.push(msg);
                 }
             } else {
@@ -76,14 +102,15 @@ pub async fn receive_messages(
 /// Monitor messages in real-time
 pub async fn monitor_messages<F>(
     receiver: &mut PacketReceiver,
-    from_node: Option<u32>,
+    filter: &MessageFilter,
+    channels: &[ChannelInfo],
     mut callback: F,
 ) -> Result<()>
 where
     F: FnMut(ReceivedMessage) -> Result<()>,
 {
     while let Some(packet) = receiver.recv().await {
-        if let Some(msg) = process_packet_for_message(packet, from_node) {
+        if let Some(msg) = process_packet_for_message(packet, filter, channels) {
             callback(msg)?;
         }
     }
@@ -91,9 +118,27 @@ where
     Ok(())
 }
 
+/// Wait for the next message matching the filter
+///
+/// Returns None once the receiver is closed.
+pub async fn next_message(
+    receiver: &mut PacketReceiver,
+    filter: &MessageFilter,
+    channels: &[ChannelInfo],
+) -> Option<ReceivedMessage> {
+    while let Some(packet) = receiver.recv().await {
+        if let Some(msg) = process_packet_for_message(packet, filter, channels) {
+            return Some(msg);
+        }
+    }
+
+    None
+}
+
 fn process_packet_for_message(
     from_radio: protobufs::FromRadio,
-    from_node_filter: Option<u32>,
+    filter: &MessageFilter,
+    channels: &[ChannelInfo],
 ) -> Option<ReceivedMessage> {
     // Check if this is a mesh packet
     let mesh_packet = match from_radio.payload_variant? {
@@ -101,10 +146,7 @@ fn process_packet_for_message(
         _ => return None,
     };
 
-    // Apply from_node filter if specified
-    if let Some(filter) = from_node_filter
-        && mesh_packet.from != filter
-    {
+    if !filter.matches(&mesh_packet) {
         return None;
     }
 
@@ -119,73 +161,184 @@ fn process_packet_for_message(
         return None;
     }
 
-    // Parse text from payload
-    let text = String::from_utf8_lossy(&data.payload).to_string();
+    let text = decode_text_payload(&data.payload);
+    let channel = channels
+        .iter()
+        .find(|channel| channel.index == mesh_packet.channel);
 
     Some(ReceivedMessage {
-        from: format!("{from:08x}", from = mesh_packet.from),
+        from: NodeId(mesh_packet.from).to_string(),
         from_node: mesh_packet.from,
-        to: format!("{to:08x}", to = mesh_packet.to),
+        to: NodeId(mesh_packet.to).to_string(),
         to_node: mesh_packet.to,
         channel: mesh_packet.channel,
+        // The primary channel is often left unnamed
+        channel_name: channel
+            .filter(|channel| !channel.name.is_empty())
+            .map(|channel| channel.name.clone()),
+        encryption: Encryption::of_packet(mesh_packet.pki_encrypted, channel),
+        via_mqtt: mesh_packet.via_mqtt,
+        own: filter.own_node == Some(mesh_packet.from),
         text,
+        // The radio stamps rx_time when it has a clock; fall back to ours
+        time: if mesh_packet.rx_time > 0 {
+            u64::from(mesh_packet.rx_time)
+        } else {
+            crate::time::unix_now()
+        },
         snr: Some(mesh_packet.rx_snr),
         rssi: Some(mesh_packet.rx_rssi),
     })
 }
 
+/// Decode a text message payload
+///
+/// Invalid UTF-8 is replaced with U+FFFD rather than dropping the message;
+/// the decoded text may still contain control characters, see
+/// [`sanitize_for_terminal`].
+pub fn decode_text_payload(payload: &[u8]) -> String {
+    match std::str::from_utf8(payload) {
+        Ok(text) => text.to_string(),
+        Err(e) => {
+            debug!("Text payload is not valid UTF-8: {e}");
+            String::from_utf8_lossy(payload).into_owned()
+        }
+    }
+}
+
+/// Make untrusted text safe to print to a terminal
+///
+/// Text from the mesh is attacker controlled: escape sequences could
+/// rewrite the screen or the window title, carriage returns could overwrite
+/// earlier output and bidi overrides could reorder it. Such characters are
+/// shown escaped, e.g. `\u{1b}`; everything else is left as is.
+pub fn sanitize_for_terminal(text: &str) -> Cow<'_, str> {
+    if !text.chars().any(is_unsafe_for_terminal) {
+        return Cow::Borrowed(text);
+    }
+
+    let mut sanitized = String::with_capacity(text.len());
+    for c in text.chars() {
+        if is_unsafe_for_terminal(c) {
+            sanitized.extend(c.escape_default());
+        } else {
+            sanitized.push(c);
+        }
+    }
+    Cow::Owned(sanitized)
+}
+
+/// Control characters (C0, DEL and C1) and bidi embedding/override/isolate marks
+fn is_unsafe_for_terminal(c: char) -> bool {
+    c.is_control() || matches!(c, '\u{202a}'..='\u{202e}' | '\u{2066}'..='\u{2069}')
+}
+
+/// How long to wait for an ACK before giving up or retransmitting
+pub const DEFAULT_ACK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Acknowledgment handling for a message sent with want_ack
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AckOptions {
+    /// How long each transmission waits for its ACK
+    pub timeout: Duration,
+    /// Retransmissions after the first send when no ACK arrives
+    pub retries: u32,
+}
+
+impl Default for AckOptions {
+    fn default() -> Self {
+        Self {
+            timeout: DEFAULT_ACK_TIMEOUT,
+            retries: 0,
+        }
+    }
+}
+
+/// Outcome of a message sent with want_ack
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AckReport {
+    pub acknowledged: bool,
+    /// Transmissions made, including the first
+    pub attempts: u32,
+    /// Id of the acknowledged transmission, or of the last one sent
+    pub packet_id: u32,
+}
+
+/// Summary of a sent text message
 #[derive(Debug, Clone, Serialize)]
+pub struct SentMessage {
+    pub text: String,
+    pub destination: String,
+    pub channel: u32,
+    pub acknowledged: Option<bool>,
+    /// Transmissions made while waiting for the ACK
+    pub attempts: Option<u32>,
+}
+
+/// How a received message was protected on the air
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum Encryption {
+    /// Encrypted to our node's public key
+    Pki,
+    /// Encrypted with the channel's pre-shared key
+    Psk,
+    /// Sent on a channel without a key
+    None,
+    /// The channel is not in the device state
+    Unknown,
+}
+
+impl Encryption {
+    /// Encryption of a packet received on `channel`
+    pub fn of_packet(pki_encrypted: bool, channel: Option<&ChannelInfo>) -> Self {
+        if pki_encrypted {
+            return Self::Pki;
+        }
+        let Some(channel) = channel else {
+            return Self::Unknown;
+        };
+        let psk = channel
+            .settings
+            .as_ref()
+            .map(|settings| settings.psk.as_slice())
+            .unwrap_or_default();
+        if psk_strength(psk) == PskStrength::None {
+            Self::None
+        } else {
+            Self::Psk
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ReceivedMessage {
     pub from: String,
     pub from_node: u32,
     pub to: String,
     pub to_node: u32,
     pub channel: u32,
+    /// Name of the channel, when the device reported it
+    pub channel_name: Option<String>,
+    pub encryption: Encryption,
+    /// Whether the message reached the mesh through an MQTT gateway
+    pub via_mqtt: bool,
+    /// Sent from our own node and echoed back by the device
+    pub own: bool,
     pub text: String,
+    /// Receive time in seconds since the Unix epoch
+    pub time: u64,
     pub snr: Option<f32>,
     pub rssi: Option<i32>,
 }
 
-// Simple packet router that ignores all packets
-struct SimplePacketRouter;
-
-// NodeId is exported in meshtastic::types when tokio feature is enabled
-use meshtastic::types::NodeId;
-
-impl meshtastic::packet::PacketRouter<(), std::convert::Infallible> for SimplePacketRouter {
-    fn handle_packet_from_radio(
-        &mut self,
-        packet: protobufs::FromRadio,
-    ) -> Result<(), std::convert::Infallible> {
-        if let Some(variant) = &packet.payload_variant {
-            debug!(
-                "SimplePacketRouter: Ignoring FromRadio packet (variant: {variant:?})",
-                variant = std::mem::discriminant(variant)
-            );
-        } else {
-            debug!("SimplePacketRouter: Ignoring empty FromRadio packet");
-        }
-        Ok(())
-    }
-
-    fn handle_mesh_packet(
-        &mut self,
-        packet: protobufs::MeshPacket,
-    ) -> Result<(), std::convert::Infallible> {
-        let portnum = packet.payload_variant.as_ref().and_then(|p| match p {
-            protobufs::mesh_packet::PayloadVariant::Decoded(d) => Some(d.portnum()),
-            _ => None,
-        });
-
-        debug!(
-            "SimplePacketRouter: Ignoring MeshPacket (from: {from:08x}, to: {to:08x}, portnum: {portnum:?})",
-            from = packet.from,
-            to = packet.to
-        );
-        Ok(())
-    }
-
-    fn source_node_id(&self) -> NodeId {
-        0u32.into() // Default node ID for simple router
+impl ReceivedMessage {
+    /// Check whether the text contains any of the keywords, ignoring case
+    pub fn contains_keyword(&self, keywords: &[String]) -> bool {
+        let text = self.text.to_lowercase();
+        keywords
+            .iter()
+            .any(|keyword| !keyword.is_empty() && text.contains(&keyword.to_lowercase()))
     }
 }
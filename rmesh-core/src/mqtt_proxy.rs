@@ -0,0 +1,193 @@
+use crate::connection::ConnectionManager;
+use crate::state::MqttConfig;
+use anyhow::{Context, Result, bail};
+use meshtastic::protobufs;
+use serde::Serialize;
+use strum::Display;
+use tracing::debug;
+
+/// Broker the firmware uses when no address is configured
+pub const DEFAULT_BROKER: &str = "mqtt.meshtastic.org";
+
+/// Credentials of the public broker, used with the default address
+const DEFAULT_USERNAME: &str = "meshdev";
+const DEFAULT_PASSWORD: &str = "large4cats";
+
+/// Topic root the firmware uses when none is configured
+const DEFAULT_ROOT: &str = "msh";
+
+const MQTT_PORT: u16 = 1883;
+const MQTTS_PORT: u16 = 8883;
+
+/// An MQTT publish carried between the device and the broker
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProxyMessage {
+    pub topic: String,
+    pub payload: Vec<u8>,
+    pub retained: bool,
+}
+
+/// Which way a proxied message travelled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ProxyDirection {
+    /// From the device to the broker
+    Uplink,
+    /// From the broker to the device
+    Downlink,
+}
+
+/// Record of a message shuttled by the proxy
+#[derive(Debug, Clone, Serialize)]
+pub struct ProxyTraffic {
+    pub direction: ProxyDirection,
+    pub topic: String,
+    pub bytes: usize,
+    pub retained: bool,
+}
+
+impl ProxyTraffic {
+    pub fn new(direction: ProxyDirection, message: &ProxyMessage) -> Self {
+        Self {
+            direction,
+            topic: message.topic.clone(),
+            bytes: message.payload.len(),
+            retained: message.retained,
+        }
+    }
+}
+
+/// Where and how to reach the broker for a device
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BrokerSettings {
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub tls: bool,
+    /// Topic filter for downlink traffic to the device
+    pub subscription: String,
+}
+
+impl BrokerSettings {
+    /// Broker settings from the device's MQTT module config
+    ///
+    /// Mirrors the firmware defaults: the public broker and its credentials
+    /// when no address is set, and the `msh` topic root.
+    pub fn from_config(config: &MqttConfig) -> Result<Self> {
+        let tls = config.tls_enabled;
+        let default_port = if tls { MQTTS_PORT } else { MQTT_PORT };
+        let (host, port) = if config.address.is_empty() {
+            (DEFAULT_BROKER.to_string(), default_port)
+        } else {
+            parse_address(&config.address, default_port)?
+        };
+
+        let credential = |value: &str, default: &str| {
+            if !value.is_empty() {
+                Some(value.to_string())
+            } else if config.address.is_empty() {
+                Some(default.to_string())
+            } else {
+                None
+            }
+        };
+        let root = if config.root.is_empty() {
+            DEFAULT_ROOT
+        } else {
+            config.root.trim_end_matches('/')
+        };
+
+        Ok(Self {
+            host,
+            port,
+            username: credential(&config.username, DEFAULT_USERNAME),
+            password: credential(&config.password, DEFAULT_PASSWORD),
+            tls,
+            // Encrypted envelopes of every channel; the firmware drops what
+            // it has no downlink for
+            subscription: format!("{root}/2/e/#"),
+        })
+    }
+}
+
+/// Split `host[:port]` into host and port
+///
+/// IPv6 addresses take a port only in brackets, as in `[::1]:1883`; a bare
+/// IPv6 address is all host.
+pub fn parse_address(address: &str, default_port: u16) -> Result<(String, u16)> {
+    let (host, port) = match address.strip_prefix('[') {
+        Some(rest) => {
+            let (host, rest) = rest
+                .split_once(']')
+                .with_context(|| format!("MQTT broker address '{address}' has no closing ']'"))?;
+            let port = match rest {
+                "" => None,
+                _ => Some(rest.strip_prefix(':').with_context(|| {
+                    format!("Unexpected '{rest}' after the host in '{address}'")
+                })?),
+            };
+            (host, port)
+        }
+        None => match address.split_once(':') {
+            Some((host, port)) if !port.contains(':') => (host, Some(port)),
+            _ => (address, None),
+        },
+    };
+    let port = match port {
+        Some(port) => port
+            .parse()
+            .with_context(|| format!("Invalid MQTT port '{port}'"))?,
+        None => default_port,
+    };
+    if host.is_empty() {
+        bail!("MQTT broker address '{address}' has no host");
+    }
+    Ok((host.to_string(), port))
+}
+
+/// Proxy message the device wants published, if the frame carries one
+pub fn uplink(frame: &protobufs::FromRadio) -> Option<ProxyMessage> {
+    let Some(protobufs::from_radio::PayloadVariant::MqttClientProxyMessage(message)) =
+        &frame.payload_variant
+    else {
+        return None;
+    };
+
+    let payload = match &message.payload_variant {
+        Some(protobufs::mqtt_client_proxy_message::PayloadVariant::Data(data)) => data.clone(),
+        Some(protobufs::mqtt_client_proxy_message::PayloadVariant::Text(text)) => {
+            text.clone().into_bytes()
+        }
+        None => return None,
+    };
+    Some(ProxyMessage {
+        topic: message.topic.clone(),
+        payload,
+        retained: message.retained,
+    })
+}
+
+/// Hand a message received from the broker to the device
+pub async fn downlink(connection: &mut ConnectionManager, message: ProxyMessage) -> Result<()> {
+    debug!(
+        "Forwarding {len} byte MQTT message on {topic} to the device",
+        len = message.payload.len(),
+        topic = message.topic
+    );
+    let proxy_message = protobufs::MqttClientProxyMessage {
+        topic: message.topic,
+        payload_variant: Some(protobufs::mqtt_client_proxy_message::PayloadVariant::Data(
+            message.payload,
+        )),
+        retained: message.retained,
+    };
+    connection
+        .send_to_radio(protobufs::to_radio::PayloadVariant::MqttClientProxyMessage(
+            proxy_message,
+        ))
+        .await
+        .context("Failed to send MQTT proxy message to the device")?;
+    Ok(())
+}
@@ -0,0 +1,103 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// A node number, shown in the standard Meshtastic form `!67ea9400`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct NodeId(pub u32);
+
+impl NodeId {
+    /// Address of every node on the mesh
+    pub const BROADCAST: NodeId = NodeId(u32::MAX);
+
+    pub fn num(self) -> u32 {
+        self.0
+    }
+}
+
+impl fmt::Display for NodeId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "!{num:08x}", num = self.0)
+    }
+}
+
+impl From<u32> for NodeId {
+    fn from(num: u32) -> Self {
+        Self(num)
+    }
+}
+
+impl From<NodeId> for u32 {
+    fn from(id: NodeId) -> Self {
+        id.0
+    }
+}
+
+impl FromStr for NodeId {
+    type Err = NodeIdError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        parse_node_id(input).map(Self)
+    }
+}
+
+impl Serialize for NodeId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for NodeId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let input = String::deserialize(deserializer)?;
+        input.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Why a node id could not be parsed
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum NodeIdError {
+    #[error("Node id is empty")]
+    Empty,
+
+    /// Hex digits without a prefix, which could be mistaken for decimal
+    #[error("'{input}' looks like a hex node id; write it as '!{input}' or '0x{input}'")]
+    MissingPrefix { input: String },
+
+    #[error("Invalid node id '{input}'; use '!67ea9400', '0x67ea9400' or a decimal node number")]
+    Invalid { input: String },
+}
+
+/// Parse a node id into its node number
+///
+/// Accepts the standard Meshtastic form `!67ea9400`, 0x-prefixed hex and
+/// decimal node numbers. Hex without a prefix is refused, since a number
+/// such as `12345678` reads as both.
+pub fn parse_node_id(input: &str) -> Result<u32, NodeIdError> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Err(NodeIdError::Empty);
+    }
+
+    let hex = input
+        .strip_prefix('!')
+        .or_else(|| input.strip_prefix("0x"))
+        .or_else(|| input.strip_prefix("0X"));
+    let parsed = match hex {
+        Some(digits) if digits.len() <= 8 => u32::from_str_radix(digits, 16).ok(),
+        Some(_) => None,
+        None => input.parse().ok(),
+    };
+
+    parsed.ok_or_else(|| {
+        if hex.is_none() && input.len() <= 8 && input.chars().all(|c| c.is_ascii_hexdigit()) {
+            NodeIdError::MissingPrefix {
+                input: input.to_string(),
+            }
+        } else {
+            NodeIdError::Invalid {
+                input: input.to_string(),
+            }
+        }
+    })
+}
@@ -0,0 +1,460 @@
+use crate::admin::BROADCAST_NODE_NUM;
+use crate::node_id::{NodeId, parse_node_id};
+use crate::time::unix_now;
+use anyhow::Result;
+use meshtastic::packet::PacketReceiver;
+use meshtastic::protobufs::{self, mesh_packet};
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::str::FromStr;
+
+/// A received mesh packet, reduced to the fields a filter can test
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PacketSummary {
+    pub id: u32,
+    pub from: String,
+    pub from_node: u32,
+    pub to: String,
+    pub to_node: u32,
+    pub channel: u32,
+    /// Port name such as `TEXT_MESSAGE_APP`, `None` while encrypted
+    pub port: Option<String>,
+    pub encrypted: bool,
+    /// Payload bytes, encrypted or not
+    pub size: usize,
+    pub snr: f32,
+    pub rssi: i32,
+    pub hop_limit: u32,
+    pub hop_start: u32,
+    /// Hops taken so far, `None` when the sender did not report its hop start
+    pub hops: Option<u32>,
+    pub want_ack: bool,
+    pub via_mqtt: bool,
+    /// Unix time the packet was received
+    pub time: u64,
+}
+
+impl PacketSummary {
+    pub fn from_packet(packet: &protobufs::MeshPacket) -> Self {
+        let (port, encrypted, size) = match &packet.payload_variant {
+            Some(mesh_packet::PayloadVariant::Decoded(data)) => (
+                Some(data.portnum().as_str_name().to_string()),
+                false,
+                data.payload.len(),
+            ),
+            Some(mesh_packet::PayloadVariant::Encrypted(bytes)) => (None, true, bytes.len()),
+            None => (None, false, 0),
+        };
+
+        Self {
+            id: packet.id,
+            from: NodeId(packet.from).to_string(),
+            from_node: packet.from,
+            to: NodeId(packet.to).to_string(),
+            to_node: packet.to,
+            channel: packet.channel,
+            port,
+            encrypted,
+            size,
+            snr: packet.rx_snr,
+            rssi: packet.rx_rssi,
+            hop_limit: packet.hop_limit,
+            hop_start: packet.hop_start,
+            // Firmware before 2.3 leaves hop_start at zero
+            hops: (packet.hop_start > 0).then(|| packet.hop_start.saturating_sub(packet.hop_limit)),
+            want_ack: packet.want_ack,
+            via_mqtt: packet.via_mqtt,
+            // The radio stamps rx_time when it has a clock; fall back to ours
+            time: if packet.rx_time > 0 {
+                u64::from(packet.rx_time)
+            } else {
+                unix_now()
+            },
+        }
+    }
+}
+
+/// Packet fields usable in a filter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Id,
+    From,
+    To,
+    Channel,
+    Port,
+    Encrypted,
+    Size,
+    Snr,
+    Rssi,
+    HopLimit,
+    HopStart,
+    Hops,
+    WantAck,
+    ViaMqtt,
+}
+
+const FIELDS: &[(&str, Field)] = &[
+    ("id", Field::Id),
+    ("from", Field::From),
+    ("to", Field::To),
+    ("channel", Field::Channel),
+    ("port", Field::Port),
+    ("encrypted", Field::Encrypted),
+    ("size", Field::Size),
+    ("snr", Field::Snr),
+    ("rssi", Field::Rssi),
+    ("hop_limit", Field::HopLimit),
+    ("hop_start", Field::HopStart),
+    ("hops", Field::Hops),
+    ("want_ack", Field::WantAck),
+    ("via_mqtt", Field::ViaMqtt),
+];
+
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+enum Value {
+    Number(f64),
+    Text(String),
+    Bool(bool),
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Self> {
+        FIELDS
+            .iter()
+            .find(|(field, _)| field.eq_ignore_ascii_case(name))
+            .map(|&(_, field)| field)
+    }
+
+    fn is_bool(self) -> bool {
+        matches!(self, Field::Encrypted | Field::WantAck | Field::ViaMqtt)
+    }
+
+    fn value(self, packet: &PacketSummary) -> Option<Value> {
+        let number = |n: u32| Some(Value::Number(f64::from(n)));
+        match self {
+            Field::Id => number(packet.id),
+            Field::From => number(packet.from_node),
+            Field::To => number(packet.to_node),
+            Field::Channel => number(packet.channel),
+            Field::Port => packet.port.clone().map(Value::Text),
+            Field::Encrypted => Some(Value::Bool(packet.encrypted)),
+            Field::Size => Some(Value::Number(packet.size as f64)),
+            Field::Snr => Some(Value::Number(f64::from(packet.snr))),
+            Field::Rssi => Some(Value::Number(f64::from(packet.rssi))),
+            Field::HopLimit => number(packet.hop_limit),
+            Field::HopStart => number(packet.hop_start),
+            Field::Hops => packet.hops.and_then(number),
+            Field::WantAck => Some(Value::Bool(packet.want_ack)),
+            Field::ViaMqtt => Some(Value::Bool(packet.via_mqtt)),
+        }
+    }
+
+    /// Parse the right-hand side of a comparison against this field
+    fn operand(self, raw: &str, op: CompareOp) -> Result<Value, String> {
+        match self {
+            Field::From | Field::To => {
+                if raw == "^all" {
+                    return Ok(Value::Number(f64::from(BROADCAST_NODE_NUM)));
+                }
+                parse_node_id(raw)
+                    .map(|num| Value::Number(f64::from(num)))
+                    .map_err(|e| e.to_string())
+            }
+            Field::Port => {
+                if !op.is_equality() {
+                    return Err("Ports can only be compared with == or !=".to_string());
+                }
+                // Accept TEXT_MESSAGE_APP, text_message_app and TEXT_MESSAGE
+                let name = raw.to_ascii_uppercase();
+                [name.clone(), format!("{name}_APP")]
+                    .into_iter()
+                    .find_map(|name| protobufs::PortNum::from_str_name(&name))
+                    .map(|port| Value::Text(port.as_str_name().to_string()))
+                    .ok_or_else(|| format!("Unknown port '{raw}'"))
+            }
+            Field::Encrypted | Field::WantAck | Field::ViaMqtt => {
+                if !op.is_equality() {
+                    return Err("Flags can only be compared with == or !=".to_string());
+                }
+                raw.parse()
+                    .map(Value::Bool)
+                    .map_err(|_| format!("Expected true or false, got '{raw}'"))
+            }
+            _ => raw
+                .parse()
+                .map(Value::Number)
+                .map_err(|_| format!("Expected a number, got '{raw}'")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// Longer operators first, so `<=` is not read as `<`
+const OPERATORS: &[(&str, CompareOp)] = &[
+    ("==", CompareOp::Eq),
+    ("!=", CompareOp::Ne),
+    ("<=", CompareOp::Le),
+    (">=", CompareOp::Ge),
+    ("<", CompareOp::Lt),
+    (">", CompareOp::Gt),
+];
+
+impl CompareOp {
+    fn is_equality(self) -> bool {
+        matches!(self, CompareOp::Eq | CompareOp::Ne)
+    }
+
+    fn holds(self, ordering: Option<Ordering>) -> bool {
+        match self {
+            CompareOp::Eq => ordering == Some(Ordering::Equal),
+            CompareOp::Ne => ordering != Some(Ordering::Equal),
+            CompareOp::Lt => ordering == Some(Ordering::Less),
+            CompareOp::Le => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+            CompareOp::Gt => ordering == Some(Ordering::Greater),
+            CompareOp::Ge => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Compare {
+        field: Field,
+        op: CompareOp,
+        value: Value,
+    },
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    fn matches(&self, packet: &PacketSummary) -> bool {
+        match self {
+            Expr::Compare { field, op, value } => match field.value(packet) {
+                Some(actual) => op.holds(actual.partial_cmp(value)),
+                // A missing value, such as the port of an encrypted packet,
+                // equals nothing
+                None => *op == CompareOp::Ne,
+            },
+            Expr::Not(inner) => !inner.matches(packet),
+            Expr::And(left, right) => left.matches(packet) && right.matches(packet),
+            Expr::Or(left, right) => left.matches(packet) || right.matches(packet),
+        }
+    }
+}
+
+/// A filter expression that could not be parsed
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("{message} at column {column}")]
+pub struct FilterError {
+    /// 1-based character position of the problem
+    pub column: usize,
+    pub message: String,
+}
+
+/// A `--filter` expression over packet fields
+///
+/// Comparisons such as `snr<-10` or `from==!abc123` are combined with `&&`,
+/// `||`, `!` and parentheses. Nodes are given in any form node ids are
+/// accepted in, ports by name with or without the `_APP` suffix, and flags
+/// such as `want_ack` may stand alone to mean `want_ack==true`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PacketFilter {
+    expr: Expr,
+}
+
+impl PacketFilter {
+    pub fn matches(&self, packet: &PacketSummary) -> bool {
+        self.expr.matches(packet)
+    }
+}
+
+impl FromStr for PacketFilter {
+    type Err = FilterError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser { input, pos: 0 };
+        let expr = parser.parse_or()?;
+        parser.skip_whitespace();
+        if let Some(c) = parser.rest().chars().next() {
+            return Err(parser.error(format!("Unexpected '{c}'")));
+        }
+        Ok(Self { expr })
+    }
+}
+
+/// Recursive descent parser, `&&` binding tighter than `||`
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &str {
+        &self.input[self.pos..]
+    }
+
+    fn error(&self, message: String) -> FilterError {
+        self.error_at(self.pos, message)
+    }
+
+    fn error_at(&self, pos: usize, message: String) -> FilterError {
+        FilterError {
+            column: self.input[..pos].chars().count() + 1,
+            message,
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    /// Consume `token` if it comes next
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        if self.rest().starts_with(token) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn take_while(&mut self, keep: impl Fn(char) -> bool) -> &'a str {
+        let start = self.pos;
+        let len = self
+            .rest()
+            .find(|c: char| !keep(c))
+            .unwrap_or(self.rest().len());
+        self.pos += len;
+        &self.input[start..self.pos]
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, FilterError> {
+        let mut expr = self.parse_and()?;
+        while self.eat("||") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, FilterError> {
+        let mut expr = self.parse_unary()?;
+        while self.eat("&&") {
+            expr = Expr::And(Box::new(expr), Box::new(self.parse_unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, FilterError> {
+        if self.eat("!") {
+            return Ok(Expr::Not(Box::new(self.parse_unary()?)));
+        }
+        if self.eat("(") {
+            let expr = self.parse_or()?;
+            if !self.eat(")") {
+                return Err(self.error("Expected ')'".to_string()));
+            }
+            return Ok(expr);
+        }
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expr, FilterError> {
+        self.skip_whitespace();
+        let start = self.pos;
+        let name = self.take_while(|c| c.is_ascii_alphanumeric() || c == '_');
+        if name.is_empty() {
+            return Err(self.error("Expected a field name".to_string()));
+        }
+        let field = Field::from_name(name).ok_or_else(|| {
+            let known = FIELDS
+                .iter()
+                .map(|(name, _)| *name)
+                .collect::<Vec<_>>()
+                .join(", ");
+            self.error_at(
+                start,
+                format!("Unknown field '{name}', expected one of {known}"),
+            )
+        })?;
+
+        self.skip_whitespace();
+        let Some(&(symbol, op)) = OPERATORS
+            .iter()
+            .find(|(symbol, _)| self.rest().starts_with(*symbol))
+        else {
+            if field.is_bool() {
+                return Ok(Expr::Compare {
+                    field,
+                    op: CompareOp::Eq,
+                    value: Value::Bool(true),
+                });
+            }
+            return Err(self.error("Expected a comparison such as == or <".to_string()));
+        };
+        self.pos += symbol.len();
+
+        self.skip_whitespace();
+        let value_start = self.pos;
+        let raw = self.parse_operand()?;
+        let value = field
+            .operand(&raw, op)
+            .map_err(|message| self.error_at(value_start, message))?;
+        Ok(Expr::Compare { field, op, value })
+    }
+
+    /// A double-quoted string, or everything up to whitespace or an operator
+    fn parse_operand(&mut self) -> Result<String, FilterError> {
+        if self.eat("\"") {
+            let start = self.pos;
+            let Some(len) = self.rest().find('"') else {
+                return Err(self.error_at(start - 1, "Unterminated string".to_string()));
+            };
+            self.pos += len + 1;
+            return Ok(self.input[start..start + len].to_string());
+        }
+
+        let raw = self.take_while(|c| !c.is_whitespace() && !matches!(c, '(' | ')' | '&' | '|'));
+        if raw.is_empty() {
+            return Err(self.error("Expected a value".to_string()));
+        }
+        Ok(raw.to_string())
+    }
+}
+
+/// Monitor every mesh packet in real-time, passing those matching `filter`
+/// to `callback`
+pub async fn monitor_packets<F>(
+    receiver: &mut PacketReceiver,
+    filter: Option<&PacketFilter>,
+    mut callback: F,
+) -> Result<()>
+where
+    F: FnMut(PacketSummary) -> Result<()>,
+{
+    while let Some(from_radio) = receiver.recv().await {
+        let Some(protobufs::from_radio::PayloadVariant::Packet(packet)) =
+            from_radio.payload_variant
+        else {
+            continue;
+        };
+        let summary = PacketSummary::from_packet(&packet);
+        if filter.is_none_or(|filter| filter.matches(&summary)) {
+            callback(summary)?;
+        }
+    }
+
+    Ok(())
+}
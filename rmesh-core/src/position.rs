@@ -1,12 +1,19 @@
-use crate::connection::ConnectionManager;
+use crate::admin::{AdminDestination, BROADCAST_NODE_NUM, send_admin_message};
+use crate::channel::{FULL_POSITION_PRECISION, position_precision_m};
+use crate::connection::{ConnectionManager, RequestResponse, SendPriority};
+use crate::geofence::haversine_distance_m;
+use crate::node_id::NodeId;
+use crate::progress::{ProgressCallback, ProgressReporter};
 use crate::state::Position;
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, ensure};
 use meshtastic::Message;
-use meshtastic::packet::{PacketDestination, PacketReceiver};
+use meshtastic::packet::PacketReceiver;
 use meshtastic::protobufs;
-use meshtastic::types::EncodedMeshPacketData;
+use serde::Serialize;
 use std::collections::HashMap;
-use tokio::time::{Duration, timeout};
+use strum::Display;
+use tokio::task::JoinSet;
+use tokio::time::{Duration, Instant, timeout};
 use tracing::{debug, info};
 
 /// Get position for a specific node
@@ -40,87 +47,68 @@ pub async fn request_position(
         let state = connection.get_device_state().await;
         if let Some(existing_pos) = state.positions.get(&node_num) {
             // If we have position data less than 60 seconds old, return it
-            let current_time = std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap_or_default()
-                .as_secs();
+            let current_time = crate::time::unix_now();
             if current_time - existing_pos.last_updated < 60 {
-                debug!("Returning cached position for node {node_num:08x}");
+                debug!(
+                    "Returning cached position for node {node}",
+                    node = NodeId(node_num)
+                );
                 return Ok(Some(existing_pos.clone()));
             }
         }
     }
 
-    // Create an empty position packet to request position
-    let position = protobufs::Position::default();
-
-    // Create a simple packet router
-    let mut packet_router = SimplePacketRouter;
-
-    // Get API and send position request with wantResponse flag
-    let api = connection.get_api()?;
-
-    // Encode position to bytes
-    let byte_data: EncodedMeshPacketData = position.encode_to_vec().into();
-
-    // Send mesh packet directly with want_response set to true
-    api.send_mesh_packet(
-        &mut packet_router,
-        byte_data,
-        protobufs::PortNum::PositionApp,
-        PacketDestination::Node(node_num.into()),
-        0.into(), // primary channel
-        false,    // want_ack
-        true,     // want_response - THIS IS THE KEY!
-        false,    // echo_response
-        None,     // reply_id
-        None,     // emoji
-    )
-    .await?;
-
-    debug!("Sent position request to node {node_num:08x} with wantResponse=true");
-
-    // Wait for the response to be processed by the background task
-    // We'll poll the device state for updates
-    let start_time = std::time::Instant::now();
-    let timeout_duration = Duration::from_secs(timeout_secs);
+    // An empty position with want_response asks the node for its position
+    let pending = connection
+        .send_request(
+            node_num,
+            protobufs::PortNum::PositionApp,
+            protobufs::Position::default().encode_to_vec(),
+        )
+        .await?;
+
+    debug!(
+        "Sent position request to node {node} with wantResponse=true",
+        node = NodeId(node_num)
+    );
 
-    while start_time.elapsed() < timeout_duration {
-        // Check if we've received an update
-        {
-            let state = connection.get_device_state().await;
-            if let Some(pos) = state.positions.get(&node_num) {
-                // Check if this position is newer than when we started
-                let current_time = std::time::SystemTime::now()
-                    .duration_since(std::time::UNIX_EPOCH)
-                    .unwrap_or_default()
-                    .as_secs();
-                if pos.last_updated > (current_time - timeout_secs) {
-                    debug!("Received position response from node {node_num:08x}");
-                    return Ok(Some(pos.clone()));
-                }
-            }
+    match pending.wait(Duration::from_secs(timeout_secs)).await {
+        Some(RequestResponse::Position(position)) => {
+            debug!(
+                "Received position response from node {node}",
+                node = NodeId(node_num)
+            );
+            Ok(Some(position))
+        }
+        Some(RequestResponse::NoPosition) => {
+            debug!(
+                "Node {node} has no position to share",
+                node = NodeId(node_num)
+            );
+            Ok(None)
+        }
+        _ => {
+            debug!("Position request timeout after {timeout_secs} seconds");
+            Ok(None)
         }
-
-        // Wait a bit before checking again
-        tokio::time::sleep(Duration::from_millis(100)).await;
     }
-
-    debug!("Position request timeout after {timeout_secs} seconds");
-    Ok(None)
 }
 
-/// Set the position of the connected device
-pub async fn set_position(
-    connection: &mut ConnectionManager,
+/// Position packet for the given coordinates, stamped with the current time
+fn position_packet(
     latitude: f64,
     longitude: f64,
     altitude: Option<i32>,
-) -> Result<()> {
-    let api = connection.get_api()?;
-
-    // Create position protobuf
-    let position = protobufs::Position {
+) -> Result<protobufs::Position> {
+    ensure!(
+        (-90.0..=90.0).contains(&latitude),
+        "Latitude {latitude} is outside -90..90"
+    );
+    ensure!(
+        (-180.0..=180.0).contains(&longitude),
+        "Longitude {longitude} is outside -180..180"
+    );
+    Ok(protobufs::Position {
         latitude_i: Some((latitude * 1e7) as i32),
         longitude_i: Some((longitude * 1e7) as i32),
         altitude,
@@ -129,25 +117,137 @@ pub async fn set_position(
             .context("Failed to get system time")?
             .as_secs() as u32,
         ..Default::default()
+    })
+}
+
+/// Set the position of the connected device
+pub async fn set_position(
+    connection: &mut ConnectionManager,
+    latitude: f64,
+    longitude: f64,
+    altitude: Option<i32>,
+) -> Result<()> {
+    let position = position_packet(latitude, longitude, altitude)?;
+    let data = protobufs::Data {
+        portnum: protobufs::PortNum::PositionApp as i32,
+        payload: position.encode_to_vec(),
+        ..Default::default()
     };
+    // Broadcast on the primary channel
+    connection
+        .send_data(BROADCAST_NODE_NUM, 0, data, true)
+        .await?;
 
-    // Create a simple packet router
-    let mut packet_router = SimplePacketRouter;
+    debug!("Position set to {latitude}, {longitude}, alt: {altitude:?}");
+    Ok(())
+}
 
-    // Send position update
-    api.send_position(
-        &mut packet_router,
-        position,
-        PacketDestination::Broadcast,
-        true,     // want_ack
-        0.into(), // primary channel
+/// Give a node a fixed position, which it reports instead of a GPS fix
+///
+/// Remote nodes are reached through remote admin, so managed field nodes
+/// with a wrong position can be corrected without a visit.
+pub async fn set_fixed_position(
+    connection: &mut ConnectionManager,
+    destination: AdminDestination,
+    latitude: f64,
+    longitude: f64,
+    altitude: Option<i32>,
+) -> Result<()> {
+    let position = position_packet(latitude, longitude, altitude)?;
+    send_admin_message(
+        connection,
+        destination,
+        protobufs::admin_message::PayloadVariant::SetFixedPosition(position),
     )
     .await?;
-
-    debug!("Position set to {latitude}, {longitude}, alt: {altitude:?}");
+    debug!("Fixed position {latitude}, {longitude}, alt: {altitude:?} sent to {destination}");
     Ok(())
 }
 
+/// Smallest distance a read-back position may differ by, for rounding
+const MIN_POSITION_TOLERANCE_M: f64 = 10.0;
+
+/// Distance from the requested position a report may be off by, given the
+/// precision the node shares its position with
+pub(crate) fn position_tolerance_m(precision_bits: Option<u32>) -> f64 {
+    // A whole grid cell, as the node may sit at its edge; with no bits
+    // shared any position matches
+    position_precision_m(precision_bits.unwrap_or(FULL_POSITION_PRECISION))
+        .map_or(f64::INFINITY, |precision_m| precision_m * 2.0)
+        .max(MIN_POSITION_TOLERANCE_M)
+}
+
+/// A fixed position set on a node, checked against what it reports
+#[derive(Debug, Clone, Serialize)]
+pub struct FixedPositionReport {
+    /// Node given the position, e.g. `!abcd1234`
+    pub node: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub altitude: Option<i32>,
+    /// Position the node reported afterwards, `None` if it did not answer
+    pub reported: Option<Position>,
+    /// Meters between the requested and the reported position
+    pub offset_m: Option<f64>,
+    /// The reported position matches within its precision
+    pub verified: bool,
+}
+
+impl FixedPositionReport {
+    pub(crate) fn new(
+        node: u32,
+        latitude: f64,
+        longitude: f64,
+        altitude: Option<i32>,
+        reported: Option<Position>,
+    ) -> Self {
+        let offset_m = reported.as_ref().map(|position| {
+            haversine_distance_m(latitude, longitude, position.latitude, position.longitude)
+        });
+        let verified = reported
+            .as_ref()
+            .zip(offset_m)
+            .is_some_and(|(position, offset)| {
+                offset <= position_tolerance_m(position.precision_bits)
+            });
+        Self {
+            node: NodeId(node).to_string(),
+            latitude,
+            longitude,
+            altitude,
+            reported,
+            offset_m,
+            verified,
+        }
+    }
+}
+
+/// Ask `node` for its position, bypassing the cache, and compare it to the
+/// fixed position just set
+pub async fn verify_fixed_position(
+    connection: &mut ConnectionManager,
+    node: u32,
+    latitude: f64,
+    longitude: f64,
+    altitude: Option<i32>,
+    timeout: Duration,
+) -> Result<FixedPositionReport> {
+    let pending = connection
+        .send_request(
+            node,
+            protobufs::PortNum::PositionApp,
+            protobufs::Position::default().encode_to_vec(),
+        )
+        .await?;
+    let reported = match pending.wait(timeout).await {
+        Some(RequestResponse::Position(position)) => Some(position),
+        _ => None,
+    };
+    Ok(FixedPositionReport::new(
+        node, latitude, longitude, altitude, reported,
+    ))
+}
+
 /// Track positions from multiple nodes
 pub async fn track_positions(
     receiver: &mut PacketReceiver,
@@ -155,25 +255,49 @@ pub async fn track_positions(
     timeout_secs: u64,
 ) -> Result<Vec<Position>> {
     let mut positions = Vec::new();
+    monitor_positions(receiver, &node_filter, timeout_secs, |pos| {
+        positions.push(pos);
+        Ok(())
+    })
+    .await?;
+
+    Ok(positions)
+}
+
+/// Stream position updates to a callback until the timeout elapses
+pub async fn monitor_positions<F>(
+    receiver: &mut PacketReceiver,
+    node_filter: &[u32],
+    timeout_secs: u64,
+    mut callback: F,
+) -> Result<()>
+where
+    F: FnMut(Position) -> Result<()>,
+{
     let timeout_duration = Duration::from_secs(timeout_secs);
 
     // Track positions until timeout
     let result = timeout(timeout_duration, async {
         while let Some(packet) = receiver.recv().await {
-            if let Some(pos) = process_packet_for_position(packet, &node_filter) {
-                positions.push(pos);
+            if let Some(pos) = process_packet_for_position(packet, node_filter) {
+                callback(pos)?;
             }
         }
+        Ok::<_, anyhow::Error>(())
     })
     .await;
 
     // Handle timeout result
     match result {
-        Ok(_) => debug!("Position tracking completed before timeout"),
-        Err(_) => debug!("Position tracking timeout after {timeout_secs} seconds"),
+        Ok(outcome) => {
+            debug!("Position tracking completed before timeout");
+            outcome
+        }
+        Err(_) => {
+            debug!("Position tracking timeout after {timeout_secs} seconds");
+            Ok(())
+        }
     }
-
-    Ok(positions)
 }
 
 fn process_packet_for_position(
@@ -205,78 +329,25 @@ fn process_packet_for_position(
     // Decode position protobuf
     let position_proto = protobufs::Position::decode(data.payload.as_slice()).ok()?;
 
-    // Convert to our Position type
-    let (lat, lon) = (position_proto.latitude_i?, position_proto.longitude_i?);
-
-    Some(Position {
-        node_id: format!("{from:08x}", from = mesh_packet.from),
-        node_num: mesh_packet.from,
-        latitude: lat as f64 / 1e7,
-        longitude: lon as f64 / 1e7,
-        altitude: position_proto.altitude,
-        time: if position_proto.time > 0 {
-            chrono::DateTime::from_timestamp(position_proto.time as i64, 0)
-                .map(|dt| dt.to_rfc3339())
-        } else {
-            None
-        },
-        last_updated: std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs(),
-    })
-}
-
-// Simple packet router that ignores all packets
-struct SimplePacketRouter;
-
-// NodeId is exported in meshtastic::types when tokio feature is enabled
-use meshtastic::types::NodeId;
-
-impl meshtastic::packet::PacketRouter<(), std::convert::Infallible> for SimplePacketRouter {
-    fn handle_packet_from_radio(
-        &mut self,
-        packet: protobufs::FromRadio,
-    ) -> Result<(), std::convert::Infallible> {
-        if let Some(variant) = &packet.payload_variant {
-            debug!(
-                "SimplePacketRouter: Ignoring FromRadio packet (variant: {variant:?})",
-                variant = std::mem::discriminant(variant)
-            );
-        } else {
-            debug!("SimplePacketRouter: Ignoring empty FromRadio packet");
-        }
-        Ok(())
-    }
-
-    fn handle_mesh_packet(
-        &mut self,
-        packet: protobufs::MeshPacket,
-    ) -> Result<(), std::convert::Infallible> {
-        let portnum = packet.payload_variant.as_ref().and_then(|p| match p {
-            protobufs::mesh_packet::PayloadVariant::Decoded(d) => Some(d.portnum()),
-            _ => None,
-        });
-
-        debug!(
-            "SimplePacketRouter: Ignoring MeshPacket (from: {from:08x}, to: {to:08x}, portnum: {portnum:?})",
-            from = packet.from,
-            to = packet.to
-        );
-        Ok(())
-    }
-
-    fn source_node_id(&self) -> NodeId {
-        0u32.into() // Default node ID for simple router
-    }
+    Position::from_protobuf(mesh_packet.from, &position_proto)
 }
 
 /// Collect positions from all nodes for a specified duration
 pub async fn collect_positions(
     connection: &mut ConnectionManager,
     wait_seconds: u64,
+) -> Result<HashMap<u32, Position>> {
+    collect_positions_with_progress(connection, wait_seconds, None).await
+}
+
+/// Collect positions, reporting elapsed seconds of the collection window
+pub async fn collect_positions_with_progress(
+    connection: &mut ConnectionManager,
+    wait_seconds: u64,
+    progress: Option<ProgressCallback<'_>>,
 ) -> Result<HashMap<u32, Position>> {
     info!("Collecting position broadcasts for {wait_seconds} seconds...");
+    let mut reporter = ProgressReporter::start(progress, "collect_positions", Some(wait_seconds));
 
     // Record initial state
     let initial_state = connection.get_device_state().await;
@@ -288,10 +359,8 @@ pub async fn collect_positions(
     // Poll for new positions during the wait period
     let start_time = std::time::Instant::now();
     let timeout_duration = Duration::from_secs(wait_seconds);
-    let mut last_check_time = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_secs();
+    let mut last_check_time = crate::time::unix_now();
+    let mut reported_count = 0;
 
     while start_time.elapsed() < timeout_duration {
         // Get current state
@@ -301,20 +370,34 @@ pub async fn collect_positions(
         for (node_num, position) in &state.positions {
             // Check if this position is new or updated since we started
             if position.last_updated > last_check_time {
-                debug!("Received position update from node {node_num:08x}");
+                debug!(
+                    "Received position update from node {node}",
+                    node = NodeId(*node_num)
+                );
                 collected_positions.insert(*node_num, position.clone());
             }
         }
 
         // Update check time
-        last_check_time = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs();
+        last_check_time = crate::time::unix_now();
+
+        // Report the running count only when it changes
+        let count = collected_positions.len();
+        let message = (count != reported_count).then(|| format!("{count} position update(s)"));
+        reported_count = count;
+        reporter.set_completed(start_time.elapsed().as_secs(), message);
 
         // Wait a bit before checking again
         tokio::time::sleep(Duration::from_millis(250)).await;
     }
+    reporter.set_completed(
+        wait_seconds,
+        Some(format!(
+            "{count} position update(s) received",
+            count = collected_positions.len()
+        )),
+    );
+    reporter.finish();
 
     // Get final state and merge all positions
     let final_state = connection.get_device_state().await;
@@ -344,6 +427,14 @@ pub async fn collect_positions(
 
 /// Send position requests to all known nodes (without waiting for responses)
 pub async fn send_position_requests(connection: &mut ConnectionManager) -> Result<()> {
+    send_position_requests_with_progress(connection, None).await
+}
+
+/// Send position requests, reporting each node that was asked
+pub async fn send_position_requests_with_progress(
+    connection: &mut ConnectionManager,
+    progress: Option<ProgressCallback<'_>>,
+) -> Result<()> {
     // Get list of all known nodes
     let state = connection.get_device_state().await;
     let node_nums: Vec<u32> = state.nodes.keys().copied().collect();
@@ -354,66 +445,174 @@ pub async fn send_position_requests(connection: &mut ConnectionManager) -> Resul
     }
 
     info!("Sending position requests to {} nodes...", node_nums.len());
+    let mut reporter = ProgressReporter::start(
+        progress,
+        "send_position_requests",
+        Some(node_nums.len() as u64),
+    );
 
     // Send position requests to all nodes
     for node_num in &node_nums {
-        // Create an empty position packet to request position
-        let position = protobufs::Position::default();
-
-        // Create a simple packet router
-        let mut packet_router = SimplePacketRouter;
-
-        // Get API and send position request with wantResponse flag
-        let api = connection.get_api()?;
-
-        // Encode position to bytes
-        let byte_data: EncodedMeshPacketData = position.encode_to_vec().into();
-
-        // Send mesh packet directly with want_response set to true
-        if let Err(e) = api
-            .send_mesh_packet(
-                &mut packet_router,
-                byte_data,
-                protobufs::PortNum::PositionApp,
-                PacketDestination::Node((*node_num).into()),
-                0.into(), // primary channel
-                false,    // want_ack
-                true,     // want_response
-                false,    // echo_response
-                None,     // reply_id
-                None,     // emoji
-            )
+        let node = NodeId(*node_num);
+        // An empty position with want_response asks for the node's position
+        let data = protobufs::Data {
+            portnum: protobufs::PortNum::PositionApp as i32,
+            payload: protobufs::Position::default().encode_to_vec(),
+            want_response: true,
+            ..Default::default()
+        };
+
+        if let Err(e) = connection
+            .send_data_with_priority(*node_num, 0, data, false, SendPriority::Background)
             .await
         {
-            debug!("Failed to send position request to {node_num:08x}: {e}");
+            debug!("Failed to send position request to {node}: {e}");
+            reporter.advance(1, Some(format!("Failed to request {node}")));
         } else {
-            debug!("Sent position request to {node_num:08x}");
+            debug!("Sent position request to {node}");
+            reporter.advance(1, Some(format!("Requested {node}")));
         }
 
         // Small delay between requests to avoid overwhelming the mesh
         tokio::time::sleep(Duration::from_millis(100)).await;
     }
 
+    reporter.finish();
     Ok(())
 }
 
+/// Outcome of a single request in a position sweep
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum PositionRequestStatus {
+    /// The node replied with its position
+    Responded,
+    /// The node replied without a position, e.g. as it has no fix
+    NoPosition,
+    /// No reply arrived before the timeout
+    TimedOut,
+    /// The request could not be sent
+    SendFailed,
+}
+
+/// Per-node result of a position sweep
+#[derive(Debug, Clone, Serialize)]
+pub struct PositionRequestResult {
+    pub node_id: String,
+    pub node_num: u32,
+    pub status: PositionRequestStatus,
+}
+
+/// Result of requesting positions from all known nodes
+#[derive(Debug, Clone, Serialize)]
+pub struct PositionSweep {
+    /// All known positions after the sweep, including previously cached ones
+    pub positions: HashMap<u32, Position>,
+    /// Outcome of each request, ordered by node number
+    pub results: Vec<PositionRequestResult>,
+}
+
 /// Request positions from all known nodes (sends requests and waits for responses)
+///
+/// Returns as soon as every node has responded or `timeout_secs` lapses.
 pub async fn request_all_positions(
     connection: &mut ConnectionManager,
-) -> Result<HashMap<u32, Position>> {
-    // Send position requests to all nodes
-    send_position_requests(connection).await?;
+    timeout_secs: u64,
+) -> Result<PositionSweep> {
+    request_all_positions_with_progress(connection, timeout_secs, None).await
+}
 
-    // Wait for responses (give 10 seconds for all nodes to respond)
-    info!("Waiting for position responses...");
-    tokio::time::sleep(Duration::from_secs(10)).await;
+/// Request positions from all known nodes, reporting each node as it resolves
+pub async fn request_all_positions_with_progress(
+    connection: &mut ConnectionManager,
+    timeout_secs: u64,
+    progress: Option<ProgressCallback<'_>>,
+) -> Result<PositionSweep> {
+    let state = connection.get_device_state().await;
+    let my_node_num = state.my_node_info.as_ref().map(|info| info.node_num);
+    let mut node_nums: Vec<u32> = state
+        .nodes
+        .keys()
+        .copied()
+        .filter(|num| Some(*num) != my_node_num)
+        .collect();
+    node_nums.sort_unstable();
 
-    // Return all collected positions from device state
-    let final_state = connection.get_device_state().await;
+    info!(
+        "Requesting positions from {count} nodes...",
+        count = node_nums.len()
+    );
+    let mut reporter = ProgressReporter::start(
+        progress,
+        "request_all_positions",
+        Some(node_nums.len() as u64),
+    );
+
+    let mut results = Vec::with_capacity(node_nums.len());
+    let mut waiting = JoinSet::new();
+    let deadline = Instant::now() + Duration::from_secs(timeout_secs);
+
+    for node_num in node_nums {
+        let node = NodeId(node_num);
+        match connection
+            .send_request_with_priority(
+                node_num,
+                protobufs::PortNum::PositionApp,
+                protobufs::Position::default().encode_to_vec(),
+                SendPriority::Background,
+            )
+            .await
+        {
+            Ok(pending) => {
+                debug!("Sent position request to {node}");
+                waiting.spawn(async move { (node_num, pending.wait_until(deadline).await) });
+            }
+            Err(e) => {
+                debug!("Failed to send position request to {node}: {e}");
+                reporter.advance(1, Some(format!("Failed to request {node}")));
+                results.push(PositionRequestResult {
+                    node_id: node.to_string(),
+                    node_num,
+                    status: PositionRequestStatus::SendFailed,
+                });
+            }
+        }
 
+        // Small delay between requests to avoid overwhelming the mesh
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    // Replies resolve in arrival order; the rest time out at the shared deadline
+    while let Some(joined) = waiting.join_next().await {
+        let (node_num, response) = joined.context("Position request task failed")?;
+        let status = match response {
+            Some(RequestResponse::Position(_)) => PositionRequestStatus::Responded,
+            Some(RequestResponse::NoPosition) => PositionRequestStatus::NoPosition,
+            _ => PositionRequestStatus::TimedOut,
+        };
+        let node = NodeId(node_num);
+        reporter.advance(1, Some(format!("{node}: {status}")));
+        results.push(PositionRequestResult {
+            node_id: node.to_string(),
+            node_num,
+            status,
+        });
+    }
+    reporter.finish();
+    results.sort_by_key(|result| result.node_num);
+
+    let responded = results
+        .iter()
+        .filter(|result| result.status == PositionRequestStatus::Responded)
+        .count();
     info!(
-        "Received positions from {} nodes",
-        final_state.positions.len()
+        "Received positions from {responded} of {total} nodes",
+        total = results.len()
     );
-    Ok(final_state.positions)
+
+    Ok(PositionSweep {
+        positions: connection.get_device_state().await.positions,
+        results,
+    })
 }
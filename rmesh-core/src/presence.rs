@@ -0,0 +1,72 @@
+use crate::state::NodeInfo;
+use serde::{Deserialize, Serialize};
+use strum::Display;
+
+/// How recently a node was heard from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum NodePresence {
+    /// Heard within the online window
+    Online,
+    /// Not heard lately, but within the offline threshold
+    Recently,
+    /// Not heard within the offline threshold, or never
+    Offline,
+}
+
+/// Thresholds deciding whether a node counts as online, recent or offline
+///
+/// Nodes that are not offline count as active in network statistics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PresencePolicy {
+    /// Nodes heard within this many seconds are online
+    pub online_secs: u64,
+    /// Nodes not heard for this many seconds are offline
+    pub offline_secs: u64,
+}
+
+impl Default for PresencePolicy {
+    fn default() -> Self {
+        Self {
+            online_secs: 15 * 60,
+            offline_secs: 60 * 60,
+        }
+    }
+}
+
+impl PresencePolicy {
+    /// Presence of a node last heard at `last_heard`, relative to `now`
+    ///
+    /// Timestamps in the future (clock skew between nodes) count as just
+    /// heard.
+    pub fn classify_at(&self, last_heard: Option<u64>, now: u64) -> NodePresence {
+        let Some(last_heard) = last_heard else {
+            return NodePresence::Offline;
+        };
+
+        let age = now.saturating_sub(last_heard);
+        if age < self.online_secs {
+            NodePresence::Online
+        } else if age < self.offline_secs {
+            NodePresence::Recently
+        } else {
+            NodePresence::Offline
+        }
+    }
+
+    /// Presence of a node last heard at `last_heard`
+    pub fn classify(&self, last_heard: Option<u64>) -> NodePresence {
+        self.classify_at(last_heard, crate::time::unix_now())
+    }
+
+    /// Presence of a node
+    pub fn presence(&self, node: &NodeInfo) -> NodePresence {
+        self.classify(node.last_heard)
+    }
+
+    /// Whether a node was heard within the offline threshold
+    pub fn is_active(&self, node: &NodeInfo) -> bool {
+        self.presence(node) != NodePresence::Offline
+    }
+}
@@ -0,0 +1,91 @@
+use serde::Serialize;
+
+/// Progress of a long-running operation, for GUI and CLI wrappers
+///
+/// Steps are operation specific: nodes for request sweeps, seconds for
+/// timed collection windows.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ProgressEvent {
+    /// The operation started
+    Started {
+        operation: &'static str,
+        total: Option<u64>,
+    },
+    /// One or more steps completed
+    Advanced {
+        operation: &'static str,
+        completed: u64,
+        total: Option<u64>,
+        message: Option<String>,
+    },
+    /// The operation finished
+    Finished {
+        operation: &'static str,
+        completed: u64,
+    },
+}
+
+/// Callback receiving progress events
+pub type ProgressCallback<'a> = &'a (dyn Fn(ProgressEvent) + Send + Sync);
+
+/// Tracks the steps of one operation and forwards them to an optional callback
+pub struct ProgressReporter<'a> {
+    callback: Option<ProgressCallback<'a>>,
+    operation: &'static str,
+    total: Option<u64>,
+    completed: u64,
+}
+
+impl<'a> ProgressReporter<'a> {
+    /// Start reporting an operation, emitting [`ProgressEvent::Started`]
+    pub fn start(
+        callback: Option<ProgressCallback<'a>>,
+        operation: &'static str,
+        total: Option<u64>,
+    ) -> Self {
+        let reporter = Self {
+            callback,
+            operation,
+            total,
+            completed: 0,
+        };
+        reporter.emit(ProgressEvent::Started { operation, total });
+        reporter
+    }
+
+    /// Record completed steps
+    pub fn advance(&mut self, steps: u64, message: Option<String>) {
+        self.set_completed(self.completed + steps, message);
+    }
+
+    /// Set the absolute number of completed steps, ignoring no-op updates
+    pub fn set_completed(&mut self, completed: u64, message: Option<String>) {
+        let completed = self.total.map_or(completed, |total| completed.min(total));
+        if completed == self.completed && message.is_none() {
+            return;
+        }
+
+        self.completed = completed;
+        self.emit(ProgressEvent::Advanced {
+            operation: self.operation,
+            completed,
+            total: self.total,
+            message,
+        });
+    }
+
+    /// Finish the operation, emitting [`ProgressEvent::Finished`]
+    pub fn finish(self) {
+        self.emit(ProgressEvent::Finished {
+            operation: self.operation,
+            completed: self.completed,
+        });
+    }
+
+    fn emit(&self, event: ProgressEvent) {
+        if let Some(callback) = self.callback {
+            callback(event);
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use serde_json::{Value, json};
+
+/// Placeholder output in place of a secret
+pub const REDACTED: &str = "<redacted>";
+
+/// Fields holding secrets, lowercase without separators so protobuf
+/// camelCase names match too
+const SECRET_FIELDS: &[&str] = &["psk", "wifipsk", "password", "privatekey"];
+
+/// Whether a field, or the last segment of a dotted config path, holds a
+/// secret
+pub fn is_secret_field(path: &str) -> bool {
+    let field = path.rsplit('.').next().unwrap_or(path);
+    let field = field.replace('_', "").to_lowercase();
+    SECRET_FIELDS.contains(&field.as_str())
+}
+
+/// Whether a serialized secret is set; unset ones stay visibly unset
+fn is_set(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::String(text) => !text.is_empty(),
+        Value::Array(bytes) => !bytes.is_empty(),
+        _ => true,
+    }
+}
+
+/// Replace a secret value with [`REDACTED`]
+///
+/// An empty secret stays empty so an unset password remains visible.
+pub fn redact_value(value: &mut Value) {
+    if is_set(value) {
+        *value = json!(REDACTED);
+    }
+}
+
+/// Redact every secret field in serialized output, at any depth
+///
+/// Serialization itself never redacts, so state saved for a later run
+/// keeps its secrets; output meant to be shared goes through this first.
+pub fn redact_secrets(value: &mut Value) {
+    replace_secrets(value, &|_| json!(REDACTED));
+}
+
+/// Replace every set secret field in serialized data, at any depth
+pub fn replace_secrets(value: &mut Value, replace: &impl Fn(&Value) -> Value) {
+    match value {
+        Value::Object(fields) => {
+            for (name, field) in fields.iter_mut() {
+                if !is_secret_field(name) {
+                    replace_secrets(field, replace);
+                } else if is_set(field) {
+                    *field = replace(field);
+                }
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                replace_secrets(item, replace);
+            }
+        }
+        _ => {}
+    }
+}
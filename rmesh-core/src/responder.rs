@@ -0,0 +1,103 @@
+use crate::message::ReceivedMessage;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Keyword-triggered automatic replies with per-sender rate limiting
+///
+/// The reply template may reference `{node}` (sender ID), `{text}` (the
+/// received text), `{snr}` and `{rssi}`.
+#[derive(Debug, Clone)]
+pub struct Responder {
+    pattern: String,
+    reply_template: String,
+    dm_only: bool,
+    cooldown: Duration,
+    last_reply: HashMap<u32, Instant>,
+}
+
+/// A reply the responder decided to send
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reply {
+    pub text: String,
+    /// Destination node, or None to broadcast
+    pub destination: Option<u32>,
+    pub channel: u32,
+}
+
+/// Record of a reply sent by the responder
+#[derive(Debug, Clone, Serialize)]
+pub struct SentReply {
+    /// Destination node ID or "Broadcast"
+    pub to: String,
+    pub channel: u32,
+    /// Text of the message that triggered the reply
+    pub trigger: String,
+    pub reply: String,
+}
+
+impl Responder {
+    pub fn new(pattern: &str, reply_template: &str, dm_only: bool, cooldown: Duration) -> Self {
+        Self {
+            pattern: pattern.to_string(),
+            reply_template: reply_template.to_string(),
+            dm_only,
+            cooldown,
+            last_reply: HashMap::new(),
+        }
+    }
+
+    /// Decide whether to reply to a message, recording the reply for rate limiting
+    pub fn respond(&mut self, message: &ReceivedMessage, my_node_num: u32) -> Option<Reply> {
+        self.respond_at(message, my_node_num, Instant::now())
+    }
+
+    /// Same as [`Responder::respond`] with an explicit current time
+    pub fn respond_at(
+        &mut self,
+        message: &ReceivedMessage,
+        my_node_num: u32,
+        now: Instant,
+    ) -> Option<Reply> {
+        // Never answer ourselves, that would loop forever
+        if message.from_node == my_node_num {
+            return None;
+        }
+
+        let is_direct = message.to_node == my_node_num;
+        if self.dm_only && !is_direct {
+            return None;
+        }
+
+        if !message.contains_keyword(std::slice::from_ref(&self.pattern)) {
+            return None;
+        }
+
+        if let Some(last) = self.last_reply.get(&message.from_node)
+            && now.saturating_duration_since(*last) < self.cooldown
+        {
+            return None;
+        }
+        self.last_reply.insert(message.from_node, now);
+
+        Some(Reply {
+            text: self.render(message),
+            destination: is_direct.then_some(message.from_node),
+            channel: message.channel,
+        })
+    }
+
+    fn render(&self, message: &ReceivedMessage) -> String {
+        self.reply_template
+            .replace("{node}", &message.from)
+            .replace("{text}", &message.text)
+            .replace(
+                "{snr}",
+                &message.snr.map(|s| format!("{s:.1}")).unwrap_or_default(),
+            )
+            .replace(
+                "{rssi}",
+                &message.rssi.map(|r| r.to_string()).unwrap_or_default(),
+            )
+    }
+}
@@ -0,0 +1,805 @@
+use crate::airtime::AirtimeSample;
+use crate::channel::{AuditSeverity, ChannelAudit, ChannelAuditFinding, ChannelInfo};
+use crate::clock::ClockReport;
+use crate::config::{ConfigListing, ConfigValue};
+use crate::connection::{LinkQuality, QualityRating};
+use crate::device::{NodeAnnouncement, RadioInfo};
+use crate::doctor::{CheckStatus, DoctorCheck, DoctorReport};
+use crate::fingerprint::{ConfigFingerprint, SectionHash};
+use crate::geofence::{GeofenceEvent, GeofenceTransition};
+use crate::link_budget::{LinkVerdict, PresetEstimate};
+use crate::lora::{FrequencySlot, ModemPreset};
+use crate::map::{AsciiMap, MapLegendEntry};
+use crate::mesh::{MeshEdge, MeshNode, MeshTopology, RouteHop};
+use crate::message::{Encryption, ReceivedMessage, SentMessage};
+use crate::mqtt_proxy::{ProxyDirection, ProxyTraffic};
+use crate::packet_filter::PacketSummary;
+use crate::position::FixedPositionReport;
+use crate::responder::SentReply;
+use crate::state::{
+    AirQualityMetrics, BluetoothConfig, ChangeRecord, DeviceConfig, DeviceMetrics, DisplayConfig,
+    EnvironmentMetrics, LoraConfig, MyNodeInfo, NeighborInfoConfig, NetworkConfig, NodeInfo,
+    Position, PositionConfig, PowerConfig, RouteEntry, TelemetryConfig, TelemetryData, User,
+};
+use crate::telemetry::{TelemetryPollResult, TelemetryPollStatus};
+use crate::transcript::PruneReport;
+use crate::waypoint::{Waypoint, WaypointImportResult, WaypointImportStatus};
+use serde_json::{Map, Value, json};
+use std::collections::HashMap;
+
+/// Version of the JSON output formats, bumped on breaking changes
+///
+/// Version 2 writes node ids in the Meshtastic form `!67ea9400`.
+pub const OUTPUT_SCHEMA_VERSION: u32 = 2;
+
+/// Commands with a published output schema
+pub const SCHEMA_COMMANDS: &[&str] = &[
+    "info radio",
+    "info nodes",
+    "info channels",
+    "info metrics",
+    "info position",
+    "info telemetry",
+    "message send",
+    "message alert",
+    "message recv",
+    "message monitor",
+    "message clear",
+    "monitor packets",
+    "config get",
+    "config list",
+    "config neighbor-info",
+    "config telemetry",
+    "config fingerprint",
+    "channel list",
+    "channel audit",
+    "position get",
+    "position set",
+    "position request",
+    "position track",
+    "position geofence",
+    "mesh topology",
+    "mesh traceroute",
+    "mesh routes",
+    "mesh events",
+    "mesh neighbors",
+    "mesh map",
+    "mesh airtime",
+    "mesh linkbudget",
+    "node announce",
+    "device clock",
+    "telemetry poll-all",
+    "waypoint import",
+    "responder",
+    "mqtt-proxy",
+    "doctor",
+];
+
+/// Types that can describe their JSON serialization as a JSON Schema
+pub trait JsonSchema {
+    fn json_schema() -> Value;
+}
+
+/// JSON Schema document for the output of a command such as "info nodes"
+///
+/// Streaming commands describe a single emitted record.
+pub fn command_schema(command: &str) -> Option<Value> {
+    let command = command.split_whitespace().collect::<Vec<_>>().join(" ");
+    let schema = match command.as_str() {
+        "info radio" => RadioInfo::json_schema(),
+        "info nodes" | "mesh neighbors" => Vec::<NodeInfo>::json_schema(),
+        "info channels" | "channel list" => Vec::<ChannelInfo>::json_schema(),
+        "info metrics" => Option::<DeviceMetrics>::json_schema(),
+        "info position" => HashMap::<u32, Position>::json_schema(),
+        "info telemetry" => HashMap::<u32, TelemetryData>::json_schema(),
+        "message send" | "message alert" => SentMessage::json_schema(),
+        "message recv" => Vec::<ReceivedMessage>::json_schema(),
+        "message monitor" => ReceivedMessage::json_schema(),
+        "message clear" => PruneReport::json_schema(),
+        "monitor packets" => PacketSummary::json_schema(),
+        "channel audit" => ChannelAudit::json_schema(),
+        "config get" => ConfigValue::json_schema(),
+        "config list" => ConfigListing::json_schema(),
+        "config neighbor-info" => NeighborInfoConfig::json_schema(),
+        "config telemetry" => TelemetryConfig::json_schema(),
+        "config fingerprint" => ConfigFingerprint::json_schema(),
+        "position get" | "position request" => Position::json_schema(),
+        "position set" => FixedPositionReport::json_schema(),
+        "position track" => Vec::<Position>::json_schema(),
+        "position geofence" => GeofenceEvent::json_schema(),
+        "mesh topology" => MeshTopology::json_schema(),
+        "mesh traceroute" => Vec::<RouteHop>::json_schema(),
+        "mesh routes" => Vec::<RouteEntry>::json_schema(),
+        "mesh events" => Vec::<ChangeRecord>::json_schema(),
+        "mesh map" => AsciiMap::json_schema(),
+        "mesh airtime" => Vec::<AirtimeSample>::json_schema(),
+        "mesh linkbudget" => Vec::<PresetEstimate>::json_schema(),
+        "node announce" => NodeAnnouncement::json_schema(),
+        "device clock" => ClockReport::json_schema(),
+        "telemetry poll-all" => Vec::<TelemetryPollResult>::json_schema(),
+        "waypoint import" => Vec::<WaypointImportResult>::json_schema(),
+        "responder" => SentReply::json_schema(),
+        "mqtt-proxy" => ProxyTraffic::json_schema(),
+        "doctor" => DoctorReport::json_schema(),
+        _ => return None,
+    };
+
+    let mut document = Map::new();
+    document.insert(
+        "$schema".to_string(),
+        json!("https://json-schema.org/draft/2020-12/schema"),
+    );
+    document.insert("title".to_string(), json!(format!("rmesh {command}")));
+    document.insert(
+        "x-rmesh-schema-version".to_string(),
+        json!(OUTPUT_SCHEMA_VERSION),
+    );
+    if let Value::Object(fields) = schema {
+        document.extend(fields);
+    }
+    Some(Value::Object(document))
+}
+
+/// Keep only `fields` of a JSON output, for `--fields`
+///
+/// A field is a top-level key or a dotted path such as `user.long_name`,
+/// looked up as a JSON pointer and emitted under the name as given. Arrays
+/// are projected element by element; missing fields are left out.
+pub fn project_fields(value: &Value, fields: &[String]) -> Value {
+    match value {
+        Value::Array(items) => Value::Array(
+            items
+                .iter()
+                .map(|item| project_fields(item, fields))
+                .collect(),
+        ),
+        Value::Object(_) => {
+            let mut projected = Map::new();
+            for field in fields {
+                let pointer = format!("/{path}", path = field.replace('.', "/"));
+                if let Some(selected) = value.pointer(&pointer) {
+                    projected.insert(field.clone(), selected.clone());
+                }
+            }
+            Value::Object(projected)
+        }
+        other => other.clone(),
+    }
+}
+
+macro_rules! impl_primitive_schema {
+    ($($ty:ty => $schema:tt),* $(,)?) => {
+        $(
+            impl JsonSchema for $ty {
+                fn json_schema() -> Value {
+                    json!($schema)
+                }
+            }
+        )*
+    };
+}
+
+impl_primitive_schema! {
+    String => {"type": "string"},
+    &'static str => {"type": "string"},
+    char => {"type": "string", "minLength": 1, "maxLength": 1},
+    bool => {"type": "boolean"},
+    u32 => {"type": "integer", "minimum": 0},
+    u64 => {"type": "integer", "minimum": 0},
+    usize => {"type": "integer", "minimum": 0},
+    i32 => {"type": "integer"},
+    i64 => {"type": "integer"},
+    f32 => {"type": "number"},
+    f64 => {"type": "number"},
+}
+
+/// Arbitrary JSON, such as a single configuration value
+impl JsonSchema for Value {
+    fn json_schema() -> Value {
+        json!({})
+    }
+}
+
+impl<T: JsonSchema> JsonSchema for Option<T> {
+    fn json_schema() -> Value {
+        json!({"anyOf": [T::json_schema(), {"type": "null"}]})
+    }
+}
+
+impl<T: JsonSchema> JsonSchema for Vec<T> {
+    fn json_schema() -> Value {
+        json!({"type": "array", "items": T::json_schema()})
+    }
+}
+
+/// Maps serialize with their keys as strings, e.g. node numbers
+impl<K, V: JsonSchema> JsonSchema for HashMap<K, V> {
+    fn json_schema() -> Value {
+        json!({"type": "object", "additionalProperties": V::json_schema()})
+    }
+}
+
+/// Implement [`JsonSchema`] for a struct from its field list
+///
+/// Every field is required since `Option` fields serialize as `null`. The
+/// exhaustive destructuring fails to compile if the list drifts from the
+/// struct definition.
+macro_rules! impl_struct_schema {
+    ($name:ident { $($field:ident: $ty:ty),* $(,)? }) => {
+        impl JsonSchema for $name {
+            fn json_schema() -> Value {
+                let _check = |value: &$name| {
+                    let $name { $($field),* } = value;
+                    $(let _: &$ty = $field;)*
+                };
+
+                let mut properties = Map::new();
+                $(properties.insert(stringify!($field).to_string(), <$ty>::json_schema());)*
+                json!({
+                    "type": "object",
+                    "properties": properties,
+                    "required": [$(stringify!($field)),*],
+                    "additionalProperties": false,
+                })
+            }
+        }
+    };
+}
+
+macro_rules! impl_string_enum_schema {
+    ($name:ident [$($value:literal),* $(,)?]) => {
+        impl JsonSchema for $name {
+            fn json_schema() -> Value {
+                json!({"type": "string", "enum": [$($value),*]})
+            }
+        }
+    };
+}
+
+impl_string_enum_schema!(GeofenceTransition["enter", "exit"]);
+impl_string_enum_schema!(AuditSeverity["info", "warning", "critical"]);
+impl_string_enum_schema!(WaypointImportStatus["planned", "sent", "send_failed"]);
+impl_string_enum_schema!(TelemetryPollStatus["responded", "timed_out", "send_failed", "skipped"]);
+impl_string_enum_schema!(CheckStatus["ok", "skipped", "warning", "failed"]);
+impl_string_enum_schema!(ProxyDirection["uplink", "downlink"]);
+impl_string_enum_schema!(Encryption["pki", "psk", "none", "unknown"]);
+impl_string_enum_schema!(LinkVerdict["reliable", "marginal", "unlikely"]);
+impl_string_enum_schema!(QualityRating["excellent", "good", "fair", "poor"]);
+impl_string_enum_schema!(
+    ModemPreset[
+        "LONG_FAST",
+        "LONG_SLOW",
+        "VERY_LONG_SLOW",
+        "MEDIUM_SLOW",
+        "MEDIUM_FAST",
+        "SHORT_SLOW",
+        "SHORT_FAST",
+        "LONG_MODERATE",
+        "SHORT_TURBO",
+    ]
+);
+
+impl_struct_schema!(DoctorCheck {
+    name: &'static str,
+    status: CheckStatus,
+    detail: String,
+    fix: Option<String>,
+});
+
+impl_struct_schema!(DoctorReport {
+    target: Option<String>,
+    checks: Vec<DoctorCheck>,
+});
+
+impl_struct_schema!(PruneReport {
+    kept: usize,
+    removed: usize,
+});
+
+impl_struct_schema!(AirtimeSample {
+    time: u64,
+    channel_utilization: Option<f32>,
+    air_util_tx: Option<f32>,
+});
+
+impl_struct_schema!(RadioInfo {
+    firmware_version: String,
+    hardware_model: String,
+    region: String,
+    node_id: String,
+    node_num: u32,
+    has_gps: bool,
+    num_channels: usize,
+    platform: Option<String>,
+    device_id: Option<String>,
+    frequency: Option<FrequencySlot>,
+    quality: Option<LinkQuality>,
+});
+
+impl_struct_schema!(LinkQuality {
+    frames_processed: u64,
+    sync_errors: u64,
+    decode_errors: u64,
+    responses: u64,
+    timeouts: u64,
+    error_rate: f64,
+    average_response_time_ms: Option<u64>,
+    rating: Option<QualityRating>,
+});
+
+impl_struct_schema!(PresetEstimate {
+    preset: ModemPreset,
+    selected: bool,
+    spread_factor: u32,
+    bandwidth_khz: f64,
+    airtime_ms: f64,
+    sensitivity_dbm: f64,
+    expected_snr_db: f64,
+    margin_db: f64,
+    verdict: LinkVerdict,
+    max_range_m: Option<f64>,
+});
+
+impl_struct_schema!(FrequencySlot {
+    region: String,
+    modem: String,
+    channel_name: String,
+    slot: u32,
+    num_slots: u32,
+    frequency_mhz: f64,
+    bandwidth_khz: f64,
+});
+
+impl_struct_schema!(User {
+    id: String,
+    long_name: String,
+    short_name: String,
+    hw_model: Option<String>,
+    role: Option<String>,
+    public_key: Option<String>,
+    is_licensed: bool,
+});
+
+impl_struct_schema!(NodeInfo {
+    id: String,
+    num: u32,
+    user: User,
+    last_heard: Option<u64>,
+    last_heard_iso: Option<String>,
+    snr: Option<f32>,
+    rssi: Option<i32>,
+    device_metrics: Option<DeviceMetrics>,
+});
+
+impl_struct_schema!(ChannelInfo {
+    index: u32,
+    name: String,
+    role: String,
+    has_psk: bool,
+    position_precision: u32,
+    position_precision_m: Option<f64>,
+    exposes_position: bool,
+});
+
+impl_struct_schema!(ChannelAuditFinding {
+    channel_index: u32,
+    channel_name: String,
+    severity: AuditSeverity,
+    issue: String,
+    recommendation: String,
+});
+
+impl_struct_schema!(ChannelAudit {
+    channels_checked: usize,
+    findings: Vec<ChannelAuditFinding>,
+});
+
+impl_struct_schema!(Position {
+    node_id: String,
+    node_num: u32,
+    latitude: f64,
+    longitude: f64,
+    altitude: Option<i32>,
+    time: Option<String>,
+    last_updated: u64,
+    ground_speed: Option<u32>,
+    ground_track: Option<f64>,
+    sats_in_view: Option<u32>,
+    precision_bits: Option<u32>,
+    fix_quality: Option<u32>,
+    pdop: Option<f64>,
+});
+
+impl_struct_schema!(FixedPositionReport {
+    node: String,
+    latitude: f64,
+    longitude: f64,
+    altitude: Option<i32>,
+    reported: Option<Position>,
+    offset_m: Option<f64>,
+    verified: bool,
+});
+
+impl_struct_schema!(DeviceMetrics {
+    battery_level: Option<u32>,
+    voltage: Option<f32>,
+    channel_utilization: Option<f32>,
+    air_util_tx: Option<f32>,
+    uptime_seconds: Option<u32>,
+});
+
+impl_struct_schema!(EnvironmentMetrics {
+    temperature: Option<f32>,
+    relative_humidity: Option<f32>,
+    barometric_pressure: Option<f32>,
+    gas_resistance: Option<f32>,
+    iaq: Option<u32>,
+    distance: Option<f32>,
+    lux: Option<f32>,
+    white_lux: Option<f32>,
+    ir_lux: Option<f32>,
+    uv_lux: Option<f32>,
+    wind_direction: Option<u32>,
+    wind_speed: Option<f32>,
+    weight: Option<f32>,
+});
+
+impl_struct_schema!(AirQualityMetrics {
+    pm10_standard: Option<u32>,
+    pm25_standard: Option<u32>,
+    pm100_standard: Option<u32>,
+    pm10_environmental: Option<u32>,
+    pm25_environmental: Option<u32>,
+    pm100_environmental: Option<u32>,
+    particles_03um: Option<u32>,
+    particles_05um: Option<u32>,
+    particles_10um: Option<u32>,
+    particles_25um: Option<u32>,
+    particles_50um: Option<u32>,
+    particles_100um: Option<u32>,
+});
+
+impl_struct_schema!(TelemetryData {
+    node_num: u32,
+    time: u64,
+    device_metrics: Option<DeviceMetrics>,
+    environment_metrics: Option<EnvironmentMetrics>,
+    air_quality_metrics: Option<AirQualityMetrics>,
+});
+
+impl_struct_schema!(SentMessage {
+    text: String,
+    destination: String,
+    channel: u32,
+    acknowledged: Option<bool>,
+    attempts: Option<u32>,
+});
+
+impl_struct_schema!(ReceivedMessage {
+    from: String,
+    from_node: u32,
+    to: String,
+    to_node: u32,
+    channel: u32,
+    channel_name: Option<String>,
+    encryption: Encryption,
+    via_mqtt: bool,
+    own: bool,
+    text: String,
+    time: u64,
+    snr: Option<f32>,
+    rssi: Option<i32>,
+});
+
+impl_struct_schema!(PacketSummary {
+    id: u32,
+    from: String,
+    from_node: u32,
+    to: String,
+    to_node: u32,
+    channel: u32,
+    port: Option<String>,
+    encrypted: bool,
+    size: usize,
+    snr: f32,
+    rssi: i32,
+    hop_limit: u32,
+    hop_start: u32,
+    hops: Option<u32>,
+    want_ack: bool,
+    via_mqtt: bool,
+    time: u64,
+});
+
+impl_struct_schema!(ConfigValue {
+    key: String,
+    value: Value,
+});
+
+impl_struct_schema!(DeviceConfig {
+    role: String,
+    button_gpio: u32,
+    buzzer_gpio: u32,
+    rebroadcast_mode: String,
+    node_info_broadcast_secs: u32,
+    tzdef: Option<String>,
+    disable_triple_click: bool,
+});
+
+impl_struct_schema!(PositionConfig {
+    position_broadcast_secs: u32,
+    position_broadcast_smart_enabled: bool,
+    fixed_position: bool,
+    gps_enabled: bool,
+    gps_mode: String,
+});
+
+impl_struct_schema!(PowerConfig {
+    is_power_saving: bool,
+    on_battery_shutdown_after_secs: u32,
+    adc_multiplier_override: f32,
+    wait_bluetooth_secs: u32,
+    sds_secs: u32,
+    ls_secs: u32,
+    min_wake_secs: u32,
+});
+
+impl_struct_schema!(NetworkConfig {
+    wifi_enabled: bool,
+    wifi_ssid: String,
+    wifi_psk: String,
+    ntp_server: String,
+    eth_enabled: bool,
+    ipv4_config: Option<String>,
+});
+
+impl_struct_schema!(DisplayConfig {
+    screen_on_secs: u32,
+    gps_format: String,
+    auto_screen_carousel_secs: u32,
+    compass_north_top: bool,
+    flip_screen: bool,
+    units: String,
+    displaymode: String,
+    heading_bold: bool,
+    wake_on_tap_or_motion: bool,
+});
+
+impl_struct_schema!(LoraConfig {
+    use_preset: bool,
+    modem_preset: String,
+    bandwidth: u32,
+    spread_factor: u32,
+    coding_rate: u32,
+    frequency_offset: f32,
+    region: String,
+    hop_limit: u32,
+    tx_enabled: bool,
+    tx_power: i32,
+    channel_num: u32,
+    ignore_mqtt: bool,
+    override_frequency: f32,
+    override_duty_cycle: bool,
+    sx126x_rx_boosted_gain: bool,
+});
+
+impl_struct_schema!(BluetoothConfig {
+    enabled: bool,
+    mode: String,
+    fixed_pin: u32,
+    device_logging_enabled: bool,
+});
+
+impl_struct_schema!(NeighborInfoConfig {
+    enabled: bool,
+    update_interval: u32,
+});
+
+impl_struct_schema!(TelemetryConfig {
+    device_update_interval: u32,
+    environment_update_interval: u32,
+    environment_measurement_enabled: bool,
+    environment_screen_enabled: bool,
+    environment_display_fahrenheit: bool,
+    air_quality_enabled: bool,
+    air_quality_interval: u32,
+    power_measurement_enabled: bool,
+    power_update_interval: u32,
+    power_screen_enabled: bool,
+});
+
+impl_struct_schema!(ConfigListing {
+    device: Option<DeviceConfig>,
+    position: Option<PositionConfig>,
+    power: Option<PowerConfig>,
+    network: Option<NetworkConfig>,
+    display: Option<DisplayConfig>,
+    lora: Option<LoraConfig>,
+    bluetooth: Option<BluetoothConfig>,
+});
+
+impl_struct_schema!(SectionHash {
+    section: String,
+    hash: String,
+});
+
+impl_struct_schema!(ConfigFingerprint {
+    fingerprint: String,
+    sections: Vec<SectionHash>,
+});
+
+impl_struct_schema!(NodeAnnouncement {
+    node_id: String,
+    long_name: String,
+    short_name: String,
+    to: String,
+    want_response: bool,
+});
+
+impl_struct_schema!(ClockReport {
+    node: String,
+    device_time: Option<u64>,
+    host_time: u64,
+    drift_secs: Option<i64>,
+    round_trip_ms: u64,
+    synced: bool,
+});
+
+impl_struct_schema!(GeofenceEvent {
+    node_id: String,
+    node_num: u32,
+    transition: GeofenceTransition,
+    latitude: f64,
+    longitude: f64,
+    distance_m: f64,
+});
+
+impl_struct_schema!(MyNodeInfo {
+    node_num: u32,
+    node_id: String,
+    reboot_count: u32,
+    min_app_version: u32,
+    device_id: String,
+    pio_env: Option<String>,
+});
+
+impl_struct_schema!(MeshNode {
+    id: String,
+    num: u32,
+    name: String,
+    snr: Option<f32>,
+    rssi: Option<i32>,
+    last_heard: Option<u64>,
+    hops_away: Option<u32>,
+});
+
+impl_struct_schema!(MeshEdge {
+    from: String,
+    to: String,
+    snr: Option<f32>,
+    rssi: Option<i32>,
+});
+
+impl_struct_schema!(MeshTopology {
+    nodes: Vec<MeshNode>,
+    edges: Vec<MeshEdge>,
+    total_nodes: usize,
+    my_node: Option<MyNodeInfo>,
+});
+
+impl_struct_schema!(RouteHop {
+    node_id: u32,
+    node_name: String,
+    hop_number: u32,
+    snr: Option<f32>,
+    rssi: Option<i32>,
+});
+
+impl_struct_schema!(RouteEntry {
+    destination: u32,
+    next_hop: u32,
+    hop_count: u32,
+    path: Vec<u32>,
+    first_seen: u64,
+    last_seen: u64,
+    observations: u32,
+});
+
+/// One object per kind of change, named by its `change` field
+impl JsonSchema for ChangeRecord {
+    fn json_schema() -> Value {
+        let variant = |change: &str, fields: &[(&str, Value)]| {
+            let mut properties = Map::new();
+            properties.insert("time".to_string(), u64::json_schema());
+            properties.insert("change".to_string(), json!({"const": change}));
+            let mut required = vec!["time", "change"];
+            for (name, schema) in fields {
+                properties.insert(name.to_string(), schema.clone());
+                required.push(*name);
+            }
+            json!({
+                "type": "object",
+                "properties": properties,
+                "required": required,
+                "additionalProperties": false,
+            })
+        };
+        let text = String::json_schema();
+        json!({"oneOf": [
+            variant("node_joined", &[("node", text.clone()), ("name", text.clone())]),
+            variant(
+                "node_renamed",
+                &[
+                    ("node", text.clone()),
+                    ("old_name", text.clone()),
+                    ("new_name", text.clone()),
+                ],
+            ),
+            variant(
+                "role_changed",
+                &[
+                    ("node", text.clone()),
+                    ("old_role", text.clone()),
+                    ("new_role", text.clone()),
+                ],
+            ),
+            variant(
+                "channel_changed",
+                &[("index", u32::json_schema()), ("name", text.clone())],
+            ),
+            variant("config_changed", &[("section", text)]),
+        ]})
+    }
+}
+
+impl_struct_schema!(MapLegendEntry {
+    symbol: char,
+    node_num: u32,
+    label: String,
+    distance_m: f64,
+});
+
+impl_struct_schema!(AsciiMap {
+    center_latitude: f64,
+    center_longitude: f64,
+    rows: Vec<String>,
+    meters_per_column: f64,
+    legend: Vec<MapLegendEntry>,
+});
+
+impl_struct_schema!(SentReply {
+    to: String,
+    channel: u32,
+    trigger: String,
+    reply: String,
+});
+
+impl_struct_schema!(ProxyTraffic {
+    direction: ProxyDirection,
+    topic: String,
+    bytes: usize,
+    retained: bool,
+});
+
+impl_struct_schema!(Waypoint {
+    id: u32,
+    name: String,
+    description: String,
+    icon: Option<char>,
+    latitude: f64,
+    longitude: f64,
+    expire: Option<u64>,
+});
+
+impl_struct_schema!(TelemetryPollResult {
+    node_id: String,
+    node_num: u32,
+    status: TelemetryPollStatus,
+    response_secs: Option<f64>,
+    telemetry: Option<TelemetryData>,
+});
+
+impl_struct_schema!(WaypointImportResult {
+    waypoint: Waypoint,
+    status: WaypointImportStatus,
+});
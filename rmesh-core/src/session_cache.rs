@@ -0,0 +1,138 @@
+use crate::time::unix_now;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Longest a cached passkey is trusted
+///
+/// The firmware issues a new passkey 150 seconds after the previous one and
+/// rejects a passkey 300 seconds after issuing it, so any passkey it hands
+/// out is still accepted 150 seconds later.
+pub const MAX_SESSION_TTL: Duration = Duration::from_secs(150);
+
+/// Default lifetime of a cached passkey, with some margin under the maximum
+pub const DEFAULT_SESSION_TTL: Duration = Duration::from_secs(120);
+
+/// Device a passkey was issued by
+///
+/// A reboot makes the device forget its passkey, so the reboot count is part
+/// of the identity.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionId {
+    /// Port or address the device was reached through
+    pub target: String,
+    pub node_num: u32,
+    pub reboot_count: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedSession {
+    #[serde(flatten)]
+    id: SessionId,
+    /// Hex encoded passkey
+    passkey: String,
+    /// Unix time after which the passkey is not used
+    expires_at: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SessionFile {
+    sessions: Vec<CachedSession>,
+}
+
+/// Admin passkeys kept on disk between runs, so consecutive commands skip
+/// the session key round trip
+///
+/// The file holds secrets and is created readable by the owner only.
+/// Unreadable or corrupt files are treated as empty.
+#[derive(Debug, Clone)]
+pub struct SessionCache {
+    path: PathBuf,
+    ttl: Duration,
+}
+
+impl SessionCache {
+    /// Cache in `path`, trusting passkeys for `ttl`, at most
+    /// [`MAX_SESSION_TTL`]
+    pub fn new(path: impl Into<PathBuf>, ttl: Duration) -> Self {
+        Self {
+            path: path.into(),
+            ttl: ttl.min(MAX_SESSION_TTL),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Passkey cached for a device, if it has not expired
+    pub fn load(&self, id: &SessionId) -> Option<Vec<u8>> {
+        self.load_at(id, unix_now())
+    }
+
+    pub(crate) fn load_at(&self, id: &SessionId, now: u64) -> Option<Vec<u8>> {
+        self.read()
+            .sessions
+            .into_iter()
+            .find(|session| session.id == *id && session.expires_at > now)
+            .and_then(|session| hex::decode(session.passkey).ok())
+    }
+
+    /// Remember a passkey just received from a device
+    pub fn store(&self, id: &SessionId, passkey: &[u8]) -> Result<()> {
+        self.store_at(id, passkey, unix_now())
+    }
+
+    pub(crate) fn store_at(&self, id: &SessionId, passkey: &[u8], now: u64) -> Result<()> {
+        let mut file = self.read();
+        // Drop expired entries along the way so the file does not grow
+        file.sessions
+            .retain(|session| session.id != *id && session.expires_at > now);
+        file.sessions.push(CachedSession {
+            id: id.clone(),
+            passkey: hex::encode(passkey),
+            expires_at: now + self.ttl.as_secs(),
+        });
+        self.write(&file)
+    }
+
+    /// Drop the passkey of a device, as when it stopped accepting it
+    pub fn forget(&self, id: &SessionId) -> Result<()> {
+        let mut file = self.read();
+        let before = file.sessions.len();
+        file.sessions.retain(|session| session.id != *id);
+        if file.sessions.len() == before {
+            return Ok(());
+        }
+        self.write(&file)
+    }
+
+    fn read(&self) -> SessionFile {
+        std::fs::read_to_string(&self.path)
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default()
+    }
+
+    fn write(&self, file: &SessionFile) -> Result<()> {
+        let text = serde_json::to_string_pretty(file)?;
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        options
+            .open(&self.path)
+            .and_then(|mut out| out.write_all(text.as_bytes()))
+            .with_context(|| {
+                format!(
+                    "Failed to write session file {path}",
+                    path = self.path.display()
+                )
+            })
+    }
+}
@@ -0,0 +1,89 @@
+use crate::admin::{AdminDestination, send_admin_message};
+use crate::channel::{CHANNEL_REQUEST_TIMEOUT, refresh_channels};
+use crate::channel_set::{ChannelSet, export_channels, import_channels};
+use crate::config::get_config_value;
+use crate::connection::ConnectionManager;
+use crate::state::NetworkConfig;
+use anyhow::{Context, Result, ensure};
+use meshtastic::protobufs;
+use tracing::debug;
+
+/// Settings captured before a change so they can be written back
+///
+/// Covers what rmesh can write: every channel slot, PSKs included, and the
+/// network settings. Held in memory only, since it carries secrets.
+#[derive(Debug, Clone)]
+pub struct SettingsSnapshot {
+    pub channels: ChannelSet,
+    /// Network settings, when the device has any
+    pub network: Option<NetworkConfig>,
+}
+
+/// Read the current settings from the device
+///
+/// Channels are asked for again rather than taken from the cache, since a
+/// slot missing from the cache would be restored as disabled.
+pub async fn take_snapshot(connection: &mut ConnectionManager) -> Result<SettingsSnapshot> {
+    refresh_channels(connection, CHANNEL_REQUEST_TIMEOUT)
+        .await
+        .context("Failed to read the channels for a snapshot")?;
+    let channels = export_channels(connection, true).await;
+    let network = read_network_config(connection).await?;
+    Ok(SettingsSnapshot { channels, network })
+}
+
+/// Write a snapshot back to the device
+///
+/// Channels are written in one settings transaction. Network settings are
+/// only written when they changed, because static IPv4 settings cannot be
+/// restored.
+pub async fn restore_snapshot(
+    connection: &mut ConnectionManager,
+    snapshot: &SettingsSnapshot,
+) -> Result<()> {
+    import_channels(connection, &snapshot.channels)
+        .await
+        .context("Failed to restore the channels")?;
+
+    let Some(network) = &snapshot.network else {
+        return Ok(());
+    };
+    if read_network_config(connection).await?.as_ref() == Some(network) {
+        debug!("Network settings unchanged, not restoring them");
+        return Ok(());
+    }
+    ensure!(
+        network.ipv4_config.is_none(),
+        "Network settings changed but static IPv4 settings cannot be restored"
+    );
+
+    send_admin_message(
+        connection,
+        AdminDestination::Local,
+        protobufs::admin_message::PayloadVariant::SetConfig(protobufs::Config {
+            payload_variant: Some(protobufs::config::PayloadVariant::Network(
+                protobufs::config::NetworkConfig {
+                    wifi_enabled: network.wifi_enabled,
+                    wifi_ssid: network.wifi_ssid.clone(),
+                    wifi_psk: network.wifi_psk.clone(),
+                    ntp_server: network.ntp_server.clone(),
+                    eth_enabled: network.eth_enabled,
+                    ..Default::default()
+                },
+            )),
+        }),
+    )
+    .await
+    .context("Failed to restore the network settings")?;
+
+    let state = connection.get_device_state_ref();
+    state.lock().await.network_config = Some(network.clone());
+    Ok(())
+}
+
+/// Ask the device for its network settings, refreshing the cache
+async fn read_network_config(connection: &mut ConnectionManager) -> Result<Option<NetworkConfig>> {
+    // Any network key fetches the whole section into the cache
+    get_config_value(connection, "network.ntp_server").await?;
+    Ok(connection.get_device_state().await.network_config)
+}
@@ -1,13 +1,35 @@
+use crate::admin::BROADCAST_NODE_NUM;
+use crate::channel::MAX_CHANNELS;
+use crate::node_id::NodeId;
+use crate::presence::PresencePolicy;
+use crate::time::unix_now;
+use anyhow::{Result, ensure};
+use meshtastic::protobufs;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use strum::Display;
+
+/// Entries kept per node in the position and telemetry histories by default
+pub const DEFAULT_HISTORY_DEPTH: usize = 100;
+
+/// Entries kept in the change journal, oldest dropped first
+pub const MAX_CHANGE_RECORDS: usize = 1000;
+
+/// Version of [`StateSnapshot`], bumped when older snapshots cannot be read
+pub const STATE_SNAPSHOT_VERSION: u32 = 1;
 
 /// Cached device state from received packets
-#[derive(Debug, Clone, Default)]
+///
+/// Fields missing from a serialized state take their default, so snapshots
+/// taken by older versions still load.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct DeviceState {
     pub nodes: HashMap<u32, NodeInfo>,
     pub channels: Vec<ChannelInfo>,
     pub config: HashMap<String, serde_json::Value>,
     pub my_node_info: Option<MyNodeInfo>,
+    pub metadata: Option<DeviceMetadata>,
     pub positions: HashMap<u32, Position>,
     pub messages: Vec<TextMessage>,
     pub device_config: Option<DeviceConfig>,
@@ -17,7 +39,193 @@ pub struct DeviceState {
     pub display_config: Option<DisplayConfig>,
     pub lora_config: Option<LoraConfig>,
     pub bluetooth_config: Option<BluetoothConfig>,
+    pub neighbor_info_config: Option<NeighborInfoConfig>,
+    pub mqtt_config: Option<MqttConfig>,
+    pub telemetry_config: Option<TelemetryConfig>,
+    /// RTTTL tune played by the external notification module
+    pub ringtone: Option<String>,
+    /// Messages of the canned message module, in menu order
+    pub canned_messages: Option<Vec<String>>,
     pub telemetry: HashMap<u32, TelemetryData>,
+    /// Recent positions per node, oldest first
+    pub position_history: HashMap<u32, VecDeque<Position>>,
+    /// Recent telemetry reports per node, oldest first
+    pub telemetry_history: HashMap<u32, VecDeque<TelemetryData>>,
+    pub retention: RetentionPolicy,
+    /// Thresholds for reporting nodes as online or offline
+    pub presence: PresencePolicy,
+    pub retention_stats: RetentionStats,
+    /// Number of rebroadcast copies dropped by the packet processor
+    pub duplicate_packets: u64,
+    /// Paths to other nodes learned from traceroute traffic, by destination
+    pub routes: HashMap<u32, RouteEntry>,
+    /// Changes seen on the mesh, oldest first
+    pub changes: VecDeque<ChangeRecord>,
+    /// Configuration sections as the device sent them, so a change to one
+    /// field is written back without resetting the others. Never
+    /// serialized, as they hold secrets such as the private key.
+    #[serde(skip)]
+    pub raw_config: protobufs::LocalConfig,
+    /// Module configuration as the device sent it, see `raw_config`
+    #[serde(skip)]
+    pub raw_module_config: protobufs::LocalModuleConfig,
+    /// Lookup tables over `nodes`, kept current by [`DeviceState::update_node`]
+    #[serde(skip)]
+    node_index: NodeIndex,
+    /// The device sent its node database, so nodes new to `nodes` joined
+    #[serde(skip)]
+    synced: bool,
+}
+
+/// A [`DeviceState`] captured for storage
+///
+/// Plain serde data, so it can be kept as JSON, RON or in a database and
+/// handed back to [`DeviceState::from_snapshot`] in a later run. Secrets
+/// are kept, so store it as carefully as a channel export with PSKs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StateSnapshot {
+    pub version: u32,
+    /// Unix time the snapshot was taken
+    pub taken_at: u64,
+    pub state: DeviceState,
+}
+
+impl StateSnapshot {
+    /// Whether secrets were replaced by the redaction placeholder, as in
+    /// output meant to be shared, so restoring would lose them
+    fn has_redacted_secrets(&self) -> bool {
+        let redacted = |secret: &str| secret == crate::redact::REDACTED;
+        self.state
+            .network_config
+            .as_ref()
+            .is_some_and(|config| redacted(&config.wifi_psk))
+            || self
+                .state
+                .mqtt_config
+                .as_ref()
+                .is_some_and(|config| redacted(&config.password))
+    }
+}
+
+/// Part of the device state a command builds its output from
+///
+/// The device streams its state after `connect()`: its own node info,
+/// metadata, channels, configuration and, last, the other nodes of its node
+/// database. A slice counts as ready once it arrived or the device said it
+/// sent everything, since it will not come after that.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+pub enum StateSlice {
+    #[strum(to_string = "node info")]
+    MyNodeInfo,
+    #[strum(to_string = "metadata")]
+    Metadata,
+    #[strum(to_string = "channels")]
+    Channels,
+    #[strum(to_string = "LoRa settings")]
+    LoraConfig,
+    #[strum(to_string = "node database")]
+    NodeDb,
+}
+
+impl StateSlice {
+    /// Whether `state` holds this slice, given whether the device reported
+    /// the end of its configuration
+    pub fn is_ready(self, state: &DeviceState, config_complete: bool) -> bool {
+        config_complete
+            || match self {
+                StateSlice::MyNodeInfo => state.my_node_info.is_some(),
+                StateSlice::Metadata => state.metadata.is_some(),
+                // Every slot is sent, disabled ones included
+                StateSlice::Channels => state.channels.len() >= MAX_CHANNELS as usize,
+                StateSlice::LoraConfig => state.lora_config.is_some(),
+                // Only the end of the configuration says the last node was sent
+                StateSlice::NodeDb => false,
+            }
+    }
+}
+
+/// Node numbers by id string and by lowercased long and short name
+///
+/// Lets lookups on large meshes avoid scanning every node. Nodes inserted
+/// into `nodes` directly are not indexed, so lookups verify each hit and
+/// fall back to a scan.
+#[derive(Debug, Clone, Default)]
+struct NodeIndex {
+    by_id: HashMap<String, u32>,
+    by_name: HashMap<String, Vec<u32>>,
+}
+
+impl NodeIndex {
+    fn insert(&mut self, node: &NodeInfo) {
+        self.by_id.insert(node.id.clone(), node.num);
+        for name in node_names(node) {
+            let nums = self.by_name.entry(name).or_default();
+            if !nums.contains(&node.num) {
+                nums.push(node.num);
+            }
+        }
+    }
+
+    fn remove(&mut self, node: &NodeInfo) {
+        if self.by_id.get(&node.id) == Some(&node.num) {
+            self.by_id.remove(&node.id);
+        }
+        for name in node_names(node) {
+            if let Some(nums) = self.by_name.get_mut(&name) {
+                nums.retain(|num| *num != node.num);
+                if nums.is_empty() {
+                    self.by_name.remove(&name);
+                }
+            }
+        }
+    }
+}
+
+/// Names a node can be looked up by, lowercased
+fn node_names(node: &NodeInfo) -> impl Iterator<Item = String> + '_ {
+    [&node.user.long_name, &node.user.short_name]
+        .into_iter()
+        .filter(|name| !name.is_empty())
+        .map(|name| name.to_lowercase())
+}
+
+/// Limits applied to cached data so long monitor sessions stay bounded
+///
+/// The latest position and telemetry of each node are always kept; their
+/// histories hold at most `history_depth` entries per node, so memory is
+/// bounded by the number of nodes in the mesh.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetentionPolicy {
+    /// Maximum number of messages kept across all nodes (oldest evicted first)
+    pub max_messages: Option<usize>,
+    /// Maximum number of messages kept per sending node
+    pub max_messages_per_node: Option<usize>,
+    /// Maximum age of a kept message in seconds
+    pub max_message_age_secs: Option<u64>,
+    /// Positions and telemetry reports kept per node, 0 to keep no history
+    pub history_depth: usize,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            max_messages: Some(1000),
+            max_messages_per_node: None,
+            max_message_age_secs: None,
+            history_depth: DEFAULT_HISTORY_DEPTH,
+        }
+    }
+}
+
+/// Counters of entries dropped by the retention policy
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RetentionStats {
+    /// Messages dropped because a count limit was exceeded
+    pub messages_evicted: u64,
+    /// Messages dropped because they exceeded the maximum age
+    pub messages_expired: u64,
+    /// Position and telemetry history entries dropped beyond the depth
+    pub history_evicted: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -29,6 +237,16 @@ pub struct NodeInfo {
     pub last_heard_iso: Option<String>,
     pub snr: Option<f32>,
     pub rssi: Option<i32>,
+    /// Battery and airtime figures the node last reported
+    #[serde(default)]
+    pub device_metrics: Option<DeviceMetrics>,
+}
+
+impl NodeInfo {
+    /// Whether direct messages to the node can be end-to-end encrypted
+    pub fn supports_secure_dm(&self) -> bool {
+        self.user.public_key.is_some() && !self.user.is_licensed
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -37,6 +255,15 @@ pub struct User {
     pub long_name: String,
     pub short_name: String,
     pub hw_model: Option<String>,
+    /// Device role, e.g. "Client" or "Router"
+    #[serde(default)]
+    pub role: Option<String>,
+    /// Hex encoded key for encrypted direct messages, when the node shared one
+    #[serde(default)]
+    pub public_key: Option<String>,
+    /// Licensed amateur radio operator; such nodes never encrypt
+    #[serde(default)]
+    pub is_licensed: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -45,6 +272,7 @@ pub struct ChannelInfo {
     pub name: String,
     pub role: String,
     pub has_psk: bool,
+    #[serde(default)]
     pub settings: Option<meshtastic::protobufs::ChannelSettings>,
 }
 
@@ -55,9 +283,24 @@ pub struct MyNodeInfo {
     pub reboot_count: u32,
     pub min_app_version: u32,
     pub device_id: String,
+    /// PlatformIO environment the firmware was built for, e.g. `tbeam`
+    #[serde(default)]
+    pub pio_env: Option<String>,
 }
 
+/// Firmware and hardware details reported by the device during the handshake
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceMetadata {
+    pub firmware_version: String,
+    pub device_state_version: u32,
+    pub hw_model: String,
+    pub role: String,
+    pub has_wifi: bool,
+    pub has_bluetooth: bool,
+    pub has_ethernet: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct Position {
     pub node_id: String,
     pub node_num: u32,
@@ -66,6 +309,60 @@ pub struct Position {
     pub altitude: Option<i32>,
     pub time: Option<String>,
     pub last_updated: u64,
+    /// Speed over ground in m/s
+    #[serde(default)]
+    pub ground_speed: Option<u32>,
+    /// Course over ground in degrees from true north
+    #[serde(default)]
+    pub ground_track: Option<f64>,
+    /// Satellites in view of the receiver
+    #[serde(default)]
+    pub sats_in_view: Option<u32>,
+    /// Bits of the coordinates that were kept; fewer means a coarser position
+    #[serde(default)]
+    pub precision_bits: Option<u32>,
+    /// NMEA fix quality, e.g. 1 for a GPS fix and 2 for DGPS
+    #[serde(default)]
+    pub fix_quality: Option<u32>,
+    /// Position dilution of precision; lower is better
+    #[serde(default)]
+    pub pdop: Option<f64>,
+}
+
+impl Position {
+    /// Convert a position packet from `node_num`
+    ///
+    /// Returns None when the packet carries no coordinates. The firmware
+    /// leaves figures it does not know at zero, so zeros become None.
+    pub fn from_protobuf(
+        node_num: u32,
+        position: &meshtastic::protobufs::Position,
+    ) -> Option<Self> {
+        let (lat, lon) = (position.latitude_i?, position.longitude_i?);
+        let nonzero = |value: u32| (value != 0).then_some(value);
+
+        Some(Self {
+            node_id: NodeId(node_num).to_string(),
+            node_num,
+            latitude: f64::from(lat) / 1e7,
+            longitude: f64::from(lon) / 1e7,
+            altitude: position.altitude,
+            time: if position.time > 0 {
+                crate::time::to_rfc3339(u64::from(position.time))
+            } else {
+                None
+            },
+            last_updated: crate::time::unix_now(),
+            ground_speed: position.ground_speed,
+            // Reported in hundredths of a degree
+            ground_track: position.ground_track.map(|track| f64::from(track) / 100.0),
+            sats_in_view: nonzero(position.sats_in_view),
+            precision_bits: nonzero(position.precision_bits),
+            fix_quality: nonzero(position.fix_quality),
+            // Reported in hundredths
+            pdop: nonzero(position.pdop).map(|pdop| f64::from(pdop) / 100.0),
+        })
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -80,6 +377,135 @@ pub struct TextMessage {
     pub snr: Option<f32>,
     pub rssi: Option<i32>,
     pub acknowledged: bool,
+    /// Sent from our own node and echoed back by the device
+    #[serde(default)]
+    pub own: bool,
+}
+
+/// A path from the local node to another node, learned from a traceroute
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RouteEntry {
+    pub destination: u32,
+    /// First node on the path; the destination itself when it is in range
+    pub next_hop: u32,
+    pub hop_count: u32,
+    /// Relaying nodes between the local node and the destination, nearest first
+    pub path: Vec<u32>,
+    /// When this path was first seen, reset when the path changes
+    pub first_seen: u64,
+    pub last_seen: u64,
+    /// How often this path was seen
+    pub observations: u32,
+}
+
+/// A change to the mesh kept in the change journal, for auditing
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum MeshChange {
+    /// A node was heard for the first time
+    NodeJoined { node: String, name: String },
+    /// A node changed its long or short name
+    NodeRenamed {
+        node: String,
+        old_name: String,
+        new_name: String,
+    },
+    /// A node changed its device role
+    RoleChanged {
+        node: String,
+        old_role: String,
+        new_role: String,
+    },
+    /// A channel of the local device changed
+    ChannelChanged { index: u32, name: String },
+    /// A configuration section of the local device changed, e.g. "lora"
+    ConfigChanged { section: String },
+}
+
+impl MeshChange {
+    /// Name of the change, as in JSON
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::NodeJoined { .. } => "node_joined",
+            Self::NodeRenamed { .. } => "node_renamed",
+            Self::RoleChanged { .. } => "role_changed",
+            Self::ChannelChanged { .. } => "channel_changed",
+            Self::ConfigChanged { .. } => "config_changed",
+        }
+    }
+}
+
+impl std::fmt::Display for MeshChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NodeJoined { node, name } => write!(f, "{node} joined as {name}"),
+            Self::NodeRenamed {
+                node,
+                old_name,
+                new_name,
+            } => write!(f, "{node} renamed from {old_name} to {new_name}"),
+            Self::RoleChanged {
+                node,
+                old_role,
+                new_role,
+            } => write!(f, "{node} changed role from {old_role} to {new_role}"),
+            Self::ChannelChanged { index, name } => write!(f, "Channel {index} ({name}) changed"),
+            Self::ConfigChanged { section } => write!(f, "{section} settings changed"),
+        }
+    }
+}
+
+/// A [`MeshChange`] and when it was seen
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChangeRecord {
+    pub time: u64,
+    #[serde(flatten)]
+    pub change: MeshChange,
+}
+
+/// Long and short name of a node, as shown in the change journal
+fn node_name(user: &User) -> String {
+    format!(
+        "{long} ({short})",
+        long = user.long_name,
+        short = user.short_name
+    )
+}
+
+/// Renames and role changes between two reports of a node's owner
+fn node_changes(previous: &User, node: &NodeInfo) -> Vec<MeshChange> {
+    let mut changes = Vec::new();
+    let user = &node.user;
+    // A node known only by number has no name to change from
+    if !previous.long_name.is_empty()
+        && (&previous.long_name, &previous.short_name) != (&user.long_name, &user.short_name)
+    {
+        changes.push(MeshChange::NodeRenamed {
+            node: node.id.clone(),
+            old_name: node_name(previous),
+            new_name: node_name(user),
+        });
+    }
+    // A role first reported is learned, not changed
+    if let (Some(old_role), Some(new_role)) = (&previous.role, &user.role)
+        && old_role != new_role
+    {
+        changes.push(MeshChange::RoleChanged {
+            node: node.id.clone(),
+            old_role: old_role.clone(),
+            new_role: new_role.clone(),
+        });
+    }
+    changes
+}
+
+/// Replace a kept configuration section, telling whether it changed
+///
+/// The first report of a section is not a change.
+fn store_section<T: Clone + PartialEq>(kept: &mut Option<T>, section: &T) -> bool {
+    let changed = kept.as_ref().is_some_and(|kept| kept != section);
+    *kept = Some(section.clone());
+    changed
 }
 
 impl DeviceState {
@@ -87,32 +513,489 @@ impl DeviceState {
         Self::default()
     }
 
-    pub fn update_node(&mut self, node_num: u32, node_info: NodeInfo) {
+    /// Capture the state for storage
+    pub fn to_snapshot(&self) -> StateSnapshot {
+        StateSnapshot {
+            version: STATE_SNAPSHOT_VERSION,
+            taken_at: unix_now(),
+            state: self.clone(),
+        }
+    }
+
+    /// Restore a state saved with [`DeviceState::to_snapshot`]
+    ///
+    /// The node lookup tables are rebuilt and the retention policy saved
+    /// with the state is applied, so messages past their age are dropped.
+    pub fn from_snapshot(snapshot: StateSnapshot) -> Result<Self> {
+        ensure!(
+            snapshot.version == STATE_SNAPSHOT_VERSION,
+            "Unsupported state snapshot version {version}, expected {STATE_SNAPSHOT_VERSION}",
+            version = snapshot.version
+        );
+        ensure!(
+            !snapshot.has_redacted_secrets(),
+            "The state snapshot has redacted secrets and cannot be restored"
+        );
+        let mut state = snapshot.state;
+        state.node_index = NodeIndex::default();
+        for node in state.nodes.values() {
+            state.node_index.insert(node);
+        }
+        state.enforce_retention();
+        Ok(state)
+    }
+
+    /// Store what is known about a node
+    ///
+    /// Joins, renames and role changes are recorded in the change journal
+    /// and returned.
+    pub fn update_node(&mut self, node_num: u32, node_info: NodeInfo) -> Vec<ChangeRecord> {
+        let changes = match self.nodes.get(&node_num) {
+            Some(previous) => node_changes(&previous.user, &node_info),
+            None if self.synced => vec![MeshChange::NodeJoined {
+                node: node_info.id.clone(),
+                name: node_name(&node_info.user),
+            }],
+            None => Vec::new(),
+        };
+        if let Some(previous) = self.nodes.get(&node_num) {
+            self.node_index.remove(previous);
+        }
+        self.node_index.insert(&node_info);
         self.nodes.insert(node_num, node_info);
+        changes
+            .into_iter()
+            .map(|change| self.record_change(change))
+            .collect()
+    }
+
+    /// Record the owner a node reported, keeping what else is known about it
+    pub fn update_owner(&mut self, node_num: u32, user: User) -> Vec<ChangeRecord> {
+        let node = match self.nodes.get(&node_num) {
+            Some(node) => NodeInfo {
+                user,
+                ..node.clone()
+            },
+            None => NodeInfo {
+                id: NodeId(node_num).to_string(),
+                num: node_num,
+                user,
+                last_heard: None,
+                last_heard_iso: None,
+                snr: None,
+                rssi: None,
+                device_metrics: None,
+            },
+        };
+        self.update_node(node_num, node)
+    }
+
+    /// Note the device sent its whole node database, so nodes added from
+    /// now on are journaled as joins
+    pub fn mark_synced(&mut self) {
+        self.synced = true;
+    }
+
+    /// Add a change to the journal, dropping the oldest beyond
+    /// [`MAX_CHANGE_RECORDS`]
+    pub fn record_change(&mut self, change: MeshChange) -> ChangeRecord {
+        let record = ChangeRecord {
+            time: unix_now(),
+            change,
+        };
+        self.changes.push_back(record.clone());
+        while self.changes.len() > MAX_CHANGE_RECORDS {
+            self.changes.pop_front();
+        }
+        record
+    }
+
+    /// Journaled changes seen at or after `since`, oldest first
+    pub fn changes_since(&self, since: Option<u64>) -> Vec<ChangeRecord> {
+        self.changes
+            .iter()
+            .filter(|record| since.is_none_or(|since| record.time >= since))
+            .cloned()
+            .collect()
+    }
+
+    /// Store canned messages from the `|`-separated form the firmware uses
+    pub fn set_canned_messages(&mut self, messages: &str) {
+        self.canned_messages = Some(
+            messages
+                .split('|')
+                .map(str::trim)
+                .filter(|message| !message.is_empty())
+                .map(str::to_string)
+                .collect(),
+        );
     }
 
     pub fn update_position(&mut self, node_num: u32, position: Position) {
+        let history = self.position_history.entry(node_num).or_default();
+        // Rebroadcasts and unchanged fixes repeat the previous report
+        let repeated = history.back().is_some_and(|last| {
+            last.latitude == position.latitude
+                && last.longitude == position.longitude
+                && last.altitude == position.altitude
+                && last.time == position.time
+        });
+        if !repeated {
+            history.push_back(position.clone());
+            self.retention_stats.history_evicted +=
+                truncate_history(history, self.retention.history_depth);
+        }
         self.positions.insert(node_num, position);
     }
 
+    /// Recent positions of a node, oldest first
+    pub fn position_history(&self, node_num: u32) -> impl Iterator<Item = &Position> {
+        self.position_history.get(&node_num).into_iter().flatten()
+    }
+
+    /// Recent telemetry reports of a node, oldest first
+    pub fn telemetry_history(&self, node_num: u32) -> impl Iterator<Item = &TelemetryData> {
+        self.telemetry_history.get(&node_num).into_iter().flatten()
+    }
+
+    /// Positions recorded at or after `since` (Unix seconds) across all
+    /// nodes, oldest first
+    ///
+    /// Only what the retention policy kept in the histories is searched.
+    pub fn positions_since(&self, since: u64) -> Vec<&Position> {
+        let mut positions: Vec<&Position> = self
+            .position_history
+            .values()
+            .flatten()
+            .filter(|position| position.last_updated >= since)
+            .collect();
+        positions.sort_by_key(|position| position.last_updated);
+        positions
+    }
+
+    /// Telemetry reports from `start` to `end` inclusive (Unix seconds)
+    /// across all nodes, oldest first
+    pub fn telemetry_between(&self, start: u64, end: u64) -> Vec<&TelemetryData> {
+        let mut reports: Vec<&TelemetryData> = self
+            .telemetry_history
+            .values()
+            .flatten()
+            .filter(|telemetry| (start..=end).contains(&telemetry.time))
+            .collect();
+        reports.sort_by_key(|telemetry| telemetry.time);
+        reports
+    }
+
+    /// Messages received at or after `since` (Unix seconds), oldest first
+    pub fn messages_since(&self, since: u64) -> impl Iterator<Item = &TextMessage> {
+        self.messages
+            .iter()
+            .filter(move |message| message.time >= since)
+    }
+
     pub fn add_message(&mut self, message: TextMessage) {
         self.messages.push(message);
+        self.enforce_retention();
     }
 
-    pub fn update_channel(&mut self, channel: ChannelInfo) {
-        if let Some(existing) = self.channels.iter_mut().find(|c| c.index == channel.index) {
-            *existing = channel;
-        } else {
-            self.channels.push(channel);
+    /// Drop the cached messages received before `cutoff`, or all of them,
+    /// returning how many were dropped
+    pub fn clear_messages(&mut self, cutoff: Option<u64>) -> usize {
+        let before = self.messages.len();
+        self.messages
+            .retain(|message| cutoff.is_some_and(|cutoff| message.time >= cutoff));
+        before - self.messages.len()
+    }
+
+    /// Replace the retention policy and immediately apply it to cached data
+    pub fn set_retention_policy(&mut self, policy: RetentionPolicy) {
+        self.retention = policy;
+        self.enforce_retention();
+    }
+
+    /// Drop cached entries that exceed the configured retention limits
+    pub fn enforce_retention(&mut self) {
+        let depth = self.retention.history_depth;
+        for history in self.position_history.values_mut() {
+            self.retention_stats.history_evicted += truncate_history(history, depth);
+        }
+        for history in self.telemetry_history.values_mut() {
+            self.retention_stats.history_evicted += truncate_history(history, depth);
+        }
+        self.position_history
+            .retain(|_, history| !history.is_empty());
+        self.telemetry_history
+            .retain(|_, history| !history.is_empty());
+
+        if let Some(max_age) = self.retention.max_message_age_secs {
+            let now = crate::time::unix_now();
+            let before = self.messages.len();
+            self.messages
+                .retain(|m| now.saturating_sub(m.time) <= max_age);
+            self.retention_stats.messages_expired += (before - self.messages.len()) as u64;
+        }
+
+        if let Some(max_per_node) = self.retention.max_messages_per_node {
+            // Walk from newest to oldest so the most recent messages of each node survive
+            let mut per_node: HashMap<u32, usize> = HashMap::new();
+            let mut keep = vec![false; self.messages.len()];
+            for (idx, message) in self.messages.iter().enumerate().rev() {
+                let count = per_node.entry(message.from_node).or_default();
+                if *count < max_per_node {
+                    *count += 1;
+                    keep[idx] = true;
+                }
+            }
+
+            let before = self.messages.len();
+            let mut keep_iter = keep.into_iter();
+            self.messages.retain(|_| keep_iter.next().unwrap_or(true));
+            self.retention_stats.messages_evicted += (before - self.messages.len()) as u64;
+        }
+
+        if let Some(max_messages) = self.retention.max_messages
+            && self.messages.len() > max_messages
+        {
+            let excess = self.messages.len() - max_messages;
+            self.messages.drain(..excess);
+            self.retention_stats.messages_evicted += excess as u64;
         }
     }
 
+    /// Store a channel, journaling a change to one already known
+    pub fn update_channel(&mut self, channel: ChannelInfo) -> Option<ChangeRecord> {
+        let Some(existing) = self.channels.iter_mut().find(|c| c.index == channel.index) else {
+            self.channels.push(channel);
+            return None;
+        };
+        let changed = (
+            &existing.name,
+            &existing.role,
+            existing.has_psk,
+            &existing.settings,
+        ) != (
+            &channel.name,
+            &channel.role,
+            channel.has_psk,
+            &channel.settings,
+        );
+        let change = MeshChange::ChannelChanged {
+            index: channel.index,
+            name: channel.name.clone(),
+        };
+        *existing = channel;
+        changed.then(|| self.record_change(change))
+    }
+
     pub fn set_my_node_info(&mut self, info: MyNodeInfo) {
         self.my_node_info = Some(info);
     }
 
+    /// Whether a reported reboot count shows the device restarted since
+    /// its node info was cached
+    pub fn is_reboot(&self, reboot_count: u32) -> bool {
+        self.my_node_info
+            .as_ref()
+            .is_some_and(|info| reboot_count > info.reboot_count)
+    }
+
+    /// Drop everything the device reports about itself during the handshake
+    ///
+    /// Used after a reboot, when the configuration may have changed. Nodes,
+    /// messages, positions and telemetry heard from the mesh are kept.
+    pub fn clear_device_config(&mut self) {
+        self.my_node_info = None;
+        self.metadata = None;
+        self.channels.clear();
+        self.config.clear();
+        self.device_config = None;
+        self.position_config = None;
+        self.power_config = None;
+        self.network_config = None;
+        self.display_config = None;
+        self.lora_config = None;
+        self.bluetooth_config = None;
+        self.neighbor_info_config = None;
+        self.mqtt_config = None;
+        self.telemetry_config = None;
+        self.ringtone = None;
+        self.canned_messages = None;
+        self.raw_config = Default::default();
+        self.raw_module_config = Default::default();
+    }
+
+    /// Keep a configuration section as received, in [`Self::raw_config`]
+    ///
+    /// A section that differs from the one kept is journaled as changed.
+    pub fn store_raw_config(
+        &mut self,
+        payload: &protobufs::config::PayloadVariant,
+    ) -> Option<ChangeRecord> {
+        use protobufs::config::PayloadVariant;
+
+        let config = &mut self.raw_config;
+        let (section, changed) = match payload {
+            PayloadVariant::Device(section) => {
+                ("device", store_section(&mut config.device, section))
+            }
+            PayloadVariant::Position(section) => {
+                ("position", store_section(&mut config.position, section))
+            }
+            PayloadVariant::Power(section) => ("power", store_section(&mut config.power, section)),
+            PayloadVariant::Network(section) => {
+                ("network", store_section(&mut config.network, section))
+            }
+            PayloadVariant::Display(section) => {
+                ("display", store_section(&mut config.display, section))
+            }
+            PayloadVariant::Lora(section) => ("lora", store_section(&mut config.lora, section)),
+            PayloadVariant::Bluetooth(section) => {
+                ("bluetooth", store_section(&mut config.bluetooth, section))
+            }
+            PayloadVariant::Security(section) => {
+                ("security", store_section(&mut config.security, section))
+            }
+            _ => return None,
+        };
+        changed.then(|| {
+            self.record_change(MeshChange::ConfigChanged {
+                section: section.to_string(),
+            })
+        })
+    }
+
+    /// Keep a module configuration as received, in [`Self::raw_module_config`]
+    ///
+    /// A module whose settings differ from the ones kept is journaled as
+    /// changed.
+    pub fn store_raw_module_config(
+        &mut self,
+        payload: &protobufs::module_config::PayloadVariant,
+    ) -> Option<ChangeRecord> {
+        use protobufs::module_config::PayloadVariant;
+
+        let config = &mut self.raw_module_config;
+        let (section, changed) = match payload {
+            PayloadVariant::Mqtt(module) => ("mqtt", store_section(&mut config.mqtt, module)),
+            PayloadVariant::Serial(module) => ("serial", store_section(&mut config.serial, module)),
+            PayloadVariant::ExternalNotification(module) => (
+                "external_notification",
+                store_section(&mut config.external_notification, module),
+            ),
+            PayloadVariant::StoreForward(module) => (
+                "store_forward",
+                store_section(&mut config.store_forward, module),
+            ),
+            PayloadVariant::RangeTest(module) => {
+                ("range_test", store_section(&mut config.range_test, module))
+            }
+            PayloadVariant::Telemetry(module) => {
+                ("telemetry", store_section(&mut config.telemetry, module))
+            }
+            PayloadVariant::CannedMessage(module) => (
+                "canned_message",
+                store_section(&mut config.canned_message, module),
+            ),
+            PayloadVariant::Audio(module) => ("audio", store_section(&mut config.audio, module)),
+            PayloadVariant::RemoteHardware(module) => (
+                "remote_hardware",
+                store_section(&mut config.remote_hardware, module),
+            ),
+            PayloadVariant::NeighborInfo(module) => (
+                "neighbor_info",
+                store_section(&mut config.neighbor_info, module),
+            ),
+            PayloadVariant::AmbientLighting(module) => (
+                "ambient_lighting",
+                store_section(&mut config.ambient_lighting, module),
+            ),
+            PayloadVariant::DetectionSensor(module) => (
+                "detection_sensor",
+                store_section(&mut config.detection_sensor, module),
+            ),
+            PayloadVariant::Paxcounter(module) => {
+                ("paxcounter", store_section(&mut config.paxcounter, module))
+            }
+        };
+        changed.then(|| {
+            self.record_change(MeshChange::ConfigChanged {
+                section: section.to_string(),
+            })
+        })
+    }
+
+    /// Look a node up by its id, falling back to any form [`NodeId`] parses
     pub fn get_node_by_id(&self, node_id: &str) -> Option<&NodeInfo> {
-        self.nodes.values().find(|n| n.id == node_id)
+        self.node_index
+            .by_id
+            .get(node_id)
+            .and_then(|num| self.nodes.get(num))
+            .filter(|node| node.id == node_id)
+            .or_else(|| self.nodes.values().find(|n| n.id == node_id))
+            .or_else(|| {
+                let NodeId(num) = node_id.parse().ok()?;
+                self.nodes.get(&num)
+            })
+    }
+
+    /// Nodes whose long or short name matches, ignoring case
+    pub fn get_nodes_by_name(&self, name: &str) -> Vec<&NodeInfo> {
+        let name = name.to_lowercase();
+        let matches = |node: &&NodeInfo| node_names(node).any(|candidate| candidate == name);
+
+        let indexed: Vec<&NodeInfo> = self
+            .node_index
+            .by_name
+            .get(&name)
+            .into_iter()
+            .flatten()
+            .filter_map(|num| self.nodes.get(num))
+            .filter(matches)
+            .collect();
+        if indexed.is_empty() {
+            self.nodes.values().filter(matches).collect()
+        } else {
+            indexed
+        }
+    }
+
+    /// Record a path to `destination` through the relays in `path`
+    ///
+    /// Each relay on the path is reachable through the relays before it, so
+    /// routes to them are recorded as well. A newer path replaces an older one.
+    /// Relays the firmware could not identify are listed as the broadcast
+    /// address and get no route of their own.
+    pub fn record_route(&mut self, destination: u32, path: &[u32], seen_at: u64) {
+        let relays = path
+            .iter()
+            .enumerate()
+            .map(|(index, relay)| (*relay, &path[..index]));
+        for (destination, path) in relays.chain([(destination, path)]) {
+            if destination == BROADCAST_NODE_NUM {
+                continue;
+            }
+            let next_hop = path.first().copied().unwrap_or(destination);
+            match self.routes.get_mut(&destination) {
+                Some(route) if route.path == path => {
+                    route.last_seen = route.last_seen.max(seen_at);
+                    route.observations += 1;
+                }
+                _ => {
+                    self.routes.insert(
+                        destination,
+                        RouteEntry {
+                            destination,
+                            next_hop,
+                            hop_count: path.len() as u32 + 1,
+                            path: path.to_vec(),
+                            first_seen: seen_at,
+                            last_seen: seen_at,
+                            observations: 1,
+                        },
+                    );
+                }
+            }
+        }
     }
 
     pub fn get_node_by_num(&self, node_num: u32) -> Option<&NodeInfo> {
@@ -120,10 +1003,23 @@ impl DeviceState {
     }
 
     pub fn update_telemetry(&mut self, node_num: u32, telemetry: TelemetryData) {
+        let history = self.telemetry_history.entry(node_num).or_default();
+        if history.back() != Some(&telemetry) {
+            history.push_back(telemetry.clone());
+            self.retention_stats.history_evicted +=
+                truncate_history(history, self.retention.history_depth);
+        }
         self.telemetry.insert(node_num, telemetry);
     }
 }
 
+/// Drop the oldest entries beyond `depth`, returning how many were dropped
+fn truncate_history<T>(history: &mut VecDeque<T>, depth: usize) -> u64 {
+    let excess = history.len().saturating_sub(depth);
+    history.drain(..excess);
+    excess as u64
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DeviceConfig {
     pub role: String,
@@ -155,7 +1051,7 @@ pub struct PowerConfig {
     pub min_wake_secs: u32,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct NetworkConfig {
     pub wifi_enabled: bool,
     pub wifi_ssid: String,
@@ -192,6 +1088,15 @@ pub struct LoraConfig {
     pub tx_power: i32,
     pub channel_num: u32,
     pub ignore_mqtt: bool,
+    /// Fixed frequency in MHz used instead of the channel slot, 0 when unset
+    #[serde(default)]
+    pub override_frequency: f32,
+    /// Ignore the region's duty cycle limit, for licensed operators
+    #[serde(default)]
+    pub override_duty_cycle: bool,
+    /// Boosted receive gain on SX126x radios
+    #[serde(default)]
+    pub sx126x_rx_boosted_gain: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -202,7 +1107,51 @@ pub struct BluetoothConfig {
     pub device_logging_enabled: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Settings of the NeighborInfo module, which broadcasts the nodes a node
+/// hears directly
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NeighborInfoConfig {
+    pub enabled: bool,
+    /// Seconds between broadcasts, 0 for the firmware default
+    pub update_interval: u32,
+}
+
+/// Settings of the Telemetry module, which broadcasts metrics of the node
+/// and its sensors
+///
+/// Intervals are in seconds, 0 for the firmware default.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TelemetryConfig {
+    pub device_update_interval: u32,
+    pub environment_update_interval: u32,
+    pub environment_measurement_enabled: bool,
+    pub environment_screen_enabled: bool,
+    pub environment_display_fahrenheit: bool,
+    pub air_quality_enabled: bool,
+    pub air_quality_interval: u32,
+    pub power_measurement_enabled: bool,
+    pub power_update_interval: u32,
+    pub power_screen_enabled: bool,
+}
+
+/// Settings of the MQTT module
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MqttConfig {
+    pub enabled: bool,
+    /// Broker as `host[:port]`, empty for the public Meshtastic broker
+    pub address: String,
+    pub username: String,
+    pub password: String,
+    pub encryption_enabled: bool,
+    pub json_enabled: bool,
+    pub tls_enabled: bool,
+    /// Topic root, empty for the firmware default
+    pub root: String,
+    /// Send MQTT traffic through the client instead of the device's own network
+    pub proxy_to_client_enabled: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct TelemetryData {
     pub node_num: u32,
     pub time: u64,
@@ -211,7 +1160,7 @@ pub struct TelemetryData {
     pub air_quality_metrics: Option<AirQualityMetrics>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct DeviceMetrics {
     pub battery_level: Option<u32>,
     pub voltage: Option<f32>,
@@ -220,7 +1169,7 @@ pub struct DeviceMetrics {
     pub uptime_seconds: Option<u32>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct EnvironmentMetrics {
     pub temperature: Option<f32>,
     pub relative_humidity: Option<f32>,
@@ -237,7 +1186,7 @@ pub struct EnvironmentMetrics {
     pub weight: Option<f32>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct AirQualityMetrics {
     pub pm10_standard: Option<u32>,
     pub pm25_standard: Option<u32>,
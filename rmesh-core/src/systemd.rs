@@ -0,0 +1,89 @@
+//! Service manager notifications for running rmesh under systemd with
+//! `Type=notify` and `WatchdogSec=`
+//!
+//! Speaks the `sd_notify` datagram protocol directly, so no libsystemd is
+//! needed. Everything is a no-op when systemd did not start the process.
+
+use crate::connection::HealthProbe;
+use anyhow::{Context, Result};
+use std::ffi::OsStr;
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::path::Path;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+/// Send `state`, such as `READY=1` or `STATUS=...`, to the service manager
+///
+/// Returns `false` without sending when `NOTIFY_SOCKET` is not set.
+pub fn notify(state: &str) -> Result<bool> {
+    let Some(socket) = std::env::var_os("NOTIFY_SOCKET") else {
+        return Ok(false);
+    };
+    send_notification(&socket, state)?;
+    Ok(true)
+}
+
+pub(crate) fn send_notification(socket: &OsStr, state: &str) -> Result<()> {
+    // A leading @ names a socket in the abstract namespace
+    let address = match socket.as_bytes().strip_prefix(b"@") {
+        Some(name) => SocketAddr::from_abstract_name(name)?,
+        None => SocketAddr::from_pathname(Path::new(socket))?,
+    };
+    UnixDatagram::unbound()?
+        .send_to_addr(state.as_bytes(), &address)
+        .with_context(|| format!("Failed to notify {socket}", socket = socket.display()))?;
+    Ok(())
+}
+
+/// Watchdog timeout systemd expects heartbeats within, if it watches us
+pub fn watchdog_timeout() -> Option<Duration> {
+    let usec = std::env::var("WATCHDOG_USEC").ok();
+    let pid = std::env::var("WATCHDOG_PID").ok();
+    parse_watchdog(usec.as_deref(), pid.as_deref(), std::process::id())
+}
+
+/// Timeout from `WATCHDOG_USEC`, unless `WATCHDOG_PID` names another process
+pub(crate) fn parse_watchdog(
+    usec: Option<&str>,
+    pid: Option<&str>,
+    own_pid: u32,
+) -> Option<Duration> {
+    if pid.is_some_and(|pid| pid.parse() != Ok(own_pid)) {
+        return None;
+    }
+    let usec: u64 = usec?.parse().ok().filter(|usec| *usec > 0)?;
+    Some(Duration::from_micros(usec))
+}
+
+/// Send `WATCHDOG=1` at half the watchdog timeout while the connection is
+/// live, so systemd restarts a gateway whose device went silent
+///
+/// With a `max_packet_age`, the link only counts as live while packets
+/// arrive within it. Returns `None` when no watchdog is configured.
+pub fn spawn_watchdog(
+    probe: HealthProbe,
+    max_packet_age: Option<Duration>,
+) -> Option<JoinHandle<()>> {
+    let timeout = watchdog_timeout()?;
+    debug!(
+        "Sending systemd watchdog heartbeats every {interval:?}",
+        interval = timeout / 2
+    );
+
+    Some(tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(timeout / 2);
+        loop {
+            ticker.tick().await;
+            if !probe.report().await.is_live(max_packet_age) {
+                debug!("Withholding the watchdog heartbeat: the device link is not live");
+                continue;
+            }
+            if let Err(e) = notify("WATCHDOG=1") {
+                warn!("Failed to send the watchdog heartbeat: {e}");
+            }
+        }
+    }))
+}
@@ -1,12 +1,16 @@
-use crate::connection::ConnectionManager;
-use crate::state::DeviceMetrics;
-use anyhow::Result;
+use crate::connection::{ConnectionManager, RequestResponse, SendPriority};
+use crate::events::MeshEvent;
+use crate::node_id::NodeId;
+use crate::progress::{ProgressCallback, ProgressReporter};
+use crate::state::{DeviceMetrics, TelemetryData};
+use anyhow::{Context, Result, ensure};
 use meshtastic::Message;
-use meshtastic::packet::PacketDestination;
 use meshtastic::protobufs;
-use meshtastic::types::EncodedMeshPacketData;
 use serde::Serialize;
-use tokio::time::{Duration, sleep};
+use strum::Display;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinSet;
+use tokio::time::{Duration, Instant, timeout_at};
 use tracing::{debug, info};
 
 /// Request telemetry from the local device
@@ -21,43 +25,76 @@ pub async fn request_device_telemetry(connection: &mut ConnectionManager) -> Res
         }
     };
 
-    // Create an empty telemetry packet to request telemetry
-    let telemetry = protobufs::Telemetry::default();
-
-    // Create a simple packet router
-    let mut packet_router = SimplePacketRouter;
-
-    // Get API and send telemetry request with wantResponse flag
-    let api = connection.get_api()?;
-
-    // Encode telemetry to bytes
-    let byte_data: EncodedMeshPacketData = telemetry.encode_to_vec().into();
-
-    // Send mesh packet to self with want_response set to true
-    api.send_mesh_packet(
-        &mut packet_router,
-        byte_data,
-        protobufs::PortNum::TelemetryApp,
-        PacketDestination::Node(local_node_num.into()),
-        0.into(), // primary channel
-        false,    // want_ack
-        true,     // want_response - request telemetry
-        false,    // echo_response
-        None,     // reply_id
-        None,     // emoji
-    )
-    .await?;
+    // An empty telemetry packet with want_response requests telemetry
+    let data = protobufs::Data {
+        portnum: protobufs::PortNum::TelemetryApp as i32,
+        payload: protobufs::Telemetry::default().encode_to_vec(),
+        want_response: true,
+        ..Default::default()
+    };
+    connection.send_data(local_node_num, 0, data, false).await?;
 
     info!("Sent telemetry request to local device");
     Ok(())
 }
 
+/// Request device metrics from the local device and wait for the reply
+///
+/// Falls back to the cached metrics when no reply arrives in time.
+pub async fn request_device_metrics(
+    connection: &mut ConnectionManager,
+    timeout_secs: u64,
+) -> Result<Option<DeviceMetrics>> {
+    let state = connection.get_device_state().await;
+    let Some(local_node_num) = state.my_node_info.as_ref().map(|info| info.node_num) else {
+        debug!("No local node information available");
+        return Ok(None);
+    };
+
+    let pending = connection
+        .send_request(
+            local_node_num,
+            protobufs::PortNum::TelemetryApp,
+            protobufs::Telemetry::default().encode_to_vec(),
+        )
+        .await?;
+    info!("Sent telemetry request to local device");
+
+    if let Some(RequestResponse::Telemetry(telemetry)) =
+        pending.wait(Duration::from_secs(timeout_secs)).await
+        && let Some(metrics) = telemetry.device_metrics
+    {
+        debug!("Received telemetry response from local device");
+        return Ok(Some(metrics));
+    }
+
+    debug!("No telemetry response within {timeout_secs} seconds, using cached metrics");
+    let state = connection.get_device_state().await;
+    Ok(state
+        .telemetry
+        .get(&local_node_num)
+        .and_then(|t| t.device_metrics.clone()))
+}
+
 /// Collect telemetry data for a specified duration
 pub async fn collect_telemetry(
     connection: &mut ConnectionManager,
     wait_seconds: u64,
+) -> Result<Option<DeviceMetrics>> {
+    collect_telemetry_with_progress(connection, wait_seconds, None).await
+}
+
+/// Collect telemetry, reporting elapsed seconds of the collection window
+pub async fn collect_telemetry_with_progress(
+    connection: &mut ConnectionManager,
+    wait_seconds: u64,
+    progress: Option<ProgressCallback<'_>>,
 ) -> Result<Option<DeviceMetrics>> {
     info!("Collecting telemetry broadcasts for {wait_seconds} seconds...");
+    let mut reporter = ProgressReporter::start(progress, "collect_telemetry", Some(wait_seconds));
+
+    // Subscribe before reading the state so no update is missed in between
+    let mut events = connection.subscribe_events();
 
     // Get local node number
     let state = connection.get_device_state().await;
@@ -69,41 +106,36 @@ pub async fn collect_telemetry(
         }
     };
 
-    // Record initial state
-    let initial_metrics = state
-        .telemetry
-        .get(&local_node_num)
-        .and_then(|t| t.device_metrics.clone());
-    let initial_time = initial_metrics.as_ref().map(|_| {
-        std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs()
-    });
-
-    // Poll for new telemetry during the wait period
-    let start_time = std::time::Instant::now();
-    let timeout_duration = Duration::from_secs(wait_seconds);
-
-    while start_time.elapsed() < timeout_duration {
-        // Get current state
-        let state = connection.get_device_state().await;
-
-        // Check for new or updated telemetry
-        if let Some(telemetry) = state.telemetry.get(&local_node_num)
-            && let Some(metrics) = &telemetry.device_metrics
-        {
-            // Check if this is newer than what we started with
-            if initial_time.is_none() || telemetry.time > initial_time.unwrap() {
-                debug!("Received telemetry update from local device");
-                return Ok(Some(metrics.clone()));
+    // Wait for a telemetry update, ticking once a second for progress
+    let start_time = Instant::now();
+    let deadline = start_time + Duration::from_secs(wait_seconds);
+
+    while Instant::now() < deadline {
+        let tick = (Instant::now() + Duration::from_secs(1)).min(deadline);
+        match timeout_at(tick, events.recv()).await {
+            Ok(Ok(MeshEvent::TelemetryUpdated(telemetry)))
+                if telemetry.node_num == local_node_num =>
+            {
+                if let Some(metrics) = telemetry.device_metrics {
+                    debug!("Received telemetry update from local device");
+                    reporter.set_completed(
+                        start_time.elapsed().as_secs(),
+                        Some("Telemetry received".to_string()),
+                    );
+                    reporter.finish();
+                    return Ok(Some(metrics));
+                }
             }
+            Ok(Err(RecvError::Closed)) => break,
+            Ok(Ok(_)) | Ok(Err(RecvError::Lagged(_))) | Err(_) => {}
         }
 
-        // Wait a bit before checking again
-        sleep(Duration::from_millis(250)).await;
+        reporter.set_completed(start_time.elapsed().as_secs(), None);
     }
 
+    reporter.set_completed(wait_seconds, None);
+    reporter.finish();
+
     // Return whatever we have (could be initial metrics or nothing)
     let final_state = connection.get_device_state().await;
     Ok(final_state
@@ -118,90 +150,237 @@ pub async fn request_telemetry(
     telemetry_type: TelemetryType,
     node_id: Option<u32>,
 ) -> Result<()> {
-    let api = connection.get_api()?;
-
-    // Create a simple packet router
-    let mut router = SimplePacketRouter;
-
     // For telemetry, we send an empty telemetry packet with want_response set
     // This triggers the remote node to send back its telemetry data
     let telemetry_request = protobufs::Telemetry {
-        time: std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs() as u32,
+        time: crate::time::unix_now() as u32,
         variant: None, // Empty variant acts as a request
     };
-
-    // Send telemetry request
-    api.send_mesh_packet(
-        &mut router,
-        telemetry_request.encode_to_vec().into(),
-        protobufs::PortNum::TelemetryApp,
-        if let Some(node) = node_id {
-            meshtastic::packet::PacketDestination::Node(node.into())
-        } else {
-            meshtastic::packet::PacketDestination::Local
-        },
-        0.into(), // Primary channel
-        false,    // want_ack
-        true,     // want_response
-        false,    // echo_response
-        None,     // packet_id
-        None,     // emoji
-    )
-    .await?;
+    let destination = match node_id {
+        Some(node) => node,
+        None => connection
+            .get_device_state()
+            .await
+            .my_node_info
+            .map(|info| info.node_num)
+            .context("Local node information is not available")?,
+    };
+    let data = protobufs::Data {
+        portnum: protobufs::PortNum::TelemetryApp as i32,
+        payload: telemetry_request.encode_to_vec(),
+        want_response: true,
+        ..Default::default()
+    };
+    connection.send_data(destination, 0, data, false).await?;
 
     debug!("Telemetry request sent for type: {telemetry_type:?}, node: {node_id:?}");
     Ok(())
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
 pub enum TelemetryType {
     Battery,
     Environment,
     Device,
 }
 
-// Simple packet router that ignores all packets
-struct SimplePacketRouter;
-
-use meshtastic::types::NodeId;
-
-impl meshtastic::packet::PacketRouter<(), std::convert::Infallible> for SimplePacketRouter {
-    fn handle_packet_from_radio(
-        &mut self,
-        packet: protobufs::FromRadio,
-    ) -> Result<(), std::convert::Infallible> {
-        if let Some(variant) = &packet.payload_variant {
-            debug!(
-                "SimplePacketRouter: Ignoring FromRadio packet (variant: {variant:?})",
-                variant = std::mem::discriminant(variant)
-            );
-        } else {
-            debug!("SimplePacketRouter: Ignoring empty FromRadio packet");
+impl TelemetryType {
+    /// Empty telemetry of this type, which asks a node for its current values
+    pub fn request(self) -> protobufs::Telemetry {
+        let variant = match self {
+            Self::Battery | Self::Device => {
+                protobufs::telemetry::Variant::DeviceMetrics(protobufs::DeviceMetrics::default())
+            }
+            Self::Environment => protobufs::telemetry::Variant::EnvironmentMetrics(
+                protobufs::EnvironmentMetrics::default(),
+            ),
+        };
+        protobufs::Telemetry {
+            time: 0,
+            variant: Some(variant),
+        }
+    }
+}
+
+/// Pause between two requests of a poll, so a large node list does not
+/// flood the channel
+pub const POLL_REQUEST_SPACING: Duration = Duration::from_millis(500);
+
+/// How to poll every known node for telemetry
+#[derive(Debug, Clone, Copy)]
+pub struct TelemetryPollOptions {
+    pub telemetry_type: TelemetryType,
+    /// Requests waiting for a reply at any one time
+    pub concurrency: usize,
+    /// Time each node gets to reply
+    pub request_timeout: Duration,
+    /// Time the whole poll may take; nodes not asked by then are skipped
+    pub timeout: Duration,
+}
+
+/// Outcome of a single request in a telemetry poll
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum TelemetryPollStatus {
+    /// The node replied with its telemetry
+    Responded,
+    /// No reply arrived before the timeout
+    TimedOut,
+    /// The request could not be sent
+    SendFailed,
+    /// The poll ran out of time before the node was asked
+    Skipped,
+}
+
+/// Per-node result of a telemetry poll
+#[derive(Debug, Clone, Serialize)]
+pub struct TelemetryPollResult {
+    pub node_id: String,
+    pub node_num: u32,
+    pub status: TelemetryPollStatus,
+    /// Seconds between the request and the reply
+    pub response_secs: Option<f64>,
+    pub telemetry: Option<TelemetryData>,
+}
+
+impl TelemetryPollResult {
+    fn unanswered(node_num: u32, status: TelemetryPollStatus) -> Self {
+        Self {
+            node_id: NodeId(node_num).to_string(),
+            node_num,
+            status,
+            response_secs: None,
+            telemetry: None,
         }
-        Ok(())
     }
+}
+
+/// Request telemetry from every known node, a few at a time
+///
+/// At most `concurrency` requests wait for a reply at once, each for up to
+/// `request_timeout`, and requests are spaced by [`POLL_REQUEST_SPACING`].
+/// Results are ordered by node number.
+pub async fn poll_all_telemetry(
+    connection: &mut ConnectionManager,
+    options: &TelemetryPollOptions,
+    progress: Option<ProgressCallback<'_>>,
+) -> Result<Vec<TelemetryPollResult>> {
+    ensure!(options.concurrency > 0, "Concurrency must be at least 1");
+
+    let state = connection.get_device_state().await;
+    let my_node_num = state.my_node_info.as_ref().map(|info| info.node_num);
+    let mut node_nums: Vec<u32> = state
+        .nodes
+        .keys()
+        .copied()
+        .filter(|num| Some(*num) != my_node_num)
+        .collect();
+    node_nums.sort_unstable();
+
+    info!(
+        "Polling {count} nodes for {kind:?} telemetry...",
+        count = node_nums.len(),
+        kind = options.telemetry_type
+    );
+    let mut reporter =
+        ProgressReporter::start(progress, "poll_all_telemetry", Some(node_nums.len() as u64));
+
+    let payload = options.telemetry_type.request().encode_to_vec();
+    let deadline = Instant::now() + options.timeout;
+    let mut results = Vec::with_capacity(node_nums.len());
+    let mut waiting = JoinSet::new();
+    let mut queue = node_nums.into_iter();
+
+    while Instant::now() < deadline {
+        // Free a slot before sending the next request
+        if waiting.len() >= options.concurrency {
+            let Some(joined) = waiting.join_next().await else {
+                break;
+            };
+            let result = joined.context("Telemetry request task failed")?;
+            results.push(report_result(&mut reporter, result));
+            continue;
+        }
+        let Some(node_num) = queue.next() else {
+            break;
+        };
+
+        let node = NodeId(node_num);
+        match connection
+            .send_request_with_priority(
+                node_num,
+                protobufs::PortNum::TelemetryApp,
+                payload.clone(),
+                SendPriority::Background,
+            )
+            .await
+        {
+            Ok(pending) => {
+                debug!("Sent telemetry request to {node}");
+                let sent = Instant::now();
+                let request_deadline = (sent + options.request_timeout).min(deadline);
+                waiting.spawn(async move {
+                    match pending.wait_until(request_deadline).await {
+                        Some(RequestResponse::Telemetry(telemetry)) => TelemetryPollResult {
+                            response_secs: Some(sent.elapsed().as_secs_f64()),
+                            telemetry: Some(telemetry),
+                            ..TelemetryPollResult::unanswered(
+                                node_num,
+                                TelemetryPollStatus::Responded,
+                            )
+                        },
+                        _ => {
+                            TelemetryPollResult::unanswered(node_num, TelemetryPollStatus::TimedOut)
+                        }
+                    }
+                });
+            }
+            Err(e) => {
+                debug!("Failed to send telemetry request to {node}: {e}");
+                let result =
+                    TelemetryPollResult::unanswered(node_num, TelemetryPollStatus::SendFailed);
+                results.push(report_result(&mut reporter, result));
+            }
+        }
 
-    fn handle_mesh_packet(
-        &mut self,
-        packet: protobufs::MeshPacket,
-    ) -> Result<(), std::convert::Infallible> {
-        let portnum = packet.payload_variant.as_ref().and_then(|p| match p {
-            protobufs::mesh_packet::PayloadVariant::Decoded(d) => Some(d.portnum()),
-            _ => None,
-        });
-
-        debug!(
-            "SimplePacketRouter: Ignoring MeshPacket (from: {from:08x}, to: {to:08x}, portnum: {portnum:?})",
-            from = packet.from,
-            to = packet.to
-        );
-        Ok(())
+        tokio::time::sleep(POLL_REQUEST_SPACING).await;
     }
 
-    fn source_node_id(&self) -> NodeId {
-        0u32.into() // Default node ID for simple router
+    // Outstanding requests time out on their own deadlines
+    while let Some(joined) = waiting.join_next().await {
+        let result = joined.context("Telemetry request task failed")?;
+        results.push(report_result(&mut reporter, result));
     }
+    for node_num in queue {
+        let result = TelemetryPollResult::unanswered(node_num, TelemetryPollStatus::Skipped);
+        results.push(report_result(&mut reporter, result));
+    }
+    reporter.finish();
+    results.sort_by_key(|result| result.node_num);
+
+    let responded = results
+        .iter()
+        .filter(|result| result.status == TelemetryPollStatus::Responded)
+        .count();
+    info!(
+        "Received telemetry from {responded} of {total} nodes",
+        total = results.len()
+    );
+    Ok(results)
+}
+
+fn report_result(
+    reporter: &mut ProgressReporter<'_>,
+    result: TelemetryPollResult,
+) -> TelemetryPollResult {
+    reporter.advance(
+        1,
+        Some(format!(
+            "{node}: {status}",
+            node = result.node_id,
+            status = result.status
+        )),
+    );
+    result
 }
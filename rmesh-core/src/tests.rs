@@ -1,8 +1,30 @@
 #[cfg(test)]
 mod state_tests {
+    use crate::state::RetentionPolicy;
+    use crate::state::{ChannelInfo, STATE_SNAPSHOT_VERSION, StateSlice, StateSnapshot};
     use crate::state::{DeviceConfig, DeviceMetrics, PositionConfig, TelemetryData};
-    use crate::state::{DeviceState, MyNodeInfo, NodeInfo, Position, TextMessage, User};
+    use crate::state::{
+        DeviceState, MeshChange, MyNodeInfo, NodeInfo, Position, TextMessage, User,
+    };
+    use crate::state::{MqttConfig, NetworkConfig};
     use anyhow::{Context, Result};
+    use meshtastic::protobufs;
+
+    fn test_message(from_node: u32, text: &str, time: u64) -> TextMessage {
+        TextMessage {
+            from: format!("!{from_node:08x}"),
+            from_node,
+            to: "!ffffffff".to_string(),
+            to_node: 0xFFFFFFFF,
+            channel: 0,
+            text: text.to_string(),
+            time,
+            snr: None,
+            rssi: None,
+            acknowledged: false,
+            own: false,
+        }
+    }
 
     #[test]
     fn test_device_state_creation() -> Result<()> {
@@ -26,12 +48,16 @@ mod state_tests {
                 long_name: "Test User".to_string(),
                 short_name: "TU".to_string(),
                 hw_model: Some("T-Beam".to_string()),
+                role: None,
+                public_key: None,
+                is_licensed: false,
             },
             last_heard: Some(1234567890),
             last_heard_iso: chrono::DateTime::from_timestamp(1234567890, 0)
                 .map(|dt| dt.to_rfc3339()),
             snr: Some(5.5),
             rssi: Some(-70),
+            device_metrics: None,
         };
 
         state.update_node(0x12345678, node.clone());
@@ -42,6 +68,335 @@ mod state_tests {
         Ok(())
     }
 
+    #[test]
+    fn test_clear_messages() -> Result<()> {
+        let mut state = DeviceState::new();
+        for (index, time) in [100, 200, 300].into_iter().enumerate() {
+            state.add_message(test_message(1, &format!("message {index}"), time));
+        }
+
+        assert_eq!(state.clear_messages(Some(200)), 1);
+        let times = state.messages.iter().map(|m| m.time).collect::<Vec<_>>();
+        assert_eq!(times, [200, 300]);
+
+        assert_eq!(state.clear_messages(None), 2);
+        assert!(state.messages.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_change_journal() -> Result<()> {
+        let node = |long_name: &str, role: Option<&str>| NodeInfo {
+            id: "!00000abc".to_string(),
+            num: 0xabc,
+            user: User {
+                id: "!00000abc".to_string(),
+                long_name: long_name.to_string(),
+                short_name: "ABC".to_string(),
+                hw_model: None,
+                role: role.map(str::to_string),
+                public_key: None,
+                is_licensed: false,
+            },
+            last_heard: None,
+            last_heard_iso: None,
+            snr: None,
+            rssi: None,
+            device_metrics: None,
+        };
+        let mut state = DeviceState::new();
+
+        // The node database sent at connect is not a wave of joins
+        assert!(state.update_node(0xabc, node("Base", None)).is_empty());
+        state.update_node(0xabc, node("Base", Some("Client")));
+        assert!(state.changes.is_empty());
+
+        let changes = state.update_node(0xabc, node("Summit", Some("Router")));
+        let kinds: Vec<_> = changes.iter().map(|record| record.change.kind()).collect();
+        assert_eq!(kinds, ["node_renamed", "role_changed"]);
+        assert_eq!(
+            changes[0].change,
+            MeshChange::NodeRenamed {
+                node: "!00000abc".to_string(),
+                old_name: "Base (ABC)".to_string(),
+                new_name: "Summit (ABC)".to_string(),
+            }
+        );
+
+        state.mark_synced();
+        let mut newcomer = node("Newcomer", None);
+        newcomer.num = 0xdef;
+        let changes = state.update_node(0xdef, newcomer);
+        assert_eq!(changes[0].change.kind(), "node_joined");
+
+        let channel = |name: &str| ChannelInfo {
+            index: 1,
+            name: name.to_string(),
+            role: "SECONDARY".to_string(),
+            has_psk: true,
+            settings: None,
+        };
+        assert!(state.update_channel(channel("ops")).is_none());
+        assert!(state.update_channel(channel("ops")).is_none());
+        let change = state
+            .update_channel(channel("ops2"))
+            .context("Channel change")?;
+        assert_eq!(
+            change.change,
+            MeshChange::ChannelChanged {
+                index: 1,
+                name: "ops2".to_string(),
+            }
+        );
+
+        assert_eq!(state.changes.len(), 4);
+        assert!(state.changes_since(Some(change.time + 1)).is_empty());
+        assert_eq!(state.changes_since(Some(change.time)).last(), Some(&change));
+        assert_eq!(state.changes_since(None).len(), 4);
+
+        let json = serde_json::to_value(&change)?;
+        assert_eq!(json["change"], "channel_changed");
+        assert_eq!(json["index"], 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_record_route() -> Result<()> {
+        let mut state = DeviceState::new();
+        state.record_route(0x300, &[0x201, 0x202], 100);
+
+        let route = state.routes.get(&0x300).context("Route to destination")?;
+        assert_eq!(route.next_hop, 0x201);
+        assert_eq!(route.hop_count, 3);
+        // Relays on the path are reachable too
+        let relay = state.routes.get(&0x202).context("Route to second relay")?;
+        assert_eq!(relay.path, vec![0x201]);
+        assert_eq!(relay.hop_count, 2);
+        let neighbor = state.routes.get(&0x201).context("Route to first relay")?;
+        assert_eq!(neighbor.next_hop, 0x201);
+        assert_eq!(neighbor.hop_count, 1);
+
+        // Seeing the same path again keeps when it was first learned
+        state.record_route(0x300, &[0x201, 0x202], 200);
+        let route = state.routes.get(&0x300).context("Route after repeat")?;
+        assert_eq!((route.first_seen, route.last_seen), (100, 200));
+        assert_eq!(route.observations, 2);
+
+        // A new path replaces the old one
+        state.record_route(0x300, &[0x203], 300);
+        let route = state.routes.get(&0x300).context("Route after change")?;
+        assert_eq!(route.next_hop, 0x203);
+        assert_eq!((route.first_seen, route.observations), (300, 1));
+
+        // Unidentified relays get no route of their own
+        state.record_route(0x400, &[0xffff_ffff], 300);
+        assert!(!state.routes.contains_key(&0xffff_ffff));
+        assert!(state.routes.contains_key(&0x400));
+        Ok(())
+    }
+
+    #[test]
+    fn test_node_lookup_indexes() -> Result<()> {
+        let mut state = DeviceState::new();
+        let node = |num: u32, long_name: &str, short_name: &str| NodeInfo {
+            id: format!("!{num:08x}"),
+            num,
+            user: User {
+                id: format!("!{num:08x}"),
+                long_name: long_name.to_string(),
+                short_name: short_name.to_string(),
+                hw_model: None,
+                role: None,
+                public_key: None,
+                is_licensed: false,
+            },
+            last_heard: None,
+            last_heard_iso: None,
+            snr: None,
+            rssi: None,
+            device_metrics: None,
+        };
+        state.update_node(0x1111, node(0x1111, "Base Camp", "BASE"));
+        state.update_node(0x2222, node(0x2222, "Summit", "TOP"));
+
+        let found = state
+            .get_node_by_id("!00002222")
+            .context("Node not found")?;
+        assert_eq!(found.num, 0x2222);
+        // Any accepted node id form finds the node
+        let found = state.get_node_by_id("0x2222").context("Node not found")?;
+        assert_eq!(found.num, 0x2222);
+        let found = state.get_nodes_by_name("base camp");
+        assert_eq!(found.len(), 1);
+        assert_eq!(state.get_nodes_by_name("top").len(), 1);
+
+        // Renaming drops the old name from the index
+        state.update_node(0x1111, node(0x1111, "Valley", "VAL"));
+        assert!(state.get_nodes_by_name("Base Camp").is_empty());
+        assert_eq!(state.get_nodes_by_name("VALLEY").len(), 1);
+
+        // Nodes inserted directly are still found
+        state.nodes.insert(0x3333, node(0x3333, "Ridge", "RDG"));
+        assert!(state.get_node_by_id("!00003333").is_some());
+        assert_eq!(state.get_nodes_by_name("ridge").len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_state_slice_readiness() -> Result<()> {
+        let mut state = DeviceState::new();
+        assert!(!StateSlice::Channels.is_ready(&state, false));
+        assert!(!StateSlice::NodeDb.is_ready(&state, false));
+
+        for index in 0..8 {
+            state.update_channel(ChannelInfo {
+                index,
+                name: String::new(),
+                role: "Disabled".to_string(),
+                has_psk: false,
+                settings: None,
+            });
+        }
+        assert!(StateSlice::Channels.is_ready(&state, false));
+        // The node database is only known complete once the device says so
+        assert!(!StateSlice::NodeDb.is_ready(&state, false));
+        assert!(StateSlice::NodeDb.is_ready(&state, true));
+        // Nothing more arrives after the end of the configuration
+        assert!(StateSlice::LoraConfig.is_ready(&state, true));
+        assert_eq!(StateSlice::LoraConfig.to_string(), "LoRa settings");
+        Ok(())
+    }
+
+    #[test]
+    fn test_state_snapshot_round_trip() -> Result<()> {
+        let mut state = DeviceState::new();
+        state.update_owner(
+            0x1111,
+            User {
+                id: "!00001111".to_string(),
+                long_name: "Base Camp".to_string(),
+                short_name: "BASE".to_string(),
+                hw_model: None,
+                role: None,
+                public_key: None,
+                is_licensed: false,
+            },
+        );
+        state.add_message(test_message(0x1111, "hello", 1234567890));
+        state.record_route(0x2222, &[0x1111], 100);
+        state.channels.push(ChannelInfo {
+            index: 0,
+            name: "Private".to_string(),
+            role: "Primary".to_string(),
+            has_psk: true,
+            settings: Some(protobufs::ChannelSettings {
+                name: "Private".to_string(),
+                psk: vec![0x42; 32],
+                ..Default::default()
+            }),
+        });
+        state.network_config = Some(NetworkConfig {
+            wifi_enabled: true,
+            wifi_ssid: "home".to_string(),
+            wifi_psk: "correct horse".to_string(),
+            ntp_server: "pool.ntp.org".to_string(),
+            eth_enabled: false,
+            ipv4_config: None,
+        });
+        state.mqtt_config = Some(MqttConfig {
+            username: "meshdev".to_string(),
+            password: "large4cats".to_string(),
+            ..Default::default()
+        });
+
+        let json = serde_json::to_string(&state.to_snapshot())?;
+        let restored = DeviceState::from_snapshot(serde_json::from_str(&json)?)?;
+        assert_eq!(restored.messages.len(), 1);
+        assert_eq!(restored.routes, state.routes);
+        // Lookups work without the nodes being inserted again
+        assert_eq!(restored.get_nodes_by_name("base camp").len(), 1);
+
+        // Secrets survive, so restored channels still decrypt
+        let settings = restored.channels[0]
+            .settings
+            .as_ref()
+            .context("Channel settings were dropped")?;
+        assert_eq!(settings.psk, vec![0x42; 32]);
+        let network = restored.network_config.context("Network config dropped")?;
+        assert_eq!(network.wifi_psk, "correct horse");
+        let mqtt = restored.mqtt_config.context("MQTT config dropped")?;
+        assert_eq!(mqtt.password, "large4cats");
+
+        // Output with its secrets redacted is refused rather than restored
+        let mut redacted = state.to_snapshot();
+        if let Some(network) = &mut redacted.state.network_config {
+            network.wifi_psk = crate::redact::REDACTED.to_string();
+        }
+        assert!(DeviceState::from_snapshot(redacted).is_err());
+        let mut value = serde_json::to_value(state.to_snapshot())?;
+        crate::redact::redact_secrets(&mut value);
+        assert!(serde_json::from_value::<StateSnapshot>(value).is_err());
+
+        // Snapshots from before a field existed still load
+        let minimal: StateSnapshot =
+            serde_json::from_str(r#"{"version": 1, "taken_at": 0, "state": {"nodes": {}}}"#)?;
+        assert!(DeviceState::from_snapshot(minimal)?.nodes.is_empty());
+
+        let mut future = state.to_snapshot();
+        future.version = STATE_SNAPSHOT_VERSION + 1;
+        assert!(DeviceState::from_snapshot(future).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_owner_update() -> Result<()> {
+        let mut state = DeviceState::new();
+        let user = |long_name: &str| User {
+            id: "!12345678".to_string(),
+            long_name: long_name.to_string(),
+            short_name: "BASE".to_string(),
+            hw_model: None,
+            role: None,
+            public_key: None,
+            is_licensed: false,
+        };
+
+        // An owner response for an unknown node adds it
+        state.update_owner(0x12345678, user("Base camp"));
+        let node = state.nodes.get(&0x12345678).context("Node not added")?;
+        assert_eq!(node.id, "!12345678");
+        assert_eq!(node.user.long_name, "Base camp");
+
+        state
+            .nodes
+            .get_mut(&0x12345678)
+            .context("Node not found")?
+            .snr = Some(7.5);
+        state.update_owner(0x12345678, user("Summit"));
+        let node = state.nodes.get(&0x12345678).context("Node not found")?;
+        assert_eq!(node.user.long_name, "Summit");
+        assert_eq!(node.snr, Some(7.5));
+        Ok(())
+    }
+
+    #[test]
+    fn test_canned_messages() -> Result<()> {
+        let mut state = DeviceState::new();
+        state.set_canned_messages("Hi|On my way | |Need help|");
+        assert_eq!(
+            state.canned_messages,
+            Some(vec![
+                "Hi".to_string(),
+                "On my way".to_string(),
+                "Need help".to_string()
+            ])
+        );
+
+        state.clear_device_config();
+        assert!(state.canned_messages.is_none());
+        Ok(())
+    }
+
     #[test]
     fn test_position_update() -> Result<()> {
         let mut state = DeviceState::new();
@@ -53,6 +408,7 @@ mod state_tests {
             altitude: Some(100),
             time: Some("2024-01-01T00:00:00Z".to_string()),
             last_updated: 1234567890,
+            ..Default::default()
         };
 
         state.update_position(0x12345678, position.clone());
@@ -66,6 +422,66 @@ mod state_tests {
         Ok(())
     }
 
+    #[test]
+    fn test_supports_secure_dm() -> Result<()> {
+        let mut node = NodeInfo {
+            id: "12345678".to_string(),
+            num: 0x12345678,
+            user: User {
+                id: "!12345678".to_string(),
+                long_name: "Test User".to_string(),
+                short_name: "TU".to_string(),
+                hw_model: None,
+                role: Some("Client".to_string()),
+                public_key: None,
+                is_licensed: false,
+            },
+            last_heard: None,
+            last_heard_iso: None,
+            snr: None,
+            rssi: None,
+            device_metrics: None,
+        };
+        assert!(!node.supports_secure_dm());
+
+        node.user.public_key = Some("ab".repeat(32));
+        assert!(node.supports_secure_dm());
+
+        // Licensed operators may not encrypt
+        node.user.is_licensed = true;
+        assert!(!node.supports_secure_dm());
+        Ok(())
+    }
+
+    #[test]
+    fn test_position_from_protobuf() -> Result<()> {
+        let proto = meshtastic::protobufs::Position {
+            latitude_i: Some(377_749_000),
+            longitude_i: Some(-1_224_194_000),
+            ground_speed: Some(3),
+            ground_track: Some(27_050),
+            sats_in_view: 9,
+            precision_bits: 32,
+            pdop: 145,
+            ..Default::default()
+        };
+        let position =
+            Position::from_protobuf(0x12345678, &proto).context("Expected a position")?;
+        assert_eq!(position.node_id, "!12345678");
+        assert!((position.latitude - 37.7749).abs() < 1e-9);
+        assert_eq!(position.ground_speed, Some(3));
+        assert_eq!(position.ground_track, Some(270.5));
+        assert_eq!(position.sats_in_view, Some(9));
+        assert_eq!(position.precision_bits, Some(32));
+        // Unreported figures are zero on the wire
+        assert_eq!(position.fix_quality, None);
+        assert_eq!(position.pdop, Some(1.45));
+
+        let no_fix = meshtastic::protobufs::Position::default();
+        assert!(Position::from_protobuf(0x12345678, &no_fix).is_none());
+        Ok(())
+    }
+
     #[test]
     fn test_message_add() -> Result<()> {
         let mut state = DeviceState::new();
@@ -80,6 +496,7 @@ mod state_tests {
             snr: Some(5.0),
             rssi: Some(-80),
             acknowledged: false,
+            own: false,
         };
 
         state.add_message(message.clone());
@@ -88,6 +505,69 @@ mod state_tests {
         Ok(())
     }
 
+    #[test]
+    fn test_message_retention_max_messages() -> Result<()> {
+        let mut state = DeviceState::new();
+        state.set_retention_policy(RetentionPolicy {
+            max_messages: Some(3),
+            max_messages_per_node: None,
+            max_message_age_secs: None,
+            ..RetentionPolicy::default()
+        });
+
+        for i in 0..5 {
+            state.add_message(test_message(0x11111111, &format!("msg {i}"), 1234567890));
+        }
+
+        assert_eq!(state.messages.len(), 3);
+        assert_eq!(state.messages[0].text, "msg 2");
+        assert_eq!(state.retention_stats.messages_evicted, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_retention_per_node() -> Result<()> {
+        let mut state = DeviceState::new();
+        state.set_retention_policy(RetentionPolicy {
+            max_messages: None,
+            max_messages_per_node: Some(2),
+            max_message_age_secs: None,
+            ..RetentionPolicy::default()
+        });
+
+        state.add_message(test_message(0x11111111, "a1", 1234567890));
+        state.add_message(test_message(0x22222222, "b1", 1234567890));
+        state.add_message(test_message(0x11111111, "a2", 1234567890));
+        state.add_message(test_message(0x11111111, "a3", 1234567890));
+
+        let texts: Vec<&str> = state.messages.iter().map(|m| m.text.as_str()).collect();
+        assert_eq!(texts, vec!["b1", "a2", "a3"]);
+        assert_eq!(state.retention_stats.messages_evicted, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_retention_max_age() -> Result<()> {
+        let mut state = DeviceState::new();
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+
+        state.add_message(test_message(0x11111111, "old", now - 7200));
+        state.add_message(test_message(0x11111111, "new", now));
+        state.set_retention_policy(RetentionPolicy {
+            max_messages: None,
+            max_messages_per_node: None,
+            max_message_age_secs: Some(3600),
+            ..RetentionPolicy::default()
+        });
+
+        assert_eq!(state.messages.len(), 1);
+        assert_eq!(state.messages[0].text, "new");
+        assert_eq!(state.retention_stats.messages_expired, 1);
+        Ok(())
+    }
+
     #[test]
     fn test_my_node_info() -> Result<()> {
         let mut state = DeviceState::new();
@@ -97,6 +577,7 @@ mod state_tests {
             reboot_count: 5,
             min_app_version: 20300,
             device_id: "abcdef123456".to_string(),
+            pio_env: None,
         };
 
         state.set_my_node_info(my_info.clone());
@@ -118,11 +599,15 @@ mod state_tests {
                 long_name: "Test User".to_string(),
                 short_name: "TU".to_string(),
                 hw_model: None,
+                role: None,
+                public_key: None,
+                is_licensed: false,
             },
             last_heard: None,
             last_heard_iso: None,
             snr: None,
             rssi: None,
+            device_metrics: None,
         };
 
         state.update_node(0x12345678, node.clone());
@@ -210,12 +695,251 @@ mod state_tests {
         assert!(pos_config.gps_enabled);
         Ok(())
     }
+
+    fn test_position(latitude: f64, time: u64) -> Position {
+        Position {
+            node_id: "12345678".to_string(),
+            node_num: 0x12345678,
+            latitude,
+            longitude: 13.4,
+            altitude: None,
+            time: crate::time::to_rfc3339(time),
+            last_updated: time,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_position_history_is_bounded_and_deduplicated() -> Result<()> {
+        let mut state = DeviceState::new();
+        state.set_retention_policy(RetentionPolicy {
+            history_depth: 3,
+            ..RetentionPolicy::default()
+        });
+
+        state.update_position(0x12345678, test_position(52.0, 1000));
+        // A rebroadcast of the same fix is not a new history entry
+        state.update_position(0x12345678, test_position(52.0, 1000));
+        for i in 1..=3u32 {
+            state.update_position(
+                0x12345678,
+                test_position(52.0 + f64::from(i), 1000 + u64::from(i)),
+            );
+        }
+
+        let latitudes: Vec<f64> = state
+            .position_history(0x12345678)
+            .map(|position| position.latitude)
+            .collect();
+        assert_eq!(latitudes, vec![53.0, 54.0, 55.0]);
+        assert_eq!(state.retention_stats.history_evicted, 1);
+        assert_eq!(state.position_history(0x9999).count(), 0);
+
+        let latest = state
+            .positions
+            .get(&0x12345678)
+            .context("Missing position")?;
+        assert_eq!(latest.latitude, 55.0);
+
+        state.set_retention_policy(RetentionPolicy {
+            history_depth: 1,
+            ..RetentionPolicy::default()
+        });
+        assert_eq!(state.position_history(0x12345678).count(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_time_window_queries() -> Result<()> {
+        let mut state = DeviceState::new();
+        let position = |node_num: u32, latitude: f64, last_updated: u64| Position {
+            node_id: format!("!{node_num:08x}"),
+            node_num,
+            latitude,
+            longitude: 13.4,
+            altitude: None,
+            time: None,
+            last_updated,
+            ..Default::default()
+        };
+        state.update_position(0x1111, position(0x1111, 52.0, 100));
+        state.update_position(0x2222, position(0x2222, 48.0, 150));
+        state.update_position(0x1111, position(0x1111, 52.1, 200));
+
+        let since: Vec<(u32, u64)> = state
+            .positions_since(150)
+            .iter()
+            .map(|position| (position.node_num, position.last_updated))
+            .collect();
+        assert_eq!(since, vec![(0x2222, 150), (0x1111, 200)]);
+
+        let report = |node_num: u32, time: u64| TelemetryData {
+            node_num,
+            time,
+            device_metrics: None,
+            environment_metrics: None,
+            air_quality_metrics: None,
+        };
+        state.update_telemetry(0x1111, report(0x1111, 100));
+        state.update_telemetry(0x2222, report(0x2222, 300));
+        state.update_telemetry(0x1111, report(0x1111, 200));
+
+        let between: Vec<u64> = state
+            .telemetry_between(100, 200)
+            .iter()
+            .map(|telemetry| telemetry.time)
+            .collect();
+        assert_eq!(between, vec![100, 200]);
+        assert!(state.telemetry_between(201, 299).is_empty());
+
+        state.add_message(test_message(0x1111, "early", 100));
+        state.add_message(test_message(0x2222, "late", 200));
+        let texts: Vec<&str> = state
+            .messages_since(150)
+            .map(|message| message.text.as_str())
+            .collect();
+        assert_eq!(texts, vec!["late"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_telemetry_history() -> Result<()> {
+        let mut state = DeviceState::new();
+        let report = |time: u64, battery_level: u32| TelemetryData {
+            node_num: 0x12345678,
+            time,
+            device_metrics: Some(DeviceMetrics {
+                battery_level: Some(battery_level),
+                voltage: None,
+                channel_utilization: None,
+                air_util_tx: None,
+                uptime_seconds: None,
+            }),
+            environment_metrics: None,
+            air_quality_metrics: None,
+        };
+
+        state.update_telemetry(0x12345678, report(100, 90));
+        state.update_telemetry(0x12345678, report(100, 90));
+        state.update_telemetry(0x12345678, report(200, 85));
+
+        let levels: Vec<Option<u32>> = state
+            .telemetry_history(0x12345678)
+            .map(|telemetry| telemetry.device_metrics.as_ref()?.battery_level)
+            .collect();
+        assert_eq!(levels, vec![Some(90), Some(85)]);
+
+        state.set_retention_policy(RetentionPolicy {
+            history_depth: 0,
+            ..RetentionPolicy::default()
+        });
+        assert_eq!(state.telemetry_history(0x12345678).count(), 0);
+        assert!(state.telemetry.contains_key(&0x12345678));
+        Ok(())
+    }
+
+    #[test]
+    fn test_reboot_clears_device_config() -> Result<()> {
+        let mut state = DeviceState::new();
+        assert!(!state.is_reboot(1));
+
+        state.set_my_node_info(MyNodeInfo {
+            node_num: 0x12345678,
+            node_id: "12345678".to_string(),
+            reboot_count: 5,
+            min_app_version: 20300,
+            device_id: "abcdef123456".to_string(),
+            pio_env: None,
+        });
+        assert!(!state.is_reboot(5));
+        assert!(state.is_reboot(6));
+
+        state
+            .config
+            .insert("lora.region".to_string(), serde_json::json!("EU_868"));
+        state.add_message(test_message(0x1111, "before reboot", 100));
+
+        state.clear_device_config();
+        assert!(state.my_node_info.is_none());
+        assert!(state.config.is_empty());
+        assert_eq!(state.messages.len(), 1);
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod mesh_tests {
-    use crate::mesh::{MeshHealth, MeshNode, NetworkStats, RouteHop};
-    use anyhow::Result;
+    use crate::mesh::{
+        MeshEdge, MeshHealth, MeshNode, MeshTopology, NetworkStats, RouteHop, learned_route,
+        node_page,
+    };
+    use crate::state::{DeviceState, NodeInfo, User};
+    use anyhow::{Context, Result};
+
+    #[test]
+    fn test_node_page() -> Result<()> {
+        let mut state = DeviceState::new();
+        for (num, last_heard) in [(1, Some(100)), (2, Some(300)), (3, None), (4, Some(200))] {
+            state.update_node(
+                num,
+                NodeInfo {
+                    id: format!("!{num:08x}"),
+                    num,
+                    user: User {
+                        id: format!("!{num:08x}"),
+                        long_name: format!("Node {num}"),
+                        short_name: format!("N{num}"),
+                        hw_model: None,
+                        role: None,
+                        public_key: None,
+                        is_licensed: false,
+                    },
+                    last_heard,
+                    last_heard_iso: None,
+                    snr: None,
+                    rssi: None,
+                    device_metrics: None,
+                },
+            );
+        }
+
+        let nums = |offset, limit| -> Vec<u32> {
+            node_page(&state, offset, limit)
+                .nodes
+                .iter()
+                .map(|node| node.num)
+                .collect()
+        };
+        // Most recently heard first, never heard last
+        assert_eq!(nums(0, None), vec![2, 4, 1, 3]);
+        assert_eq!(nums(1, Some(2)), vec![4, 1]);
+        assert!(nums(10, Some(2)).is_empty());
+
+        let page = node_page(&state, 2, Some(10));
+        assert_eq!(page.total, 4);
+        assert_eq!(page.offset, 2);
+        assert_eq!(page.nodes.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_learned_route() -> Result<()> {
+        let local = 0x100;
+        // Reply to our traceroute: relays listed from us towards the sender
+        assert_eq!(
+            learned_route(local, 0x300, local, 42, &[0x201, 0x202]),
+            Some((0x300, vec![0x201, 0x202]))
+        );
+        // Traceroute sent to us: relays listed from the sender towards us
+        assert_eq!(
+            learned_route(local, 0x300, local, 0, &[0x202, 0x201]),
+            Some((0x300, vec![0x201, 0x202]))
+        );
+        // Traceroutes between other nodes say nothing about our routes
+        assert_eq!(learned_route(local, 0x300, 0x400, 42, &[0x201]), None);
+        assert_eq!(learned_route(local, local, 0x300, 0, &[]), None);
+        Ok(())
+    }
 
     #[test]
     fn test_network_stats_creation() -> Result<()> {
@@ -226,6 +950,7 @@ mod mesh_tests {
             average_snr: Some(5.5),
             average_rssi: Some(-75),
             mesh_health: MeshHealth::Good,
+            duplicate_packets: 0,
         };
 
         assert_eq!(stats.total_nodes, 10);
@@ -290,4 +1015,4059 @@ mod mesh_tests {
         assert_eq!(hop.snr, Some(5.5));
         Ok(())
     }
+    #[test]
+    fn test_topology_json_layout() -> Result<()> {
+        let topology = MeshTopology {
+            nodes: Vec::new(),
+            edges: vec![MeshEdge {
+                from: "!00000001".to_string(),
+                to: "!00000002".to_string(),
+                snr: Some(4.0),
+                rssi: None,
+            }],
+            total_nodes: 0,
+            my_node: None,
+        };
+
+        // Same keys the untyped topology output used to have
+        let json = serde_json::to_value(&topology)?;
+        let keys: Vec<&String> = json
+            .as_object()
+            .context("Expected object")?
+            .keys()
+            .collect();
+        assert_eq!(keys, ["edges", "my_node", "nodes", "total_nodes"]);
+        assert!(json["my_node"].is_null());
+        assert_eq!(json["edges"][0]["to"], "!00000002");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod dedup_tests {
+    use crate::connection::DuplicateFilter;
+    use anyhow::Result;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_duplicate_detection() -> Result<()> {
+        let mut filter = DuplicateFilter::default();
+        assert!(!filter.check_and_insert(0x11111111, 42));
+        assert!(filter.check_and_insert(0x11111111, 42));
+        // Same id from a different node is a different packet
+        assert!(!filter.check_and_insert(0x22222222, 42));
+        Ok(())
+    }
+
+    #[test]
+    fn test_duplicate_filter_expiry() -> Result<()> {
+        let mut filter = DuplicateFilter::new(16, Duration::from_secs(10));
+        let start = Instant::now();
+        assert!(!filter.check_and_insert_at(0x11111111, 1, start));
+        assert!(filter.check_and_insert_at(0x11111111, 1, start + Duration::from_secs(5)));
+        assert!(!filter.check_and_insert_at(0x11111111, 1, start + Duration::from_secs(11)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_duplicate_filter_capacity() -> Result<()> {
+        let mut filter = DuplicateFilter::new(2, Duration::from_secs(60));
+        let now = Instant::now();
+        assert!(!filter.check_and_insert_at(1, 1, now));
+        assert!(!filter.check_and_insert_at(1, 2, now));
+        assert!(!filter.check_and_insert_at(1, 3, now));
+        // The oldest entry was evicted to make room
+        assert!(!filter.check_and_insert_at(1, 1, now));
+        assert!(filter.check_and_insert_at(1, 3, now));
+        Ok(())
+    }
+
+    #[test]
+    fn test_contains_does_not_record() -> Result<()> {
+        let mut filter = DuplicateFilter::default();
+        assert!(!filter.contains(0, 7));
+        assert!(!filter.contains(0, 7));
+        filter.insert(0, 7);
+        assert!(filter.contains(0, 7));
+        assert!(!filter.contains(0x11111111, 7));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod handshake_tests {
+    use crate::connection::framing::{FrameScanner, ScanErrors, encode_frame};
+    use crate::connection::handshake::{CountingStream, diagnose_handshake_failure};
+    use crate::connection::{
+        ConnectionCounters, ConnectionError, ConnectionStats, HandshakeOptions, LinkQuality,
+        QualityRating,
+    };
+    use anyhow::{Context, Result};
+    use meshtastic::Message;
+    use meshtastic::protobufs::{FromRadio, from_radio};
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[test]
+    fn test_silent_link_is_not_responding() -> Result<()> {
+        let options = HandshakeOptions::default();
+        let error = diagnose_handshake_failure("/dev/ttyUSB0", &options, 0, 0);
+        assert!(matches!(
+            error,
+            ConnectionError::NotResponding {
+                attempts: 3,
+                frames_received: 0,
+                ..
+            }
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_undecodable_bytes_are_protocol_mismatch() -> Result<()> {
+        let options = HandshakeOptions::default();
+        let error = diagnose_handshake_failure("/dev/ttyUSB0", &options, 512, 0);
+        assert!(matches!(
+            error,
+            ConnectionError::ProtocolMismatch {
+                bytes_received: 512,
+                ..
+            }
+        ));
+        assert!(error.to_string().contains("baud rate"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_stalled_device_is_not_responding() -> Result<()> {
+        let options = HandshakeOptions::default();
+        let error = diagnose_handshake_failure("192.168.1.10:4403", &options, 2048, 7);
+        assert!(matches!(
+            error,
+            ConnectionError::NotResponding {
+                frames_received: 7,
+                ..
+            }
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_counting_stream_counts_both_directions() -> Result<()> {
+        let counters = ConnectionCounters::default();
+        let (near, mut far) = tokio::io::duplex(64);
+        let mut stream = CountingStream::new(near, &counters);
+
+        stream.write_all(&[0x94, 0xc3, 0x00, 0x02]).await?;
+        far.write_all(&[0x94, 0xc3, 0x00]).await?;
+        let mut buf = [0u8; 3];
+        stream.read_exact(&mut buf).await?;
+        counters.record_connect_time(Duration::from_millis(1500));
+
+        let stats = counters.snapshot();
+        assert_eq!(stats.bytes_tx, 4);
+        assert_eq!(stats.bytes_rx, 3);
+        assert_eq!(stats.connect_time, Duration::from_millis(1500));
+
+        counters.reset();
+        assert_eq!(counters.snapshot(), ConnectionStats::default());
+        Ok(())
+    }
+
+    fn frame() -> Vec<u8> {
+        let packet = FromRadio {
+            id: 1,
+            payload_variant: Some(from_radio::PayloadVariant::ConfigCompleteId(42)),
+        };
+        encode_frame(&packet.encode_to_vec())
+    }
+
+    #[test]
+    fn test_frame_scanner_counts_noise_after_the_first_frame() -> Result<()> {
+        let mut scanner = FrameScanner::default();
+        // Stale bytes left from before the link was opened
+        let mut bytes = b"stale".to_vec();
+        bytes.extend(frame());
+        assert_eq!(scanner.feed(&bytes), ScanErrors::default());
+
+        // One run of noise is one sync error, even split across reads
+        assert_eq!(scanner.feed(b"log li").sync, 1);
+        assert_eq!(scanner.feed(b"ne\n").sync, 0);
+        let mut bytes = frame();
+        bytes.extend(encode_frame(&[0xff, 0xff]));
+        assert_eq!(scanner.feed(&bytes), ScanErrors { sync: 0, decode: 1 });
+
+        // Lengths beyond any frame mean the framing was lost
+        assert_eq!(scanner.feed(&[0x94, 0xc3, 0x7f, 0xff]).sync, 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_counting_stream_measures_link_quality() -> Result<()> {
+        let counters = ConnectionCounters::default();
+        let (near, mut far) = tokio::io::duplex(256);
+        let mut stream = CountingStream::new(near, &counters);
+
+        let mut bytes = frame();
+        bytes.extend(b"noise");
+        bytes.extend(frame());
+        far.write_all(&bytes).await?;
+        let mut buf = vec![0u8; bytes.len()];
+        stream.read_exact(&mut buf).await?;
+        counters.record_response(Duration::from_millis(200));
+        counters.record_response(Duration::from_millis(400));
+        counters.record_timeout();
+
+        let quality = counters.quality();
+        assert_eq!(quality.sync_errors, 1);
+        assert_eq!(quality.decode_errors, 0);
+        assert_eq!(quality.responses, 2);
+        assert_eq!(quality.timeouts, 1);
+        assert_eq!(quality.average_response_time_ms, Some(300));
+
+        counters.reset();
+        assert_eq!(counters.quality(), LinkQuality::default());
+        Ok(())
+    }
+
+    #[test]
+    fn test_link_quality_rating() -> Result<()> {
+        let quality = LinkQuality::new(990, 5, 0, 5, 0, None);
+        assert_eq!(quality.errors(), 5);
+        assert_eq!(quality.successes(), 995);
+        assert!((quality.error_rate - 0.005).abs() < 1e-9);
+        assert_eq!(quality.rating, Some(QualityRating::Excellent));
+
+        let quality = LinkQuality::new(8, 0, 1, 0, 1, None);
+        let rating = quality.rating.context("Expected a rating")?;
+        assert_eq!(rating, QualityRating::Poor);
+        assert_eq!(LinkQuality::default().rating, None);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod packet_id_tests {
+    use crate::connection::PacketIdSource;
+    use anyhow::Result;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_packet_ids_increment() -> Result<()> {
+        let ids = PacketIdSource::with_seed(100);
+        assert_eq!(ids.next_id(), 100);
+        assert_eq!(ids.next_id(), 101);
+        assert_eq!(ids.next_id(), 102);
+        Ok(())
+    }
+
+    #[test]
+    fn test_packet_ids_roll_over_without_zero() -> Result<()> {
+        let ids = PacketIdSource::with_seed(u32::MAX - 1);
+        assert_eq!(ids.next_id(), u32::MAX - 1);
+        assert_eq!(ids.next_id(), u32::MAX);
+        // Zero means "firmware assigns", so the rollover skips it
+        assert_eq!(ids.next_id(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_packet_ids_unique_within_session() -> Result<()> {
+        let ids = PacketIdSource::new();
+        let seen: HashSet<u32> = (0..10_000).map(|_| ids.next_id()).collect();
+        assert_eq!(seen.len(), 10_000);
+        assert!(!seen.contains(&0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_packet_ids_reseed() -> Result<()> {
+        let ids = PacketIdSource::with_seed(5);
+        ids.next_id();
+        ids.reseed(42);
+        assert_eq!(ids.next_id(), 42);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod trace_tests {
+    use crate::connection::trace::PacketTracer;
+    use anyhow::{Context, Result};
+    use meshtastic::protobufs;
+
+    #[test]
+    fn test_tracer_matches_first_reply() -> Result<()> {
+        let tracer = PacketTracer::default();
+        tracer.record_sent(&protobufs::MeshPacket {
+            id: 7,
+            to: 0x1234,
+            payload_variant: Some(protobufs::mesh_packet::PayloadVariant::Decoded(
+                protobufs::Data {
+                    portnum: protobufs::PortNum::AdminApp as i32,
+                    ..Default::default()
+                },
+            )),
+            ..Default::default()
+        });
+
+        assert!(tracer.complete(8).is_none());
+        let round_trip = tracer.complete(7).context("Reply should match packet 7")?;
+        assert_eq!(round_trip.portnum, protobufs::PortNum::AdminApp);
+        assert_eq!(round_trip.to, 0x1234);
+        // A second reply, e.g. an ACK after the response, is not matched again
+        assert!(tracer.complete(7).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_tracer_ignores_unsolicited_packets() -> Result<()> {
+        let tracer = PacketTracer::default();
+        tracer.record_sent(&protobufs::MeshPacket::default());
+        assert!(tracer.complete(0).is_none());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod events_tests {
+    use crate::events::{MeshEvent, RoutingReport};
+    use anyhow::{Context, Result};
+
+    #[test]
+    fn test_routing_events_are_tagged() -> Result<()> {
+        let report = RoutingReport {
+            packet_id: 0x1234,
+            from: "deadbeef".to_string(),
+            from_node: 0xdeadbeef,
+            reason: "MaxRetransmit".to_string(),
+        };
+
+        let json = serde_json::to_value(MeshEvent::Nak(report.clone()))?;
+        assert_eq!(json["type"], "nak");
+        assert_eq!(json["packet_id"], 0x1234);
+        assert_eq!(json["reason"], "MaxRetransmit");
+
+        let json = serde_json::to_value(MeshEvent::RoutingError(report))?;
+        let tag = json["type"].as_str().context("missing event tag")?;
+        assert_eq!(tag, "routing_error");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod admin_tests {
+    use crate::admin::{
+        AdminDestination, BROADCAST_NODE_NUM, SessionKeyError, admin_packet, is_admin_refusal,
+        role_advice, session_key_backoff,
+    };
+    use crate::state::{DeviceConfig, DeviceState, PositionConfig};
+    use anyhow::Result;
+    use meshtastic::protobufs;
+    use protobufs::config::device_config::Role;
+    use std::time::Duration;
+
+    fn reboot_message() -> protobufs::AdminMessage {
+        protobufs::AdminMessage {
+            payload_variant: Some(protobufs::admin_message::PayloadVariant::RebootSeconds(5)),
+            session_passkey: vec![1, 2, 3],
+        }
+    }
+
+    #[test]
+    fn test_local_admin_packet() -> Result<()> {
+        let packet = admin_packet(AdminDestination::Local, 7, &reboot_message(), false);
+        assert_eq!(packet.to, 0);
+        assert_eq!(packet.id, 7);
+        assert!(!packet.want_ack);
+        assert!(!packet.pki_encrypted);
+        Ok(())
+    }
+
+    #[test]
+    fn test_remote_admin_packet() -> Result<()> {
+        let packet = admin_packet(
+            AdminDestination::Node(0x12345678),
+            8,
+            &reboot_message(),
+            true,
+        );
+        assert_eq!(packet.to, 0x12345678);
+        assert!(packet.want_ack);
+        assert!(packet.pki_encrypted);
+        assert_eq!(packet.hop_limit, 3);
+        let Some(protobufs::mesh_packet::PayloadVariant::Decoded(data)) = packet.payload_variant
+        else {
+            anyhow::bail!("admin packet is not decoded");
+        };
+        assert!(data.want_response);
+        assert_eq!(data.portnum, protobufs::PortNum::AdminApp as i32);
+        Ok(())
+    }
+
+    #[test]
+    fn test_node_info_packet() -> Result<()> {
+        use crate::device::node_info_packet;
+        use meshtastic::Message;
+
+        let user = protobufs::User {
+            id: "!12345678".to_string(),
+            long_name: "Renamed Node".to_string(),
+            short_name: "RN".to_string(),
+            ..Default::default()
+        };
+        let packet = node_info_packet(&user, BROADCAST_NODE_NUM, 9, true);
+        assert_eq!((packet.to, packet.id), (BROADCAST_NODE_NUM, 9));
+        assert!(!packet.want_ack);
+        let Some(protobufs::mesh_packet::PayloadVariant::Decoded(data)) = packet.payload_variant
+        else {
+            anyhow::bail!("NodeInfo packet is not decoded");
+        };
+        assert!(data.want_response);
+        assert_eq!(data.portnum, protobufs::PortNum::NodeinfoApp as i32);
+        assert_eq!(protobufs::User::decode(data.payload.as_slice())?, user);
+        Ok(())
+    }
+
+    #[test]
+    fn test_broadcast_admin_is_refused() -> Result<()> {
+        let result = AdminDestination::resolve(Some(BROADCAST_NODE_NUM), false);
+        let error = result.err().map(|e| e.to_string()).unwrap_or_default();
+        assert!(error.contains("Refusing"), "unexpected result: {error}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_broadcast_admin_override() -> Result<()> {
+        let destination = AdminDestination::resolve(Some(BROADCAST_NODE_NUM), true)?;
+        assert_eq!(destination, AdminDestination::Broadcast);
+
+        let packet = admin_packet(destination, 9, &reboot_message(), false);
+        assert_eq!(packet.to, BROADCAST_NODE_NUM);
+        // Broadcasts can neither be acknowledged nor PKI encrypted
+        assert!(!packet.want_ack);
+        assert!(!packet.pki_encrypted);
+        Ok(())
+    }
+
+    #[test]
+    fn test_admin_destination_resolution() -> Result<()> {
+        assert_eq!(
+            AdminDestination::resolve(None, false)?,
+            AdminDestination::Local
+        );
+        assert_eq!(
+            AdminDestination::resolve(Some(0x12345678), false)?,
+            AdminDestination::Node(0x12345678)
+        );
+        assert_eq!(AdminDestination::Local.node_num(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_infrastructure_roles_need_acknowledgement() -> Result<()> {
+        assert!(role_advice(Role::Router, None).requires_acknowledgement);
+        assert!(role_advice(Role::Repeater, None).requires_acknowledgement);
+        assert!(!role_advice(Role::ClientMute, None).requires_acknowledgement);
+        assert!(role_advice(Role::ClientMute, None).warnings.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_tracker_without_position_source_warns() -> Result<()> {
+        let mut state = DeviceState::new();
+        state.position_config = Some(PositionConfig {
+            position_broadcast_secs: 900,
+            position_broadcast_smart_enabled: true,
+            fixed_position: false,
+            gps_enabled: false,
+            gps_mode: "Disabled".to_string(),
+        });
+        let advice = role_advice(Role::Tracker, Some(&state));
+        assert_eq!(advice.role, "TRACKER");
+        assert!(
+            advice
+                .warnings
+                .iter()
+                .any(|w| w.contains("GPS is disabled"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_current_role_is_reported() -> Result<()> {
+        let mut state = DeviceState::new();
+        state.device_config = Some(DeviceConfig {
+            role: "ClientMute".to_string(),
+            button_gpio: 0,
+            buzzer_gpio: 0,
+            rebroadcast_mode: "All".to_string(),
+            node_info_broadcast_secs: 10800,
+            tzdef: None,
+            disable_triple_click: false,
+        });
+        let advice = role_advice(Role::ClientMute, Some(&state));
+        assert!(advice.warnings.iter().any(|w| w.contains("already uses")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_session_key_backoff_doubles() -> Result<()> {
+        assert_eq!(session_key_backoff(1), Duration::from_secs(1));
+        assert_eq!(session_key_backoff(2), Duration::from_secs(2));
+        assert_eq!(session_key_backoff(3), Duration::from_secs(4));
+        Ok(())
+    }
+
+    #[test]
+    fn test_admin_refusal_reasons() -> Result<()> {
+        assert!(is_admin_refusal("NotAuthorized"));
+        assert!(is_admin_refusal("AdminPublicKeyUnauthorized"));
+        assert!(is_admin_refusal("NoChannel"));
+        // Delivery failures are worth retrying
+        assert!(!is_admin_refusal("MaxRetransmit"));
+        assert!(!is_admin_refusal("Timeout"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_session_key_error_hints_at_admin_channel() -> Result<()> {
+        let refused = SessionKeyError::Refused {
+            target: AdminDestination::Node(0x1234abcd).to_string(),
+            reason: "NotAuthorized".to_string(),
+        };
+        let message = refused.to_string();
+        assert!(message.contains("node !1234abcd"));
+        assert!(message.contains("NotAuthorized"));
+        assert!(message.contains("\"admin\""));
+
+        let no_response = SessionKeyError::NoResponse {
+            target: AdminDestination::Local.to_string(),
+            attempts: 3,
+        };
+        assert!(no_response.to_string().contains("after 3 attempt(s)"));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod time_tests {
+    use crate::time::{
+        TimeFormat, format_age, format_rfc3339, format_timestamp_at, parse_age, to_rfc3339,
+    };
+    use anyhow::{Context, Result};
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    #[test]
+    fn test_time_format_parses_cli_names() -> Result<()> {
+        assert_eq!(TimeFormat::from_str("rfc3339")?, TimeFormat::Rfc3339);
+        assert_eq!(TimeFormat::from_str("Local")?, TimeFormat::Local);
+        assert_eq!(TimeFormat::from_str("relative")?, TimeFormat::Relative);
+        assert_eq!(TimeFormat::from_str("epoch")?, TimeFormat::Epoch);
+        assert!(TimeFormat::from_str("iso").is_err());
+        assert_eq!(TimeFormat::Relative.to_string(), "relative");
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_age_units() -> Result<()> {
+        assert_eq!(format_age(0), "0s ago");
+        assert_eq!(format_age(59), "59s ago");
+        assert_eq!(format_age(60), "1m ago");
+        assert_eq!(format_age(3599), "59m ago");
+        assert_eq!(format_age(7200), "2h ago");
+        assert_eq!(format_age(3 * 86_400 + 5), "3d ago");
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_timestamp_at() -> Result<()> {
+        let timestamp = 1_700_000_000;
+        assert_eq!(
+            format_timestamp_at(timestamp, TimeFormat::Epoch, 0),
+            "1700000000"
+        );
+        assert_eq!(
+            format_timestamp_at(timestamp, TimeFormat::Rfc3339, 0),
+            "2023-11-14T22:13:20+00:00"
+        );
+        assert_eq!(
+            format_timestamp_at(timestamp, TimeFormat::Relative, timestamp + 90),
+            "1m ago"
+        );
+        // Clock skew must not underflow
+        assert_eq!(
+            format_timestamp_at(timestamp, TimeFormat::Relative, timestamp - 10),
+            "0s ago"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_rfc3339_round_trip() -> Result<()> {
+        let time = to_rfc3339(1_700_000_000).context("timestamp in range")?;
+        assert_eq!(format_rfc3339(&time, TimeFormat::Epoch), "1700000000");
+        assert_eq!(
+            format_rfc3339("not a time", TimeFormat::Epoch),
+            "not a time"
+        );
+        assert_eq!(to_rfc3339(u64::MAX), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_age() -> Result<()> {
+        assert_eq!(parse_age("90")?, Duration::from_secs(90));
+        assert_eq!(parse_age("30m")?, Duration::from_secs(1800));
+        assert_eq!(parse_age(" 7D ")?, Duration::from_secs(7 * 86_400));
+        assert_eq!(parse_age("2w")?, Duration::from_secs(14 * 86_400));
+        for bad in ["", "d", "7y", "1.5h", "-3d", "99999999999999999999w"] {
+            assert!(parse_age(bad).is_err(), "{bad} should not parse");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod channel_tests {
+    use crate::channel::{
+        AuditSeverity, PskStrength, audit_channels, channel_index_by_name, exposes_exact_position,
+        position_precision_m, psk_strength,
+    };
+    use crate::channel_set::{ChannelRole, ChannelSet, ChannelSlot};
+    use crate::state::ChannelInfo;
+    use anyhow::{Context, Result, ensure};
+    use meshtastic::protobufs;
+
+    fn channel(index: u32, role: &str, psk: &[u8], position_precision: u32) -> ChannelInfo {
+        ChannelInfo {
+            index,
+            name: format!("ch{index}"),
+            role: role.to_string(),
+            has_psk: !psk.is_empty(),
+            settings: Some(protobufs::ChannelSettings {
+                psk: psk.to_vec(),
+                module_settings: Some(protobufs::ModuleSettings {
+                    position_precision,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+        }
+    }
+
+    #[test]
+    fn test_psk_strength() -> Result<()> {
+        assert_eq!(psk_strength(&[]), PskStrength::None);
+        assert_eq!(psk_strength(&[0]), PskStrength::None);
+        assert_eq!(psk_strength(&[1]), PskStrength::Default);
+        assert_eq!(psk_strength(&[5]), PskStrength::Simple);
+        assert_eq!(psk_strength(&[7; 16]), PskStrength::Aes128);
+        assert_eq!(psk_strength(&[7; 32]), PskStrength::Aes256);
+        assert_eq!(psk_strength(b"hunter2"), PskStrength::Invalid);
+        Ok(())
+    }
+
+    #[test]
+    fn test_audit_flags_unencrypted_primary_with_full_precision() -> Result<()> {
+        let audit = audit_channels(&[channel(0, "Primary", &[], 32)]);
+        assert_eq!(audit.channels_checked, 1);
+        assert_eq!(audit.highest_severity(), Some(AuditSeverity::Critical));
+        let first = audit.findings.first().context("Expected a finding")?;
+        assert_eq!(first.severity, AuditSeverity::Critical);
+        assert!(first.issue.contains("exact positions"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_audit_flags_default_psk() -> Result<()> {
+        let audit = audit_channels(&[channel(0, "Primary", &[1], 13)]);
+        assert_eq!(audit.findings.len(), 1);
+        assert_eq!(audit.highest_severity(), Some(AuditSeverity::Warning));
+        let finding = audit.findings.first().context("Expected a finding")?;
+        assert!(finding.issue.contains("AQ=="));
+        Ok(())
+    }
+
+    #[test]
+    fn test_audit_accepts_strong_channels_and_skips_disabled() -> Result<()> {
+        let audit = audit_channels(&[
+            channel(0, "Primary", &[9; 32], 32),
+            channel(1, "Disabled", &[], 32),
+        ]);
+        assert_eq!(audit.channels_checked, 1);
+        assert!(audit.findings.is_empty());
+        assert_eq!(audit.highest_severity(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_position_precision() -> Result<()> {
+        assert_eq!(position_precision_m(0), None);
+        let exact = position_precision_m(32).context("Expected a precision")?;
+        assert!(exact < 0.01);
+        // The firmware's 13 bit setting is listed as ~2.9 km
+        let coarse = position_precision_m(13).context("Expected a precision")?;
+        assert!((2_900.0..3_000.0).contains(&coarse), "{coarse}");
+        let medium = position_precision_m(16).context("Expected a precision")?;
+        assert!((coarse / medium - 8.0).abs() < 1e-9);
+        Ok(())
+    }
+
+    #[test]
+    fn test_exact_position_exposure() -> Result<()> {
+        assert!(exposes_exact_position(&[], 32));
+        assert!(exposes_exact_position(&[1], 32));
+        assert!(!exposes_exact_position(&[1], 16));
+        assert!(!exposes_exact_position(&[9; 32], 32));
+
+        let info = crate::channel::ChannelInfo::from(channel(0, "Primary", &[1], 32));
+        assert!(info.exposes_position);
+        assert_eq!(info.position_precision, 32);
+        let info = crate::channel::ChannelInfo::from(channel(1, "Secondary", &[9; 32], 0));
+        assert!(!info.exposes_position);
+        assert_eq!(info.position_precision_m, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_channel_index_by_name() -> Result<()> {
+        let named = |index, role: &str, name: &str| ChannelInfo {
+            name: name.to_string(),
+            ..channel(index, role, &[1], 0)
+        };
+        let channels = [
+            named(2, "Secondary", "longfast2"),
+            named(0, "Primary", ""),
+            named(1, "Secondary", "LongFast2"),
+            named(3, "Disabled", "Old"),
+        ];
+
+        assert_eq!(channel_index_by_name(&channels, "LongFast2")?, 1);
+        assert_eq!(channel_index_by_name(&channels, "LONGFAST2")?, 1);
+        assert_eq!(channel_index_by_name(&channels, "longfast2")?, 2);
+
+        let error = channel_index_by_name(&channels, "Old")
+            .err()
+            .context("Disabled channel was selected")?;
+        assert_eq!(
+            error.to_string(),
+            "No channel named \"Old\"; available channels: 0 (unnamed), 1 (LongFast2), \
+             2 (longfast2)"
+        );
+        ensure!(channel_index_by_name(&[], "LongFast").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_channel_set_covers_every_slot() -> Result<()> {
+        let channels = [
+            channel(0, "Primary", &[9; 32], 13),
+            channel(2, "Secondary", &[1], 0),
+        ];
+        let set = ChannelSet::from_channels(&channels, true);
+        assert_eq!(set.channels.len(), 8);
+        set.validate()?;
+
+        let primary = set.channels.first().context("Expected slot 0")?;
+        assert_eq!(primary.role, ChannelRole::Primary);
+        assert_eq!(primary.psk.as_deref(), Some("09".repeat(32).as_str()));
+        assert_eq!(primary.position_precision, 13);
+        let missing = set.channels.get(1).context("Expected slot 1")?;
+        assert_eq!(missing.role, ChannelRole::Disabled);
+        let secondary = set.channels.get(2).context("Expected slot 2")?;
+        assert_eq!(secondary.role, ChannelRole::Secondary);
+        assert_eq!(secondary.psk.as_deref(), Some("01"));
+
+        let redacted = ChannelSet::from_channels(&channels, false);
+        assert!(redacted.channels.iter().all(|slot| slot.psk.is_none()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_channel_set_yaml_round_trip() -> Result<()> {
+        let set = ChannelSet::from_channels(&[channel(0, "Primary", &[7; 16], 32)], false);
+        let yaml = serde_yaml::to_string(&set)?;
+        assert!(!yaml.contains("psk"));
+        let parsed: ChannelSet = serde_yaml::from_str(&yaml)?;
+        assert_eq!(parsed, set);
+
+        let parsed: ChannelSet =
+            serde_yaml::from_str("channels:\n  - index: 0\n    role: primary\n    psk: \"01\"\n")?;
+        let slot = parsed.channels.first().context("Expected a slot")?;
+        assert_eq!(slot.name, "");
+        assert!(!slot.uplink_enabled);
+        parsed.validate()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_channel_set_validation() -> Result<()> {
+        let slot = |index, role| ChannelSlot {
+            index,
+            role,
+            name: String::new(),
+            psk: None,
+            uplink_enabled: false,
+            downlink_enabled: false,
+            position_precision: 0,
+        };
+        let set = |channels| ChannelSet { channels };
+
+        assert!(
+            set(vec![
+                slot(0, ChannelRole::Primary),
+                slot(1, ChannelRole::Secondary)
+            ])
+            .validate()
+            .is_ok()
+        );
+        assert!(
+            set(vec![slot(0, ChannelRole::Secondary)])
+                .validate()
+                .is_err()
+        );
+        assert!(
+            set(vec![
+                slot(0, ChannelRole::Primary),
+                slot(3, ChannelRole::Primary)
+            ])
+            .validate()
+            .is_err()
+        );
+        assert!(
+            set(vec![
+                slot(0, ChannelRole::Primary),
+                slot(0, ChannelRole::Primary)
+            ])
+            .validate()
+            .is_err()
+        );
+        assert!(
+            set(vec![slot(8, ChannelRole::Secondary)])
+                .validate()
+                .is_err()
+        );
+
+        let mut bad_psk = slot(0, ChannelRole::Primary);
+        bad_psk.psk = Some("abcd".to_string());
+        assert!(set(vec![bad_psk]).validate().is_err());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod redact_tests {
+    use crate::redact::{REDACTED, is_secret_field, redact_secrets};
+    use crate::state::{ChannelInfo, NetworkConfig};
+    use anyhow::Result;
+    use meshtastic::protobufs;
+    use serde_json::json;
+
+    fn network() -> NetworkConfig {
+        NetworkConfig {
+            wifi_enabled: true,
+            wifi_ssid: "home".to_string(),
+            wifi_psk: "correct horse".to_string(),
+            ntp_server: "pool.ntp.org".to_string(),
+            eth_enabled: false,
+            ipv4_config: None,
+        }
+    }
+
+    fn channel() -> ChannelInfo {
+        ChannelInfo {
+            index: 0,
+            name: "Private".to_string(),
+            role: "Primary".to_string(),
+            has_psk: true,
+            settings: Some(protobufs::ChannelSettings {
+                name: "Private".to_string(),
+                psk: vec![0x42; 32],
+                ..Default::default()
+            }),
+        }
+    }
+
+    #[test]
+    fn test_serialization_keeps_secrets() -> Result<()> {
+        let json = serde_json::to_value(network())?;
+        assert_eq!(json["wifi_psk"], "correct horse");
+        let json = serde_json::to_value(channel())?;
+        assert_ne!(json["settings"]["psk"], REDACTED);
+        Ok(())
+    }
+
+    #[test]
+    fn test_secrets_redacted_at_any_depth() -> Result<()> {
+        let mut json = json!({
+            "network": network(),
+            "channels": [channel()],
+            "mqtt": {"username": "meshdev", "password": "large4cats"},
+        });
+        redact_secrets(&mut json);
+        assert_eq!(json["network"]["wifi_psk"], REDACTED);
+        assert_eq!(json["network"]["wifi_ssid"], "home");
+        assert_eq!(json["channels"][0]["settings"]["psk"], REDACTED);
+        assert_eq!(json["channels"][0]["has_psk"], true);
+        assert_eq!(json["mqtt"]["password"], REDACTED);
+        assert_eq!(json["mqtt"]["username"], "meshdev");
+
+        // Unset secrets stay visibly unset
+        let mut json = json!({"wifi_psk": "", "psk": [], "password": null});
+        redact_secrets(&mut json);
+        assert_eq!(json, json!({"wifi_psk": "", "psk": [], "password": null}));
+        Ok(())
+    }
+
+    #[test]
+    fn test_secret_field_names() -> Result<()> {
+        assert!(is_secret_field("network.wifi_psk"));
+        // Raw protobuf sections use camelCase names
+        assert!(is_secret_field("wifiPsk"));
+        assert!(is_secret_field("security.privateKey"));
+        assert!(is_secret_field("mqtt.password"));
+        assert!(!is_secret_field("network.wifi_ssid"));
+        assert!(!is_secret_field("has_psk"));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod waypoint_tests {
+    use crate::waypoint::{derive_waypoint_id, parse_geojson, waypoint_packet};
+    use anyhow::{Context, Result};
+    use meshtastic::{Message, protobufs};
+
+    const EVENT_MAP: &str = r#"{
+        "type": "FeatureCollection",
+        "features": [
+            {
+                "type": "Feature",
+                "geometry": {"type": "Point", "coordinates": [13.4050, 52.5200]},
+                "properties": {
+                    "name": "First aid",
+                    "description": "Next to the main stage",
+                    "icon": "⛑️",
+                    "expire": "2999-01-01T00:00:00Z"
+                }
+            },
+            {
+                "type": "Feature",
+                "id": 42,
+                "geometry": {"type": "Point", "coordinates": [13.41, 52.52, 35.0]},
+                "properties": {"name": "Parking"}
+            }
+        ]
+    }"#;
+
+    #[test]
+    fn test_parse_feature_collection() -> Result<()> {
+        let waypoints = parse_geojson(EVENT_MAP)?;
+        assert_eq!(waypoints.len(), 2);
+
+        let first_aid = waypoints.first().context("Missing first waypoint")?;
+        assert_eq!(first_aid.name, "First aid");
+        assert_eq!(first_aid.description, "Next to the main stage");
+        assert_eq!(first_aid.icon, Some('⛑'));
+        assert!((first_aid.latitude - 52.52).abs() < 1e-9);
+        assert!((first_aid.longitude - 13.405).abs() < 1e-9);
+        assert_eq!(first_aid.expire, Some(32_472_144_000));
+        assert_eq!(first_aid.id, derive_waypoint_id("First aid", 52.52, 13.405));
+
+        let parking = waypoints.get(1).context("Missing second waypoint")?;
+        assert_eq!(parking.id, 42);
+        assert_eq!(parking.icon, None);
+        assert_eq!(parking.expire, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_rejects_invalid_features() -> Result<()> {
+        let line = r#"{"type": "Feature", "geometry": {"type": "LineString", "coordinates": [[0, 0], [1, 1]]}, "properties": {"name": "Route"}}"#;
+        assert!(parse_geojson(line).is_err());
+
+        let unnamed = r#"{"type": "Feature", "geometry": {"type": "Point", "coordinates": [0, 0]}, "properties": {}}"#;
+        assert!(parse_geojson(unnamed).is_err());
+
+        let expired = r#"{"type": "Feature", "geometry": {"type": "Point", "coordinates": [0, 0]}, "properties": {"name": "Old", "expire": 1000}}"#;
+        assert!(parse_geojson(expired).is_err());
+
+        let swapped = r#"{"type": "Feature", "geometry": {"type": "Point", "coordinates": [0, 120]}, "properties": {"name": "Swapped"}}"#;
+        assert!(parse_geojson(swapped).is_err());
+
+        let long_name = format!(
+            r#"{{"type": "Feature", "geometry": {{"type": "Point", "coordinates": [0, 0]}}, "properties": {{"name": "{name}"}}}}"#,
+            name = "x".repeat(31)
+        );
+        assert!(parse_geojson(&long_name).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_waypoint_ids_are_stable() -> Result<()> {
+        let id = derive_waypoint_id("Stage", 52.52, 13.405);
+        assert_eq!(id, derive_waypoint_id("Stage", 52.52, 13.405));
+        assert_ne!(id, derive_waypoint_id("Stage", 52.53, 13.405));
+        assert_ne!(id, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_waypoint_packet() -> Result<()> {
+        let waypoints = parse_geojson(EVENT_MAP)?;
+        let waypoint = waypoints.first().context("Missing waypoint")?;
+        let packet = waypoint_packet(waypoint, 7, 2);
+
+        assert_eq!(packet.to, 0xffff_ffff);
+        assert_eq!(packet.id, 7);
+        assert_eq!(packet.channel, 2);
+
+        let Some(protobufs::mesh_packet::PayloadVariant::Decoded(data)) = packet.payload_variant
+        else {
+            anyhow::bail!("Expected a decoded payload");
+        };
+        assert_eq!(data.portnum, protobufs::PortNum::WaypointApp as i32);
+
+        let decoded = protobufs::Waypoint::decode(data.payload.as_slice())?;
+        assert_eq!(decoded.id, waypoint.id);
+        assert_eq!(decoded.latitude_i, Some(525_200_000));
+        assert_eq!(decoded.longitude_i, Some(134_050_000));
+        assert_eq!(decoded.icon, u32::from('⛑'));
+        assert_eq!(decoded.name, "First aid");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod presence_tests {
+    use crate::presence::{NodePresence, PresencePolicy};
+    use anyhow::Result;
+
+    #[test]
+    fn test_presence_thresholds() -> Result<()> {
+        let policy = PresencePolicy::default();
+        let now = 100_000;
+
+        assert_eq!(
+            policy.classify_at(Some(now - 60), now),
+            NodePresence::Online
+        );
+        assert_eq!(
+            policy.classify_at(Some(now - 30 * 60), now),
+            NodePresence::Recently
+        );
+        assert_eq!(
+            policy.classify_at(Some(now - 2 * 3600), now),
+            NodePresence::Offline
+        );
+        assert_eq!(policy.classify_at(None, now), NodePresence::Offline);
+        // Clock skew puts some reports in the future
+        assert_eq!(
+            policy.classify_at(Some(now + 60), now),
+            NodePresence::Online
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_custom_presence_policy() -> Result<()> {
+        let policy = PresencePolicy {
+            online_secs: 60,
+            offline_secs: 300,
+        };
+        assert_eq!(policy.classify_at(Some(900), 1000), NodePresence::Recently);
+        assert_eq!(policy.classify_at(Some(600), 1000), NodePresence::Offline);
+        assert_eq!(NodePresence::Recently.to_string(), "recently");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod discovery_tests {
+    use crate::connection::discovery::{
+        Likelihood, PortTarget, classify_port, identify_usb, parse_port,
+    };
+    use anyhow::Result;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_identify_usb_ids() -> Result<()> {
+        // Espressif matches any product, bridges only their exact product
+        assert_eq!(
+            identify_usb(0x303a, 0x1001).map(|(_, likelihood)| likelihood),
+            Some(Likelihood::Known)
+        );
+        assert_eq!(
+            identify_usb(0x10c4, 0xea60).map(|(_, likelihood)| likelihood),
+            Some(Likelihood::Likely)
+        );
+        assert_eq!(identify_usb(0x10c4, 0x0001), None);
+        assert_eq!(identify_usb(0x046d, 0xc52b), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_classify_port_ranking() -> Result<()> {
+        assert_eq!(
+            classify_port("/dev/ttyACM0", Some((0x239a, 0x8029)), None),
+            Likelihood::Known
+        );
+        assert_eq!(
+            classify_port(
+                "/dev/ttyUSB0",
+                None,
+                Some("usb-Heltec_WiFi_LoRa_32-if00-port0")
+            ),
+            Likelihood::Known
+        );
+        assert_eq!(
+            classify_port(
+                "/dev/ttyUSB1",
+                None,
+                Some("usb-Silicon_Labs_CP2102_USB_to_UART-if00-port0")
+            ),
+            Likelihood::Likely
+        );
+        assert_eq!(
+            classify_port("/dev/ttyUSB2", Some((0x046d, 0xc52b)), None),
+            Likelihood::Possible
+        );
+        assert_eq!(
+            classify_port("/dev/cu.usbmodem101", None, None),
+            Likelihood::Possible
+        );
+        assert_eq!(
+            classify_port("/dev/ttyS0", None, None),
+            Likelihood::Unlikely
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_port_targets() -> Result<()> {
+        assert_eq!(
+            parse_port("/dev/ttyUSB0"),
+            PortTarget::Serial("/dev/ttyUSB0".to_string())
+        );
+        assert_eq!(
+            parse_port("192.168.1.100:4403"),
+            PortTarget::Tcp("192.168.1.100:4403".to_string())
+        );
+        assert_eq!(
+            parse_port("tcp://localhost"),
+            PortTarget::Tcp("localhost:4403".to_string())
+        );
+        assert_eq!(
+            parse_port("localhost"),
+            PortTarget::Tcp("localhost:4403".to_string())
+        );
+        assert_eq!(
+            parse_port("unix:///run/meshtasticd.sock"),
+            PortTarget::Unix(PathBuf::from("/run/meshtasticd.sock"))
+        );
+        assert_eq!(
+            parse_port("unix:///run/meshtasticd.sock").to_string(),
+            "unix:///run/meshtasticd.sock"
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod airtime_tests {
+    use crate::airtime::{AirtimeSample, percent_bar, sparkline};
+    use crate::state::DeviceMetrics;
+    use anyhow::{Context, Result};
+
+    #[test]
+    fn test_sparkline_scaling() -> Result<()> {
+        assert_eq!(
+            sparkline(&[Some(0.0), Some(12.5), Some(25.0), Some(40.0)], 25.0),
+            "▁▅██"
+        );
+        // Missing samples leave gaps instead of reading as zero
+        assert_eq!(sparkline(&[Some(25.0), None, Some(0.0)], 25.0), "█ ▁");
+        assert_eq!(sparkline(&[Some(5.0)], 0.0), "▁");
+        assert_eq!(sparkline(&[], 10.0), "");
+        Ok(())
+    }
+
+    #[test]
+    fn test_percent_bar() -> Result<()> {
+        assert_eq!(percent_bar(50.0, 10), "█████░░░░░");
+        assert_eq!(percent_bar(0.0, 4), "░░░░");
+        assert_eq!(percent_bar(150.0, 4), "████");
+        assert_eq!(percent_bar(-5.0, 4), "░░░░");
+        Ok(())
+    }
+
+    #[test]
+    fn test_sample_from_metrics() -> Result<()> {
+        let mut metrics = DeviceMetrics {
+            battery_level: Some(80),
+            voltage: None,
+            channel_utilization: None,
+            air_util_tx: None,
+            uptime_seconds: None,
+        };
+        assert_eq!(AirtimeSample::from_metrics(100, &metrics), None);
+
+        metrics.channel_utilization = Some(31.0);
+        let sample =
+            AirtimeSample::from_metrics(100, &metrics).context("Expected an airtime sample")?;
+        assert_eq!(sample.air_util_tx, None);
+        assert!(sample.is_congested());
+
+        metrics.channel_utilization = Some(8.0);
+        metrics.air_util_tx = Some(2.5);
+        let sample =
+            AirtimeSample::from_metrics(100, &metrics).context("Expected an airtime sample")?;
+        assert!(!sample.is_congested());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod config_tests {
+    use crate::config::{
+        ConfigAssignment, ConfigValueType, NeighborInfoUpdate, TelemetryConfigUpdate,
+        apply_assignment,
+    };
+    use crate::state::{NeighborInfoConfig, TelemetryConfig};
+    use anyhow::Result;
+    use meshtastic::protobufs;
+    use serde_json::json;
+
+    #[test]
+    fn test_neighbor_info_update_keeps_unchanged_settings() -> Result<()> {
+        let current = NeighborInfoConfig {
+            enabled: true,
+            update_interval: 14_400,
+        };
+
+        let update = NeighborInfoUpdate {
+            update_interval: Some(21_600),
+            ..Default::default()
+        };
+        assert_eq!(
+            update.apply(Some(&current)),
+            NeighborInfoConfig {
+                enabled: true,
+                update_interval: 21_600,
+            }
+        );
+
+        let update = NeighborInfoUpdate {
+            enabled: Some(false),
+            ..Default::default()
+        };
+        assert_eq!(
+            update.apply(Some(&current)),
+            NeighborInfoConfig {
+                enabled: false,
+                update_interval: 14_400,
+            }
+        );
+
+        // Without reported settings the firmware default interval is kept
+        let update = NeighborInfoUpdate {
+            enabled: Some(true),
+            ..Default::default()
+        };
+        assert_eq!(
+            update.apply(None),
+            NeighborInfoConfig {
+                enabled: true,
+                update_interval: 0,
+            }
+        );
+        assert!(NeighborInfoUpdate::default().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_telemetry_update_keeps_unchanged_settings() -> Result<()> {
+        let current = TelemetryConfig {
+            device_update_interval: 1800,
+            environment_update_interval: 900,
+            environment_measurement_enabled: true,
+            power_measurement_enabled: true,
+            ..Default::default()
+        };
+
+        let update = TelemetryConfigUpdate {
+            device_update_interval: Some(3600),
+            air_quality_enabled: Some(true),
+            ..Default::default()
+        };
+        assert_eq!(
+            update.apply(Some(&current)),
+            TelemetryConfig {
+                device_update_interval: 3600,
+                air_quality_enabled: true,
+                ..current.clone()
+            }
+        );
+
+        let update = TelemetryConfigUpdate {
+            environment_measurement_enabled: Some(false),
+            ..Default::default()
+        };
+        assert_eq!(
+            update.apply(None),
+            TelemetryConfig::default(),
+            "Without reported settings the firmware defaults are kept"
+        );
+        assert!(TelemetryConfigUpdate::default().is_empty());
+        assert!(!update.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_assignment_parsing() -> Result<()> {
+        let assignment: ConfigAssignment = "mqtt.root=msh/EU=868".parse()?;
+        assert_eq!(assignment, ConfigAssignment::new("mqtt.root", "msh/EU=868"));
+        assert!("lora.hop_limit".parse::<ConfigAssignment>().is_err());
+        assert!("=5".parse::<ConfigAssignment>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_assignment_types() -> Result<()> {
+        let mut section = json!({
+            "enabled": false,
+            "address": "mqtt.meshtastic.org",
+            "mapReportSettings": {"publishIntervalSecs": 0, "positionPrecision": 0},
+            "frequency_offset": 0.0,
+            "optional_limit": null,
+        });
+        let set = |section: &mut serde_json::Value, pair: &str, value_type| {
+            apply_assignment(section, &pair.parse()?, value_type)
+        };
+
+        set(&mut section, "mqtt.enabled=TRUE", None)?;
+        set(
+            &mut section,
+            "mqtt.map_report_settings.publish_interval_secs=3600",
+            None,
+        )?;
+        set(&mut section, "mqtt.frequency_offset=2", None)?;
+        set(&mut section, "mqtt.optional_limit=7", None)?;
+        set(
+            &mut section,
+            "mqtt.address=12",
+            Some(ConfigValueType::String),
+        )?;
+        assert_eq!(
+            section,
+            json!({
+                "enabled": true,
+                "address": "12",
+                "mapReportSettings": {"publishIntervalSecs": 3600, "positionPrecision": 0},
+                "frequency_offset": 2.0,
+                "optional_limit": 7,
+            })
+        );
+
+        assert!(set(&mut section, "mqtt.enabled=maybe", None).is_err());
+        assert!(set(&mut section, "mqtt.map_report_settings=1", None).is_err());
+        assert!(set(&mut section, "mqtt.missing=1", None).is_err());
+        assert!(set(&mut section, "mqtt=1", None).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_apply_assignment_enums() -> Result<()> {
+        let lora = protobufs::config::LoRaConfig {
+            hop_limit: 3,
+            ..Default::default()
+        };
+        let mut section = serde_json::to_value(&lora)?;
+        apply_assignment(&mut section, &"lora.region=EU_868".parse()?, None)?;
+        apply_assignment(&mut section, &"lora.modem_preset=long_fast".parse()?, None)?;
+        apply_assignment(&mut section, &"lora.hop_limit=5".parse()?, None)?;
+        let lora: protobufs::config::LoRaConfig = serde_json::from_value(section.clone())?;
+        assert_eq!(
+            lora.region(),
+            protobufs::config::lo_ra_config::RegionCode::Eu868
+        );
+        assert_eq!(
+            lora.modem_preset(),
+            protobufs::config::lo_ra_config::ModemPreset::LongFast
+        );
+        assert_eq!(lora.hop_limit, 5);
+
+        // Enum names only go to enum fields
+        assert!(apply_assignment(&mut section, &"lora.region=MOON".parse()?, None).is_err());
+        assert!(
+            apply_assignment(
+                &mut section,
+                &"lora.hop_limit=LONG_FAST".parse()?,
+                Some(ConfigValueType::Enum)
+            )
+            .is_err()
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod firmware_tests {
+    use crate::firmware::{
+        BUNDLED_PROTOBUF_VERSION, VersionIssue, app_version_from_code, check_versions,
+        compare_versions, require_firmware, role_min_firmware,
+    };
+    use crate::state::{DeviceMetadata, DeviceState, MyNodeInfo};
+    use anyhow::{Context, Result};
+    use std::cmp::Ordering;
+
+    fn state_with_firmware(firmware_version: &str, min_app_version: u32) -> DeviceState {
+        let mut state = DeviceState::new();
+        state.metadata = Some(DeviceMetadata {
+            firmware_version: firmware_version.to_string(),
+            device_state_version: 23,
+            hw_model: "Tbeam".to_string(),
+            role: "Client".to_string(),
+            has_wifi: false,
+            has_bluetooth: true,
+            has_ethernet: false,
+        });
+        state.my_node_info = Some(MyNodeInfo {
+            node_num: 0x12345678,
+            node_id: "12345678".to_string(),
+            reboot_count: 1,
+            min_app_version,
+            device_id: String::new(),
+            pio_env: None,
+        });
+        state
+    }
+
+    #[test]
+    fn test_compare_versions() -> Result<()> {
+        assert_eq!(compare_versions("2.5.6.abc1234", "2.5.6"), Ordering::Equal);
+        assert_eq!(compare_versions("2.5", "2.5.0"), Ordering::Equal);
+        assert_eq!(compare_versions("2.10.0", "2.9.9"), Ordering::Greater);
+        assert_eq!(compare_versions("v2.3.1", "2.4"), Ordering::Less);
+        Ok(())
+    }
+
+    #[test]
+    fn test_app_version_code() -> Result<()> {
+        assert_eq!(app_version_from_code(30200), "2.2.0");
+        assert_eq!(app_version_from_code(30520), "2.5.20");
+        Ok(())
+    }
+
+    #[test]
+    fn test_matching_firmware_has_no_issues() -> Result<()> {
+        assert!(check_versions(&state_with_firmware("2.6.11.60ec05e", 30200)).is_empty());
+        // Nothing is known before the handshake
+        assert!(check_versions(&DeviceState::new()).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_version_mismatches_are_reported() -> Result<()> {
+        let issues = check_versions(&state_with_firmware("1.3.48", 0));
+        assert!(matches!(
+            issues.as_slice(),
+            [VersionIssue::FirmwareTooOld { .. }]
+        ));
+
+        let issues = check_versions(&state_with_firmware("9.1.0", 100_000));
+        assert!(
+            issues
+                .iter()
+                .any(|issue| matches!(issue, VersionIssue::FirmwareNewer { .. }))
+        );
+        assert!(issues.iter().any(|issue| matches!(
+            issue,
+            VersionIssue::ClientTooOld { bundled, .. } if bundled == BUNDLED_PROTOBUF_VERSION
+        )));
+        Ok(())
+    }
+
+    #[test]
+    fn test_feature_requirements() -> Result<()> {
+        let state = state_with_firmware("2.2.24", 30200);
+        assert_eq!(role_min_firmware("CLIENT"), None);
+
+        let minimum = role_min_firmware("CLIENT_HIDDEN").context("Expected a minimum firmware")?;
+        let issue = require_firmware(&state, "The CLIENT_HIDDEN role", minimum);
+        assert!(issue.is_some_and(|issue| issue.to_string().contains("needs firmware 2.3.0")));
+        assert_eq!(require_firmware(&state, "TAK", "2.2.0"), None);
+        assert_eq!(require_firmware(&DeviceState::new(), "TAK", "2.2.0"), None);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod debug_tests {
+    use crate::debug::{decode_to_radio, parse_hex};
+    use anyhow::Result;
+    use meshtastic::{Message, protobufs};
+
+    fn want_config(id: u32) -> protobufs::ToRadio {
+        protobufs::ToRadio {
+            payload_variant: Some(protobufs::to_radio::PayloadVariant::WantConfigId(id)),
+        }
+    }
+
+    #[test]
+    fn test_parse_hex_formats() -> Result<()> {
+        let expected = vec![0x18, 0x2a, 0xff];
+        assert_eq!(parse_hex("182aff")?, expected);
+        assert_eq!(parse_hex("0x182AFF")?, expected);
+        assert_eq!(parse_hex(" 18 2a ff\n")?, expected);
+        assert_eq!(parse_hex("18:2a:ff")?, expected);
+
+        assert!(parse_hex("").is_err());
+        assert!(parse_hex("18 2").is_err());
+        assert!(parse_hex("zz").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_to_radio() -> Result<()> {
+        let bytes = want_config(42).encode_to_vec();
+        assert_eq!(decode_to_radio(&bytes)?, want_config(42));
+
+        // The same message as captured from the serial stream
+        let mut framed = vec![0x94, 0xc3, 0x00, bytes.len() as u8];
+        framed.extend(&bytes);
+        assert_eq!(decode_to_radio(&framed)?, want_config(42));
+
+        framed.push(0x00);
+        assert!(decode_to_radio(&framed).is_err());
+        assert!(decode_to_radio(&[0x94, 0xc3]).is_err());
+        assert!(decode_to_radio(&[]).is_err());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod doctor_tests {
+    use crate::doctor::{CheckStatus, DoctorReport, group_name, parse_process_ids};
+    use anyhow::{Context, Result};
+
+    #[test]
+    fn test_parse_process_ids() -> Result<()> {
+        let status = "Name:\trmesh\nUid:\t1000\t1001\t1000\t1000\n\
+                      Gid:\t1000\t1000\t1000\t1000\nGroups:\t4 20 27\n";
+        let ids = parse_process_ids(status).context("Status should parse")?;
+
+        // The effective uid, with supplementary groups before the primary one
+        assert_eq!(ids.uid, 1001);
+        assert_eq!(ids.gids, vec![4, 20, 27, 1000]);
+
+        assert!(parse_process_ids("Name:\trmesh\n").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_group_name() -> Result<()> {
+        let groups = "root:x:0:\ndialout:x:20:alice,bob\nuucp:x:14:\n";
+        assert_eq!(group_name(groups, 20).as_deref(), Some("dialout"));
+        assert_eq!(group_name(groups, 14).as_deref(), Some("uucp"));
+        assert_eq!(group_name(groups, 99), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_report_status_is_worst_check() -> Result<()> {
+        let report = DoctorReport {
+            target: None,
+            checks: Vec::new(),
+        };
+        assert_eq!(report.status(), CheckStatus::Ok);
+        assert!(CheckStatus::Failed > CheckStatus::Warning);
+        assert!(CheckStatus::Warning > CheckStatus::Skipped);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod node_id_tests {
+    use crate::node_id::{NodeId, NodeIdError, parse_node_id};
+    use anyhow::Result;
+
+    #[test]
+    fn test_node_id_display_and_parse() -> Result<()> {
+        assert_eq!(NodeId(0x67ea9400).to_string(), "!67ea9400");
+        assert_eq!(NodeId(1).to_string(), "!00000001");
+        assert_eq!(NodeId::BROADCAST.to_string(), "!ffffffff");
+        assert_eq!("!67ea9400".parse::<NodeId>()?, NodeId(0x67ea9400));
+
+        let json = serde_json::to_string(&NodeId(0x67ea9400))?;
+        assert_eq!(json, "\"!67ea9400\"");
+        assert_eq!(serde_json::from_str::<NodeId>(&json)?, NodeId(0x67ea9400));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_node_id_formats() -> Result<()> {
+        assert_eq!(parse_node_id("!67ea9400")?, 0x67ea9400);
+        assert_eq!(parse_node_id("0x67EA9400")?, 0x67ea9400);
+        assert_eq!(parse_node_id("1743426560")?, 0x67ea9400);
+        assert_eq!(parse_node_id(" !1 ")?, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_node_id_errors() -> Result<()> {
+        assert_eq!(parse_node_id(""), Err(NodeIdError::Empty));
+        assert_eq!(
+            parse_node_id("67ea9400"),
+            Err(NodeIdError::MissingPrefix {
+                input: "67ea9400".to_string()
+            })
+        );
+        assert!(matches!(
+            parse_node_id("!67ea94001"),
+            Err(NodeIdError::Invalid { .. })
+        ));
+        assert!(matches!(
+            parse_node_id("node-7"),
+            Err(NodeIdError::Invalid { .. })
+        ));
+        // Too large for a node number
+        assert!(matches!(
+            parse_node_id("4294967296"),
+            Err(NodeIdError::Invalid { .. })
+        ));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod mqtt_proxy_tests {
+    use crate::mqtt_proxy::{BrokerSettings, parse_address, uplink};
+    use crate::state::MqttConfig;
+    use anyhow::{Context, Result};
+    use meshtastic::protobufs;
+
+    #[test]
+    fn test_parse_address() -> Result<()> {
+        assert_eq!(
+            parse_address("broker.local", 1883)?,
+            ("broker.local".to_string(), 1883)
+        );
+        assert_eq!(
+            parse_address("broker.local:1884", 1883)?,
+            ("broker.local".to_string(), 1884)
+        );
+        assert_eq!(
+            parse_address("10.0.0.5:8883", 1883)?,
+            ("10.0.0.5".to_string(), 8883)
+        );
+
+        // IPv6 takes a port only in brackets
+        assert_eq!(
+            parse_address("[::1]:1884", 1883)?,
+            ("::1".to_string(), 1884)
+        );
+        assert_eq!(
+            parse_address("[fe80::1]", 8883)?,
+            ("fe80::1".to_string(), 8883)
+        );
+        assert_eq!(
+            parse_address("fe80::1", 1883)?,
+            ("fe80::1".to_string(), 1883)
+        );
+
+        assert!(parse_address(":1883", 1883).is_err());
+        assert!(parse_address("broker.local:mqtt", 1883).is_err());
+        assert!(parse_address("[::1", 1883).is_err());
+        assert!(parse_address("[::1]1883", 1883).is_err());
+        assert!(parse_address("[]:1883", 1883).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_broker_settings_defaults() -> Result<()> {
+        let settings = BrokerSettings::from_config(&MqttConfig::default())?;
+        assert_eq!(settings.host, "mqtt.meshtastic.org");
+        assert_eq!(settings.port, 1883);
+        assert_eq!(settings.username.as_deref(), Some("meshdev"));
+        assert_eq!(settings.subscription, "msh/2/e/#");
+
+        let settings = BrokerSettings::from_config(&MqttConfig {
+            address: "broker.local:1884".to_string(),
+            root: "msh/EU_868/".to_string(),
+            tls_enabled: true,
+            ..Default::default()
+        })?;
+        assert_eq!(
+            (settings.host.as_str(), settings.port),
+            ("broker.local", 1884)
+        );
+        // Public broker credentials are not sent to other brokers
+        assert_eq!(settings.username, None);
+        assert!(settings.tls);
+        assert_eq!(settings.subscription, "msh/EU_868/2/e/#");
+
+        let settings = BrokerSettings::from_config(&MqttConfig {
+            address: "broker.local".to_string(),
+            tls_enabled: true,
+            ..Default::default()
+        })?;
+        assert_eq!(settings.port, 8883);
+        Ok(())
+    }
+
+    #[test]
+    fn test_uplink() -> Result<()> {
+        let frame = protobufs::FromRadio {
+            payload_variant: Some(
+                protobufs::from_radio::PayloadVariant::MqttClientProxyMessage(
+                    protobufs::MqttClientProxyMessage {
+                        topic: "msh/2/e/LongFast/!1".to_string(),
+                        payload_variant: Some(
+                            protobufs::mqtt_client_proxy_message::PayloadVariant::Data(vec![1, 2]),
+                        ),
+                        retained: false,
+                    },
+                ),
+            ),
+            ..Default::default()
+        };
+        let message = uplink(&frame).context("Proxy message should be forwarded")?;
+        assert_eq!(message.payload, vec![1, 2]);
+
+        assert!(uplink(&protobufs::FromRadio::default()).is_none());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod message_tests {
+    use crate::message::{
+        Encryption, MessageFilter, ReceivedMessage, decode_text_payload, sanitize_for_terminal,
+    };
+    use crate::state::ChannelInfo;
+    use anyhow::Result;
+    use meshtastic::protobufs::{ChannelSettings, MeshPacket};
+    use std::borrow::Cow;
+
+    fn packet(from: u32, to: u32, channel: u32) -> MeshPacket {
+        MeshPacket {
+            from,
+            to,
+            channel,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_message_filter_default_matches_everything() -> Result<()> {
+        let filter = MessageFilter::default();
+        assert!(filter.matches(&packet(1, 2, 0)));
+        assert!(filter.matches(&packet(3, 0xffffffff, 7)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_filter_criteria() -> Result<()> {
+        let filter = MessageFilter {
+            from: Some(1),
+            to: None,
+            channel: Some(2),
+            own_node: None,
+        };
+        assert!(filter.matches(&packet(1, 5, 2)));
+        assert!(!filter.matches(&packet(1, 5, 0)));
+        assert!(!filter.matches(&packet(9, 5, 2)));
+
+        let filter = MessageFilter {
+            to: Some(5),
+            ..Default::default()
+        };
+        assert!(filter.matches(&packet(1, 5, 0)));
+        assert!(!filter.matches(&packet(1, 6, 0)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_keyword_match() -> Result<()> {
+        let message = ReceivedMessage {
+            from: "11111111".to_string(),
+            from_node: 0x11111111,
+            to: "!ffffffff".to_string(),
+            to_node: 0xffffffff,
+            channel: 0,
+            channel_name: None,
+            encryption: Encryption::Unknown,
+            via_mqtt: false,
+            own: false,
+            text: "Need HELP at the trailhead".to_string(),
+            time: 1_700_000_000,
+            snr: None,
+            rssi: None,
+        };
+        assert!(message.contains_keyword(&["help".to_string()]));
+        assert!(message.contains_keyword(&["sos".to_string(), "Trailhead".to_string()]));
+        assert!(!message.contains_keyword(&["sos".to_string()]));
+        assert!(!message.contains_keyword(&[]));
+        assert!(!message.contains_keyword(&[String::new()]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_sanitize_escapes_malicious_payloads() -> Result<()> {
+        // Clear screen, then set the window title
+        let payload = "\x1b[2J\x1b]0;pwned\x07hello";
+        let sanitized = sanitize_for_terminal(payload);
+        assert!(!sanitized.contains('\x1b'));
+        assert!(!sanitized.contains('\x07'));
+        assert_eq!(sanitized, "\\u{1b}[2J\\u{1b}]0;pwned\\u{7}hello");
+
+        // Carriage return hiding the start of the line
+        assert_eq!(sanitize_for_terminal("safe\rEVIL"), "safe\\rEVIL");
+
+        // C1 control introducing a CSI sequence
+        assert!(!sanitize_for_terminal("\u{9b}31m").contains('\u{9b}'));
+
+        // Right-to-left override reordering the text
+        assert_eq!(
+            sanitize_for_terminal("file\u{202e}txt.exe"),
+            "file\\u{202e}txt.exe"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_sanitize_keeps_plain_text() -> Result<()> {
+        let text = "Hello, mesh! Ünïcödé 📡 ok";
+        assert!(matches!(sanitize_for_terminal(text), Cow::Borrowed(_)));
+        assert_eq!(sanitize_for_terminal(text), text);
+        Ok(())
+    }
+
+    #[test]
+    fn test_encryption_of_packet() -> Result<()> {
+        let channel = |psk: Vec<u8>| ChannelInfo {
+            index: 1,
+            name: "Team".to_string(),
+            role: "Secondary".to_string(),
+            has_psk: !psk.is_empty(),
+            settings: Some(ChannelSettings {
+                psk,
+                ..Default::default()
+            }),
+        };
+
+        assert_eq!(
+            Encryption::of_packet(false, Some(&channel(vec![7; 32]))),
+            Encryption::Psk
+        );
+        assert_eq!(
+            Encryption::of_packet(false, Some(&channel(vec![1]))),
+            Encryption::Psk
+        );
+        assert_eq!(
+            Encryption::of_packet(false, Some(&channel(vec![0]))),
+            Encryption::None
+        );
+        assert_eq!(
+            Encryption::of_packet(true, Some(&channel(vec![1]))),
+            Encryption::Pki
+        );
+        assert_eq!(Encryption::of_packet(false, None), Encryption::Unknown);
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_text_payload_replaces_invalid_utf8() -> Result<()> {
+        assert_eq!(decode_text_payload(b"hi there"), "hi there");
+        assert_eq!(
+            decode_text_payload(&[b'o', b'k', 0xff, 0xfe]),
+            "ok\u{fffd}\u{fffd}"
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod responder_tests {
+    use crate::message::{Encryption, ReceivedMessage};
+    use crate::responder::{Reply, Responder};
+    use anyhow::{Context, Result};
+    use std::time::{Duration, Instant};
+
+    const MY_NODE: u32 = 0xaaaaaaaa;
+
+    fn message(from_node: u32, to_node: u32, text: &str) -> ReceivedMessage {
+        ReceivedMessage {
+            from: format!("!{from_node:08x}"),
+            from_node,
+            to: format!("!{to_node:08x}"),
+            to_node,
+            channel: 1,
+            channel_name: Some("Team".to_string()),
+            encryption: Encryption::Psk,
+            via_mqtt: false,
+            own: false,
+            text: text.to_string(),
+            time: 1_700_000_000,
+            snr: Some(4.5),
+            rssi: Some(-80),
+        }
+    }
+
+    #[test]
+    fn test_responder_replies_to_match() -> Result<()> {
+        let mut responder =
+            Responder::new("ping", "pong from {node} ({snr} dB)", false, Duration::ZERO);
+
+        let reply = responder
+            .respond(&message(0x11111111, MY_NODE, "Ping?"), MY_NODE)
+            .context("Expected reply to DM")?;
+        assert_eq!(
+            reply,
+            Reply {
+                text: "pong from !11111111 (4.5 dB)".to_string(),
+                destination: Some(0x11111111),
+                channel: 1,
+            }
+        );
+
+        // Channel messages are answered on the channel
+        let reply = responder
+            .respond(&message(0x11111111, 0xffffffff, "ping"), MY_NODE)
+            .context("Expected reply to broadcast")?;
+        assert_eq!(reply.destination, None);
+
+        assert!(
+            responder
+                .respond(&message(0x11111111, MY_NODE, "hello"), MY_NODE)
+                .is_none()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_responder_dm_only_and_self() -> Result<()> {
+        let mut responder = Responder::new("ping", "pong", true, Duration::ZERO);
+        assert!(
+            responder
+                .respond(&message(0x11111111, 0xffffffff, "ping"), MY_NODE)
+                .is_none()
+        );
+        assert!(
+            responder
+                .respond(&message(MY_NODE, MY_NODE, "ping"), MY_NODE)
+                .is_none()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_responder_rate_limit_per_sender() -> Result<()> {
+        let mut responder = Responder::new("ping", "pong", false, Duration::from_secs(60));
+        let start = Instant::now();
+        let ping = message(0x11111111, MY_NODE, "ping");
+
+        assert!(responder.respond_at(&ping, MY_NODE, start).is_some());
+        assert!(
+            responder
+                .respond_at(&ping, MY_NODE, start + Duration::from_secs(30))
+                .is_none()
+        );
+        // Other senders have their own budget
+        assert!(
+            responder
+                .respond_at(&message(0x22222222, MY_NODE, "ping"), MY_NODE, start)
+                .is_some()
+        );
+        assert!(
+            responder
+                .respond_at(&ping, MY_NODE, start + Duration::from_secs(61))
+                .is_some()
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod progress_tests {
+    use crate::progress::{ProgressEvent, ProgressReporter};
+    use anyhow::Result;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_progress_reporter_events() -> Result<()> {
+        let events = Mutex::new(Vec::new());
+        let callback = |event: ProgressEvent| {
+            if let Ok(mut events) = events.lock() {
+                events.push(event);
+            }
+        };
+
+        let mut reporter = ProgressReporter::start(Some(&callback), "sweep", Some(2));
+        reporter.advance(1, None);
+        // Repeated updates without a message are not reported
+        reporter.set_completed(1, None);
+        reporter.advance(5, Some("done".to_string()));
+        reporter.finish();
+
+        let events = events
+            .into_inner()
+            .map_err(|_| anyhow::anyhow!("lock poisoned"))?;
+        assert_eq!(
+            events,
+            [
+                ProgressEvent::Started {
+                    operation: "sweep",
+                    total: Some(2),
+                },
+                ProgressEvent::Advanced {
+                    operation: "sweep",
+                    completed: 1,
+                    total: Some(2),
+                    message: None,
+                },
+                // Completion is clamped to the total
+                ProgressEvent::Advanced {
+                    operation: "sweep",
+                    completed: 2,
+                    total: Some(2),
+                    message: Some("done".to_string()),
+                },
+                ProgressEvent::Finished {
+                    operation: "sweep",
+                    completed: 2,
+                },
+            ]
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod geofence_tests {
+    use crate::geofence::{
+        Geofence, GeofenceMonitor, GeofenceTransition, haversine_distance_m, parse_coordinates,
+        parse_distance_m,
+    };
+    use crate::state::Position;
+    use anyhow::{Context, Result};
+
+    fn position(node_num: u32, latitude: f64, longitude: f64) -> Position {
+        Position {
+            node_id: format!("!{node_num:08x}"),
+            node_num,
+            latitude,
+            longitude,
+            altitude: None,
+            time: None,
+            last_updated: 0,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_haversine_distance() -> Result<()> {
+        // One degree of latitude is roughly 111 km
+        let distance = haversine_distance_m(0.0, 0.0, 1.0, 0.0);
+        assert!((distance - 111_195.0).abs() < 100.0);
+        assert!(haversine_distance_m(52.5, 13.4, 52.5, 13.4) < f64::EPSILON);
+        Ok(())
+    }
+
+    #[test]
+    fn test_geofence_transitions() -> Result<()> {
+        let fence = Geofence::new(52.5200, 13.4050, 500.0)?;
+        let mut monitor = GeofenceMonitor::new(fence, Vec::new());
+
+        // First seen outside: recorded, no event
+        assert!(monitor.update(&position(1, 52.5300, 13.4050)).is_none());
+
+        let event = monitor
+            .update(&position(1, 52.5201, 13.4050))
+            .context("Expected enter event")?;
+        assert_eq!(event.transition, GeofenceTransition::Enter);
+
+        // Moving within the fence does not repeat the event
+        assert!(monitor.update(&position(1, 52.5202, 13.4051)).is_none());
+
+        let event = monitor
+            .update(&position(1, 52.5300, 13.4050))
+            .context("Expected exit event")?;
+        assert_eq!(event.transition, GeofenceTransition::Exit);
+        assert!(event.distance_m > 500.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_geofence_node_filter() -> Result<()> {
+        let fence = Geofence::new(0.0, 0.0, 1000.0)?;
+        let mut monitor = GeofenceMonitor::new(fence, vec![2]);
+        assert!(monitor.update(&position(1, 0.0, 0.0)).is_none());
+        assert!(monitor.update(&position(2, 0.0, 0.0)).is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn test_geofence_parsing() -> Result<()> {
+        assert_eq!(parse_coordinates("52.52, -13.40")?, (52.52, -13.40));
+        assert!(parse_coordinates("52.52").is_err());
+        assert_eq!(parse_distance_m("500m")?, 500.0);
+        assert_eq!(parse_distance_m("1.5km")?, 1500.0);
+        assert_eq!(parse_distance_m("250")?, 250.0);
+        assert!(parse_distance_m("-5m").is_err());
+        assert!(Geofence::new(91.0, 0.0, 10.0).is_err());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod fixed_position_tests {
+    use crate::position::{FixedPositionReport, position_tolerance_m};
+    use crate::state::Position;
+    use anyhow::Result;
+
+    fn reported(latitude: f64, longitude: f64, precision_bits: Option<u32>) -> Position {
+        Position {
+            node_id: "!1234abcd".to_string(),
+            node_num: 0x1234_abcd,
+            latitude,
+            longitude,
+            precision_bits,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_position_tolerance() -> Result<()> {
+        assert_eq!(position_tolerance_m(None), 10.0);
+        assert_eq!(position_tolerance_m(Some(32)), 10.0);
+        // 13 bits, the default for shared channels, is a ~6 km grid
+        let coarse = position_tolerance_m(Some(13));
+        assert!((5_000.0..7_000.0).contains(&coarse), "{coarse}");
+        assert_eq!(position_tolerance_m(Some(0)), f64::INFINITY);
+        Ok(())
+    }
+
+    #[test]
+    fn test_fixed_position_report() -> Result<()> {
+        let matching = FixedPositionReport::new(
+            0x1234_abcd,
+            52.52,
+            13.405,
+            None,
+            Some(reported(52.52001, 13.405, None)),
+        );
+        assert!(matching.verified);
+        assert_eq!(matching.node, "!1234abcd");
+
+        let elsewhere = reported(48.8566, 2.3522, None);
+        let moved = FixedPositionReport::new(0x1234_abcd, 52.52, 13.405, None, Some(elsewhere));
+        assert!(!moved.verified);
+        assert!(moved.offset_m.is_some_and(|offset| offset > 800_000.0));
+
+        let silent = FixedPositionReport::new(0x1234_abcd, 52.52, 13.405, None, None);
+        assert!(!silent.verified);
+        assert_eq!(silent.offset_m, None);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod map_tests {
+    use crate::map::{MapNode, render_ascii_map};
+    use anyhow::{Context, Result};
+
+    fn node(node_num: u32, latitude: f64, longitude: f64) -> MapNode {
+        MapNode {
+            node_num,
+            label: format!("node-{node_num}"),
+            latitude,
+            longitude,
+        }
+    }
+
+    fn find(rows: &[String], symbol: char) -> Option<(usize, usize)> {
+        rows.iter().enumerate().find_map(|(row, line)| {
+            line.chars()
+                .position(|c| c == symbol)
+                .map(|column| (row, column))
+        })
+    }
+
+    #[test]
+    fn test_map_places_nodes_by_direction() -> Result<()> {
+        let center = (52.5200, 13.4050);
+        let nodes = [node(1, 52.5300, 13.4050), node(2, 52.5200, 13.4200)];
+        let map = render_ascii_map(center, &nodes, 21, 11);
+
+        // Frame plus inner rows
+        assert_eq!(map.rows.len(), 13);
+        assert!(map.rows.iter().all(|row| row.chars().count() == 23));
+
+        let (center_row, center_column) = find(&map.rows, '@').context("Missing center")?;
+        let (north_row, north_column) = find(&map.rows, '1').context("Missing north node")?;
+        let (east_row, east_column) = find(&map.rows, '2').context("Missing east node")?;
+
+        assert!(north_row < center_row);
+        assert_eq!(north_column, center_column);
+        assert!(east_column > center_column);
+        assert_eq!(east_row, center_row);
+
+        assert_eq!(map.legend.len(), 2);
+        assert_eq!(map.legend[0].symbol, '1');
+        assert!((map.legend[0].distance_m - 1112.0).abs() < 5.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_map_marks_collisions() -> Result<()> {
+        let center = (0.0, 0.0);
+        let nodes = [
+            node(1, 0.01, 0.01),
+            node(2, 0.01, 0.01),
+            node(3, -0.01, -0.01),
+        ];
+        let map = render_ascii_map(center, &nodes, 11, 5);
+
+        assert!(find(&map.rows, '*').is_some());
+        assert!(find(&map.rows, '1').is_none());
+        assert!(find(&map.rows, '3').is_some());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod schema_tests {
+    use crate::schema::{
+        JsonSchema, OUTPUT_SCHEMA_VERSION, SCHEMA_COMMANDS, command_schema, project_fields,
+    };
+    use crate::state::{NodeInfo, User};
+    use anyhow::{Context, Result};
+    use serde_json::{Value, json};
+
+    #[test]
+    fn test_schema_properties_match_serialization() -> Result<()> {
+        let node = NodeInfo {
+            id: "!12345678".to_string(),
+            num: 0x12345678,
+            user: User {
+                id: "!12345678".to_string(),
+                long_name: "Test Node".to_string(),
+                short_name: "TN".to_string(),
+                hw_model: None,
+                role: None,
+                public_key: None,
+                is_licensed: false,
+            },
+            last_heard: None,
+            last_heard_iso: None,
+            snr: Some(5.0),
+            rssi: None,
+            device_metrics: None,
+        };
+
+        let serialized = serde_json::to_value(&node)?;
+        let serialized_keys: Vec<&String> = serialized
+            .as_object()
+            .context("Expected an object")?
+            .keys()
+            .collect();
+
+        let schema = NodeInfo::json_schema();
+        let property_keys: Vec<&String> = schema["properties"]
+            .as_object()
+            .context("Expected properties")?
+            .keys()
+            .collect();
+
+        assert_eq!(serialized_keys, property_keys);
+        assert_eq!(schema["properties"]["user"]["type"], "object");
+        Ok(())
+    }
+
+    #[test]
+    fn test_command_schemas() -> Result<()> {
+        for command in SCHEMA_COMMANDS {
+            let schema =
+                command_schema(command).with_context(|| format!("Missing schema for {command}"))?;
+            assert_eq!(
+                schema["x-rmesh-schema-version"],
+                Value::from(OUTPUT_SCHEMA_VERSION)
+            );
+        }
+
+        let nodes = command_schema("info  nodes").context("Expected info nodes schema")?;
+        assert_eq!(nodes["type"], "array");
+        assert!(command_schema("info unknown").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_project_fields() -> Result<()> {
+        let fields = ["num".to_string(), "user.long_name".to_string()];
+        let nodes = json!([
+            {"num": 1, "snr": 5.0, "user": {"long_name": "Base", "short_name": "B"}},
+            {"num": 2, "user": {}},
+        ]);
+
+        assert_eq!(
+            project_fields(&nodes, &fields),
+            json!([
+                {"num": 1, "user.long_name": "Base"},
+                {"num": 2},
+            ])
+        );
+        assert_eq!(project_fields(&json!("text"), &fields), json!("text"));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod simulation_tests {
+    use crate::connection::framing::encode_frame;
+    use crate::connection::simulation::{
+        SIMULATED_LOCAL_NODE, SimulationOptions, Topology, VirtualMesh,
+    };
+    use anyhow::{Context, Result};
+    use meshtastic::Message;
+    use meshtastic::protobufs::{self, from_radio, mesh_packet};
+    use std::str::FromStr;
+
+    fn options(topology: Topology, loss: f64) -> SimulationOptions {
+        SimulationOptions {
+            nodes: 3,
+            topology,
+            loss,
+            seed: Some(7),
+            ..Default::default()
+        }
+    }
+
+    fn text_packet(to: u32, id: u32) -> protobufs::MeshPacket {
+        protobufs::MeshPacket {
+            to,
+            id,
+            want_ack: true,
+            payload_variant: Some(mesh_packet::PayloadVariant::Decoded(protobufs::Data {
+                portnum: protobufs::PortNum::TextMessageApp as i32,
+                payload: b"hi".to_vec(),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+    }
+
+    /// Routing error code and request id of a scheduled ACK or NAK
+    fn routing_status(frame: &protobufs::FromRadio) -> Result<(i32, u32)> {
+        let Some(from_radio::PayloadVariant::Packet(packet)) = &frame.payload_variant else {
+            anyhow::bail!("Expected a mesh packet");
+        };
+        let Some(mesh_packet::PayloadVariant::Decoded(data)) = &packet.payload_variant else {
+            anyhow::bail!("Expected a decoded packet");
+        };
+        let routing = protobufs::Routing::decode(data.payload.as_slice())?;
+        match routing.variant {
+            Some(protobufs::routing::Variant::ErrorReason(code)) => Ok((code, data.request_id)),
+            _ => anyhow::bail!("Expected a routing status"),
+        }
+    }
+
+    #[test]
+    fn test_topology_relays() -> Result<()> {
+        let nodes = [1, 2, 3];
+        assert!(Topology::Full.relays(2, &nodes).is_empty());
+        assert!(Topology::Star.relays(0, &nodes).is_empty());
+        assert_eq!(Topology::Star.relays(2, &nodes), vec![1]);
+        assert_eq!(Topology::Line.relays(2, &nodes), vec![1, 2]);
+        assert_eq!(Topology::from_str("LINE")?, Topology::Line);
+        Ok(())
+    }
+
+    #[test]
+    fn test_options_validation() -> Result<()> {
+        assert!(SimulationOptions::default().validate().is_ok());
+        assert!(options(Topology::Full, 1.5).validate().is_err());
+        let no_nodes = SimulationOptions {
+            nodes: 0,
+            ..Default::default()
+        };
+        assert!(no_nodes.validate().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_handshake_reports_every_node() -> Result<()> {
+        let mut mesh = VirtualMesh::new(options(Topology::Full, 0.0));
+        let frames = mesh.config_frames(42);
+
+        let node_infos = frames
+            .iter()
+            .filter(|frame| {
+                matches!(
+                    frame.payload_variant,
+                    Some(from_radio::PayloadVariant::NodeInfo(_))
+                )
+            })
+            .count();
+        assert_eq!(node_infos, 4);
+        assert!(matches!(
+            frames.first().and_then(|frame| frame.payload_variant.as_ref()),
+            Some(from_radio::PayloadVariant::MyInfo(info)) if info.my_node_num == SIMULATED_LOCAL_NODE
+        ));
+        assert!(matches!(
+            frames
+                .last()
+                .and_then(|frame| frame.payload_variant.as_ref()),
+            Some(from_radio::PayloadVariant::ConfigCompleteId(42))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_direct_message_is_acked_by_destination() -> Result<()> {
+        let mut mesh = VirtualMesh::new(options(Topology::Line, 0.0));
+        let destination = *mesh.nodes().last().context("Expected fake nodes")?;
+
+        let replies = mesh.handle_packet(text_packet(destination, 100));
+        let ack = replies.first().context("Expected an ACK")?;
+        assert_eq!(
+            routing_status(&ack.frame)?,
+            (protobufs::routing::Error::None as i32, 100)
+        );
+        let nearest = mesh.nodes()[0];
+        let direct = mesh.handle_packet(text_packet(nearest, 101));
+        let direct_ack = direct.first().context("Expected an ACK")?;
+        // Two relays each way on top of the direct round trip
+        assert!(ack.delay > direct_ack.delay);
+        Ok(())
+    }
+
+    #[test]
+    fn test_lost_message_is_naked() -> Result<()> {
+        let mut mesh = VirtualMesh::new(options(Topology::Full, 1.0));
+        let destination = mesh.nodes()[0];
+
+        let replies = mesh.handle_packet(text_packet(destination, 7));
+        let nak = replies.first().context("Expected a NAK")?;
+        assert_eq!(
+            routing_status(&nak.frame)?,
+            (protobufs::routing::Error::MaxRetransmit as i32, 7)
+        );
+        assert!(mesh.tick().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_frame_encoding() -> Result<()> {
+        let frame = encode_frame(&[1, 2, 3]);
+        assert_eq!(frame, vec![0x94, 0xc3, 0x00, 0x03, 1, 2, 3]);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod keep_awake_tests {
+    use crate::connection::keep_awake::{keep_awake_ping, parse_wake_sequence};
+    use anyhow::Result;
+    use meshtastic::Message;
+    use meshtastic::protobufs::{ToRadio, to_radio};
+
+    #[test]
+    fn test_parse_wake_sequence() -> Result<()> {
+        assert_eq!(parse_wake_sequence("c3c3")?, vec![0xc3, 0xc3]);
+        assert_eq!(parse_wake_sequence("0x94 0xC3")?, vec![0x94, 0xc3]);
+        assert_eq!(parse_wake_sequence("c3:c3,c3")?, vec![0xc3; 3]);
+        assert!(parse_wake_sequence("").is_err());
+        assert!(parse_wake_sequence("c").is_err());
+        assert!(parse_wake_sequence("zz").is_err());
+        assert!(parse_wake_sequence(&"c3".repeat(257)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_ping_is_wake_sequence_then_heartbeat() -> Result<()> {
+        let ping = keep_awake_ping(&[0xc3, 0xc3]);
+        assert_eq!(&ping[..2], &[0xc3, 0xc3]);
+        assert_eq!(&ping[2..4], &[0x94, 0xc3]);
+
+        let len = usize::from(u16::from_be_bytes([ping[4], ping[5]]));
+        assert_eq!(ping.len(), 6 + len);
+        let to_radio = ToRadio::decode(&ping[6..])?;
+        assert!(matches!(
+            to_radio.payload_variant,
+            Some(to_radio::PayloadVariant::Heartbeat(_))
+        ));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod lora_tests {
+    use crate::lora::{
+        ModemPreset, channel_name_hash, check_override_frequency, frequency_slot, region_band,
+    };
+    use crate::state::LoraConfig;
+    use anyhow::{Context, Result};
+
+    fn lora(region: &str, preset: &str) -> LoraConfig {
+        LoraConfig {
+            use_preset: true,
+            modem_preset: preset.to_string(),
+            bandwidth: 0,
+            spread_factor: 0,
+            coding_rate: 0,
+            frequency_offset: 0.0,
+            region: region.to_string(),
+            hop_limit: 3,
+            tx_enabled: true,
+            tx_power: 0,
+            channel_num: 0,
+            ignore_mqtt: false,
+            override_frequency: 0.0,
+            override_duty_cycle: false,
+            sx126x_rx_boosted_gain: false,
+        }
+    }
+
+    #[test]
+    fn test_channel_name_hash() -> Result<()> {
+        assert_eq!(channel_name_hash(""), 5381);
+        assert_eq!(channel_name_hash("LongFast"), 130_429_955);
+        Ok(())
+    }
+
+    #[test]
+    fn test_default_channel_frequencies() -> Result<()> {
+        // The well known defaults of an unnamed primary channel
+        let slot = frequency_slot(&lora("US", "LongFast"), "").context("No slot")?;
+        assert_eq!(slot.channel_name, "LongFast");
+        assert_eq!((slot.slot, slot.num_slots), (20, 104));
+        assert!((slot.frequency_mhz - 906.875).abs() < 1e-6);
+
+        let slot = frequency_slot(&lora("US", "MediumFast"), "").context("No slot")?;
+        assert_eq!(slot.slot, 45);
+        assert!((slot.frequency_mhz - 913.125).abs() < 1e-6);
+
+        let slot = frequency_slot(&lora("EU868", "LongFast"), "").context("No slot")?;
+        assert_eq!((slot.slot, slot.num_slots), (1, 1));
+        assert!((slot.frequency_mhz - 869.525).abs() < 1e-6);
+        Ok(())
+    }
+
+    #[test]
+    fn test_pinned_slot_and_offset() -> Result<()> {
+        let mut config = lora("US", "LONG_FAST");
+        config.channel_num = 1;
+        config.frequency_offset = 0.01;
+        let slot = frequency_slot(&config, "Private").context("No slot")?;
+        assert_eq!(slot.slot, 1);
+        assert!((slot.frequency_mhz - 902.135).abs() < 1e-4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_override_frequency() -> Result<()> {
+        check_override_frequency("US", 0.0)?;
+        check_override_frequency("Unset", 0.0)?;
+        check_override_frequency("US", 915.0)?;
+        check_override_frequency("eu868", 869.5)?;
+        assert!(check_override_frequency("US", 869.5).is_err());
+        assert!(check_override_frequency("EU868", 868.0).is_err());
+        assert!(check_override_frequency("Unset", 915.0).is_err());
+        assert!(check_override_frequency("US", -1.0).is_err());
+        assert!(check_override_frequency("US", f32::NAN).is_err());
+
+        let mut config = lora("US", "LongFast");
+        config.override_frequency = 915.0;
+        let slot = frequency_slot(&config, "").context("No slot")?;
+        assert!((slot.frequency_mhz - 915.0).abs() < 1e-6);
+        Ok(())
+    }
+
+    #[test]
+    fn test_unknown_settings_have_no_slot() -> Result<()> {
+        assert!(frequency_slot(&lora("Unset", "LongFast"), "").is_none());
+        assert!(frequency_slot(&lora("US", "Bogus"), "").is_none());
+
+        let mut custom = lora("US", "LongFast");
+        custom.use_preset = false;
+        assert!(frequency_slot(&custom, "").is_none());
+        custom.bandwidth = 125;
+        let slot = frequency_slot(&custom, "").context("No slot")?;
+        assert_eq!((slot.modem.as_str(), slot.num_slots), ("Custom", 208));
+        Ok(())
+    }
+
+    #[test]
+    fn test_modem_presets() -> Result<()> {
+        assert_eq!(
+            ModemPreset::from_name("long-moderate"),
+            Some(ModemPreset::LongModerate)
+        );
+        let params = ModemPreset::ShortTurbo.params(false);
+        assert_eq!((params.bandwidth_khz, params.spread_factor), (500.0, 7));
+        let wide = region_band("lora24").context("No band")?;
+        assert!(wide.wide_lora);
+        assert_eq!(ModemPreset::LongFast.params(true).bandwidth_khz, 812.5);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod link_budget_tests {
+    use crate::link_budget::{
+        LinkSetup, LinkSource, LinkVerdict, airtime_ms, estimate_presets, free_space_path_loss_db,
+        noise_floor_dbm, snr_limit_db,
+    };
+    use crate::lora::ModemPreset;
+    use anyhow::{Context, Result};
+
+    fn setup(frequency_mhz: f64) -> LinkSetup {
+        LinkSetup {
+            tx_power_dbm: 20.0,
+            antenna_gain_dbi: 0.0,
+            extra_loss_db: 0.0,
+            frequency_mhz,
+            payload_bytes: 50,
+            wide_lora: false,
+        }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 0.01,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn test_snr_limits_and_noise() -> Result<()> {
+        assert_close(snr_limit_db(7), -7.5);
+        assert_close(snr_limit_db(12), -20.0);
+        assert_close(noise_floor_dbm(125.0), -117.03);
+        assert_close(free_space_path_loss_db(1000.0, 915.0), 91.67);
+        Ok(())
+    }
+
+    #[test]
+    fn test_airtime() -> Result<()> {
+        // 70 bytes on air with LongFast
+        assert_close(
+            airtime_ms(&ModemPreset::LongFast.params(false), 70, 16),
+            337.92,
+        );
+        // LongSlow symbols are long enough for low data rate optimization
+        assert_close(
+            airtime_ms(&ModemPreset::LongSlow.params(false), 70, 16),
+            2760.70,
+        );
+        // Slower presets always take longer
+        let airtimes: Vec<f64> = ModemPreset::ALL
+            .iter()
+            .map(|preset| airtime_ms(&preset.params(false), 70, 16))
+            .collect();
+        assert!(airtimes.windows(2).all(|pair| pair[0] < pair[1]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_estimate_from_distance() -> Result<()> {
+        let estimates = estimate_presets(
+            LinkSource::Distance(5000.0),
+            &setup(906.875),
+            ModemPreset::LongFast,
+        );
+        assert_eq!(estimates.len(), ModemPreset::ALL.len());
+        assert_eq!(estimates[0].preset, ModemPreset::ShortTurbo);
+
+        let long_fast = estimates
+            .iter()
+            .find(|estimate| estimate.selected)
+            .context("No selected preset")?;
+        assert_eq!(long_fast.preset, ModemPreset::LongFast);
+        assert_close(long_fast.expected_snr_db, 28.45);
+        assert_close(long_fast.margin_db, 45.95);
+        assert_eq!(long_fast.verdict, LinkVerdict::Reliable);
+
+        // At the maximum range the margin is gone
+        let range = long_fast.max_range_m.context("No range")?;
+        let at_range = estimate_presets(
+            LinkSource::Distance(range),
+            &setup(906.875),
+            ModemPreset::LongFast,
+        );
+        let at_range = at_range
+            .iter()
+            .find(|estimate| estimate.selected)
+            .context("No selected preset")?;
+        assert_close(at_range.margin_db, 0.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_estimate_from_observed_snr() -> Result<()> {
+        let source = LinkSource::ObservedSnr {
+            snr_db: -10.0,
+            preset: ModemPreset::LongFast,
+        };
+        let estimates = estimate_presets(source, &setup(906.875), ModemPreset::LongFast);
+        let find = |preset| {
+            estimates
+                .iter()
+                .find(|estimate| estimate.preset == preset)
+                .context("Missing preset")
+        };
+
+        let long_fast = find(ModemPreset::LongFast)?;
+        assert_close(long_fast.expected_snr_db, -10.0);
+        assert_close(long_fast.margin_db, 7.5);
+        assert_eq!(long_fast.verdict, LinkVerdict::Marginal);
+        assert!(long_fast.max_range_m.is_none());
+
+        // Half the bandwidth halves the noise
+        let long_slow = find(ModemPreset::LongSlow)?;
+        assert_close(long_slow.expected_snr_db, -6.99);
+        assert_eq!(long_slow.verdict, LinkVerdict::Reliable);
+
+        let short_turbo = find(ModemPreset::ShortTurbo)?;
+        assert_close(short_turbo.margin_db, -5.51);
+        assert_eq!(short_turbo.verdict, LinkVerdict::Unlikely);
+        Ok(())
+    }
+
+    #[test]
+    fn test_link_verdicts() -> Result<()> {
+        assert_eq!(LinkVerdict::from_margin(10.0), LinkVerdict::Reliable);
+        assert_eq!(LinkVerdict::from_margin(0.0), LinkVerdict::Marginal);
+        assert_eq!(LinkVerdict::from_margin(-0.1), LinkVerdict::Unlikely);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod fingerprint_tests {
+    use crate::channel_set::ChannelSet;
+    use crate::config::ConfigListing;
+    use crate::fingerprint::{ConfigFingerprint, fingerprint_config};
+    use crate::state::{
+        BluetoothConfig, ChannelInfo, DeviceConfig, DisplayConfig, LoraConfig, NetworkConfig,
+        PositionConfig, PowerConfig,
+    };
+    use anyhow::Result;
+    use meshtastic::protobufs;
+
+    fn listing() -> ConfigListing {
+        ConfigListing {
+            device: Some(DeviceConfig {
+                role: "Client".to_string(),
+                button_gpio: 0,
+                buzzer_gpio: 0,
+                rebroadcast_mode: "All".to_string(),
+                node_info_broadcast_secs: 900,
+                tzdef: None,
+                disable_triple_click: false,
+            }),
+            position: Some(PositionConfig {
+                position_broadcast_secs: 900,
+                position_broadcast_smart_enabled: true,
+                fixed_position: false,
+                gps_enabled: true,
+                gps_mode: "Enabled".to_string(),
+            }),
+            power: Some(PowerConfig {
+                is_power_saving: false,
+                on_battery_shutdown_after_secs: 0,
+                adc_multiplier_override: 0.0,
+                wait_bluetooth_secs: 60,
+                sds_secs: 0,
+                ls_secs: 300,
+                min_wake_secs: 10,
+            }),
+            network: Some(NetworkConfig {
+                wifi_enabled: true,
+                wifi_ssid: "home".to_string(),
+                wifi_psk: "correct horse".to_string(),
+                ntp_server: "pool.ntp.org".to_string(),
+                eth_enabled: false,
+                ipv4_config: None,
+            }),
+            display: Some(DisplayConfig {
+                screen_on_secs: 60,
+                gps_format: "Dec".to_string(),
+                auto_screen_carousel_secs: 0,
+                compass_north_top: false,
+                flip_screen: false,
+                units: "Metric".to_string(),
+                displaymode: "Default".to_string(),
+                heading_bold: false,
+                wake_on_tap_or_motion: false,
+            }),
+            lora: Some(LoraConfig {
+                use_preset: true,
+                modem_preset: "LongFast".to_string(),
+                bandwidth: 0,
+                spread_factor: 0,
+                coding_rate: 0,
+                frequency_offset: 0.0,
+                region: "US".to_string(),
+                hop_limit: 3,
+                tx_enabled: true,
+                tx_power: 0,
+                channel_num: 0,
+                ignore_mqtt: false,
+                override_frequency: 0.0,
+                override_duty_cycle: false,
+                sx126x_rx_boosted_gain: false,
+            }),
+            bluetooth: Some(BluetoothConfig {
+                enabled: true,
+                mode: "RandomPin".to_string(),
+                fixed_pin: 123456,
+                device_logging_enabled: false,
+            }),
+        }
+    }
+
+    fn channels(psk: &[u8]) -> ChannelSet {
+        let primary = ChannelInfo {
+            index: 0,
+            name: "Private".to_string(),
+            role: "Primary".to_string(),
+            has_psk: true,
+            settings: Some(protobufs::ChannelSettings {
+                name: "Private".to_string(),
+                psk: psk.to_vec(),
+                ..Default::default()
+            }),
+        };
+        ChannelSet::from_channels(&[primary], true)
+    }
+
+    fn modules(mqtt_password: &str) -> protobufs::LocalModuleConfig {
+        protobufs::LocalModuleConfig {
+            mqtt: Some(protobufs::module_config::MqttConfig {
+                enabled: true,
+                username: "meshdev".to_string(),
+                password: mqtt_password.to_string(),
+                ..Default::default()
+            }),
+            telemetry: Some(protobufs::module_config::TelemetryConfig {
+                device_update_interval: 900,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_fingerprint_is_stable() -> Result<()> {
+        let fingerprint = fingerprint_config(&listing(), &modules("secret"), &channels(&[1]))?;
+        assert_eq!(
+            fingerprint,
+            fingerprint_config(&listing(), &modules("secret"), &channels(&[1]))?
+        );
+        assert_eq!(fingerprint.fingerprint.len(), 16);
+        // Seven config sections, the reported modules and the channels
+        assert_eq!(fingerprint.sections.len(), 10);
+        assert!(fingerprint.matches(&format!(
+            " {hash} ",
+            hash = fingerprint.fingerprint.to_uppercase()
+        )));
+        Ok(())
+    }
+
+    #[test]
+    fn test_fingerprint_covers_secrets_but_not_random_pin() -> Result<()> {
+        let fingerprint = fingerprint_config(&listing(), &modules("secret"), &channels(&[1]))?;
+        let changed_sections = |other: &ConfigFingerprint| {
+            other
+                .sections
+                .iter()
+                .zip(&fingerprint.sections)
+                .filter(|(a, b)| a.hash != b.hash)
+                .map(|(a, _)| a.section.clone())
+                .collect::<Vec<_>>()
+        };
+
+        // A wrong key is drift like any other setting
+        let wrong_psk = fingerprint_config(&listing(), &modules("secret"), &channels(&[2; 16]))?;
+        assert_eq!(changed_sections(&wrong_psk), ["channels"]);
+        let wrong_password = fingerprint_config(&listing(), &modules("guess"), &channels(&[1]))?;
+        assert_eq!(changed_sections(&wrong_password), ["mqtt"]);
+        let mut changed = listing();
+        if let Some(network) = changed.network.as_mut() {
+            network.wifi_psk = "hunter2".to_string();
+        }
+        let wrong_wifi = fingerprint_config(&changed, &modules("secret"), &channels(&[1]))?;
+        assert_eq!(changed_sections(&wrong_wifi), ["network"]);
+
+        // Only hashes of the secrets go into the hashed sections
+        let mut changed = listing();
+        if let Some(bluetooth) = changed.bluetooth.as_mut() {
+            bluetooth.fixed_pin = 654321;
+        }
+        assert_eq!(
+            fingerprint,
+            fingerprint_config(&changed, &modules("secret"), &channels(&[1]))?
+        );
+
+        // A fixed PIN is part of the configuration
+        if let Some(bluetooth) = changed.bluetooth.as_mut() {
+            bluetooth.mode = "FixedPin".to_string();
+        }
+        let fixed = fingerprint_config(&changed, &modules("secret"), &channels(&[1]))?;
+        if let Some(bluetooth) = changed.bluetooth.as_mut() {
+            bluetooth.fixed_pin = 111111;
+        }
+        assert_ne!(
+            fixed,
+            fingerprint_config(&changed, &modules("secret"), &channels(&[1]))?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_fingerprint_detects_drift() -> Result<()> {
+        let fingerprint = fingerprint_config(&listing(), &modules("secret"), &channels(&[1]))?;
+
+        let mut drifted = listing();
+        if let Some(lora) = drifted.lora.as_mut() {
+            lora.hop_limit = 5;
+        }
+        let drifted = fingerprint_config(&drifted, &modules("secret"), &channels(&[1]))?;
+        assert!(!drifted.matches(&fingerprint.fingerprint));
+
+        let changed = drifted
+            .sections
+            .iter()
+            .zip(&fingerprint.sections)
+            .filter(|(a, b)| a.hash != b.hash)
+            .map(|(a, _)| a.section.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(changed, ["lora"]);
+
+        // Module settings are part of the configuration too
+        let mut modules = modules("secret");
+        if let Some(telemetry) = modules.telemetry.as_mut() {
+            telemetry.device_update_interval = 60;
+        }
+        let drifted = fingerprint_config(&listing(), &modules, &channels(&[1]))?;
+        assert!(!drifted.matches(&fingerprint.fingerprint));
+        Ok(())
+    }
+
+    #[test]
+    fn test_fingerprint_needs_every_section() -> Result<()> {
+        let mut partial = listing();
+        partial.lora = None;
+        let error = fingerprint_config(&partial, &modules("secret"), &channels(&[1]))
+            .err()
+            .map(|error| error.to_string());
+        assert_eq!(
+            error.as_deref(),
+            Some("The device has not reported its lora settings")
+        );
+
+        let error = fingerprint_config(&listing(), &Default::default(), &channels(&[1]))
+            .err()
+            .map(|error| error.to_string());
+        assert_eq!(
+            error.as_deref(),
+            Some("The device has not reported its module settings")
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod packet_processor_tests {
+    use crate::connection::{PacketProcessor, RequestResponse};
+    use crate::events::{EventFilter, MeshEvent};
+    use crate::state::DeviceState;
+    use anyhow::{Context, Result};
+    use meshtastic::Message;
+    use meshtastic::protobufs::{self, FromRadio, from_radio};
+    use std::sync::Arc;
+    use tokio::sync::Mutex;
+
+    const LOCAL: u32 = 0x0000_0001;
+    const REMOTE: u32 = 0x1234_5678;
+
+    /// Encoded frames, as the radio sends them
+    mod fixtures {
+        use super::{LOCAL, REMOTE};
+        use meshtastic::Message;
+        use meshtastic::protobufs::{self, FromRadio, from_radio, mesh_packet};
+
+        pub fn frame(variant: from_radio::PayloadVariant) -> Vec<u8> {
+            FromRadio {
+                id: 1,
+                payload_variant: Some(variant),
+            }
+            .encode_to_vec()
+        }
+
+        fn packet(portnum: protobufs::PortNum, payload: Vec<u8>, request_id: u32) -> Vec<u8> {
+            frame(from_radio::PayloadVariant::Packet(protobufs::MeshPacket {
+                from: REMOTE,
+                to: LOCAL,
+                id: 0x100,
+                rx_snr: 6.5,
+                rx_rssi: -90,
+                payload_variant: Some(mesh_packet::PayloadVariant::Decoded(protobufs::Data {
+                    portnum: portnum as i32,
+                    payload,
+                    request_id,
+                    ..Default::default()
+                })),
+                ..Default::default()
+            }))
+        }
+
+        pub fn my_info() -> Vec<u8> {
+            frame(from_radio::PayloadVariant::MyInfo(protobufs::MyNodeInfo {
+                my_node_num: LOCAL,
+                reboot_count: 3,
+                ..Default::default()
+            }))
+        }
+
+        pub fn node_info() -> Vec<u8> {
+            frame(from_radio::PayloadVariant::NodeInfo(protobufs::NodeInfo {
+                num: REMOTE,
+                user: Some(protobufs::User {
+                    id: "!12345678".to_string(),
+                    long_name: "Remote Node".to_string(),
+                    short_name: "RN".to_string(),
+                    ..Default::default()
+                }),
+                snr: 4.0,
+                last_heard: 1_700_000_000,
+                ..Default::default()
+            }))
+        }
+
+        pub fn text(text: &str) -> Vec<u8> {
+            packet(
+                protobufs::PortNum::TextMessageApp,
+                text.as_bytes().to_vec(),
+                0,
+            )
+        }
+
+        pub fn position(request_id: u32) -> Vec<u8> {
+            let position = protobufs::Position {
+                latitude_i: Some(525_200_000),
+                longitude_i: Some(134_050_000),
+                altitude: Some(34),
+                ..Default::default()
+            };
+            packet(
+                protobufs::PortNum::PositionApp,
+                position.encode_to_vec(),
+                request_id,
+            )
+        }
+
+        /// Reply of a node without a fix: a position with no coordinates
+        pub fn empty_position(request_id: u32) -> Vec<u8> {
+            let position = protobufs::Position::default();
+            packet(
+                protobufs::PortNum::PositionApp,
+                position.encode_to_vec(),
+                request_id,
+            )
+        }
+
+        pub fn device_telemetry() -> Vec<u8> {
+            let telemetry = protobufs::Telemetry {
+                time: 1_700_000_000,
+                variant: Some(protobufs::telemetry::Variant::DeviceMetrics(
+                    protobufs::DeviceMetrics {
+                        battery_level: Some(87),
+                        voltage: Some(4.1),
+                        ..Default::default()
+                    },
+                )),
+            };
+            packet(
+                protobufs::PortNum::TelemetryApp,
+                telemetry.encode_to_vec(),
+                0,
+            )
+        }
+
+        pub fn environment_telemetry() -> Vec<u8> {
+            let telemetry = protobufs::Telemetry {
+                time: 1_700_000_060,
+                variant: Some(protobufs::telemetry::Variant::EnvironmentMetrics(
+                    protobufs::EnvironmentMetrics {
+                        temperature: Some(21.5),
+                        relative_humidity: Some(40.0),
+                        ..Default::default()
+                    },
+                )),
+            };
+            packet(
+                protobufs::PortNum::TelemetryApp,
+                telemetry.encode_to_vec(),
+                0,
+            )
+        }
+
+        pub fn routing(error: protobufs::routing::Error, request_id: u32) -> Vec<u8> {
+            let routing = protobufs::Routing {
+                variant: Some(protobufs::routing::Variant::ErrorReason(error as i32)),
+            };
+            packet(
+                protobufs::PortNum::RoutingApp,
+                routing.encode_to_vec(),
+                request_id,
+            )
+        }
+
+        pub fn lora_config_response(passkey: &[u8]) -> Vec<u8> {
+            let admin = protobufs::AdminMessage {
+                payload_variant: Some(protobufs::admin_message::PayloadVariant::GetConfigResponse(
+                    protobufs::Config {
+                        payload_variant: Some(protobufs::config::PayloadVariant::Lora(
+                            protobufs::config::LoRaConfig {
+                                use_preset: true,
+                                region: protobufs::config::lo_ra_config::RegionCode::Eu868 as i32,
+                                hop_limit: 5,
+                                ..Default::default()
+                            },
+                        )),
+                    },
+                )),
+                session_passkey: passkey.to_vec(),
+            };
+            packet(protobufs::PortNum::AdminApp, admin.encode_to_vec(), 0)
+        }
+
+        pub fn encrypted() -> Vec<u8> {
+            frame(from_radio::PayloadVariant::Packet(protobufs::MeshPacket {
+                from: REMOTE,
+                to: LOCAL,
+                id: 0x101,
+                payload_variant: Some(mesh_packet::PayloadVariant::Encrypted(vec![0xde, 0xad])),
+                ..Default::default()
+            }))
+        }
+
+        /// A packet with a mangled payload for its port
+        pub fn undecodable_position() -> Vec<u8> {
+            packet(protobufs::PortNum::PositionApp, vec![0xff, 0xff, 0xff], 0)
+        }
+    }
+
+    fn processor() -> PacketProcessor {
+        PacketProcessor::new(Arc::new(Mutex::new(DeviceState::new())))
+    }
+
+    async fn feed(processor: &PacketProcessor, frames: &[Vec<u8>]) -> Result<()> {
+        for frame in frames {
+            processor
+                .process_from_radio(FromRadio::decode(frame.as_slice())?)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn state(processor: &PacketProcessor) -> DeviceState {
+        processor.device_state().lock().await.clone()
+    }
+
+    #[tokio::test]
+    async fn test_handshake_frames() -> Result<()> {
+        let processor = processor();
+        feed(&processor, &[fixtures::my_info(), fixtures::node_info()]).await?;
+
+        let state = state(&processor).await;
+        let my_info = state.my_node_info.context("No node info")?;
+        assert_eq!((my_info.node_num, my_info.reboot_count), (LOCAL, 3));
+        assert_eq!(my_info.node_id, "!00000001");
+        let node = state.nodes.get(&REMOTE).context("Remote node missing")?;
+        assert_eq!(node.id, "!12345678");
+        assert_eq!(node.user.long_name, "Remote Node");
+        assert_eq!(node.snr, Some(4.0));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_text_message() -> Result<()> {
+        let processor = processor();
+        let mut events = processor.subscribe_events();
+        feed(
+            &processor,
+            &[fixtures::my_info(), fixtures::text("hello mesh")],
+        )
+        .await?;
+
+        let state = state(&processor).await;
+        let [message] = state.messages.as_slice() else {
+            anyhow::bail!(
+                "Expected one message, got {messages:?}",
+                messages = state.messages
+            );
+        };
+        assert_eq!(message.text, "hello mesh");
+        assert_eq!((message.from_node, message.to_node), (REMOTE, LOCAL));
+        assert_eq!((message.snr, message.rssi), (Some(6.5), Some(-90)));
+        assert!(!message.own);
+
+        let Ok(MeshEvent::TextMessage(event)) = events.try_recv() else {
+            anyhow::bail!("No text message event");
+        };
+        assert_eq!(
+            (event.from_node, event.text.as_str()),
+            (REMOTE, "hello mesh")
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_filtered_events() -> Result<()> {
+        let processor = processor();
+        let mut texts = processor.subscribe_filtered(
+            EventFilter::new()
+                .ports([protobufs::PortNum::TextMessageApp])
+                .nodes([REMOTE]),
+        );
+        let mut others = processor.subscribe_filtered(EventFilter::new().nodes([LOCAL + 1]));
+        feed(
+            &processor,
+            &[
+                fixtures::my_info(),
+                fixtures::position(0),
+                fixtures::text("hello mesh"),
+            ],
+        )
+        .await?;
+
+        let Ok(MeshEvent::TextMessage(event)) = texts.try_recv() else {
+            anyhow::bail!("No text message event");
+        };
+        assert_eq!(event.text, "hello mesh");
+        // The position was never queued for the text subscriber
+        assert!(texts.try_recv().is_err());
+        assert!(others.try_recv().is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_position() -> Result<()> {
+        let processor = processor();
+        feed(&processor, &[fixtures::position(0)]).await?;
+
+        let state = state(&processor).await;
+        let position = state.positions.get(&REMOTE).context("No position")?;
+        assert!((position.latitude - 52.52).abs() < 1e-6);
+        assert!((position.longitude - 13.405).abs() < 1e-6);
+        assert_eq!(position.altitude, Some(34));
+        assert_eq!(
+            state.position_history.get(&REMOTE).map(|h| h.len()),
+            Some(1)
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_telemetry_variants() -> Result<()> {
+        let processor = processor();
+        feed(&processor, &[fixtures::device_telemetry()]).await?;
+        let device = state(&processor)
+            .await
+            .telemetry
+            .get(&REMOTE)
+            .and_then(|telemetry| telemetry.device_metrics.clone())
+            .context("No device metrics")?;
+        assert_eq!(device.battery_level, Some(87));
+        assert_eq!(device.voltage, Some(4.1));
+
+        feed(&processor, &[fixtures::environment_telemetry()]).await?;
+        let state = state(&processor).await;
+        let latest = state.telemetry.get(&REMOTE).context("No telemetry")?;
+        let environment = latest
+            .environment_metrics
+            .as_ref()
+            .context("No environment metrics")?;
+        assert_eq!(environment.temperature, Some(21.5));
+        assert!(latest.device_metrics.is_none());
+        assert_eq!(
+            state.telemetry_history.get(&REMOTE).map(|h| h.len()),
+            Some(2)
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_routing_reports() -> Result<()> {
+        let processor = processor();
+        let mut events = processor.subscribe_events();
+        feed(
+            &processor,
+            &[
+                fixtures::routing(protobufs::routing::Error::None, 42),
+                fixtures::routing(protobufs::routing::Error::MaxRetransmit, 43),
+                fixtures::routing(protobufs::routing::Error::NoRoute, 0),
+                // A plain status without a packet is not reported
+                fixtures::routing(protobufs::routing::Error::None, 0),
+            ],
+        )
+        .await?;
+
+        let Ok(MeshEvent::Ack(ack)) = events.try_recv() else {
+            anyhow::bail!("No ACK event");
+        };
+        assert_eq!((ack.packet_id, ack.reason.as_str()), (42, "None"));
+        let Ok(MeshEvent::Nak(nak)) = events.try_recv() else {
+            anyhow::bail!("No NAK event");
+        };
+        assert_eq!((nak.packet_id, nak.reason.as_str()), (43, "MaxRetransmit"));
+        let Ok(MeshEvent::RoutingError(error)) = events.try_recv() else {
+            anyhow::bail!("No routing error event");
+        };
+        assert_eq!(
+            (error.from_node, error.reason.as_str()),
+            (REMOTE, "NoRoute")
+        );
+        assert!(events.try_recv().is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_admin_response() -> Result<()> {
+        let processor = processor();
+        feed(&processor, &[fixtures::lora_config_response(&[7, 7, 7])]).await?;
+
+        let lora = state(&processor)
+            .await
+            .lora_config
+            .context("No LoRa config")?;
+        assert_eq!(lora.region, "EU868");
+        assert_eq!(lora.hop_limit, 5);
+        assert_eq!(processor.session_key(REMOTE).await, Some(vec![7, 7, 7]));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_unreadable_packets_leave_state_alone() -> Result<()> {
+        let processor = processor();
+        let mut events = processor.subscribe_events();
+        feed(
+            &processor,
+            &[
+                fixtures::encrypted(),
+                fixtures::undecodable_position(),
+                fixtures::frame(from_radio::PayloadVariant::ConfigCompleteId(9)),
+            ],
+        )
+        .await?;
+
+        let state = state(&processor).await;
+        assert!(state.messages.is_empty());
+        assert!(state.positions.is_empty());
+        assert!(events.try_recv().is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_empty_position_resolves_request() -> Result<()> {
+        let processor = processor();
+        let (sender, receiver) = tokio::sync::oneshot::channel();
+        processor
+            .response_waiters
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Waiters poisoned"))?
+            .insert(42, sender);
+        feed(
+            &processor,
+            &[fixtures::my_info(), fixtures::empty_position(42)],
+        )
+        .await?;
+
+        let response = receiver.await.context("Waiter dropped")?;
+        assert!(matches!(response, RequestResponse::NoPosition));
+        assert!(state(&processor).await.positions.is_empty());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod session_cache_tests {
+    use crate::session_cache::{MAX_SESSION_TTL, SessionCache, SessionId};
+    use anyhow::Result;
+    use std::time::Duration;
+
+    fn session_id(reboot_count: u32) -> SessionId {
+        SessionId {
+            target: "/dev/ttyUSB0".to_string(),
+            node_num: 0x1234_5678,
+            reboot_count,
+        }
+    }
+
+    #[test]
+    fn test_session_key_expires_and_follows_reboots() -> Result<()> {
+        let path = std::env::temp_dir().join(format!(
+            "rmesh-session-{random:016x}.json",
+            random = rand::random::<u64>()
+        ));
+        let cache = SessionCache::new(&path, Duration::from_secs(100));
+        let passkey = [0xde, 0xad, 0xbe, 0xef, 0x01, 0x02, 0x03, 0x04];
+
+        assert_eq!(cache.load_at(&session_id(3), 1000), None);
+        cache.store_at(&session_id(3), &passkey, 1000)?;
+        assert_eq!(cache.load_at(&session_id(3), 1099), Some(passkey.to_vec()));
+        assert_eq!(cache.load_at(&session_id(3), 1100), None);
+        // The device forgets its passkey when it reboots
+        assert_eq!(cache.load_at(&session_id(4), 1050), None);
+
+        cache.forget(&session_id(3))?;
+        assert_eq!(cache.load_at(&session_id(3), 1050), None);
+
+        // Longer lifetimes are capped below the firmware's
+        let capped = SessionCache::new(&path, Duration::from_secs(3600));
+        capped.store_at(&session_id(3), &passkey, 1000)?;
+        assert_eq!(
+            capped.load_at(&session_id(3), 1000 + MAX_SESSION_TTL.as_secs()),
+            None
+        );
+
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod alert_tests {
+    use crate::admin::BROADCAST_NODE_NUM;
+    use crate::alert::{MIN_ALERT_INTERVAL, alert_packet, check_alert_rate};
+    use anyhow::Result;
+    use meshtastic::protobufs::{self, mesh_packet};
+
+    #[test]
+    fn test_alert_rate_limit() -> Result<()> {
+        check_alert_rate(None, 1_000, MIN_ALERT_INTERVAL)?;
+        check_alert_rate(Some(1_000), 1_060, MIN_ALERT_INTERVAL)?;
+
+        let limited = check_alert_rate(Some(1_000), 1_045, MIN_ALERT_INTERVAL)
+            .expect_err("An alert 45s after the last one is refused");
+        assert_eq!((limited.elapsed, limited.wait), (45, 15));
+        Ok(())
+    }
+
+    #[test]
+    fn test_alert_packet() -> Result<()> {
+        let packet = alert_packet("Evacuate now", BROADCAST_NODE_NUM, 1, 42);
+        assert_eq!(packet.to, BROADCAST_NODE_NUM);
+        assert_eq!(packet.channel, 1);
+        assert_eq!(packet.priority(), mesh_packet::Priority::Alert);
+
+        let Some(mesh_packet::PayloadVariant::Decoded(data)) = packet.payload_variant else {
+            anyhow::bail!("Alert packet is not decoded");
+        };
+        assert_eq!(data.portnum(), protobufs::PortNum::AlertApp);
+        assert_eq!(data.payload, b"Evacuate now");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod packet_filter_tests {
+    use crate::packet_filter::{PacketFilter, PacketSummary};
+    use anyhow::{Result, bail};
+    use meshtastic::protobufs::{self, mesh_packet};
+
+    fn text_packet(from: u32, snr: f32) -> PacketSummary {
+        PacketSummary::from_packet(&protobufs::MeshPacket {
+            from,
+            to: 0xffff_ffff,
+            rx_snr: snr,
+            hop_start: 3,
+            hop_limit: 1,
+            payload_variant: Some(mesh_packet::PayloadVariant::Decoded(protobufs::Data {
+                portnum: protobufs::PortNum::TextMessageApp as i32,
+                payload: b"hi".to_vec(),
+                ..Default::default()
+            })),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_packet_filter_matches() -> Result<()> {
+        let filter: PacketFilter = "port==TEXT_MESSAGE_APP && from==!abc123 && snr<-10".parse()?;
+        assert!(filter.matches(&text_packet(0xabc123, -12.5)));
+        assert!(!filter.matches(&text_packet(0xabc123, -8.0)));
+        assert!(!filter.matches(&text_packet(0xabc124, -12.5)));
+
+        let filter: PacketFilter = "!(port == text_message || hops>=3) && to==^all".parse()?;
+        assert!(!filter.matches(&text_packet(1, 0.0)));
+
+        // An encrypted packet has no port, so it never equals one
+        let encrypted = PacketSummary::from_packet(&protobufs::MeshPacket {
+            payload_variant: Some(mesh_packet::PayloadVariant::Encrypted(vec![0; 16])),
+            ..Default::default()
+        });
+        let filter: PacketFilter = "encrypted && port!=TEXT_MESSAGE_APP".parse()?;
+        assert!(filter.matches(&encrypted));
+        assert_eq!(text_packet(1, 0.0).hops, Some(2));
+        Ok(())
+    }
+
+    #[test]
+    fn test_packet_filter_errors() -> Result<()> {
+        let Err(error) = "snr<-10 && rsi>-100".parse::<PacketFilter>() else {
+            bail!("Unknown fields should be rejected");
+        };
+        assert_eq!(error.column, 12);
+
+        for bad in [
+            "port<TEXT_MESSAGE_APP",
+            "port==NOT_A_PORT",
+            "from==abc123",
+            "snr<",
+            "(snr<1",
+            "snr<1 snr>2",
+        ] {
+            assert!(
+                bad.parse::<PacketFilter>().is_err(),
+                "{bad} should not parse"
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod transcript_tests {
+    use crate::message::{Encryption, ReceivedMessage};
+    use crate::state::{NodeInfo, User};
+    use crate::time::TimeFormat;
+    use crate::transcript::{PruneReport, Transcript, parse_archive, prune_archive_text};
+    use anyhow::Result;
+    use std::collections::HashMap;
+
+    fn message(from_node: u32, to_node: u32, text: &str, time: u64) -> ReceivedMessage {
+        ReceivedMessage {
+            from: format!("!{from_node:08x}"),
+            from_node,
+            to: format!("!{to_node:08x}"),
+            to_node,
+            channel: 0,
+            channel_name: Some("LongFast".to_string()),
+            encryption: Encryption::Psk,
+            via_mqtt: false,
+            own: false,
+            text: text.to_string(),
+            time,
+            snr: None,
+            rssi: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_archive_accepts_jsonl_and_json_lines() -> Result<()> {
+        let record = serde_json::json!({
+            "schema_version": 1,
+            "kind": "message",
+            "data": message(1, 0xffff_ffff, "from jsonl", 100),
+        });
+        let bare = serde_json::to_string(&message(2, 0xffff_ffff, "from json", 200))?;
+        let position = serde_json::json!({"schema_version": 1, "kind": "position", "data": {}});
+        let archive = format!("{record}\n\n{position}\n{bare}\n");
+
+        let messages = parse_archive(&archive)?;
+        let texts = messages.iter().map(|m| m.text.as_str()).collect::<Vec<_>>();
+        assert_eq!(texts, ["from jsonl", "from json"]);
+
+        let error = parse_archive("not json").expect_err("Garbage is rejected");
+        assert!(error.to_string().contains("Line 1"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_prune_archive() -> Result<()> {
+        let old = serde_json::to_string(&message(1, 0xffff_ffff, "old", 100))?;
+        let recent = serde_json::to_string(&message(2, 0xffff_ffff, "recent", 200))?;
+        let position = serde_json::json!({"schema_version": 1, "kind": "position", "data": {}});
+        let archive = format!("{old}\n{position}\n{recent}\n");
+
+        let (pruned, report) = prune_archive_text(&archive, Some(150))?;
+        assert_eq!(
+            report,
+            PruneReport {
+                kept: 1,
+                removed: 1
+            }
+        );
+        assert_eq!(pruned, format!("{position}\n{recent}\n"));
+
+        // Clearing everything keeps records of other kinds
+        let (pruned, report) = prune_archive_text(&archive, None)?;
+        assert_eq!(
+            report,
+            PruneReport {
+                kept: 0,
+                removed: 2
+            }
+        );
+        assert_eq!(pruned, format!("{position}\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_transcript_groups_conversations() -> Result<()> {
+        let alice = NodeInfo {
+            id: "!00000001".to_string(),
+            num: 1,
+            user: User {
+                id: "!00000001".to_string(),
+                long_name: "Alice <base>".to_string(),
+                short_name: "AL".to_string(),
+                hw_model: None,
+                role: None,
+                public_key: None,
+                is_licensed: false,
+            },
+            last_heard: None,
+            last_heard_iso: None,
+            snr: None,
+            rssi: None,
+            device_metrics: None,
+        };
+        let nodes = HashMap::from([(1, alice)]);
+        let messages = [
+            message(2, 1, "dm reply", 300),
+            message(1, 0xffff_ffff, "hello all", 200),
+            message(1, 2, "dm", 250),
+            message(1, 0xffff_ffff, "too old", 50),
+        ];
+
+        let transcript = Transcript::build(&messages, Some(100), &nodes);
+        let titles = transcript
+            .conversations
+            .iter()
+            .map(|c| c.title.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            titles,
+            ["#LongFast (channel 0)", "Alice <base> and !00000002"]
+        );
+        assert_eq!(transcript.message_count(), 3);
+        let direct = &transcript.conversations[1].entries;
+        assert_eq!(
+            (direct[0].text.as_str(), direct[1].text.as_str()),
+            ("dm", "dm reply")
+        );
+
+        let text = transcript.to_text(TimeFormat::Epoch);
+        assert!(text.contains("[200] Alice <base>: hello all"));
+        let html = transcript.to_html(TimeFormat::Epoch);
+        assert!(html.contains("Alice &lt;base&gt;"));
+        assert!(!html.contains("Alice <base>"));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod health_tests {
+    use crate::connection::health::health_response;
+    use crate::connection::{HealthReport, QueueDepths};
+    use anyhow::{Context, Result};
+    use std::time::Duration;
+
+    fn report(connected: bool, handshake_complete: bool, age: Option<u64>) -> HealthReport {
+        HealthReport {
+            connected,
+            handshake_complete,
+            reboots: 0,
+            last_packet_age_secs: age,
+            queues: QueueDepths::default(),
+        }
+    }
+
+    #[test]
+    fn test_liveness_and_readiness() -> Result<()> {
+        let max_age = Some(Duration::from_secs(60));
+
+        let healthy = report(true, true, Some(5));
+        assert!(healthy.is_live(max_age) && healthy.is_ready(max_age));
+
+        let handshaking = report(true, false, Some(5));
+        assert!(handshaking.is_live(max_age));
+        assert!(!handshaking.is_ready(max_age));
+
+        let silent = report(true, true, Some(300));
+        assert!(silent.is_live(None));
+        assert!(!silent.is_live(max_age));
+        assert!(!report(true, true, None).is_live(max_age));
+
+        assert!(!report(false, true, Some(5)).is_live(None));
+        Ok(())
+    }
+
+    #[test]
+    fn test_health_response() -> Result<()> {
+        let handshaking = report(true, false, Some(5));
+
+        let (status, body) =
+            health_response("/healthz", &handshaking, None).context("/healthz is served")?;
+        assert_eq!(status, 200);
+        let body: serde_json::Value = serde_json::from_str(&body)?;
+        assert_eq!(body["status"], "ok");
+        assert_eq!(body["last_packet_age_secs"], 5);
+        assert_eq!(body["queues"]["pending_acks"], 0);
+
+        let (status, body) =
+            health_response("/readyz", &handshaking, None).context("/readyz is served")?;
+        assert_eq!(status, 503);
+        assert!(body.contains(r#""status":"unavailable""#));
+
+        assert!(health_response("/metrics", &handshaking, None).is_none());
+        Ok(())
+    }
+}
+
+#[cfg(all(test, target_os = "linux", feature = "systemd"))]
+mod systemd_tests {
+    use crate::systemd::{parse_watchdog, send_notification};
+    use anyhow::Result;
+    use std::os::unix::net::UnixDatagram;
+    use std::time::Duration;
+
+    #[test]
+    fn test_parse_watchdog() -> Result<()> {
+        assert_eq!(
+            parse_watchdog(Some("30000000"), None, 42),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            parse_watchdog(Some("30000000"), Some("42"), 42),
+            Some(Duration::from_secs(30))
+        );
+        // Meant for another process, or not configured
+        assert_eq!(parse_watchdog(Some("30000000"), Some("7"), 42), None);
+        assert_eq!(parse_watchdog(None, None, 42), None);
+        assert_eq!(parse_watchdog(Some("0"), None, 42), None);
+        assert_eq!(parse_watchdog(Some("soon"), None, 42), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_send_notification() -> Result<()> {
+        let path =
+            std::env::temp_dir().join(format!("rmesh-notify-{pid}", pid = std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let socket = UnixDatagram::bind(&path)?;
+
+        let sent = send_notification(path.as_os_str(), "READY=1");
+        let mut buffer = [0; 64];
+        let received = socket.recv(&mut buffer);
+        std::fs::remove_file(&path)?;
+
+        sent?;
+        assert_eq!(&buffer[..received?], b"READY=1");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod inject_tests {
+    use crate::connection::Injection;
+    use crate::connection::handshake::HandshakeProgress;
+    use crate::connection::inject::{merge_injected, parse_capture};
+    use anyhow::{Context, Result};
+    use meshtastic::{Message, protobufs};
+    use std::time::Duration;
+    use tokio::sync::{mpsc, watch};
+
+    fn config_complete(id: u32) -> protobufs::FromRadio {
+        protobufs::FromRadio {
+            id,
+            payload_variant: Some(protobufs::from_radio::PayloadVariant::ConfigCompleteId(id)),
+        }
+    }
+
+    #[test]
+    fn test_parse_capture() -> Result<()> {
+        let bytes = config_complete(7).encode_to_vec();
+        let mut framed = vec![0x94, 0xc3, 0x00, bytes.len() as u8];
+        framed.extend(&bytes);
+
+        let capture = [
+            serde_json::to_string(&config_complete(5))?,
+            String::new(),
+            serde_json::to_string(&hex::encode(&bytes))?,
+            serde_json::to_string(&hex::encode(&framed))?,
+        ]
+        .join("\n");
+        let frames = parse_capture(&capture)?;
+        assert_eq!(
+            frames,
+            vec![config_complete(5), config_complete(7), config_complete(7)]
+        );
+
+        let error = parse_capture("\"0a\"\nnot json").expect_err("Bad lines are refused");
+        assert!(error.to_string().contains("Line 1"));
+        assert!(parse_capture("not json").is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_injected_frames_follow_the_handshake() -> Result<()> {
+        let (device, receiver) = mpsc::unbounded_channel();
+        let (progress, progress_receiver) = watch::channel(HandshakeProgress::default());
+        let injection = Injection::new(vec![config_complete(9)], Duration::ZERO);
+        let mut merged = merge_injected(receiver, injection, progress_receiver);
+
+        device.send(config_complete(1))?;
+        assert_eq!(merged.recv().await, Some(config_complete(1)));
+
+        progress.send_modify(|progress| progress.config_complete_id = Some(1));
+        let injected = tokio::time::timeout(Duration::from_secs(1), merged.recv())
+            .await
+            .context("Injected frame never arrived")?;
+        assert_eq!(injected, Some(config_complete(9)));
+
+        // The merged stream ends with the device's
+        drop(device);
+        assert_eq!(merged.recv().await, None);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod clock_tests {
+    use crate::clock::{ClockReport, clock_drift};
+    use anyhow::Result;
+    use std::time::Duration;
+
+    fn report(drift_secs: Option<i64>) -> ClockReport {
+        ClockReport {
+            node: "!abcd1234".to_string(),
+            device_time: drift_secs.map(|drift| (1_700_000_000 + drift) as u64),
+            host_time: 1_700_000_000,
+            drift_secs,
+            round_trip_ms: 300,
+            synced: false,
+        }
+    }
+
+    #[test]
+    fn test_clock_drift_allows_for_the_round_trip() -> Result<()> {
+        let sent_at = 1_700_000_000.0;
+        assert_eq!(
+            clock_drift(1_700_000_001, sent_at, Duration::from_secs(2)),
+            0
+        );
+        assert_eq!(
+            clock_drift(1_700_000_091, sent_at, Duration::from_secs(2)),
+            90
+        );
+        assert_eq!(clock_drift(1_699_999_000, sent_at, Duration::ZERO), -1000);
+        Ok(())
+    }
+
+    #[test]
+    fn test_clock_needs_sync() -> Result<()> {
+        assert!(report(None).needs_sync());
+        assert!(!report(Some(3)).needs_sync());
+        assert!(!report(Some(-60)).needs_sync());
+        assert!(report(Some(-61)).needs_sync());
+        assert!(report(Some(3600)).needs_sync());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod send_queue_tests {
+    use crate::connection::ConnectionCounters;
+    use crate::connection::send_queue::{
+        FrameSink, RadioWriter, SEND_QUEUE_CAPACITY, SendPriority,
+    };
+    use anyhow::{Result, bail, ensure};
+    use meshtastic::protobufs::to_radio::PayloadVariant;
+    use meshtastic::protobufs::{Data, MeshPacket, PortNum, mesh_packet};
+    use std::time::Duration;
+    use tokio::sync::oneshot;
+    use tokio::task::JoinSet;
+
+    /// Writes each frame in two halves, as a slow serial port would
+    #[derive(Default)]
+    struct RecordingSink {
+        writes: Vec<(u32, &'static str)>,
+    }
+
+    impl FrameSink for RecordingSink {
+        async fn send_frame(&mut self, payload: PayloadVariant) -> Result<()> {
+            let PayloadVariant::WantConfigId(id) = payload else {
+                bail!("Unexpected frame {payload:?}");
+            };
+            self.writes.push((id, "start"));
+            tokio::time::sleep(Duration::from_millis(1)).await;
+            self.writes.push((id, "end"));
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_sends_are_written_whole() -> Result<()> {
+        let counters = ConnectionCounters::default();
+        let writer = RadioWriter::spawn(RecordingSink::default(), counters.clone());
+        let senders = SEND_QUEUE_CAPACITY as u32 + 8;
+
+        let mut tasks = JoinSet::new();
+        for id in 0..senders {
+            let sender = writer.sender().clone();
+            tasks.spawn(async move { sender.send(PayloadVariant::WantConfigId(id)).await });
+        }
+        while let Some(result) = tasks.join_next().await {
+            result??;
+        }
+
+        assert_eq!(counters.send_queue_depth(), 0);
+        ensure!(
+            counters.snapshot().send_queue_peak > 1,
+            "Sends never queued up"
+        );
+
+        let sink = writer.close().await?;
+        assert_eq!(sink.writes.len(), 2 * senders as usize);
+        for halves in sink.writes.chunks(2) {
+            ensure!(
+                halves[0].0 == halves[1].0 && halves[0].1 == "start" && halves[1].1 == "end",
+                "Frames interleaved: {halves:?}"
+            );
+        }
+        Ok(())
+    }
+
+    /// Holds the first frame until the gate opens, so the rest queue up
+    struct GatedSink {
+        started: Option<oneshot::Sender<()>>,
+        gate: Option<oneshot::Receiver<()>>,
+        written: Vec<u32>,
+    }
+
+    impl FrameSink for GatedSink {
+        async fn send_frame(&mut self, payload: PayloadVariant) -> Result<()> {
+            if let (Some(started), Some(gate)) = (self.started.take(), self.gate.take()) {
+                let _ = started.send(());
+                gate.await?;
+            }
+            let PayloadVariant::WantConfigId(id) = payload else {
+                bail!("Unexpected frame {payload:?}");
+            };
+            self.written.push(id);
+            Ok(())
+        }
+    }
+
+    fn packet(portnum: PortNum) -> PayloadVariant {
+        PayloadVariant::Packet(MeshPacket {
+            payload_variant: Some(mesh_packet::PayloadVariant::Decoded(Data {
+                portnum: portnum as i32,
+                ..Default::default()
+            })),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_send_priority_of_frames() -> Result<()> {
+        assert_eq!(
+            SendPriority::of(&packet(PortNum::AdminApp)),
+            SendPriority::High
+        );
+        assert_eq!(
+            SendPriority::of(&packet(PortNum::RoutingApp)),
+            SendPriority::High
+        );
+        assert_eq!(
+            SendPriority::of(&packet(PortNum::TextMessageApp)),
+            SendPriority::Normal
+        );
+        // A user's request for one node is not held back behind polls;
+        // polls ask for background priority themselves
+        for port in [
+            PortNum::TelemetryApp,
+            PortNum::PositionApp,
+            PortNum::NodeinfoApp,
+        ] {
+            assert_eq!(SendPriority::of(&packet(port)), SendPriority::Normal);
+        }
+        assert_eq!(
+            SendPriority::of(&PayloadVariant::Packet(MeshPacket {
+                payload_variant: Some(mesh_packet::PayloadVariant::Encrypted(vec![1, 2])),
+                ..Default::default()
+            })),
+            SendPriority::Normal
+        );
+        assert_eq!(
+            SendPriority::of(&PayloadVariant::WantConfigId(1)),
+            SendPriority::High
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_higher_priorities_are_written_first() -> Result<()> {
+        let counters = ConnectionCounters::default();
+        let (started, mut first_taken) = oneshot::channel();
+        let (open, gate) = oneshot::channel();
+        let sink = GatedSink {
+            started: Some(started),
+            gate: Some(gate),
+            written: Vec::new(),
+        };
+        let writer = RadioWriter::spawn(sink, counters.clone());
+
+        let mut tasks = JoinSet::new();
+        for (id, priority) in [
+            (0, SendPriority::Background),
+            (1, SendPriority::Background),
+            (2, SendPriority::Normal),
+            (3, SendPriority::High),
+            (4, SendPriority::Normal),
+        ] {
+            let sender = writer.sender().clone();
+            tasks.spawn(async move {
+                sender
+                    .send_with_priority(PayloadVariant::WantConfigId(id), priority)
+                    .await
+            });
+            if id == 0 {
+                (&mut first_taken).await?;
+            }
+            // The first frame is held by the sink; the rest wait in the queues
+            while counters.send_queue_depth() <= u64::from(id) {
+                tokio::task::yield_now().await;
+            }
+        }
+        let _ = open.send(());
+        while let Some(result) = tasks.join_next().await {
+            result??;
+        }
+
+        let sink = writer.close().await?;
+        assert_eq!(sink.written, vec![0, 3, 2, 4, 1]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_send_after_close_fails() -> Result<()> {
+        let writer = RadioWriter::spawn(RecordingSink::default(), ConnectionCounters::default());
+        let sender = writer.sender().clone();
+        writer.close().await?;
+
+        let result = sender.send(PayloadVariant::WantConfigId(1)).await;
+        ensure!(result.is_err(), "Send to a closed writer succeeded");
+        Ok(())
+    }
 }
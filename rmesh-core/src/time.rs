@@ -0,0 +1,114 @@
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use std::time::Duration;
+use strum::{Display, EnumString};
+
+/// How timestamps are shown in human-readable output
+///
+/// JSON output always carries the raw values; this only affects tables and
+/// text lines.
+#[derive(Debug, Clone, Copy, Default, Serialize, Display, EnumString, PartialEq, Eq)]
+#[strum(serialize_all = "lowercase", ascii_case_insensitive)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeFormat {
+    /// UTC RFC 3339, e.g. 2024-05-01T12:00:00+00:00
+    #[default]
+    Rfc3339,
+    /// Local time zone, e.g. 2024-05-01 14:00:00
+    Local,
+    /// Age relative to now, e.g. 5m ago
+    Relative,
+    /// Seconds since the Unix epoch
+    Epoch,
+}
+
+/// Current time in seconds since the Unix epoch
+pub fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// UTC RFC 3339 form of a Unix timestamp, `None` when out of range
+pub fn to_rfc3339(timestamp: u64) -> Option<String> {
+    DateTime::from_timestamp(i64::try_from(timestamp).ok()?, 0).map(|dt| dt.to_rfc3339())
+}
+
+/// Short age of an event `seconds` in the past, e.g. "42s ago" or "3d ago"
+pub fn format_age(seconds: u64) -> String {
+    if seconds < 60 {
+        format!("{seconds}s ago")
+    } else if seconds < 3600 {
+        format!("{minutes}m ago", minutes = seconds / 60)
+    } else if seconds < 86_400 {
+        format!("{hours}h ago", hours = seconds / 3600)
+    } else {
+        format!("{days}d ago", days = seconds / 86_400)
+    }
+}
+
+/// Parse a span such as `90s`, `30m`, `12h`, `7d` or `2w`
+///
+/// A bare number is taken as seconds.
+pub fn parse_age(input: &str) -> Result<Duration> {
+    let input = input.trim().to_lowercase();
+    let split = input
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(input.len());
+    let (number, unit) = input.split_at(split);
+    let multiplier = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86_400,
+        "w" => 7 * 86_400,
+        _ => bail!("Invalid unit in '{input}' (expected s, m, h, d or w)"),
+    };
+    let number = number
+        .parse::<u64>()
+        .with_context(|| format!("Invalid span '{input}' (expected e.g. 12h or 7d)"))?;
+    let seconds = number
+        .checked_mul(multiplier)
+        .with_context(|| format!("Span '{input}' is too long"))?;
+    Ok(Duration::from_secs(seconds))
+}
+
+/// Format a Unix timestamp
+pub fn format_timestamp(timestamp: u64, format: TimeFormat) -> String {
+    format_timestamp_at(timestamp, format, unix_now())
+}
+
+/// Format a Unix timestamp, taking `now` as the reference for relative ages
+///
+/// Timestamps in the future (clock skew between nodes) show as "0s ago".
+pub fn format_timestamp_at(timestamp: u64, format: TimeFormat, now: u64) -> String {
+    match format {
+        TimeFormat::Epoch => timestamp.to_string(),
+        TimeFormat::Relative => format_age(now.saturating_sub(timestamp)),
+        TimeFormat::Rfc3339 => to_rfc3339(timestamp).unwrap_or_else(|| timestamp.to_string()),
+        TimeFormat::Local => i64::try_from(timestamp)
+            .ok()
+            .and_then(|secs| DateTime::from_timestamp(secs, 0))
+            .map(|dt| {
+                dt.with_timezone(&Local)
+                    .format("%Y-%m-%d %H:%M:%S")
+                    .to_string()
+            })
+            .unwrap_or_else(|| timestamp.to_string()),
+    }
+}
+
+/// Reformat an RFC 3339 time string, as stored in [`crate::state::Position`]
+///
+/// Strings that do not parse are returned unchanged.
+pub fn format_rfc3339(time: &str, format: TimeFormat) -> String {
+    match DateTime::parse_from_rfc3339(time) {
+        Ok(dt) => match u64::try_from(dt.timestamp()) {
+            Ok(timestamp) => format_timestamp(timestamp, format),
+            Err(_) => time.to_string(),
+        },
+        Err(_) => time.to_string(),
+    }
+}
@@ -0,0 +1,299 @@
+use crate::admin::BROADCAST_NODE_NUM;
+use crate::message::ReceivedMessage;
+use crate::node_id::NodeId;
+use crate::state::NodeInfo;
+use crate::time::{TimeFormat, format_timestamp};
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+/// Record kind of messages in `message monitor` JSONL output
+const MESSAGE_KIND: &str = "message";
+
+/// Read the messages logged by `message monitor --output`
+///
+/// Lines may be versioned JSONL records or the bare messages printed with
+/// `--json`. Records of other kinds and blank lines are skipped.
+pub fn read_archive(path: &Path) -> Result<Vec<ReceivedMessage>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {path}", path = path.display()))?;
+    parse_archive(&text).with_context(|| format!("Failed to parse {path}", path = path.display()))
+}
+
+pub(crate) fn parse_archive(text: &str) -> Result<Vec<ReceivedMessage>> {
+    let mut messages = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        if let Some(message) = archive_message(line, index + 1)? {
+            messages.push(message);
+        }
+    }
+    Ok(messages)
+}
+
+/// Message logged on a line of the archive, `None` for blank lines and
+/// records of other kinds
+fn archive_message(line: &str, line_number: usize) -> Result<Option<ReceivedMessage>> {
+    if line.trim().is_empty() {
+        return Ok(None);
+    }
+    let mut value: Value =
+        serde_json::from_str(line).with_context(|| format!("Line {line_number} is not JSON"))?;
+    if let Some(kind) = value.get("kind") {
+        if kind != MESSAGE_KIND {
+            return Ok(None);
+        }
+        value = value["data"].take();
+    }
+    let message = serde_json::from_value(value)
+        .with_context(|| format!("Line {line_number} is not a message"))?;
+    Ok(Some(message))
+}
+
+/// Messages kept and deleted by [`prune_archive`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct PruneReport {
+    pub kept: usize,
+    pub removed: usize,
+}
+
+/// Delete the messages received before `cutoff`, or all of them, from a log
+/// written by `message monitor --output`
+///
+/// Records of other kinds are kept. The log is rewritten through a
+/// temporary file, so an interrupted prune leaves it whole.
+pub fn prune_archive(path: &Path, cutoff: Option<u64>) -> Result<PruneReport> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {path}", path = path.display()))?;
+    let (pruned, report) = prune_archive_text(&text, cutoff)
+        .with_context(|| format!("Failed to parse {path}", path = path.display()))?;
+    if report.removed == 0 {
+        return Ok(report);
+    }
+
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    std::fs::write(&temporary, pruned)
+        .and_then(|()| std::fs::rename(&temporary, path))
+        .with_context(|| format!("Failed to rewrite {path}", path = path.display()))?;
+    Ok(report)
+}
+
+pub(crate) fn prune_archive_text(text: &str, cutoff: Option<u64>) -> Result<(String, PruneReport)> {
+    let mut pruned = String::with_capacity(text.len());
+    let mut report = PruneReport::default();
+    for (index, line) in text.lines().enumerate() {
+        if let Some(message) = archive_message(line, index + 1)? {
+            if cutoff.is_none_or(|cutoff| message.time < cutoff) {
+                report.removed += 1;
+                continue;
+            }
+            report.kept += 1;
+        }
+        pruned.push_str(line);
+        pruned.push('\n');
+    }
+    Ok((pruned, report))
+}
+
+/// Where a message was said: a channel, or between two nodes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum ConversationKey {
+    Channel(u32),
+    /// Node numbers of both sides, lowest first
+    Direct(u32, u32),
+}
+
+impl ConversationKey {
+    fn of(message: &ReceivedMessage) -> Self {
+        if message.to_node == BROADCAST_NODE_NUM {
+            Self::Channel(message.channel)
+        } else {
+            let (a, b) = (message.from_node, message.to_node);
+            Self::Direct(a.min(b), a.max(b))
+        }
+    }
+}
+
+/// One line of a transcript
+#[derive(Debug, Clone, PartialEq)]
+pub struct TranscriptEntry {
+    pub time: u64,
+    /// Node id of the sender, e.g. `!abcd1234`
+    pub from: String,
+    /// Long name of the sender, or its node id when unknown
+    pub sender: String,
+    pub text: String,
+    /// Sent from our own node
+    pub own: bool,
+}
+
+/// Messages of one channel or one direct conversation, oldest first
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conversation {
+    pub title: String,
+    pub entries: Vec<TranscriptEntry>,
+}
+
+/// Message history grouped into conversations, channels first
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Transcript {
+    pub conversations: Vec<Conversation>,
+}
+
+impl Transcript {
+    /// Group messages received at or after `since` into conversations
+    ///
+    /// Senders are named after the node database, so a transcript built
+    /// while connected reads better than the bare node ids in the archive.
+    pub fn build(
+        messages: &[ReceivedMessage],
+        since: Option<u64>,
+        nodes: &HashMap<u32, NodeInfo>,
+    ) -> Self {
+        let name = |node: u32| {
+            nodes
+                .get(&node)
+                .map(|info| info.user.long_name.trim())
+                .filter(|name| !name.is_empty())
+                .map(str::to_string)
+                .unwrap_or_else(|| NodeId(node).to_string())
+        };
+
+        let mut grouped = BTreeMap::<ConversationKey, Vec<&ReceivedMessage>>::new();
+        for message in messages {
+            if since.is_some_and(|since| message.time < since) {
+                continue;
+            }
+            grouped
+                .entry(ConversationKey::of(message))
+                .or_default()
+                .push(message);
+        }
+
+        let conversations = grouped
+            .into_iter()
+            .map(|(key, mut messages)| {
+                messages.sort_by_key(|message| message.time);
+                let title = match key {
+                    ConversationKey::Channel(index) => {
+                        // Name the channel after the newest message that knew it
+                        let channel_name = messages
+                            .iter()
+                            .rev()
+                            .find_map(|message| message.channel_name.as_deref());
+                        match channel_name {
+                            Some(channel) => format!("#{channel} (channel {index})"),
+                            None => format!("Channel {index}"),
+                        }
+                    }
+                    ConversationKey::Direct(a, b) => {
+                        format!("{a} and {b}", a = name(a), b = name(b))
+                    }
+                };
+                let entries = messages
+                    .into_iter()
+                    .map(|message| TranscriptEntry {
+                        time: message.time,
+                        from: message.from.clone(),
+                        sender: name(message.from_node),
+                        text: message.text.clone(),
+                        own: message.own,
+                    })
+                    .collect();
+                Conversation { title, entries }
+            })
+            .collect();
+
+        Self { conversations }
+    }
+
+    pub fn message_count(&self) -> usize {
+        self.conversations
+            .iter()
+            .map(|conversation| conversation.entries.len())
+            .sum()
+    }
+
+    /// Plain text transcript, one block per conversation
+    pub fn to_text(&self, time_format: TimeFormat) -> String {
+        let blocks = self
+            .conversations
+            .iter()
+            .map(|conversation| {
+                let mut lines = vec![format!("== {title} ==", title = conversation.title)];
+                lines.extend(conversation.entries.iter().map(|entry| {
+                    format!(
+                        "[{time}] {sender}: {text}",
+                        time = format_timestamp(entry.time, time_format),
+                        sender = entry.sender,
+                        text = entry.text
+                    )
+                }));
+                lines.join("\n") + "\n"
+            })
+            .collect::<Vec<_>>();
+        blocks.join("\n")
+    }
+
+    /// Self-contained HTML page, with our own messages as bubbles on the
+    /// right as in a phone messenger
+    pub fn to_html(&self, time_format: TimeFormat) -> String {
+        let mut lines = vec![HTML_HEAD.to_string()];
+        for conversation in &self.conversations {
+            lines.push("<section>".to_string());
+            lines.push(format!(
+                "<h2>{title}</h2>",
+                title = escape_html(&conversation.title)
+            ));
+            lines.extend(conversation.entries.iter().map(|entry| {
+                format!(
+                    "<div class=\"msg{own}\"><div class=\"meta\"><span title=\"{from}\">{sender}</span> \
+                     <time>{time}</time></div><div class=\"text\">{text}</div></div>",
+                    own = if entry.own { " own" } else { "" },
+                    from = escape_html(&entry.from),
+                    sender = escape_html(&entry.sender),
+                    time = escape_html(&format_timestamp(entry.time, time_format)),
+                    text = escape_html(&entry.text)
+                )
+            }));
+            lines.push("</section>".to_string());
+        }
+        lines.push("</body>\n</html>\n".to_string());
+        lines.join("\n")
+    }
+}
+
+const HTML_HEAD: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Meshtastic transcript</title>
+<style>
+body { font-family: sans-serif; background: #eceff1; max-width: 48em; margin: 0 auto; padding: 1em; }
+h2 { font-size: 1.1em; color: #455a64; border-bottom: 1px solid #b0bec5; }
+.msg { background: #fff; border-radius: 0.8em; padding: 0.4em 0.8em; margin: 0.4em 25% 0.4em 0; }
+.msg.own { background: #c8e6c9; margin: 0.4em 0 0.4em 25%; }
+.meta { font-size: 0.8em; color: #607d8b; }
+.meta span { font-weight: bold; }
+.text { white-space: pre-wrap; overflow-wrap: anywhere; }
+</style>
+</head>
+<body>"#;
+
+/// Escape text for use in HTML content and attribute values
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,322 @@
+use crate::admin::BROADCAST_NODE_NUM;
+use crate::connection::ConnectionManager;
+use crate::node_id::NodeId;
+use crate::progress::{ProgressCallback, ProgressReporter};
+use anyhow::{Context, Result, bail, ensure};
+use chrono::DateTime;
+use meshtastic::{Message, protobufs};
+use serde::Serialize;
+use serde_json::Value;
+use std::path::Path;
+use strum::Display;
+use tokio::time::Duration;
+use tracing::{debug, info};
+
+/// Longest waypoint name the firmware stores, in bytes
+pub const MAX_NAME_BYTES: usize = 30;
+
+/// Longest waypoint description the firmware stores, in bytes
+pub const MAX_DESCRIPTION_BYTES: usize = 100;
+
+/// Default pause between waypoint broadcasts
+pub const DEFAULT_SEND_INTERVAL: Duration = Duration::from_secs(5);
+
+/// A waypoint to share with the mesh
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Waypoint {
+    /// Identifier nodes use to update or delete the waypoint
+    pub id: u32,
+    pub name: String,
+    pub description: String,
+    /// Emoji shown on the map
+    pub icon: Option<char>,
+    pub latitude: f64,
+    pub longitude: f64,
+    /// Unix time after which nodes drop the waypoint, `None` to keep it
+    pub expire: Option<u64>,
+}
+
+impl Waypoint {
+    /// Protobuf form of the waypoint
+    pub fn to_protobuf(&self) -> protobufs::Waypoint {
+        protobufs::Waypoint {
+            id: self.id,
+            latitude_i: Some((self.latitude * 1e7).round() as i32),
+            longitude_i: Some((self.longitude * 1e7).round() as i32),
+            expire: self
+                .expire
+                .map_or(0, |expire| u32::try_from(expire).unwrap_or(u32::MAX)),
+            name: self.name.clone(),
+            description: self.description.clone(),
+            icon: self.icon.map_or(0, u32::from),
+            ..Default::default()
+        }
+    }
+}
+
+/// Read waypoints from a GeoJSON file
+pub fn load_geojson(path: &Path) -> Result<Vec<Waypoint>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {path}", path = path.display()))?;
+    parse_geojson(&text)
+        .with_context(|| format!("Invalid GeoJSON in {path}", path = path.display()))
+}
+
+/// Parse a GeoJSON FeatureCollection, or a single Feature, of points
+///
+/// Each feature's `name`, `description`, `icon` (an emoji) and `expire`
+/// (Unix time or RFC 3339) properties are used; a numeric `id` property or
+/// feature id is kept, otherwise one is derived from the name and location so
+/// importing the same file again updates the waypoints instead of duplicating
+/// them.
+pub fn parse_geojson(text: &str) -> Result<Vec<Waypoint>> {
+    let document: Value = serde_json::from_str(text).context("Failed to parse JSON")?;
+
+    let features = match document.get("type").and_then(Value::as_str) {
+        Some("FeatureCollection") => document
+            .get("features")
+            .and_then(Value::as_array)
+            .context("FeatureCollection has no features array")?
+            .iter()
+            .collect::<Vec<_>>(),
+        Some("Feature") => vec![&document],
+        Some(other) => bail!("Expected a FeatureCollection or Feature, found {other}"),
+        None => bail!("Missing GeoJSON type"),
+    };
+
+    let now = crate::time::unix_now();
+    features
+        .into_iter()
+        .enumerate()
+        .map(|(index, feature)| {
+            parse_feature(feature, now)
+                .with_context(|| format!("Feature {number}", number = index + 1))
+        })
+        .collect()
+}
+
+fn parse_feature(feature: &Value, now: u64) -> Result<Waypoint> {
+    let geometry = feature.get("geometry").context("Missing geometry")?;
+    let geometry_type = geometry
+        .get("type")
+        .and_then(Value::as_str)
+        .context("Missing geometry type")?;
+    ensure!(
+        geometry_type == "Point",
+        "Only Point geometries can be waypoints, found {geometry_type}"
+    );
+
+    // GeoJSON orders coordinates as longitude, latitude
+    let coordinates = geometry
+        .get("coordinates")
+        .and_then(Value::as_array)
+        .context("Missing point coordinates")?;
+    let longitude = coordinates
+        .first()
+        .and_then(Value::as_f64)
+        .context("Missing longitude")?;
+    let latitude = coordinates
+        .get(1)
+        .and_then(Value::as_f64)
+        .context("Missing latitude")?;
+    ensure!(
+        (-90.0..=90.0).contains(&latitude),
+        "Latitude {latitude} out of range"
+    );
+    ensure!(
+        (-180.0..=180.0).contains(&longitude),
+        "Longitude {longitude} out of range"
+    );
+
+    let properties = feature.get("properties").filter(|value| value.is_object());
+    let property = |key: &str| properties.and_then(|properties| properties.get(key));
+
+    let name = property("name")
+        .and_then(Value::as_str)
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .context("Missing name property")?
+        .to_string();
+    ensure!(
+        name.len() <= MAX_NAME_BYTES,
+        "Name '{name}' is longer than {MAX_NAME_BYTES} bytes"
+    );
+
+    let description = property("description")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .trim()
+        .to_string();
+    ensure!(
+        description.len() <= MAX_DESCRIPTION_BYTES,
+        "Description of '{name}' is longer than {MAX_DESCRIPTION_BYTES} bytes"
+    );
+
+    let icon = match property("icon") {
+        None | Some(Value::Null) => None,
+        Some(Value::String(icon)) => Some(
+            icon.chars()
+                .next()
+                .with_context(|| format!("Empty icon for '{name}'"))?,
+        ),
+        Some(other) => bail!("Icon of '{name}' must be an emoji, found {other}"),
+    };
+
+    let expire = match property("expire") {
+        None | Some(Value::Null) => None,
+        Some(value) => {
+            let expire =
+                parse_expire(value).with_context(|| format!("Invalid expire time for '{name}'"))?;
+            ensure!(expire > now, "Waypoint '{name}' has already expired");
+            Some(expire)
+        }
+    };
+
+    let id = match property("id").or_else(|| feature.get("id")) {
+        Some(Value::Number(id)) => id
+            .as_u64()
+            .and_then(|id| u32::try_from(id).ok())
+            .filter(|id| *id != 0)
+            .with_context(|| {
+                format!("Id of '{name}' must be between 1 and {max}", max = u32::MAX)
+            })?,
+        _ => derive_waypoint_id(&name, latitude, longitude),
+    };
+
+    Ok(Waypoint {
+        id,
+        name,
+        description,
+        icon,
+        latitude,
+        longitude,
+        expire,
+    })
+}
+
+fn parse_expire(value: &Value) -> Result<u64> {
+    match value {
+        Value::Number(number) => number.as_u64().context("Expected a positive Unix time"),
+        Value::String(text) => {
+            let time = DateTime::parse_from_rfc3339(text)
+                .with_context(|| format!("Expected RFC 3339, found '{text}'"))?;
+            u64::try_from(time.timestamp()).context("Time is before 1970")
+        }
+        other => bail!("Expected a Unix time or RFC 3339 string, found {other}"),
+    }
+}
+
+/// Stable, non-zero waypoint id from its name and location (FNV-1a)
+pub fn derive_waypoint_id(name: &str, latitude: f64, longitude: f64) -> u32 {
+    let key = format!("{name}@{latitude:.7},{longitude:.7}");
+    let hash = key.bytes().fold(0x811c_9dc5_u32, |hash, byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+    });
+    hash.max(1)
+}
+
+/// Build the broadcast mesh packet carrying a waypoint
+pub fn waypoint_packet(waypoint: &Waypoint, packet_id: u32, channel: u32) -> protobufs::MeshPacket {
+    protobufs::MeshPacket {
+        payload_variant: Some(protobufs::mesh_packet::PayloadVariant::Decoded(
+            protobufs::Data {
+                portnum: protobufs::PortNum::WaypointApp as i32,
+                payload: waypoint.to_protobuf().encode_to_vec(),
+                ..Default::default()
+            },
+        )),
+        to: BROADCAST_NODE_NUM,
+        id: packet_id,
+        channel,
+        ..Default::default()
+    }
+}
+
+/// Outcome of importing one waypoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum WaypointImportStatus {
+    /// Listed by a dry run, nothing was sent
+    Planned,
+    /// Handed to the device for broadcast
+    Sent,
+    /// The device did not accept the packet
+    SendFailed,
+}
+
+/// Per-waypoint result of an import
+#[derive(Debug, Clone, Serialize)]
+pub struct WaypointImportResult {
+    pub waypoint: Waypoint,
+    pub status: WaypointImportStatus,
+}
+
+/// List what an import would send without touching the device
+pub fn plan_import(waypoints: Vec<Waypoint>) -> Vec<WaypointImportResult> {
+    waypoints
+        .into_iter()
+        .map(|waypoint| WaypointImportResult {
+            waypoint,
+            status: WaypointImportStatus::Planned,
+        })
+        .collect()
+}
+
+/// Broadcast waypoints one at a time, pausing `interval` between them
+///
+/// Each waypoint takes airtime on every node that rebroadcasts it, so large
+/// imports are spread out rather than flooding the mesh.
+pub async fn import_waypoints(
+    connection: &mut ConnectionManager,
+    waypoints: Vec<Waypoint>,
+    channel: u32,
+    interval: Duration,
+) -> Result<Vec<WaypointImportResult>> {
+    import_waypoints_with_progress(connection, waypoints, channel, interval, None).await
+}
+
+/// Broadcast waypoints, reporting each one as it is sent
+pub async fn import_waypoints_with_progress(
+    connection: &mut ConnectionManager,
+    waypoints: Vec<Waypoint>,
+    channel: u32,
+    interval: Duration,
+    progress: Option<ProgressCallback<'_>>,
+) -> Result<Vec<WaypointImportResult>> {
+    info!("Sending {count} waypoint(s)...", count = waypoints.len());
+    let mut reporter =
+        ProgressReporter::start(progress, "import_waypoints", Some(waypoints.len() as u64));
+
+    let mut results = Vec::with_capacity(waypoints.len());
+    for (index, waypoint) in waypoints.into_iter().enumerate() {
+        if index > 0 {
+            tokio::time::sleep(interval).await;
+        }
+
+        let packet = waypoint_packet(&waypoint, connection.next_packet_id(), channel);
+        let status = match connection.send_mesh_packet(packet).await {
+            Ok(()) => {
+                debug!(
+                    "Sent waypoint {id} '{name}'",
+                    id = NodeId(waypoint.id),
+                    name = waypoint.name
+                );
+                WaypointImportStatus::Sent
+            }
+            Err(e) => {
+                debug!(
+                    "Failed to send waypoint '{name}': {e}",
+                    name = waypoint.name
+                );
+                WaypointImportStatus::SendFailed
+            }
+        };
+
+        reporter.advance(1, Some(format!("{name}: {status}", name = waypoint.name)));
+        results.push(WaypointImportResult { waypoint, status });
+    }
+    reporter.finish();
+
+    Ok(results)
+}
@@ -0,0 +1,226 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::report::{TestOutcome, TestReport, TestResult};
+
+/// Slowdown ratio above which a test's duration counts as a regression
+const DURATION_REGRESSION_RATIO: f64 = 1.5;
+
+/// Slowdowns smaller than this are timing noise, whatever the ratio
+const DURATION_REGRESSION_MIN_MS: u64 = 500;
+
+/// SNR drop in dB that counts as a regression
+const SNR_REGRESSION_DB: f64 = 3.0;
+
+/// What changed for one test between the baseline and the current run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Change {
+    /// Passed in the baseline, fails now
+    NewlyFailing,
+    /// Failed in the baseline, passes now
+    NewlyPassing,
+    /// Took noticeably longer than in the baseline
+    Slower { baseline_ms: u64, current_ms: u64 },
+    /// A reported SNR dropped, for a node or the mesh average
+    SnrDrop {
+        subject: String,
+        baseline_db: f64,
+        current_db: f64,
+    },
+    /// Ran in the baseline but not now
+    Missing,
+}
+
+impl Change {
+    /// Whether the change should fail the run
+    pub fn is_regression(&self) -> bool {
+        !matches!(self, Self::NewlyPassing | Self::Missing)
+    }
+}
+
+impl std::fmt::Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NewlyFailing => write!(f, "now failing"),
+            Self::NewlyPassing => write!(f, "now passing"),
+            Self::Slower {
+                baseline_ms,
+                current_ms,
+            } => write!(f, "slower, {baseline_ms}ms -> {current_ms}ms"),
+            Self::SnrDrop {
+                subject,
+                baseline_db,
+                current_db,
+            } => write!(
+                f,
+                "SNR of {subject} dropped, {baseline_db:.1} dB -> {current_db:.1} dB"
+            ),
+            Self::Missing => write!(f, "not run"),
+        }
+    }
+}
+
+/// A change to a single test
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TestChange {
+    pub category: String,
+    pub name: String,
+    pub change: Change,
+}
+
+/// Differences between a run and a previous report
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BaselineComparison {
+    pub baseline_test_id: String,
+    pub changes: Vec<TestChange>,
+}
+
+impl BaselineComparison {
+    pub fn has_regressions(&self) -> bool {
+        self.changes
+            .iter()
+            .any(|change| change.change.is_regression())
+    }
+
+    pub fn print_summary(&self) {
+        use colored::*;
+
+        println!(
+            "\n{section}",
+            section = format!("Compared with baseline {id}:", id = self.baseline_test_id).bold()
+        );
+
+        if self.changes.is_empty() {
+            println!("  {note}", note = "No changes".green());
+            return;
+        }
+
+        for change in &self.changes {
+            let line = format!(
+                "{category}/{name}: {kind}",
+                category = change.category,
+                name = change.name,
+                kind = change.change
+            );
+            let line = match change.change {
+                Change::NewlyFailing => line.red().bold(),
+                Change::NewlyPassing => line.green(),
+                Change::Slower { .. } | Change::SnrDrop { .. } => line.yellow(),
+                Change::Missing => line.normal(),
+            };
+            println!("  • {line}");
+        }
+    }
+}
+
+/// Load a JSON report written by an earlier `--format json` run
+pub fn load_baseline(path: &Path) -> Result<TestReport> {
+    let json = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read baseline {path}", path = path.display()))?;
+    serde_json::from_str(&json)
+        .with_context(|| format!("Invalid baseline report {path}", path = path.display()))
+}
+
+/// Compare a run against a baseline report, test by test
+pub fn compare(baseline: &TestReport, current: &TestReport) -> BaselineComparison {
+    let previous: HashMap<(&str, &str), &TestResult> = baseline
+        .test_results
+        .iter()
+        .map(|result| ((result.category.as_str(), result.name.as_str()), result))
+        .collect();
+
+    let mut changes = Vec::new();
+    for result in &current.test_results {
+        let Some(before) = previous.get(&(result.category.as_str(), result.name.as_str())) else {
+            continue;
+        };
+        for change in compare_results(before, result) {
+            changes.push(TestChange {
+                category: result.category.clone(),
+                name: result.name.clone(),
+                change,
+            });
+        }
+    }
+
+    for before in &baseline.test_results {
+        let still_run = current
+            .test_results
+            .iter()
+            .any(|result| result.category == before.category && result.name == before.name);
+        if !still_run {
+            changes.push(TestChange {
+                category: before.category.clone(),
+                name: before.name.clone(),
+                change: Change::Missing,
+            });
+        }
+    }
+
+    BaselineComparison {
+        baseline_test_id: baseline.test_id.clone(),
+        changes,
+    }
+}
+
+fn compare_results(before: &TestResult, now: &TestResult) -> Vec<Change> {
+    match (&before.outcome, &now.outcome) {
+        (TestOutcome::Pass, TestOutcome::Fail) => return vec![Change::NewlyFailing],
+        (TestOutcome::Fail, TestOutcome::Pass) => return vec![Change::NewlyPassing],
+        (TestOutcome::Pass, TestOutcome::Pass) => {}
+        // Skipped or still failing tests have nothing comparable
+        _ => return Vec::new(),
+    }
+
+    let mut changes = Vec::new();
+    let slowdown = now.duration_ms.saturating_sub(before.duration_ms);
+    if slowdown >= DURATION_REGRESSION_MIN_MS
+        && now.duration_ms as f64 > before.duration_ms as f64 * DURATION_REGRESSION_RATIO
+    {
+        changes.push(Change::Slower {
+            baseline_ms: before.duration_ms,
+            current_ms: now.duration_ms,
+        });
+    }
+
+    let baseline_snr = snr_readings(&before.details);
+    let mut current_snr: Vec<(String, f64)> = snr_readings(&now.details).into_iter().collect();
+    current_snr.sort_by(|a, b| a.0.cmp(&b.0));
+    for (subject, current_db) in current_snr {
+        if let Some(&baseline_db) = baseline_snr.get(&subject)
+            && baseline_db - current_db >= SNR_REGRESSION_DB
+        {
+            changes.push(Change::SnrDrop {
+                subject,
+                baseline_db,
+                current_db,
+            });
+        }
+    }
+    changes
+}
+
+/// SNR values in test details: the mesh average and per-node readings by id
+fn snr_readings(details: &Value) -> HashMap<String, f64> {
+    let mut readings = HashMap::new();
+    if let Some(average) = details.get("average_snr").and_then(Value::as_f64) {
+        readings.insert("mesh average".to_string(), average);
+    }
+
+    for list in ["nodes", "neighbors"] {
+        let nodes = details.get(list).and_then(Value::as_array);
+        for node in nodes.into_iter().flatten() {
+            if let (Some(id), Some(snr)) = (
+                node.get("id").and_then(Value::as_str),
+                node.get("snr").and_then(Value::as_f64),
+            ) {
+                readings.insert(format!("node {id}"), snr);
+            }
+        }
+    }
+    readings
+}
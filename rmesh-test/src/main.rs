@@ -1,12 +1,20 @@
+mod baseline;
+mod profile;
 mod report;
 mod runner;
+mod stream;
 mod tests;
+#[cfg(test)]
+mod unit_tests;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use clap::{Parser, ValueEnum};
 use colored::*;
+use rmesh_core::connection::discovery;
 use std::io::IsTerminal;
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 use tracing_subscriber::EnvFilter;
 
 #[derive(Debug, Clone, ValueEnum)]
@@ -14,6 +22,19 @@ enum OutputFormat {
     Human,
     Json,
     Markdown,
+    Junit,
+    /// One JSON line per result, written as each test completes
+    Jsonl,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum ColorChoice {
+    /// Color when stdout is a terminal and NO_COLOR is not set
+    Auto,
+    /// Always color, even when piped
+    Always,
+    /// Never color
+    Never,
 }
 
 #[derive(Parser, Debug)]
@@ -24,6 +45,22 @@ struct Args {
     #[arg(short, long)]
     port: Option<String>,
 
+    /// Serial port or TCP address of a second device for over-the-air tests
+    #[arg(long)]
+    peer_port: Option<String>,
+
+    /// Profile of expected values (region, role, channel_count, firmware_min)
+    #[arg(long, value_name = "PROFILE")]
+    expect: Option<PathBuf>,
+
+    /// Also run tests that write config and channels, restoring the originals
+    #[arg(long)]
+    destructive: bool,
+
+    /// Minutes the GPS fix test waits for a fix
+    #[arg(long, value_name = "MINUTES", default_value = "5")]
+    gps_timeout: u64,
+
     /// Auto-detect connected device
     #[arg(short, long, conflicts_with = "port")]
     auto_detect: bool,
@@ -36,10 +73,14 @@ struct Args {
     #[arg(short = 'f', long, default_value = "human")]
     format: OutputFormat,
 
-    /// Output file path
+    /// Output file path; with `--format jsonl` results are appended as they complete
     #[arg(short, long)]
     output: Option<PathBuf>,
 
+    /// JSON report of a previous run to compare against; regressions fail the run
+    #[arg(long, value_name = "REPORT")]
+    baseline: Option<PathBuf>,
+
     /// Enable verbose output
     #[arg(short, long)]
     verbose: bool,
@@ -55,6 +96,10 @@ struct Args {
     /// Quiet mode (suppress non-critical errors like packet sync issues)
     #[arg(short = 'q', long)]
     quiet: bool,
+
+    /// When to use colors; `auto` disables them when piped or NO_COLOR is set
+    #[arg(long, value_enum, default_value = "auto")]
+    color: ColorChoice,
 }
 
 #[tokio::main]
@@ -76,8 +121,19 @@ async fn main() -> Result<()> {
     let is_tty = std::io::stdout().is_terminal();
     let non_interactive = args.non_interactive || !is_tty;
 
+    // NO_COLOR disables colors when any non-empty value is set
+    let colors = match args.color {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => {
+            is_tty && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+        }
+    };
+    colored::control::set_override(colors);
+
     tracing_subscriber::fmt()
         .with_env_filter(filter)
+        .with_ansi(colors)
         .with_target(false)
         .with_thread_ids(false)
         .with_thread_names(false)
@@ -106,44 +162,64 @@ async fn main() -> Result<()> {
     } else if args.auto_detect {
         auto_detect_device().await?
     } else {
-        // Try common ports
-        let common_ports = vec![
-            "/dev/ttyACM0",
-            "/dev/ttyUSB0",
-            "/dev/ttyUSB1",
-            "/dev/tty.usbserial",
-            "/dev/tty.usbmodem",
-        ];
-
-        let mut found_port = String::new();
-        for port in common_ports {
-            if std::path::Path::new(port).exists() {
-                eprintln!(
-                    "{arrow} Found device at {port}",
-                    arrow = "→".green(),
-                    port = port.bold()
-                );
-                found_port = port.to_string();
-                break;
-            }
-        }
-
-        anyhow::ensure!(
-            !found_port.is_empty(),
-            "No device found. Please specify --port or use --auto-detect"
+        // Take the most likely port without probing it
+        let candidates = discovery::discover_devices()?;
+        let candidate = candidates
+            .first()
+            .context("No device found. Please specify --port or use --auto-detect")?;
+        eprintln!(
+            "{arrow} Found device at {port}",
+            arrow = "→".green(),
+            port = candidate.port.bold()
         );
-        found_port
+        candidate.port.clone()
     };
 
+    // Read the baseline up front so a bad path fails before the tests run
+    let baseline = args
+        .baseline
+        .as_deref()
+        .map(baseline::load_baseline)
+        .transpose()?;
+
+    // Load the expected provisioning profile before connecting
+    let profile = args
+        .expect
+        .as_deref()
+        .map(profile::DeviceProfile::load)
+        .transpose()?;
+
     // Create test runner
-    let mut runner = runner::TestRunner::new(port.clone(), args.verbose, non_interactive).await?;
+    let mut runner = runner::TestRunner::new(
+        port.clone(),
+        args.peer_port.clone(),
+        profile,
+        args.destructive,
+        Duration::from_secs(args.gps_timeout * 60),
+        args.verbose,
+        non_interactive,
+    )
+    .await?;
+
+    // Results are written as they complete so a crash mid-run loses nothing
+    let stream = match args.format {
+        OutputFormat::Jsonl => {
+            let stream = Arc::new(stream::StreamWriter::create(args.output.as_deref())?);
+            runner.set_stream(stream.clone());
+            Some(stream)
+        }
+        _ => None,
+    };
 
     // Run tests
-    let report = if let Some(test_list) = args.tests {
+    let mut report = if let Some(test_list) = args.tests {
         runner.run_specific_tests(test_list).await?
     } else {
         runner.run_all_tests().await?
     };
+    report.baseline = baseline
+        .as_ref()
+        .map(|baseline| baseline::compare(baseline, &report));
 
     // Output results
     match args.format {
@@ -166,10 +242,27 @@ async fn main() -> Result<()> {
                 println!("{markdown}");
             }
         }
+        OutputFormat::Jsonl => {
+            if let Some(stream) = &stream {
+                stream.write_summary(&report)?;
+            }
+        }
+        OutputFormat::Junit => {
+            let junit = generate_junit_report(&report);
+            if let Some(output_path) = args.output {
+                std::fs::write(output_path, junit)?;
+            } else {
+                println!("{junit}");
+            }
+        }
     }
 
     // Exit with appropriate code
-    if report.tests_failed > 0 {
+    let regressed = report
+        .baseline
+        .as_ref()
+        .is_some_and(baseline::BaselineComparison::has_regressions);
+    if report.tests_failed > 0 || regressed {
         std::process::exit(1);
     }
 
@@ -182,95 +275,21 @@ async fn auto_detect_device() -> Result<String> {
         arrow = "→".cyan()
     );
 
-    // Check common serial port locations used by Meshtastic devices
-    // First check /dev/serial/by-id for most reliable identification
-    if let Ok(entries) = std::fs::read_dir("/dev/serial/by-id") {
-        for entry in entries.flatten() {
-            if let Some(name) = entry.file_name().to_str() {
-                let lower_name = name.to_lowercase();
-                // Check for Meshtastic-related identifiers
-                if lower_name.contains("meshtastic") ||
-                   lower_name.contains("esp32") ||
-                   lower_name.contains("heltec") ||
-                   lower_name.contains("lilygo") ||
-                   lower_name.contains("tbeam") ||
-                   lower_name.contains("t-beam") ||
-                   lower_name.contains("rak") ||
-                   lower_name.contains("wisblock") ||
-                   lower_name.contains("cp210") ||  // CP2102/CP2104 USB-Serial
-                   lower_name.contains("ch340") ||  // CH340 USB-Serial
-                   lower_name.contains("ch9102")
-                {
-                    // CH9102 USB-Serial
-                    if let Ok(path) = entry.path().canonicalize() {
-                        eprintln!(
-                            "{check} Found device: {name} -> {path}",
-                            check = "✓".green(),
-                            name = name.bold(),
-                            path = path.display()
-                        );
-                        return Ok(path.to_string_lossy().to_string());
-                    }
-                }
-            }
-        }
-    }
-
-    // Check common device paths directly
-    let common_ports = vec![
-        "/dev/ttyACM0", // Most common for modern ESP32-S3 devices
-        "/dev/ttyACM1",
-        "/dev/ttyUSB0", // Common for CP2102/CH340 based devices
-        "/dev/ttyUSB1",
-        "/dev/tty.usbserial",      // macOS
-        "/dev/tty.usbmodem",       // macOS
-        "/dev/tty.SLAB_USBtoUART", // macOS Silicon Labs
-    ];
-
-    for port in common_ports {
-        if std::path::Path::new(port).exists() {
-            // Try to verify it's actually accessible as a serial port
-            // We'll just check if the path exists and is a character device
-            if let Ok(metadata) = std::fs::metadata(port) {
-                use std::os::unix::fs::FileTypeExt;
-                if metadata.file_type().is_char_device() {
-                    eprintln!(
-                        "{check} Found device at {port}",
-                        check = "✓".green(),
-                        port = port.bold()
-                    );
-                    return Ok(port.to_string());
-                }
-            }
-        }
-    }
-
-    // Also check numbered variants
-    for base in &[
-        "/dev/ttyACM",
-        "/dev/ttyUSB",
-        "/dev/tty.usbserial-",
-        "/dev/tty.usbmodem",
-    ] {
-        for i in 0..10 {
-            let port = format!("{base}{i}");
-            if std::path::Path::new(&port).exists()
-                && let Ok(metadata) = std::fs::metadata(&port)
-            {
-                use std::os::unix::fs::FileTypeExt;
-                if metadata.file_type().is_char_device() {
-                    eprintln!(
-                        "{check} Found device at {port}",
-                        check = "✓".green(),
-                        port = port.bold()
-                    );
-                    return Ok(port);
-                }
-            }
-        }
-    }
-
-    anyhow::bail!("No Meshtastic device detected. Please connect a device or specify --port")
+    let candidate = discovery::find_device(discovery::DEFAULT_PROBE_TIMEOUT)
+        .await
+        .context("No Meshtastic device detected. Please connect a device or specify --port")?;
+    eprintln!(
+        "{check} Found device: {name} -> {port}",
+        check = "✓".green(),
+        name = candidate
+            .by_id
+            .as_deref()
+            .or(candidate.description.as_deref())
+            .unwrap_or("unknown device")
+            .bold(),
+        port = candidate.port
+    );
+    Ok(candidate.port)
 }
 
 fn generate_markdown_report(report: &report::TestReport) -> String {
@@ -302,6 +321,11 @@ fn generate_markdown_report(report: &report::TestReport) -> String {
         failed = report.tests_failed,
         percentage = report.tests_failed as f64 / report.tests_run as f64 * 100.0
     ));
+    md.push_str(&format!(
+        "- **Skipped:** {skipped} ({percentage:.1}%)\n",
+        skipped = report.tests_skipped,
+        percentage = report.tests_skipped as f64 / report.tests_run as f64 * 100.0
+    ));
     md.push('\n');
 
     md.push_str("## Test Results\n\n");
@@ -309,15 +333,16 @@ fn generate_markdown_report(report: &report::TestReport) -> String {
     md.push_str("|----------|------|--------|----------|----------|\n");
 
     for result in &report.test_results {
-        let status = if result.passed {
-            "✅ Pass"
-        } else {
-            "❌ Fail"
-        };
-        let details = if let Some(err) = &result.error {
-            err.clone()
-        } else {
-            "OK".to_string()
+        let (status, details) = match &result.outcome {
+            report::TestOutcome::Pass => ("✅ Pass", "OK".to_string()),
+            report::TestOutcome::Fail => (
+                "❌ Fail",
+                result
+                    .error
+                    .clone()
+                    .unwrap_or_else(|| "Unknown error".to_string()),
+            ),
+            report::TestOutcome::Skip(reason) => ("⏭️ Skip", reason.clone()),
         };
 
         md.push_str(&format!(
@@ -330,6 +355,29 @@ fn generate_markdown_report(report: &report::TestReport) -> String {
         ));
     }
 
+    if let Some(baseline) = &report.baseline {
+        md.push_str(&format!(
+            "\n## Changes Since Baseline {id}\n\n",
+            id = baseline.baseline_test_id
+        ));
+        if baseline.changes.is_empty() {
+            md.push_str("No changes.\n");
+        }
+        for change in &baseline.changes {
+            let marker = if change.change.is_regression() {
+                "❌"
+            } else {
+                "ℹ️"
+            };
+            md.push_str(&format!(
+                "- {marker} {category}/{name}: {change}\n",
+                category = change.category,
+                name = change.name,
+                change = change.change
+            ));
+        }
+    }
+
     md.push_str("\n## Recommendations\n\n");
     for rec in &report.recommendations {
         md.push_str(&format!("- {rec}\n"));
@@ -337,3 +385,105 @@ fn generate_markdown_report(report: &report::TestReport) -> String {
 
     md
 }
+
+fn generate_junit_report(report: &report::TestReport) -> String {
+    let mut xml = String::new();
+
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(&format!(
+        "<testsuites name=\"rmesh-test\" tests=\"{tests}\" failures=\"{failures}\" skipped=\"{skipped}\" time=\"{time:.3}\">\n",
+        tests = report.tests_run,
+        failures = report.tests_failed,
+        skipped = report.tests_skipped,
+        time = report.duration_ms as f64 / 1000.0
+    ));
+
+    // One suite per category, in the order the categories ran
+    let mut categories: Vec<&str> = Vec::new();
+    for result in &report.test_results {
+        if !categories.contains(&result.category.as_str()) {
+            categories.push(&result.category);
+        }
+    }
+
+    for category in categories {
+        let results: Vec<&report::TestResult> = report
+            .test_results
+            .iter()
+            .filter(|r| r.category == category)
+            .collect();
+        let failures = results
+            .iter()
+            .filter(|r| r.outcome == report::TestOutcome::Fail)
+            .count();
+        let skipped = results
+            .iter()
+            .filter(|r| matches!(r.outcome, report::TestOutcome::Skip(_)))
+            .count();
+        let duration_ms: u64 = results.iter().map(|r| r.duration_ms).sum();
+
+        xml.push_str(&format!(
+            "  <testsuite name=\"{name}\" tests=\"{tests}\" failures=\"{failures}\" skipped=\"{skipped}\" time=\"{time:.3}\" timestamp=\"{timestamp}\" hostname=\"{port}\">\n",
+            name = xml_escape(category),
+            tests = results.len(),
+            time = duration_ms as f64 / 1000.0,
+            timestamp = report.timestamp.format("%Y-%m-%dT%H:%M:%S"),
+            port = xml_escape(&report.device_info.port)
+        ));
+
+        for result in results {
+            xml.push_str(&format!(
+                "    <testcase classname=\"rmesh-test.{category}\" name=\"{name}\" time=\"{time:.3}\"",
+                category = xml_escape(category),
+                name = xml_escape(&result.name),
+                time = result.duration_ms as f64 / 1000.0
+            ));
+
+            match &result.outcome {
+                report::TestOutcome::Pass => xml.push_str(" />\n"),
+                report::TestOutcome::Skip(reason) => {
+                    xml.push_str(">\n");
+                    xml.push_str(&format!(
+                        "      <skipped message=\"{reason}\" />\n",
+                        reason = xml_escape(reason)
+                    ));
+                    xml.push_str("    </testcase>\n");
+                }
+                report::TestOutcome::Fail => {
+                    let error = result.error.as_deref().unwrap_or("Unknown error");
+                    let message = error.lines().next().unwrap_or_default();
+                    xml.push_str(">\n");
+                    xml.push_str(&format!(
+                        "      <failure message=\"{message}\">{error}</failure>\n",
+                        message = xml_escape(message),
+                        error = xml_escape(error)
+                    ));
+                    xml.push_str("    </testcase>\n");
+                }
+            }
+        }
+
+        xml.push_str("  </testsuite>\n");
+    }
+
+    xml.push_str("</testsuites>\n");
+    xml
+}
+
+/// Escape text for use in XML attributes and element content
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // Control characters other than whitespace are not allowed in XML 1.0
+            c if c.is_control() && !matches!(c, '\n' | '\r' | '\t') => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,122 @@
+use anyhow::{Context, Result};
+use rmesh_core::firmware::compare_versions;
+use rmesh_core::state::DeviceState;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::path::Path;
+
+/// Expected device provisioning, checked as a fleet acceptance test
+///
+/// Profiles are TOML files:
+///
+/// ```toml
+/// region = "US"
+/// role = "ROUTER"
+/// channel_count = 2
+/// firmware_min = "2.5.0"
+/// ```
+///
+/// Unset keys are not checked; unknown ones are rejected so a typo doesn't
+/// silently skip a check.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DeviceProfile {
+    pub region: Option<String>,
+    pub role: Option<String>,
+    pub channel_count: Option<usize>,
+    pub firmware_min: Option<String>,
+}
+
+/// A single way the device differs from its profile
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProfileMismatch {
+    pub field: &'static str,
+    pub expected: String,
+    pub actual: Option<String>,
+}
+
+impl DeviceProfile {
+    /// Load a profile from a TOML file
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read profile {path}", path = path.display()))?;
+        Self::parse(&text).with_context(|| format!("Invalid profile {path}", path = path.display()))
+    }
+
+    /// Parse a profile from TOML text
+    pub fn parse(text: &str) -> Result<Self> {
+        Ok(toml::from_str(text)?)
+    }
+
+    /// Compare the device state against the profile
+    pub fn check(&self, state: &DeviceState) -> Vec<ProfileMismatch> {
+        let mut mismatches = Vec::new();
+
+        if let Some(expected) = &self.region {
+            let actual = state.lora_config.as_ref().map(|c| c.region.clone());
+            if actual
+                .as_deref()
+                .is_none_or(|a| !a.eq_ignore_ascii_case(expected))
+            {
+                mismatches.push(ProfileMismatch {
+                    field: "region",
+                    expected: expected.clone(),
+                    actual,
+                });
+            }
+        }
+
+        if let Some(expected) = &self.role {
+            let actual = state.device_config.as_ref().map(|c| c.role.clone());
+            if actual
+                .as_deref()
+                .is_none_or(|a| normalize_enum_name(a) != normalize_enum_name(expected))
+            {
+                mismatches.push(ProfileMismatch {
+                    field: "role",
+                    expected: expected.clone(),
+                    actual,
+                });
+            }
+        }
+
+        if let Some(expected) = self.channel_count {
+            let actual = state
+                .channels
+                .iter()
+                .filter(|c| c.role != "Disabled")
+                .count();
+            if actual != expected {
+                mismatches.push(ProfileMismatch {
+                    field: "channel_count",
+                    expected: expected.to_string(),
+                    actual: Some(actual.to_string()),
+                });
+            }
+        }
+
+        if let Some(minimum) = &self.firmware_min {
+            let actual = state.metadata.as_ref().map(|m| m.firmware_version.clone());
+            if actual
+                .as_deref()
+                .is_none_or(|a| compare_versions(a, minimum) == Ordering::Less)
+            {
+                mismatches.push(ProfileMismatch {
+                    field: "firmware_min",
+                    expected: format!(">= {minimum}"),
+                    actual,
+                });
+            }
+        }
+
+        mismatches
+    }
+}
+
+/// Enum names are reported as "ClientMute" but users write "CLIENT_MUTE"
+fn normalize_enum_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| *c != '_' && *c != '-')
+        .collect::<String>()
+        .to_lowercase()
+}
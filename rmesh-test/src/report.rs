@@ -1,12 +1,25 @@
 use chrono::{DateTime, Utc};
+use rmesh_core::connection::{LinkQuality, QualityRating};
 use serde::{Deserialize, Serialize};
 
+use crate::baseline::BaselineComparison;
+
+/// Outcome of a single test
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", content = "reason", rename_all = "snake_case")]
+pub enum TestOutcome {
+    Pass,
+    Fail,
+    /// The test could not run, e.g. because required hardware is missing
+    Skip(String),
+}
+
 /// Result of a single test
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TestResult {
     pub name: String,
     pub category: String,
-    pub passed: bool,
+    pub outcome: TestOutcome,
     pub duration_ms: u64,
     pub error: Option<String>,
     pub details: serde_json::Value,
@@ -52,6 +65,9 @@ pub struct TestReport {
     pub test_results: Vec<TestResult>,
     pub category_stats: Vec<CategoryStats>,
     pub recommendations: Vec<String>,
+    /// Differences from the `--baseline` report, when one was given
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub baseline: Option<BaselineComparison>,
 }
 
 /// Connection quality metrics
@@ -94,19 +110,27 @@ impl TestReport {
             test_results: Vec::new(),
             category_stats: Vec::new(),
             recommendations: Vec::new(),
+            baseline: None,
         }
     }
 
     pub fn add_test_result(&mut self, result: TestResult) {
         self.tests_run += 1;
-        if result.passed {
-            self.tests_passed += 1;
-        } else {
-            self.tests_failed += 1;
+        match result.outcome {
+            TestOutcome::Pass => self.tests_passed += 1,
+            TestOutcome::Fail => self.tests_failed += 1,
+            TestOutcome::Skip(_) => self.tests_skipped += 1,
         }
         self.test_results.push(result);
     }
 
+    /// Take the error and success counts measured by the connection
+    pub fn set_link_quality(&mut self, quality: &LinkQuality) {
+        self.connection_quality.packet_errors = quality.errors() as usize;
+        self.connection_quality.successful_packets = quality.successes() as usize;
+        self.connection_quality.average_response_time_ms = quality.average_response_time_ms;
+    }
+
     pub fn calculate_stats(&mut self) {
         let mut category_map: std::collections::HashMap<String, CategoryStats> =
             std::collections::HashMap::new();
@@ -126,10 +150,10 @@ impl TestReport {
             stat.total += 1;
             stat.duration_ms += result.duration_ms;
 
-            if result.passed {
-                stat.passed += 1;
-            } else {
-                stat.failed += 1;
+            match result.outcome {
+                TestOutcome::Pass => stat.passed += 1,
+                TestOutcome::Fail => stat.failed += 1,
+                TestOutcome::Skip(_) => stat.skipped += 1,
             }
         }
 
@@ -144,14 +168,8 @@ impl TestReport {
             self.connection_quality.error_rate =
                 self.connection_quality.packet_errors as f64 / total as f64;
 
-            self.connection_quality.connection_stability = match self.connection_quality.error_rate
-            {
-                r if r < 0.01 => "Excellent",
-                r if r < 0.05 => "Good",
-                r if r < 0.10 => "Fair",
-                _ => "Poor",
-            }
-            .to_string();
+            self.connection_quality.connection_stability =
+                QualityRating::from_error_rate(self.connection_quality.error_rate).to_string();
         }
 
         // Generate recommendations
@@ -235,6 +253,17 @@ impl TestReport {
                 "".normal()
             }
         );
+        if self.tests_skipped > 0 {
+            println!(
+                "  Skipped: {skipped} {percentage}",
+                skipped = self.tests_skipped,
+                percentage = format!(
+                    "({percent}%)",
+                    percent = self.tests_skipped * 100 / self.tests_run.max(1)
+                )
+                .yellow()
+            );
+        }
 
         println!("\n{section}", section = "Connection Quality:".bold());
         println!(
@@ -252,6 +281,10 @@ impl TestReport {
             }
         );
 
+        if let Some(baseline) = &self.baseline {
+            baseline.print_summary();
+        }
+
         if !self.recommendations.is_empty() {
             println!("\n{section}", section = "Recommendations:".bold().yellow());
             for rec in &self.recommendations {
@@ -1,60 +1,89 @@
+use crate::profile::DeviceProfile;
 use anyhow::Result;
 use chrono::Utc;
 use colored::*;
 use indicatif::{ProgressBar, ProgressStyle};
 use rmesh_core::ConnectionManager;
+use serde_json::{Value, json};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
-use crate::report::{TestReport, TestResult};
-use crate::tests::{TestCategory, TestContext};
+use crate::report::{TestOutcome, TestReport, TestResult};
+use crate::stream::StreamWriter;
+use crate::tests::{SkipTest, Test, TestCategory, TestContext};
 
 pub struct TestRunner {
     connection: ConnectionManager,
+    peer_connection: Option<ConnectionManager>,
+    profile: Option<DeviceProfile>,
+    /// Run tests that write settings
+    destructive: bool,
+    gps_fix_timeout: Duration,
     report: TestReport,
     verbose: bool,
     non_interactive: bool,
     categories: Vec<TestCategory>,
     progress: Option<ProgressBar>,
+    /// Receives each result as soon as it completes
+    stream: Option<Arc<StreamWriter>>,
 }
 
 impl TestRunner {
-    pub async fn new(port: String, verbose: bool, non_interactive: bool) -> Result<Self> {
-        eprintln!(
-            "{arrow} Connecting to device on {port}...",
-            arrow = "→".cyan(),
-            port = port.bold()
-        );
-
-        let mut connection = ConnectionManager::new(
-            Some(port.clone()),
-            None, // No BLE support in test
-            Duration::from_secs(30),
-        )
-        .await?;
+    pub async fn new(
+        port: String,
+        peer_port: Option<String>,
+        profile: Option<DeviceProfile>,
+        destructive: bool,
+        gps_fix_timeout: Duration,
+        verbose: bool,
+        non_interactive: bool,
+    ) -> Result<Self> {
+        let connection = connect_device(&port).await?;
 
-        connection.connect().await?;
+        // A second radio enables the over-the-air messaging tests
+        let peer_connection = match &peer_port {
+            Some(peer_port) => Some(connect_device(peer_port).await?),
+            None => None,
+        };
 
-        eprintln!("{check} Connected successfully!", check = "✓".green());
+        let mut categories = vec![
+            TestCategory::Connection,
+            TestCategory::Device,
+            TestCategory::Messaging,
+            TestCategory::Configuration,
+            TestCategory::Channels,
+            TestCategory::Position,
+            TestCategory::Mesh,
+            TestCategory::Telemetry,
+        ];
+        if profile.is_some() {
+            categories.push(TestCategory::Profile);
+        }
+        // Settings are only written when asked for; default runs stay read-only
+        if destructive {
+            categories.push(TestCategory::Destructive);
+        }
 
         Ok(Self {
             connection,
+            peer_connection,
+            profile,
+            destructive,
+            gps_fix_timeout,
             report: TestReport::new(port),
             verbose,
             non_interactive,
-            categories: vec![
-                TestCategory::Connection,
-                TestCategory::Device,
-                TestCategory::Messaging,
-                TestCategory::Configuration,
-                TestCategory::Channels,
-                TestCategory::Position,
-                TestCategory::Mesh,
-                TestCategory::Telemetry,
-            ],
+            categories,
             progress: None,
+            stream: None,
         })
     }
 
+    /// Stream the run header and every result as they become available
+    pub fn set_stream(&mut self, stream: Arc<StreamWriter>) {
+        self.stream = Some(stream);
+    }
+
     pub async fn run_all_tests(&mut self) -> Result<TestReport> {
         let start_time = Instant::now();
 
@@ -85,6 +114,14 @@ impl TestRunner {
             );
         }
 
+        self.stream_record(
+            "run",
+            &json!({
+                "timestamp": self.report.timestamp,
+                "device_info": self.report.device_info,
+            }),
+        );
+
         // Run tests for each category
         for category in self.categories.clone() {
             self.run_category_tests(category).await?;
@@ -92,6 +129,8 @@ impl TestRunner {
 
         // Finalize report
         self.report.duration_ms = start_time.elapsed().as_millis() as u64;
+        self.report
+            .set_link_quality(&self.connection.counters().quality());
         self.report.calculate_stats();
 
         if let Some(pb) = &self.progress {
@@ -114,6 +153,12 @@ impl TestRunner {
 
         let tests = category.get_tests();
 
+        // Category setup decides whether its tests can run at all
+        let setup_failure = match category.setup(&mut self.context()).await {
+            Ok(()) => None,
+            Err(e) => Some(classify_error(&e.context("Category setup failed"))),
+        };
+
         for test in tests {
             let test_start = Instant::now();
 
@@ -128,23 +173,15 @@ impl TestRunner {
                 );
             }
 
-            let mut context = TestContext::new(&mut self.connection, self.verbose);
-            let (passed, details, error) = match (test.run_fn)(&mut context).await {
-                Ok(details) => (true, details, None),
-                Err(e) => {
-                    let error_msg = format!("{e:?}");
-                    (
-                        false,
-                        serde_json::json!({"error": &error_msg}),
-                        Some(error_msg),
-                    )
-                }
+            let (outcome, details, error) = match &setup_failure {
+                Some(failure) => failure.clone(),
+                None => self.run_test(&test).await,
             };
 
             let result = TestResult {
                 name: test.name.to_string(),
                 category: category_name.clone(),
-                passed,
+                outcome,
                 duration_ms: test_start.elapsed().as_millis() as u64,
                 error,
                 details,
@@ -153,15 +190,20 @@ impl TestRunner {
 
             // Always show results in non-interactive mode or when verbose
             if self.verbose || self.non_interactive {
-                if result.passed {
-                    eprintln!(
+                match &result.outcome {
+                    TestOutcome::Pass => eprintln!(
                         "  {check} {name} ({duration}ms)",
                         check = "✓".green(),
                         name = test.name,
                         duration = result.duration_ms
-                    );
-                } else {
-                    eprintln!(
+                    ),
+                    TestOutcome::Skip(reason) => eprintln!(
+                        "  {skip} {name} - skipped: {reason}",
+                        skip = "○".yellow(),
+                        name = test.name,
+                        reason = reason.yellow()
+                    ),
+                    TestOutcome::Fail => eprintln!(
                         "  {cross} {name} - {error} ({duration}ms)",
                         cross = "✗".red(),
                         name = test.name,
@@ -171,10 +213,11 @@ impl TestRunner {
                             .unwrap_or(&"Unknown error".to_string())
                             .red(),
                         duration = result.duration_ms
-                    );
+                    ),
                 }
             }
 
+            self.stream_record("result", &result);
             self.report.add_test_result(result);
 
             // Update progress bar only if it exists
@@ -183,9 +226,63 @@ impl TestRunner {
             }
         }
 
+        // Only tear down categories that were set up
+        if setup_failure.is_none()
+            && let Err(e) = category.teardown(&mut self.context()).await
+        {
+            eprintln!(
+                "  {warning} {category} teardown failed: {e}",
+                warning = "⚠".yellow(),
+                category = category_name
+            );
+        }
+
         Ok(())
     }
 
+    /// Run a single test after checking its prerequisites
+    async fn run_test(&mut self, test: &Test) -> (TestOutcome, Value, Option<String>) {
+        let mut context = self.context();
+
+        for prerequisite in test.prerequisites {
+            if let Some(reason) = prerequisite.unmet_reason(&context).await {
+                return (
+                    TestOutcome::Skip(reason.clone()),
+                    json!({"skipped": reason}),
+                    None,
+                );
+            }
+        }
+
+        match (test.run_fn)(&mut context).await {
+            Ok(details) => (TestOutcome::Pass, details, None),
+            Err(e) => classify_error(&e),
+        }
+    }
+
+    fn context(&mut self) -> TestContext<'_> {
+        TestContext::new(
+            &mut self.connection,
+            self.peer_connection.as_mut(),
+            self.profile.as_ref(),
+            self.destructive,
+            self.gps_fix_timeout,
+            self.verbose,
+        )
+    }
+
+    /// Write a record to the stream, warning instead of aborting the run
+    fn stream_record<T: serde::Serialize>(&self, kind: &str, data: &T) {
+        if let Some(stream) = &self.stream
+            && let Err(e) = stream.write_record(kind, &self.report.test_id, data)
+        {
+            eprintln!(
+                "  {warning} Failed to stream {kind} record: {e}",
+                warning = "⚠".yellow()
+            );
+        }
+    }
+
     fn estimate_total_tests(&self) -> usize {
         self.categories.iter().map(|c| c.get_tests().len()).sum()
     }
@@ -196,6 +293,16 @@ impl TestRunner {
             .filter_map(|name| TestCategory::from_str(name))
             .collect();
 
+        // A provided profile is always checked
+        if self.profile.is_some()
+            && !self
+                .categories
+                .iter()
+                .any(|c| matches!(c, TestCategory::Profile))
+        {
+            self.categories.push(TestCategory::Profile);
+        }
+
         anyhow::ensure!(
             !self.categories.is_empty(),
             "No valid test categories specified"
@@ -204,3 +311,43 @@ impl TestRunner {
         self.run_all_tests().await
     }
 }
+
+async fn connect_device(port: &str) -> Result<ConnectionManager> {
+    eprintln!(
+        "{arrow} Connecting to device on {port}...",
+        arrow = "→".cyan(),
+        port = port.bold()
+    );
+
+    let mut connection = ConnectionManager::new(
+        Some(port.to_string()),
+        None, // No BLE support in test
+        Duration::from_secs(30),
+    )
+    .await?;
+
+    connection.connect().await?;
+
+    eprintln!("{check} Connected successfully!", check = "✓".green());
+
+    Ok(connection)
+}
+
+/// Turn a test error into a failure, or a skip when the test raised [`SkipTest`]
+fn classify_error(e: &anyhow::Error) -> (TestOutcome, Value, Option<String>) {
+    if let Some(skip) = e.downcast_ref::<SkipTest>() {
+        let reason = skip.0.clone();
+        return (
+            TestOutcome::Skip(reason.clone()),
+            json!({"skipped": reason}),
+            None,
+        );
+    }
+
+    let error_msg = format!("{e:?}");
+    (
+        TestOutcome::Fail,
+        json!({"error": &error_msg}),
+        Some(error_msg),
+    )
+}
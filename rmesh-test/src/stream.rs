@@ -0,0 +1,110 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::report::TestReport;
+
+/// Where streamed records go
+enum Target {
+    File(File),
+    Stdout,
+}
+
+/// Writes report records as JSON lines the moment they are available
+///
+/// Each record is written with a single call and synced to disk, so a crash
+/// or a bricked device mid-run keeps every result completed before it. The
+/// file is opened for appending, and the lock keeps lines whole when several
+/// tasks write at once.
+pub struct StreamWriter {
+    target: Mutex<Target>,
+}
+
+/// One JSON line: the record kind plus its fields
+#[derive(Serialize)]
+struct Record<'a, T: Serialize> {
+    kind: &'a str,
+    test_id: &'a str,
+    #[serde(flatten)]
+    data: &'a T,
+}
+
+/// Totals written as the last record of a run
+#[derive(Serialize)]
+struct Summary<'a> {
+    tests_run: usize,
+    tests_passed: usize,
+    tests_failed: usize,
+    tests_skipped: usize,
+    duration_ms: u64,
+    recommendations: &'a [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    baseline: Option<&'a crate::baseline::BaselineComparison>,
+}
+
+impl StreamWriter {
+    /// Stream to a file, appending to it, or to stdout when no path is given
+    pub fn create(path: Option<&Path>) -> Result<Self> {
+        let target = match path {
+            Some(path) => Target::File(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .with_context(|| format!("Failed to open {path}", path = path.display()))?,
+            ),
+            None => Target::Stdout,
+        };
+        Ok(Self {
+            target: Mutex::new(target),
+        })
+    }
+
+    /// Write one record of the run identified by `test_id`
+    pub fn write_record<T: Serialize>(&self, kind: &str, test_id: &str, data: &T) -> Result<()> {
+        let mut line = serde_json::to_string(&Record {
+            kind,
+            test_id,
+            data,
+        })?;
+        line.push('\n');
+
+        let mut target = self
+            .target
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Report stream lock poisoned"))?;
+        match &mut *target {
+            Target::File(file) => {
+                file.write_all(line.as_bytes())
+                    .context("Failed to write report record")?;
+                file.sync_data().context("Failed to sync report file")?;
+            }
+            Target::Stdout => {
+                let mut stdout = std::io::stdout().lock();
+                stdout.write_all(line.as_bytes())?;
+                stdout.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the closing record with the totals of a finished report
+    pub fn write_summary(&self, report: &TestReport) -> Result<()> {
+        self.write_record(
+            "summary",
+            &report.test_id,
+            &Summary {
+                tests_run: report.tests_run,
+                tests_passed: report.tests_passed,
+                tests_failed: report.tests_failed,
+                tests_skipped: report.tests_skipped,
+                duration_ms: report.duration_ms,
+                recommendations: &report.recommendations,
+                baseline: report.baseline.as_ref(),
+            },
+        )
+    }
+}
@@ -26,7 +26,7 @@ async fn test_read_config(ctx: &mut TestContext<'_>) -> Result<Value> {
     Ok(json!({
         "config_readable": true,
         "test_key": "lora.region",
-        "value": config_value,
+        "value": config_value.value,
     }))
 }
 
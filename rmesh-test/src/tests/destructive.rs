@@ -0,0 +1,144 @@
+use anyhow::{Context, Result, ensure};
+use rmesh_core::channel::{CHANNEL_REQUEST_TIMEOUT, get_channel};
+use rmesh_core::channel_set::{ChannelRole, ChannelSet, ChannelSlot, import_channels};
+use rmesh_core::snapshot::{SettingsSnapshot, restore_snapshot, take_snapshot};
+use serde_json::{Value, json};
+use std::time::Duration;
+
+use crate::define_test;
+use crate::tests::{Prerequisite, SkipTest, Test, TestContext};
+
+/// Time the device gets to apply a write before it is read back
+const SETTLE_DELAY: Duration = Duration::from_secs(2);
+
+/// Name of the channel written to a free slot
+const TEST_CHANNEL_NAME: &str = "rmeshtest";
+
+pub fn get_tests() -> Vec<Test> {
+    vec![
+        define_test!(
+            "Config Write Round Trip",
+            "Write a network setting, read it back and restore the original",
+            test_config_round_trip,
+            requires = [Prerequisite::Destructive]
+        ),
+        define_test!(
+            "Channel Write Round Trip",
+            "Write a channel to a free slot, read it back and restore the original",
+            test_channel_round_trip,
+            requires = [Prerequisite::Destructive]
+        ),
+    ]
+}
+
+async fn test_config_round_trip(ctx: &mut TestContext<'_>) -> Result<Value> {
+    let snapshot = take_snapshot(ctx.connection).await?;
+    let original = snapshot
+        .network
+        .as_ref()
+        .map(|network| network.ntp_server.clone())
+        .ok_or_else(|| SkipTest("Device has no network settings".to_string()))?;
+
+    let written = format!(
+        "{id}.rmesh.test",
+        id = &uuid::Uuid::new_v4().simple().to_string()[..8]
+    );
+    let result = write_ntp_server(ctx, &written).await;
+    restore(ctx, &snapshot).await?;
+    let read_back = result?;
+
+    let restored = rmesh_core::config::get_config_value(ctx.connection, "network.ntp_server")
+        .await?
+        .value;
+    ensure!(
+        restored == json!(original),
+        "Restored ntp_server is {restored}, expected {original:?}"
+    );
+
+    Ok(json!({
+        "key": "network.ntp_server",
+        "written": written,
+        "read_back": read_back,
+        "restored": restored,
+    }))
+}
+
+/// Write the NTP server and check the device reports it back
+async fn write_ntp_server(ctx: &mut TestContext<'_>, value: &str) -> Result<Value> {
+    rmesh_core::config::set_config_value(ctx.connection, "network.ntp_server", value).await?;
+    tokio::time::sleep(SETTLE_DELAY).await;
+
+    let read_back = rmesh_core::config::get_config_value(ctx.connection, "network.ntp_server")
+        .await?
+        .value;
+    ensure!(
+        read_back == json!(value),
+        "Wrote ntp_server {value:?} but the device reports {read_back}"
+    );
+    Ok(read_back)
+}
+
+async fn test_channel_round_trip(ctx: &mut TestContext<'_>) -> Result<Value> {
+    let snapshot = take_snapshot(ctx.connection).await?;
+    let index = snapshot
+        .channels
+        .channels
+        .iter()
+        .find(|slot| slot.role == ChannelRole::Disabled)
+        .map(|slot| slot.index)
+        .ok_or_else(|| SkipTest("No free channel slot to write to".to_string()))?;
+
+    let result = write_channel(ctx, index).await;
+    restore(ctx, &snapshot).await?;
+    result?;
+
+    let restored = get_channel(ctx.connection, index, CHANNEL_REQUEST_TIMEOUT).await?;
+    ensure!(
+        restored.role == "Disabled",
+        "Channel {index} is {role} after restoring, expected Disabled",
+        role = restored.role
+    );
+
+    Ok(json!({
+        "index": index,
+        "written": TEST_CHANNEL_NAME,
+        "restored_role": restored.role,
+    }))
+}
+
+/// Write a secondary channel to the slot and check the device reports it back
+async fn write_channel(ctx: &mut TestContext<'_>, index: u32) -> Result<()> {
+    let set = ChannelSet {
+        channels: vec![ChannelSlot {
+            index,
+            role: ChannelRole::Secondary,
+            name: TEST_CHANNEL_NAME.to_string(),
+            // The default key, so nothing secret is written
+            psk: Some("01".to_string()),
+            uplink_enabled: false,
+            downlink_enabled: false,
+            position_precision: 0,
+        }],
+    };
+    import_channels(ctx.connection, &set).await?;
+    tokio::time::sleep(SETTLE_DELAY).await;
+
+    let channel = get_channel(ctx.connection, index, CHANNEL_REQUEST_TIMEOUT).await?;
+    ensure!(
+        channel.name == TEST_CHANNEL_NAME && channel.role == "Secondary",
+        "Wrote secondary channel {TEST_CHANNEL_NAME:?} to slot {index} but the device reports \
+         {role} channel {name:?}",
+        role = channel.role,
+        name = channel.name
+    );
+    Ok(())
+}
+
+/// Put the snapshot back, whatever the outcome of the test
+async fn restore(ctx: &mut TestContext<'_>, snapshot: &SettingsSnapshot) -> Result<()> {
+    restore_snapshot(ctx.connection, snapshot)
+        .await
+        .context("Failed to restore the original settings; check the device by hand")?;
+    tokio::time::sleep(SETTLE_DELAY).await;
+    Ok(())
+}
@@ -1,8 +1,11 @@
 use anyhow::{Context, Result};
+use rmesh_core::clock::{MAX_CLOCK_DRIFT_SECS, measure_clock};
+use rmesh_core::node_id::NodeId;
 use serde_json::{Value, json};
+use std::time::Duration;
 
 use crate::define_test;
-use crate::tests::{Test, TestContext};
+use crate::tests::{SkipTest, Test, TestContext};
 
 pub fn get_tests() -> Vec<Test> {
     vec![
@@ -26,6 +29,11 @@ pub fn get_tests() -> Vec<Test> {
             "Verify node ID and configuration",
             test_node_config
         ),
+        define_test!(
+            "Clock Drift",
+            "Compare the device clock with the host clock",
+            test_clock_drift
+        ),
     ]
 }
 
@@ -136,8 +144,28 @@ async fn test_node_config(ctx: &mut TestContext<'_>) -> Result<Value> {
 
     Ok(json!({
         "node_id": my_info.node_id,
-        "node_num": format!("{num:08x}", num = my_info.node_num),
+        "node_num": NodeId(my_info.node_num).to_string(),
         "device_id": my_info.device_id,
         "valid": true,
     }))
 }
+
+async fn test_clock_drift(ctx: &mut TestContext<'_>) -> Result<Value> {
+    let report = measure_clock(ctx.connection, Duration::from_secs(10)).await?;
+
+    // Without GPS or a phone the device never learns the time
+    let drift = report.drift_secs.ok_or_else(|| {
+        SkipTest("Device has no valid time; set it with `rmesh device clock --sync`".to_string())
+    })?;
+    anyhow::ensure!(
+        drift.unsigned_abs() <= MAX_CLOCK_DRIFT_SECS,
+        "Device clock is {drift:+}s off the host clock"
+    );
+
+    Ok(json!({
+        "device_time": report.device_time,
+        "host_time": report.host_time,
+        "drift_secs": drift,
+        "round_trip_ms": report.round_trip_ms,
+    }))
+}
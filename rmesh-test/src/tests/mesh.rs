@@ -2,7 +2,7 @@ use anyhow::Result;
 use serde_json::{Value, json};
 
 use crate::define_test;
-use crate::tests::{Test, TestContext};
+use crate::tests::{Prerequisite, Test, TestContext};
 
 pub fn get_tests() -> Vec<Test> {
     vec![
@@ -14,7 +14,8 @@ pub fn get_tests() -> Vec<Test> {
         define_test!(
             "Neighbor Detection",
             "Find direct mesh neighbors",
-            test_neighbor_detection
+            test_neighbor_detection,
+            requires = [Prerequisite::RemoteNode]
         ),
         define_test!(
             "Network Stats",
@@ -61,5 +62,6 @@ async fn test_network_stats(ctx: &mut TestContext<'_>) -> Result<Value> {
         "average_snr": stats.average_snr,
         "average_rssi": stats.average_rssi,
         "mesh_health": stats.mesh_health,
+        "duplicate_packets": stats.duplicate_packets,
     }))
 }
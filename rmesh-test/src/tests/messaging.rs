@@ -1,9 +1,11 @@
-use anyhow::Result;
+use anyhow::{Context, Result, ensure};
+use rmesh_core::message::{AckOptions, MessageFilter};
+use rmesh_core::node_id::NodeId;
 use serde_json::{Value, json};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use crate::define_test;
-use crate::tests::{Test, TestContext};
+use crate::tests::{Prerequisite, SkipTest, Test, TestContext};
 
 pub fn get_tests() -> Vec<Test> {
     vec![
@@ -17,9 +19,36 @@ pub fn get_tests() -> Vec<Test> {
             "Test message queue functionality",
             test_message_queue
         ),
+        define_test!(
+            "Peer Message Exchange",
+            "Send direct messages over RF to the peer device and verify delivery and ACKs",
+            test_peer_message_exchange,
+            requires = [Prerequisite::PeerDevice]
+        ),
     ]
 }
 
+/// Messaging tests address packets from the local node, so it must be known
+pub async fn setup(ctx: &mut TestContext<'_>) -> Result<()> {
+    let state = ctx.connection.get_device_state().await;
+    if state.my_node_info.is_none() {
+        return Err(SkipTest("Local node information not available".to_string()).into());
+    }
+    Ok(())
+}
+
+/// Let queued messages leave the radio before other categories run
+pub async fn teardown(_ctx: &mut TestContext<'_>) -> Result<()> {
+    tokio::time::sleep(Duration::from_secs(2)).await;
+    Ok(())
+}
+
+/// Number of messages sent to the peer device
+const PEER_MESSAGE_COUNT: usize = 5;
+
+/// Seconds to wait for the ACK of each message
+const PEER_ACK_TIMEOUT_SECS: u64 = 30;
+
 async fn test_send_message(ctx: &mut TestContext<'_>) -> Result<Value> {
     let start = Instant::now();
 
@@ -72,3 +101,100 @@ async fn test_message_queue(ctx: &mut TestContext<'_>) -> Result<Value> {
         "queue_working": true,
     }))
 }
+
+async fn test_peer_message_exchange(ctx: &mut TestContext<'_>) -> Result<Value> {
+    let peer = ctx
+        .peer
+        .as_deref_mut()
+        .context("No peer device configured")?;
+
+    let local_node = ctx
+        .connection
+        .get_device_state()
+        .await
+        .my_node_info
+        .map(|info| info.node_num)
+        .context("Local node information not available")?;
+    let peer_node = peer
+        .get_device_state()
+        .await
+        .my_node_info
+        .map(|info| info.node_num)
+        .context("Peer node information not available")?;
+
+    // Only count messages sent by the local device
+    let mut receiver = peer.take_packet_receiver()?;
+    let filter = MessageFilter {
+        from: Some(local_node),
+        to: Some(peer_node),
+        ..Default::default()
+    };
+
+    let mut acked = 0;
+    let mut received = 0;
+    let mut rtts_ms = Vec::new();
+
+    for i in 0..PEER_MESSAGE_COUNT {
+        let text = format!("rmesh-test peer {i}");
+        let start = Instant::now();
+
+        let report = ctx
+            .connection
+            .send_text_with_ack(
+                text.clone(),
+                peer_node,
+                0,
+                AckOptions {
+                    timeout: Duration::from_secs(PEER_ACK_TIMEOUT_SECS),
+                    retries: 0,
+                },
+            )
+            .await?;
+        if report.acknowledged {
+            acked += 1;
+            rtts_ms.push(start.elapsed().as_millis() as u64);
+        }
+
+        // The message normally arrives before its ACK, so a short wait is enough
+        let delivered = tokio::time::timeout(Duration::from_secs(5), async {
+            // Only the text is compared, so channel names need not be resolved
+            while let Some(msg) =
+                rmesh_core::message::next_message(&mut receiver, &filter, &[]).await
+            {
+                if msg.text == text {
+                    return true;
+                }
+            }
+            false
+        })
+        .await
+        .unwrap_or(false);
+        if delivered {
+            received += 1;
+        }
+    }
+
+    ensure!(
+        received > 0,
+        "Peer {peer_node} did not receive any of {PEER_MESSAGE_COUNT} messages",
+        peer_node = NodeId(peer_node)
+    );
+
+    let average_rtt_ms = if rtts_ms.is_empty() {
+        None
+    } else {
+        Some(rtts_ms.iter().sum::<u64>() / rtts_ms.len() as u64)
+    };
+
+    Ok(json!({
+        "local_node": NodeId(local_node).to_string(),
+        "peer_node": NodeId(peer_node).to_string(),
+        "messages_sent": PEER_MESSAGE_COUNT,
+        "messages_received": received,
+        "messages_acked": acked,
+        "loss_percent": (PEER_MESSAGE_COUNT - received) as f64 / PEER_MESSAGE_COUNT as f64 * 100.0,
+        "rtt_min_ms": rtts_ms.iter().min(),
+        "rtt_avg_ms": average_rtt_ms,
+        "rtt_max_ms": rtts_ms.iter().max(),
+    }))
+}
@@ -1,29 +1,52 @@
 pub mod channels;
 pub mod config;
 pub mod connection;
+pub mod destructive;
 pub mod device;
 pub mod mesh;
 pub mod messaging;
 pub mod position;
+pub mod profile;
 pub mod telemetry;
 
+use crate::profile::DeviceProfile;
 use anyhow::Result;
 use rmesh_core::ConnectionManager;
 use serde_json::Value;
 use std::future::Future;
 use std::pin::Pin;
+use std::time::Duration;
 
 /// Test context passed to all test functions
 pub struct TestContext<'a> {
     pub connection: &'a mut ConnectionManager,
+    /// Second radio used for over-the-air tests, if one was given
+    pub peer: Option<&'a mut ConnectionManager>,
+    /// Expected provisioning the device is checked against
+    pub profile: Option<&'a DeviceProfile>,
+    /// Tests may write settings, restoring them afterwards
+    pub destructive: bool,
+    /// How long the GPS fix test waits for a fix
+    pub gps_fix_timeout: Duration,
     #[allow(dead_code)]
     pub verbose: bool,
 }
 
 impl<'a> TestContext<'a> {
-    pub fn new(connection: &'a mut ConnectionManager, verbose: bool) -> Self {
+    pub fn new(
+        connection: &'a mut ConnectionManager,
+        peer: Option<&'a mut ConnectionManager>,
+        profile: Option<&'a DeviceProfile>,
+        destructive: bool,
+        gps_fix_timeout: Duration,
+        verbose: bool,
+    ) -> Self {
         Self {
             connection,
+            peer,
+            profile,
+            destructive,
+            gps_fix_timeout,
             verbose,
         }
     }
@@ -41,9 +64,67 @@ pub struct Test {
     pub name: &'static str,
     #[allow(dead_code)]
     pub description: &'static str,
+    /// Conditions checked before running; unmet ones skip the test
+    pub prerequisites: &'static [Prerequisite],
     pub run_fn: TestFn,
 }
 
+/// Error returned by a test or category setup to be reported as skipped
+#[derive(Debug, thiserror::Error)]
+#[error("Skipped: {0}")]
+pub struct SkipTest(pub String);
+
+/// Hardware or setup a test needs in order to run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Prerequisite {
+    /// The device has GPS enabled
+    Gps,
+    /// A second device was given with --peer-port
+    PeerDevice,
+    /// At least one other node is present in the node database
+    RemoteNode,
+    /// A profile was given with --expect
+    Profile,
+    /// Writing settings was allowed with --destructive
+    Destructive,
+}
+
+impl Prerequisite {
+    /// Describe why the prerequisite is not met, or None when it is
+    pub async fn unmet_reason(&self, ctx: &TestContext<'_>) -> Option<String> {
+        match self {
+            Self::Gps => {
+                let state = ctx.connection.get_device_state().await;
+                match &state.position_config {
+                    Some(config) if config.gps_enabled || config.gps_mode == "Enabled" => None,
+                    Some(config) => Some(format!(
+                        "GPS not enabled (mode: {mode})",
+                        mode = config.gps_mode
+                    )),
+                    None => Some("Position configuration not available".to_string()),
+                }
+            }
+            Self::PeerDevice => ctx
+                .peer
+                .is_none()
+                .then(|| "No peer device configured (use --peer-port)".to_string()),
+            Self::RemoteNode => {
+                let state = ctx.connection.get_device_state().await;
+                let my_node_num = state.my_node_info.as_ref().map(|info| info.node_num);
+                let has_remote = state.nodes.keys().any(|num| Some(*num) != my_node_num);
+                (!has_remote).then(|| "No other nodes in the mesh".to_string())
+            }
+            Self::Profile => ctx
+                .profile
+                .is_none()
+                .then(|| "No profile given (use --expect profile.toml)".to_string()),
+            Self::Destructive => {
+                (!ctx.destructive).then(|| "Writes settings; enable with --destructive".to_string())
+            }
+        }
+    }
+}
+
 /// Test categories
 #[derive(Debug, Clone, Copy)]
 pub enum TestCategory {
@@ -55,6 +136,8 @@ pub enum TestCategory {
     Position,
     Mesh,
     Telemetry,
+    Profile,
+    Destructive,
 }
 
 impl TestCategory {
@@ -68,6 +151,8 @@ impl TestCategory {
             "position" | "gps" => Some(Self::Position),
             "mesh" | "network" => Some(Self::Mesh),
             "telemetry" => Some(Self::Telemetry),
+            "profile" | "expect" => Some(Self::Profile),
+            "destructive" | "write" => Some(Self::Destructive),
             _ => None,
         }
     }
@@ -82,6 +167,26 @@ impl TestCategory {
             Self::Position => position::get_tests(),
             Self::Mesh => mesh::get_tests(),
             Self::Telemetry => telemetry::get_tests(),
+            Self::Profile => profile::get_tests(),
+            Self::Destructive => destructive::get_tests(),
+        }
+    }
+
+    /// Prepare the category before its tests run
+    ///
+    /// Returning [`SkipTest`] marks every test in the category as skipped.
+    pub async fn setup(&self, ctx: &mut TestContext<'_>) -> Result<()> {
+        match self {
+            Self::Messaging => messaging::setup(ctx).await,
+            _ => Ok(()),
+        }
+    }
+
+    /// Clean up after the category's tests have run
+    pub async fn teardown(&self, ctx: &mut TestContext<'_>) -> Result<()> {
+        match self {
+            Self::Messaging => messaging::teardown(ctx).await,
+            _ => Ok(()),
         }
     }
 }
@@ -90,9 +195,13 @@ impl TestCategory {
 #[macro_export]
 macro_rules! define_test {
     ($name:expr, $desc:expr, $func:expr) => {
+        $crate::define_test!($name, $desc, $func, requires = [])
+    };
+    ($name:expr, $desc:expr, $func:expr, requires = [$($req:expr),* $(,)?]) => {
         Test {
             name: $name,
             description: $desc,
+            prerequisites: &[$($req),*],
             run_fn: Box::new(move |ctx| Box::pin($func(ctx))),
         }
     };
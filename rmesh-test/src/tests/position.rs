@@ -1,8 +1,11 @@
-use anyhow::Result;
+use anyhow::{Context, Result, bail};
+use meshtastic::Message;
+use meshtastic::protobufs;
 use serde_json::{Value, json};
+use std::time::{Duration, Instant};
 
 use crate::define_test;
-use crate::tests::{Test, TestContext};
+use crate::tests::{Prerequisite, Test, TestContext};
 
 pub fn get_tests() -> Vec<Test> {
     vec![
@@ -10,7 +13,14 @@ pub fn get_tests() -> Vec<Test> {
         define_test!(
             "Position Data",
             "Test position data retrieval",
-            test_position_data
+            test_position_data,
+            requires = [Prerequisite::Gps]
+        ),
+        define_test!(
+            "GPS Fix",
+            "Wait for a GPS fix and report satellite and DOP figures",
+            test_gps_fix,
+            requires = [Prerequisite::Gps]
         ),
     ]
 }
@@ -49,3 +59,105 @@ async fn test_position_data(ctx: &mut TestContext<'_>) -> Result<Value> {
         }))
     }
 }
+
+/// Pause between position requests while waiting for a fix
+const GPS_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+async fn test_gps_fix(ctx: &mut TestContext<'_>) -> Result<Value> {
+    let my_node_num = ctx
+        .connection
+        .get_device_state()
+        .await
+        .my_node_info
+        .map(|info| info.node_num)
+        .context("Local node information not available")?;
+
+    let start = Instant::now();
+    let deadline = tokio::time::Instant::now() + ctx.gps_fix_timeout;
+    let mut receiver = ctx.connection.take_packet_receiver()?;
+    let mut next_poll = tokio::time::Instant::now();
+    let mut positions_seen = 0;
+
+    loop {
+        // The device only broadcasts its position now and then; ask for it
+        if tokio::time::Instant::now() >= next_poll {
+            ctx.connection
+                .send_request(
+                    my_node_num,
+                    protobufs::PortNum::PositionApp,
+                    protobufs::Position::default().encode_to_vec(),
+                )
+                .await?;
+            next_poll = tokio::time::Instant::now() + GPS_POLL_INTERVAL;
+        }
+
+        let packet = match tokio::time::timeout_at(deadline.min(next_poll), receiver.recv()).await {
+            Ok(Some(packet)) => packet,
+            Ok(None) => bail!("Connection closed while waiting for a GPS fix"),
+            Err(_) if tokio::time::Instant::now() >= deadline => bail!(
+                "No GPS fix within {secs}s ({positions_seen} positions without a fix)",
+                secs = ctx.gps_fix_timeout.as_secs()
+            ),
+            Err(_) => continue,
+        };
+
+        let Some(position) = own_position(packet, my_node_num) else {
+            continue;
+        };
+        positions_seen += 1;
+        if !has_fix(&position) {
+            continue;
+        }
+
+        return Ok(json!({
+            "time_to_fix_secs": start.elapsed().as_secs(),
+            "latitude": position.latitude_i.map(|lat| f64::from(lat) / 1e7),
+            "longitude": position.longitude_i.map(|lon| f64::from(lon) / 1e7),
+            "altitude": position.altitude,
+            "fix_quality": nonzero(position.fix_quality),
+            "fix_type": nonzero(position.fix_type),
+            "sats_in_view": nonzero(position.sats_in_view),
+            // DOPs are reported in hundredths
+            "pdop": dop(position.pdop),
+            "hdop": dop(position.hdop),
+            "vdop": dop(position.vdop),
+            "positions_seen": positions_seen,
+        }));
+    }
+}
+
+/// Decode a position packet sent by the local node
+fn own_position(packet: protobufs::FromRadio, my_node_num: u32) -> Option<protobufs::Position> {
+    let protobufs::from_radio::PayloadVariant::Packet(mesh_packet) = packet.payload_variant? else {
+        return None;
+    };
+    if mesh_packet.from != my_node_num {
+        return None;
+    }
+    let protobufs::mesh_packet::PayloadVariant::Decoded(data) = mesh_packet.payload_variant? else {
+        return None;
+    };
+    if data.portnum() != protobufs::PortNum::PositionApp {
+        return None;
+    }
+    protobufs::Position::decode(data.payload.as_slice()).ok()
+}
+
+/// Whether a position carries coordinates from the receiver
+///
+/// Without a fix the firmware sends no coordinates, or zeros.
+fn has_fix(position: &protobufs::Position) -> bool {
+    let coordinates = position.latitude_i.is_some_and(|lat| lat != 0)
+        && position.longitude_i.is_some_and(|lon| lon != 0);
+    // A fix type of 0 or 1 means no fix when the firmware reports it
+    coordinates && (position.fix_type == 0 || position.fix_type >= 2)
+}
+
+/// Firmware leaves unknown figures at zero
+fn nonzero(value: u32) -> Option<u32> {
+    (value != 0).then_some(value)
+}
+
+fn dop(value: u32) -> Option<f64> {
+    nonzero(value).map(|value| f64::from(value) / 100.0)
+}
@@ -0,0 +1,44 @@
+use anyhow::{Context, Result, bail};
+use serde_json::{Value, json};
+
+use crate::define_test;
+use crate::tests::{Prerequisite, Test, TestContext};
+
+pub fn get_tests() -> Vec<Test> {
+    vec![define_test!(
+        "Provisioning Profile",
+        "Check the device against the expected profile",
+        test_provisioning_profile,
+        requires = [Prerequisite::Profile]
+    )]
+}
+
+async fn test_provisioning_profile(ctx: &mut TestContext<'_>) -> Result<Value> {
+    let profile = ctx
+        .profile
+        .context("No profile given (use --expect profile.toml)")?;
+
+    let state = ctx.connection.get_device_state().await;
+    let mismatches = profile.check(&state);
+
+    if !mismatches.is_empty() {
+        let summary = mismatches
+            .iter()
+            .map(|m| {
+                format!(
+                    "{field}: expected {expected}, got {actual}",
+                    field = m.field,
+                    expected = m.expected,
+                    actual = m.actual.as_deref().unwrap_or("unknown")
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("; ");
+        bail!("Device does not match profile: {summary}");
+    }
+
+    Ok(json!({
+        "profile": profile,
+        "matches": true,
+    }))
+}
@@ -0,0 +1,449 @@
+#[cfg(test)]
+mod profile_tests {
+    use crate::profile::DeviceProfile;
+    use anyhow::{Context, Result};
+    use rmesh_core::state::{ChannelInfo, DeviceConfig, DeviceMetadata, DeviceState, LoraConfig};
+
+    fn provisioned_state() -> DeviceState {
+        let mut state = DeviceState::new();
+        state.device_config = Some(DeviceConfig {
+            role: "ClientMute".to_string(),
+            button_gpio: 0,
+            buzzer_gpio: 0,
+            rebroadcast_mode: "All".to_string(),
+            node_info_broadcast_secs: 900,
+            tzdef: None,
+            disable_triple_click: false,
+        });
+        state.lora_config = Some(LoraConfig {
+            use_preset: true,
+            modem_preset: "LongFast".to_string(),
+            bandwidth: 0,
+            spread_factor: 0,
+            coding_rate: 0,
+            frequency_offset: 0.0,
+            region: "US".to_string(),
+            hop_limit: 3,
+            tx_enabled: true,
+            tx_power: 0,
+            channel_num: 0,
+            ignore_mqtt: false,
+            override_frequency: 0.0,
+            override_duty_cycle: false,
+            sx126x_rx_boosted_gain: false,
+        });
+        state.metadata = Some(DeviceMetadata {
+            firmware_version: "2.5.6.abc1234".to_string(),
+            device_state_version: 23,
+            hw_model: "Tbeam".to_string(),
+            role: "ClientMute".to_string(),
+            has_wifi: true,
+            has_bluetooth: true,
+            has_ethernet: false,
+        });
+        for (index, role) in ["Primary", "Secondary", "Disabled"].iter().enumerate() {
+            state.update_channel(ChannelInfo {
+                index: index as u32,
+                name: format!("ch{index}"),
+                role: role.to_string(),
+                has_psk: true,
+                settings: None,
+            });
+        }
+        state
+    }
+
+    #[test]
+    fn test_profile_parse() -> Result<()> {
+        let profile = DeviceProfile::parse(
+            "# Fleet router\nregion = \"US\" # trailing\nchannel_count = 1_0\n",
+        )?;
+        assert_eq!(
+            profile,
+            DeviceProfile {
+                region: Some("US".to_string()),
+                channel_count: Some(10),
+                ..Default::default()
+            }
+        );
+
+        // Unknown keys are rejected so typos don't silently skip checks
+        assert!(DeviceProfile::parse("regoin = \"US\"").is_err());
+        assert!(DeviceProfile::parse("region = \"US\"\nregion = \"EU868\"").is_err());
+        assert!(DeviceProfile::parse("channel_count = -1").is_err());
+        assert!(DeviceProfile::parse("region = US").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_profile_matches_device() -> Result<()> {
+        let profile = DeviceProfile::parse(
+            "region = \"us\"\nrole = \"CLIENT_MUTE\"\nchannel_count = 2\nfirmware_min = \"2.5.0\"\n",
+        )?;
+        assert!(profile.check(&provisioned_state()).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_profile_reports_mismatches() -> Result<()> {
+        let profile = DeviceProfile::parse(
+            "region = \"EU868\"\nchannel_count = 1\nfirmware_min = \"2.6\"\n",
+        )?;
+        let mismatches = profile.check(&provisioned_state());
+        let fields: Vec<&str> = mismatches.iter().map(|m| m.field).collect();
+        assert_eq!(fields, ["region", "channel_count", "firmware_min"]);
+
+        let region = mismatches.first().context("Expected region mismatch")?;
+        assert_eq!(region.actual.as_deref(), Some("US"));
+
+        // Settings the device never reported count as mismatches
+        let mismatches = profile.check(&DeviceState::new());
+        assert_eq!(mismatches.len(), 3);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod junit_tests {
+    use crate::report::{TestOutcome, TestReport, TestResult};
+    use crate::{generate_junit_report, xml_escape};
+    use anyhow::Result;
+    use chrono::Utc;
+
+    fn result(category: &str, name: &str, outcome: TestOutcome, error: Option<&str>) -> TestResult {
+        TestResult {
+            name: name.to_string(),
+            category: category.to_string(),
+            outcome,
+            duration_ms: 1500,
+            error: error.map(str::to_string),
+            details: serde_json::Value::Null,
+            timestamp: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_xml_escape() -> Result<()> {
+        assert_eq!(
+            xml_escape(r#"<a href="x">Tom & Jerry's</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&apos;s&lt;/a&gt;"
+        );
+        assert_eq!(xml_escape("plain text"), "plain text");
+        Ok(())
+    }
+
+    #[test]
+    fn test_xml_escape_skips_control_chars() -> Result<()> {
+        // Not allowed anywhere in XML 1.0, so they are dropped
+        assert_eq!(
+            xml_escape("bell\u{7} escape\u{1b}[0m null\0"),
+            "bell escape[0m null"
+        );
+        // Whitespace control characters are kept
+        assert_eq!(xml_escape("a\tb\r\nc"), "a\tb\r\nc");
+        Ok(())
+    }
+
+    #[test]
+    fn test_junit_report() -> Result<()> {
+        let mut report = TestReport::new("/dev/ttyUSB0".to_string());
+        report.add_test_result(result("device", "info", TestOutcome::Pass, None));
+        report.add_test_result(result(
+            "device",
+            "reboot <fast>",
+            TestOutcome::Fail,
+            Some("Timed out\nafter 10s & gave up"),
+        ));
+        report.add_test_result(result(
+            "mesh",
+            "neighbors",
+            TestOutcome::Skip("No \"peers\"".to_string()),
+            None,
+        ));
+
+        let xml = generate_junit_report(&report);
+        assert!(xml.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n"));
+        assert!(
+            xml.contains(r#"<testsuites name="rmesh-test" tests="3" failures="1" skipped="1""#)
+        );
+        // One suite per category, in the order they ran
+        assert!(xml.contains(
+            r#"<testsuite name="device" tests="2" failures="1" skipped="0" time="3.000""#
+        ));
+        assert!(xml.contains(r#"<testsuite name="mesh" tests="1" failures="0" skipped="1""#));
+        assert!(xml.find("name=\"device\"") < xml.find("name=\"mesh\""));
+
+        assert!(
+            xml.contains(r#"<testcase classname="rmesh-test.device" name="info" time="1.500" />"#)
+        );
+        // The failure message is the first line of the escaped error
+        assert!(xml.contains(
+            "<failure message=\"Timed out\">Timed out\nafter 10s &amp; gave up</failure>"
+        ));
+        assert!(xml.contains(r#"name="reboot &lt;fast&gt;""#));
+        assert!(xml.contains(r#"<skipped message="No &quot;peers&quot;" />"#));
+        assert!(xml.ends_with("</testsuites>\n"));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod baseline_tests {
+    use crate::baseline::{Change, compare, load_baseline};
+    use crate::report::{TestOutcome, TestReport, TestResult};
+    use anyhow::{Context, Result};
+    use chrono::Utc;
+    use serde_json::{Value, json};
+
+    fn result(name: &str, outcome: TestOutcome, duration_ms: u64, details: Value) -> TestResult {
+        TestResult {
+            name: name.to_string(),
+            category: "mesh".to_string(),
+            outcome,
+            duration_ms,
+            error: None,
+            details,
+            timestamp: Utc::now(),
+        }
+    }
+
+    fn report(results: Vec<TestResult>) -> TestReport {
+        let mut report = TestReport::new("/dev/ttyUSB0".to_string());
+        for result in results {
+            report.add_test_result(result);
+        }
+        report
+    }
+
+    fn changes(baseline: &TestReport, current: &TestReport) -> Vec<(String, Change)> {
+        compare(baseline, current)
+            .changes
+            .into_iter()
+            .map(|change| (change.name, change.change))
+            .collect()
+    }
+
+    #[test]
+    fn test_outcome_changes() -> Result<()> {
+        let baseline = report(vec![
+            result("ping", TestOutcome::Pass, 100, Value::Null),
+            result("trace", TestOutcome::Fail, 100, Value::Null),
+            result(
+                "gps",
+                TestOutcome::Skip("No GPS".to_string()),
+                100,
+                Value::Null,
+            ),
+            result("removed", TestOutcome::Pass, 100, Value::Null),
+        ]);
+        let current = report(vec![
+            result("ping", TestOutcome::Fail, 100, Value::Null),
+            result("trace", TestOutcome::Pass, 100, Value::Null),
+            result("gps", TestOutcome::Fail, 100, Value::Null),
+            result("added", TestOutcome::Fail, 100, Value::Null),
+        ]);
+
+        let comparison = compare(&baseline, &current);
+        assert_eq!(comparison.baseline_test_id, baseline.test_id);
+        assert!(comparison.has_regressions());
+        // Skipped and new tests have nothing to compare with
+        assert_eq!(
+            changes(&baseline, &current),
+            [
+                ("ping".to_string(), Change::NewlyFailing),
+                ("trace".to_string(), Change::NewlyPassing),
+                ("removed".to_string(), Change::Missing),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_slowdown_needs_ratio_and_margin() -> Result<()> {
+        let baseline = report(vec![
+            result("fast", TestOutcome::Pass, 100, Value::Null),
+            result("slow", TestOutcome::Pass, 2_000, Value::Null),
+            result("noisy", TestOutcome::Pass, 2_000, Value::Null),
+        ]);
+        let current = report(vec![
+            // Four times slower, but only by 300ms
+            result("fast", TestOutcome::Pass, 400, Value::Null),
+            result("slow", TestOutcome::Pass, 3_500, Value::Null),
+            // 1.4 times slower
+            result("noisy", TestOutcome::Pass, 2_800, Value::Null),
+        ]);
+
+        assert_eq!(
+            changes(&baseline, &current),
+            [(
+                "slow".to_string(),
+                Change::Slower {
+                    baseline_ms: 2_000,
+                    current_ms: 3_500
+                }
+            )]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_snr_drop() -> Result<()> {
+        let baseline = report(vec![result(
+            "neighbors",
+            TestOutcome::Pass,
+            100,
+            json!({
+                "average_snr": 8.0,
+                "nodes": [{"id": "!00000001", "snr": 6.0}, {"id": "!00000002", "snr": 6.0}],
+            }),
+        )]);
+        let current = report(vec![result(
+            "neighbors",
+            TestOutcome::Pass,
+            100,
+            json!({
+                "average_snr": 7.0,
+                "nodes": [{"id": "!00000001", "snr": 2.5}, {"id": "!00000002", "snr": 4.0}],
+            }),
+        )]);
+
+        assert_eq!(
+            changes(&baseline, &current),
+            [(
+                "neighbors".to_string(),
+                Change::SnrDrop {
+                    subject: "node !00000001".to_string(),
+                    baseline_db: 6.0,
+                    current_db: 2.5
+                }
+            )]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_improvements_are_not_regressions() -> Result<()> {
+        assert!(!Change::NewlyPassing.is_regression());
+        assert!(!Change::Missing.is_regression());
+        assert!(Change::NewlyFailing.is_regression());
+
+        let baseline = report(vec![result("ping", TestOutcome::Fail, 100, Value::Null)]);
+        let current = report(vec![result("ping", TestOutcome::Pass, 100, Value::Null)]);
+        assert!(!compare(&baseline, &current).has_regressions());
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_baseline() -> Result<()> {
+        let path = std::env::temp_dir().join(format!(
+            "rmesh-baseline-{pid}.json",
+            pid = std::process::id()
+        ));
+        let saved = report(vec![result("ping", TestOutcome::Pass, 100, Value::Null)]);
+        std::fs::write(&path, serde_json::to_string(&saved)?)?;
+        let loaded = load_baseline(&path);
+        std::fs::write(&path, "not json")?;
+        let invalid = load_baseline(&path);
+        std::fs::remove_file(&path)?;
+
+        let loaded = loaded?;
+        assert_eq!(loaded.test_id, saved.test_id);
+        let ping = loaded.test_results.first().context("Expected a result")?;
+        assert_eq!(ping.outcome, TestOutcome::Pass);
+        assert!(invalid.is_err());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod stream_tests {
+    use crate::report::{TestOutcome, TestReport, TestResult};
+    use crate::stream::StreamWriter;
+    use anyhow::{Context, Result};
+    use chrono::Utc;
+    use serde_json::{Value, json};
+    use std::path::{Path, PathBuf};
+
+    fn stream_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "rmesh-stream-{name}-{pid}.jsonl",
+            pid = std::process::id()
+        ))
+    }
+
+    fn read_records(path: &Path) -> Result<Vec<Value>> {
+        let text = std::fs::read_to_string(path)?;
+        std::fs::remove_file(path)?;
+        text.lines()
+            .map(|line| serde_json::from_str(line).context("Invalid record line"))
+            .collect()
+    }
+
+    #[test]
+    fn test_records_are_flattened_lines() -> Result<()> {
+        let path = stream_path("records");
+        let stream = StreamWriter::create(Some(&path))?;
+        stream.write_record("device", "run-1", &json!({"port": "/dev/ttyUSB0"}))?;
+        stream.write_record("device", "run-1", &json!({"port": "/dev/ttyACM0"}))?;
+
+        let records = read_records(&path)?;
+        assert_eq!(
+            records,
+            [
+                json!({"kind": "device", "test_id": "run-1", "port": "/dev/ttyUSB0"}),
+                json!({"kind": "device", "test_id": "run-1", "port": "/dev/ttyACM0"}),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_stream_appends() -> Result<()> {
+        let path = stream_path("append");
+        StreamWriter::create(Some(&path))?.write_record("a", "run-1", &json!({}))?;
+        // A second run adds to the file rather than replacing it
+        StreamWriter::create(Some(&path))?.write_record("b", "run-2", &json!({}))?;
+
+        let kinds: Vec<Value> = read_records(&path)?
+            .into_iter()
+            .map(|record| record["kind"].clone())
+            .collect();
+        assert_eq!(kinds, [json!("a"), json!("b")]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_summary_record() -> Result<()> {
+        let mut report = TestReport::new("/dev/ttyUSB0".to_string());
+        report.add_test_result(TestResult {
+            name: "info".to_string(),
+            category: "device".to_string(),
+            outcome: TestOutcome::Fail,
+            duration_ms: 10,
+            error: Some("No reply".to_string()),
+            details: Value::Null,
+            timestamp: Utc::now(),
+        });
+        report.duration_ms = 10;
+        report.recommendations.push("Check the cable".to_string());
+
+        let path = stream_path("summary");
+        StreamWriter::create(Some(&path))?.write_summary(&report)?;
+
+        let records = read_records(&path)?;
+        assert_eq!(
+            records,
+            [json!({
+                "kind": "summary",
+                "test_id": report.test_id,
+                "tests_run": 1,
+                "tests_passed": 0,
+                "tests_failed": 1,
+                "tests_skipped": 0,
+                "duration_ms": 10,
+                "recommendations": ["Check the cable"],
+            })]
+        );
+        Ok(())
+    }
+}
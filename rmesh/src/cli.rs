@@ -1,4 +1,15 @@
-use clap::{Parser, Subcommand, ValueEnum};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use rmesh_core::config::{ConfigAssignment, ConfigValueType};
+use rmesh_core::connection::keep_awake::parse_wake_sequence;
+use rmesh_core::connection::{
+    HandshakeOptions, Injection, SerialWakeOptions, SimulationOptions, Topology,
+};
+use rmesh_core::node_id::parse_node_id;
+use rmesh_core::packet_filter::PacketFilter;
+use rmesh_core::session_cache::SessionCache;
+use rmesh_core::time::{TimeFormat, parse_age};
+use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::time::Duration;
 
 #[derive(Parser, Debug)]
@@ -6,7 +17,8 @@ use std::time::Duration;
 #[command(author, version, about = "rmesh - A Rust CLI for Meshtastic devices", long_about = None)]
 #[command(arg_required_else_help = true)]
 pub struct Cli {
-    /// Serial port or TCP address (e.g., /dev/ttyUSB0 or 192.168.1.100:4403)
+    /// Serial port, TCP address or meshtasticd socket (e.g., /dev/ttyUSB0,
+    /// 192.168.1.100:4403 or unix:///run/meshtasticd.sock)
     #[arg(short, long, global = true)]
     pub port: Option<String>,
 
@@ -18,10 +30,81 @@ pub struct Cli {
     #[arg(short = 'j', long, global = true)]
     pub json: bool,
 
+    /// Limit JSON output to these comma-separated fields (e.g. num,user.long_name)
+    #[arg(long, global = true, value_delimiter = ',')]
+    pub fields: Vec<String>,
+
+    /// Stable tab-separated output for scripts (nodes, channels, radio, message send)
+    #[arg(long, global = true, conflicts_with = "json")]
+    pub porcelain: bool,
+
+    /// Suppress informational and success messages on stderr
+    #[arg(short = 'q', long, global = true)]
+    pub quiet: bool,
+
+    /// When to use colors; `auto` disables them when piped or NO_COLOR is set
+    #[arg(long, global = true, value_enum, default_value = "auto")]
+    pub color: ColorChoice,
+
+    /// Include PSKs and passwords in output instead of redacting them
+    #[arg(long, global = true)]
+    pub show_secrets: bool,
+
+    /// How to show times in tables: rfc3339, local, relative or epoch
+    #[arg(long, global = true, default_value = "rfc3339")]
+    pub time_format: TimeFormat,
+
     /// Connection timeout in seconds
     #[arg(short = 't', long, global = true, default_value = "30")]
     pub timeout: u64,
 
+    /// Seconds to wait for the device to finish the config handshake
+    #[arg(long, global = true, default_value = "10")]
+    pub handshake_timeout: u64,
+
+    /// Number of config handshake attempts before giving up
+    #[arg(long, global = true, default_value = "3")]
+    pub handshake_attempts: u32,
+
+    /// Don't send the wake sequence when opening a serial port
+    #[arg(long, global = true)]
+    pub no_wake: bool,
+
+    /// Bytes, in hex, sent to wake a serial device (default: 32 x c3)
+    // Spelled out so clap takes one hex value rather than a list of bytes
+    #[arg(long, global = true, value_name = "HEX", value_parser = parse_wake_sequence)]
+    pub wake_sequence: Option<std::vec::Vec<u8>>,
+
+    /// Resend the wake sequence and a heartbeat after this many idle seconds,
+    /// for serial devices that sleep during long monitors
+    #[arg(
+        long,
+        global = true,
+        value_name = "SECS",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub keep_awake: Option<u64>,
+
+    /// Keep the admin session key in this file between runs, so scripts
+    /// issuing many admin commands skip the key request
+    #[arg(long, global = true, env = "RMESH_SESSION_FILE", value_name = "PATH")]
+    pub session_file: Option<PathBuf>,
+
+    /// Seconds a cached session key is reused, at most 150
+    #[arg(
+        long,
+        global = true,
+        value_name = "SECS",
+        default_value = "120",
+        value_parser = clap::value_parser!(u64).range(1..=150)
+    )]
+    pub session_ttl: u64,
+
+    /// Print connection setup time, traffic and total run time on stderr
+    /// after the command
+    #[arg(long, global = true)]
+    pub stats: bool,
+
     /// Enable debug logging
     #[arg(short = 'd', long, global = true)]
     pub debug: bool,
@@ -30,6 +113,77 @@ pub struct Cli {
     #[arg(short = 'v', long, global = true)]
     pub verbose: bool,
 
+    /// Log every packet sent and every reply, with round-trip times
+    #[arg(long, global = true)]
+    pub trace_packets: bool,
+
+    /// Never transmit: refuse sends, admin requests and requests expecting a reply
+    #[arg(long, global = true)]
+    pub listen_only: bool,
+
+    /// Right after connecting, broadcast our NodeInfo and ask every node for
+    /// theirs, to fill the node list of a fresh device quickly
+    #[arg(long, global = true, conflicts_with = "listen_only")]
+    pub refresh_nodes: bool,
+
+    /// Seconds to wait for NodeInfo replies with --refresh-nodes
+    #[arg(long, global = true, value_name = "SECS", default_value = "10")]
+    pub refresh_wait: u64,
+
+    /// Show text messages sent from this node when the device echoes them back
+    #[arg(long, global = true)]
+    pub include_own: bool,
+
+    /// Serve /healthz and /readyz on this address while connected, so a
+    /// supervisor can watch long-running modes such as responder or mqtt-proxy
+    #[arg(long, global = true, env = "RMESH_HEALTH_LISTEN", value_name = "ADDR")]
+    pub health_listen: Option<SocketAddr>,
+
+    /// Report unhealthy, and withhold systemd watchdog heartbeats, when no
+    /// packet arrived from the device for this many seconds
+    #[arg(long, global = true, value_name = "SECS")]
+    pub health_max_packet_age: Option<u64>,
+
+    /// Feed the FromRadio frames recorded in this JSONL file to the packet
+    /// processor after the handshake, alongside real traffic
+    #[arg(long, global = true, hide = true, value_name = "CAPTURE")]
+    pub inject_from: Option<PathBuf>,
+
+    /// Milliseconds between the handshake and the first injected frame
+    #[arg(
+        long,
+        global = true,
+        hide = true,
+        value_name = "MS",
+        default_value = "500",
+        requires = "inject_from"
+    )]
+    pub inject_delay: u64,
+
+    /// Connect to an in-process virtual mesh of fake nodes instead of a radio
+    #[arg(long, global = true, conflicts_with_all = ["port", "ble"])]
+    pub simulate: bool,
+
+    /// Number of fake nodes in the simulated mesh
+    #[arg(long, global = true, default_value = "4", requires = "simulate")]
+    pub sim_nodes: usize,
+
+    /// How simulated nodes reach the local radio: full, star or line
+    #[arg(long, global = true, default_value = "full", requires = "simulate")]
+    pub sim_topology: Topology,
+
+    /// Chance, from 0 to 1, that a simulated packet is lost on each hop
+    #[arg(long, global = true, default_value = "0", requires = "simulate")]
+    pub sim_loss: f64,
+
+    /// Seconds between packets broadcast by the simulated nodes
+    #[arg(long, global = true, default_value = "10", requires = "simulate")]
+    pub sim_interval: u64,
+
+    /// Seed for a repeatable simulation
+    #[arg(long, global = true, requires = "simulate")]
+    pub sim_seed: Option<u64>,
+
     #[command(subcommand)]
     pub command: Commands,
 }
@@ -72,14 +226,36 @@ pub enum Commands {
         subcommand: MeshCommands,
     },
 
+    /// Act on the local node's identity on the mesh
+    Node {
+        #[command(subcommand)]
+        subcommand: NodeCommands,
+    },
+
+    /// Inspect the connected device itself
+    Device {
+        #[command(subcommand)]
+        subcommand: DeviceCommands,
+    },
+
+    /// Share waypoints with the mesh
+    Waypoint {
+        #[command(subcommand)]
+        subcommand: WaypointCommands,
+    },
+
     /// Device telemetry
+    #[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
     Telemetry {
+        #[command(subcommand)]
+        subcommand: Option<TelemetryCommands>,
+
         /// Type of telemetry to request
-        #[arg(value_enum)]
-        telemetry_type: TelemetryType,
+        #[arg(value_enum, required = true)]
+        telemetry_type: Option<TelemetryType>,
 
         /// Destination node ID
-        #[arg(short = 'd', long)]
+        #[arg(short = 'd', long, value_parser = parse_node_id)]
         dest: Option<u32>,
     },
 
@@ -87,9 +263,104 @@ pub enum Commands {
     Admin {
         #[command(subcommand)]
         subcommand: AdminCommands,
+
+        /// Allow admin messages addressed to broadcast (every node on the mesh)
+        #[arg(long = "i-know-what-im-doing", global = true)]
+        allow_broadcast: bool,
+    },
+
+    /// Automatically reply to messages matching a keyword
+    Responder {
+        /// Keyword to match (case-insensitive)
+        #[arg(short = 'm', long = "match")]
+        pattern: String,
+
+        /// Reply template ({node}, {text}, {snr} and {rssi} are substituted)
+        #[arg(short = 'r', long)]
+        reply: String,
+
+        /// Only respond to direct messages
+        #[arg(long)]
+        dm_only: bool,
+
+        /// Minimum seconds between replies to the same sender
+        #[arg(long, default_value = "60")]
+        cooldown: u64,
+    },
+
+    /// Watch mesh traffic as it arrives
+    Monitor {
+        #[command(subcommand)]
+        subcommand: MonitorCommands,
+    },
+
+    /// Low-level access to the device for debugging firmware issues
+    Debug {
+        #[command(subcommand)]
+        subcommand: DebugCommands,
+    },
+
+    /// Act as the MQTT proxy of a device whose MQTT module is set to client proxy
+    ///
+    /// Keeps one connection to the broker, by default the one configured on
+    /// the device.
+    MqttProxy {
+        /// Broker as host[:port] or [ipv6]:port, instead of the device's MQTT address
+        #[arg(long)]
+        broker: Option<String>,
+
+        /// Broker username, instead of the device's
+        #[arg(long)]
+        username: Option<String>,
+
+        /// Broker password, instead of the device's; `-` prompts for it
+        /// without echo
+        #[arg(
+            long,
+            conflicts_with_all = ["prompt_password", "password_env", "password_file"]
+        )]
+        password: Option<String>,
+
+        /// Prompt for the broker password without echoing it
+        #[arg(long, conflicts_with_all = ["password_env", "password_file"])]
+        prompt_password: bool,
+
+        /// Read the broker password from this environment variable
+        #[arg(long, value_name = "VAR", conflicts_with = "password_file")]
+        password_env: Option<String>,
+
+        /// Read the broker password from a file (a trailing newline is ignored)
+        #[arg(long, value_name = "PATH")]
+        password_file: Option<PathBuf>,
+
+        /// Connect to the broker over TLS
+        #[arg(long)]
+        tls: bool,
+    },
+
+    /// Accept common Python meshtastic CLI flags, e.g. --sendtext and --set
+    Compat(CompatArgs),
+
+    /// Diagnose serial permissions, port conflicts and Bluetooth, then try a handshake
+    Doctor,
+
+    /// Print the JSON Schema of a command's --json output
+    Schema {
+        /// Command words, e.g. "info nodes" (lists commands if omitted)
+        command: Vec<String>,
     },
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ColorChoice {
+    /// Color when stdout is a terminal and NO_COLOR is not set
+    Auto,
+    /// Always color, even when piped
+    Always,
+    /// Never color
+    Never,
+}
+
 #[derive(Debug, Clone, Copy, ValueEnum)]
 pub enum TelemetryType {
     /// Device telemetry (battery, voltage, etc.)
@@ -98,14 +369,52 @@ pub enum TelemetryType {
     Environment,
 }
 
+#[derive(Subcommand, Debug)]
+pub enum TelemetryCommands {
+    /// Request telemetry from every known node and list who answered
+    PollAll {
+        /// Type of telemetry to request
+        #[arg(long = "type", value_enum, default_value = "device")]
+        telemetry_type: TelemetryType,
+
+        /// Seconds the whole poll may take
+        #[arg(long, default_value = "60")]
+        timeout: u64,
+
+        /// Seconds each node gets to reply
+        #[arg(long, default_value = "20")]
+        request_timeout: u64,
+
+        /// Requests waiting for a reply at any one time
+        #[arg(long, default_value = "3", value_parser = clap::value_parser!(u64).range(1..=16))]
+        concurrency: u64,
+    },
+}
+
 #[derive(Subcommand, Debug)]
 pub enum InfoCommands {
     /// Display radio information
-    Radio,
+    Radio {
+        /// Include link quality: framing errors, timeouts and response times
+        #[arg(long)]
+        quality: bool,
+    },
     /// Display channel configuration
     Channels,
-    /// Display node list
-    Nodes,
+    /// Display node list, most recently heard first
+    Nodes {
+        /// Show at most this many nodes
+        #[arg(long)]
+        limit: Option<usize>,
+
+        /// Skip this many nodes first
+        #[arg(long, default_value = "0")]
+        offset: usize,
+
+        /// Also show battery, role and whether encrypted direct messages work
+        #[arg(long)]
+        detailed: bool,
+    },
     /// Display position information
     Position {
         /// Wait for position broadcasts (in seconds)
@@ -139,35 +448,167 @@ pub enum MessageCommands {
         text: String,
 
         /// Destination node ID (broadcast if not specified)
-        #[arg(short = 'd', long)]
+        #[arg(short = 'd', long, value_parser = parse_node_id)]
         dest: Option<u32>,
 
         /// Channel index
         #[arg(short = 'c', long, default_value = "0")]
         channel: u32,
 
+        /// Channel name, resolved to its index on the device
+        #[arg(long, conflicts_with = "channel")]
+        channel_name: Option<String>,
+
         /// Wait for acknowledgment
         #[arg(short = 'a', long)]
         ack: bool,
+
+        /// Seconds to wait for each acknowledgment
+        #[arg(long, default_value = "30", requires = "ack")]
+        ack_timeout: u64,
+
+        /// Retransmissions when no acknowledgment arrives in time
+        #[arg(long, default_value = "0", requires = "ack")]
+        retries: u32,
+    },
+
+    /// Send a critical alert, which sounds on receiving nodes even when muted
+    Alert {
+        /// Alert text to send
+        #[arg(short = 'm', long)]
+        text: String,
+
+        /// Destination node ID (broadcast if not specified)
+        #[arg(short = 'd', long, value_parser = parse_node_id)]
+        dest: Option<u32>,
+
+        /// Channel index
+        #[arg(short = 'c', long, default_value = "0")]
+        channel: u32,
+
+        /// Send without asking for confirmation
+        #[arg(short = 'y', long)]
+        confirm: bool,
+
+        /// Send even if another alert went out in the last minute
+        #[arg(long)]
+        force: bool,
     },
 
     /// Receive messages
     Recv {
         /// Filter by sender node ID
-        #[arg(short = 'f', long)]
+        #[arg(short = 'f', long, value_parser = parse_node_id)]
         from: Option<u32>,
 
+        /// Filter by channel index
+        #[arg(short = 'c', long)]
+        channel: Option<u32>,
+
         /// Maximum messages to receive (0 for unlimited)
         #[arg(short = 'n', long, default_value = "0")]
         count: usize,
+
+        /// Print message text as received, without escaping control characters
+        #[arg(long)]
+        raw: bool,
     },
 
     /// Monitor messages in real-time
     Monitor {
         /// Filter by sender node ID
-        #[arg(short = 'f', long)]
+        #[arg(short = 'f', long, value_parser = parse_node_id)]
         from: Option<u32>,
+
+        /// Filter by channel index
+        #[arg(short = 'c', long)]
+        channel: Option<u32>,
+
+        /// Alert on direct messages and keyword matches
+        #[arg(long, value_enum, num_args = 0..=1, default_missing_value = "both")]
+        notify: Option<NotifyMode>,
+
+        /// Also alert on messages containing this keyword (repeatable)
+        #[arg(long = "on-keyword", value_name = "KEYWORD")]
+        on_keyword: Vec<String>,
+
+        /// Emit one versioned JSON object per line
+        #[arg(long)]
+        jsonl: bool,
+
+        /// Print message text as received, without escaping control characters
+        #[arg(long)]
+        raw: bool,
+
+        /// Also append each message as a JSON line to this file
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+
+        /// Forget messages older than this span, e.g. 30d, and prune them
+        /// from the --output file when monitoring starts
+        #[arg(long, env = "RMESH_MESSAGE_RETENTION", value_parser = parse_age)]
+        retain: Option<Duration>,
+
+        /// Also POST each message as JSON to this URL (repeatable)
+        #[arg(long, value_name = "URL")]
+        webhook: Vec<String>,
+
+        /// Also publish each message to an MQTT topic, as mqtt://host[:port]/topic (repeatable)
+        #[arg(long, value_name = "URL")]
+        mqtt: Vec<String>,
     },
+
+    /// Render logged messages as a transcript, one section per channel or conversation
+    Export {
+        /// Message log written by `message monitor --output`
+        #[arg(short, long, value_name = "FILE")]
+        input: PathBuf,
+
+        /// Transcript format
+        #[arg(short = 'f', long, value_enum, default_value = "html")]
+        format: TranscriptFormat,
+
+        /// Only export messages from this recent span, e.g. 12h or 7d
+        #[arg(long, value_parser = parse_age)]
+        since: Option<Duration>,
+
+        /// Write the transcript to this file instead of stdout
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+
+    /// Delete messages from a log written by `message monitor --output`
+    Clear {
+        /// Message log to prune
+        #[arg(short, long, value_name = "FILE")]
+        input: PathBuf,
+
+        /// Only delete messages older than this span, e.g. 30d
+        #[arg(long, value_parser = parse_age)]
+        older_than: Option<Duration>,
+
+        /// Delete every message without asking for confirmation
+        #[arg(short = 'y', long)]
+        confirm: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TranscriptFormat {
+    /// Standalone web page with chat bubbles
+    Html,
+    /// Plain text
+    Txt,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum NotifyMode {
+    /// Desktop notification
+    Desktop,
+    /// Terminal bell
+    Bell,
+    /// Desktop notification and terminal bell
+    Both,
 }
 
 #[derive(Subcommand, Debug)]
@@ -179,25 +620,121 @@ pub enum ConfigCommands {
         key: String,
     },
 
-    /// Set configuration value
+    /// Set configuration values, all in one transaction
     Set {
-        /// Configuration key (e.g., lora.region)
+        /// Settings as key=value, keys being dotted paths such as
+        /// lora.hop_limit or mqtt.map_report_settings.publish_interval_secs
+        #[arg(
+            value_name = "KEY=VALUE",
+            required_unless_present = "key",
+            conflicts_with = "key"
+        )]
+        pairs: Vec<ConfigAssignment>,
+
+        /// Configuration key (e.g., lora.region), to set a single value
         #[arg(short = 'k', long)]
-        key: String,
+        key: Option<String>,
+
+        /// Configuration value; `-` prompts for it without echo, for secrets
+        /// such as network.wifi_psk
+        #[arg(
+            short = 'v',
+            long,
+            requires = "key",
+            conflicts_with_all = ["value_env", "value_file"]
+        )]
+        value: Option<String>,
+
+        /// Read the value from this environment variable
+        #[arg(
+            long,
+            value_name = "VAR",
+            requires = "key",
+            conflicts_with = "value_file"
+        )]
+        value_env: Option<String>,
 
-        /// Configuration value
-        #[arg(short = 'v', long)]
-        value: String,
+        /// Read the value from a file (a trailing newline is ignored)
+        #[arg(long, value_name = "PATH", requires = "key")]
+        value_file: Option<PathBuf>,
+
+        /// Read values as bool, int, float, string or enum instead of
+        /// following the type of the current value
+        #[arg(long = "type", value_name = "TYPE")]
+        value_type: Option<ConfigValueType>,
     },
 
     /// List all configuration values
     List,
+
+    /// Print a stable hash of the configuration, to spot drifted devices
+    Fingerprint {
+        /// Fail unless the fingerprint matches this hash
+        #[arg(long, value_name = "HASH")]
+        verify: Option<String>,
+    },
+
+    /// Show or change the NeighborInfo module, which shares direct neighbors for topology maps
+    NeighborInfo {
+        /// Turn the module on
+        #[arg(long, conflicts_with = "disabled")]
+        enabled: bool,
+
+        /// Turn the module off
+        #[arg(long)]
+        disabled: bool,
+
+        /// Seconds between neighbor broadcasts
+        #[arg(short = 'i', long)]
+        interval: Option<u32>,
+    },
+
+    /// Show or change how often the Telemetry module broadcasts metrics
+    Telemetry {
+        #[command(subcommand)]
+        subcommand: TelemetryConfigCommands,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum TelemetryConfigCommands {
+    /// Show the Telemetry module settings
+    Show,
+
+    /// Change the Telemetry module settings, keeping the ones not given
+    Set {
+        /// Seconds between device metrics broadcasts, 0 for the firmware default
+        #[arg(long, value_name = "SECS")]
+        device_interval: Option<u32>,
+
+        /// Seconds between environment metrics broadcasts, 0 for the firmware default
+        #[arg(long, value_name = "SECS")]
+        environment_interval: Option<u32>,
+
+        /// Read and broadcast environment sensors
+        #[arg(long, value_name = "BOOL")]
+        environment_measurement: Option<bool>,
+
+        /// Read and broadcast air quality sensors
+        #[arg(long, value_name = "BOOL")]
+        air_quality: Option<bool>,
+    },
 }
 
 #[derive(Subcommand, Debug)]
 pub enum ChannelCommands {
     /// List all channels
-    List,
+    List {
+        /// Ask the device for current values instead of using those read at connect
+        #[arg(long)]
+        refresh: bool,
+        /// Also show the frequency slot the primary channel hashes to
+        #[arg(long)]
+        detailed: bool,
+    },
+
+    /// Check channels for weak encryption and location leaks
+    Audit,
 
     /// Add a new channel
     Add {
@@ -205,9 +742,8 @@ pub enum ChannelCommands {
         #[arg(short = 'n', long)]
         name: String,
 
-        /// Pre-shared key (PSK)
-        #[arg(short = 'p', long)]
-        psk: Option<String>,
+        #[command(flatten)]
+        psk: PskArgs,
     },
 
     /// Delete a channel
@@ -227,9 +763,8 @@ pub enum ChannelCommands {
         #[arg(short = 'n', long)]
         name: Option<String>,
 
-        /// Pre-shared key (PSK)
-        #[arg(short = 'p', long)]
-        psk: Option<String>,
+        #[command(flatten)]
+        psk: PskArgs,
 
         /// Uplink enabled
         #[arg(short = 'u', long)]
@@ -238,49 +773,198 @@ pub enum ChannelCommands {
         /// Downlink enabled
         #[arg(short = 'd', long)]
         downlink: Option<bool>,
+
+        /// Bits of position to share on the channel: 0 for none, 32 for exact
+        #[arg(long, value_name = "BITS", value_parser = clap::value_parser!(u32).range(0..=32))]
+        position_precision: Option<u32>,
+    },
+
+    /// Save all channel slots to a YAML or JSON file
+    Export {
+        /// File to write; a `.json` extension writes JSON, anything else YAML
+        #[arg(short = 'f', long)]
+        file: PathBuf,
+
+        /// Leave PSKs out so the file can be shared without the keys
+        #[arg(long)]
+        redact: bool,
+
+        /// Ask the device for current values instead of using those read at connect
+        #[arg(long)]
+        refresh: bool,
+    },
+
+    /// Apply channel slots from a file written by `channel export`
+    Import {
+        /// YAML or JSON channel file
+        #[arg(short = 'f', long)]
+        file: PathBuf,
     },
 }
 
+/// Ways to supply a channel PSK
+///
+/// A PSK given on the command line ends up in shell history and process
+/// listings; the other sources keep it out of both.
+#[derive(Args, Debug, Clone)]
+pub struct PskArgs {
+    /// Pre-shared key (PSK); `-` prompts for it without echo
+    #[arg(
+        short = 'p',
+        long,
+        conflicts_with_all = ["prompt_psk", "psk_env", "psk_file"]
+    )]
+    pub psk: Option<String>,
+
+    /// Prompt for the PSK without echoing it
+    #[arg(long, conflicts_with_all = ["psk_env", "psk_file"])]
+    pub prompt_psk: bool,
+
+    /// Read the PSK from this environment variable
+    #[arg(long, value_name = "VAR", conflicts_with = "psk_file")]
+    pub psk_env: Option<String>,
+
+    /// Read the PSK from a file (a trailing newline is ignored)
+    #[arg(long, value_name = "PATH")]
+    pub psk_file: Option<PathBuf>,
+}
+
 #[derive(Subcommand, Debug)]
 pub enum PositionCommands {
     /// Get current position
     Get {
         /// Node ID (local if not specified)
-        #[arg(short = 'n', long)]
+        #[arg(short = 'n', long, value_parser = parse_node_id)]
         node: Option<u32>,
+
+        /// Also show speed, heading, satellites, precision and fix quality
+        #[arg(long)]
+        detailed: bool,
     },
 
     /// Set position
     Set {
         /// Latitude in decimal degrees
-        #[arg(long)]
+        #[arg(long, allow_hyphen_values = true)]
         lat: f64,
 
         /// Longitude in decimal degrees
-        #[arg(long)]
+        #[arg(long, allow_hyphen_values = true)]
         lon: f64,
 
         /// Altitude in meters
-        #[arg(long)]
+        #[arg(long, allow_hyphen_values = true)]
         alt: Option<i32>,
+
+        /// Store it as the node's fixed position instead of broadcasting it once
+        #[arg(long)]
+        fixed: bool,
+
+        /// Remote node to give the fixed position, via remote admin
+        #[arg(short = 'd', long, value_parser = parse_node_id, requires = "fixed")]
+        dest: Option<u32>,
+
+        /// Don't ask the remote node for its position afterwards
+        #[arg(long, requires = "dest")]
+        no_verify: bool,
+
+        /// Seconds to wait for the remote node to report its position
+        #[arg(long, value_name = "SECS", default_value = "60", requires = "dest")]
+        verify_timeout: u64,
     },
 
     /// Track node positions
     Track {
         /// Node IDs to track (all if not specified)
-        #[arg(short = 'n', long)]
+        #[arg(short = 'n', long, value_parser = parse_node_id)]
         nodes: Vec<u32>,
+
+        /// Stream each update as one versioned JSON object per line
+        #[arg(long)]
+        jsonl: bool,
     },
 
     /// Request position from a specific node
     Request {
         /// Node ID to request position from
+        #[arg(value_parser = parse_node_id)]
         node: u32,
 
         /// Timeout in seconds
         #[arg(short = 't', long, default_value = "30")]
         timeout: u64,
     },
+
+    /// Watch node positions and report geofence enter/exit events
+    Geofence {
+        /// Fence center as "lat,lon"
+        #[arg(long, allow_hyphen_values = true)]
+        center: String,
+
+        /// Fence radius (e.g. 500m, 1.5km)
+        #[arg(short = 'r', long)]
+        radius: String,
+
+        /// Node IDs to watch (all if not specified)
+        #[arg(short = 'n', long, value_parser = parse_node_id)]
+        node: Vec<u32>,
+
+        /// Shell command run on each event (RMESH_EVENT, RMESH_NODE, RMESH_LAT,
+        /// RMESH_LON and RMESH_DISTANCE_M are set)
+        #[arg(long)]
+        exec: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum NodeCommands {
+    /// Broadcast the node's NodeInfo now, e.g. after renaming it
+    Announce {
+        /// Send to one node instead of broadcasting
+        #[arg(short = 'd', long, value_parser = parse_node_id)]
+        dest: Option<u32>,
+
+        /// Ask receivers to reply with their own NodeInfo
+        #[arg(long)]
+        want_response: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum DeviceCommands {
+    /// Compare the device clock with this computer's, e.g. before trusting
+    /// message and position timestamps
+    Clock {
+        /// Set the device clock to this computer's time
+        #[arg(long)]
+        sync: bool,
+
+        /// Seconds to wait for the device to report its time
+        #[arg(long, default_value = "10")]
+        timeout: u64,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum WaypointCommands {
+    /// Broadcast each Point feature of a GeoJSON file as a waypoint
+    Import {
+        /// GeoJSON FeatureCollection with name, description, icon and expire properties
+        #[arg(short = 'f', long)]
+        file: PathBuf,
+
+        /// Channel index to send on
+        #[arg(short = 'c', long, default_value = "0")]
+        channel: u32,
+
+        /// Seconds to wait between waypoints
+        #[arg(long, default_value = "5")]
+        interval: u64,
+
+        /// List the waypoints without sending them (no device needed)
+        #[arg(long)]
+        dry_run: bool,
+    },
 }
 
 #[derive(Subcommand, Debug)]
@@ -291,12 +975,111 @@ pub enum MeshCommands {
     /// Trace route to destination
     Traceroute {
         /// Destination node ID
-        #[arg(short = 'd', long)]
+        #[arg(short = 'd', long, value_parser = parse_node_id)]
         dest: u32,
     },
 
+    /// Show routes to other nodes learned from traceroutes seen on the mesh
+    Routes {
+        /// Keep recording and print routes as they are learned, until interrupted
+        #[arg(short = 'w', long)]
+        watch: bool,
+
+        /// Seconds between checks for new routes with --watch
+        #[arg(short = 'i', long, default_value = "30")]
+        interval: u64,
+    },
+
+    /// Show the change journal: nodes joining, renamed or changing role, and
+    /// changes to the channels and configuration of the local device
+    Events {
+        /// Only show changes from this recent span, e.g. 1h or 7d
+        #[arg(long, value_parser = parse_age)]
+        since: Option<Duration>,
+
+        /// Keep printing changes as they are seen, until interrupted
+        #[arg(short = 'w', long)]
+        watch: bool,
+    },
+
     /// List neighboring nodes
     Neighbors,
+
+    /// Plot node positions relative to the local node
+    Map {
+        /// Map width in characters
+        #[arg(long, default_value = "61")]
+        width: usize,
+
+        /// Map height in characters
+        #[arg(long, default_value = "21")]
+        height: usize,
+    },
+
+    /// Graph channel utilization and transmit airtime of the local node
+    Airtime {
+        /// Keep sampling and redraw the graphs until interrupted
+        #[arg(short = 'w', long)]
+        watch: bool,
+
+        /// Seconds between samples with --watch
+        #[arg(short = 'i', long, default_value = "60")]
+        interval: u64,
+
+        /// Samples shown in each graph
+        #[arg(long, default_value = "40")]
+        width: usize,
+    },
+
+    /// Compare expected SNR margin and airtime of the modem presets
+    #[command(name = "linkbudget")]
+    LinkBudget(LinkBudgetArgs),
+}
+
+/// Inputs of `mesh linkbudget`
+///
+/// Either a distance, estimated as free space propagation, or an SNR seen
+/// on the link. Only `--node` needs a device.
+#[derive(Args, Debug, Clone)]
+#[command(group = clap::ArgGroup::new("link").required(true).args(["distance", "snr", "node"]))]
+pub struct LinkBudgetArgs {
+    /// Preset in use, or the one the SNR was observed with, e.g. LONG_FAST
+    /// [default: the device's preset with --node, otherwise LONG_FAST]
+    #[arg(long)]
+    pub preset: Option<String>,
+
+    /// Distance between the nodes, e.g. 800m or 5km
+    #[arg(long)]
+    pub distance: Option<String>,
+
+    /// SNR observed on the link in dB
+    #[arg(long, allow_hyphen_values = true)]
+    pub snr: Option<f64>,
+
+    /// Use the last SNR the local node heard from this node
+    #[arg(long, value_parser = parse_node_id)]
+    pub node: Option<u32>,
+
+    /// Transmit power in dBm
+    #[arg(long, default_value = "20")]
+    pub txpower: f64,
+
+    /// Antenna gain at each end in dBi
+    #[arg(long, default_value = "0", allow_hyphen_values = true)]
+    pub gain: f64,
+
+    /// Cable and obstruction losses in dB
+    #[arg(long, default_value = "0")]
+    pub loss: f64,
+
+    /// Region, which sets the frequency
+    /// [default: the device's region with --node, otherwise US]
+    #[arg(long)]
+    pub region: Option<String>,
+
+    /// Message length in bytes
+    #[arg(long, default_value = "50")]
+    pub payload: usize,
 }
 
 #[derive(Subcommand, Debug)]
@@ -308,8 +1091,16 @@ pub enum AdminCommands {
         confirm: bool,
     },
 
-    /// Factory reset the device
+    /// Factory reset the device (full reset unless --config-only)
     FactoryReset {
+        /// Only restore default config and channels, keeping the node database and keys
+        #[arg(long, conflicts_with = "full")]
+        config_only: bool,
+
+        /// Erase everything, including the node database and device keys (default)
+        #[arg(long)]
+        full: bool,
+
         /// Confirm the action
         #[arg(short = 'y', long)]
         confirm: bool,
@@ -321,10 +1112,188 @@ pub enum AdminCommands {
         #[arg(short = 'y', long)]
         confirm: bool,
     },
+
+    /// Clear the node database (the device forgets all other nodes)
+    ResetNodedb {
+        /// Node to reset via remote admin (local node if omitted)
+        #[arg(long, value_parser = parse_node_id)]
+        dest: Option<u32>,
+
+        /// Confirm the action
+        #[arg(short = 'y', long)]
+        confirm: bool,
+    },
+
+    /// Remove the fixed position so the device uses its GPS again
+    ResetPosition {
+        /// Node to reset via remote admin (local node if omitted)
+        #[arg(long, value_parser = parse_node_id)]
+        dest: Option<u32>,
+
+        /// Confirm the action
+        #[arg(short = 'y', long)]
+        confirm: bool,
+    },
+
+    /// Switch the device role (e.g. CLIENT, CLIENT_MUTE, ROUTER)
+    SetRole {
+        /// Role name
+        role: String,
+
+        /// Node to reconfigure via remote admin (local node if omitted)
+        #[arg(long, value_parser = parse_node_id)]
+        dest: Option<u32>,
+
+        /// Confirm the action
+        #[arg(short = 'y', long)]
+        confirm: bool,
+
+        /// Acknowledge that ROUTER and REPEATER are for well-sited infrastructure nodes
+        #[arg(long)]
+        infrastructure: bool,
+    },
+}
+
+/// Flags of the Python meshtastic CLI understood by `rmesh compat`
+///
+/// Each run performs one action, mapped to the equivalent rmesh command.
+#[derive(Args, Debug, Clone)]
+#[command(group(
+    clap::ArgGroup::new("action")
+        .required(true)
+        .args(["sendtext", "get", "set", "info", "nodes"])
+))]
+pub struct CompatArgs {
+    /// Send a text message (rmesh message send)
+    #[arg(long, value_name = "TEXT")]
+    pub sendtext: Option<String>,
+
+    /// Destination node, as '!abcd1234', '^all', 0x-prefixed hex or a node number
+    #[arg(long, value_name = "NODE", allow_hyphen_values = true)]
+    pub dest: Option<String>,
+
+    /// Channel index to send on
+    #[arg(long = "ch-index", value_name = "INDEX", default_value = "0")]
+    pub ch_index: u32,
+
+    /// Wait for an acknowledgment of the sent message
+    // `requires = "sendtext"` is met by any member of the required action
+    // group, so the other actions are excluded instead
+    #[arg(long, conflicts_with_all = ["get", "set", "info", "nodes"])]
+    pub ack: bool,
+
+    /// Print a configuration value (rmesh config get)
+    #[arg(long, value_name = "KEY")]
+    pub get: Option<String>,
+
+    /// Change a configuration value (rmesh config set); repeat for several
+    #[arg(
+        long,
+        num_args = 2,
+        value_names = ["KEY", "VALUE"],
+        action = clap::ArgAction::Append
+    )]
+    pub set: Option<Vec<String>>,
+
+    /// Show radio information (rmesh info radio)
+    #[arg(long)]
+    pub info: bool,
+
+    /// List known nodes (rmesh info nodes)
+    #[arg(long)]
+    pub nodes: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum DebugCommands {
+    /// Send a raw ToRadio protobuf to the device
+    SendToradio {
+        /// Encoded ToRadio message, optionally with its stream frame header
+        #[arg(long)]
+        hex: String,
+    },
+
+    /// Print the complete cached device state, raw protobufs included
+    DumpState {
+        /// Serialization to print
+        #[arg(short = 'f', long, value_enum, default_value = "json")]
+        format: DumpFormat,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum MonitorCommands {
+    /// Print every mesh packet received, whatever its port
+    Packets {
+        /// Only print packets matching an expression, e.g.
+        /// "port==TEXT_MESSAGE_APP && from==!abc123 && snr<-10"
+        ///
+        /// Fields: id, from, to, channel, port, encrypted, size, snr, rssi,
+        /// hop_limit, hop_start, hops, want_ack and via_mqtt. Comparisons use
+        /// ==, !=, <, <=, > and >=, and combine with &&, || and ! and
+        /// parentheses.
+        #[arg(long)]
+        filter: Option<PacketFilter>,
+
+        /// Emit one versioned JSON object per line
+        #[arg(long)]
+        jsonl: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DumpFormat {
+    Json,
+    /// Rusty Object Notation, closer to the Rust types
+    Ron,
 }
 
 impl Cli {
     pub fn timeout_duration(&self) -> Duration {
         Duration::from_secs(self.timeout)
     }
+
+    /// Virtual mesh settings when `--simulate` is given
+    pub fn simulation_options(&self) -> Option<SimulationOptions> {
+        self.simulate.then(|| SimulationOptions {
+            nodes: self.sim_nodes,
+            topology: self.sim_topology,
+            loss: self.sim_loss,
+            interval: Duration::from_secs(self.sim_interval),
+            seed: self.sim_seed,
+        })
+    }
+
+    /// Serial wake settings from `--no-wake`, `--wake-sequence` and `--keep-awake`
+    pub fn serial_wake_options(&self) -> SerialWakeOptions {
+        let defaults = SerialWakeOptions::default();
+        SerialWakeOptions {
+            wake_on_connect: !self.no_wake,
+            wake_sequence: self.wake_sequence.clone().unwrap_or(defaults.wake_sequence),
+            keep_awake: self.keep_awake.map(Duration::from_secs),
+        }
+    }
+
+    /// On-disk session key cache when `--session-file` is given
+    pub fn session_cache(&self) -> Option<SessionCache> {
+        self.session_file
+            .as_ref()
+            .map(|path| SessionCache::new(path, Duration::from_secs(self.session_ttl)))
+    }
+
+    /// Recorded frames to inject from `--inject-from`, read up front so a
+    /// bad capture fails before connecting
+    pub fn injection(&self) -> anyhow::Result<Option<Injection>> {
+        self.inject_from
+            .as_deref()
+            .map(|path| Injection::from_file(path, Duration::from_millis(self.inject_delay)))
+            .transpose()
+    }
+
+    pub fn handshake_options(&self) -> HandshakeOptions {
+        HandshakeOptions {
+            timeout: Duration::from_secs(self.handshake_timeout),
+            attempts: self.handshake_attempts,
+        }
+    }
 }
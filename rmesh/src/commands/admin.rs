@@ -1,13 +1,16 @@
 use crate::cli::AdminCommands;
 use crate::output::OutputFormat;
-use crate::utils::{print_error, print_success, print_warning};
+use crate::utils::{print_error, print_info, print_success, print_warning};
 use anyhow::{Result, bail};
 use colored::*;
-use rmesh_core::{ConnectionManager, device};
+use rmesh_core::admin::AdminDestination;
+use rmesh_core::device::FactoryResetScope;
+use rmesh_core::{ConnectionManager, admin, config, device};
 
 pub async fn handle_admin(
     mut connection: ConnectionManager,
     subcommand: AdminCommands,
+    allow_broadcast: bool,
     _format: OutputFormat,
 ) -> Result<()> {
     match subcommand {
@@ -22,9 +25,26 @@ pub async fn handle_admin(
             print_success("Reboot command sent. Device will restart in 5 seconds.");
         }
 
-        AdminCommands::FactoryReset { confirm } => {
+        AdminCommands::FactoryReset {
+            config_only,
+            full: _,
+            confirm,
+        } => {
+            let scope = if config_only {
+                FactoryResetScope::Config
+            } else {
+                FactoryResetScope::Device
+            };
+
             if !confirm {
-                print_error("FACTORY RESET WILL ERASE ALL SETTINGS!");
+                match scope {
+                    FactoryResetScope::Config => {
+                        print_error("FACTORY RESET WILL ERASE ALL CONFIG AND CHANNELS!")
+                    }
+                    FactoryResetScope::Device => print_error(
+                        "FACTORY RESET WILL ERASE ALL SETTINGS, THE NODE DATABASE AND KEYS!",
+                    ),
+                }
                 println!(
                     "{message}",
                     message = "This operation cannot be undone.".red().bold()
@@ -33,9 +53,16 @@ pub async fn handle_admin(
                 bail!("Operation cancelled");
             }
 
-            print_warning("Sending factory reset command...");
-            device::factory_reset_device(&mut connection).await?;
-            print_success("Factory reset command sent. Device will reset to defaults.");
+            print_warning(&format!("Sending {scope} factory reset command..."));
+            device::factory_reset(&mut connection, scope).await?;
+            match scope {
+                FactoryResetScope::Config => print_success(
+                    "Factory reset command sent. Config will reset to defaults; nodes and keys are kept.",
+                ),
+                FactoryResetScope::Device => {
+                    print_success("Factory reset command sent. Device will reset to defaults.")
+                }
+            }
         }
 
         AdminCommands::Shutdown { confirm } => {
@@ -48,7 +75,84 @@ pub async fn handle_admin(
             device::shutdown_device(&mut connection, Some(5)).await?;
             print_success("Shutdown command sent. Device will power off in 5 seconds.");
         }
+
+        AdminCommands::ResetNodedb { dest, confirm } => {
+            if !confirm {
+                print_warning("Resetting the node database removes every known node.");
+                print_warning("Use --confirm to proceed with the node database reset.");
+                bail!("Operation cancelled");
+            }
+
+            let target = resolve_destination(dest, allow_broadcast)?;
+            print_warning(&format!("Sending node database reset to {target}..."));
+            device::reset_node_db(&mut connection, target).await?;
+            print_success(
+                "Node database reset sent. The node will reboot with an empty node list.",
+            );
+        }
+
+        AdminCommands::ResetPosition { dest, confirm } => {
+            if !confirm {
+                print_warning("Position reset requires confirmation. Use --confirm to proceed.");
+                bail!("Operation cancelled");
+            }
+
+            let target = resolve_destination(dest, allow_broadcast)?;
+            print_warning(&format!("Removing fixed position on {target}..."));
+            device::remove_fixed_position(&mut connection, target).await?;
+            print_success("Fixed position removed. The node will report its GPS position, if any.");
+        }
+
+        AdminCommands::SetRole {
+            role,
+            dest,
+            confirm,
+            infrastructure,
+        } => {
+            let role = config::parse_role(&role)?;
+            let target = resolve_destination(dest, allow_broadcast)?;
+            let state = connection.get_device_state().await;
+            let advice =
+                admin::role_advice(role, (target == AdminDestination::Local).then_some(&state));
+
+            print_info(&format!(
+                "{role}: {summary}",
+                role = advice.role.bold(),
+                summary = advice.summary
+            ));
+            for warning in &advice.warnings {
+                print_warning(warning);
+            }
+
+            if advice.requires_acknowledgement && !infrastructure {
+                print_error(&format!(
+                    "{role} is an infrastructure role and affects the whole mesh.",
+                    role = advice.role
+                ));
+                print_warning("Use --infrastructure together with --confirm to proceed.");
+                bail!("Operation cancelled");
+            }
+
+            if !confirm {
+                print_warning("Role change requires confirmation. Use --confirm to proceed.");
+                bail!("Operation cancelled");
+            }
+
+            print_warning(&format!(
+                "Setting role {role} on {target}...",
+                role = advice.role
+            ));
+            admin::set_device_role(&mut connection, role, target).await?;
+            print_success("Role change sent. The node will reboot to apply it.");
+        }
     }
 
     Ok(())
 }
+
+/// Resolve `--dest`, explaining the override when a broadcast is refused
+fn resolve_destination(dest: Option<u32>, allow_broadcast: bool) -> Result<AdminDestination> {
+    AdminDestination::resolve(dest, allow_broadcast).inspect_err(|_| {
+        print_warning("Pass --i-know-what-im-doing to send it to every node anyway.");
+    })
+}
@@ -1,8 +1,11 @@
 use crate::cli::ChannelCommands;
-use crate::output::{OutputFormat, print_output};
-use crate::utils::{print_error, print_info, print_success};
+use crate::output::{OutputFormat, detailed_channel_table, format_position_precision, render};
+use crate::utils::secret::read_psk;
+use crate::utils::{print_error, print_info, print_success, print_warning};
 use anyhow::Result;
 use rmesh_core::ConnectionManager;
+use rmesh_core::channel::ChannelInfo;
+use rmesh_core::channel_set::ChannelSet;
 
 pub async fn handle_channel(
     mut connection: ConnectionManager,
@@ -10,41 +13,35 @@ pub async fn handle_channel(
     format: OutputFormat,
 ) -> Result<()> {
     match subcommand {
-        ChannelCommands::List => {
-            // List all channels
-            let channels = rmesh_core::channel::list_channels(&connection).await?;
-
-            match format {
-                OutputFormat::Json => print_output(&channels, format),
-                OutputFormat::Table => {
-                    if channels.is_empty() {
-                        print_info("No channels configured");
-                    } else {
-                        use comfy_table::{Cell, Table};
-                        let mut table = Table::new();
-                        table.set_header(vec![
-                            Cell::new("Index"),
-                            Cell::new("Name"),
-                            Cell::new("Role"),
-                            Cell::new("PSK"),
-                        ]);
-
-                        for channel in channels {
-                            table.add_row(vec![
-                                Cell::new(channel.index.to_string()),
-                                Cell::new(&channel.name),
-                                Cell::new(&channel.role),
-                                Cell::new(if channel.has_psk { "Yes" } else { "No" }),
-                            ]);
-                        }
-
-                        println!("{table}");
-                    }
-                }
+        ChannelCommands::List { refresh, detailed } => {
+            let channels = if refresh {
+                print_info("Requesting channels from the device...");
+                rmesh_core::channel::refresh_channels(
+                    &mut connection,
+                    rmesh_core::channel::CHANNEL_REQUEST_TIMEOUT,
+                )
+                .await?
+            } else {
+                rmesh_core::channel::list_channels(&connection).await?
+            };
+            if detailed && matches!(format, OutputFormat::Table) && !channels.is_empty() {
+                let slot = rmesh_core::channel::get_frequency_slot(&connection).await;
+                println!(
+                    "{table}",
+                    table = detailed_channel_table(&channels, slot.as_ref())
+                );
+            } else {
+                render(channels.as_slice(), format);
             }
         }
 
+        ChannelCommands::Audit => {
+            let audit = rmesh_core::channel::audit_channel_security(&connection).await?;
+            render(&audit, format);
+        }
+
         ChannelCommands::Add { name, psk } => {
+            let psk = read_psk(&psk)?;
             print_info(&format!("Adding channel '{name}'..."));
 
             // Add the channel
@@ -57,15 +54,7 @@ pub async fn handle_channel(
 
             // List channels to show the new one
             let channels = rmesh_core::channel::list_channels(&connection).await?;
-            match format {
-                OutputFormat::Json => print_output(&channels, format),
-                OutputFormat::Table => {
-                    print_info("Current channels:");
-                    for channel in channels {
-                        println!("  [{}] {} ({})", channel.index, channel.name, channel.role);
-                    }
-                }
-            }
+            render(channels.as_slice(), format);
         }
 
         ChannelCommands::Delete { index } => {
@@ -88,7 +77,9 @@ pub async fn handle_channel(
             psk,
             uplink,
             downlink,
+            position_precision,
         } => {
+            let psk = read_psk(&psk)?;
             print_info(&format!("Configuring channel at index {index}..."));
 
             // For now, we'll use the simpler set_channel that doesn't support uplink/downlink
@@ -98,17 +89,86 @@ pub async fn handle_channel(
             }
 
             // Set the channel configuration
-            rmesh_core::channel::set_channel(
+            let channel = rmesh_core::channel::set_channel(
                 &mut connection,
                 index,
                 name.as_deref(),
                 psk.as_deref(),
+                position_precision,
             )
             .await?;
 
             print_success(&format!("Channel {index} updated successfully"));
+            report_position_sharing(std::slice::from_ref(&channel));
+        }
+
+        ChannelCommands::Export {
+            file,
+            redact,
+            refresh,
+        } => {
+            if refresh {
+                print_info("Requesting channels from the device...");
+                rmesh_core::channel::refresh_channels(
+                    &mut connection,
+                    rmesh_core::channel::CHANNEL_REQUEST_TIMEOUT,
+                )
+                .await?;
+            }
+
+            let set = rmesh_core::channel_set::export_channels(&connection, !redact).await;
+            set.save(&file)?;
+            print_success(&format!(
+                "Saved {count} channel slots to {path}",
+                count = set.channels.len(),
+                path = file.display()
+            ));
+            if !redact {
+                print_warning("The file holds the channel PSKs; share it only with trusted users");
+            }
+            report_position_sharing(&rmesh_core::channel::list_channels(&connection).await?);
+        }
+
+        ChannelCommands::Import { file } => {
+            let set = ChannelSet::load(&file)?;
+            print_info(&format!(
+                "Writing {count} channel slots from {path}...",
+                count = set.channels.len(),
+                path = file.display()
+            ));
+
+            let written = rmesh_core::channel_set::import_channels(&mut connection, &set).await?;
+            print_success(&format!("Imported {written} channel slots"));
+
+            let channels = rmesh_core::channel::list_channels(&connection).await?;
+            render(channels.as_slice(), format);
+            report_position_sharing(&channels);
         }
     }
 
     Ok(())
 }
+
+/// Print how precisely each channel shares positions, warning about exact
+/// positions on channels anyone can read
+fn report_position_sharing(channels: &[ChannelInfo]) {
+    for channel in channels.iter().filter(|channel| channel.role != "Disabled") {
+        if channel.position_precision_m.is_none() {
+            continue;
+        }
+        print_info(&format!(
+            "Channel {index} ({name}) shares positions: {precision}",
+            index = channel.index,
+            name = channel.name,
+            precision = format_position_precision(channel.position_precision)
+        ));
+        if channel.exposes_position {
+            print_warning(&format!(
+                "Channel {index} ({name}) shares exact positions without a private PSK; \
+                 anyone in range can locate this node",
+                index = channel.index,
+                name = channel.name
+            ));
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use crate::cli::{Commands, CompatArgs, ConfigCommands, InfoCommands, MessageCommands};
+use anyhow::{Result, bail};
+use rmesh_core::config::ConfigAssignment;
+use rmesh_core::message::DEFAULT_ACK_TIMEOUT;
+use rmesh_core::node_id::parse_node_id;
+
+/// Broadcast destination in the Python CLI
+const PYTHON_BROADCAST_DEST: &str = "^all";
+
+/// Map Python meshtastic CLI flags to the equivalent rmesh command
+pub fn translate(args: &CompatArgs) -> Result<Commands> {
+    if let Some(text) = &args.sendtext {
+        return Ok(Commands::Message {
+            subcommand: MessageCommands::Send {
+                text: text.clone(),
+                dest: args.dest.as_deref().map(parse_dest).transpose()?.flatten(),
+                channel: args.ch_index,
+                channel_name: None,
+                ack: args.ack,
+                ack_timeout: DEFAULT_ACK_TIMEOUT.as_secs(),
+                retries: 0,
+            },
+        });
+    }
+
+    if let Some(key) = &args.get {
+        return Ok(Commands::Config {
+            subcommand: ConfigCommands::Get { key: key.clone() },
+        });
+    }
+
+    if let Some(set) = &args.set {
+        // Repeated --set flags are applied together, as by the Python CLI
+        let pairs = set
+            .chunks(2)
+            .map(|pair| match pair {
+                [key, value] => Ok(ConfigAssignment::new(key, value)),
+                _ => bail!("--set takes a key and a value"),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        return Ok(Commands::Config {
+            subcommand: ConfigCommands::Set {
+                pairs,
+                key: None,
+                value: None,
+                value_env: None,
+                value_file: None,
+                value_type: None,
+            },
+        });
+    }
+
+    if args.info {
+        return Ok(Commands::Info {
+            subcommand: InfoCommands::Radio { quality: false },
+        });
+    }
+
+    if args.nodes {
+        return Ok(Commands::Info {
+            subcommand: InfoCommands::Nodes {
+                limit: None,
+                offset: 0,
+                detailed: false,
+            },
+        });
+    }
+
+    bail!("No action given; use --sendtext, --get, --set, --info or --nodes")
+}
+
+/// Parse a Python CLI destination, `None` meaning broadcast
+fn parse_dest(dest: &str) -> Result<Option<u32>> {
+    if dest == PYTHON_BROADCAST_DEST {
+        return Ok(None);
+    }
+    Ok(Some(parse_node_id(dest)?))
+}
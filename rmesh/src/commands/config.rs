@@ -1,17 +1,16 @@
-use crate::cli::ConfigCommands;
+use crate::cli::{ConfigCommands, TelemetryConfigCommands};
 use crate::output::{OutputFormat, create_table, print_output};
-use crate::utils::{print_info, print_success};
-use anyhow::Result;
+use crate::utils::secret::read_secret;
+use crate::utils::{print_info, print_success, print_warning};
+use anyhow::{Context, Result, ensure};
 use colored::*;
 use comfy_table::Cell;
 use rmesh_core::ConnectionManager;
-use serde::Serialize;
-
-#[derive(Debug, Serialize)]
-struct ConfigValue {
-    key: String,
-    value: serde_json::Value,
-}
+use rmesh_core::config::{
+    ConfigAssignment, MIN_NEIGHBOR_INFO_INTERVAL_SECS, NeighborInfoUpdate, TelemetryConfigUpdate,
+};
+use rmesh_core::redact::{is_secret_field, redact_secrets, redact_value};
+use rmesh_core::state::TelemetryConfig;
 
 pub async fn handle_config(
     mut connection: ConnectionManager,
@@ -21,15 +20,19 @@ pub async fn handle_config(
     match subcommand {
         ConfigCommands::Get { key } => {
             // Use the core library function
-            let value = rmesh_core::config::get_config_value(&mut connection, &key).await?;
-
-            let config_value = ConfigValue {
-                key: key.clone(),
-                value,
-            };
+            let mut config_value =
+                rmesh_core::config::get_config_value(&mut connection, &key).await?;
+            // The value is printed as is in tables, so redact it here
+            if !crate::output::show_secrets() {
+                if is_secret_field(&config_value.key) {
+                    redact_value(&mut config_value.value);
+                } else {
+                    redact_secrets(&mut config_value.value);
+                }
+            }
 
             match format {
-                OutputFormat::Json => print_output(&config_value, format),
+                OutputFormat::Json | OutputFormat::Porcelain => print_output(&config_value, format),
                 OutputFormat::Table => {
                     let mut table = create_table();
                     table.set_header(vec![Cell::new("Key"), Cell::new("Value")]);
@@ -44,11 +47,41 @@ pub async fn handle_config(
             print_info(&format!("Configuration value for '{key}' retrieved"));
         }
 
-        ConfigCommands::Set { key, value } => {
-            // Use the core library function
-            rmesh_core::config::set_config_value(&mut connection, &key, &value).await?;
+        ConfigCommands::Set {
+            pairs,
+            key,
+            value,
+            value_env,
+            value_file,
+            value_type,
+        } => {
+            let (assignments, echo_value) = match key {
+                Some(key) => {
+                    // Values not typed on the command line are treated as secrets
+                    let echo_value = value.as_deref().is_some_and(|value| value != "-");
+                    let value = read_secret(
+                        "value",
+                        value,
+                        false,
+                        value_env.as_deref(),
+                        value_file.as_deref(),
+                    )?
+                    .context("No configuration value given")?;
+                    (vec![ConfigAssignment::new(key, value)], echo_value)
+                }
+                None => (pairs, true),
+            };
+
+            rmesh_core::config::set_config_values(&mut connection, &assignments, value_type)
+                .await?;
 
-            print_success(&format!("Configuration '{key}' set to '{value}'"));
+            for ConfigAssignment { key, value } in &assignments {
+                if echo_value {
+                    print_success(&format!("Configuration '{key}' set to '{value}'"));
+                } else {
+                    print_success(&format!("Configuration '{key}' set"));
+                }
+            }
             println!(
                 "{}",
                 "Note: Some settings may require a device reboot to take effect".yellow()
@@ -59,37 +92,213 @@ pub async fn handle_config(
             // Use the core library function
             let config = rmesh_core::config::list_config(&mut connection).await?;
 
+            if config.is_empty() {
+                print_warning(
+                    "No configuration data available. Device may not be fully synchronized.",
+                );
+            }
+
             match format {
-                OutputFormat::Json => print_output(&config, format),
+                OutputFormat::Json | OutputFormat::Porcelain => print_output(&config, format),
                 OutputFormat::Table => {
                     // Display configuration in a readable table format
-                    if let Some(obj) = config.as_object() {
+                    if let Ok(serde_json::Value::Object(obj)) = serde_json::to_value(&config) {
                         for (category, values) in obj {
-                            println!("\n{}", category.to_uppercase().bold().cyan());
+                            // Sections the device has not reported serialize as null
+                            let Some(cat_obj) = values.as_object() else {
+                                continue;
+                            };
 
-                            if let Some(cat_obj) = values.as_object() {
-                                let mut table = create_table();
-                                table.set_header(vec![Cell::new("Setting"), Cell::new("Value")]);
+                            println!("\n{}", category.to_uppercase().bold().cyan());
 
-                                for (key, value) in cat_obj {
-                                    let value_str = match value {
-                                        serde_json::Value::String(s) => s.clone(),
-                                        serde_json::Value::Null => "Not set".to_string(),
-                                        v => v.to_string(),
-                                    };
-                                    table.add_row(vec![Cell::new(key), Cell::new(&value_str)]);
-                                }
+                            let mut table = create_table();
+                            table.set_header(vec![Cell::new("Setting"), Cell::new("Value")]);
 
-                                println!("{table}");
+                            for (key, value) in cat_obj {
+                                let value_str = match value {
+                                    serde_json::Value::String(s) => s.clone(),
+                                    serde_json::Value::Null => "Not set".to_string(),
+                                    v => v.to_string(),
+                                };
+                                table.add_row(vec![Cell::new(key), Cell::new(&value_str)]);
                             }
+
+                            println!("{table}");
                         }
-                    } else {
-                        println!("No configuration available");
                     }
                 }
             }
         }
+
+        ConfigCommands::Fingerprint { verify } => {
+            let fingerprint = rmesh_core::fingerprint::config_fingerprint(&mut connection).await?;
+
+            match format {
+                OutputFormat::Json | OutputFormat::Porcelain => print_output(&fingerprint, format),
+                OutputFormat::Table => {
+                    let mut table = create_table();
+                    table.set_header(vec![Cell::new("Section"), Cell::new("Hash")]);
+                    for section in &fingerprint.sections {
+                        table.add_row(vec![Cell::new(&section.section), Cell::new(&section.hash)]);
+                    }
+                    println!("{table}");
+                    println!(
+                        "Fingerprint: {fingerprint}",
+                        fingerprint = fingerprint.fingerprint.bold()
+                    );
+                }
+            }
+
+            if let Some(expected) = verify {
+                ensure!(
+                    fingerprint.matches(&expected),
+                    "Configuration fingerprint {actual} does not match {expected}",
+                    actual = fingerprint.fingerprint
+                );
+                print_success("Configuration fingerprint matches");
+            }
+        }
+
+        ConfigCommands::NeighborInfo {
+            enabled,
+            disabled,
+            interval,
+        } => {
+            let update = NeighborInfoUpdate {
+                enabled: match (enabled, disabled) {
+                    (true, _) => Some(true),
+                    (_, true) => Some(false),
+                    _ => None,
+                },
+                update_interval: interval,
+            };
+
+            let config = if update.is_empty() {
+                let Some(config) = rmesh_core::config::get_neighbor_info(&connection).await else {
+                    print_warning("The device has not reported its NeighborInfo settings");
+                    return Ok(());
+                };
+                config
+            } else {
+                if let Some(issue) = rmesh_core::firmware::require_firmware(
+                    &connection.get_device_state().await,
+                    "The NeighborInfo module",
+                    rmesh_core::firmware::NEIGHBOR_INFO_FIRMWARE,
+                ) {
+                    print_warning(&issue.to_string());
+                }
+                if let Some(interval) = interval
+                    && interval < MIN_NEIGHBOR_INFO_INTERVAL_SECS
+                {
+                    print_warning(&format!(
+                        "Firmware 2.5 and later raise intervals below \
+                         {MIN_NEIGHBOR_INFO_INTERVAL_SECS} seconds to that minimum"
+                    ));
+                }
+                let config = rmesh_core::config::set_neighbor_info(&mut connection, update).await?;
+                print_success("NeighborInfo module updated");
+                config
+            };
+
+            match format {
+                OutputFormat::Json | OutputFormat::Porcelain => print_output(&config, format),
+                OutputFormat::Table => {
+                    let mut table = create_table();
+                    table.set_header(vec![Cell::new("Setting"), Cell::new("Value")]);
+                    table.add_row(vec![
+                        Cell::new("enabled"),
+                        Cell::new(config.enabled.to_string()),
+                    ]);
+                    table.add_row(vec![
+                        Cell::new("update_interval"),
+                        Cell::new(match config.update_interval {
+                            0 => "firmware default".to_string(),
+                            secs => format!("{secs} s"),
+                        }),
+                    ]);
+                    println!("{table}");
+                }
+            }
+        }
+
+        ConfigCommands::Telemetry { subcommand } => {
+            let config = match subcommand {
+                TelemetryConfigCommands::Show => {
+                    let Some(config) = rmesh_core::config::get_telemetry_config(&connection).await
+                    else {
+                        print_warning("The device has not reported its Telemetry settings");
+                        return Ok(());
+                    };
+                    config
+                }
+                TelemetryConfigCommands::Set {
+                    device_interval,
+                    environment_interval,
+                    environment_measurement,
+                    air_quality,
+                } => {
+                    let update = TelemetryConfigUpdate {
+                        device_update_interval: device_interval,
+                        environment_update_interval: environment_interval,
+                        environment_measurement_enabled: environment_measurement,
+                        air_quality_enabled: air_quality,
+                    };
+                    let config =
+                        rmesh_core::config::set_telemetry_config(&mut connection, update).await?;
+                    print_success("Telemetry module updated");
+                    config
+                }
+            };
+
+            match format {
+                OutputFormat::Json | OutputFormat::Porcelain => print_output(&config, format),
+                OutputFormat::Table => print_telemetry_config(&config),
+            }
+        }
     }
 
     Ok(())
 }
+
+fn print_telemetry_config(config: &TelemetryConfig) {
+    let interval = |secs: u32| match secs {
+        0 => "firmware default".to_string(),
+        secs => format!("{secs} s"),
+    };
+
+    let mut table = create_table();
+    table.set_header(vec![Cell::new("Setting"), Cell::new("Value")]);
+    for (setting, value) in [
+        (
+            "device_update_interval",
+            interval(config.device_update_interval),
+        ),
+        (
+            "environment_update_interval",
+            interval(config.environment_update_interval),
+        ),
+        (
+            "environment_measurement_enabled",
+            config.environment_measurement_enabled.to_string(),
+        ),
+        (
+            "air_quality_enabled",
+            config.air_quality_enabled.to_string(),
+        ),
+        (
+            "air_quality_interval",
+            interval(config.air_quality_interval),
+        ),
+        (
+            "power_measurement_enabled",
+            config.power_measurement_enabled.to_string(),
+        ),
+        (
+            "power_update_interval",
+            interval(config.power_update_interval),
+        ),
+    ] {
+        table.add_row(vec![Cell::new(setting), Cell::new(value)]);
+    }
+    println!("{table}");
+}
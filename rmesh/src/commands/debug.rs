@@ -0,0 +1,33 @@
+use crate::cli::{DebugCommands, DumpFormat};
+use crate::utils::{print_info, print_success};
+use anyhow::{Context, Result};
+use rmesh_core::ConnectionManager;
+
+pub async fn handle_debug(
+    mut connection: ConnectionManager,
+    subcommand: DebugCommands,
+) -> Result<()> {
+    match subcommand {
+        DebugCommands::SendToradio { hex } => {
+            let bytes = rmesh_core::debug::parse_hex(&hex)?;
+            let to_radio = rmesh_core::debug::decode_to_radio(&bytes)?;
+            print_info(&format!("Sending {to_radio:?}"));
+
+            rmesh_core::debug::send_to_radio(&mut connection, to_radio).await?;
+            print_success("ToRadio message sent");
+        }
+        DebugCommands::DumpState { format } => {
+            // Secrets are redacted like in any other output
+            let state = crate::output::to_redacted_value(&connection.get_device_state().await)?;
+            let dump = match format {
+                DumpFormat::Json => serde_json::to_string_pretty(&state)
+                    .context("Failed to serialize device state")?,
+                DumpFormat::Ron => ron::ser::to_string_pretty(&state, Default::default())
+                    .context("Failed to serialize device state")?,
+            };
+            println!("{dump}");
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,54 @@
+use crate::cli::DeviceCommands;
+use crate::output::{OutputFormat, render};
+use crate::utils::{print_info, print_success, print_warning};
+use anyhow::Result;
+use rmesh_core::ConnectionManager;
+use rmesh_core::clock::MAX_CLOCK_DRIFT_SECS;
+use std::time::Duration;
+
+pub async fn handle_device(
+    mut connection: ConnectionManager,
+    subcommand: DeviceCommands,
+    format: OutputFormat,
+) -> Result<()> {
+    match subcommand {
+        DeviceCommands::Clock { sync, timeout } => {
+            let timeout = Duration::from_secs(timeout);
+            let measured = rmesh_core::clock::measure_clock(&mut connection, timeout).await;
+
+            let report = if sync {
+                match measured.map(|report| report.drift_secs) {
+                    Ok(Some(drift)) => print_info(&format!(
+                        "Device clock is {drift:+}s off; setting it to this computer's time..."
+                    )),
+                    Ok(None) => print_info("Device has no valid time; setting it..."),
+                    Err(e) => print_warning(&format!("Could not read the device clock: {e}")),
+                }
+                rmesh_core::clock::sync_clock(&mut connection).await?;
+
+                // Read the clock back, since a device with GPS time may keep its own
+                let mut report = rmesh_core::clock::measure_clock(&mut connection, timeout).await?;
+                report.synced = true;
+                if report.needs_sync() {
+                    print_warning("The device did not take the new time");
+                } else {
+                    print_success("Device clock synchronized");
+                }
+                report
+            } else {
+                let report = measured?;
+                if report.needs_sync() {
+                    print_warning(&format!(
+                        "Device clock is unset or more than {MAX_CLOCK_DRIFT_SECS}s off; \
+                         run with --sync to correct it"
+                    ));
+                }
+                report
+            };
+
+            render(&report, format);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,33 @@
+use crate::output::{OutputFormat, render};
+use crate::utils::{print_info, print_success, print_warning};
+use anyhow::{Result, bail};
+use rmesh_core::doctor::CheckStatus;
+use std::time::Duration;
+
+pub async fn handle_doctor(
+    port: Option<&str>,
+    ble: Option<&str>,
+    timeout: Duration,
+    format: OutputFormat,
+) -> Result<()> {
+    print_info("Checking the connection environment...");
+    let report = rmesh_core::doctor::run_doctor(port, ble, timeout).await;
+
+    render(&report, format);
+
+    match report.status() {
+        CheckStatus::Failed => {
+            let failed = report
+                .checks
+                .iter()
+                .filter(|check| check.status == CheckStatus::Failed)
+                .count();
+            bail!("{failed} check(s) failed; see the Fix column for what to do");
+        }
+        CheckStatus::Warning => {
+            print_warning("The device is reachable, but see the warnings above")
+        }
+        CheckStatus::Ok | CheckStatus::Skipped => print_success("No problems found"),
+    }
+    Ok(())
+}
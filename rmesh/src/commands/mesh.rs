@@ -1,10 +1,27 @@
-use crate::cli::MeshCommands;
-use crate::output::{OutputFormat, create_table, print_output};
-use crate::utils::print_info;
-use anyhow::Result;
+use crate::cli::{LinkBudgetArgs, MeshCommands};
+use crate::output::{
+    OutputFormat, create_table, presence_cell, print_jsonl, print_output, print_porcelain,
+};
+use crate::utils::{format_time, print_info, print_warning};
+use anyhow::{Context, Result};
 use colored::*;
 use comfy_table::Cell;
 use rmesh_core::ConnectionManager;
+use rmesh_core::airtime::{
+    AirtimeSample, BUSY_CHANNEL_UTILIZATION, TX_AIRTIME_LIMIT, percent_bar, sparkline,
+};
+use rmesh_core::events::MeshEvent;
+use rmesh_core::geofence::parse_distance_m;
+use rmesh_core::link_budget::{
+    LinkSetup, LinkSource, LinkVerdict, PresetEstimate, RELIABLE_MARGIN_DB, estimate_presets,
+};
+use rmesh_core::lora::{ModemPreset, region_band};
+use rmesh_core::message::sanitize_for_terminal;
+use rmesh_core::node_id::NodeId;
+use rmesh_core::state::{ChangeRecord, RouteEntry};
+use rmesh_core::time::unix_now;
+use std::collections::HashMap;
+use tokio::sync::broadcast::error::RecvError;
 
 pub async fn handle_mesh(
     mut connection: ConnectionManager,
@@ -19,119 +36,86 @@ pub async fn handle_mesh(
             let topology = rmesh_core::mesh::get_topology(&connection).await?;
 
             match format {
-                OutputFormat::Json => print_output(&topology, format),
+                OutputFormat::Json | OutputFormat::Porcelain => print_output(&topology, format),
                 OutputFormat::Table => {
                     // Print network summary
-                    if let Some(my_node) = topology.get("my_node") {
+                    if let Some(my_node) = &topology.my_node {
                         println!("\n{title}", title = "My Node:".bold().cyan());
-                        if let Some(node_obj) = my_node.as_object() {
-                            if let Some(id) = node_obj.get("node_id").and_then(|v| v.as_str()) {
-                                println!("  ID: {id}");
-                            }
-                            if let Some(num) = node_obj.get("node_num").and_then(|v| v.as_u64()) {
-                                println!("  Number: {num:08x}");
-                            }
-                        }
+                        println!("  ID: {id}", id = my_node.node_id);
+                        println!("  Number: {num}", num = NodeId(my_node.node_num));
                     }
 
                     // Print nodes table
-                    if let Some(nodes) = topology.get("nodes").and_then(|n| n.as_array()) {
-                        println!(
-                            "\n{title}",
-                            title = format!("Network Nodes ({total} total):", total = nodes.len())
-                                .bold()
-                                .green()
-                        );
-
-                        let mut table = create_table();
-                        table.set_header(vec![
-                            Cell::new("Node ID"),
-                            Cell::new("Name"),
-                            Cell::new("SNR (dB)"),
-                            Cell::new("RSSI (dBm)"),
-                            Cell::new("Last Heard"),
-                        ]);
+                    println!(
+                        "\n{title}",
+                        title = format!(
+                            "Network Nodes ({total} total):",
+                            total = topology.total_nodes
+                        )
+                        .bold()
+                        .green()
+                    );
 
-                        for node in nodes {
-                            if let Some(obj) = node.as_object() {
-                                table.add_row(vec![
-                                    Cell::new(
-                                        obj.get("id").and_then(|v| v.as_str()).unwrap_or("unknown"),
-                                    ),
-                                    Cell::new(
-                                        obj.get("name")
-                                            .and_then(|v| v.as_str())
-                                            .unwrap_or("unknown"),
-                                    ),
-                                    Cell::new(
-                                        obj.get("snr")
-                                            .and_then(|v| v.as_f64())
-                                            .map(|s| format!("{s:.1}"))
-                                            .unwrap_or_else(|| "N/A".to_string()),
-                                    ),
-                                    Cell::new(
-                                        obj.get("rssi")
-                                            .and_then(|v| v.as_i64())
-                                            .map(|r| r.to_string())
-                                            .unwrap_or_else(|| "N/A".to_string()),
-                                    ),
-                                    Cell::new(
-                                        obj.get("last_heard")
-                                            .and_then(|v| v.as_u64())
-                                            .map(|h| {
-                                                let now = std::time::SystemTime::now()
-                                                    .duration_since(std::time::UNIX_EPOCH)
-                                                    .unwrap_or_default()
-                                                    .as_secs();
-                                                let ago = now.saturating_sub(h);
-                                                if ago < 60 {
-                                                    format!("{ago}s ago")
-                                                } else if ago < 3600 {
-                                                    format!("{minutes}m ago", minutes = ago / 60)
-                                                } else {
-                                                    format!("{hours}h ago", hours = ago / 3600)
-                                                }
-                                            })
-                                            .unwrap_or_else(|| "Never".to_string()),
-                                    ),
-                                ]);
-                            }
-                        }
+                    let mut table = create_table();
+                    table.set_header(vec![
+                        Cell::new("Node ID"),
+                        Cell::new("Name"),
+                        Cell::new("SNR (dB)"),
+                        Cell::new("RSSI (dBm)"),
+                        Cell::new("Status"),
+                        Cell::new("Last Heard"),
+                    ]);
 
-                        println!("{table}");
+                    for node in &topology.nodes {
+                        table.add_row(vec![
+                            Cell::new(&node.id),
+                            Cell::new(sanitize_for_terminal(&node.name)),
+                            Cell::new(
+                                node.snr
+                                    .map(|s| format!("{s:.1}"))
+                                    .unwrap_or_else(|| "N/A".to_string()),
+                            ),
+                            Cell::new(
+                                node.rssi
+                                    .map(|r| r.to_string())
+                                    .unwrap_or_else(|| "N/A".to_string()),
+                            ),
+                            presence_cell(node.last_heard),
+                            Cell::new(
+                                node.last_heard
+                                    .map(format_time)
+                                    .unwrap_or_else(|| "Never".to_string()),
+                            ),
+                        ]);
                     }
 
+                    println!("{table}");
+
                     // Print network edges if available
-                    if let Some(edges) = topology.get("edges").and_then(|e| e.as_array())
-                        && !edges.is_empty()
-                    {
+                    if !topology.edges.is_empty() {
                         println!(
                             "\n{title}",
-                            title = format!("Direct Connections ({count}):", count = edges.len())
-                                .bold()
-                                .blue()
+                            title = format!(
+                                "Direct Connections ({count}):",
+                                count = topology.edges.len()
+                            )
+                            .bold()
+                            .blue()
                         );
-                        for edge in edges {
-                            if let Some(obj) = edge.as_object() {
-                                let from = obj
-                                    .get("from")
-                                    .and_then(|v| v.as_str())
-                                    .unwrap_or("unknown");
-                                let to =
-                                    obj.get("to").and_then(|v| v.as_str()).unwrap_or("unknown");
-                                let snr = obj.get("snr").and_then(|v| v.as_f64());
-                                let rssi = obj.get("rssi").and_then(|v| v.as_i64());
-
-                                print!("  {from} → {to}", from = from.yellow(), to = to.yellow());
-                                if let Some(s) = snr {
-                                    print!(" (SNR: {snr:.1} dB", snr = s);
-                                    if let Some(r) = rssi {
-                                        print!(", RSSI: {r} dBm");
-                                    }
-                                    print!(")");
+                        for edge in &topology.edges {
+                            print!(
+                                "  {from} → {to}",
+                                from = edge.from.yellow(),
+                                to = edge.to.yellow()
+                            );
+                            if let Some(snr) = edge.snr {
+                                print!(" (SNR: {snr:.1} dB");
+                                if let Some(r) = edge.rssi {
+                                    print!(", RSSI: {r} dBm");
                                 }
-                                println!();
+                                print!(")");
                             }
+                            println!();
                         }
                     }
                 }
@@ -139,7 +123,10 @@ pub async fn handle_mesh(
         }
 
         MeshCommands::Traceroute { dest } => {
-            print_info(&format!("Performing traceroute to node {dest:08x}..."));
+            print_info(&format!(
+                "Performing traceroute to node {dest}...",
+                dest = NodeId(dest)
+            ));
 
             // Perform traceroute
             let hops = rmesh_core::mesh::traceroute(&mut connection, dest).await?;
@@ -153,11 +140,13 @@ pub async fn handle_mesh(
             }
 
             match format {
-                OutputFormat::Json => print_output(&hops, format),
+                OutputFormat::Json | OutputFormat::Porcelain => print_output(&hops, format),
                 OutputFormat::Table => {
                     println!(
                         "\n{title}",
-                        title = format!("Traceroute to {dest:08x}:").bold().green()
+                        title = format!("Traceroute to {dest}:", dest = NodeId(dest))
+                            .bold()
+                            .green()
                     );
 
                     let mut table = create_table();
@@ -172,8 +161,8 @@ pub async fn handle_mesh(
                     for hop in hops {
                         table.add_row(vec![
                             Cell::new(hop.hop_number),
-                            Cell::new(format!("{node_id:08x}", node_id = hop.node_id)),
-                            Cell::new(&hop.node_name),
+                            Cell::new(NodeId(hop.node_id).to_string()),
+                            Cell::new(sanitize_for_terminal(&hop.node_name)),
                             Cell::new(
                                 hop.snr
                                     .map(|s| format!("{s:.1} dB"))
@@ -192,6 +181,96 @@ pub async fn handle_mesh(
             }
         }
 
+        MeshCommands::Routes { watch, interval } => {
+            if !watch {
+                let routes = rmesh_core::mesh::get_routes(&connection).await;
+                match format {
+                    OutputFormat::Json => print_output(&routes, format),
+                    OutputFormat::Porcelain => {
+                        for route in &routes {
+                            print_porcelain(&route_porcelain_row(route));
+                        }
+                    }
+                    OutputFormat::Table if routes.is_empty() => print_warning(
+                        "No routes learned yet; use --watch to record traceroutes as they arrive",
+                    ),
+                    OutputFormat::Table => print_routes(&connection, &routes).await,
+                }
+                return Ok(());
+            }
+
+            print_info(&format!(
+                "Recording routes, checking every {interval} seconds... Press Ctrl+C to stop"
+            ));
+            // Last seen time and observations of each route already printed
+            let mut reported: HashMap<u32, (u64, u32)> = HashMap::new();
+            loop {
+                let routes = rmesh_core::mesh::get_routes(&connection).await;
+                let updated: Vec<&RouteEntry> = routes
+                    .iter()
+                    .filter(|route| {
+                        reported.get(&route.destination)
+                            != Some(&(route.last_seen, route.observations))
+                    })
+                    .collect();
+
+                match format {
+                    OutputFormat::Json => {
+                        for route in &updated {
+                            print_jsonl("route", route)?;
+                        }
+                    }
+                    OutputFormat::Porcelain => {
+                        for route in &updated {
+                            print_porcelain(&route_porcelain_row(route));
+                        }
+                    }
+                    OutputFormat::Table if updated.is_empty() => {}
+                    OutputFormat::Table => print_routes(&connection, &routes).await,
+                }
+                for route in &routes {
+                    reported.insert(route.destination, (route.last_seen, route.observations));
+                }
+
+                tokio::time::sleep(std::time::Duration::from_secs(interval)).await;
+            }
+        }
+
+        MeshCommands::Events { since, watch } => {
+            let since = since.map(|span| unix_now().saturating_sub(span.as_secs()));
+            // Subscribe before reading the journal so no change falls between the two
+            let mut events = connection.subscribe_events();
+            let changes = rmesh_core::mesh::get_changes(&connection, since).await;
+
+            match format {
+                OutputFormat::Json if !watch => print_output(&changes, format),
+                OutputFormat::Table if changes.is_empty() && !watch => print_warning(
+                    "No changes seen yet; use --watch to record changes as they happen",
+                ),
+                OutputFormat::Table => print_changes(&changes),
+                _ => {
+                    for change in &changes {
+                        print_change(change, format)?;
+                    }
+                }
+            }
+            if !watch {
+                return Ok(());
+            }
+
+            print_info("Watching for changes... Press Ctrl+C to stop");
+            loop {
+                match events.recv().await {
+                    Ok(MeshEvent::MeshChanged(change)) => print_change(&change, format)?,
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(missed)) => {
+                        print_warning(&format!("Missed {missed} events while busy"));
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        }
+
         MeshCommands::Neighbors => {
             print_info("Finding direct mesh neighbors...");
 
@@ -204,7 +283,7 @@ pub async fn handle_mesh(
             }
 
             match format {
-                OutputFormat::Json => print_output(&neighbors, format),
+                OutputFormat::Json | OutputFormat::Porcelain => print_output(&neighbors, format),
                 OutputFormat::Table => {
                     println!(
                         "\n{title}",
@@ -220,13 +299,14 @@ pub async fn handle_mesh(
                         Cell::new("Name"),
                         Cell::new("SNR (dB)"),
                         Cell::new("RSSI (dBm)"),
+                        Cell::new("Status"),
                         Cell::new("Last Heard"),
                     ]);
 
                     for neighbor in neighbors {
                         table.add_row(vec![
                             Cell::new(&neighbor.id),
-                            Cell::new(&neighbor.user.long_name),
+                            Cell::new(sanitize_for_terminal(&neighbor.user.long_name)),
                             Cell::new(
                                 neighbor
                                     .snr
@@ -239,23 +319,11 @@ pub async fn handle_mesh(
                                     .map(|r| r.to_string())
                                     .unwrap_or_else(|| "N/A".to_string()),
                             ),
+                            presence_cell(neighbor.last_heard),
                             Cell::new(
                                 neighbor
                                     .last_heard
-                                    .map(|h| {
-                                        let now = std::time::SystemTime::now()
-                                            .duration_since(std::time::UNIX_EPOCH)
-                                            .unwrap_or_default()
-                                            .as_secs();
-                                        let ago = now.saturating_sub(h);
-                                        if ago < 60 {
-                                            format!("{ago}s ago")
-                                        } else if ago < 3600 {
-                                            format!("{minutes}m ago", minutes = ago / 60)
-                                        } else {
-                                            format!("{hours}h ago", hours = ago / 3600)
-                                        }
-                                    })
+                                    .map(format_time)
                                     .unwrap_or_else(|| "Never".to_string()),
                             ),
                         ]);
@@ -278,6 +346,10 @@ pub async fn handle_mesh(
                         if let Some(rssi) = stats.average_rssi {
                             println!("  Average RSSI: {rssi} dBm");
                         }
+                        println!(
+                            "  Duplicate Packets: {duplicates}",
+                            duplicates = stats.duplicate_packets
+                        );
                         use rmesh_core::mesh::MeshHealth;
                         let health_str = stats.mesh_health.to_string();
                         let colored_health = match stats.mesh_health {
@@ -291,7 +363,459 @@ pub async fn handle_mesh(
                 }
             }
         }
+
+        MeshCommands::Map { width, height } => {
+            let Some(map) = rmesh_core::map::get_node_map(&connection, width, height).await? else {
+                print_warning("No position data available to plot");
+                return Ok(());
+            };
+
+            match format {
+                OutputFormat::Json | OutputFormat::Porcelain => print_output(&map, format),
+                OutputFormat::Table => {
+                    println!(
+                        "\n{title}",
+                        title = format!(
+                            "Node Map around {lat:.5}, {lon:.5} (1 column ≈ {scale}):",
+                            lat = map.center_latitude,
+                            lon = map.center_longitude,
+                            scale = format_distance(map.meters_per_column)
+                        )
+                        .bold()
+                        .green()
+                    );
+                    for row in &map.rows {
+                        println!("{row}");
+                    }
+                    println!("  {center} = this node / map center", center = "@".cyan());
+
+                    if !map.legend.is_empty() {
+                        let mut table = create_table();
+                        table.set_header(vec![
+                            Cell::new("Symbol"),
+                            Cell::new("Node"),
+                            Cell::new("Name"),
+                            Cell::new("Distance"),
+                        ]);
+                        for entry in &map.legend {
+                            table.add_row(vec![
+                                Cell::new(entry.symbol),
+                                Cell::new(NodeId(entry.node_num).to_string()),
+                                Cell::new(sanitize_for_terminal(&entry.label)),
+                                Cell::new(format_distance(entry.distance_m)),
+                            ]);
+                        }
+                        println!("{table}");
+                    }
+                }
+            }
+        }
+
+        MeshCommands::Airtime {
+            watch,
+            interval,
+            width,
+        } => {
+            let mut samples = rmesh_core::airtime::get_airtime_history(&connection).await?;
+            if let Some(sample) =
+                rmesh_core::airtime::sample_airtime(&mut connection, AIRTIME_SAMPLE_TIMEOUT_SECS)
+                    .await?
+            {
+                samples.push(sample);
+            }
+
+            if !watch {
+                if samples.is_empty() {
+                    print_warning("No airtime telemetry available from the local node");
+                    return Ok(());
+                }
+                match format {
+                    OutputFormat::Json => print_output(&samples, format),
+                    OutputFormat::Porcelain => {
+                        for sample in &samples {
+                            print_porcelain(&airtime_porcelain_row(sample));
+                        }
+                    }
+                    OutputFormat::Table => print_airtime(&samples, width),
+                }
+                return Ok(());
+            }
+
+            print_info(&format!(
+                "Sampling airtime every {interval} seconds... Press Ctrl+C to stop"
+            ));
+            let mut reported: usize = 0;
+            loop {
+                // Keep only what the graphs show
+                let excess = samples.len().saturating_sub(width);
+                samples.drain(..excess);
+                reported = reported.saturating_sub(excess);
+
+                match format {
+                    OutputFormat::Json => {
+                        for sample in samples.iter().skip(reported) {
+                            print_jsonl("airtime", sample)?;
+                        }
+                    }
+                    OutputFormat::Porcelain => {
+                        for sample in samples.iter().skip(reported) {
+                            print_porcelain(&airtime_porcelain_row(sample));
+                        }
+                    }
+                    OutputFormat::Table => print_airtime(&samples, width),
+                }
+                reported = samples.len();
+
+                tokio::time::sleep(std::time::Duration::from_secs(interval)).await;
+                match rmesh_core::airtime::sample_airtime(
+                    &mut connection,
+                    AIRTIME_SAMPLE_TIMEOUT_SECS,
+                )
+                .await?
+                {
+                    Some(sample) => samples.push(sample),
+                    None => print_warning("No airtime figures in the latest telemetry"),
+                }
+            }
+        }
+
+        MeshCommands::LinkBudget(args) => {
+            // Only --node gets here; the other modes need no device
+            let node = args.node.context("Give --distance, --snr or --node")?;
+            let state = connection.get_device_state().await;
+            let snr = state
+                .nodes
+                .get(&node)
+                .and_then(|info| info.snr)
+                .with_context(|| {
+                    format!(
+                        "No SNR heard from {node} yet; try --snr or --distance",
+                        node = NodeId(node)
+                    )
+                })?;
+            let lora = state.lora_config.as_ref();
+            let args = LinkBudgetArgs {
+                preset: args
+                    .preset
+                    .or_else(|| lora.map(|lora| lora.modem_preset.clone())),
+                region: args.region.or_else(|| lora.map(|lora| lora.region.clone())),
+                snr: Some(f64::from(snr)),
+                ..args
+            };
+            handle_link_budget(&args, format)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Estimate a link with every modem preset; needs no device
+pub fn handle_link_budget(args: &LinkBudgetArgs, format: OutputFormat) -> Result<()> {
+    let preset_name = args.preset.as_deref().unwrap_or("LONG_FAST");
+    let preset = ModemPreset::from_name(preset_name)
+        .with_context(|| format!("Unknown modem preset '{preset_name}'"))?;
+    let region = args.region.as_deref().unwrap_or("US");
+    let band = region_band(region).with_context(|| format!("Unknown region '{region}'"))?;
+
+    let source = match (&args.distance, args.snr) {
+        (Some(distance), _) => LinkSource::Distance(parse_distance_m(distance)?),
+        (None, Some(snr_db)) => LinkSource::ObservedSnr { snr_db, preset },
+        (None, None) => anyhow::bail!("Give --distance, --snr or --node"),
+    };
+    let setup = LinkSetup {
+        tx_power_dbm: args.txpower,
+        antenna_gain_dbi: args.gain,
+        extra_loss_db: args.loss,
+        // Slots spread over the band, so its middle is close enough for path loss
+        frequency_mhz: (band.freq_start_mhz + band.freq_end_mhz) / 2.0,
+        payload_bytes: args.payload,
+        wide_lora: band.wide_lora,
+    };
+    let estimates = estimate_presets(source, &setup, preset);
+
+    match format {
+        OutputFormat::Json => print_output(&estimates, format),
+        OutputFormat::Porcelain => {
+            for estimate in &estimates {
+                print_porcelain(&link_budget_porcelain_row(estimate));
+            }
+        }
+        OutputFormat::Table => print_link_budget(&estimates, source),
+    }
+    Ok(())
+}
+
+/// Seconds to wait for the device to answer a metrics request
+const AIRTIME_SAMPLE_TIMEOUT_SECS: u64 = 10;
+
+/// Width of the percentage bars in the airtime view
+const AIRTIME_BAR_WIDTH: usize = 20;
+
+/// time, channel utilization, transmit airtime (empty when unknown)
+fn airtime_porcelain_row(sample: &AirtimeSample) -> Vec<String> {
+    let percent = |value: Option<f32>| value.map(|v| format!("{v:.2}")).unwrap_or_default();
+    vec![
+        sample.time.to_string(),
+        percent(sample.channel_utilization),
+        percent(sample.air_util_tx),
+    ]
+}
+
+/// destination, next hop, hop count, relays (comma separated), last seen
+fn print_changes(changes: &[ChangeRecord]) {
+    println!(
+        "\n{title}",
+        title = format!("Mesh Changes ({total}):", total = changes.len())
+            .bold()
+            .green()
+    );
+
+    let mut table = create_table();
+    table.set_header(vec![
+        Cell::new("Time"),
+        Cell::new("Change"),
+        Cell::new("Details"),
+    ]);
+    for record in changes {
+        table.add_row(vec![
+            Cell::new(format_time(record.time)),
+            Cell::new(record.change.kind()),
+            Cell::new(sanitize_for_terminal(&record.change.to_string())),
+        ]);
     }
 
+    println!("{table}");
+}
+
+/// Print one change as it is seen
+fn print_change(record: &ChangeRecord, format: OutputFormat) -> Result<()> {
+    match format {
+        OutputFormat::Json => print_jsonl("change", record)?,
+        OutputFormat::Porcelain => print_porcelain(&[
+            record.time.to_string(),
+            record.change.kind().to_string(),
+            record.change.to_string(),
+        ]),
+        OutputFormat::Table => println!(
+            "[{time}] {kind} {details}",
+            time = format_time(record.time),
+            kind = record.change.kind().cyan(),
+            details = sanitize_for_terminal(&record.change.to_string())
+        ),
+    }
     Ok(())
 }
+
+fn route_porcelain_row(route: &RouteEntry) -> Vec<String> {
+    vec![
+        NodeId(route.destination).to_string(),
+        NodeId(route.next_hop).to_string(),
+        route.hop_count.to_string(),
+        route
+            .path
+            .iter()
+            .map(|relay| NodeId(*relay).to_string())
+            .collect::<Vec<_>>()
+            .join(","),
+        route.last_seen.to_string(),
+    ]
+}
+
+async fn print_routes(connection: &ConnectionManager, routes: &[RouteEntry]) {
+    let state = connection.get_device_state_ref();
+    let state = state.lock().await;
+    let name = |node_num: u32| {
+        state
+            .get_node_by_num(node_num)
+            .map(|node| sanitize_for_terminal(&node.user.long_name).into_owned())
+            .unwrap_or_else(|| NodeId(node_num).to_string())
+    };
+
+    println!(
+        "\n{title}",
+        title = format!("Learned Routes ({total}):", total = routes.len())
+            .bold()
+            .green()
+    );
+
+    let mut table = create_table();
+    table.set_header(vec![
+        Cell::new("Destination"),
+        Cell::new("Name"),
+        Cell::new("Next Hop"),
+        Cell::new("Hops"),
+        Cell::new("Path"),
+        Cell::new("Seen"),
+        Cell::new("Last Seen"),
+    ]);
+
+    for route in routes {
+        let path = route
+            .path
+            .iter()
+            .chain([&route.destination])
+            .map(|node_num| name(*node_num))
+            .collect::<Vec<_>>()
+            .join(" > ");
+        table.add_row(vec![
+            Cell::new(format!(
+                "{destination}",
+                destination = NodeId(route.destination)
+            )),
+            Cell::new(name(route.destination)),
+            Cell::new(name(route.next_hop)),
+            Cell::new(route.hop_count),
+            Cell::new(path),
+            Cell::new(format!("{count}x", count = route.observations)),
+            Cell::new(format_time(route.last_seen)),
+        ]);
+    }
+
+    println!("{table}");
+}
+
+fn print_airtime(samples: &[AirtimeSample], width: usize) {
+    let shown = &samples[samples.len().saturating_sub(width)..];
+    let Some(latest) = shown.last() else {
+        print_warning("No airtime telemetry available from the local node");
+        return;
+    };
+
+    println!(
+        "\n{title}",
+        title = format!(
+            "Airtime at {time} ({count} samples):",
+            time = format_time(latest.time),
+            count = shown.len()
+        )
+        .bold()
+        .green()
+    );
+
+    let rows = [
+        (
+            "Channel utilization",
+            shown
+                .iter()
+                .map(|sample| sample.channel_utilization)
+                .collect::<Vec<_>>(),
+            BUSY_CHANNEL_UTILIZATION,
+        ),
+        (
+            "Transmit airtime",
+            shown.iter().map(|sample| sample.air_util_tx).collect(),
+            TX_AIRTIME_LIMIT,
+        ),
+    ];
+    for (label, values, limit) in rows {
+        let current = values.iter().rev().find_map(|value| *value);
+        // Scale graphs to the limit so a busy channel reaches the top
+        let ceiling = values
+            .iter()
+            .flatten()
+            .fold(limit, |max, value| max.max(*value));
+        let graph = sparkline(&values, ceiling);
+
+        let (bar, figure) = match current {
+            Some(percent) => (
+                percent_bar(percent, AIRTIME_BAR_WIDTH),
+                format!("{percent:5.1}%"),
+            ),
+            None => (" ".repeat(AIRTIME_BAR_WIDTH), "  N/A".to_string()),
+        };
+        let line = format!("{label:<20} [{bar}] {figure}  {graph}");
+        if current.is_some_and(|percent| percent > limit) {
+            println!("{line}", line = line.red());
+        } else {
+            println!("{line}", line = line.green());
+        }
+    }
+
+    if latest.is_congested() {
+        print_warning(&format!(
+            "Channel is congested (above {BUSY_CHANNEL_UTILIZATION}% utilization or \
+             {TX_AIRTIME_LIMIT}% transmit airtime); consider longer broadcast intervals"
+        ));
+    }
+}
+
+/// preset, selected, spread factor, bandwidth kHz, airtime ms, expected
+/// SNR dB, margin dB, verdict, max range m (empty from an observed SNR)
+fn link_budget_porcelain_row(estimate: &PresetEstimate) -> Vec<String> {
+    vec![
+        estimate.preset.to_string(),
+        estimate.selected.to_string(),
+        estimate.spread_factor.to_string(),
+        estimate.bandwidth_khz.to_string(),
+        format!("{ms:.1}", ms = estimate.airtime_ms),
+        format!("{snr:.1}", snr = estimate.expected_snr_db),
+        format!("{margin:.1}", margin = estimate.margin_db),
+        estimate.verdict.to_string(),
+        estimate
+            .max_range_m
+            .map(|range| format!("{range:.0}"))
+            .unwrap_or_default(),
+    ]
+}
+
+fn print_link_budget(estimates: &[PresetEstimate], source: LinkSource) {
+    let title = match source {
+        LinkSource::Distance(meters) => format!(
+            "Link budget over {distance} (free space)",
+            distance = format_distance(meters)
+        ),
+        LinkSource::ObservedSnr { snr_db, preset } => {
+            format!("Link budget from {snr_db:.1} dB SNR observed with {preset}")
+        }
+    };
+    println!("\n{title}", title = title.bold().green());
+
+    let mut table = create_table();
+    let mut header = vec![
+        Cell::new("Preset"),
+        Cell::new("SF"),
+        Cell::new("BW"),
+        Cell::new("Airtime"),
+        Cell::new("SNR"),
+        Cell::new("Margin"),
+        Cell::new("Verdict"),
+    ];
+    if matches!(source, LinkSource::Distance(_)) {
+        header.push(Cell::new("Max Range"));
+    }
+    table.set_header(header);
+
+    for estimate in estimates {
+        let marker = if estimate.selected { " *" } else { "" };
+        let verdict = match estimate.verdict {
+            LinkVerdict::Reliable => estimate.verdict.to_string().green(),
+            LinkVerdict::Marginal => estimate.verdict.to_string().yellow(),
+            LinkVerdict::Unlikely => estimate.verdict.to_string().red(),
+        };
+        let mut row = vec![
+            Cell::new(format!("{preset}{marker}", preset = estimate.preset)),
+            Cell::new(estimate.spread_factor),
+            Cell::new(format!("{bw} kHz", bw = estimate.bandwidth_khz)),
+            Cell::new(format!("{ms:.0} ms", ms = estimate.airtime_ms)),
+            Cell::new(format!("{snr:.1} dB", snr = estimate.expected_snr_db)),
+            Cell::new(format!("{margin:+.1} dB", margin = estimate.margin_db)),
+            Cell::new(verdict),
+        ];
+        if let Some(range) = estimate.max_range_m {
+            row.push(Cell::new(format_distance(range)));
+        }
+        table.add_row(row);
+    }
+    println!("{table}");
+    print_info(&format!(
+        "* marks the selected preset; {RELIABLE_MARGIN_DB} dB of margin rides out most fading"
+    ));
+}
+
+fn format_distance(meters: f64) -> String {
+    if meters >= 1000.0 {
+        format!("{km:.2} km", km = meters / 1000.0)
+    } else {
+        format!("{meters:.0} m")
+    }
+}
@@ -1,18 +1,19 @@
-use crate::cli::MessageCommands;
-use crate::output::{OutputFormat, print_output};
-use crate::utils::{print_info, print_success};
-use anyhow::Result;
-use colored::*;
+use crate::cli::{MessageCommands, TranscriptFormat};
+use crate::output::sink::{FileSink, MqttSink, Tee, TerminalSink, WebhookSink};
+use crate::output::{OutputFormat, print_output, print_porcelain, print_received_message};
+use crate::utils::notify::notify;
+use crate::utils::{confirm_prompt, print_info, print_success, print_warning, time_format};
+use anyhow::{Context, Result, bail};
 use rmesh_core::ConnectionManager;
-use serde::Serialize;
-
-#[derive(Debug, Serialize)]
-struct SentMessage {
-    pub text: String,
-    pub destination: String,
-    pub channel: u32,
-    pub acknowledged: Option<bool>,
-}
+use rmesh_core::admin::BROADCAST_NODE_NUM;
+use rmesh_core::alert::AlertLog;
+use rmesh_core::channel::channel_index_by_name;
+use rmesh_core::message::{AckOptions, MessageFilter, SentMessage};
+use rmesh_core::node_id::NodeId;
+use rmesh_core::time::unix_now;
+use rmesh_core::transcript::{Transcript, prune_archive, read_archive};
+use std::path::Path;
+use std::time::Duration;
 
 pub async fn handle_message(
     mut connection: ConnectionManager,
@@ -24,48 +25,167 @@ pub async fn handle_message(
             text,
             dest,
             channel,
+            channel_name,
             ack,
+            ack_timeout,
+            retries,
         } => {
-            // Use the core library function
-            rmesh_core::message::send_text_message(&mut connection, &text, dest, channel, ack)
+            let channel = match channel_name {
+                Some(name) => {
+                    channel_index_by_name(&connection.get_device_state().await.channels, &name)?
+                }
+                None => channel,
+            };
+
+            let report = if ack {
+                if format == OutputFormat::Table {
+                    print_info("Waiting for acknowledgment...");
+                }
+                let options = AckOptions {
+                    timeout: Duration::from_secs(ack_timeout),
+                    retries,
+                };
+                let report = connection
+                    .send_text_with_ack(
+                        text.clone(),
+                        dest.unwrap_or(BROADCAST_NODE_NUM),
+                        channel,
+                        options,
+                    )
+                    .await?;
+                Some(report)
+            } else {
+                rmesh_core::message::send_text_message(
+                    &mut connection,
+                    &text,
+                    dest,
+                    channel,
+                    false,
+                )
                 .await?;
+                None
+            };
 
             let sent_msg = SentMessage {
                 text: text.clone(),
                 destination: dest
-                    .map(|d| format!("{d:08x}"))
+                    .map(|d| NodeId(d).to_string())
                     .unwrap_or_else(|| "Broadcast".to_string()),
                 channel,
-                acknowledged: if ack { Some(false) } else { None },
+                acknowledged: report.map(|report| report.acknowledged),
+                attempts: report.map(|report| report.attempts),
             };
 
             match format {
                 OutputFormat::Json => print_output(&sent_msg, format),
-                OutputFormat::Table => {
-                    print_success(&format!(
+                // destination, channel, ack state (sent/acked/unacked), text
+                OutputFormat::Porcelain => print_porcelain(&[
+                    sent_msg.destination.clone(),
+                    channel.to_string(),
+                    match report {
+                        None => "sent",
+                        Some(report) if report.acknowledged => "acked",
+                        Some(_) => "unacked",
+                    }
+                    .to_string(),
+                    text,
+                ]),
+                OutputFormat::Table => match report {
+                    None => print_success(&format!(
                         "Message sent to {destination} on channel {channel}",
                         destination = sent_msg.destination
-                    ));
-                    if ack {
-                        println!(
-                            "{message}",
-                            message = "Waiting for acknowledgment...".yellow()
-                        );
-                    }
+                    )),
+                    Some(report) if report.acknowledged => print_success(&format!(
+                        "Message to {destination} on channel {channel} acknowledged \
+                         after {attempts} transmission(s)",
+                        destination = sent_msg.destination,
+                        attempts = report.attempts
+                    )),
+                    Some(report) => print_warning(&format!(
+                        "No acknowledgment from {destination} after {attempts} \
+                         transmission(s) of {ack_timeout}s each",
+                        destination = sent_msg.destination,
+                        attempts = report.attempts
+                    )),
+                },
+            }
+        }
+
+        MessageCommands::Alert {
+            text,
+            dest,
+            channel,
+            confirm,
+            force,
+        } => {
+            if let Some(issue) = rmesh_core::firmware::require_firmware(
+                &connection.get_device_state().await,
+                "Critical alerts",
+                rmesh_core::firmware::ALERT_FIRMWARE,
+            ) {
+                print_warning(&issue.to_string());
+            }
+
+            let destination = dest
+                .map(|d| NodeId(d).to_string())
+                .unwrap_or_else(|| "every node".to_string());
+            if !confirm
+                && !confirm_prompt(&format!(
+                    "Send a critical alert to {destination} on channel {channel}?"
+                ))?
+            {
+                bail!("Operation cancelled");
+            }
+
+            let sent_msg = rmesh_core::alert::send_alert(
+                &mut connection,
+                &text,
+                dest,
+                channel,
+                &AlertLog::default(),
+                force,
+            )
+            .await?;
+
+            match format {
+                OutputFormat::Json => print_output(&sent_msg, format),
+                // destination, channel, ack state, text
+                OutputFormat::Porcelain => print_porcelain(&[
+                    sent_msg.destination,
+                    channel.to_string(),
+                    "sent".to_string(),
+                    text,
+                ]),
+                OutputFormat::Table => {
+                    print_success(&format!("Alert sent to {destination} on channel {channel}"))
                 }
             }
         }
 
-        MessageCommands::Recv { from, count } => {
+        MessageCommands::Recv {
+            from,
+            channel,
+            count,
+            raw,
+        } => {
             print_info("Receiving messages...");
 
+            let state = connection.get_device_state().await;
+            let filter = MessageFilter {
+                from,
+                channel,
+                own_node: state.my_node_info.map(|info| info.node_num),
+                ..Default::default()
+            };
+
             // Get packet receiver
             let mut receiver = connection.take_packet_receiver()?;
 
             // Use the core library function
             let messages = rmesh_core::message::receive_messages(
                 &mut receiver,
-                from,
+                &filter,
+                &state.channels,
                 if count == 0 { None } else { Some(count) },
                 30, // 30 second timeout
             )
@@ -75,63 +195,178 @@ pub async fn handle_message(
                 print_info("No messages received");
             } else {
                 match format {
-                    OutputFormat::Json => print_output(&messages, format),
+                    OutputFormat::Json | OutputFormat::Porcelain => print_output(&messages, format),
                     OutputFormat::Table => {
-                        for msg in messages {
-                            println!(
-                                "{from} [{channel}]: {text}",
-                                from = msg.from.blue().bold(),
-                                channel = msg.channel,
-                                text = msg.text
-                            );
-                            if let (Some(snr), Some(rssi)) = (msg.snr, msg.rssi) {
-                                println!(
-                                    "  {label} SNR: {snr:.1} dB, RSSI: {rssi} dBm",
-                                    label = "Signal:".dimmed()
-                                );
-                            }
+                        for msg in &messages {
+                            print_received_message(msg, raw);
                         }
                     }
                 }
             }
         }
 
-        MessageCommands::Monitor { from } => {
+        MessageCommands::Monitor {
+            from,
+            channel,
+            notify: notify_mode,
+            on_keyword,
+            jsonl,
+            raw,
+            output,
+            retain,
+            webhook,
+            mqtt,
+        } => {
+            if let Some(retain) = retain {
+                apply_retention(&connection, retain, output.as_deref()).await?;
+            }
+
+            // Open every output before listening so a bad path or URL fails early
+            let mut sinks = Tee::default();
+            sinks.push(TerminalSink { format, jsonl, raw });
+            if let Some(path) = &output {
+                sinks.push(FileSink::create(path)?);
+            }
+            for url in &webhook {
+                sinks.push(WebhookSink::new(url)?);
+            }
+            for url in &mqtt {
+                sinks.push(MqttSink::new(url)?);
+            }
+
             print_info("Monitoring messages... Press Ctrl+C to stop");
 
+            // Direct messages are addressed to our own node
+            let state = connection.get_device_state().await;
+            let my_node_num = state.my_node_info.map(|info| info.node_num);
+
+            let filter = MessageFilter {
+                from,
+                channel,
+                own_node: my_node_num,
+                ..Default::default()
+            };
+
             // Get packet receiver
             let mut receiver = connection.take_packet_receiver()?;
 
             // Use the core library function
-            rmesh_core::message::monitor_messages(&mut receiver, from, |msg| {
-                match format {
-                    OutputFormat::Json => {
-                        if let Ok(json) = serde_json::to_string(&msg) {
-                            println!("{json}");
-                        }
-                    }
-                    OutputFormat::Table => {
-                        println!(
-                            "{from} [{channel}]: {text}",
-                            from = msg.from.blue().bold(),
-                            channel = msg.channel,
-                            text = msg.text
-                        );
-                        if let (Some(snr), Some(rssi)) = (msg.snr, msg.rssi) {
-                            println!(
-                                "  {label} SNR: {snr:.1} dB, RSSI: {rssi} dBm",
-                                label = "Signal:".dimmed(),
-                                snr = snr,
-                                rssi = rssi
-                            );
-                        }
+            rmesh_core::message::monitor_messages(&mut receiver, &filter, &state.channels, |msg| {
+                // Never notify about our own messages echoing back
+                if let Some(mode) = notify_mode
+                    && !msg.own
+                {
+                    let is_direct = my_node_num.is_some_and(|num| msg.to_node == num);
+                    if is_direct || msg.contains_keyword(&on_keyword) {
+                        let title = if is_direct {
+                            format!("Direct message from {from}", from = msg.from)
+                        } else {
+                            format!("Message from {from}", from = msg.from)
+                        };
+                        notify(mode, &title, &msg.text);
                     }
                 }
-                Ok(())
+
+                sinks.write(&msg)
             })
             .await?;
         }
+
+        MessageCommands::Export {
+            input,
+            format: transcript_format,
+            since,
+            output,
+        } => {
+            let messages = read_archive(&input)?;
+            let since = since.map(|span| unix_now().saturating_sub(span.as_secs()));
+            // The log only has node ids; name senders after the node database
+            let state = connection.get_device_state().await;
+            let transcript = Transcript::build(&messages, since, &state.nodes);
+            if transcript.conversations.is_empty() {
+                print_warning("No messages to export");
+                return Ok(());
+            }
+
+            let rendered = match transcript_format {
+                TranscriptFormat::Html => transcript.to_html(time_format()),
+                TranscriptFormat::Txt => transcript.to_text(time_format()),
+            };
+            match output {
+                Some(path) => {
+                    std::fs::write(&path, rendered).with_context(|| {
+                        format!("Failed to write {path}", path = path.display())
+                    })?;
+                    print_success(&format!(
+                        "Exported {count} message(s) in {conversations} conversation(s) to {path}",
+                        count = transcript.message_count(),
+                        conversations = transcript.conversations.len(),
+                        path = path.display()
+                    ));
+                }
+                None => print!("{rendered}"),
+            }
+        }
+
+        // Handled before connecting, since it only touches the log file
+        MessageCommands::Clear { .. } => {}
+    }
+
+    Ok(())
+}
+
+/// Delete messages from a log written by `message monitor --output`
+pub fn handle_clear(
+    input: &Path,
+    older_than: Option<Duration>,
+    confirm: bool,
+    format: OutputFormat,
+) -> Result<()> {
+    if older_than.is_none()
+        && !confirm
+        && !confirm_prompt(&format!(
+            "Delete every message logged in {path}?",
+            path = input.display()
+        ))?
+    {
+        bail!("Operation cancelled");
     }
 
+    let cutoff = older_than.map(|age| unix_now().saturating_sub(age.as_secs()));
+    let report = prune_archive(input, cutoff)?;
+    match format {
+        OutputFormat::Table => print_success(&format!(
+            "Deleted {removed} message(s) from {path}, kept {kept}",
+            removed = report.removed,
+            path = input.display(),
+            kept = report.kept
+        )),
+        _ => print_output(report, format),
+    }
+    Ok(())
+}
+
+/// Forget cached messages older than `retain` and prune them from the log
+async fn apply_retention(
+    connection: &ConnectionManager,
+    retain: Duration,
+    log: Option<&Path>,
+) -> Result<()> {
+    let mut policy = connection.get_device_state().await.retention;
+    policy.max_message_age_secs = Some(retain.as_secs());
+    connection.set_retention_policy(policy).await;
+
+    // A log not written yet has nothing to prune
+    if let Some(path) = log.filter(|path| path.exists()) {
+        let cutoff = unix_now().saturating_sub(retain.as_secs());
+        let report = prune_archive(path, Some(cutoff))?;
+        if report.removed > 0 {
+            print_info(&format!(
+                "Pruned {removed} message(s) past the retention from {path}",
+                removed = report.removed,
+                path = path.display()
+            ));
+        }
+    }
     Ok(())
 }
@@ -1,33 +1,180 @@
 mod admin;
 mod channel;
+pub(crate) mod compat;
 mod config;
+mod debug;
+mod device;
+mod doctor;
 mod info;
 mod mesh;
 mod message;
+mod monitor;
+mod mqtt_proxy;
+mod node;
 mod position;
+mod responder;
+mod schema;
+mod waypoint;
 
-use crate::cli::{Cli, Commands};
+use crate::cli::{
+    ChannelCommands, Cli, Commands, InfoCommands, MeshCommands, MessageCommands, TelemetryType,
+    WaypointCommands,
+};
 use crate::output::OutputFormat;
+use crate::utils::secret::read_secret;
 use anyhow::Result;
 use rmesh_core::ConnectionManager;
+use rmesh_core::responder::Responder;
+use rmesh_core::state::StateSlice;
+use std::time::{Duration, Instant};
+
+pub async fn handle_command(mut cli: Cli) -> Result<()> {
+    let started = Instant::now();
+
+    // Python-style flags run the rmesh command they correspond to
+    if let Commands::Compat(args) = &cli.command {
+        cli.command = compat::translate(args)?;
+    }
+
+    crate::utils::set_quiet(cli.quiet);
+    crate::utils::set_time_format(cli.time_format);
+    crate::output::set_fields(cli.fields.clone());
+    crate::output::set_show_secrets(cli.show_secrets);
+
+    // Schemas are static and need no device
+    if let Commands::Schema { command } = &cli.command {
+        return schema::handle_schema(command);
+    }
 
-pub async fn handle_command(cli: Cli) -> Result<()> {
     // Determine output format
-    let output_format = if cli.json {
+    let output_format = if cli.porcelain {
+        OutputFormat::Porcelain
+    } else if cli.json {
         OutputFormat::Json
     } else {
         OutputFormat::Table
     };
 
+    // The doctor makes its own connection attempt and reports its failure
+    if let Commands::Doctor = &cli.command {
+        return doctor::handle_doctor(
+            cli.port.as_deref(),
+            cli.ble.as_deref(),
+            cli.handshake_options().timeout,
+            output_format,
+        )
+        .await;
+    }
+
+    // A dry run only reads the file
+    if let Commands::Waypoint {
+        subcommand:
+            WaypointCommands::Import {
+                file,
+                dry_run: true,
+                ..
+            },
+    } = &cli.command
+    {
+        return waypoint::handle_dry_run(file, output_format);
+    }
+
+    // Message logs are pruned offline
+    if let Commands::Message {
+        subcommand:
+            MessageCommands::Clear {
+                input,
+                older_than,
+                confirm,
+            },
+    } = &cli.command
+    {
+        return message::handle_clear(input, *older_than, *confirm, output_format);
+    }
+
+    // Link budgets are computed offline unless they use a node's SNR
+    if let Commands::Mesh {
+        subcommand: MeshCommands::LinkBudget(args),
+    } = &cli.command
+        && args.node.is_none()
+    {
+        return mesh::handle_link_budget(args, output_format);
+    }
+
     // Establish connection
     let mut connection =
         ConnectionManager::new(cli.port.clone(), cli.ble.clone(), cli.timeout_duration()).await?;
+    connection.set_handshake_options(cli.handshake_options());
+    connection.set_listen_only(cli.listen_only);
+    connection.set_include_own(cli.include_own);
+    connection.set_simulation(cli.simulation_options());
+    connection.set_serial_wake(cli.serial_wake_options());
+    connection.set_session_cache(cli.session_cache());
+    connection.set_injection(cli.injection()?);
+
+    // Probes answer from before the connection is up, so a slow handshake
+    // reads as not ready rather than as a dead process
+    let health_server = match cli.health_listen {
+        Some(address) => Some(
+            rmesh_core::connection::health::serve_health(
+                address,
+                connection.health_probe(),
+                cli.health_max_packet_age.map(Duration::from_secs),
+            )
+            .await?,
+        ),
+        None => None,
+    };
 
     // Connect to the device
     connection.connect().await?;
 
+    // Settings written to mismatched firmware can be dropped without an error
+    for issue in rmesh_core::firmware::check_versions(&connection.get_device_state().await) {
+        crate::utils::print_warning(&issue.to_string());
+    }
+    if cli.listen_only {
+        crate::utils::print_info("Listen-only mode: nothing will be transmitted");
+    }
+    if cli.simulate {
+        crate::utils::print_info("Simulation mode: connected to a virtual mesh, not a radio");
+    }
+    if cli.refresh_nodes {
+        crate::utils::print_info(&format!(
+            "Asking nodes for their NodeInfo, waiting {secs}s for replies...",
+            secs = cli.refresh_wait
+        ));
+        let refresh = rmesh_core::device::refresh_nodes(
+            &mut connection,
+            Duration::from_secs(cli.refresh_wait),
+        )
+        .await?;
+        crate::utils::print_info(&format!(
+            "Heard from {count} node(s)",
+            count = refresh.updated.len()
+        ));
+    }
+
+    // A device still sending its state would make tables look empty
+    let needed = required_state(&cli.command);
+    if !needed.is_empty() {
+        wait_for_state(&connection, needed, cli.timeout_duration(), output_format).await;
+    }
+
+    // Under systemd with Type=notify, the service is up once the command
+    // has the state it needs
+    #[cfg(all(target_os = "linux", feature = "systemd"))]
+    let watchdog = notify_systemd_ready(
+        &connection,
+        cli.health_max_packet_age.map(Duration::from_secs),
+    );
+
+    // The manager is moved into the command, so keep a handle on its counters
+    let counters = connection.counters();
+    let show_stats = cli.stats;
+
     // Handle the specific command
-    match cli.command {
+    let result = match cli.command {
         Commands::Info { subcommand } => {
             info::handle_info(connection, subcommand, output_format).await
         }
@@ -46,15 +193,162 @@ pub async fn handle_command(cli: Cli) -> Result<()> {
         Commands::Mesh { subcommand } => {
             mesh::handle_mesh(connection, subcommand, output_format).await
         }
+        Commands::Node { subcommand } => {
+            node::handle_node(connection, subcommand, output_format).await
+        }
+        Commands::Device { subcommand } => {
+            device::handle_device(connection, subcommand, output_format).await
+        }
+        Commands::Waypoint { subcommand } => {
+            waypoint::handle_waypoint(connection, subcommand, output_format).await
+        }
+        Commands::Telemetry {
+            subcommand: Some(subcommand),
+            ..
+        } => info::handle_telemetry_command(connection, subcommand, output_format).await,
         Commands::Telemetry {
             telemetry_type,
             dest,
+            ..
         } => {
-            // Handle telemetry command
+            // Required by clap unless a subcommand is given
+            let telemetry_type = telemetry_type.unwrap_or(TelemetryType::Device);
             info::handle_telemetry(connection, telemetry_type, dest, output_format).await
         }
-        Commands::Admin { subcommand } => {
-            admin::handle_admin(connection, subcommand, output_format).await
+        Commands::Admin {
+            subcommand,
+            allow_broadcast,
+        } => admin::handle_admin(connection, subcommand, allow_broadcast, output_format).await,
+        Commands::Responder {
+            pattern,
+            reply,
+            dm_only,
+            cooldown,
+        } => {
+            let responder =
+                Responder::new(&pattern, &reply, dm_only, Duration::from_secs(cooldown));
+            responder::handle_responder(connection, responder, output_format).await
+        }
+        Commands::Monitor { subcommand } => {
+            monitor::handle_monitor(connection, subcommand, output_format).await
+        }
+        Commands::Debug { subcommand } => debug::handle_debug(connection, subcommand).await,
+        Commands::MqttProxy {
+            broker,
+            username,
+            password,
+            prompt_password,
+            password_env,
+            password_file,
+            tls,
+        } => {
+            let password = read_secret(
+                "password",
+                password,
+                prompt_password,
+                password_env.as_deref(),
+                password_file.as_deref(),
+            )?;
+            let overrides = mqtt_proxy::BrokerOverrides {
+                broker,
+                username,
+                password,
+                tls,
+            };
+            mqtt_proxy::handle_mqtt_proxy(connection, overrides, output_format).await
+        }
+        Commands::Schema { command } => schema::handle_schema(&command),
+        // Handled before connecting, since the doctor makes its own connection
+        Commands::Doctor => Ok(()),
+        // Translated into another command before dispatch
+        Commands::Compat(_) => Ok(()),
+    };
+
+    if let Some(server) = health_server {
+        server.abort();
+    }
+    #[cfg(all(target_os = "linux", feature = "systemd"))]
+    notify_systemd_stopping(watchdog);
+    if show_stats {
+        crate::utils::print_stats(&counters.snapshot(), started.elapsed());
+    }
+    result
+}
+
+/// Send READY=1 to systemd and start watchdog heartbeats if it expects them
+#[cfg(all(target_os = "linux", feature = "systemd"))]
+fn notify_systemd_ready(
+    connection: &ConnectionManager,
+    max_packet_age: Option<Duration>,
+) -> Option<tokio::task::JoinHandle<()>> {
+    use rmesh_core::systemd;
+
+    if let Err(e) = systemd::notify("READY=1\nSTATUS=Connected to the device") {
+        crate::utils::print_warning(&format!("Failed to notify systemd: {e}"));
+    }
+    systemd::spawn_watchdog(connection.health_probe(), max_packet_age)
+}
+
+#[cfg(all(target_os = "linux", feature = "systemd"))]
+fn notify_systemd_stopping(watchdog: Option<tokio::task::JoinHandle<()>>) {
+    if let Some(watchdog) = watchdog {
+        watchdog.abort();
+    }
+    if let Err(e) = rmesh_core::systemd::notify("STOPPING=1") {
+        crate::utils::print_warning(&format!("Failed to notify systemd: {e}"));
+    }
+}
+
+/// State the output of a command is built from, waited for before it runs
+fn required_state(command: &Commands) -> &'static [StateSlice] {
+    match command {
+        Commands::Info {
+            subcommand: InfoCommands::Radio { .. },
+        } => &[
+            StateSlice::MyNodeInfo,
+            StateSlice::Metadata,
+            StateSlice::LoraConfig,
+        ],
+        Commands::Info {
+            subcommand: InfoCommands::Nodes { .. },
+        } => &[StateSlice::NodeDb],
+        Commands::Info {
+            subcommand: InfoCommands::Channels,
         }
+        | Commands::Channel {
+            subcommand: ChannelCommands::List { .. },
+        } => &[StateSlice::Channels],
+        Commands::Device { .. } => &[StateSlice::MyNodeInfo],
+        Commands::Message {
+            subcommand:
+                MessageCommands::Send {
+                    channel_name: Some(_),
+                    ..
+                },
+        } => &[StateSlice::Channels],
+        _ => &[],
+    }
+}
+
+/// Wait for the state a command needs, warning about what never arrived
+async fn wait_for_state(
+    connection: &ConnectionManager,
+    slices: &[StateSlice],
+    timeout: Duration,
+    format: OutputFormat,
+) {
+    let spinner = crate::utils::progress::spinner(format, "Waiting for the device state...");
+    let missing = connection.wait_for_state(slices, timeout).await;
+    spinner.finish_and_clear();
+
+    if !missing.is_empty() {
+        let missing = missing
+            .iter()
+            .map(StateSlice::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        crate::utils::print_warning(&format!(
+            "The device has not sent its {missing} yet; output may be incomplete"
+        ));
     }
 }
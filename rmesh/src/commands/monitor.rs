@@ -0,0 +1,64 @@
+use crate::cli::MonitorCommands;
+use crate::output::{OutputFormat, print_jsonl, to_json_line};
+use crate::utils::{format_time, print_info};
+use anyhow::Result;
+use colored::*;
+use rmesh_core::ConnectionManager;
+use rmesh_core::packet_filter::{PacketSummary, monitor_packets};
+
+/// Record kind of monitored packets in JSONL output
+const PACKET_KIND: &str = "packet";
+
+pub async fn handle_monitor(
+    mut connection: ConnectionManager,
+    subcommand: MonitorCommands,
+    format: OutputFormat,
+) -> Result<()> {
+    match subcommand {
+        MonitorCommands::Packets { filter, jsonl } => {
+            print_info("Monitoring packets... Press Ctrl+C to stop");
+
+            let mut receiver = connection.take_packet_receiver()?;
+            monitor_packets(&mut receiver, filter.as_ref(), |packet| {
+                if jsonl {
+                    return print_jsonl(PACKET_KIND, &packet);
+                }
+                match format {
+                    OutputFormat::Json | OutputFormat::Porcelain => {
+                        println!("{json}", json = to_json_line(&packet)?);
+                    }
+                    OutputFormat::Table => print_packet(&packet),
+                }
+                Ok(())
+            })
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Print a packet as a single line of its routing and signal fields
+fn print_packet(packet: &PacketSummary) {
+    let port = match &packet.port {
+        Some(port) => port.as_str().normal(),
+        None => "ENCRYPTED".yellow(),
+    };
+    let hops = packet
+        .hops
+        .map(|hops| format!(" hops {hops}"))
+        .unwrap_or_default();
+    let mqtt = if packet.via_mqtt { " mqtt" } else { "" };
+
+    println!(
+        "{time} {from} -> {to} ch{channel} {port} {size}B snr {snr:.1} rssi {rssi}{hops}{mqtt}",
+        time = format_time(packet.time).dimmed(),
+        from = packet.from.blue().bold(),
+        to = packet.to,
+        channel = packet.channel,
+        size = packet.size,
+        snr = packet.snr,
+        rssi = packet.rssi,
+        mqtt = mqtt.cyan()
+    );
+}
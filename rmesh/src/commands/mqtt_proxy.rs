@@ -0,0 +1,141 @@
+use crate::output::{OutputFormat, print_jsonl, print_porcelain};
+use crate::utils::mqtt;
+use crate::utils::{print_info, print_warning};
+use anyhow::{Context, Result, bail};
+use colored::*;
+use rmesh_core::ConnectionManager;
+use rmesh_core::message::sanitize_for_terminal;
+use rmesh_core::mqtt_proxy::{
+    BrokerSettings, ProxyDirection, ProxyMessage, ProxyTraffic, downlink, uplink,
+};
+use rumqttc::{ConnectionError, Event, Packet, QoS};
+
+/// Broker settings given on the command line, replacing the device's
+pub struct BrokerOverrides {
+    pub broker: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub tls: bool,
+}
+
+pub async fn handle_mqtt_proxy(
+    mut connection: ConnectionManager,
+    overrides: BrokerOverrides,
+    format: OutputFormat,
+) -> Result<()> {
+    let config = connection.get_device_state().await.mqtt_config;
+    match &config {
+        None => print_warning("Device did not report MQTT module settings; using defaults"),
+        Some(config) if !config.enabled => {
+            print_warning("The MQTT module is disabled on the device; nothing will be proxied")
+        }
+        Some(config) if !config.proxy_to_client_enabled => print_warning(
+            "The device's MQTT module is not set to client proxy; \
+             it will use its own network instead",
+        ),
+        Some(_) => {}
+    }
+
+    let mut config = config.unwrap_or_default();
+    config.tls_enabled |= overrides.tls;
+    if let Some(broker) = overrides.broker {
+        config.address = broker;
+    }
+    if let Some(username) = overrides.username {
+        config.username = username;
+    }
+    if let Some(password) = overrides.password {
+        config.password = password;
+    }
+    let settings = BrokerSettings::from_config(&config)?;
+
+    let (client, mut broker) = mqtt::connect(&mqtt::client_id("proxy"), &settings);
+    let mut packets = connection.take_packet_receiver()?;
+
+    print_info(&format!(
+        "Proxying MQTT for the device via {host}:{port}... Press Ctrl+C to stop",
+        host = settings.host,
+        port = settings.port
+    ));
+
+    loop {
+        tokio::select! {
+            frame = packets.recv() => {
+                let Some(frame) = frame else {
+                    bail!("Lost the connection to the device");
+                };
+                let Some(message) = uplink(&frame) else {
+                    continue;
+                };
+                report(ProxyDirection::Uplink, &message, format)?;
+                // Never wait on a full queue here: it only drains while the
+                // broker is polled below
+                if let Err(e) = client.try_publish(
+                    &message.topic,
+                    QoS::AtMostOnce,
+                    message.retained,
+                    message.payload,
+                ) {
+                    print_warning(&format!(
+                        "Dropped the message for {topic}: {e}",
+                        topic = message.topic
+                    ));
+                }
+            }
+            event = broker.poll() => match event {
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    // Subscriptions end with the session, so renew them on
+                    // every reconnect
+                    client
+                        .try_subscribe(&settings.subscription, QoS::AtMostOnce)
+                        .context("Failed to subscribe to the broker")?;
+                }
+                Ok(Event::Incoming(Packet::Publish(publish))) => {
+                    let message = ProxyMessage {
+                        topic: publish.topic,
+                        payload: publish.payload.to_vec(),
+                        retained: publish.retain,
+                    };
+                    report(ProxyDirection::Downlink, &message, format)?;
+                    downlink(&mut connection, message).await?;
+                }
+                Ok(_) => {}
+                Err(ConnectionError::ConnectionRefused(code)) => {
+                    bail!("The broker refused the connection ({code:?}); check the credentials");
+                }
+                Err(e) => {
+                    print_warning(&format!(
+                        "Broker connection failed: {e}; retrying in {delay}s",
+                        delay = mqtt::RECONNECT_DELAY.as_secs()
+                    ));
+                    tokio::time::sleep(mqtt::RECONNECT_DELAY).await;
+                }
+            }
+        }
+    }
+}
+
+fn report(direction: ProxyDirection, message: &ProxyMessage, format: OutputFormat) -> Result<()> {
+    let traffic = ProxyTraffic::new(direction, message);
+    match format {
+        OutputFormat::Json => print_jsonl("mqtt_proxy", &traffic)?,
+        OutputFormat::Porcelain => print_porcelain(&[
+            traffic.direction.to_string(),
+            traffic.topic,
+            traffic.bytes.to_string(),
+        ]),
+        OutputFormat::Table => {
+            let arrow = match direction {
+                ProxyDirection::Uplink => "device -> broker".cyan(),
+                ProxyDirection::Downlink => "broker -> device".green(),
+            };
+            println!(
+                "{arrow} {topic} ({bytes} bytes{retained})",
+                topic = sanitize_for_terminal(&traffic.topic),
+                bytes = traffic.bytes,
+                retained = if traffic.retained { ", retained" } else { "" }
+            );
+        }
+    }
+    Ok(())
+}
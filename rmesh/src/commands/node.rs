@@ -0,0 +1,40 @@
+use crate::cli::NodeCommands;
+use crate::output::{OutputFormat, print_output};
+use crate::utils::{print_info, print_success};
+use anyhow::Result;
+use rmesh_core::ConnectionManager;
+
+pub async fn handle_node(
+    mut connection: ConnectionManager,
+    subcommand: NodeCommands,
+    format: OutputFormat,
+) -> Result<()> {
+    match subcommand {
+        NodeCommands::Announce {
+            dest,
+            want_response,
+        } => {
+            let announcement =
+                rmesh_core::device::announce_node_info(&mut connection, dest, want_response)
+                    .await?;
+
+            match format {
+                OutputFormat::Json | OutputFormat::Porcelain => print_output(&announcement, format),
+                OutputFormat::Table => {
+                    print_success(&format!(
+                        "Sent NodeInfo of {long_name} ({short_name}, {node_id}) to {to}",
+                        long_name = announcement.long_name,
+                        short_name = announcement.short_name,
+                        node_id = announcement.node_id,
+                        to = announcement.to
+                    ));
+                    if want_response {
+                        print_info("Receivers will answer with their own NodeInfo");
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
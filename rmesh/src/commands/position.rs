@@ -1,10 +1,22 @@
 use crate::cli::PositionCommands;
-use crate::output::{OutputFormat, create_table, print_output};
-use crate::utils::{print_info, print_success, print_warning};
-use anyhow::Result;
+use crate::output::{OutputFormat, create_table, print_jsonl, print_output, to_json_line};
+use crate::utils::{format_time_str, print_info, print_success, print_warning};
+use anyhow::{Result, bail};
 use colored::*;
-use comfy_table::Cell;
+use comfy_table::{Cell, Table};
 use rmesh_core::ConnectionManager;
+use rmesh_core::admin::AdminDestination;
+use rmesh_core::events::MeshEvent;
+use rmesh_core::geofence::{
+    Geofence, GeofenceEvent, GeofenceMonitor, GeofenceTransition, parse_coordinates,
+    parse_distance_m,
+};
+use rmesh_core::node_id::NodeId;
+use rmesh_core::position::{set_fixed_position, verify_fixed_position};
+use rmesh_core::state::Position;
+use std::process::Command;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
 
 pub async fn handle_position(
     mut connection: ConnectionManager,
@@ -12,13 +24,13 @@ pub async fn handle_position(
     format: OutputFormat,
 ) -> Result<()> {
     match subcommand {
-        PositionCommands::Get { node } => {
+        PositionCommands::Get { node, detailed } => {
             // Use the core library function
             let position = rmesh_core::position::get_position(&connection, node).await?;
 
             if let Some(pos) = position {
                 match format {
-                    OutputFormat::Json => print_output(&pos, format),
+                    OutputFormat::Json | OutputFormat::Porcelain => print_output(&pos, format),
                     OutputFormat::Table => {
                         let mut table = create_table();
                         table.set_header(vec![Cell::new("Property"), Cell::new("Value")]);
@@ -39,7 +51,11 @@ pub async fn handle_position(
                             ]);
                         }
                         if let Some(time) = &pos.time {
-                            table.add_row(vec![Cell::new("Time"), Cell::new(time)]);
+                            table
+                                .add_row(vec![Cell::new("Time"), Cell::new(format_time_str(time))]);
+                        }
+                        if detailed {
+                            add_detail_rows(&mut table, &pos);
                         }
                         println!("{table}");
                     }
@@ -49,7 +65,13 @@ pub async fn handle_position(
             }
         }
 
-        PositionCommands::Set { lat, lon, alt } => {
+        PositionCommands::Set {
+            lat,
+            lon,
+            alt,
+            fixed: false,
+            ..
+        } => {
             // Use the core library function
             rmesh_core::position::set_position(&mut connection, lat, lon, alt).await?;
 
@@ -59,16 +81,85 @@ pub async fn handle_position(
             ));
         }
 
-        PositionCommands::Track { nodes } => {
+        PositionCommands::Set {
+            lat,
+            lon,
+            alt,
+            fixed: true,
+            dest,
+            no_verify,
+            verify_timeout,
+        } => {
+            let destination = AdminDestination::resolve(dest, false)?;
+            set_fixed_position(&mut connection, destination, lat, lon, alt).await?;
+            print_success(&format!(
+                "Fixed position {lat:.6}, {lon:.6}{altitude} sent to the {destination}",
+                altitude = alt.map(|a| format!(" at {a} m")).unwrap_or_default()
+            ));
+
+            let Some(node) = dest.filter(|_| !no_verify) else {
+                return Ok(());
+            };
+            print_info(&format!(
+                "Asking {node} for its position to verify...",
+                node = NodeId(node)
+            ));
+            let report = verify_fixed_position(
+                &mut connection,
+                node,
+                lat,
+                lon,
+                alt,
+                Duration::from_secs(verify_timeout),
+            )
+            .await?;
+
+            if format != OutputFormat::Table {
+                print_output(&report, format);
+            } else if let (Some(reported), Some(offset)) = (&report.reported, report.offset_m) {
+                print_info(&format!(
+                    "{node} now reports {lat:.6}, {lon:.6}, {offset:.0} m from the requested position",
+                    node = report.node,
+                    lat = reported.latitude,
+                    lon = reported.longitude
+                ));
+            }
+            match (&report.reported, report.verified) {
+                (_, true) => print_success(&format!(
+                    "{node} confirmed the position",
+                    node = report.node
+                )),
+                (Some(_), false) => bail!(
+                    "{node} reports a different position; it may not accept remote admin from this client",
+                    node = report.node
+                ),
+                (None, false) => bail!(
+                    "{node} did not report its position within {verify_timeout}s; the change is unverified",
+                    node = report.node
+                ),
+            }
+        }
+
+        PositionCommands::Track { nodes, jsonl } => {
             print_info("Starting position tracking...");
+
+            // Get packet receiver
+            let mut receiver = connection.take_packet_receiver()?;
+
+            if jsonl {
+                // Keep stdout line-oriented: stream updates as they arrive
+                rmesh_core::position::monitor_positions(&mut receiver, &nodes, 60, |pos| {
+                    print_jsonl("position", &pos)
+                })
+                .await?;
+                return Ok(());
+            }
+
             println!(
                 "{message}",
                 message = "Press Ctrl+C to stop tracking".yellow()
             );
 
-            // Get packet receiver
-            let mut receiver = connection.take_packet_receiver()?;
-
             // Use the core library function
             let positions = rmesh_core::position::track_positions(
                 &mut receiver,
@@ -81,7 +172,9 @@ pub async fn handle_position(
                 print_warning("No position updates received");
             } else {
                 match format {
-                    OutputFormat::Json => print_output(&positions, format),
+                    OutputFormat::Json | OutputFormat::Porcelain => {
+                        print_output(&positions, format)
+                    }
                     OutputFormat::Table => {
                         let mut table = create_table();
                         table.set_header(vec![
@@ -102,7 +195,12 @@ pub async fn handle_position(
                                         .map(|a| format!("{a} m"))
                                         .unwrap_or_else(|| "N/A".to_string()),
                                 ),
-                                Cell::new(pos.time.unwrap_or_else(|| "Unknown".to_string())),
+                                Cell::new(
+                                    pos.time
+                                        .as_deref()
+                                        .map(format_time_str)
+                                        .unwrap_or_else(|| "Unknown".to_string()),
+                                ),
                             ]);
                         }
 
@@ -113,7 +211,10 @@ pub async fn handle_position(
         }
 
         PositionCommands::Request { node, timeout } => {
-            print_info(&format!("Requesting position from node {node:08x}..."));
+            print_info(&format!(
+                "Requesting position from node {node}...",
+                node = NodeId(node)
+            ));
 
             // Use the core library function
             let position =
@@ -121,7 +222,7 @@ pub async fn handle_position(
 
             if let Some(pos) = position {
                 match format {
-                    OutputFormat::Json => print_output(&pos, format),
+                    OutputFormat::Json | OutputFormat::Porcelain => print_output(&pos, format),
                     OutputFormat::Table => {
                         let mut table = create_table();
                         table.set_header(vec![Cell::new("Property"), Cell::new("Value")]);
@@ -142,18 +243,138 @@ pub async fn handle_position(
                             ]);
                         }
                         if let Some(time) = &pos.time {
-                            table.add_row(vec![Cell::new("Time"), Cell::new(time)]);
+                            table
+                                .add_row(vec![Cell::new("Time"), Cell::new(format_time_str(time))]);
                         }
                         println!("{table}");
                     }
                 }
             } else {
                 print_warning(&format!(
-                    "No position response received from node {node:08x} (timeout: {timeout}s)"
+                    "No position response received from node {node} (timeout: {timeout}s)",
+                    node = NodeId(node)
                 ));
             }
         }
+
+        PositionCommands::Geofence {
+            center,
+            radius,
+            node,
+            exec,
+        } => {
+            let (latitude, longitude) = parse_coordinates(&center)?;
+            let fence = Geofence::new(latitude, longitude, parse_distance_m(&radius)?)?;
+            let mut monitor = GeofenceMonitor::new(fence, node);
+
+            // Subscribe before seeding so no update falls between the two
+            let mut events = connection.subscribe_events();
+
+            print_info(&format!(
+                "Watching geofence of {radius:.0} m around {latitude:.6}, {longitude:.6}... Press Ctrl+C to stop",
+                radius = fence.radius_m
+            ));
+
+            // Nodes already known to be inside report an enter event right away
+            let state = connection.get_device_state().await;
+            for position in state.positions.values() {
+                if let Some(event) = monitor.update(position) {
+                    report_geofence_event(&event, exec.as_deref(), format);
+                }
+            }
+
+            loop {
+                match events.recv().await {
+                    Ok(MeshEvent::PositionUpdated(position)) => {
+                        if let Some(event) = monitor.update(&position) {
+                            report_geofence_event(&event, exec.as_deref(), format);
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(missed)) => {
+                        print_warning(&format!("Missed {missed} events while busy"));
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        }
     }
 
     Ok(())
 }
+
+fn report_geofence_event(event: &GeofenceEvent, exec: Option<&str>, format: OutputFormat) {
+    match format {
+        OutputFormat::Json | OutputFormat::Porcelain => {
+            if let Ok(json) = to_json_line(event) {
+                println!("{json}");
+            }
+        }
+        OutputFormat::Table => {
+            let transition = match event.transition {
+                GeofenceTransition::Enter => "ENTER".green().bold(),
+                GeofenceTransition::Exit => "EXIT".red().bold(),
+            };
+            println!(
+                "{transition} {node} at {lat:.6}, {lon:.6} ({distance:.0} m from center)",
+                node = event.node_id.blue().bold(),
+                lat = event.latitude,
+                lon = event.longitude,
+                distance = event.distance_m
+            );
+        }
+    }
+
+    if let Some(command) = exec {
+        let result = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .env("RMESH_EVENT", event.transition.to_string())
+            .env("RMESH_NODE", &event.node_id)
+            .env("RMESH_LAT", event.latitude.to_string())
+            .env("RMESH_LON", event.longitude.to_string())
+            .env(
+                "RMESH_DISTANCE_M",
+                format!("{distance:.1}", distance = event.distance_m),
+            )
+            .status();
+
+        match result {
+            Ok(status) if !status.success() => {
+                print_warning(&format!("Geofence command exited with {status}"));
+            }
+            Ok(_) => {}
+            Err(e) => print_warning(&format!("Failed to run geofence command: {e}")),
+        }
+    }
+}
+
+/// Rows for the GPS details the firmware reports only with some position flags
+fn add_detail_rows(table: &mut Table, pos: &Position) {
+    let rows = [
+        (
+            "Ground Speed",
+            pos.ground_speed.map(|speed| format!("{speed} m/s")),
+        ),
+        (
+            "Heading",
+            pos.ground_track.map(|track| format!("{track:.0}°")),
+        ),
+        ("Satellites", pos.sats_in_view.map(|sats| sats.to_string())),
+        (
+            "Precision",
+            pos.precision_bits.map(|bits| format!("{bits} bits")),
+        ),
+        (
+            "Fix Quality",
+            pos.fix_quality.map(|quality| quality.to_string()),
+        ),
+        ("PDOP", pos.pdop.map(|pdop| format!("{pdop:.2}"))),
+    ];
+    for (label, value) in rows {
+        table.add_row(vec![
+            Cell::new(label),
+            Cell::new(value.as_deref().unwrap_or("unknown")),
+        ]);
+    }
+}
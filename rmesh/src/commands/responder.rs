@@ -0,0 +1,79 @@
+use crate::output::{OutputFormat, to_json_line};
+use crate::utils::{print_info, print_success, print_warning};
+use anyhow::{Context, Result};
+use colored::*;
+use rmesh_core::ConnectionManager;
+use rmesh_core::message::MessageFilter;
+use rmesh_core::node_id::NodeId;
+use rmesh_core::responder::{Responder, SentReply};
+
+pub async fn handle_responder(
+    mut connection: ConnectionManager,
+    mut responder: Responder,
+    format: OutputFormat,
+) -> Result<()> {
+    let state = connection.get_device_state().await;
+    let my_node_num = state
+        .my_node_info
+        .as_ref()
+        .map(|info| info.node_num)
+        .context("Local node information not available")?;
+
+    print_info("Responder running... Press Ctrl+C to stop");
+
+    let mut receiver = connection.take_packet_receiver()?;
+    let filter = MessageFilter {
+        own_node: Some(my_node_num),
+        ..Default::default()
+    };
+
+    while let Some(msg) =
+        rmesh_core::message::next_message(&mut receiver, &filter, &state.channels).await
+    {
+        let Some(reply) = responder.respond(&msg, my_node_num) else {
+            continue;
+        };
+
+        if let Err(e) = rmesh_core::message::send_text_message(
+            &mut connection,
+            &reply.text,
+            reply.destination,
+            reply.channel,
+            false,
+        )
+        .await
+        {
+            print_warning(&format!("Failed to reply to {from}: {e}", from = msg.from));
+            continue;
+        }
+
+        let to = reply
+            .destination
+            .map(|d| NodeId(d).to_string())
+            .unwrap_or_else(|| "Broadcast".to_string());
+
+        match format {
+            OutputFormat::Json | OutputFormat::Porcelain => {
+                let sent = SentReply {
+                    to,
+                    channel: reply.channel,
+                    trigger: msg.text.clone(),
+                    reply: reply.text.clone(),
+                };
+                if let Ok(json) = to_json_line(&sent) {
+                    println!("{json}");
+                }
+            }
+            OutputFormat::Table => {
+                print_success(&format!(
+                    "Replied to {from} via {to} [{channel}]: {text}",
+                    from = msg.from.blue().bold(),
+                    channel = reply.channel,
+                    text = reply.text
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
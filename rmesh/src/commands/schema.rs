@@ -0,0 +1,23 @@
+use anyhow::{Result, bail};
+use rmesh_core::schema::{SCHEMA_COMMANDS, command_schema};
+
+pub fn handle_schema(command: &[String]) -> Result<()> {
+    if command.is_empty() {
+        println!("Commands with a published --json schema:");
+        for name in SCHEMA_COMMANDS {
+            println!("  {name}");
+        }
+        return Ok(());
+    }
+
+    let name = command.join(" ");
+    let Some(schema) = command_schema(&name) else {
+        bail!(
+            "No schema for '{name}'. Available: {available}",
+            available = SCHEMA_COMMANDS.join(", ")
+        );
+    };
+
+    println!("{json}", json = serde_json::to_string_pretty(&schema)?);
+    Ok(())
+}
@@ -0,0 +1,71 @@
+use crate::cli::WaypointCommands;
+use crate::output::{OutputFormat, render};
+use crate::utils::{print_info, print_success, print_warning};
+use anyhow::{Result, ensure};
+use rmesh_core::ConnectionManager;
+use rmesh_core::waypoint::{WaypointImportStatus, load_geojson, plan_import};
+use std::path::Path;
+use std::time::Duration;
+
+pub async fn handle_waypoint(
+    mut connection: ConnectionManager,
+    subcommand: WaypointCommands,
+    format: OutputFormat,
+) -> Result<()> {
+    match subcommand {
+        WaypointCommands::Import {
+            file,
+            channel,
+            interval,
+            dry_run,
+        } => {
+            if dry_run {
+                return handle_dry_run(&file, format);
+            }
+
+            let waypoints = load_geojson(&file)?;
+            ensure!(
+                !waypoints.is_empty(),
+                "No waypoints in {path}",
+                path = file.display()
+            );
+
+            print_info(&format!(
+                "Sending {count} waypoint(s) on channel {channel}, {interval}s apart...",
+                count = waypoints.len()
+            ));
+            let results = rmesh_core::waypoint::import_waypoints(
+                &mut connection,
+                waypoints,
+                channel,
+                Duration::from_secs(interval),
+            )
+            .await?;
+
+            let failed = results
+                .iter()
+                .filter(|result| result.status == WaypointImportStatus::SendFailed)
+                .count();
+            render(results.as_slice(), format);
+
+            if failed > 0 {
+                print_warning(&format!("{failed} waypoint(s) could not be sent"));
+            } else {
+                print_success(&format!("Sent {count} waypoint(s)", count = results.len()));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// List the waypoints a file would import, without a device
+pub fn handle_dry_run(file: &Path, format: OutputFormat) -> Result<()> {
+    let results = plan_import(load_geojson(file)?);
+    render(results.as_slice(), format);
+    print_info(&format!(
+        "Dry run: {count} waypoint(s) would be sent",
+        count = results.len()
+    ));
+    Ok(())
+}
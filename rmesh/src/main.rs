@@ -5,16 +5,24 @@ mod utils;
 
 use anyhow::Result;
 use clap::Parser;
+use rmesh_core::connection::trace::{PACKET_TRACE_TARGET, set_trace_packets};
+use tracing_subscriber::fmt::format::FmtSpan;
 use tracing_subscriber::{EnvFilter, fmt, prelude::*};
 
 use crate::cli::Cli;
 use crate::commands::handle_command;
 
+#[cfg(test)]
+mod tests;
+
 #[tokio::main]
 async fn main() -> Result<()> {
     // Parse command line arguments
     let cli = Cli::parse();
 
+    // Decide on colors before anything is printed
+    utils::set_color_choice(cli.color);
+
     // Set up logging
     setup_logging(&cli);
 
@@ -31,13 +39,29 @@ fn setup_logging(cli: &Cli) {
         "warn"
     };
 
-    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(filter_level));
+    let mut filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(filter_level));
+    if cli.trace_packets {
+        set_trace_packets(true);
+        if let Ok(directive) = format!("{PACKET_TRACE_TARGET}=info").parse() {
+            filter = filter.add_directive(directive);
+        }
+    }
+
+    // Closing a packet span logs how long the send or the processing took
+    let span_events = if cli.trace_packets {
+        FmtSpan::CLOSE
+    } else {
+        FmtSpan::NONE
+    };
 
     let fmt_layer = fmt::layer()
         .with_writer(std::io::stderr)
+        .with_ansi(utils::colors_enabled())
         .with_target(false)
         .with_thread_ids(false)
-        .with_thread_names(false);
+        .with_thread_names(false)
+        .with_span_events(span_events);
 
     tracing_subscriber::registry()
         .with(filter)
@@ -1,32 +1,247 @@
-use comfy_table::Table;
+use anyhow::Result;
+use colored::*;
+use comfy_table::{Cell, Color, Table};
+use rmesh_core::message::{Encryption, ReceivedMessage, sanitize_for_terminal};
+use rmesh_core::presence::{NodePresence, PresencePolicy};
+use rmesh_core::schema::{OUTPUT_SCHEMA_VERSION, project_fields};
 use serde::Serialize;
+use serde_json::Value;
+use std::borrow::Cow;
+use std::io::Write;
+use std::sync::OnceLock;
+
+pub mod sink;
+mod tables;
+
+pub use tables::{detailed_channel_table, detailed_node_table, format_position_precision};
+
+/// Table layout of a command output, shown when `--json` is not given
+///
+/// Implemented once per output type in [`tables`], so a new field only
+/// needs adding to the type and its table.
+pub trait ToTable {
+    fn to_table(&self) -> Table;
+
+    /// Message printed instead of a table when there is nothing to show
+    fn empty_message(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// Rows printed by `--porcelain`, one tab-separated line each
+    ///
+    /// The column order is part of the scripting interface and must not
+    /// change. Types returning `None` fall back to JSON.
+    fn porcelain_rows(&self) -> Option<Vec<Vec<String>>> {
+        None
+    }
+}
+
+/// One line of `--jsonl` output: the record fields plus a version and kind
+#[derive(Serialize)]
+struct JsonlRecord<'a, T: Serialize> {
+    schema_version: u32,
+    kind: &'a str,
+    #[serde(flatten)]
+    data: &'a T,
+}
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum OutputFormat {
     Json,
     Table,
+    /// Stable tab-separated lines without headers, for scripts
+    Porcelain,
+}
+
+/// Set once from `--fields`; empty keeps every field
+static FIELDS: OnceLock<Vec<String>> = OnceLock::new();
+
+pub fn set_fields(fields: Vec<String>) {
+    let fields = fields
+        .into_iter()
+        .map(|field| field.trim().to_string())
+        .filter(|field| !field.is_empty())
+        .collect();
+    // Only the first call counts; the fields are fixed for the whole run
+    let _ = FIELDS.set(fields);
+}
+
+/// Set once from `--show-secrets`; secrets are redacted when unset
+static SHOW_SECRETS: OnceLock<bool> = OnceLock::new();
+
+pub fn set_show_secrets(show: bool) {
+    // Only the first call counts, like the fields
+    let _ = SHOW_SECRETS.set(show);
+}
+
+/// Whether `--show-secrets` asked for PSKs and passwords in the output
+pub fn show_secrets() -> bool {
+    SHOW_SECRETS.get().copied().unwrap_or(false)
+}
+
+/// JSON form of a value with its secrets redacted unless they are shown
+pub fn to_redacted_value<T: Serialize + ?Sized>(data: &T) -> Result<Value> {
+    let mut value = serde_json::to_value(data)?;
+    if !show_secrets() {
+        rmesh_core::redact::redact_secrets(&mut value);
+    }
+    Ok(value)
+}
+
+/// JSON form of a command output, limited to the `--fields` of this run
+pub fn to_json_value<T: Serialize + ?Sized>(data: &T) -> Result<Value> {
+    let value = to_redacted_value(data)?;
+    Ok(match FIELDS.get() {
+        Some(fields) if !fields.is_empty() => project_fields(&value, fields),
+        _ => value,
+    })
+}
+
+/// One-line JSON of a streamed record, limited to `--fields`
+pub fn to_json_line<T: Serialize + ?Sized>(data: &T) -> Result<String> {
+    Ok(serde_json::to_string(&to_json_value(data)?)?)
 }
 
 pub fn print_output<T: Serialize>(data: T, format: OutputFormat) {
+    let json = to_json_value(&data).and_then(|value| Ok(serde_json::to_string_pretty(&value)?));
     match format {
-        OutputFormat::Json => {
-            if let Ok(json) = serde_json::to_string_pretty(&data) {
+        OutputFormat::Json | OutputFormat::Porcelain => {
+            if let Ok(json) = json {
                 println!("{json}");
             }
         }
         OutputFormat::Table => {
             // Default table output - override in specific implementations
-            if let Ok(json) = serde_json::to_string_pretty(&data) {
+            if let Ok(json) = json {
                 println!("{json}");
             }
         }
     }
 }
 
+/// Print a command output as JSON, as its table or as porcelain lines
+pub fn render<T: Serialize + ToTable + ?Sized>(data: &T, format: OutputFormat) {
+    match format {
+        OutputFormat::Json => print_output(data, format),
+        OutputFormat::Table => match data.empty_message() {
+            Some(message) => println!("{message}"),
+            None => println!("{table}", table = data.to_table()),
+        },
+        OutputFormat::Porcelain => match data.porcelain_rows() {
+            Some(rows) => {
+                for row in rows {
+                    print_porcelain(&row);
+                }
+            }
+            None => print_output(data, format),
+        },
+    }
+}
+
+/// Print one porcelain line, keeping each field free of separators
+pub fn print_porcelain<S: AsRef<str>>(fields: &[S]) {
+    let line = fields
+        .iter()
+        .map(|field| field.as_ref().replace(['\t', '\n', '\r'], " "))
+        .collect::<Vec<_>>()
+        .join("\t");
+    println!("{line}");
+}
+
+/// Print a single JSON record on its own line and flush immediately
+///
+/// Write errors are returned so streaming commands stop once the reading
+/// end of a pipe goes away.
+pub fn print_jsonl<T: Serialize>(kind: &str, data: &T) -> Result<()> {
+    let line = jsonl_line(kind, &to_json_value(data)?)?;
+
+    let mut stdout = std::io::stdout().lock();
+    writeln!(stdout, "{line}")?;
+    stdout.flush()?;
+    Ok(())
+}
+
+/// A `--jsonl` record as a single line of JSON, without the newline
+pub fn jsonl_line<T: Serialize>(kind: &str, data: &T) -> Result<String> {
+    Ok(serde_json::to_string(&JsonlRecord {
+        schema_version: OUTPUT_SCHEMA_VERSION,
+        kind,
+        data,
+    })?)
+}
+
+/// Message text for the terminal, escaped unless `--raw` was given
+pub fn display_text(text: &str, raw: bool) -> Cow<'_, str> {
+    if raw {
+        Cow::Borrowed(text)
+    } else {
+        sanitize_for_terminal(text)
+    }
+}
+
+/// Print a received message as a header line plus its signal, if known
+///
+/// The channel is shown by name when the device reported one, followed by
+/// how the message was encrypted, whether it came in through MQTT and
+/// whether it is an echo of one we sent.
+pub fn print_received_message(msg: &ReceivedMessage, raw: bool) {
+    let channel = match &msg.channel_name {
+        Some(name) => sanitize_for_terminal(name).into_owned(),
+        None => msg.channel.to_string(),
+    };
+    let mut tags = Vec::new();
+    match msg.encryption {
+        Encryption::Pki => tags.push("pki".green().to_string()),
+        Encryption::None => tags.push("unencrypted".yellow().to_string()),
+        Encryption::Psk | Encryption::Unknown => {}
+    }
+    if msg.via_mqtt {
+        tags.push("mqtt".cyan().to_string());
+    }
+    if msg.own {
+        tags.push("own".dimmed().to_string());
+    }
+    let tags = if tags.is_empty() {
+        String::new()
+    } else {
+        format!(" ({tags})", tags = tags.join(", "))
+    };
+
+    println!(
+        "{time} {from} [{channel}]{tags}: {text}",
+        time = crate::utils::format_time(msg.time).dimmed(),
+        from = msg.from.blue().bold(),
+        text = display_text(&msg.text, raw)
+    );
+    if let (Some(snr), Some(rssi)) = (msg.snr, msg.rssi) {
+        println!(
+            "  {label} SNR: {snr:.1} dB, RSSI: {rssi} dBm",
+            label = "Signal:".dimmed()
+        );
+    }
+}
+
 pub fn create_table() -> Table {
     let mut table = Table::new();
     table
         .load_preset(comfy_table::presets::UTF8_FULL)
         .apply_modifier(comfy_table::modifiers::UTF8_ROUND_CORNERS);
+    // Tables otherwise decide on styling from their own tty check
+    if crate::utils::colors_enabled() {
+        table.enforce_styling();
+    } else {
+        table.force_no_tty();
+    }
     table
 }
+
+/// Colored presence cell for a node last heard at `last_heard`
+pub fn presence_cell(last_heard: Option<u64>) -> Cell {
+    let presence = PresencePolicy::default().classify(last_heard);
+    let cell = Cell::new(presence);
+    match presence {
+        NodePresence::Online => cell.fg(Color::Green),
+        NodePresence::Recently => cell.fg(Color::Yellow),
+        NodePresence::Offline => cell.fg(Color::DarkGrey),
+    }
+}
@@ -0,0 +1,292 @@
+use anyhow::{Context, Result, bail};
+use rmesh_core::message::ReceivedMessage;
+use rmesh_core::mqtt_proxy::parse_address;
+use rumqttc::{AsyncClient, ConnectionError, QoS};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::time::Duration;
+use ureq::Agent;
+use ureq::tls::TlsConfig;
+
+use super::{OutputFormat, jsonl_line, print_jsonl, print_received_message, to_json_line};
+use crate::utils::{mqtt, print_warning};
+
+/// Record kind of monitored messages in JSONL output
+const MESSAGE_KIND: &str = "message";
+
+/// Port MQTT brokers listen on without TLS
+const DEFAULT_MQTT_PORT: u16 = 1883;
+
+/// Messages a webhook or MQTT sink holds while sending before new ones are
+/// dropped
+pub const SINK_QUEUE_CAPACITY: usize = 64;
+
+/// Longest a webhook request may take, connecting included
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A destination for messages seen by `message monitor`
+///
+/// The monitor hands every message to each configured sink in turn, so
+/// outputs such as a log file and a webhook can run alongside the terminal.
+pub trait Sink {
+    /// Short label used in warnings
+    fn name(&self) -> String;
+
+    fn write(&mut self, message: &ReceivedMessage) -> Result<()>;
+
+    /// Whether a failed write stops the monitor instead of only warning
+    fn is_required(&self) -> bool {
+        false
+    }
+}
+
+/// Fan each message out to several sinks
+#[derive(Default)]
+pub struct Tee {
+    sinks: Vec<Box<dyn Sink>>,
+}
+
+impl Tee {
+    pub fn push(&mut self, sink: impl Sink + 'static) {
+        self.sinks.push(Box::new(sink));
+    }
+
+    /// Write a message to every sink
+    ///
+    /// A failing optional sink is reported and skipped so, say, a webhook
+    /// outage does not end a long monitoring session.
+    pub fn write(&mut self, message: &ReceivedMessage) -> Result<()> {
+        for sink in &mut self.sinks {
+            if let Err(e) = sink.write(message) {
+                if sink.is_required() {
+                    return Err(e);
+                }
+                print_warning(&format!("{name}: {e:#}", name = sink.name()));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Messages printed to stdout in the selected output format
+pub struct TerminalSink {
+    pub format: OutputFormat,
+    pub jsonl: bool,
+    pub raw: bool,
+}
+
+impl Sink for TerminalSink {
+    fn name(&self) -> String {
+        "terminal".to_string()
+    }
+
+    fn write(&mut self, msg: &ReceivedMessage) -> Result<()> {
+        if self.jsonl {
+            return print_jsonl(MESSAGE_KIND, msg);
+        }
+
+        match self.format {
+            OutputFormat::Json | OutputFormat::Porcelain => {
+                if let Ok(json) = to_json_line(msg) {
+                    println!("{json}");
+                }
+            }
+            OutputFormat::Table => print_received_message(msg, self.raw),
+        }
+        Ok(())
+    }
+
+    // A closed stdout means whoever reads the monitor went away
+    fn is_required(&self) -> bool {
+        true
+    }
+}
+
+/// Messages appended to a file as versioned JSON lines
+pub struct FileSink {
+    path: String,
+    file: File,
+}
+
+impl FileSink {
+    pub fn create(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open {path}", path = path.display()))?;
+        Ok(Self {
+            path: path.display().to_string(),
+            file,
+        })
+    }
+}
+
+impl Sink for FileSink {
+    fn name(&self) -> String {
+        self.path.clone()
+    }
+
+    fn write(&mut self, message: &ReceivedMessage) -> Result<()> {
+        let line = jsonl_line(MESSAGE_KIND, message)?;
+        writeln!(self.file, "{line}").context("Failed to write message")?;
+        self.file.flush().context("Failed to flush message")?;
+        Ok(())
+    }
+}
+
+/// Messages POSTed as JSON to an HTTP endpoint
+///
+/// Requests are made one at a time by a worker thread, so a slow endpoint
+/// does not hold up the monitor. Messages arriving while
+/// [`SINK_QUEUE_CAPACITY`] are already waiting are dropped with a warning.
+pub struct WebhookSink {
+    url: String,
+    queue: SyncSender<String>,
+}
+
+impl WebhookSink {
+    pub fn new(url: &str) -> Result<Self> {
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            bail!("Webhook URL must start with http:// or https://, found '{url}'");
+        }
+
+        let tls = TlsConfig::builder()
+            .unversioned_rustls_crypto_provider(Arc::new(
+                rustls::crypto::aws_lc_rs::default_provider(),
+            ))
+            .build();
+        let agent: Agent = Agent::config_builder()
+            .timeout_global(Some(WEBHOOK_TIMEOUT))
+            .tls_config(tls)
+            .build()
+            .into();
+        let (queue, bodies) = mpsc::sync_channel::<String>(SINK_QUEUE_CAPACITY);
+        let target = url.to_string();
+        std::thread::Builder::new()
+            .name("webhook".to_string())
+            .spawn(move || {
+                // Runs until the sink, and with it the queue, is dropped
+                for body in bodies {
+                    if let Err(e) = agent
+                        .post(&target)
+                        .header("Content-Type", "application/json")
+                        .send(body)
+                    {
+                        print_warning(&format!("webhook {target}: {e}"));
+                    }
+                }
+            })
+            .context("Failed to start the webhook worker")?;
+
+        Ok(Self {
+            url: url.to_string(),
+            queue,
+        })
+    }
+}
+
+impl Sink for WebhookSink {
+    fn name(&self) -> String {
+        format!("webhook {url}", url = self.url)
+    }
+
+    fn write(&mut self, message: &ReceivedMessage) -> Result<()> {
+        let body = jsonl_line(MESSAGE_KIND, message)?;
+        match self.queue.try_send(body) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => {
+                bail!("Dropped a message; the endpoint is not keeping up")
+            }
+            Err(TrySendError::Disconnected(_)) => bail!("The webhook worker stopped"),
+        }
+    }
+}
+
+/// Broker and topic an [`MqttSink`] publishes to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MqttTarget {
+    pub host: String,
+    pub port: u16,
+    pub topic: String,
+}
+
+impl MqttTarget {
+    /// Parse `mqtt://host[:port]/topic`, with IPv6 hosts in brackets
+    pub fn parse(url: &str) -> Result<Self> {
+        let Some(rest) = url.strip_prefix("mqtt://") else {
+            bail!("MQTT URL must look like mqtt://host[:port]/topic, found '{url}'");
+        };
+        let (address, topic) = rest
+            .split_once('/')
+            .filter(|(_, topic)| !topic.is_empty())
+            .with_context(|| format!("MQTT URL '{url}' has no topic"))?;
+        let (host, port) = parse_address(address, DEFAULT_MQTT_PORT)
+            .with_context(|| format!("Invalid MQTT URL '{url}'"))?;
+
+        Ok(Self {
+            host,
+            port,
+            topic: topic.to_string(),
+        })
+    }
+}
+
+/// Messages published as JSON to an MQTT topic
+///
+/// Publishes go out over one connection kept by a background task. Messages
+/// arriving while [`SINK_QUEUE_CAPACITY`] are already waiting are dropped
+/// with a warning.
+pub struct MqttSink {
+    url: String,
+    topic: String,
+    client: AsyncClient,
+}
+
+impl MqttSink {
+    /// Connect to the broker of `mqtt://host[:port]/topic`
+    ///
+    /// Must be called within the Tokio runtime that drives the connection.
+    pub fn new(url: &str) -> Result<Self> {
+        let target = MqttTarget::parse(url)?;
+        let options = mqtt::options(&mqtt::client_id("monitor"), &target.host, target.port);
+        let (client, mut events) = AsyncClient::new(options, SINK_QUEUE_CAPACITY);
+
+        let label = format!("mqtt {url}");
+        tokio::spawn(async move {
+            loop {
+                match events.poll().await {
+                    Ok(_) => {}
+                    // The sink and its client were dropped
+                    Err(ConnectionError::RequestsDone) => break,
+                    Err(e) => {
+                        print_warning(&format!("{label}: {e}"));
+                        tokio::time::sleep(mqtt::RECONNECT_DELAY).await;
+                    }
+                }
+            }
+        });
+
+        Ok(Self {
+            url: url.to_string(),
+            topic: target.topic,
+            client,
+        })
+    }
+}
+
+impl Sink for MqttSink {
+    fn name(&self) -> String {
+        format!("mqtt {url}", url = self.url)
+    }
+
+    fn write(&mut self, message: &ReceivedMessage) -> Result<()> {
+        let payload = jsonl_line(MESSAGE_KIND, message)?;
+        self.client
+            .try_publish(&self.topic, QoS::AtMostOnce, false, payload)
+            .context("Dropped a message; the broker is not keeping up")
+    }
+}
@@ -0,0 +1,764 @@
+use comfy_table::{Attribute, Cell, Color, Table};
+use rmesh_core::channel::{
+    AuditSeverity, ChannelAudit, ChannelInfo, FULL_POSITION_PRECISION, position_precision_m,
+};
+use rmesh_core::clock::ClockReport;
+use rmesh_core::device::RadioInfo;
+use rmesh_core::doctor::{CheckStatus, DoctorReport};
+use rmesh_core::lora::FrequencySlot;
+use rmesh_core::message::sanitize_for_terminal;
+use rmesh_core::node_id::NodeId;
+use rmesh_core::state::{NodeInfo, Position, TelemetryData};
+use rmesh_core::telemetry::{TelemetryPollResult, TelemetryPollStatus};
+use rmesh_core::waypoint::{WaypointImportResult, WaypointImportStatus};
+use std::collections::HashMap;
+
+use super::{ToTable, create_table, presence_cell};
+use crate::utils::{format_time, format_time_str};
+
+impl ToTable for RadioInfo {
+    fn to_table(&self) -> Table {
+        let mut table = create_table();
+        table.set_header(vec![Cell::new("Property"), Cell::new("Value")]);
+        table.add_row(vec![
+            Cell::new("Firmware Version"),
+            Cell::new(&self.firmware_version),
+        ]);
+        table.add_row(vec![
+            Cell::new("Hardware Model"),
+            Cell::new(&self.hardware_model),
+        ]);
+        table.add_row(vec![Cell::new("Region"), Cell::new(&self.region)]);
+        table.add_row(vec![Cell::new("Node ID"), Cell::new(&self.node_id)]);
+        table.add_row(vec![Cell::new("Node Number"), Cell::new(self.node_num)]);
+        table.add_row(vec![Cell::new("Has GPS"), Cell::new(self.has_gps)]);
+        table.add_row(vec![
+            Cell::new("Num Channels"),
+            Cell::new(self.num_channels),
+        ]);
+        if let Some(slot) = &self.frequency {
+            table.add_row(vec![
+                Cell::new("Frequency"),
+                Cell::new(format!(
+                    "{frequency} - {modem} on '{name}'",
+                    frequency = format_frequency_slot(slot),
+                    modem = slot.modem,
+                    name = sanitize_for_terminal(&slot.channel_name)
+                )),
+            ]);
+        }
+        let optional = [("Platform", &self.platform), ("Device ID", &self.device_id)];
+        for (label, value) in optional {
+            if let Some(value) = value {
+                table.add_row(vec![Cell::new(label), Cell::new(value)]);
+            }
+        }
+        if let Some(quality) = &self.quality {
+            let rows = [
+                ("Frames Received", quality.frames_processed.to_string()),
+                ("Sync Errors", quality.sync_errors.to_string()),
+                ("Decode Errors", quality.decode_errors.to_string()),
+                ("Responses", quality.responses.to_string()),
+                ("Timeouts", quality.timeouts.to_string()),
+                (
+                    "Error Rate",
+                    format!("{percent:.1}%", percent = quality.error_rate * 100.0),
+                ),
+                (
+                    "Avg Response Time",
+                    quality
+                        .average_response_time_ms
+                        .map(|ms| format!("{ms} ms"))
+                        .unwrap_or_else(|| "-".to_string()),
+                ),
+                (
+                    "Link Quality",
+                    quality
+                        .rating
+                        .map(|rating| rating.to_string())
+                        .unwrap_or_else(|| "Unknown".to_string()),
+                ),
+            ];
+            for (label, value) in rows {
+                table.add_row(vec![Cell::new(label), Cell::new(value)]);
+            }
+        }
+        table
+    }
+
+    /// `key<TAB>value` lines
+    fn porcelain_rows(&self) -> Option<Vec<Vec<String>>> {
+        let fields = [
+            ("firmware_version", self.firmware_version.clone()),
+            ("hardware_model", self.hardware_model.clone()),
+            ("region", self.region.clone()),
+            ("node_id", self.node_id.clone()),
+            ("node_num", self.node_num.to_string()),
+            ("has_gps", self.has_gps.to_string()),
+            ("num_channels", self.num_channels.to_string()),
+            ("platform", self.platform.clone().unwrap_or_default()),
+            ("device_id", self.device_id.clone().unwrap_or_default()),
+            (
+                "frequency_mhz",
+                self.frequency
+                    .as_ref()
+                    .map(|slot| format!("{mhz:.3}", mhz = slot.frequency_mhz))
+                    .unwrap_or_default(),
+            ),
+            (
+                "frequency_slot",
+                self.frequency
+                    .as_ref()
+                    .map(|slot| slot.slot.to_string())
+                    .unwrap_or_default(),
+            ),
+        ];
+        let quality = self.quality.iter().flat_map(|quality| {
+            [
+                ("frames_processed", quality.frames_processed.to_string()),
+                ("sync_errors", quality.sync_errors.to_string()),
+                ("decode_errors", quality.decode_errors.to_string()),
+                ("responses", quality.responses.to_string()),
+                ("timeouts", quality.timeouts.to_string()),
+                (
+                    "error_rate",
+                    format!("{rate:.4}", rate = quality.error_rate),
+                ),
+                (
+                    "average_response_time_ms",
+                    quality
+                        .average_response_time_ms
+                        .map(|ms| ms.to_string())
+                        .unwrap_or_default(),
+                ),
+            ]
+        });
+        Some(
+            fields
+                .into_iter()
+                .chain(quality)
+                .map(|(key, value)| vec![key.to_string(), value])
+                .collect(),
+        )
+    }
+}
+
+impl ToTable for ClockReport {
+    fn to_table(&self) -> Table {
+        let mut table = create_table();
+        table.set_header(vec![Cell::new("Property"), Cell::new("Value")]);
+        let device_time = self
+            .device_time
+            .map(format_time)
+            .unwrap_or_else(|| "Not set".to_string());
+        let drift = match self.drift_secs {
+            Some(drift) => {
+                let cell = Cell::new(format!("{drift:+}s"));
+                if self.needs_sync() {
+                    cell.fg(Color::Red)
+                } else {
+                    cell
+                }
+            }
+            None => Cell::new("-"),
+        };
+        table.add_row(vec![Cell::new("Node"), Cell::new(&self.node)]);
+        table.add_row(vec![Cell::new("Device Time"), Cell::new(device_time)]);
+        table.add_row(vec![
+            Cell::new("Host Time"),
+            Cell::new(format_time(self.host_time)),
+        ]);
+        table.add_row(vec![Cell::new("Drift"), drift]);
+        table.add_row(vec![
+            Cell::new("Round Trip"),
+            Cell::new(format!("{ms} ms", ms = self.round_trip_ms)),
+        ]);
+        table.add_row(vec![Cell::new("Synced"), Cell::new(self.synced)]);
+        table
+    }
+
+    /// `key<TAB>value` lines
+    fn porcelain_rows(&self) -> Option<Vec<Vec<String>>> {
+        let fields = [
+            ("node", self.node.clone()),
+            (
+                "device_time",
+                self.device_time
+                    .map(|time| time.to_string())
+                    .unwrap_or_default(),
+            ),
+            ("host_time", self.host_time.to_string()),
+            (
+                "drift_secs",
+                self.drift_secs
+                    .map(|drift| drift.to_string())
+                    .unwrap_or_default(),
+            ),
+            ("round_trip_ms", self.round_trip_ms.to_string()),
+            ("synced", self.synced.to_string()),
+        ];
+        Some(
+            fields
+                .into_iter()
+                .map(|(key, value)| vec![key.to_string(), value])
+                .collect(),
+        )
+    }
+}
+
+impl ToTable for [NodeInfo] {
+    fn to_table(&self) -> Table {
+        let mut table = create_table();
+        table.set_header(vec![
+            Cell::new("ID"),
+            Cell::new("Number"),
+            Cell::new("User"),
+            Cell::new("SNR"),
+            Cell::new("Status"),
+            Cell::new("Last Heard"),
+        ]);
+
+        for node in self {
+            table.add_row(vec![
+                Cell::new(&node.id),
+                Cell::new(node.num),
+                Cell::new(sanitize_for_terminal(&node.user.long_name)),
+                Cell::new(
+                    node.snr
+                        .map(|snr| format!("{snr:.1}"))
+                        .unwrap_or_else(|| "N/A".to_string()),
+                ),
+                presence_cell(node.last_heard),
+                Cell::new(
+                    node.last_heard
+                        .map(format_time)
+                        .unwrap_or_else(|| "Never".to_string()),
+                ),
+            ]);
+        }
+        table
+    }
+
+    fn empty_message(&self) -> Option<&'static str> {
+        self.is_empty()
+            .then_some("No nodes found in the mesh network")
+    }
+
+    /// `id num long_name snr last_heard`; missing values are empty and
+    /// `last_heard` is a Unix timestamp
+    fn porcelain_rows(&self) -> Option<Vec<Vec<String>>> {
+        Some(
+            self.iter()
+                .map(|node| {
+                    vec![
+                        node.id.clone(),
+                        node.num.to_string(),
+                        node.user.long_name.clone(),
+                        node.snr.map(|snr| format!("{snr:.1}")).unwrap_or_default(),
+                        node.last_heard
+                            .map(|timestamp| timestamp.to_string())
+                            .unwrap_or_default(),
+                    ]
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Node table with battery, role and encrypted DM support added
+pub fn detailed_node_table(nodes: &[NodeInfo]) -> Table {
+    let mut table = create_table();
+    table.set_header(vec![
+        Cell::new("ID"),
+        Cell::new("User"),
+        Cell::new("Role"),
+        Cell::new("Battery"),
+        Cell::new("Secure DM"),
+        Cell::new("Status"),
+        Cell::new("Last Heard"),
+    ]);
+
+    for node in nodes {
+        // Levels above 100 mean the node runs on external power
+        let battery = match node.device_metrics.as_ref().and_then(|m| m.battery_level) {
+            Some(level) if level > 100 => "Powered".to_string(),
+            Some(level) => format!("{level}%"),
+            None => "N/A".to_string(),
+        };
+        table.add_row(vec![
+            Cell::new(&node.id),
+            Cell::new(sanitize_for_terminal(&node.user.long_name)),
+            Cell::new(node.user.role.as_deref().unwrap_or("N/A")),
+            Cell::new(battery),
+            Cell::new(if node.supports_secure_dm() {
+                "Yes"
+            } else {
+                "No"
+            }),
+            presence_cell(node.last_heard),
+            Cell::new(
+                node.last_heard
+                    .map(format_time)
+                    .unwrap_or_else(|| "Never".to_string()),
+            ),
+        ]);
+    }
+    table
+}
+
+/// Channels with the frequency slot of the primary channel, for
+/// `channel list --detailed`
+pub fn detailed_channel_table(channels: &[ChannelInfo], slot: Option<&FrequencySlot>) -> Table {
+    let mut table = create_table();
+    table.set_header(vec![
+        Cell::new("Index"),
+        Cell::new("Name"),
+        Cell::new("Role"),
+        Cell::new("Encrypted"),
+        Cell::new("Position"),
+        Cell::new("Frequency"),
+    ]);
+
+    for channel in channels {
+        // Secondary channels share the primary channel's frequency
+        let frequency = match slot {
+            Some(slot) if channel.role == "Primary" => format_frequency_slot(slot),
+            None if channel.role == "Primary" => "Unknown".to_string(),
+            _ => "-".to_string(),
+        };
+        table.add_row(vec![
+            Cell::new(channel.index),
+            Cell::new(&channel.name),
+            Cell::new(&channel.role),
+            Cell::new(if channel.has_psk { "Yes" } else { "No" }),
+            position_cell(channel),
+            Cell::new(frequency),
+        ]);
+    }
+    table
+}
+
+/// Shared position precision, red when anyone can read exact positions
+fn position_cell(channel: &ChannelInfo) -> Cell {
+    let cell = Cell::new(format_position_precision(channel.position_precision));
+    if channel.exposes_position {
+        cell.fg(Color::Red)
+    } else {
+        cell
+    }
+}
+
+/// e.g. `Off`, `Exact`, `±364 m` or `±23.3 km`
+pub fn format_position_precision(precision_bits: u32) -> String {
+    match position_precision_m(precision_bits) {
+        None => "Off".to_string(),
+        Some(_) if precision_bits >= FULL_POSITION_PRECISION => "Exact".to_string(),
+        Some(meters) if meters < 1000.0 => format!("±{meters:.0} m"),
+        Some(meters) => format!("±{km:.1} km", km = meters / 1000.0),
+    }
+}
+
+/// e.g. `906.875 MHz (slot 20 of 104)`
+fn format_frequency_slot(slot: &FrequencySlot) -> String {
+    format!(
+        "{mhz:.3} MHz (slot {slot} of {num_slots})",
+        mhz = slot.frequency_mhz,
+        slot = slot.slot,
+        num_slots = slot.num_slots
+    )
+}
+
+impl ToTable for [ChannelInfo] {
+    fn to_table(&self) -> Table {
+        let mut table = create_table();
+        table.set_header(vec![
+            Cell::new("Index"),
+            Cell::new("Name"),
+            Cell::new("Role"),
+            Cell::new("Encrypted"),
+            Cell::new("Position"),
+        ]);
+
+        for channel in self {
+            table.add_row(vec![
+                Cell::new(channel.index),
+                Cell::new(&channel.name),
+                Cell::new(&channel.role),
+                Cell::new(if channel.has_psk { "Yes" } else { "No" }),
+                position_cell(channel),
+            ]);
+        }
+        table
+    }
+
+    fn empty_message(&self) -> Option<&'static str> {
+        self.is_empty().then_some("No channels configured")
+    }
+
+    /// `index name role encrypted`, with `encrypted` as true/false
+    fn porcelain_rows(&self) -> Option<Vec<Vec<String>>> {
+        Some(
+            self.iter()
+                .map(|channel| {
+                    vec![
+                        channel.index.to_string(),
+                        channel.name.clone(),
+                        channel.role.clone(),
+                        channel.has_psk.to_string(),
+                    ]
+                })
+                .collect(),
+        )
+    }
+}
+
+impl ToTable for ChannelAudit {
+    fn to_table(&self) -> Table {
+        let mut table = create_table();
+        table.set_header(vec![
+            Cell::new("Severity"),
+            Cell::new("Channel"),
+            Cell::new("Issue"),
+            Cell::new("Recommendation"),
+        ]);
+
+        for finding in &self.findings {
+            let severity = Cell::new(finding.severity);
+            let severity = match finding.severity {
+                AuditSeverity::Critical => severity.fg(Color::Red).add_attribute(Attribute::Bold),
+                AuditSeverity::Warning => severity.fg(Color::Yellow),
+                AuditSeverity::Info => severity,
+            };
+            table.add_row(vec![
+                severity,
+                Cell::new(format!(
+                    "{index}: {name}",
+                    index = finding.channel_index,
+                    name = sanitize_for_terminal(&finding.channel_name)
+                )),
+                Cell::new(&finding.issue),
+                Cell::new(&finding.recommendation),
+            ]);
+        }
+        table
+    }
+
+    fn empty_message(&self) -> Option<&'static str> {
+        self.findings
+            .is_empty()
+            .then_some("No weak channel settings found")
+    }
+
+    /// `severity channel_index channel_name issue`
+    fn porcelain_rows(&self) -> Option<Vec<Vec<String>>> {
+        Some(
+            self.findings
+                .iter()
+                .map(|finding| {
+                    vec![
+                        finding.severity.to_string(),
+                        finding.channel_index.to_string(),
+                        finding.channel_name.clone(),
+                        finding.issue.clone(),
+                    ]
+                })
+                .collect(),
+        )
+    }
+}
+
+impl ToTable for HashMap<u32, Position> {
+    fn to_table(&self) -> Table {
+        let mut table = create_table();
+        table.set_header(vec![
+            Cell::new("Node ID"),
+            Cell::new("Latitude"),
+            Cell::new("Longitude"),
+            Cell::new("Altitude"),
+            Cell::new("Time"),
+        ]);
+
+        for (node_num, position) in sorted_by_node(self) {
+            table.add_row(vec![
+                Cell::new(NodeId(node_num).to_string()),
+                Cell::new(format!("{lat:.6}", lat = position.latitude)),
+                Cell::new(format!("{lon:.6}", lon = position.longitude)),
+                Cell::new(
+                    position
+                        .altitude
+                        .map(|a| a.to_string())
+                        .unwrap_or_else(|| "N/A".to_string()),
+                ),
+                Cell::new(
+                    position
+                        .time
+                        .as_deref()
+                        .map(format_time_str)
+                        .unwrap_or_else(|| "N/A".to_string()),
+                ),
+            ]);
+        }
+        table
+    }
+
+    fn empty_message(&self) -> Option<&'static str> {
+        self.is_empty().then_some("No position data available")
+    }
+}
+
+impl ToTable for HashMap<u32, TelemetryData> {
+    fn to_table(&self) -> Table {
+        let mut table = create_table();
+        table.set_header(vec![
+            Cell::new("Node ID"),
+            Cell::new("Type"),
+            Cell::new("Battery"),
+            Cell::new("Voltage"),
+            Cell::new("Temperature"),
+            Cell::new("Humidity"),
+        ]);
+
+        for (node_num, telemetry) in sorted_by_node(self) {
+            let mut battery = "N/A".to_string();
+            let mut voltage = "N/A".to_string();
+            let mut temp = "N/A".to_string();
+            let mut humidity = "N/A".to_string();
+            let mut data_type = "None".to_string();
+
+            if let Some(device) = &telemetry.device_metrics {
+                data_type = "Device".to_string();
+                battery = device
+                    .battery_level
+                    .map(|b| format!("{b}%"))
+                    .unwrap_or_else(|| "N/A".to_string());
+                voltage = device
+                    .voltage
+                    .map(|v| format!("{v:.2}V"))
+                    .unwrap_or_else(|| "N/A".to_string());
+            }
+
+            if let Some(env) = &telemetry.environment_metrics {
+                data_type = if data_type == "None" {
+                    "Environment".to_string()
+                } else {
+                    format!("{data_type}, Environment")
+                };
+                temp = env
+                    .temperature
+                    .map(|t| format!("{t:.1}°C"))
+                    .unwrap_or_else(|| "N/A".to_string());
+                humidity = env
+                    .relative_humidity
+                    .map(|h| format!("{h:.1}%"))
+                    .unwrap_or_else(|| "N/A".to_string());
+            }
+
+            table.add_row(vec![
+                Cell::new(NodeId(node_num).to_string()),
+                Cell::new(data_type),
+                Cell::new(battery),
+                Cell::new(voltage),
+                Cell::new(temp),
+                Cell::new(humidity),
+            ]);
+        }
+        table
+    }
+
+    fn empty_message(&self) -> Option<&'static str> {
+        self.is_empty().then_some("No telemetry data available")
+    }
+}
+
+/// Entries of a per-node map in node number order, for stable table rows
+fn sorted_by_node<T>(entries: &HashMap<u32, T>) -> Vec<(u32, &T)> {
+    let mut sorted: Vec<(u32, &T)> = entries.iter().map(|(num, value)| (*num, value)).collect();
+    sorted.sort_unstable_by_key(|(num, _)| *num);
+    sorted
+}
+
+impl ToTable for [TelemetryPollResult] {
+    fn to_table(&self) -> Table {
+        let mut table = create_table();
+        table.set_header(vec![
+            Cell::new("Node ID"),
+            Cell::new("Status"),
+            Cell::new("Reply"),
+            Cell::new("Battery"),
+            Cell::new("Voltage"),
+            Cell::new("Ch. Util"),
+            Cell::new("Temperature"),
+            Cell::new("Humidity"),
+        ]);
+
+        let na = || "N/A".to_string();
+        for result in self {
+            let status = Cell::new(result.status);
+            let status = match result.status {
+                TelemetryPollStatus::Responded => status.fg(Color::Green),
+                TelemetryPollStatus::SendFailed => status.fg(Color::Red),
+                TelemetryPollStatus::TimedOut | TelemetryPollStatus::Skipped => {
+                    status.fg(Color::Yellow)
+                }
+            };
+            let device = result
+                .telemetry
+                .as_ref()
+                .and_then(|telemetry| telemetry.device_metrics.as_ref());
+            let env = result
+                .telemetry
+                .as_ref()
+                .and_then(|telemetry| telemetry.environment_metrics.as_ref());
+
+            table.add_row(vec![
+                Cell::new(&result.node_id),
+                status,
+                Cell::new(
+                    result
+                        .response_secs
+                        .map(|secs| format!("{secs:.1}s"))
+                        .unwrap_or_default(),
+                ),
+                Cell::new(
+                    device
+                        .and_then(|device| device.battery_level)
+                        .map(|b| format!("{b}%"))
+                        .unwrap_or_else(na),
+                ),
+                Cell::new(
+                    device
+                        .and_then(|device| device.voltage)
+                        .map(|v| format!("{v:.2}V"))
+                        .unwrap_or_else(na),
+                ),
+                Cell::new(
+                    device
+                        .and_then(|device| device.channel_utilization)
+                        .map(|u| format!("{u:.1}%"))
+                        .unwrap_or_else(na),
+                ),
+                Cell::new(
+                    env.and_then(|env| env.temperature)
+                        .map(|t| format!("{t:.1}°C"))
+                        .unwrap_or_else(na),
+                ),
+                Cell::new(
+                    env.and_then(|env| env.relative_humidity)
+                        .map(|h| format!("{h:.1}%"))
+                        .unwrap_or_else(na),
+                ),
+            ]);
+        }
+        table
+    }
+
+    fn empty_message(&self) -> Option<&'static str> {
+        self.is_empty().then_some("No other nodes known to poll")
+    }
+
+    fn porcelain_rows(&self) -> Option<Vec<Vec<String>>> {
+        Some(
+            self.iter()
+                .map(|result| {
+                    vec![
+                        result.node_id.clone(),
+                        result.status.to_string(),
+                        result
+                            .response_secs
+                            .map(|secs| format!("{secs:.1}"))
+                            .unwrap_or_default(),
+                    ]
+                })
+                .collect(),
+        )
+    }
+}
+
+impl ToTable for [WaypointImportResult] {
+    fn to_table(&self) -> Table {
+        let mut table = create_table();
+        table.set_header(vec![
+            Cell::new("ID"),
+            Cell::new("Icon"),
+            Cell::new("Name"),
+            Cell::new("Latitude"),
+            Cell::new("Longitude"),
+            Cell::new("Expires"),
+            Cell::new("Status"),
+        ]);
+
+        for result in self {
+            let waypoint = &result.waypoint;
+            let status = Cell::new(result.status);
+            let status = match result.status {
+                WaypointImportStatus::Sent => status.fg(Color::Green),
+                WaypointImportStatus::SendFailed => status.fg(Color::Red),
+                WaypointImportStatus::Planned => status,
+            };
+            table.add_row(vec![
+                Cell::new(NodeId(waypoint.id).to_string()),
+                Cell::new(waypoint.icon.map(String::from).unwrap_or_default()),
+                Cell::new(sanitize_for_terminal(&waypoint.name)),
+                Cell::new(format!("{lat:.6}", lat = waypoint.latitude)),
+                Cell::new(format!("{lon:.6}", lon = waypoint.longitude)),
+                Cell::new(
+                    waypoint
+                        .expire
+                        .map(format_time)
+                        .unwrap_or_else(|| "Never".to_string()),
+                ),
+                status,
+            ]);
+        }
+        table
+    }
+
+    fn empty_message(&self) -> Option<&'static str> {
+        self.is_empty().then_some("No waypoints found")
+    }
+}
+
+impl ToTable for DoctorReport {
+    fn to_table(&self) -> Table {
+        let mut table = create_table();
+        table.set_header(vec![
+            Cell::new("Check"),
+            Cell::new("Status"),
+            Cell::new("Detail"),
+            Cell::new("Fix"),
+        ]);
+
+        for check in &self.checks {
+            let status = Cell::new(check.status);
+            let status = match check.status {
+                CheckStatus::Ok => status.fg(Color::Green),
+                CheckStatus::Skipped => status,
+                CheckStatus::Warning => status.fg(Color::Yellow),
+                CheckStatus::Failed => status.fg(Color::Red).add_attribute(Attribute::Bold),
+            };
+            table.add_row(vec![
+                Cell::new(check.name),
+                status,
+                Cell::new(&check.detail),
+                Cell::new(check.fix.as_deref().unwrap_or_default()),
+            ]);
+        }
+        table
+    }
+
+    /// `check status detail`
+    fn porcelain_rows(&self) -> Option<Vec<Vec<String>>> {
+        Some(
+            self.checks
+                .iter()
+                .map(|check| {
+                    vec![
+                        check.name.to_string(),
+                        check.status.to_string(),
+                        check.detail.clone(),
+                    ]
+                })
+                .collect(),
+        )
+    }
+}
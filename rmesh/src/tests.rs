@@ -0,0 +1,261 @@
+#[cfg(test)]
+mod sink_tests {
+    use crate::output::sink::{MqttTarget, WebhookSink};
+    use anyhow::Result;
+
+    fn target(host: &str, port: u16, topic: &str) -> MqttTarget {
+        MqttTarget {
+            host: host.to_string(),
+            port,
+            topic: topic.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_mqtt_target_parse() -> Result<()> {
+        assert_eq!(
+            MqttTarget::parse("mqtt://broker.local/mesh/messages")?,
+            target("broker.local", 1883, "mesh/messages")
+        );
+        assert_eq!(
+            MqttTarget::parse("mqtt://10.0.0.5:1884/mesh")?,
+            target("10.0.0.5", 1884, "mesh")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_mqtt_target_parse_ipv6() -> Result<()> {
+        assert_eq!(
+            MqttTarget::parse("mqtt://[::1]:1884/mesh")?,
+            target("::1", 1884, "mesh")
+        );
+        assert_eq!(
+            MqttTarget::parse("mqtt://[fe80::1]/mesh")?,
+            target("fe80::1", 1883, "mesh")
+        );
+        // Without brackets the whole address is the host
+        assert_eq!(
+            MqttTarget::parse("mqtt://fe80::1/mesh")?,
+            target("fe80::1", 1883, "mesh")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_mqtt_target_parse_rejects_malformed() -> Result<()> {
+        assert!(MqttTarget::parse("http://broker.local/mesh").is_err());
+        assert!(MqttTarget::parse("mqtt://broker.local").is_err());
+        assert!(MqttTarget::parse("mqtt://broker.local/").is_err());
+        assert!(MqttTarget::parse("mqtt://:1883/mesh").is_err());
+        assert!(MqttTarget::parse("mqtt://broker.local:port/mesh").is_err());
+        assert!(MqttTarget::parse("mqtt://[::1/mesh").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_webhook_url_scheme() -> Result<()> {
+        assert!(WebhookSink::new("https://example.com/hook").is_ok());
+        assert!(WebhookSink::new("ftp://example.com/hook").is_err());
+        assert!(WebhookSink::new("example.com/hook").is_err());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod compat_tests {
+    use crate::cli::{Cli, Commands, ConfigCommands, InfoCommands, MessageCommands};
+    use crate::commands::compat::translate;
+    use anyhow::{Result, bail};
+    use clap::Parser;
+
+    /// Translate `rmesh compat` followed by Python CLI flags
+    fn translate_flags(flags: &[&str]) -> Result<Commands> {
+        let cli = Cli::try_parse_from(["rmesh", "compat"].iter().chain(flags))?;
+        let Commands::Compat(args) = cli.command else {
+            bail!("Expected the compat command");
+        };
+        translate(&args)
+    }
+
+    #[test]
+    fn test_sendtext() -> Result<()> {
+        let command = translate_flags(&[
+            "--sendtext",
+            "hello",
+            "--dest",
+            "!0000abcd",
+            "--ch-index",
+            "2",
+            "--ack",
+        ])?;
+        let Commands::Message {
+            subcommand:
+                MessageCommands::Send {
+                    text,
+                    dest,
+                    channel,
+                    ack,
+                    ..
+                },
+        } = command
+        else {
+            bail!("Expected message send, got {command:?}");
+        };
+        assert_eq!(text, "hello");
+        assert_eq!(dest, Some(0xabcd));
+        assert_eq!(channel, 2);
+        assert!(ack);
+        Ok(())
+    }
+
+    #[test]
+    fn test_sendtext_destinations() -> Result<()> {
+        for (flags, expected) in [
+            (&["--sendtext", "hi"][..], None),
+            (&["--sendtext", "hi", "--dest", "^all"][..], None),
+            (&["--sendtext", "hi", "--dest", "0x10"][..], Some(0x10)),
+        ] {
+            let Commands::Message {
+                subcommand: MessageCommands::Send { dest, .. },
+            } = translate_flags(flags)?
+            else {
+                bail!("Expected message send for {flags:?}");
+            };
+            assert_eq!(dest, expected, "{flags:?}");
+        }
+
+        assert!(translate_flags(&["--sendtext", "hi", "--dest", "nobody"]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_and_set() -> Result<()> {
+        let command = translate_flags(&["--get", "lora.region"])?;
+        let Commands::Config {
+            subcommand: ConfigCommands::Get { key },
+        } = command
+        else {
+            bail!("Expected config get, got {command:?}");
+        };
+        assert_eq!(key, "lora.region");
+
+        // Repeated --set flags become one change
+        let command = translate_flags(&[
+            "--set",
+            "lora.region",
+            "EU_868",
+            "--set",
+            "device.role",
+            "ROUTER",
+        ])?;
+        let Commands::Config {
+            subcommand: ConfigCommands::Set { pairs, .. },
+        } = command
+        else {
+            bail!("Expected config set, got {command:?}");
+        };
+        let pairs: Vec<(&str, &str)> = pairs
+            .iter()
+            .map(|pair| (pair.key.as_str(), pair.value.as_str()))
+            .collect();
+        assert_eq!(
+            pairs,
+            [("lora.region", "EU_868"), ("device.role", "ROUTER")]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_info_and_nodes() -> Result<()> {
+        assert!(matches!(
+            translate_flags(&["--info"])?,
+            Commands::Info {
+                subcommand: InfoCommands::Radio { quality: false }
+            }
+        ));
+        assert!(matches!(
+            translate_flags(&["--nodes"])?,
+            Commands::Info {
+                subcommand: InfoCommands::Nodes {
+                    limit: None,
+                    offset: 0,
+                    detailed: false
+                }
+            }
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_one_action_required() -> Result<()> {
+        assert!(translate_flags(&[]).is_err());
+        assert!(translate_flags(&["--info", "--nodes"]).is_err());
+        // --ack only makes sense with a message
+        assert!(translate_flags(&["--info", "--ack"]).is_err());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod secret_tests {
+    use crate::utils::secret::read_secret;
+    use anyhow::Result;
+    use std::path::PathBuf;
+
+    fn secret_file(name: &str, contents: &str) -> Result<PathBuf> {
+        let path = std::env::temp_dir().join(format!(
+            "rmesh-secret-{name}-{pid}",
+            pid = std::process::id()
+        ));
+        std::fs::write(&path, contents)?;
+        Ok(path)
+    }
+
+    #[test]
+    fn test_direct_value() -> Result<()> {
+        assert_eq!(
+            read_secret("PSK", Some("base64:abc".to_string()), false, None, None)?,
+            Some("base64:abc".to_string())
+        );
+        assert_eq!(read_secret("PSK", None, false, None, None)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_environment() -> Result<()> {
+        // Cargo sets this for every test run
+        let secret = read_secret("PSK", None, false, Some("CARGO_PKG_NAME"), None)?;
+        assert_eq!(secret.as_deref(), Some(env!("CARGO_PKG_NAME")));
+
+        // The environment wins over a value given directly
+        let secret = read_secret(
+            "PSK",
+            Some("ignored".to_string()),
+            false,
+            Some("CARGO_PKG_NAME"),
+            None,
+        )?;
+        assert_eq!(secret.as_deref(), Some(env!("CARGO_PKG_NAME")));
+
+        assert!(read_secret("PSK", None, false, Some("RMESH_TEST_UNSET_SECRET"), None).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_file() -> Result<()> {
+        // Only the line ending is trimmed, not other whitespace
+        let path = secret_file("crlf", " hunter2 \r\n")?;
+        let secret = read_secret("password", None, false, None, Some(&path));
+        std::fs::remove_file(&path)?;
+        assert_eq!(secret?.as_deref(), Some(" hunter2 "));
+
+        let path = secret_file("empty", "\n")?;
+        let empty = read_secret("password", None, false, None, Some(&path));
+        std::fs::remove_file(&path)?;
+        assert!(empty.is_err());
+
+        assert!(read_secret("password", None, false, None, Some(&path)).is_err());
+        Ok(())
+    }
+}
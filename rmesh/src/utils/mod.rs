@@ -1,10 +1,86 @@
+use crate::cli::ColorChoice;
+use anyhow::{Result, ensure};
 use colored::*;
+use rmesh_core::connection::ConnectionStats;
+use rmesh_core::time::TimeFormat;
+use std::io::{IsTerminal, Write};
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+pub mod mqtt;
+pub mod notify;
+pub mod progress;
+pub mod secret;
+
+/// Resolved `--color` choice, read when building tables
+static COLORS: AtomicBool = AtomicBool::new(true);
+
+/// Apply `--color` to colored output and tables
+///
+/// `auto` follows the NO_COLOR convention (any non-empty value disables
+/// colors) and turns colors off when stdout is not a terminal.
+pub fn set_color_choice(choice: ColorChoice) {
+    let enabled = match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => {
+            let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+            !no_color && std::io::stdout().is_terminal()
+        }
+    };
+    colored::control::set_override(enabled);
+    COLORS.store(enabled, Ordering::Relaxed);
+}
+
+/// Whether output may contain color escape sequences
+pub fn colors_enabled() -> bool {
+    COLORS.load(Ordering::Relaxed)
+}
+
+/// Set by `--quiet` to drop informational stderr banners
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Suppress [`print_info`] and [`print_success`]; warnings and errors still print
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// Set once from `--time-format`
+static TIME_FORMAT: OnceLock<TimeFormat> = OnceLock::new();
+
+pub fn set_time_format(format: TimeFormat) {
+    // Only the first call counts; the format is fixed for the whole run
+    let _ = TIME_FORMAT.set(format);
+}
+
+/// Show a Unix timestamp in the `--time-format` chosen for this run
+pub fn format_time(timestamp: u64) -> String {
+    rmesh_core::time::format_timestamp(timestamp, time_format())
+}
+
+/// Show a stored RFC 3339 time in the `--time-format` chosen for this run
+pub fn format_time_str(time: &str) -> String {
+    rmesh_core::time::format_rfc3339(time, time_format())
+}
+
+/// The `--time-format` chosen for this run
+pub fn time_format() -> TimeFormat {
+    TIME_FORMAT.get().copied().unwrap_or_default()
+}
 
 pub fn print_error(message: &str) {
     eprintln!("{prefix} {message}", prefix = "Error:".red().bold());
 }
 
 pub fn print_success(message: &str) {
+    if is_quiet() {
+        return;
+    }
     eprintln!("{prefix} {message}", prefix = "✓".green().bold());
 }
 
@@ -13,5 +89,49 @@ pub fn print_warning(message: &str) {
 }
 
 pub fn print_info(message: &str) {
+    if is_quiet() {
+        return;
+    }
     eprintln!("{prefix} {message}", prefix = "ℹ".blue().bold());
 }
+
+/// Ask a yes/no question on the terminal, defaulting to no
+///
+/// Fails when stdin is not a terminal, so scripts must confirm with a flag.
+pub fn confirm_prompt(question: &str) -> Result<bool> {
+    ensure!(
+        std::io::stdin().is_terminal(),
+        "Confirmation required; use --confirm to proceed"
+    );
+    eprint!("{prefix} {question} [y/N] ", prefix = "?".yellow().bold());
+    std::io::stderr().flush()?;
+
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(
+        answer.trim().to_ascii_lowercase().as_str(),
+        "y" | "yes"
+    ))
+}
+
+/// Footer of `--stats`, printed even with --quiet since it was asked for
+pub fn print_stats(stats: &ConnectionStats, wall_time: Duration) {
+    eprintln!(
+        "{prefix} connect {connect:.2}s, {frames} frames processed, {rx} B received, \
+         {tx} B sent, {wall:.2}s total",
+        prefix = "⏱".dimmed(),
+        connect = stats.connect_time.as_secs_f64(),
+        frames = stats.frames_processed,
+        rx = stats.bytes_rx,
+        tx = stats.bytes_tx,
+        wall = wall_time.as_secs_f64()
+    );
+    // Sends only queue up when several run at once
+    if stats.send_queue_peak > 1 {
+        eprintln!(
+            "{prefix} up to {peak} frames waited to be sent",
+            prefix = "⏱".dimmed(),
+            peak = stats.send_queue_peak
+        );
+    }
+}
@@ -0,0 +1,39 @@
+use rmesh_core::mqtt_proxy::BrokerSettings;
+use rumqttc::{AsyncClient, EventLoop, MqttOptions, Transport};
+use std::time::Duration;
+
+/// Requests queued for the event loop before publishes are refused
+pub const MQTT_QUEUE_CAPACITY: usize = 64;
+
+/// Pause before reconnecting after the broker connection drops
+pub const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Interval of the keep-alive pings sent while the connection is idle
+const KEEP_ALIVE: Duration = Duration::from_secs(30);
+
+/// Options for a plain connection to `host:port`
+pub fn options(client_id: &str, host: &str, port: u16) -> MqttOptions {
+    let mut options = MqttOptions::new(client_id, host, port);
+    options.set_keep_alive(KEEP_ALIVE);
+    options
+}
+
+/// Client for one persistent broker connection
+///
+/// Nothing is sent until the event loop is polled, which also reconnects
+/// after the connection drops.
+pub fn connect(client_id: &str, settings: &BrokerSettings) -> (AsyncClient, EventLoop) {
+    let mut options = options(client_id, &settings.host, settings.port);
+    if let Some(username) = &settings.username {
+        options.set_credentials(username, settings.password.as_deref().unwrap_or_default());
+    }
+    if settings.tls {
+        options.set_transport(Transport::tls_with_default_config());
+    }
+    AsyncClient::new(options, MQTT_QUEUE_CAPACITY)
+}
+
+/// Client id unique to this process, so two runs don't kick each other off
+pub fn client_id(purpose: &str) -> String {
+    format!("rmesh-{purpose}-{pid}", pid = std::process::id())
+}
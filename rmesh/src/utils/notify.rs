@@ -0,0 +1,35 @@
+use crate::cli::NotifyMode;
+use notify_rust::Notification;
+use std::io::Write;
+use tracing::debug;
+
+/// Alert the operator about an incoming message
+pub fn notify(mode: NotifyMode, title: &str, body: &str) {
+    if matches!(mode, NotifyMode::Bell | NotifyMode::Both) {
+        ring_bell();
+    }
+
+    if matches!(mode, NotifyMode::Desktop | NotifyMode::Both) {
+        send_desktop_notification(title, body);
+    }
+}
+
+fn ring_bell() {
+    let mut stderr = std::io::stderr();
+    if let Err(e) = stderr.write_all(b"\x07").and_then(|_| stderr.flush()) {
+        debug!("Failed to ring terminal bell: {e}");
+    }
+}
+
+/// Show a notification over D-Bus in the background
+///
+/// Must be called within the Tokio runtime.
+fn send_desktop_notification(title: &str, body: &str) {
+    let mut notification = Notification::new();
+    notification.appname("rmesh").summary(title).body(body);
+    tokio::spawn(async move {
+        if let Err(e) = notification.show_async().await {
+            debug!("Failed to send desktop notification: {e}");
+        }
+    });
+}
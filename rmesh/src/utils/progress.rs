@@ -0,0 +1,61 @@
+use crate::output::OutputFormat;
+use indicatif::{ProgressBar, ProgressStyle};
+use rmesh_core::progress::ProgressEvent;
+use std::io::IsTerminal;
+use std::time::Duration;
+
+/// Progress bar for a long collection, drawn on stderr
+///
+/// The bar is hidden for `--json` and `--porcelain` output, with `--quiet`,
+/// and when stderr is not a terminal, so scripts and logs see no escape
+/// sequences.
+pub fn progress_bar(format: OutputFormat) -> ProgressBar {
+    if is_hidden(format) {
+        return ProgressBar::hidden();
+    }
+
+    let bar = ProgressBar::new(0);
+    bar.set_style(
+        ProgressStyle::default_bar()
+            .template("{spinner:.green} [{bar:40.cyan/blue}] {pos}/{len}s {msg}")
+            .unwrap_or_else(|_| ProgressStyle::default_bar())
+            .progress_chars("#>-"),
+    );
+    bar
+}
+
+fn is_hidden(format: OutputFormat) -> bool {
+    format != OutputFormat::Table || super::is_quiet() || !std::io::stderr().is_terminal()
+}
+
+/// Spinner for a wait of unknown length, hidden like [`progress_bar`]
+pub fn spinner(format: OutputFormat, message: &str) -> ProgressBar {
+    if is_hidden(format) {
+        return ProgressBar::hidden();
+    }
+
+    let spinner = ProgressBar::new_spinner();
+    spinner.set_message(message.to_string());
+    spinner.enable_steady_tick(Duration::from_millis(100));
+    spinner
+}
+
+/// Reflect a core progress event on a bar
+pub fn update_progress(bar: &ProgressBar, event: ProgressEvent) {
+    match event {
+        ProgressEvent::Started { total, .. } => {
+            if let Some(total) = total {
+                bar.set_length(total);
+            }
+        }
+        ProgressEvent::Advanced {
+            completed, message, ..
+        } => {
+            bar.set_position(completed);
+            if let Some(message) = message {
+                bar.set_message(message);
+            }
+        }
+        ProgressEvent::Finished { .. } => bar.finish_and_clear(),
+    }
+}
@@ -0,0 +1,104 @@
+use anyhow::{Context, Result, ensure};
+use std::io::{BufRead, IsTerminal, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::cli::PskArgs;
+
+use super::print_warning;
+
+/// Argument value asking for a secret to be read interactively
+const PROMPT_VALUE: &str = "-";
+
+/// Resolve a secret given directly, via prompt, environment or file
+///
+/// `value` of `-` or `prompt` reads it from the terminal without echo, or
+/// as one line from stdin when that is not a terminal. Returns `None` when
+/// no source was given.
+pub fn read_secret(
+    what: &str,
+    value: Option<String>,
+    prompt: bool,
+    env: Option<&str>,
+    file: Option<&Path>,
+) -> Result<Option<String>> {
+    if prompt || value.as_deref() == Some(PROMPT_VALUE) {
+        return prompt_secret(what).map(Some);
+    }
+
+    if let Some(var) = env {
+        let secret = std::env::var(var)
+            .with_context(|| format!("Environment variable {var} with the {what} is not set"))?;
+        ensure!(!secret.is_empty(), "Environment variable {var} is empty");
+        return Ok(Some(secret));
+    }
+
+    if let Some(path) = file {
+        let contents = std::fs::read_to_string(path).with_context(|| {
+            format!(
+                "Failed to read the {what} from {path}",
+                path = path.display()
+            )
+        })?;
+        let secret = trim_line_ending(&contents);
+        ensure!(!secret.is_empty(), "{path} is empty", path = path.display());
+        return Ok(Some(secret.to_string()));
+    }
+
+    Ok(value)
+}
+
+/// Resolve the PSK of a channel command
+pub fn read_psk(args: &PskArgs) -> Result<Option<String>> {
+    read_secret(
+        "PSK",
+        args.psk.clone(),
+        args.prompt_psk,
+        args.psk_env.as_deref(),
+        args.psk_file.as_deref(),
+    )
+}
+
+fn prompt_secret(what: &str) -> Result<String> {
+    let stdin = std::io::stdin();
+    let interactive = stdin.is_terminal();
+
+    let mut echo_disabled = false;
+    if interactive {
+        eprint!("{what}: ");
+        std::io::stderr().flush()?;
+        echo_disabled = set_terminal_echo(false);
+        if !echo_disabled {
+            print_warning("Could not hide input; the secret will be visible as you type");
+        }
+    }
+
+    let mut line = String::new();
+    let result = stdin.lock().read_line(&mut line);
+
+    if echo_disabled {
+        set_terminal_echo(true);
+        // The newline typed by the user was not echoed either
+        eprintln!();
+    }
+    result.with_context(|| format!("Failed to read the {what}"))?;
+
+    let secret = trim_line_ending(&line);
+    ensure!(!secret.is_empty(), "No {what} entered");
+    Ok(secret.to_string())
+}
+
+/// Toggle terminal echo with stty, returning whether it succeeded
+fn set_terminal_echo(enabled: bool) -> bool {
+    Command::new("stty")
+        .arg(if enabled { "echo" } else { "-echo" })
+        .stdin(Stdio::inherit())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+fn trim_line_ending(text: &str) -> &str {
+    text.trim_end_matches(['\n', '\r'])
+}